@@ -0,0 +1,37 @@
+//! Reinterprets arbitrary bytes as ioctl payloads.
+//!
+//! The first four bytes become an `IoctlHeader` by transmute, as the module
+//! sees it after `copy_from_user`; command and flag decoding must reject
+//! unknown values rather than panic. The whole input is then decoded as a
+//! `ReadRequest` and a `PromoteRequest`, and anything accepted must
+//! survive an encode/decode round trip unchanged.
+//!
+//! Run with `cargo +nightly fuzz run ioctl_structs`.
+
+#![no_main]
+
+use hkv_common::{IoctlCommand, IoctlHeader, PromoteRequest, ReadRequest, WireFormat};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(bytes) = data.first_chunk::<4>() {
+        // SAFETY: `IoctlHeader` is `repr(C)` with four `u8` fields, so every
+        // 4-byte pattern is a valid value.
+        let header: IoctlHeader = unsafe { std::mem::transmute(*bytes) };
+        if let Some(command) = IoctlCommand::from_u8(header.command) {
+            assert_eq!(command.as_u8(), header.command);
+        }
+        let _ = header.flags();
+    }
+
+    round_trip::<ReadRequest>(data);
+    round_trip::<PromoteRequest>(data);
+});
+
+/// Decodes `data` as `T` and, if accepted, checks re-encoding is lossless.
+fn round_trip<T: WireFormat + PartialEq + std::fmt::Debug>(data: &[u8]) {
+    if let Ok(decoded) = T::decode(data) {
+        let encoded = decoded.to_wire();
+        assert_eq!(T::decode(&encoded).as_ref(), Ok(&decoded));
+    }
+}
@@ -0,0 +1,29 @@
+//! Feeds arbitrary bytes to a fresh `RespParser`.
+//!
+//! Every call must return `Ok`, `Err(RespError::Protocol)` or
+//! `Err(RespError::TooLarge)` without panicking, and every parsed command
+//! must consume input, so a server draining a buffer in a loop always
+//! terminates.
+//!
+//! Run with `cargo +nightly fuzz run resp_parser`; seeds live in
+//! `corpus/resp_parser`.
+
+#![no_main]
+
+use bytes::BytesMut;
+use hkv_server::protocol::{RespError, RespParser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut parser = RespParser::new();
+    let mut buf = BytesMut::from(data);
+
+    loop {
+        let before = buf.len();
+        match parser.parse(&mut buf) {
+            Ok(Some(_)) => assert!(buf.len() < before, "parsed a command without consuming"),
+            Ok(None) => break,
+            Err(RespError::Protocol | RespError::TooLarge) => break,
+        }
+    }
+});
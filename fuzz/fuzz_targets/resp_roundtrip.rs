@@ -0,0 +1,27 @@
+//! Encodes arbitrary argument lists with the client's `encode_command` and
+//! parses them back with the server's `RespParser`.
+//!
+//! The parser must return exactly the encoded arguments and consume the
+//! whole buffer, so anything the client sends reaches a command handler
+//! unchanged.
+//!
+//! Run with `cargo +nightly fuzz run resp_roundtrip`.
+
+#![no_main]
+
+use bytes::BytesMut;
+use hkv_client::encode_command;
+use hkv_server::protocol::RespParser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|args: Vec<Vec<u8>>| {
+    let refs: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let mut encoded = Vec::new();
+    encode_command(&refs, &mut encoded);
+
+    let mut buf = BytesMut::from(encoded.as_slice());
+    let parsed = RespParser::new().parse(&mut buf);
+    let parsed = parsed.expect("encoded frames parse");
+    assert_eq!(parsed.expect("encoded frames are complete"), args);
+    assert!(buf.is_empty(), "{} bytes left unparsed", buf.len());
+});
@@ -8,3 +8,4 @@ mod pool;
 mod resp;
 
 pub use client::{ClientConfig, ClientError, ClientResult, ClientTtl, KVClient};
+pub use resp::encode_command;
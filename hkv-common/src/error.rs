@@ -6,6 +6,24 @@
 //! 2. **Categorized Ranges**: Codes are grouped by intent (client, server, transient, protocol).
 //! 3. **Low Overhead**: Enums are `Copy` and `repr(u16)` to keep payloads small.
 //! 4. **Recoverability Hints**: Transient errors are explicitly marked as retryable.
+//! 5. **Errno Mapping**: Each variant has a distinct Linux errno so the kernel
+//!    module can fail ioctls predictably and user space can map them back:
+//!
+//! | Error              | errno          |
+//! |--------------------|----------------|
+//! | InvalidInput       | `EINVAL`       |
+//! | NotFound           | `ENOENT`       |
+//! | KeyTooLong         | `ENAMETOOLONG` |
+//! | ValueTooLong       | `E2BIG`        |
+//! | OutOfMemory        | `ENOMEM`       |
+//! | CapacityExceeded   | `ENOSPC`       |
+//! | InternalError      | `EIO`          |
+//! | Busy               | `EAGAIN`       |
+//! | Timeout            | `ETIMEDOUT`    |
+//! | Interrupted        | `EINTR`        |
+//! | VersionMismatch    | `EPROTO`       |
+//! | ProtocolViolation  | `EBADMSG`      |
+//! | UnsupportedCommand | `EOPNOTSUPP`   |
 
 use core::fmt;
 
@@ -94,6 +112,51 @@ impl HkvError {
         self.category().is_retryable()
     }
 
+    /// Returns the negative Linux errno for this error, as a kernel handler
+    /// would return it. See the module-level table.
+    pub const fn to_errno(self) -> i32 {
+        let errno = match self {
+            Self::InvalidInput => errno::EINVAL,
+            Self::NotFound => errno::ENOENT,
+            Self::KeyTooLong => errno::ENAMETOOLONG,
+            Self::ValueTooLong => errno::E2BIG,
+            Self::OutOfMemory => errno::ENOMEM,
+            Self::CapacityExceeded => errno::ENOSPC,
+            Self::InternalError => errno::EIO,
+            Self::Busy => errno::EAGAIN,
+            Self::Timeout => errno::ETIMEDOUT,
+            Self::Interrupted => errno::EINTR,
+            Self::VersionMismatch => errno::EPROTO,
+            Self::ProtocolViolation => errno::EBADMSG,
+            Self::UnsupportedCommand => errno::EOPNOTSUPP,
+        };
+        -errno
+    }
+
+    /// Maps an errno back to the error that produced it.
+    ///
+    /// Accepts either sign, so both a kernel return value and the positive
+    /// `errno` seen by a failed `ioctl(2)` work. Errnos outside the table
+    /// return `None`.
+    pub const fn from_errno(errno: i32) -> Option<Self> {
+        match errno.unsigned_abs() as i32 {
+            errno::EINVAL => Some(Self::InvalidInput),
+            errno::ENOENT => Some(Self::NotFound),
+            errno::ENAMETOOLONG => Some(Self::KeyTooLong),
+            errno::E2BIG => Some(Self::ValueTooLong),
+            errno::ENOMEM => Some(Self::OutOfMemory),
+            errno::ENOSPC => Some(Self::CapacityExceeded),
+            errno::EIO => Some(Self::InternalError),
+            errno::EAGAIN => Some(Self::Busy),
+            errno::ETIMEDOUT => Some(Self::Timeout),
+            errno::EINTR => Some(Self::Interrupted),
+            errno::EPROTO => Some(Self::VersionMismatch),
+            errno::EBADMSG => Some(Self::ProtocolViolation),
+            errno::EOPNOTSUPP => Some(Self::UnsupportedCommand),
+            _ => None,
+        }
+    }
+
     /// Converts a numeric code into a typed error.
     pub const fn from_code(code: u16) -> Option<Self> {
         match code {
@@ -115,6 +178,23 @@ impl HkvError {
     }
 }
 
+/// Linux errno values (`asm-generic/errno-base.h` and `errno.h`).
+mod errno {
+    pub const ENOENT: i32 = 2;
+    pub const EINTR: i32 = 4;
+    pub const EIO: i32 = 5;
+    pub const E2BIG: i32 = 7;
+    pub const EAGAIN: i32 = 11;
+    pub const ENOMEM: i32 = 12;
+    pub const EINVAL: i32 = 22;
+    pub const ENOSPC: i32 = 28;
+    pub const ENAMETOOLONG: i32 = 36;
+    pub const EPROTO: i32 = 71;
+    pub const EBADMSG: i32 = 74;
+    pub const EOPNOTSUPP: i32 = 95;
+    pub const ETIMEDOUT: i32 = 110;
+}
+
 impl fmt::Display for HkvError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let label = match self {
@@ -136,6 +216,31 @@ impl fmt::Display for HkvError {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for HkvError {}
+
+#[cfg(feature = "std")]
+impl From<HkvError> for std::io::Error {
+    fn from(err: HkvError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            HkvError::InvalidInput | HkvError::KeyTooLong | HkvError::ValueTooLong => {
+                ErrorKind::InvalidInput
+            }
+            HkvError::NotFound => ErrorKind::NotFound,
+            HkvError::OutOfMemory => ErrorKind::OutOfMemory,
+            HkvError::Busy => ErrorKind::WouldBlock,
+            HkvError::Timeout => ErrorKind::TimedOut,
+            HkvError::Interrupted => ErrorKind::Interrupted,
+            HkvError::VersionMismatch | HkvError::ProtocolViolation => ErrorKind::InvalidData,
+            HkvError::UnsupportedCommand => ErrorKind::Unsupported,
+            HkvError::CapacityExceeded | HkvError::InternalError => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{HkvError, HkvErrorCategory};
@@ -162,4 +267,64 @@ mod tests {
         assert_eq!(HkvError::from_code(1), Some(HkvError::InvalidInput));
         assert_eq!(HkvError::from_code(99), None);
     }
+
+    #[test]
+    fn from_code_is_total() {
+        for code in 0..=u16::MAX {
+            if let Some(err) = HkvError::from_code(code) {
+                assert_eq!(err.code(), code, "{err:?}");
+            }
+        }
+    }
+
+    /// Golden errno table; changing a row changes what user space sees.
+    const ERRNO_TABLE: [(HkvError, i32); 13] = [
+        (HkvError::InvalidInput, -22),
+        (HkvError::NotFound, -2),
+        (HkvError::KeyTooLong, -36),
+        (HkvError::ValueTooLong, -7),
+        (HkvError::OutOfMemory, -12),
+        (HkvError::CapacityExceeded, -28),
+        (HkvError::InternalError, -5),
+        (HkvError::Busy, -11),
+        (HkvError::Timeout, -110),
+        (HkvError::Interrupted, -4),
+        (HkvError::VersionMismatch, -71),
+        (HkvError::ProtocolViolation, -74),
+        (HkvError::UnsupportedCommand, -95),
+    ];
+
+    #[test]
+    fn errno_table_is_total_and_stable() {
+        let variants: Vec<HkvError> = (0..=u16::MAX).filter_map(HkvError::from_code).collect();
+        assert_eq!(variants.len(), ERRNO_TABLE.len());
+
+        for err in variants {
+            let (_, errno) = ERRNO_TABLE
+                .iter()
+                .find(|(row, _)| *row == err)
+                .unwrap_or_else(|| panic!("{err:?} missing from errno table"));
+            assert_eq!(err.to_errno(), *errno, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn errno_round_trips() {
+        for (err, errno) in ERRNO_TABLE {
+            assert_eq!(HkvError::from_errno(errno), Some(err));
+            assert_eq!(HkvError::from_errno(-errno), Some(err));
+        }
+        assert_eq!(HkvError::from_errno(0), None);
+        assert_eq!(HkvError::from_errno(-1), None);
+    }
+
+    #[test]
+    fn converts_into_io_error() {
+        let err = std::io::Error::from(HkvError::NotFound);
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "not found");
+
+        let err = std::io::Error::from(HkvError::ProtocolViolation);
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
 }
@@ -68,8 +68,10 @@
 //!    - Kernel validates the ioctl command number and permissions
 //!    - Kernel calls our driver's ioctl handler function
 //!
-//! 3. **Kernel Space** (hkv-kernel module):
+//! 3. **Kernel Space** (hkv-kernel module, `default-features = false`):
 //!    ```text
+//!    use hkv_common::{ReadRequest, ReadResponse, CMD_READ};  // core only
+//!
 //!    fn hybridkv_ioctl(fd, cmd, arg) {
 //!        match cmd {
 //!            CMD_READ => {
@@ -159,9 +161,11 @@
 //!
 //! - Each command has a unique number (0-255)
 //! - Commands follow Linux ioctl conventions
-//! - All commands go through the /dev/hybridkv device file
+//! - All commands go through the /dev/hybridkv device file or, batched, the
+//!   `NETLINK_HYBRIDKV` socket
 //! - Magic number 'H' (0x48) identifies HybridKV commands
-//! - Commands are grouped logically: data ops (0-4), monitoring (5), control (6-7)
+//! - Commands are grouped logically: data ops (0-4), monitoring (5), control (6-7),
+//!   handshake (8)
 
 /// ioctl magic number for HybridKV device
 ///
@@ -175,6 +179,13 @@ pub const DEVICE_PATH: &str = "/dev/hybridkv";
 /// Device name (as shown in /proc/devices)
 pub const DEVICE_NAME: &str = "hybridkv";
 
+/// Netlink protocol number of the HybridKV socket family
+///
+/// A fixed unit below `MAX_LINKS` (32) that no in-tree family uses. Each
+/// message body is the same `IoctlHeader`-prefixed request struct the
+/// ioctls take, and the reply body is the matching response struct.
+pub const NETLINK_HYBRIDKV: i32 = 31;
+
 // ============================================================================
 // IOCTL COMMAND NUMBERS
 // ============================================================================
@@ -197,7 +208,9 @@ pub const CMD_READ: u8 = 0;
 /// - Output: Success or error (NoMemory, KeyTooLarge, etc.)
 ///
 /// User space decides which keys to promote based on access patterns.
-/// Kernel accepts the entry and stores it in the hash table.
+/// Kernel accepts the entry and stores it in the hash table, unless it
+/// already holds a newer version of the key (`VersionMismatch`), so a
+/// delayed retry cannot overwrite fresher data.
 pub const CMD_PROMOTE: u8 = 1;
 
 /// Command number for BATCH_PROMOTE operation
@@ -223,14 +236,15 @@ pub const CMD_DEMOTE: u8 = 3;
 
 /// Command number for INVALIDATE operation
 ///
-/// Mark a cached entry as stale (due to user-space write)
-/// - Input: Key + New version number
-/// - Output: Success
+/// Drop a cached entry made stale by a user-space write
+/// - Input: Key + version of the write (`InvalidateRequest`)
+/// - Output: Whether an entry was removed (`InvalidateResponse`)
 ///
 /// When user space updates a value, it must invalidate the kernel cache.
-/// The kernel marks the entry as stale but doesn't delete it immediately
-/// (grace period). Next read will return STALE, prompting re-promotion
-/// if the key is still hot.
+/// The kernel removes the entry unless it is newer than the write, and
+/// remembers the version so a promote of older data still in flight is
+/// rejected as stale. The next read misses, prompting re-promotion if the
+/// key is still hot.
 ///
 /// This is critical for maintaining consistency in the write-through
 /// cache model.
@@ -268,17 +282,29 @@ pub const CMD_CONFIG: u8 = 6;
 ///
 /// Clear all entries from kernel cache
 /// - Input: None
-/// - Output: Success
+/// - Output: Number of entries removed (`FlushResponse`)
 ///
 /// This is used for:
 /// - Testing and debugging
 /// - Emergency recovery (if cache is misbehaving)
 /// - Graceful shutdown before unloading module
 ///
-/// Warning: This is expensive! Must wait for RCU grace period to
-/// ensure no readers are accessing the entries being freed.
+/// The kernel empties the table in bounded steps, releasing its lock in
+/// between, so reads keep being served during a flush of a large cache.
 pub const CMD_FLUSH: u8 = 7;
 
+/// Command number for HELLO operation
+///
+/// Negotiate capabilities right after opening the device
+/// - Input: None
+/// - Output: Protocol version (in the header) and the module's `Limits`
+///
+/// A module may be built or loaded (`kv_max_key_size`, `kv_max_value_size`)
+/// with smaller key/value limits than the compile-time maxima; user space
+/// stores the advertised limits and skips promoting entries that exceed
+/// them.
+pub const CMD_HELLO: u8 = 8;
+
 // ============================================================================
 // COMMAND ENUMERATION
 // ============================================================================
@@ -302,7 +328,7 @@ pub enum IoctlCommand {
     /// Remove entry from kernel cache
     Demote = CMD_DEMOTE,
 
-    /// Mark entry as stale (on write)
+    /// Drop a stale entry (on write)
     Invalidate = CMD_INVALIDATE,
 
     /// Get cache statistics
@@ -313,6 +339,9 @@ pub enum IoctlCommand {
 
     /// Flush all entries from cache
     Flush = CMD_FLUSH,
+
+    /// Negotiate protocol version and size limits
+    Hello = CMD_HELLO,
 }
 
 impl IoctlCommand {
@@ -332,6 +361,7 @@ impl IoctlCommand {
             CMD_STATS => Some(Self::Stats),
             CMD_CONFIG => Some(Self::Config),
             CMD_FLUSH => Some(Self::Flush),
+            CMD_HELLO => Some(Self::Hello),
             _ => None,
         }
     }
@@ -347,12 +377,13 @@ impl IoctlCommand {
             Self::Stats => "STATS",
             Self::Config => "CONFIG",
             Self::Flush => "FLUSH",
+            Self::Hello => "HELLO",
         }
     }
 
     /// Check if command is read-only (doesn't modify cache)
     pub const fn is_readonly(self) -> bool {
-        matches!(self, Self::Read | Self::Stats)
+        matches!(self, Self::Read | Self::Stats | Self::Hello)
     }
 
     /// Check if command modifies cache
@@ -369,8 +400,8 @@ impl IoctlCommand {
     }
 }
 
-impl std::fmt::Display for IoctlCommand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for IoctlCommand {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.name())
     }
 }
@@ -395,6 +426,7 @@ mod tests {
             IoctlCommand::Stats,
             IoctlCommand::Config,
             IoctlCommand::Flush,
+            IoctlCommand::Hello,
         ];
 
         for cmd in commands {
@@ -416,6 +448,7 @@ mod tests {
         // Read operations
         assert!(IoctlCommand::Read.is_readonly());
         assert!(IoctlCommand::Stats.is_readonly());
+        assert!(IoctlCommand::Hello.is_readonly());
         assert!(!IoctlCommand::Read.is_write());
 
         // Write operations
@@ -462,6 +495,7 @@ mod tests {
             CMD_STATS,
             CMD_CONFIG,
             CMD_FLUSH,
+            CMD_HELLO,
         ];
 
         for i in 0..numbers.len() {
@@ -1,14 +1,29 @@
 // hkv-common - Shared types and protocol definitions for HybridKV
 //
-// This crate defines the ioctl interface for user/kernel communication
+// This crate defines the ioctl interface for user/kernel communication.
+//
+// Everything except the `std` feature's conveniences (wall-clock helpers,
+// `std::error::Error`, `io::Error` conversion, `Value::into_vec`, the `sysfs`
+// reader) builds with `core` alone, so the kernel module can share these
+// definitions via `default-features = false`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
 pub mod error;
 pub mod ioctl;
 pub mod protocol;
+pub mod sequence;
+#[cfg(feature = "std")]
+pub mod sysfs;
+#[cfg(all(feature = "std", any(test, feature = "test-vectors")))]
+pub mod test_vectors;
 pub mod types;
+pub mod wire;
 
 // Re-export for convenience
 pub use error::*;
 pub use ioctl::*;
 pub use protocol::*;
+pub use sequence::*;
 pub use types::*;
+pub use wire::WireFormat;
@@ -7,6 +7,10 @@
 //! 1. **FFI Stability**: Use `#[repr(C)]` to keep user/kernel layouts consistent.
 //! 2. **Minimal Overhead**: Keep headers tiny to reduce copy and cache pressure.
 //! 3. **Versioned ABI**: Embed a protocol version for forward compatibility checks.
+//!    Version 4 appended the `expired` counter to `CacheStats`; version 3
+//!    added the outcome and evicted key to `PromoteResponse` and a separate
+//!    `DemoteResponse`; version 2 is the sequenced header
+//!    (`PROTOCOL_VERSION_V2`).
 //!
 //! ## Usage Notes
 //!
@@ -14,6 +18,8 @@
 //!   metadata before touching payloads.
 //! - Response types return `STATUS_OK` on success or `HkvError::code()` on failure.
 //! - Fixed-size buffers keep the ABI stable even when payloads are partially filled.
+//! - Per-request options travel as `RequestFlags` in the header's `reserved`
+//!   byte; zero means no flags, so older requests are unaffected.
 //!
 //! ## Memory Layout Example
 //!
@@ -21,6 +27,7 @@
 //! IoctlHeader (4 bytes total):
 //! +--------+---------+----------+----------+
 //! | magic  | version | command  | reserved |
+//! |        |         |          | (flags)  |
 //! +--------+---------+----------+----------+
 //! | 1B     | 1B      | 1B       | 1B       |
 //! +--------+---------+----------+----------+
@@ -40,10 +47,10 @@
 //! | header:4B  | key:258B| value:1026B| version:8B| ttl:8B |
 //! +------------+---------+-----------+-----------+--------+
 //!
-//! PromoteResponse (8 bytes total):
-//! +------------+-----------+-------------+
-//! | header:4B  | status:2B | reserved:2B |
-//! +------------+-----------+-------------+
+//! PromoteResponse (266 bytes total):
+//! +------------+-----------+------------+-------------+
+//! | header:4B  | status:2B | outcome:2B | evicted:258B|
+//! +------------+-----------+------------+-------------+
 //!
 //! BatchPromoteRequest (1304008 bytes total):
 //! +------------+----------+------------+-----------------------+
@@ -60,6 +67,11 @@
 //! | header:4B  | key:258B|
 //! +------------+---------+
 //!
+//! DemoteResponse (8 bytes total):
+//! +------------+-----------+-------------+
+//! | header:4B  | status:2B | reserved:2B |
+//! +------------+-----------+-------------+
+//!
 //! InvalidateRequest (272 bytes total):
 //! +------------+---------+-----------+
 //! | header:4B  | key:258B| pad:2B    |
@@ -67,14 +79,19 @@
 //! | version:8B                       |
 //! +----------------------------------+
 //!
+//! InvalidateResponse (8 bytes total):
+//! +------------+-----------+------------+
+//! | header:4B  | status:2B | removed:2B |
+//! +------------+-----------+------------+
+//!
 //! StatsRequest (4 bytes total):
 //! +------------+
 //! | header:4B  |
 //! +------------+
 //!
-//! StatsResponse (112 bytes total):
+//! StatsResponse (120 bytes total):
 //! +------------+-----------+-------------+-------------------+
-//! | header:4B  | status:2B | reserved:2B | stats:104B        |
+//! | header:4B  | status:2B | reserved:2B | stats:112B        |
 //! +------------+-----------+-------------+-------------------+
 //!
 //! ConfigRequest (40 bytes total):
@@ -88,13 +105,26 @@
 //! +------------+
 //! | header:4B  |
 //! +------------+
+//!
+//! FlushResponse (16 bytes total):
+//! +------------+-----------+-------------+------------+
+//! | header:4B  | status:2B | reserved:2B | removed:8B |
+//! +------------+-----------+-------------+------------+
+//!
+//! HelloResponse (12 bytes total):
+//! +------------+-----------+-------------+-----------+
+//! | header:4B  | status:2B | reserved:2B | limits:4B |
+//! +------------+-----------+-------------+-----------+
 //! ```
 
+use crate::error::{HkvError, HkvResult};
 use crate::ioctl::{IoctlCommand, IOCTL_MAGIC};
-use crate::types::{Key, Ttl, Value, Version};
+use crate::types::{Key, Limits, TtlAt, Value, Version};
 
 /// Protocol version for user/kernel ABI compatibility.
-pub const PROTOCOL_VERSION: u8 = 1;
+///
+/// Skips 2, which `PROTOCOL_VERSION_V2` uses for the sequenced header.
+pub const PROTOCOL_VERSION: u8 = 4;
 
 /// Status code indicating success in ioctl responses.
 pub const STATUS_OK: u16 = 0;
@@ -117,7 +147,7 @@ pub struct IoctlHeader {
     pub version: u8,
     /// Command number describing the request.
     pub command: u8,
-    /// Reserved for alignment and future flags; must be zero.
+    /// `RequestFlags` bits; zero means no flags. Responses leave it zero.
     pub reserved: u8,
 }
 
@@ -131,6 +161,87 @@ impl IoctlHeader {
             reserved: 0,
         }
     }
+
+    /// Returns a copy of the header carrying `flags`.
+    pub const fn with_flags(mut self, flags: RequestFlags) -> Self {
+        self.reserved = flags.bits();
+        self
+    }
+
+    /// Decodes the request flags.
+    ///
+    /// # Errors
+    /// Returns `ProtocolViolation` when unknown bits are set.
+    pub const fn flags(&self) -> HkvResult<RequestFlags> {
+        match RequestFlags::from_bits(self.reserved) {
+            Some(flags) => Ok(flags),
+            None => Err(HkvError::ProtocolViolation),
+        }
+    }
+}
+
+/// Per-request options carried in `IoctlHeader::reserved`.
+///
+/// A bit set, combined with `|`. The kernel rejects bits it does not know
+/// with `ProtocolViolation` rather than ignoring them.
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestFlags(u8);
+
+impl RequestFlags {
+    /// No options; the encoding of every pre-flags request.
+    pub const NONE: Self = Self(0);
+    /// Fail with `Busy` instead of waiting when the cache lock is contended.
+    pub const NOWAIT: Self = Self(1 << 0);
+    /// Leave lookup/hit/miss counters untouched (internal probes).
+    pub const NO_PROMOTE_STATS: Self = Self(1 << 1);
+    /// Do not log expected failures such as `NotFound`.
+    pub const QUIET: Self = Self(1 << 2);
+    /// Every defined flag.
+    pub const ALL: Self = Self(Self::NOWAIT.0 | Self::NO_PROMOTE_STATS.0 | Self::QUIET.0);
+
+    /// Returns the wire byte.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Parses a wire byte, or `None` when unknown bits are set.
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !Self::ALL.0 == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true when every flag in `other` is set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true when no flag is set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the flags set in either operand.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl core::ops::BitOr for RequestFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl core::ops::BitOrAssign for RequestFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
 }
 
 /// Read request payload for a cache lookup.
@@ -156,6 +267,12 @@ impl ReadRequest {
             key,
         }
     }
+
+    /// Sets the request flags (e.g. `NOWAIT` on a latency-critical GET).
+    pub fn with_flags(mut self, flags: RequestFlags) -> Self {
+        self.header = self.header.with_flags(flags);
+        self
+    }
 }
 
 /// Read response payload for a cache lookup.
@@ -202,15 +319,16 @@ pub struct PromoteRequest {
     pub key: Key,
     /// Entry value to insert.
     pub value: Value,
-    /// Version to associate with the entry.
+    /// Version to associate with the entry; rejected as `RejectedStale`
+    /// if the cached entry is newer (see `Version::may_replace`).
     pub version: Version,
     /// Absolute expiration timestamp for the entry.
-    pub ttl: Ttl,
+    pub ttl: TtlAt,
 }
 
 impl PromoteRequest {
     /// Builds a promote request for the provided entry data.
-    pub fn new(key: Key, value: Value, version: Version, ttl: Ttl) -> Self {
+    pub fn new(key: Key, value: Value, version: Version, ttl: TtlAt) -> Self {
         PromoteRequest {
             header: IoctlHeader::new(IoctlCommand::Promote),
             key,
@@ -219,11 +337,72 @@ impl PromoteRequest {
             ttl,
         }
     }
+
+    /// Sets the request flags.
+    pub fn with_flags(mut self, flags: RequestFlags) -> Self {
+        self.header = self.header.with_flags(flags);
+        self
+    }
+}
+
+/// What the kernel cache did with a promoted entry.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromoteOutcome {
+    /// The request failed before reaching the cache (e.g. a bad header).
+    Unspecified = 0,
+    /// Stored in a free slot.
+    Admitted = 1,
+    /// Overwrote the existing entry for the same key.
+    ReplacedExisting = 2,
+    /// Stored by evicting another (expired) entry; see `evicted_key`.
+    EvictedOther = 3,
+    /// Not stored: no free slot or no entry memory.
+    RejectedFull = 4,
+    /// Not stored: the cache holds a newer version of the key (status
+    /// `VersionMismatch`; see `Version::may_replace`).
+    RejectedStale = 5,
+}
+
+impl PromoteOutcome {
+    /// Returns the wire value.
+    pub const fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    /// Parses a wire value.
+    pub const fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(Self::Unspecified),
+            1 => Some(Self::Admitted),
+            2 => Some(Self::ReplacedExisting),
+            3 => Some(Self::EvictedOther),
+            4 => Some(Self::RejectedFull),
+            5 => Some(Self::RejectedStale),
+            _ => None,
+        }
+    }
+
+    /// Returns true when the promoted entry is now in the cache.
+    pub const fn is_stored(self) -> bool {
+        matches!(
+            self,
+            Self::Admitted | Self::ReplacedExisting | Self::EvictedOther
+        )
+    }
+
+    /// Returns true when the cache needs nothing more from this promote:
+    /// the entry was stored, or a newer version already was. Promoters
+    /// treat a `RejectedStale` (`VersionMismatch`) reply as success.
+    pub const fn is_settled(self) -> bool {
+        self.is_stored() || matches!(self, Self::RejectedStale)
+    }
 }
 
 /// Promote response payload indicating success or failure.
 ///
-/// Uses `STATUS_OK` on success or an `HkvError::code()` value on failure.
+/// Uses `STATUS_OK` on success or an `HkvError::code()` value on failure,
+/// plus the admission decision and, for `EvictedOther`, the evicted key.
 ///
 /// Use: Returned by the kernel after handling a promote request.
 #[repr(C)]
@@ -233,17 +412,68 @@ pub struct PromoteResponse {
     pub header: IoctlHeader,
     /// Status code (0 on success, error code on failure).
     pub status: u16,
-    /// Reserved for future flags; must be zero.
-    pub reserved: u16,
+    /// `PromoteOutcome` wire value.
+    pub outcome: u16,
+    /// Key removed to make room; empty unless `outcome` is `EvictedOther`.
+    pub evicted: Key,
 }
 
 impl PromoteResponse {
-    /// Builds a promote response with an explicit status.
+    /// Builds a promote response with an explicit status and no outcome.
     pub fn new(status: u16) -> Self {
         PromoteResponse {
             header: IoctlHeader::new(IoctlCommand::Promote),
             status,
-            reserved: 0,
+            outcome: PromoteOutcome::Unspecified.as_u16(),
+            evicted: Key::EMPTY,
+        }
+    }
+
+    /// Builds a response carrying an admission decision.
+    pub fn with_outcome(status: u16, outcome: PromoteOutcome, evicted: Option<Key>) -> Self {
+        PromoteResponse {
+            outcome: outcome.as_u16(),
+            evicted: evicted.unwrap_or(Key::EMPTY),
+            ..Self::new(status)
+        }
+    }
+
+    /// Decodes the admission decision.
+    ///
+    /// # Errors
+    /// Returns `ProtocolViolation` for an unknown outcome value.
+    pub fn outcome(&self) -> HkvResult<PromoteOutcome> {
+        PromoteOutcome::from_u16(self.outcome).ok_or(HkvError::ProtocolViolation)
+    }
+
+    /// Returns the evicted key when the promote displaced another entry.
+    pub fn evicted_key(&self) -> Option<&Key> {
+        (self.outcome == PromoteOutcome::EvictedOther.as_u16()).then_some(&self.evicted)
+    }
+}
+
+/// Promotion counters kept by the user-space promoter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PromoteMetrics {
+    /// Promotions that left the entry in the kernel cache.
+    pub promotions_admitted: u64,
+    /// Promotions the kernel did not store.
+    pub promotions_rejected: u64,
+    /// Admitted promotions that evicted another key.
+    pub promotions_evicting: u64,
+}
+
+impl PromoteMetrics {
+    /// Counts one promote response.
+    pub fn record(&mut self, response: &PromoteResponse) {
+        match response.outcome() {
+            Ok(outcome) if response.status == STATUS_OK && outcome.is_stored() => {
+                self.promotions_admitted += 1;
+                if outcome == PromoteOutcome::EvictedOther {
+                    self.promotions_evicting += 1;
+                }
+            }
+            _ => self.promotions_rejected += 1,
         }
     }
 }
@@ -261,12 +491,12 @@ pub struct BatchPromoteEntry {
     /// Version to associate with the entry.
     pub version: Version,
     /// Absolute expiration timestamp for the entry.
-    pub ttl: Ttl,
+    pub ttl: TtlAt,
 }
 
 impl BatchPromoteEntry {
     /// Builds a batch entry for the provided data.
-    pub fn new(key: Key, value: Value, version: Version, ttl: Ttl) -> Self {
+    pub fn new(key: Key, value: Value, version: Version, ttl: TtlAt) -> Self {
         BatchPromoteEntry {
             key,
             value,
@@ -359,6 +589,37 @@ impl DemoteRequest {
             key,
         }
     }
+
+    /// Sets the request flags.
+    pub fn with_flags(mut self, flags: RequestFlags) -> Self {
+        self.header = self.header.with_flags(flags);
+        self
+    }
+}
+
+/// Demote response payload indicating success or failure.
+///
+/// Use: Returned by the kernel after handling a demote request.
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DemoteResponse {
+    /// Common ioctl header (command must be DEMOTE).
+    pub header: IoctlHeader,
+    /// Status code (0 on success, error code on failure).
+    pub status: u16,
+    /// Reserved for future flags; must be zero.
+    pub reserved: u16,
+}
+
+impl DemoteResponse {
+    /// Builds a demote response with an explicit status.
+    pub fn new(status: u16) -> Self {
+        DemoteResponse {
+            header: IoctlHeader::new(IoctlCommand::Demote),
+            status,
+            reserved: 0,
+        }
+    }
 }
 
 /// Invalidate request payload for marking a cached entry as stale.
@@ -371,7 +632,10 @@ pub struct InvalidateRequest {
     pub header: IoctlHeader,
     /// Entry key to invalidate.
     pub key: Key,
-    /// New version number for the entry.
+    /// Version of the write that made the entry stale. A cached entry with
+    /// a newer version is kept, and later promotes of older versions are
+    /// rejected as `PromoteOutcome::RejectedStale`; `Version::ZERO`
+    /// removes the entry unconditionally.
     pub version: Version,
 }
 
@@ -384,6 +648,43 @@ impl InvalidateRequest {
             version,
         }
     }
+
+    /// Sets the request flags.
+    pub fn with_flags(mut self, flags: RequestFlags) -> Self {
+        self.header = self.header.with_flags(flags);
+        self
+    }
+}
+
+/// Invalidate response payload reporting whether an entry was removed.
+///
+/// Use: Returned by the kernel after handling an invalidate request.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidateResponse {
+    /// Common ioctl header (command must be INVALIDATE).
+    pub header: IoctlHeader,
+    /// Status code (0 on success, error code on failure).
+    pub status: u16,
+    /// 1 if a cached entry was removed, 0 if the key was absent or its
+    /// entry is newer than the invalidated version.
+    pub removed: u16,
+}
+
+impl InvalidateResponse {
+    /// Builds an invalidate response with an explicit status.
+    pub fn new(status: u16, removed: bool) -> Self {
+        InvalidateResponse {
+            header: IoctlHeader::new(IoctlCommand::Invalidate),
+            status,
+            removed: removed as u16,
+        }
+    }
+
+    /// Returns true if the kernel removed an entry.
+    pub fn was_removed(&self) -> bool {
+        self.removed != 0
+    }
 }
 
 /// Snapshot of kernel cache statistics for telemetry.
@@ -391,7 +692,7 @@ impl InvalidateRequest {
 /// All fields are plain counters or gauges so user space can render telemetry
 /// without extra parsing or allocations.
 #[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct CacheStats {
     /// Total lookup attempts.
     pub lookups: u64,
@@ -419,6 +720,8 @@ pub struct CacheStats {
     pub lock_contentions: u64,
     /// Completed RCU grace periods.
     pub rcu_grace_periods: u64,
+    /// Entries removed because their TTL passed.
+    pub expired: u64,
 }
 
 /// Stats request payload for fetching kernel cache telemetry.
@@ -538,6 +841,109 @@ impl Default for FlushRequest {
     }
 }
 
+/// Flush response payload with the number of entries removed.
+///
+/// Use: Returned by the kernel after handling a flush request.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushResponse {
+    /// Common ioctl header (command must be FLUSH).
+    pub header: IoctlHeader,
+    /// Status code (0 on success, error code on failure).
+    pub status: u16,
+    /// Reserved for alignment/future flags; must be zero.
+    pub reserved: u16,
+    /// Entries removed by the flush.
+    pub removed: u64,
+}
+
+impl FlushResponse {
+    /// Builds a flush response with an explicit status.
+    pub fn new(status: u16, removed: u64) -> Self {
+        FlushResponse {
+            header: IoctlHeader::new(IoctlCommand::Flush),
+            status,
+            reserved: 0,
+            removed,
+        }
+    }
+}
+
+/// Hello request payload opening the capability handshake.
+///
+/// Use: Issued by user space once, right after opening the device.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelloRequest {
+    /// Common ioctl header (command must be HELLO).
+    pub header: IoctlHeader,
+}
+
+impl HelloRequest {
+    /// Builds a hello request.
+    pub const fn new() -> Self {
+        HelloRequest {
+            header: IoctlHeader::new(IoctlCommand::Hello),
+        }
+    }
+}
+
+impl Default for HelloRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hello response payload advertising the module's size limits.
+///
+/// Uses `STATUS_OK` on success or an `HkvError::code()` value on failure.
+///
+/// Use: Returned by the kernel; user space keeps `limits` for the lifetime
+/// of the device handle.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelloResponse {
+    /// Common ioctl header (command must be HELLO).
+    pub header: IoctlHeader,
+    /// Status code (0 on success, error code on failure).
+    pub status: u16,
+    /// Reserved for future flags; must be zero.
+    pub reserved: u16,
+    /// Key/value limits enforced by the module.
+    pub limits: Limits,
+}
+
+impl HelloResponse {
+    /// Builds a hello response with the provided status and limits.
+    pub const fn new(status: u16, limits: Limits) -> Self {
+        HelloResponse {
+            header: IoctlHeader::new(IoctlCommand::Hello),
+            status,
+            reserved: 0,
+            limits,
+        }
+    }
+
+    /// Validates the response and returns the negotiated limits.
+    ///
+    /// # Errors
+    /// Returns `VersionMismatch` for a different protocol version, the
+    /// reported error for a non-OK status, and `ProtocolViolation` for a
+    /// malformed header or limits beyond the buffer capacities.
+    pub fn negotiated_limits(&self) -> HkvResult<Limits> {
+        if self.header.version != PROTOCOL_VERSION {
+            return Err(HkvError::VersionMismatch);
+        }
+        if self.header != IoctlHeader::new(IoctlCommand::Hello) {
+            return Err(HkvError::ProtocolViolation);
+        }
+        if self.status != STATUS_OK {
+            return Err(HkvError::from_code(self.status).unwrap_or(HkvError::ProtocolViolation));
+        }
+        Limits::new(self.limits.max_key, self.limits.max_value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -551,6 +957,44 @@ mod tests {
         assert_eq!(header.reserved, 0);
     }
 
+    #[test]
+    fn test_request_flags_round_trip_through_header() {
+        let header = IoctlHeader::new(IoctlCommand::Read);
+        assert_eq!(header.flags(), Ok(RequestFlags::NONE));
+
+        for bits in 0..=RequestFlags::ALL.bits() {
+            let flags = RequestFlags::from_bits(bits).unwrap();
+            let flagged = header.with_flags(flags);
+            assert_eq!(flagged.reserved, bits);
+            assert_eq!(flagged.flags(), Ok(flags));
+            assert_eq!(flagged.command, header.command);
+        }
+
+        let flags = RequestFlags::NOWAIT | RequestFlags::QUIET;
+        assert!(flags.contains(RequestFlags::NOWAIT));
+        assert!(!flags.contains(RequestFlags::NO_PROMOTE_STATS));
+        assert!(RequestFlags::NONE.is_empty());
+
+        let mut unknown = header;
+        unknown.reserved = 1 << 7;
+        assert_eq!(unknown.flags(), Err(HkvError::ProtocolViolation));
+        assert_eq!(RequestFlags::from_bits(0xff), None);
+    }
+
+    #[test]
+    fn test_request_builders_set_flags() {
+        let key = Key::new(b"k").unwrap();
+        let read = ReadRequest::new(key.clone()).with_flags(RequestFlags::NOWAIT);
+        assert_eq!(read.header.flags(), Ok(RequestFlags::NOWAIT));
+        assert_eq!(read.header.command, IoctlCommand::Read.as_u8());
+
+        let demote = DemoteRequest::new(key).with_flags(RequestFlags::QUIET);
+        assert_eq!(demote.header.flags(), Ok(RequestFlags::QUIET));
+
+        let promote = promote_request(b"k", TtlAt::INFINITE);
+        assert_eq!(promote.header.flags(), Ok(RequestFlags::NONE));
+    }
+
     #[test]
     fn test_ioctl_header_size() {
         assert_eq!(std::mem::size_of::<IoctlHeader>(), 4);
@@ -583,12 +1027,13 @@ mod tests {
     fn test_promote_request_new() {
         let key = Key::new(b"alpha").unwrap();
         let value = Value::new(b"beta").unwrap();
-        let request = PromoteRequest::new(key.clone(), value.clone(), Version::ZERO, Ttl::INFINITE);
+        let request =
+            PromoteRequest::new(key.clone(), value.clone(), Version::ZERO, TtlAt::INFINITE);
         assert_eq!(request.header, IoctlHeader::new(IoctlCommand::Promote));
         assert_eq!(request.key, key);
         assert_eq!(request.value, value);
         assert_eq!(request.version, Version::ZERO);
-        assert_eq!(request.ttl, Ttl::INFINITE);
+        assert_eq!(request.ttl, TtlAt::INFINITE);
     }
 
     #[test]
@@ -596,13 +1041,33 @@ mod tests {
         let response = PromoteResponse::new(STATUS_OK);
         assert_eq!(response.header, IoctlHeader::new(IoctlCommand::Promote));
         assert_eq!(response.status, STATUS_OK);
-        assert_eq!(response.reserved, 0);
+        assert_eq!(response.outcome(), Ok(PromoteOutcome::Unspecified));
+        assert_eq!(response.evicted_key(), None);
     }
 
     #[test]
     fn test_promote_struct_sizes() {
         assert_eq!(std::mem::size_of::<PromoteRequest>(), 1304);
-        assert_eq!(std::mem::size_of::<PromoteResponse>(), 8);
+        assert_eq!(std::mem::size_of::<PromoteResponse>(), 266);
+        assert_eq!(std::mem::offset_of!(PromoteResponse, outcome), 6);
+        assert_eq!(std::mem::offset_of!(PromoteResponse, evicted), 8);
+        assert_eq!(std::mem::size_of::<DemoteResponse>(), 8);
+    }
+
+    #[test]
+    fn test_promote_outcome_wire_values() {
+        for value in 0..=5 {
+            let outcome = PromoteOutcome::from_u16(value).unwrap();
+            assert_eq!(outcome.as_u16(), value);
+        }
+        assert_eq!(PromoteOutcome::from_u16(6), None);
+        assert!(PromoteOutcome::RejectedStale.is_settled());
+        assert!(!PromoteOutcome::RejectedStale.is_stored());
+        assert!(!PromoteOutcome::RejectedFull.is_settled());
+
+        let mut response = PromoteResponse::new(STATUS_OK);
+        response.outcome = 6;
+        assert_eq!(response.outcome(), Err(HkvError::ProtocolViolation));
     }
 
     #[test]
@@ -645,10 +1110,20 @@ mod tests {
         assert_eq!(request.version, Version::new(42));
     }
 
+    #[test]
+    fn test_invalidate_response_new() {
+        let response = InvalidateResponse::new(STATUS_OK, true);
+        assert_eq!(response.header, IoctlHeader::new(IoctlCommand::Invalidate));
+        assert_eq!((response.status, response.removed), (STATUS_OK, 1));
+        assert!(response.was_removed());
+        assert!(!InvalidateResponse::new(STATUS_OK, false).was_removed());
+    }
+
     #[test]
     fn test_demote_invalidate_sizes() {
         assert_eq!(std::mem::size_of::<DemoteRequest>(), 262);
         assert_eq!(std::mem::size_of::<InvalidateRequest>(), 272);
+        assert_eq!(std::mem::size_of::<InvalidateResponse>(), 8);
     }
 
     #[test]
@@ -673,6 +1148,7 @@ mod tests {
             entry_count: 11,
             lock_contentions: 12,
             rcu_grace_periods: 13,
+            expired: 14,
         };
         let response = StatsResponse::new(STATUS_OK, stats);
         assert_eq!(response.header, IoctlHeader::new(IoctlCommand::Stats));
@@ -683,9 +1159,9 @@ mod tests {
 
     #[test]
     fn test_stats_struct_sizes() {
-        assert_eq!(std::mem::size_of::<CacheStats>(), 104);
+        assert_eq!(std::mem::size_of::<CacheStats>(), 112);
         assert_eq!(std::mem::size_of::<StatsRequest>(), 4);
-        assert_eq!(std::mem::size_of::<StatsResponse>(), 112);
+        assert_eq!(std::mem::size_of::<StatsResponse>(), 120);
     }
 
     #[test]
@@ -705,9 +1181,240 @@ mod tests {
         assert_eq!(request.header, IoctlHeader::new(IoctlCommand::Flush));
     }
 
+    #[test]
+    fn test_flush_response_new() {
+        let response = FlushResponse::new(STATUS_OK, 7);
+        assert_eq!(response.header, IoctlHeader::new(IoctlCommand::Flush));
+        assert_eq!(response.status, STATUS_OK);
+        assert_eq!(response.reserved, 0);
+        assert_eq!(response.removed, 7);
+    }
+
     #[test]
     fn test_config_flush_sizes() {
         assert_eq!(std::mem::size_of::<ConfigRequest>(), 40);
         assert_eq!(std::mem::size_of::<FlushRequest>(), 4);
+        assert_eq!(std::mem::size_of::<FlushResponse>(), 16);
+    }
+
+    #[test]
+    fn test_hello_sizes() {
+        assert_eq!(std::mem::size_of::<HelloRequest>(), 4);
+        assert_eq!(std::mem::size_of::<HelloResponse>(), 12);
+    }
+
+    /// Module stand-in built with smaller limits than the defaults.
+    struct MockModule {
+        limits: Limits,
+        promoted: Vec<PromoteRequest>,
+        capacity: usize,
+        /// Simulates another CPU holding the cache lock.
+        contended: bool,
+    }
+
+    impl MockModule {
+        fn hello(&self, request: HelloRequest) -> HelloResponse {
+            assert_eq!(request.header, IoctlHeader::new(IoctlCommand::Hello));
+            HelloResponse::new(STATUS_OK, self.limits)
+        }
+
+        fn read(&self, request: ReadRequest) -> ReadResponse {
+            let flags = request.header.flags().unwrap();
+            if self.contended {
+                assert!(
+                    flags.contains(RequestFlags::NOWAIT),
+                    "read would block on a contended lock"
+                );
+                return ReadResponse::new(HkvError::Busy.code(), Value::new(b"").unwrap());
+            }
+            match self.promoted.iter().find(|e| e.key == request.key) {
+                Some(entry) => ReadResponse::new(STATUS_OK, entry.value.clone()),
+                None => ReadResponse::new(HkvError::NotFound.code(), Value::new(b"").unwrap()),
+            }
+        }
+
+        fn promote(&mut self, request: PromoteRequest) -> PromoteResponse {
+            assert!(
+                self.limits
+                    .admits(request.key.as_bytes(), request.value.as_bytes()),
+                "oversized entry reached the module"
+            );
+            if let Some(slot) = self.promoted.iter_mut().find(|e| e.key == request.key) {
+                *slot = request;
+                return PromoteResponse::with_outcome(
+                    STATUS_OK,
+                    PromoteOutcome::ReplacedExisting,
+                    None,
+                );
+            }
+            if self.promoted.len() < self.capacity {
+                self.promoted.push(request);
+                return PromoteResponse::with_outcome(STATUS_OK, PromoteOutcome::Admitted, None);
+            }
+            // Full: evict the oldest entry if it has expired, like the module.
+            if self.promoted[0].ttl.is_expired(NOW) {
+                let evicted = self.promoted.remove(0).key;
+                self.promoted.push(request);
+                return PromoteResponse::with_outcome(
+                    STATUS_OK,
+                    PromoteOutcome::EvictedOther,
+                    Some(evicted),
+                );
+            }
+            PromoteResponse::with_outcome(
+                HkvError::CapacityExceeded.code(),
+                PromoteOutcome::RejectedFull,
+                None,
+            )
+        }
+    }
+
+    #[test]
+    fn test_negotiated_limits_skip_oversized_promotions() {
+        let mut module = MockModule {
+            limits: Limits::new(8, 256).unwrap(),
+            promoted: Vec::new(),
+            capacity: 16,
+            contended: false,
+        };
+        let limits = module
+            .hello(HelloRequest::new())
+            .negotiated_limits()
+            .unwrap();
+        assert_eq!(limits, Limits::new(8, 256).unwrap());
+
+        let candidates: [(&[u8], &[u8]); 3] = [
+            (b"small", b"fits"),
+            (b"key-longer-than-8", b"v"),
+            (b"big", &[b'v'; 512]),
+        ];
+        for (key, value) in candidates {
+            if !limits.admits(key, value) {
+                continue;
+            }
+            let request = PromoteRequest::new(
+                Key::new(key).unwrap(),
+                Value::new(value).unwrap(),
+                Version::new(1),
+                TtlAt::INFINITE,
+            );
+            assert_eq!(module.promote(request).status, STATUS_OK);
+        }
+
+        assert_eq!(module.promoted.len(), 1);
+        assert_eq!(module.promoted[0].key.as_bytes(), b"small");
+    }
+
+    const NOW: u64 = 1_000;
+
+    fn promote_request(key: &[u8], ttl: TtlAt) -> PromoteRequest {
+        PromoteRequest::new(
+            Key::new(key).unwrap(),
+            Value::new(b"v").unwrap(),
+            Version::new(1),
+            ttl,
+        )
+    }
+
+    #[test]
+    fn test_promote_outcomes_drive_bookkeeping_and_metrics() {
+        let mut module = MockModule {
+            limits: Limits::MAX,
+            promoted: Vec::new(),
+            capacity: 2,
+            contended: false,
+        };
+        let mut promoted_keys: Vec<Key> = Vec::new();
+        let mut metrics = PromoteMetrics::default();
+        let mut promote = |key: &[u8], ttl: TtlAt| {
+            let response = module.promote(promote_request(key, ttl));
+            metrics.record(&response);
+            if let Some(evicted) = response.evicted_key() {
+                promoted_keys.retain(|k| k != evicted);
+            }
+            let outcome = response.outcome().unwrap();
+            if outcome.is_stored() && !promoted_keys.iter().any(|k| k.as_bytes() == key) {
+                promoted_keys.push(Key::new(key).unwrap());
+            }
+            outcome
+        };
+
+        assert_eq!(
+            promote(b"old", TtlAt::from_unix_nanos(NOW - 1)),
+            PromoteOutcome::Admitted
+        );
+        assert_eq!(promote(b"hot", TtlAt::INFINITE), PromoteOutcome::Admitted);
+        assert_eq!(
+            promote(b"hot", TtlAt::INFINITE),
+            PromoteOutcome::ReplacedExisting
+        );
+        assert_eq!(
+            promote(b"new", TtlAt::INFINITE),
+            PromoteOutcome::EvictedOther
+        );
+        assert_eq!(
+            promote(b"more", TtlAt::INFINITE),
+            PromoteOutcome::RejectedFull
+        );
+
+        let keys: Vec<&[u8]> = promoted_keys.iter().map(Key::as_bytes).collect();
+        assert_eq!(keys, [b"hot".as_slice(), b"new"]);
+        assert_eq!(
+            metrics,
+            PromoteMetrics {
+                promotions_admitted: 4,
+                promotions_rejected: 1,
+                promotions_evicting: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_nowait_read_falls_back_when_kernel_is_busy() {
+        let mut module = MockModule {
+            limits: Limits::MAX,
+            promoted: Vec::new(),
+            capacity: 4,
+            contended: false,
+        };
+        module.promote(promote_request(b"hot", TtlAt::INFINITE));
+        let slow_path = |key: &[u8]| format!("engine:{}", String::from_utf8_lossy(key));
+
+        // Hot GET path: try the kernel without waiting, else ask the engine.
+        let get = |module: &MockModule, key: &[u8]| {
+            let request = ReadRequest::new(Key::new(key).unwrap()).with_flags(RequestFlags::NOWAIT);
+            let response = module.read(request);
+            match HkvError::from_code(response.status) {
+                None => String::from_utf8_lossy(response.value.as_bytes()).into_owned(),
+                Some(HkvError::Busy | HkvError::NotFound) => slow_path(key),
+                Some(err) => panic!("unexpected {err:?}"),
+            }
+        };
+
+        assert_eq!(get(&module, b"hot"), "v");
+        assert_eq!(get(&module, b"cold"), "engine:cold");
+
+        module.contended = true;
+        assert_eq!(get(&module, b"hot"), "engine:hot");
+        assert!(HkvError::Busy.is_retryable());
+    }
+
+    #[test]
+    fn test_negotiated_limits_reject_bad_responses() {
+        let mut response = HelloResponse::new(STATUS_OK, Limits::MAX);
+        assert_eq!(response.negotiated_limits(), Ok(Limits::MAX));
+
+        response.limits.max_value = u16::MAX;
+        assert_eq!(
+            response.negotiated_limits(),
+            Err(HkvError::ProtocolViolation)
+        );
+
+        let failed = HelloResponse::new(HkvError::Busy.code(), Limits::MAX);
+        assert_eq!(failed.negotiated_limits(), Err(HkvError::Busy));
+
+        let mut stale = HelloResponse::new(STATUS_OK, Limits::MAX);
+        stale.header.version = PROTOCOL_VERSION + 1;
+        assert_eq!(stale.negotiated_limits(), Err(HkvError::VersionMismatch));
     }
 }
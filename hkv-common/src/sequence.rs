@@ -0,0 +1,284 @@
+//! # Request Sequencing
+//!
+//! Sequence numbers let asynchronous transports (shared rings, io_uring)
+//! complete requests out of order and still match each response to the
+//! submission slot that issued it.
+//!
+//! ## Design Principles
+//!
+//! 1. **Opt-In**: The v2 header carries a `u32` sequence; the synchronous
+//!    ioctl path leaves it at `SEQ_SYNC` (0) and skips matching.
+//! 2. **Monotonic, Never Zero**: The generator wraps past `u32::MAX` to 1 so
+//!    a live request is never confused with a synchronous one.
+//! 3. **Fail Closed**: A completion whose sequence or command does not match
+//!    its slot is a `ProtocolViolation`; the slot stays pending.
+//! 4. **No Allocation**: Slot bookkeeping is a fixed array sized by the ring.
+//!
+//! ## Memory Layout
+//!
+//! ```text
+//! SequencedHeader (8 bytes total):
+//! +-------+---------+---------+-------+--------+
+//! | magic | version | command | flags | seq:4B |
+//! +-------+---------+---------+-------+--------+
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::error::{HkvError, HkvResult};
+use crate::ioctl::{IoctlCommand, IOCTL_MAGIC};
+
+/// Protocol version that carries a sequence number in the header.
+pub const PROTOCOL_VERSION_V2: u8 = 2;
+
+/// Sequence value used by synchronous requests.
+pub const SEQ_SYNC: u32 = 0;
+
+/// v2 request/response header with a completion sequence number.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SequencedHeader {
+    /// Magic number to validate the device protocol.
+    pub magic: u8,
+    /// Protocol version (`PROTOCOL_VERSION_V2`).
+    pub version: u8,
+    /// Command number describing the request.
+    pub command: u8,
+    /// Reserved for request flags; must be zero.
+    pub flags: u8,
+    /// Sequence number echoed by the kernel in the response.
+    pub seq: u32,
+}
+
+impl SequencedHeader {
+    /// Builds a header for an asynchronous request.
+    pub const fn new(command: IoctlCommand, seq: u32) -> Self {
+        SequencedHeader {
+            magic: IOCTL_MAGIC,
+            version: PROTOCOL_VERSION_V2,
+            command: command.as_u8(),
+            flags: 0,
+            seq,
+        }
+    }
+
+    /// Builds a header for the synchronous ioctl path (`seq` = 0).
+    pub const fn synchronous(command: IoctlCommand) -> Self {
+        Self::new(command, SEQ_SYNC)
+    }
+
+    /// Returns true when the header belongs to the synchronous path.
+    pub const fn is_synchronous(&self) -> bool {
+        self.seq == SEQ_SYNC
+    }
+
+    /// Checks that `response` completes the request described by `self`.
+    pub fn validate_completion(&self, response: &SequencedHeader) -> HkvResult<()> {
+        if response.magic != IOCTL_MAGIC {
+            return Err(HkvError::ProtocolViolation);
+        }
+        if response.version != self.version {
+            return Err(HkvError::VersionMismatch);
+        }
+        if response.command != self.command || response.seq != self.seq {
+            return Err(HkvError::ProtocolViolation);
+        }
+        Ok(())
+    }
+}
+
+/// Thread-safe source of monotonically increasing, non-zero sequences.
+#[derive(Debug)]
+pub struct SequenceGenerator {
+    next: AtomicU32,
+}
+
+impl SequenceGenerator {
+    /// Creates a generator whose first sequence is 1.
+    pub const fn new() -> Self {
+        SequenceGenerator {
+            next: AtomicU32::new(1),
+        }
+    }
+
+    /// Returns the next sequence, skipping `SEQ_SYNC` on wrap-around.
+    pub fn next(&self) -> u32 {
+        loop {
+            let seq = self.next.fetch_add(1, Ordering::Relaxed);
+            if seq != SEQ_SYNC {
+                return seq;
+            }
+        }
+    }
+}
+
+impl Default for SequenceGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Submission bookkeeping for a ring with `N` slots.
+///
+/// Each slot remembers the header it was submitted with until a matching
+/// completion arrives.
+#[derive(Debug, Clone)]
+pub struct CompletionSlots<const N: usize> {
+    pending: [Option<SequencedHeader>; N],
+}
+
+impl<const N: usize> CompletionSlots<N> {
+    /// Creates bookkeeping with every slot free.
+    pub const fn new() -> Self {
+        CompletionSlots { pending: [None; N] }
+    }
+
+    /// Records a submission in `slot`.
+    ///
+    /// Returns `InvalidInput` for out-of-range slots or synchronous headers,
+    /// and `Busy` when the slot still has a request in flight.
+    pub fn submit(&mut self, slot: usize, header: SequencedHeader) -> HkvResult<()> {
+        let pending = self.pending.get_mut(slot).ok_or(HkvError::InvalidInput)?;
+        if header.is_synchronous() {
+            return Err(HkvError::InvalidInput);
+        }
+        if pending.is_some() {
+            return Err(HkvError::Busy);
+        }
+        *pending = Some(header);
+        Ok(())
+    }
+
+    /// Matches a completion to `slot` and frees it on success.
+    ///
+    /// Mismatched or unexpected completions return `ProtocolViolation` and
+    /// leave the slot untouched.
+    pub fn complete(&mut self, slot: usize, response: &SequencedHeader) -> HkvResult<()> {
+        let pending = self
+            .pending
+            .get_mut(slot)
+            .ok_or(HkvError::ProtocolViolation)?;
+        let request = pending.as_ref().ok_or(HkvError::ProtocolViolation)?;
+        request.validate_completion(response)?;
+        *pending = None;
+        Ok(())
+    }
+
+    /// Number of requests awaiting completion.
+    pub fn in_flight(&self) -> usize {
+        self.pending.iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
+impl<const N: usize> Default for CompletionSlots<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Mock device that queues submissions and completes them in reverse.
+    #[derive(Default)]
+    struct MockRingDevice {
+        queued: VecDeque<(usize, SequencedHeader)>,
+    }
+
+    impl MockRingDevice {
+        fn submit(&mut self, slot: usize, header: SequencedHeader) {
+            self.queued.push_back((slot, header));
+        }
+
+        fn complete_reversed(&mut self) -> Vec<(usize, SequencedHeader)> {
+            self.queued.drain(..).rev().collect()
+        }
+    }
+
+    #[test]
+    fn header_is_eight_bytes() {
+        assert_eq!(std::mem::size_of::<SequencedHeader>(), 8);
+        assert_eq!(std::mem::align_of::<SequencedHeader>(), 4);
+    }
+
+    #[test]
+    fn generator_is_monotonic_and_skips_zero() {
+        let generator = SequenceGenerator::new();
+        assert_eq!(generator.next(), 1);
+        assert_eq!(generator.next(), 2);
+
+        let wrapping = SequenceGenerator {
+            next: AtomicU32::new(u32::MAX),
+        };
+        assert_eq!(wrapping.next(), u32::MAX);
+        assert_eq!(wrapping.next(), 1);
+    }
+
+    #[test]
+    fn out_of_order_completions_match_their_slots() {
+        let generator = SequenceGenerator::new();
+        let mut slots = CompletionSlots::<4>::new();
+        let mut device = MockRingDevice::default();
+
+        for (slot, command) in [
+            IoctlCommand::Read,
+            IoctlCommand::Promote,
+            IoctlCommand::Read,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let header = SequencedHeader::new(command, generator.next());
+            slots.submit(slot, header).unwrap();
+            device.submit(slot, header);
+        }
+        assert_eq!(slots.in_flight(), 3);
+
+        for (slot, response) in device.complete_reversed() {
+            slots.complete(slot, &response).unwrap();
+        }
+        assert_eq!(slots.in_flight(), 0);
+    }
+
+    #[test]
+    fn mismatched_completion_is_protocol_violation() {
+        let generator = SequenceGenerator::new();
+        let mut slots = CompletionSlots::<2>::new();
+        let first = SequencedHeader::new(IoctlCommand::Read, generator.next());
+        let second = SequencedHeader::new(IoctlCommand::Read, generator.next());
+        slots.submit(0, first).unwrap();
+        slots.submit(1, second).unwrap();
+
+        // The device routed slot 1's response to slot 0.
+        assert_eq!(slots.complete(0, &second), Err(HkvError::ProtocolViolation));
+        assert_eq!(slots.in_flight(), 2);
+
+        let wrong_command = SequencedHeader {
+            command: IoctlCommand::Promote.as_u8(),
+            ..first
+        };
+        assert_eq!(
+            slots.complete(0, &wrong_command),
+            Err(HkvError::ProtocolViolation)
+        );
+
+        slots.complete(0, &first).unwrap();
+        assert_eq!(slots.complete(0, &first), Err(HkvError::ProtocolViolation));
+    }
+
+    #[test]
+    fn submit_rejects_busy_out_of_range_and_sync_slots() {
+        let mut slots = CompletionSlots::<1>::new();
+        let header = SequencedHeader::new(IoctlCommand::Stats, 7);
+
+        slots.submit(0, header).unwrap();
+        assert_eq!(slots.submit(0, header), Err(HkvError::Busy));
+        assert_eq!(slots.submit(1, header), Err(HkvError::InvalidInput));
+        assert_eq!(
+            CompletionSlots::<1>::new().submit(0, SequencedHeader::synchronous(IoctlCommand::Read)),
+            Err(HkvError::InvalidInput)
+        );
+    }
+}
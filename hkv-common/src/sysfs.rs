@@ -0,0 +1,106 @@
+//! # Kernel Cache Tunables
+//!
+//! Reads the kernel module's `/sys/kernel/hybridkv/` attributes, so user
+//! space can report the configured limits without an ioctl.
+//!
+//! ## Design Principles
+//!
+//! 1. **Plain Files**: Every attribute holds one decimal number and a
+//!    newline; `read_attribute` parses exactly that and reports anything else
+//!    as `InvalidData`.
+//! 2. **Diagnostics Only**: Nothing here writes the tunables; operators
+//!    change them with `echo` as root.
+//! 3. **Relocatable**: `KernelTunables::read_from` takes the directory, so
+//!    tests use a scratch directory instead of a loaded module.
+
+use std::io;
+use std::path::Path;
+
+/// Directory of the kernel module's sysfs attributes.
+pub const SYSFS_DIR: &str = "/sys/kernel/hybridkv";
+
+/// The kernel cache's run-time limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KernelTunables {
+    /// Memory budget for entries with their keys and values.
+    pub max_bytes: u64,
+    /// Most entries the cache holds.
+    pub max_entries: u64,
+    /// Longest entry lifetime in seconds; 0 when off.
+    pub default_ttl_override: u64,
+}
+
+impl KernelTunables {
+    /// Reads the limits of the loaded module from `SYSFS_DIR`.
+    pub fn read() -> io::Result<Self> {
+        Self::read_from(Path::new(SYSFS_DIR))
+    }
+
+    /// Reads the limits from the attribute files in `dir`.
+    pub fn read_from(dir: &Path) -> io::Result<Self> {
+        Ok(KernelTunables {
+            max_bytes: read_attribute(dir, "max_bytes")?,
+            max_entries: read_attribute(dir, "max_entries")?,
+            default_ttl_override: read_attribute(dir, "default_ttl_override")?,
+        })
+    }
+}
+
+/// Reads the numeric attribute `name` in `dir`, e.g. `hits`.
+pub fn read_attribute(dir: &Path, name: &str) -> io::Result<u64> {
+    let text = std::fs::read_to_string(dir.join(name))?;
+    text.trim_end().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{name}: not a number: {text:?}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hkv-sysfs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_every_tunable() {
+        let dir = scratch_dir("ok");
+        fs::write(dir.join("max_bytes"), "1048576\n").unwrap();
+        fs::write(dir.join("max_entries"), "4093\n").unwrap();
+        fs::write(dir.join("default_ttl_override"), "0\n").unwrap();
+
+        let tunables = KernelTunables::read_from(&dir).unwrap();
+        assert_eq!(
+            tunables,
+            KernelTunables {
+                max_bytes: 1_048_576,
+                max_entries: 4093,
+                default_ttl_override: 0,
+            }
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_or_garbled_attributes_fail() {
+        let dir = scratch_dir("bad");
+        assert_eq!(
+            KernelTunables::read_from(&dir).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        fs::write(dir.join("hits"), "lots\n").unwrap();
+        let err = read_attribute(&dir, "hits").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,485 @@
+//! # Golden Wire Vectors
+//!
+//! Hand-written byte images of every fixed-size ioctl payload, paired with
+//! the message they encode. Exported behind the `test-vectors` feature so the
+//! kernel module's tests check its ABI mirror against the same bytes.
+//!
+//! ## Design Principles
+//!
+//! 1. **Written Out, Not Encoded**: Headers, integers, and length prefixes are
+//!    byte literals; only zero-filled buffer tails are generated. A vector
+//!    never depends on `WireFormat`, so an encoder bug cannot bless itself.
+//! 2. **Edge Cases First**: Empty and maximum-length keys and values,
+//!    infinite TTL, version zero, request flags, and every defined status
+//!    code each have their own vector.
+//! 3. **Both Directions**: Tests assert that encoding the message yields the
+//!    bytes and decoding the bytes yields the message.
+
+use crate::error::HkvResult;
+use crate::protocol::*;
+use crate::types::{Key, Limits, TtlAt, Value, Version, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use crate::wire::WireFormat;
+
+macro_rules! messages {
+    ($($ty:ident),* $(,)?) => {
+        /// A decoded payload of any fixed-size ioctl type.
+        // Variants mirror the ioctl structs by value; vectors are test data,
+        // so boxing the large ones would only add noise.
+        #[allow(clippy::large_enum_variant)]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum Message {
+            $($ty($ty),)*
+        }
+
+        impl Message {
+            /// Encodes the payload with `WireFormat`.
+            pub fn to_wire(&self) -> Vec<u8> {
+                match self {
+                    $(Message::$ty(m) => m.to_wire(),)*
+                }
+            }
+
+            /// Decodes `bytes` as the same payload type as `self`.
+            ///
+            /// # Errors
+            /// Returns the `WireFormat::decode` error.
+            pub fn decode_like(&self, bytes: &[u8]) -> HkvResult<Message> {
+                match self {
+                    $(Message::$ty(_) => $ty::decode(bytes).map(Message::$ty),)*
+                }
+            }
+
+            /// Returns the payload type name.
+            pub fn type_name(&self) -> &'static str {
+                match self {
+                    $(Message::$ty(_) => stringify!($ty),)*
+                }
+            }
+        }
+    };
+}
+
+messages!(
+    ReadRequest,
+    ReadResponse,
+    PromoteRequest,
+    PromoteResponse,
+    DemoteRequest,
+    DemoteResponse,
+    InvalidateRequest,
+    InvalidateResponse,
+    StatsRequest,
+    StatsResponse,
+    ConfigRequest,
+    FlushRequest,
+    FlushResponse,
+    HelloRequest,
+    HelloResponse,
+);
+
+/// One golden vector: a message and its exact wire bytes.
+#[derive(Debug, Clone)]
+pub struct TestVector {
+    /// Unique, descriptive name.
+    pub name: &'static str,
+    /// The payload the bytes encode.
+    pub message: Message,
+    /// The wire bytes.
+    pub bytes: Vec<u8>,
+}
+
+/// Concatenates byte fields.
+fn cat(fields: &[&[u8]]) -> Vec<u8> {
+    fields.concat()
+}
+
+/// A length-prefixed buffer: `len` (already little-endian), `data`, then
+/// zeros up to `capacity`.
+fn buffer(len: [u8; 2], data: &[u8], capacity: usize) -> Vec<u8> {
+    assert_eq!(usize::from(u16::from_le_bytes(len)), data.len());
+    let mut out = cat(&[&len, data]);
+    out.resize(2 + capacity, 0);
+    out
+}
+
+fn key_field(len: [u8; 2], data: &[u8]) -> Vec<u8> {
+    buffer(len, data, MAX_KEY_SIZE)
+}
+
+fn value_field(len: [u8; 2], data: &[u8]) -> Vec<u8> {
+    buffer(len, data, MAX_VALUE_SIZE)
+}
+
+fn key(data: &[u8]) -> Key {
+    Key::new(data).unwrap()
+}
+
+fn value(data: &[u8]) -> Value {
+    Value::new(data).unwrap()
+}
+
+// Headers: magic 'H' (0x48), PROTOCOL_VERSION 4, command, flags.
+const HDR_READ: [u8; 4] = [0x48, 0x04, 0x00, 0x00];
+const HDR_PROMOTE: [u8; 4] = [0x48, 0x04, 0x01, 0x00];
+const HDR_DEMOTE: [u8; 4] = [0x48, 0x04, 0x03, 0x00];
+const HDR_INVALIDATE: [u8; 4] = [0x48, 0x04, 0x04, 0x00];
+const HDR_STATS: [u8; 4] = [0x48, 0x04, 0x05, 0x00];
+const HDR_CONFIG: [u8; 4] = [0x48, 0x04, 0x06, 0x00];
+const HDR_FLUSH: [u8; 4] = [0x48, 0x04, 0x07, 0x00];
+const HDR_HELLO: [u8; 4] = [0x48, 0x04, 0x08, 0x00];
+
+const STATUS_OK_LE: [u8; 2] = [0x00, 0x00];
+const RESERVED_LE: [u8; 2] = [0x00, 0x00];
+
+/// Every defined status: `STATUS_OK` plus each `HkvError` code.
+const STATUSES: [(&str, [u8; 2]); 14] = [
+    ("demote_response_ok", [0, 0]),
+    ("demote_response_invalid_input", [1, 0]),
+    ("demote_response_not_found", [2, 0]),
+    ("demote_response_key_too_long", [3, 0]),
+    ("demote_response_value_too_long", [4, 0]),
+    ("demote_response_out_of_memory", [10, 0]),
+    ("demote_response_capacity_exceeded", [11, 0]),
+    ("demote_response_internal_error", [12, 0]),
+    ("demote_response_busy", [20, 0]),
+    ("demote_response_timeout", [21, 0]),
+    ("demote_response_interrupted", [22, 0]),
+    ("demote_response_version_mismatch", [30, 0]),
+    ("demote_response_protocol_violation", [31, 0]),
+    ("demote_response_unsupported_command", [32, 0]),
+];
+
+/// Returns every golden vector.
+pub fn test_vectors() -> Vec<TestVector> {
+    let max_key = [b'k'; MAX_KEY_SIZE];
+    let max_value = [b'v'; MAX_VALUE_SIZE];
+    let mut vectors = Vec::new();
+    let mut push = |name, message, bytes| {
+        vectors.push(TestVector {
+            name,
+            message,
+            bytes,
+        })
+    };
+
+    // READ
+    push(
+        "read_request",
+        Message::ReadRequest(ReadRequest::new(key(b"user:42"))),
+        cat(&[&HDR_READ, &key_field([7, 0], b"user:42")]),
+    );
+    push(
+        "read_request_empty_key",
+        Message::ReadRequest(ReadRequest::new(key(b""))),
+        cat(&[&HDR_READ, &key_field([0, 0], b"")]),
+    );
+    push(
+        "read_request_max_key",
+        Message::ReadRequest(ReadRequest::new(key(&max_key))),
+        cat(&[&HDR_READ, &key_field([0x00, 0x01], &max_key)]),
+    );
+    push(
+        "read_request_nowait_no_promote_stats",
+        Message::ReadRequest(
+            ReadRequest::new(key(b"k"))
+                .with_flags(RequestFlags::NOWAIT | RequestFlags::NO_PROMOTE_STATS),
+        ),
+        cat(&[&[0x48, 0x04, 0x00, 0x03], &key_field([1, 0], b"k")]),
+    );
+    push(
+        "read_response_hit",
+        Message::ReadResponse(ReadResponse::new(STATUS_OK, value(b"hello"))),
+        cat(&[&HDR_READ, &STATUS_OK_LE, &value_field([5, 0], b"hello")]),
+    );
+    push(
+        "read_response_max_value",
+        Message::ReadResponse(ReadResponse::new(STATUS_OK, value(&max_value))),
+        cat(&[
+            &HDR_READ,
+            &STATUS_OK_LE,
+            &value_field([0x00, 0x04], &max_value),
+        ]),
+    );
+    push(
+        "read_response_not_found",
+        Message::ReadResponse(ReadResponse::new(2, value(b""))),
+        cat(&[&HDR_READ, &[0x02, 0x00], &value_field([0, 0], b"")]),
+    );
+
+    // PROMOTE
+    push(
+        "promote_request",
+        Message::PromoteRequest(PromoteRequest::new(
+            key(b"k"),
+            value(b"v"),
+            Version::new(0x0102_0304_0506_0708),
+            TtlAt::from_unix_nanos(0x1122_3344_5566_7788),
+        )),
+        cat(&[
+            &HDR_PROMOTE,
+            &key_field([1, 0], b"k"),
+            &value_field([1, 0], b"v"),
+            &[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01],
+            &[0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+        ]),
+    );
+    push(
+        "promote_request_version_zero_infinite_ttl",
+        Message::PromoteRequest(PromoteRequest::new(
+            key(b"k"),
+            value(b""),
+            Version::new(0),
+            TtlAt::INFINITE,
+        )),
+        cat(&[
+            &HDR_PROMOTE,
+            &key_field([1, 0], b"k"),
+            &value_field([0, 0], b""),
+            &[0x00; 8],
+            &[0xff; 8],
+        ]),
+    );
+    push(
+        "promote_request_max_key_and_value",
+        Message::PromoteRequest(PromoteRequest::new(
+            key(&max_key),
+            value(&max_value),
+            Version::new(u64::MAX),
+            TtlAt::from_unix_nanos(1),
+        )),
+        cat(&[
+            &HDR_PROMOTE,
+            &key_field([0x00, 0x01], &max_key),
+            &value_field([0x00, 0x04], &max_value),
+            &[0xff; 8],
+            &[0x01, 0, 0, 0, 0, 0, 0, 0],
+        ]),
+    );
+    push(
+        "promote_response_admitted",
+        Message::PromoteResponse(PromoteResponse {
+            outcome: PromoteOutcome::Admitted.as_u16(),
+            ..PromoteResponse::new(STATUS_OK)
+        }),
+        cat(&[
+            &HDR_PROMOTE,
+            &STATUS_OK_LE,
+            &[0x01, 0x00],
+            &key_field([0, 0], b""),
+        ]),
+    );
+    push(
+        "promote_response_evicted_other",
+        Message::PromoteResponse(PromoteResponse {
+            outcome: PromoteOutcome::EvictedOther.as_u16(),
+            evicted: key(b"old"),
+            ..PromoteResponse::new(STATUS_OK)
+        }),
+        cat(&[
+            &HDR_PROMOTE,
+            &STATUS_OK_LE,
+            &[0x03, 0x00],
+            &key_field([3, 0], b"old"),
+        ]),
+    );
+    push(
+        "promote_response_rejected_full",
+        Message::PromoteResponse(PromoteResponse {
+            outcome: PromoteOutcome::RejectedFull.as_u16(),
+            ..PromoteResponse::new(11)
+        }),
+        cat(&[
+            &HDR_PROMOTE,
+            &[0x0b, 0x00],
+            &[0x04, 0x00],
+            &key_field([0, 0], b""),
+        ]),
+    );
+
+    // DEMOTE
+    push(
+        "demote_request_quiet",
+        Message::DemoteRequest(DemoteRequest::new(key(b"k")).with_flags(RequestFlags::QUIET)),
+        cat(&[&[0x48, 0x04, 0x03, 0x04], &key_field([1, 0], b"k")]),
+    );
+    for (name, status) in STATUSES {
+        push(
+            name,
+            Message::DemoteResponse(DemoteResponse::new(u16::from_le_bytes(status))),
+            cat(&[&HDR_DEMOTE, &status, &RESERVED_LE]),
+        );
+    }
+
+    // INVALIDATE
+    push(
+        "invalidate_request",
+        Message::InvalidateRequest(InvalidateRequest::new(key(b"k"), Version::new(42))),
+        cat(&[
+            &HDR_INVALIDATE,
+            &key_field([1, 0], b"k"),
+            &[0x00, 0x00],
+            &[0x2a, 0, 0, 0, 0, 0, 0, 0],
+        ]),
+    );
+    push(
+        "invalidate_request_version_zero_nowait",
+        Message::InvalidateRequest(
+            InvalidateRequest::new(key(b"k"), Version::ZERO).with_flags(RequestFlags::NOWAIT),
+        ),
+        cat(&[
+            &[0x48, 0x04, 0x04, 0x01],
+            &key_field([1, 0], b"k"),
+            &[0x00, 0x00],
+            &[0x00; 8],
+        ]),
+    );
+    push(
+        "invalidate_response_removed",
+        Message::InvalidateResponse(InvalidateResponse::new(STATUS_OK, true)),
+        cat(&[&HDR_INVALIDATE, &STATUS_OK_LE, &[0x01, 0x00]]),
+    );
+    push(
+        "invalidate_response_kept",
+        Message::InvalidateResponse(InvalidateResponse::new(STATUS_OK, false)),
+        cat(&[&HDR_INVALIDATE, &STATUS_OK_LE, &[0x00, 0x00]]),
+    );
+
+    // STATS
+    push(
+        "stats_request",
+        Message::StatsRequest(StatsRequest::new()),
+        HDR_STATS.to_vec(),
+    );
+    let mut stats_bytes = cat(&[&HDR_STATS, &STATUS_OK_LE, &RESERVED_LE]);
+    for counter in 1u8..=14 {
+        stats_bytes.extend_from_slice(&[counter, 0, 0, 0, 0, 0, 0, 0]);
+    }
+    push(
+        "stats_response",
+        Message::StatsResponse(StatsResponse::new(
+            STATUS_OK,
+            CacheStats {
+                lookups: 1,
+                hits: 2,
+                misses: 3,
+                stale_hits: 4,
+                promotions: 5,
+                demotions: 6,
+                evictions: 7,
+                invalidations: 8,
+                used_bytes: 9,
+                max_bytes: 10,
+                entry_count: 11,
+                lock_contentions: 12,
+                rcu_grace_periods: 13,
+                expired: 14,
+            },
+        )),
+        stats_bytes,
+    );
+
+    // CONFIG / FLUSH
+    push(
+        "config_request",
+        Message::ConfigRequest(ConfigRequest::new(1 << 20, 4096, 90, 70)),
+        cat(&[
+            &HDR_CONFIG,
+            &[0x00; 4],
+            &[0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &[0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &[0x5a, 0x00, 0x00, 0x00],
+            &[0x46, 0x00, 0x00, 0x00],
+            &[0x00; 8],
+        ]),
+    );
+    push(
+        "flush_request",
+        Message::FlushRequest(FlushRequest::new()),
+        HDR_FLUSH.to_vec(),
+    );
+    push(
+        "flush_response",
+        Message::FlushResponse(FlushResponse::new(STATUS_OK, 0x0102)),
+        cat(&[
+            &HDR_FLUSH,
+            &STATUS_OK_LE,
+            &RESERVED_LE,
+            &[0x02, 0x01, 0, 0, 0, 0, 0, 0],
+        ]),
+    );
+
+    // HELLO
+    push(
+        "hello_request",
+        Message::HelloRequest(HelloRequest::new()),
+        HDR_HELLO.to_vec(),
+    );
+    push(
+        "hello_response",
+        Message::HelloResponse(HelloResponse::new(STATUS_OK, Limits::default())),
+        cat(&[
+            &HDR_HELLO,
+            &STATUS_OK_LE,
+            &RESERVED_LE,
+            &[0x00, 0x01, 0x00, 0x04],
+        ]),
+    );
+
+    vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::error::HkvError;
+
+    #[test]
+    fn test_encoders_produce_golden_bytes() {
+        for vector in test_vectors() {
+            assert_eq!(
+                vector.message.to_wire(),
+                vector.bytes,
+                "encoding mismatch for {}",
+                vector.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_decoders_parse_golden_bytes() {
+        for vector in test_vectors() {
+            assert_eq!(
+                vector.message.decode_like(&vector.bytes).as_ref(),
+                Ok(&vector.message),
+                "decoding mismatch for {}",
+                vector.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_vectors_cover_every_type_and_status() {
+        let vectors = test_vectors();
+        let names: HashSet<_> = vectors.iter().map(|v| v.name).collect();
+        assert_eq!(names.len(), vectors.len(), "duplicate vector names");
+
+        let types: HashSet<_> = vectors.iter().map(|v| v.message.type_name()).collect();
+        assert_eq!(types.len(), 15);
+
+        let statuses: HashSet<u16> = vectors
+            .iter()
+            .filter_map(|v| match &v.message {
+                Message::DemoteResponse(r) => Some(r.status),
+                _ => None,
+            })
+            .collect();
+        assert!(statuses.contains(&STATUS_OK));
+        for code in 1..=u16::from(u8::MAX) {
+            if HkvError::from_code(code).is_some() {
+                assert!(statuses.contains(&code), "no vector for status {code}");
+            }
+        }
+    }
+}
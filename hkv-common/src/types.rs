@@ -17,9 +17,12 @@
 //! 4. **Version Tracking**: Each entry has a monotonic version counter for consistency
 //!    protocols (write-through invalidation, bounded staleness).
 //!
-//! 5. **TTL Support**: Time-to-live expiration with nanosecond precision using CLOCK_MONOTONIC.
+//! 5. **Typed TTLs**: `TtlAt` is an absolute wall-clock deadline (Unix nanoseconds) and
+//!    `TtlAfter` a relative duration; converting between them requires an explicit `Clock`.
 //!
-//! 6. **Len-Based Eq/Hash**: Compare and hash only initialized bytes to reduce cache traffic.
+//! 6. **Len-Based Eq/Hash/Ord**: Compare, hash, and order only initialized bytes to reduce
+//!    cache traffic. `Key` hashes exactly like its byte slice so maps keyed by `Key` can be
+//!    queried with `&[u8]` through `Borrow`.
 //!
 //! ## Memory Layout Example
 //!
@@ -47,8 +50,11 @@
 //! Note: includes 4B padding between value and metadata.
 //! ```
 
-use std::fmt;
-use std::hash::{Hash, Hasher};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::error::{HkvError, HkvResult};
@@ -59,12 +65,90 @@ pub const MAX_KEY_SIZE: usize = 256;
 /// Maximum value size in bytes (1 KB)
 pub const MAX_VALUE_SIZE: usize = 1024;
 
+/// Key/value size limits advertised by the kernel module in the HELLO handshake
+///
+/// A module may be built with limits below `MAX_KEY_SIZE`/`MAX_VALUE_SIZE`
+/// (e.g. 256-byte values on memory-constrained targets). Buffers keep their
+/// compile-time capacity; these limits decide what the module accepts.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limits {
+    /// Largest accepted key in bytes
+    pub max_key: u16,
+    /// Largest accepted value in bytes
+    pub max_value: u16,
+}
+
+impl Limits {
+    /// Compile-time maxima (what a module built with default settings reports)
+    pub const MAX: Limits = Limits {
+        max_key: MAX_KEY_SIZE as u16,
+        max_value: MAX_VALUE_SIZE as u16,
+    };
+
+    /// Creates limits, rejecting values beyond the fixed buffer capacities
+    ///
+    /// # Errors
+    /// Returns `HkvError::ProtocolViolation` if either limit exceeds
+    /// `MAX_KEY_SIZE`/`MAX_VALUE_SIZE`.
+    pub const fn new(max_key: u16, max_value: u16) -> HkvResult<Self> {
+        if max_key as usize > MAX_KEY_SIZE || max_value as usize > MAX_VALUE_SIZE {
+            return Err(HkvError::ProtocolViolation);
+        }
+        Ok(Limits { max_key, max_value })
+    }
+
+    /// Checks a key against `max_key`
+    ///
+    /// # Errors
+    /// Returns `HkvError::KeyTooLong` if the key is longer than `max_key`.
+    #[inline]
+    pub const fn check_key(&self, key: &[u8]) -> HkvResult<()> {
+        if key.len() > self.max_key as usize {
+            return Err(HkvError::KeyTooLong);
+        }
+        Ok(())
+    }
+
+    /// Checks a value against `max_value`
+    ///
+    /// # Errors
+    /// Returns `HkvError::ValueTooLong` if the value is longer than `max_value`.
+    #[inline]
+    pub const fn check_value(&self, value: &[u8]) -> HkvResult<()> {
+        if value.len() > self.max_value as usize {
+            return Err(HkvError::ValueTooLong);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the module accepts an entry of this shape
+    ///
+    /// Promotion paths use this to skip oversized entries silently instead
+    /// of surfacing an error for data that simply stays in user space.
+    #[inline]
+    pub const fn admits(&self, key: &[u8], value: &[u8]) -> bool {
+        self.check_key(key).is_ok() && self.check_value(value).is_ok()
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::MAX
+    }
+}
+
 /// Key type with bounded size
 ///
 /// Keys are limited to 256 bytes to:
 /// - Enable stack allocation in kernel (no kmalloc in fast path)
 /// - Fit in single cache line for hash computation
 /// - Match typical Redis key sizes (most <100 bytes)
+///
+/// `Eq`, `Hash`, and `Ord` consider only the first `len` bytes, and
+/// `hash(key) == hash(key.as_bytes())` for every hasher. This makes
+/// `Borrow<[u8]>` sound, so `HashMap<Key, V>` and `BTreeMap<Key, V>` can be
+/// queried with a plain `&[u8]` without building a `Key`.
 #[repr(C)]
 #[derive(Clone)]
 pub struct Key {
@@ -83,14 +167,47 @@ impl PartialEq for Key {
 
 impl Eq for Key {}
 
+// Delegate to the slice impl so hashes match `Borrow<[u8]>` lookups.
 impl Hash for Key {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.len.hash(state);
         self.as_bytes().hash(state);
     }
 }
 
+// Bytewise lexicographic order over contents, matching `[u8]` ordering.
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Borrow<[u8]> for Key {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
 impl Key {
+    /// Zero-length key
+    pub const EMPTY: Key = Key {
+        len: 0,
+        data: [0u8; MAX_KEY_SIZE],
+    };
+
     /// Creates a new Key from byte slice
     ///
     /// # Errors
@@ -141,13 +258,13 @@ impl Key {
 
 impl fmt::Debug for Key {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Key({:?})", String::from_utf8_lossy(self.as_bytes()))
+        write!(f, "Key({:?})", Lossy(self.as_bytes()))
     }
 }
 
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", String::from_utf8_lossy(self.as_bytes()))
+        write!(f, "{}", Lossy(self.as_bytes()))
     }
 }
 
@@ -201,11 +318,34 @@ impl Value {
     }
 
     /// Returns the valid value data as a slice
+    ///
+    /// Borrowing, zero-copy view over the used bytes only.
     #[inline]
     pub fn as_bytes(&self) -> &[u8] {
         &self.data[..self.len as usize]
     }
 
+    /// Consumes the value and copies the used bytes into a `Vec` (one copy).
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn into_vec(self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    /// Copies the used bytes into a `bytes::Bytes` (one copy).
+    #[cfg(feature = "bytes")]
+    #[inline]
+    pub fn to_bytes(&self) -> bytes::Bytes {
+        bytes::Bytes::copy_from_slice(self.as_bytes())
+    }
+
+    /// Appends the used bytes to `buf` without an intermediate allocation.
+    #[cfg(feature = "bytes")]
+    #[inline]
+    pub fn write_to(&self, buf: &mut impl bytes::BufMut) {
+        buf.put_slice(self.as_bytes());
+    }
+
     /// Returns the value length
     #[inline]
     pub fn len(&self) -> usize {
@@ -222,7 +362,7 @@ impl Value {
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.len() <= 32 {
-            write!(f, "Value({:?})", String::from_utf8_lossy(self.as_bytes()))
+            write!(f, "Value({:?})", Lossy(self.as_bytes()))
         } else {
             write!(f, "Value({}B)", self.len())
         }
@@ -236,6 +376,11 @@ impl fmt::Debug for Value {
 /// - Detecting stale reads (version mismatch)
 /// - Write-through invalidation protocol
 /// - Bounded staleness (version delta threshold)
+///
+/// Admission: a write carrying a version replaces a cached entry unless the
+/// entry's version is strictly greater (`may_replace`). `Version::ZERO` is
+/// unversioned and always replaces, so callers without versions keep
+/// last-writer-wins behavior.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Version(pub u64);
@@ -268,37 +413,71 @@ impl Version {
     pub const fn next(&self) -> Version {
         Version(self.0.wrapping_add(1))
     }
+
+    /// Returns true if a write at this version may replace an entry cached
+    /// at `current`: it is `ZERO` or not older.
+    #[inline]
+    pub const fn may_replace(self, current: Version) -> bool {
+        self.0 == 0 || self.0 >= current.0
+    }
 }
 
-/// Time-to-live for cache entries
+/// Source of wall-clock time in nanoseconds since the Unix epoch
 ///
-/// Uses nanoseconds since CLOCK_MONOTONIC (not wall clock) to avoid issues
-/// with time adjustments (NTP, DST, timezone changes).
-#[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Ttl(pub u64);
+/// Conversions between `TtlAfter` and `TtlAt` take a clock explicitly so the
+/// caller decides which "now" applies: system time in user space,
+/// `ktime_get_real_ts64` in the kernel, a fixed instant in tests.
+pub trait Clock {
+    /// Returns the current time in nanoseconds since the Unix epoch
+    fn now_unix_nanos(&self) -> u64;
+}
 
-impl Ttl {
-    /// No expiration (infinite TTL)
-    pub const INFINITE: Ttl = Ttl(u64::MAX);
+/// `Clock` backed by `SystemTime::now()`
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
 
-    /// Creates TTL from nanoseconds since boot
-    #[inline]
-    pub const fn from_nanos(nanos: u64) -> Self {
-        Ttl(nanos)
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_unix_nanos(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos() as u64
     }
+}
 
-    /// Creates TTL from duration from now
-    #[inline]
-    pub fn from_duration(duration: Duration) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO);
+/// `Clock` frozen at a given instant (Unix nanoseconds)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now_unix_nanos(&self) -> u64 {
+        self.0
+    }
+}
 
-        Ttl((now + duration).as_nanos() as u64)
+/// Absolute expiration deadline
+///
+/// Wall-clock nanoseconds since the Unix epoch; `u64::MAX` means the entry
+/// never expires. This is what `PromoteRequest` carries across the ioctl
+/// boundary. There is deliberately no `From` conversion to or from
+/// `TtlAfter`; use `TtlAfter::deadline` and `TtlAt::remaining`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TtlAt(pub u64);
+
+impl TtlAt {
+    /// No expiration (infinite TTL)
+    pub const INFINITE: TtlAt = TtlAt(u64::MAX);
+
+    /// Creates a deadline from nanoseconds since the Unix epoch
+    #[inline]
+    pub const fn from_unix_nanos(nanos: u64) -> Self {
+        TtlAt(nanos)
     }
 
-    /// Returns nanoseconds value
+    /// Returns nanoseconds since the Unix epoch
     #[inline]
     pub const fn as_nanos(&self) -> u64 {
         self.0
@@ -311,11 +490,88 @@ impl Ttl {
     }
 
     /// Returns true if entry has expired relative to current time
-    pub fn is_expired(&self, current_nanos: u64) -> bool {
+    pub const fn is_expired(&self, current_nanos: u64) -> bool {
         !self.is_infinite() && current_nanos >= self.0
     }
+
+    /// Time left before the deadline according to `clock`
+    ///
+    /// Returns `None` for `INFINITE` and `TtlAfter::ZERO` once the deadline
+    /// has passed.
+    pub fn remaining(&self, clock: &impl Clock) -> Option<TtlAfter> {
+        if self.is_infinite() {
+            return None;
+        }
+        let nanos = self.0.saturating_sub(clock.now_unix_nanos());
+        Some(TtlAfter(core::time::Duration::from_nanos(nanos)))
+    }
+
+    /// Creates a deadline from Unix nanoseconds
+    #[deprecated(note = "use `TtlAt::from_unix_nanos`")]
+    #[inline]
+    pub const fn from_nanos(nanos: u64) -> Self {
+        TtlAt(nanos)
+    }
+
+    /// Creates a deadline `duration` from now
+    #[cfg(feature = "std")]
+    #[deprecated(note = "use `TtlAfter::new(duration).deadline(&SystemClock)`")]
+    #[inline]
+    pub fn from_duration(duration: Duration) -> Self {
+        TtlAfter(duration).deadline(&SystemClock)
+    }
 }
 
+/// Relative time-to-live ("expire after this long")
+///
+/// What EXPIRE-style APIs accept. Turn it into a `TtlAt` with an explicit
+/// clock before it reaches anything that stores deadlines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TtlAfter(core::time::Duration);
+
+impl TtlAfter {
+    /// Expire immediately
+    pub const ZERO: TtlAfter = TtlAfter(core::time::Duration::ZERO);
+
+    /// Wraps a relative duration
+    #[inline]
+    pub const fn new(duration: core::time::Duration) -> Self {
+        TtlAfter(duration)
+    }
+
+    /// Relative TTL of whole seconds (Redis `EX`)
+    #[inline]
+    pub const fn from_secs(secs: u64) -> Self {
+        TtlAfter(core::time::Duration::from_secs(secs))
+    }
+
+    /// Relative TTL in milliseconds (Redis `PX`)
+    #[inline]
+    pub const fn from_millis(millis: u64) -> Self {
+        TtlAfter(core::time::Duration::from_millis(millis))
+    }
+
+    /// Returns the wrapped duration
+    #[inline]
+    pub const fn as_duration(&self) -> core::time::Duration {
+        self.0
+    }
+
+    /// Absolute deadline `self` from now according to `clock`
+    ///
+    /// Saturates just below `TtlAt::INFINITE` so a huge relative TTL never
+    /// turns into "no expiry".
+    pub fn deadline(&self, clock: &impl Clock) -> TtlAt {
+        let nanos = u64::try_from(self.0.as_nanos()).unwrap_or(u64::MAX);
+        let at = clock.now_unix_nanos().saturating_add(nanos);
+        TtlAt(at.min(u64::MAX - 1))
+    }
+}
+
+/// Time-to-live for cache entries
+#[deprecated(note = "use `TtlAt` for absolute deadlines or `TtlAfter` for relative TTLs")]
+pub type Ttl = TtlAt;
+
 /// Entry flags bitfield
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -385,7 +641,7 @@ pub struct EntryMetadata {
     pub version: Version,
 
     /// Time-to-live expiration
-    pub ttl: Ttl,
+    pub ttl: TtlAt,
 
     /// Creation timestamp (nanoseconds)
     pub created_at: u64,
@@ -405,11 +661,9 @@ pub struct EntryMetadata {
 
 impl EntryMetadata {
     /// Creates new metadata with current timestamp
-    pub fn new(version: Version, ttl: Ttl, key_len: u16, value_len: u16) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO)
-            .as_nanos() as u64;
+    #[cfg(feature = "std")]
+    pub fn new(version: Version, ttl: TtlAt, key_len: u16, value_len: u16) -> Self {
+        let now = SystemClock.now_unix_nanos();
 
         EntryMetadata {
             version,
@@ -423,31 +677,22 @@ impl EntryMetadata {
     }
 
     /// Updates the access timestamp
+    #[cfg(feature = "std")]
     #[inline]
     pub fn touch(&mut self) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO)
-            .as_nanos() as u64;
-        self.accessed_at = now;
+        self.accessed_at = SystemClock.now_unix_nanos();
     }
 
     /// Returns true if entry is expired
+    #[cfg(feature = "std")]
     pub fn is_expired(&self) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO)
-            .as_nanos() as u64;
-        self.ttl.is_expired(now)
+        self.ttl.is_expired(SystemClock.now_unix_nanos())
     }
 
     /// Returns entry age in nanoseconds
+    #[cfg(feature = "std")]
     pub fn age_nanos(&self) -> u64 {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO)
-            .as_nanos() as u64;
-        now.saturating_sub(self.created_at)
+        SystemClock.now_unix_nanos().saturating_sub(self.created_at)
     }
 }
 
@@ -472,7 +717,8 @@ pub struct Entry {
 
 impl Entry {
     /// Creates a new entry with current timestamp
-    pub fn new(key: Key, value: Value, version: Version, ttl: Ttl) -> Self {
+    #[cfg(feature = "std")]
+    pub fn new(key: Key, value: Value, version: Version, ttl: TtlAt) -> Self {
         let metadata = EntryMetadata::new(version, ttl, key.len() as u16, value.len() as u16);
 
         Entry {
@@ -483,6 +729,7 @@ impl Entry {
     }
 
     /// Returns true if entry is valid (not expired, not invalidated)
+    #[cfg(feature = "std")]
     pub fn is_valid(&self) -> bool {
         self.metadata.flags.is_valid()
             && !self.metadata.is_expired()
@@ -490,6 +737,7 @@ impl Entry {
     }
 
     /// Marks entry as accessed (updates access timestamp)
+    #[cfg(feature = "std")]
     #[inline]
     pub fn touch(&mut self) {
         self.metadata.touch();
@@ -497,7 +745,7 @@ impl Entry {
 
     /// Returns entry size in bytes (key + value + metadata)
     pub fn size(&self) -> usize {
-        std::mem::size_of::<Entry>()
+        core::mem::size_of::<Entry>()
     }
 }
 
@@ -513,6 +761,43 @@ impl fmt::Debug for Entry {
     }
 }
 
+/// Formats bytes like `String::from_utf8_lossy` without allocating.
+struct Lossy<'a>(&'a [u8]);
+
+impl fmt::Display for Lossy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_str("\u{FFFD}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Lossy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+
+        f.write_char('"')?;
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                // `str`'s Debug leaves single quotes unescaped.
+                if c == '\'' {
+                    f.write_char(c)?;
+                } else {
+                    write!(f, "{}", c.escape_debug())?;
+                }
+            }
+            if !chunk.invalid().is_empty() {
+                f.write_char('\u{FFFD}')?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -538,6 +823,69 @@ mod tests {
         assert!(matches!(Key::new(&data), Err(HkvError::KeyTooLong)));
     }
 
+    fn key_with_garbage_tail(data: &[u8], fill: u8) -> Key {
+        let mut key = Key::new(data).unwrap();
+        key.data[data.len()..].fill(fill);
+        key
+    }
+
+    fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_key_hash_matches_slice_hash() {
+        let clean = Key::new(b"alpha").unwrap();
+        let dirty = key_with_garbage_tail(b"alpha", 0xAA);
+
+        assert_eq!(hash_of(&clean), hash_of(b"alpha".as_slice()));
+        assert_eq!(hash_of(&clean), hash_of(&dirty));
+        assert_eq!(clean, dirty);
+    }
+
+    #[test]
+    fn test_key_maps_support_slice_lookup() {
+        use std::collections::{BTreeMap, HashMap, HashSet};
+
+        let mut promoted = HashSet::new();
+        promoted.insert(key_with_garbage_tail(b"hot", 0xFF));
+        assert!(promoted.contains(b"hot".as_slice()));
+        assert!(!promoted.contains(b"cold".as_slice()));
+
+        let mut versions = HashMap::new();
+        versions.insert(Key::new(b"user:1").unwrap(), 7u64);
+        assert_eq!(versions.get(b"user:1".as_slice()), Some(&7));
+
+        let mut pending = BTreeMap::new();
+        pending.insert(Key::new(b"b").unwrap(), ());
+        assert!(pending.contains_key(b"b".as_slice()));
+        assert!(pending.remove(b"b".as_slice()).is_some());
+    }
+
+    #[test]
+    fn test_key_ordering_is_bytewise_over_contents() {
+        let a = key_with_garbage_tail(b"a", 0xFF);
+        let ab = key_with_garbage_tail(b"ab", 0x00);
+        let b = key_with_garbage_tail(b"b", 0x00);
+        let empty = key_with_garbage_tail(b"", 0xFF);
+
+        assert!(empty < a);
+        assert!(a < ab);
+        assert!(ab < b);
+        assert_eq!(
+            key_with_garbage_tail(b"same", 0x01).cmp(&key_with_garbage_tail(b"same", 0x02)),
+            Ordering::Equal
+        );
+
+        let mut keys = vec![b.clone(), ab.clone(), empty.clone(), a.clone()];
+        keys.sort();
+        assert_eq!(keys, vec![empty, a, ab, b]);
+    }
+
     #[test]
     fn test_value_creation() {
         let data = b"test_value";
@@ -547,6 +895,52 @@ mod tests {
         assert!(!value.is_empty());
     }
 
+    fn value_with_garbage_tail(data: &[u8]) -> Value {
+        let mut value = Value::new(data).unwrap();
+        value.data[data.len()..].fill(0xEE);
+        value
+    }
+
+    #[test]
+    fn test_value_extraction_stops_at_used_length() {
+        let value = value_with_garbage_tail(b"payload");
+        assert_eq!(value.as_bytes(), b"payload");
+        assert_eq!(value.into_vec(), b"payload".to_vec());
+
+        let empty = value_with_garbage_tail(b"");
+        assert!(empty.as_bytes().is_empty());
+        assert!(empty.into_vec().is_empty());
+
+        let max = vec![b'm'; MAX_VALUE_SIZE];
+        assert_eq!(Value::new(&max).unwrap().into_vec(), max);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_value_bytes_pipeline_stops_at_used_length() {
+        use bytes::BytesMut;
+
+        let value = value_with_garbage_tail(b"payload");
+        assert_eq!(value.to_bytes(), bytes::Bytes::from_static(b"payload"));
+
+        let mut buf = BytesMut::from(&b"$7\r\n"[..]);
+        value.write_to(&mut buf);
+        assert_eq!(&buf[..], b"$7\r\npayload");
+
+        let empty = value_with_garbage_tail(b"");
+        assert!(empty.to_bytes().is_empty());
+        let mut buf = BytesMut::new();
+        empty.write_to(&mut buf);
+        assert!(buf.is_empty());
+
+        let max = vec![b'm'; MAX_VALUE_SIZE];
+        let value = Value::new(&max).unwrap();
+        assert_eq!(value.to_bytes().len(), MAX_VALUE_SIZE);
+        let mut buf = Vec::new();
+        value.write_to(&mut buf);
+        assert_eq!(buf, max);
+    }
+
     #[test]
     fn test_value_max_size() {
         let data = vec![b'x'; MAX_VALUE_SIZE];
@@ -571,18 +965,58 @@ mod tests {
         assert_eq!(v.get(), 1); // Original unchanged
     }
 
+    #[test]
+    fn test_version_admission() {
+        let (v5, v6) = (Version::new(5), Version::new(6));
+        assert!(v6.may_replace(v5));
+        assert!(v5.may_replace(v5));
+        assert!(!v5.may_replace(v6));
+        // Unversioned writes always win.
+        assert!(Version::ZERO.may_replace(v6));
+    }
+
     #[test]
     fn test_ttl() {
-        let ttl = Ttl::INFINITE;
+        let ttl = TtlAt::INFINITE;
         assert!(ttl.is_infinite());
         assert!(!ttl.is_expired(u64::MAX - 1));
 
-        let ttl = Ttl::from_nanos(1000);
+        let ttl = TtlAt::from_unix_nanos(1000);
         assert!(!ttl.is_infinite());
         assert!(ttl.is_expired(1001));
         assert!(!ttl.is_expired(999));
     }
 
+    #[test]
+    fn test_ttl_conversions_use_the_given_clock() {
+        let clock = FixedClock(1_000_000_000);
+
+        let deadline = TtlAfter::from_millis(1500).deadline(&clock);
+        assert_eq!(deadline, TtlAt::from_unix_nanos(2_500_000_000));
+        assert_eq!(
+            deadline.remaining(&FixedClock(2_000_000_000)),
+            Some(TtlAfter::from_millis(500))
+        );
+        assert_eq!(
+            deadline.remaining(&FixedClock(3_000_000_000)),
+            Some(TtlAfter::ZERO)
+        );
+        assert_eq!(TtlAt::INFINITE.remaining(&clock), None);
+
+        // A relative TTL never saturates into "no expiry".
+        let far = TtlAfter::new(Duration::MAX).deadline(&clock);
+        assert!(!far.is_infinite());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_ttl_alias_still_works() {
+        let ttl: Ttl = Ttl::from_duration(Duration::from_secs(60));
+        let remaining = ttl.remaining(&SystemClock).unwrap();
+        assert!(remaining <= TtlAfter::from_secs(60));
+        assert!(remaining > TtlAfter::from_secs(50));
+    }
+
     #[test]
     fn test_entry_flags() {
         let mut flags = EntryFlags::empty();
@@ -604,7 +1038,7 @@ mod tests {
     fn test_entry_creation() {
         let key = Key::new(b"key1").unwrap();
         let value = Value::new(b"value1").unwrap();
-        let entry = Entry::new(key.clone(), value.clone(), Version::ZERO, Ttl::INFINITE);
+        let entry = Entry::new(key.clone(), value.clone(), Version::ZERO, TtlAt::INFINITE);
 
         assert_eq!(entry.key, key);
         assert_eq!(entry.value, value);
@@ -614,7 +1048,7 @@ mod tests {
 
     #[test]
     fn test_entry_metadata() {
-        let mut metadata = EntryMetadata::new(Version::new(5), Ttl::INFINITE, 10, 20);
+        let mut metadata = EntryMetadata::new(Version::new(5), TtlAt::INFINITE, 10, 20);
 
         assert_eq!(metadata.version.get(), 5);
         assert_eq!(metadata.key_len, 10);
@@ -631,18 +1065,58 @@ mod tests {
     fn test_entry_size() {
         let key = Key::new(b"k").unwrap();
         let value = Value::new(b"v").unwrap();
-        let entry = Entry::new(key, value, Version::ZERO, Ttl::INFINITE);
+        let entry = Entry::new(key, value, Version::ZERO, TtlAt::INFINITE);
 
         // Should be 1328 bytes as documented on 64-bit targets.
         let size = entry.size();
         assert_eq!(size, 1328);
     }
 
+    #[test]
+    fn test_limits_validation() {
+        let limits = Limits::new(4, 8).unwrap();
+
+        assert_eq!(limits.check_key(b"abcd"), Ok(()));
+        assert_eq!(limits.check_key(b"abcde"), Err(HkvError::KeyTooLong));
+        assert_eq!(limits.check_value(b"12345678"), Ok(()));
+        assert_eq!(
+            limits.check_value(b"123456789"),
+            Err(HkvError::ValueTooLong)
+        );
+        assert!(limits.admits(b"k", b"v"));
+        assert!(!limits.admits(b"k", b"123456789"));
+
+        assert_eq!(Limits::default(), Limits::MAX);
+        assert_eq!(
+            Limits::new(MAX_KEY_SIZE as u16 + 1, 8),
+            Err(HkvError::ProtocolViolation)
+        );
+        assert_eq!(
+            Limits::new(4, MAX_VALUE_SIZE as u16 + 1),
+            Err(HkvError::ProtocolViolation)
+        );
+    }
+
+    #[test]
+    fn test_debug_and_display_match_lossy_string() {
+        let bytes = b"it's \"hot\"\n\xff\xfe!";
+        let key = Key::new(bytes).unwrap();
+        let lossy = String::from_utf8_lossy(bytes);
+
+        assert_eq!(format!("{key}"), lossy);
+        assert_eq!(format!("{key:?}"), format!("Key({lossy:?})"));
+        assert_eq!(
+            format!("{:?}", Value::new(bytes).unwrap()),
+            format!("Value({lossy:?})")
+        );
+    }
+
     #[test]
     fn test_struct_sizes() {
         assert_eq!(std::mem::size_of::<Key>(), 258);
         assert_eq!(std::mem::size_of::<Value>(), 1026);
         assert_eq!(std::mem::size_of::<EntryMetadata>(), 40);
         assert_eq!(std::mem::size_of::<Entry>(), 1328);
+        assert_eq!(std::mem::size_of::<Limits>(), 4);
     }
 }
@@ -0,0 +1,539 @@
+//! # Wire Encoding
+//!
+//! Field-by-field byte encoding of the ioctl payloads, independent of how the
+//! compiler lays out the `repr(C)` structs.
+//!
+//! ## Design Principles
+//!
+//! 1. **Layout-Identical**: The encoding is exactly the `repr(C)` memory image
+//!    on the little-endian targets the module supports, so these bytes are
+//!    what crosses `copy_from_user`/`copy_to_user`.
+//! 2. **Explicit Padding**: Compiler padding (after the key in
+//!    `InvalidateRequest`, after the header in `ConfigRequest`) is written as
+//!    zero and ignored on decode, as are unused key/value buffer bytes.
+//! 3. **Strict Lengths**: Decoding requires exactly `SIZE` bytes and
+//!    re-validates key/value lengths, so a truncated or oversized buffer is a
+//!    `ProtocolViolation`, never a partial struct.
+//! 4. **Fixed-Size Messages Only**: Batch payloads (over 1 MB) have no wire
+//!    impl; their layout is pinned by the size tests in `protocol`.
+
+use core::mem::size_of;
+
+use crate::error::{HkvError, HkvResult};
+use crate::protocol::*;
+use crate::types::{Key, Limits, TtlAt, Value, Version, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+
+/// A payload with a fixed-size little-endian wire encoding.
+pub trait WireFormat: Sized {
+    /// Encoded size in bytes; equal to `size_of::<Self>()`.
+    const SIZE: usize;
+
+    /// Writes the fields in declaration order.
+    fn write(&self, w: &mut Writer<'_>);
+
+    /// Reads the fields in declaration order.
+    ///
+    /// # Errors
+    /// Returns the validation error of the first malformed field.
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self>;
+
+    /// Encodes into the first `SIZE` bytes of `out`.
+    ///
+    /// # Errors
+    /// Returns `ProtocolViolation` if `out` is shorter than `SIZE`.
+    fn encode(&self, out: &mut [u8]) -> HkvResult<()> {
+        let out = out
+            .get_mut(..Self::SIZE)
+            .ok_or(HkvError::ProtocolViolation)?;
+        self.write(&mut Writer { buf: out, pos: 0 });
+        Ok(())
+    }
+
+    /// Encodes into a new `SIZE`-byte vector.
+    #[cfg(feature = "std")]
+    fn to_wire(&self) -> Vec<u8> {
+        let mut out = vec![0u8; Self::SIZE];
+        self.write(&mut Writer {
+            buf: &mut out,
+            pos: 0,
+        });
+        out
+    }
+
+    /// Decodes a payload from exactly `SIZE` bytes.
+    ///
+    /// # Errors
+    /// Returns `ProtocolViolation` for any other length, or the error of the
+    /// first field that fails validation.
+    fn decode(bytes: &[u8]) -> HkvResult<Self> {
+        if bytes.len() != Self::SIZE {
+            return Err(HkvError::ProtocolViolation);
+        }
+        Self::read(&mut Reader { buf: bytes, pos: 0 })
+    }
+}
+
+/// Sequential writer over a buffer of exactly `WireFormat::SIZE` bytes.
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    /// Appends raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    /// Appends `n` zero bytes (padding or unused buffer space).
+    pub fn zeros(&mut self, n: usize) {
+        self.buf[self.pos..self.pos + n].fill(0);
+        self.pos += n;
+    }
+
+    /// Appends a `u8`.
+    pub fn u8(&mut self, v: u8) {
+        self.bytes(&[v]);
+    }
+
+    /// Appends a little-endian `u16`.
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    /// Appends a little-endian `u32`.
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    /// Appends a little-endian `u64`.
+    pub fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+}
+
+/// Sequential reader over a buffer of exactly `WireFormat::SIZE` bytes.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Takes the next `n` bytes.
+    pub fn bytes(&mut self, n: usize) -> HkvResult<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + n)
+            .ok_or(HkvError::ProtocolViolation)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// Takes the next `N` bytes as an array.
+    fn array<const N: usize>(&mut self) -> HkvResult<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    /// Skips `n` bytes of padding.
+    pub fn skip(&mut self, n: usize) -> HkvResult<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    /// Takes a `u8`.
+    pub fn u8(&mut self) -> HkvResult<u8> {
+        self.array::<1>().map(|[v]| v)
+    }
+
+    /// Takes a little-endian `u16`.
+    pub fn u16(&mut self) -> HkvResult<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    /// Takes a little-endian `u32`.
+    pub fn u32(&mut self) -> HkvResult<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    /// Takes a little-endian `u64`.
+    pub fn u64(&mut self) -> HkvResult<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+}
+
+/// Implements `WireFormat` for a struct whose fields all implement it.
+macro_rules! wire_struct {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        impl WireFormat for $ty {
+            const SIZE: usize = size_of::<$ty>();
+
+            fn write(&self, w: &mut Writer<'_>) {
+                $(self.$field.write(w);)*
+            }
+
+            fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+                Ok($ty {
+                    $($field: WireFormat::read(r)?,)*
+                })
+            }
+        }
+    };
+}
+
+impl WireFormat for u16 {
+    const SIZE: usize = 2;
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u16(*self);
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        r.u16()
+    }
+}
+
+impl WireFormat for u64 {
+    const SIZE: usize = 8;
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u64(*self);
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        r.u64()
+    }
+}
+
+impl WireFormat for Version {
+    const SIZE: usize = 8;
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u64(self.get());
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        r.u64().map(Version::new)
+    }
+}
+
+impl WireFormat for TtlAt {
+    const SIZE: usize = 8;
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u64(self.as_nanos());
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        r.u64().map(TtlAt::from_unix_nanos)
+    }
+}
+
+impl WireFormat for Key {
+    const SIZE: usize = size_of::<Key>();
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u16(self.len() as u16);
+        w.bytes(self.as_bytes());
+        w.zeros(MAX_KEY_SIZE - self.len());
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        let len = r.u16()? as usize;
+        let data = r.bytes(MAX_KEY_SIZE)?;
+        Key::new(data.get(..len).ok_or(HkvError::KeyTooLong)?)
+    }
+}
+
+impl WireFormat for Value {
+    const SIZE: usize = size_of::<Value>();
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u16(self.len() as u16);
+        w.bytes(self.as_bytes());
+        w.zeros(MAX_VALUE_SIZE - self.len());
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        let len = r.u16()? as usize;
+        let data = r.bytes(MAX_VALUE_SIZE)?;
+        Value::new(data.get(..len).ok_or(HkvError::ValueTooLong)?)
+    }
+}
+
+impl WireFormat for IoctlHeader {
+    const SIZE: usize = size_of::<IoctlHeader>();
+
+    fn write(&self, w: &mut Writer<'_>) {
+        w.u8(self.magic);
+        w.u8(self.version);
+        w.u8(self.command);
+        w.u8(self.reserved);
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        Ok(IoctlHeader {
+            magic: r.u8()?,
+            version: r.u8()?,
+            command: r.u8()?,
+            reserved: r.u8()?,
+        })
+    }
+}
+
+wire_struct!(Limits { max_key, max_value });
+wire_struct!(ReadRequest { header, key });
+wire_struct!(ReadResponse {
+    header,
+    status,
+    value
+});
+wire_struct!(PromoteRequest {
+    header,
+    key,
+    value,
+    version,
+    ttl
+});
+wire_struct!(PromoteResponse {
+    header,
+    status,
+    outcome,
+    evicted
+});
+wire_struct!(DemoteRequest { header, key });
+wire_struct!(DemoteResponse {
+    header,
+    status,
+    reserved
+});
+wire_struct!(CacheStats {
+    lookups,
+    hits,
+    misses,
+    stale_hits,
+    promotions,
+    demotions,
+    evictions,
+    invalidations,
+    used_bytes,
+    max_bytes,
+    entry_count,
+    lock_contentions,
+    rcu_grace_periods,
+    expired,
+});
+wire_struct!(StatsRequest { header });
+wire_struct!(StatsResponse {
+    header,
+    status,
+    reserved,
+    stats
+});
+wire_struct!(InvalidateResponse {
+    header,
+    status,
+    removed
+});
+wire_struct!(FlushRequest { header });
+wire_struct!(FlushResponse {
+    header,
+    status,
+    reserved,
+    removed
+});
+wire_struct!(HelloRequest { header });
+wire_struct!(HelloResponse {
+    header,
+    status,
+    reserved,
+    limits
+});
+
+/// Padding between the key (ends at byte 262) and the 8-byte-aligned version.
+const INVALIDATE_PAD: usize = 2;
+
+impl WireFormat for InvalidateRequest {
+    const SIZE: usize = size_of::<InvalidateRequest>();
+
+    fn write(&self, w: &mut Writer<'_>) {
+        self.header.write(w);
+        self.key.write(w);
+        w.zeros(INVALIDATE_PAD);
+        self.version.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        let header = IoctlHeader::read(r)?;
+        let key = Key::read(r)?;
+        r.skip(INVALIDATE_PAD)?;
+        Ok(InvalidateRequest {
+            header,
+            key,
+            version: Version::read(r)?,
+        })
+    }
+}
+
+/// Padding between the 4-byte header and the 8-byte-aligned `max_bytes`.
+const CONFIG_PAD: usize = 4;
+
+impl WireFormat for ConfigRequest {
+    const SIZE: usize = size_of::<ConfigRequest>();
+
+    fn write(&self, w: &mut Writer<'_>) {
+        self.header.write(w);
+        w.zeros(CONFIG_PAD);
+        w.u64(self.max_bytes);
+        w.u64(self.max_entries);
+        w.u32(self.high_watermark);
+        w.u32(self.low_watermark);
+        w.u64(self.reserved);
+    }
+
+    fn read(r: &mut Reader<'_>) -> HkvResult<Self> {
+        let header = IoctlHeader::read(r)?;
+        r.skip(CONFIG_PAD)?;
+        Ok(ConfigRequest {
+            header,
+            max_bytes: r.u64()?,
+            max_entries: r.u64()?,
+            high_watermark: r.u32()?,
+            low_watermark: r.u32()?,
+            reserved: r.u64()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::offset_of;
+
+    use super::*;
+    use crate::ioctl::IoctlCommand;
+
+    /// Asserts a writer/reader walk covers exactly `T::SIZE` bytes.
+    fn covers_size<T: WireFormat>(value: &T) {
+        let mut buf = vec![0xAAu8; T::SIZE + 1];
+        let mut w = Writer {
+            buf: &mut buf,
+            pos: 0,
+        };
+        value.write(&mut w);
+        assert_eq!(w.pos, T::SIZE);
+        assert_eq!(buf[T::SIZE], 0xAA);
+    }
+
+    #[test]
+    fn test_wire_sizes_match_struct_sizes() {
+        let key = Key::new(b"k").unwrap();
+        let value = Value::new(b"v").unwrap();
+        covers_size(&ReadRequest::new(key.clone()));
+        covers_size(&ReadResponse::new(STATUS_OK, value.clone()));
+        covers_size(&PromoteRequest::new(
+            key.clone(),
+            value,
+            Version::new(1),
+            TtlAt::INFINITE,
+        ));
+        covers_size(&PromoteResponse::new(STATUS_OK));
+        covers_size(&DemoteRequest::new(key.clone()));
+        covers_size(&DemoteResponse::new(STATUS_OK));
+        covers_size(&InvalidateRequest::new(key, Version::new(1)));
+        covers_size(&InvalidateResponse::new(STATUS_OK, true));
+        covers_size(&StatsRequest::new());
+        covers_size(&StatsResponse::new(STATUS_OK, CacheStats::default()));
+        covers_size(&ConfigRequest::new(1, 2, 3, 4));
+        covers_size(&FlushRequest::new());
+        covers_size(&FlushResponse::new(STATUS_OK, 1));
+        covers_size(&HelloRequest::new());
+        covers_size(&HelloResponse::new(STATUS_OK, Limits::default()));
+    }
+
+    #[test]
+    fn test_padding_constants_match_layout() {
+        assert_eq!(
+            offset_of!(InvalidateRequest, version),
+            IoctlHeader::SIZE + Key::SIZE + INVALIDATE_PAD
+        );
+        assert_eq!(
+            offset_of!(ConfigRequest, max_bytes),
+            IoctlHeader::SIZE + CONFIG_PAD
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_wrong_lengths() {
+        let bytes = StatsRequest::new().to_wire();
+        assert_eq!(StatsRequest::decode(&bytes), Ok(StatsRequest::new()));
+        assert_eq!(
+            StatsRequest::decode(&bytes[..3]),
+            Err(HkvError::ProtocolViolation)
+        );
+
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            StatsRequest::decode(&long),
+            Err(HkvError::ProtocolViolation)
+        );
+        assert_eq!(
+            StatsRequest::new().encode(&mut [0u8; 3]),
+            Err(HkvError::ProtocolViolation)
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_lengths() {
+        let mut bytes = ReadRequest::new(Key::new(b"k").unwrap()).to_wire();
+        bytes[4..6].copy_from_slice(&(MAX_KEY_SIZE as u16 + 1).to_le_bytes());
+        assert_eq!(ReadRequest::decode(&bytes), Err(HkvError::KeyTooLong));
+
+        let mut bytes = ReadResponse::new(STATUS_OK, Value::new(b"v").unwrap()).to_wire();
+        bytes[6..8].copy_from_slice(&(MAX_VALUE_SIZE as u16 + 1).to_le_bytes());
+        assert_eq!(ReadResponse::decode(&bytes), Err(HkvError::ValueTooLong));
+    }
+
+    /// The in-memory image of a padding-free `repr(C)` value.
+    fn memory_of<T>(value: &T) -> &[u8] {
+        // SAFETY: only called with padding-free types, so every byte is
+        // initialized.
+        unsafe { core::slice::from_raw_parts((value as *const T).cast::<u8>(), size_of::<T>()) }
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_encoding_matches_memory_image() {
+        let request = PromoteRequest::new(
+            Key::new(b"user:42").unwrap(),
+            Value::new(b"payload").unwrap(),
+            Version::new(0x0102_0304_0506_0708),
+            TtlAt::from_unix_nanos(0x1122_3344_5566_7788),
+        )
+        .with_flags(RequestFlags::NOWAIT);
+        assert_eq!(request.to_wire(), memory_of(&request));
+
+        let mut response = PromoteResponse::new(STATUS_OK);
+        response.outcome = PromoteOutcome::EvictedOther.as_u16();
+        response.evicted = Key::new(b"old").unwrap();
+        assert_eq!(response.to_wire(), memory_of(&response));
+
+        let stats = StatsResponse::new(
+            STATUS_OK,
+            CacheStats {
+                hits: 7,
+                rcu_grace_periods: u64::MAX,
+                ..CacheStats::default()
+            },
+        );
+        assert_eq!(stats.to_wire(), memory_of(&stats));
+
+        let hello = HelloResponse::new(STATUS_OK, Limits::default());
+        assert_eq!(hello.to_wire(), memory_of(&hello));
+        assert_eq!(
+            IoctlHeader::new(IoctlCommand::Flush).to_wire(),
+            memory_of(&IoctlHeader::new(IoctlCommand::Flush))
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//! Proves the crate builds with `core` only (`--no-default-features`).
+
+use std::process::Command;
+
+#[test]
+fn builds_without_std() {
+    let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    // A separate target dir avoids waiting on the lock held by `cargo test`.
+    let target_dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/no-std");
+
+    let output = Command::new(env!("CARGO"))
+        .args(["check", "--lib", "--no-default-features", "--manifest-path"])
+        .arg(manifest)
+        .arg("--target-dir")
+        .arg(target_dir)
+        .output()
+        .expect("failed to run cargo");
+
+    assert!(
+        output.status.success(),
+        "no_std build failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,53 @@
+//! Property tests for `Key` and `Value` construction.
+//!
+//! Inputs span both sides of each size limit, so every run exercises the
+//! accepting and the rejecting branch.
+
+use hkv_common::{HkvError, Key, Value, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn keys_within_limit_round_trip(data in vec(any::<u8>(), 0..=MAX_KEY_SIZE)) {
+        let key = Key::new(&data).unwrap();
+        prop_assert_eq!(key.as_bytes(), data.as_slice());
+        prop_assert_eq!(key.len(), data.len());
+    }
+
+    #[test]
+    fn oversized_keys_are_rejected(data in vec(any::<u8>(), MAX_KEY_SIZE + 1..=1024)) {
+        prop_assert_eq!(Key::new(&data), Err(HkvError::KeyTooLong));
+    }
+
+    #[test]
+    fn keys_of_any_length_follow_the_limit(data in vec(any::<u8>(), 0..=1024)) {
+        match Key::new(&data) {
+            Ok(key) => {
+                prop_assert!(data.len() <= MAX_KEY_SIZE);
+                prop_assert_eq!(key.as_bytes(), data.as_slice());
+            }
+            Err(err) => {
+                prop_assert!(data.len() > MAX_KEY_SIZE);
+                prop_assert_eq!(err, HkvError::KeyTooLong);
+            }
+        }
+    }
+
+    #[test]
+    fn values_within_limit_round_trip(data in vec(any::<u8>(), 0..=MAX_VALUE_SIZE)) {
+        let value = Value::new(&data).unwrap();
+        prop_assert_eq!(value.as_bytes(), data.as_slice());
+        prop_assert_eq!(value.len(), data.len());
+    }
+
+    // `MAX_VALUE_SIZE` is 1024, so rejection needs lengths past it.
+    #[test]
+    fn oversized_values_are_rejected(
+        data in vec(any::<u8>(), MAX_VALUE_SIZE + 1..=2 * MAX_VALUE_SIZE),
+    ) {
+        prop_assert_eq!(Value::new(&data), Err(HkvError::ValueTooLong));
+    }
+}
@@ -0,0 +1,290 @@
+//! # Engine Benchmarks
+//!
+//! Criterion benchmarks for `MemoryEngine`, run in CI against the base branch
+//! so a change that slows the engine down fails review.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-engine --bench engine
+//!
+//! # Compare against a saved run
+//! cargo bench -p hkv-engine --bench engine -- --save-baseline main
+//! cargo bench -p hkv-engine --bench engine -- --baseline main
+//! ```
+//!
+//! Criterion reports throughput in ops/s; each benchmark then prints the p50
+//! and p99 latency of a single operation.
+//!
+//! ## Design Principles
+//! 1. **Per-Operation Timing**: `iter_custom` times every operation on its
+//!    own, feeding the latency histogram and keeping setup (cloning the owned
+//!    key and value `set` takes) out of the measurement. Each timing includes
+//!    one `Instant::now` pair.
+//! 2. **Deterministic Workload**: Keys, values and access order come from a
+//!    fixed-seed PRNG, as in `bench_engine`.
+//! 3. **10% Regression Budget**: `noise_threshold(0.10)` reports smaller
+//!    changes as noise; CI fails when a mean time grows past that budget.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use hkv_common::TtlAfter;
+use hkv_engine::{KVEngine, MemoryEngine};
+
+const KEY_SIZE: usize = 32;
+const VALUE_SIZE: usize = 512;
+const KEY_COUNT: usize = 1_000;
+const BATCH_SIZE: usize = 8;
+const EXPIRER_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Histogram range; slower operations land in the overflow bucket.
+const HISTOGRAM_NANOS: usize = 1 << 16;
+
+/// Tiny deterministic PRNG.
+struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    fn next_index(&mut self) -> usize {
+        (self.next_u64() % KEY_COUNT as u64) as usize
+    }
+}
+
+/// Per-operation latencies at 1 ns resolution.
+struct Latencies {
+    buckets: Vec<u64>,
+    overflow: u64,
+    count: u64,
+}
+
+impl Latencies {
+    fn new() -> Self {
+        Latencies {
+            buckets: vec![0; HISTOGRAM_NANOS],
+            overflow: 0,
+            count: 0,
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        match self.buckets.get_mut(elapsed.as_nanos() as usize) {
+            Some(bucket) => *bucket += 1,
+            None => self.overflow += 1,
+        }
+        self.count += 1;
+    }
+
+    /// The latency `quantile` of all recorded operations fall under, or
+    /// `None` if it lies past the histogram range.
+    fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let target = ((self.count as f64) * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (nanos, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Some(Duration::from_nanos(nanos as u64));
+            }
+        }
+        None
+    }
+
+    fn report(&self, name: &str) {
+        let show = |quantile| match self.quantile(quantile) {
+            Some(latency) => format!("{latency:?}"),
+            None => format!(">{:?}", Duration::from_nanos(HISTOGRAM_NANOS as u64)),
+        };
+        println!(
+            "{name}: p50 {} p99 {} over {} ops",
+            show(0.50),
+            show(0.99),
+            self.count
+        );
+    }
+}
+
+/// Runs `iters` operations, timing each `op` call but not its `setup`.
+fn measure<I>(
+    iters: u64,
+    latencies: &mut Latencies,
+    mut setup: impl FnMut() -> I,
+    mut op: impl FnMut(I),
+) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let input = setup();
+        let start = Instant::now();
+        op(input);
+        let elapsed = start.elapsed();
+        latencies.record(elapsed);
+        total += elapsed;
+    }
+    total
+}
+
+fn build_buffers(count: usize, size: usize, seed: u64) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut buffer = vec![0u8; size];
+            buffer[..8].copy_from_slice(&(seed ^ i as u64).to_le_bytes());
+            buffer
+        })
+        .collect()
+}
+
+/// Keys and values for `KEY_COUNT` entries.
+fn workload() -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    (
+        build_buffers(KEY_COUNT, KEY_SIZE, 0xA5A5_A5A5_A5A5_A5A5),
+        build_buffers(KEY_COUNT, VALUE_SIZE, 0x5A5A_5A5A_5A5A_5A5A),
+    )
+}
+
+fn preloaded(keys: &[Vec<u8>], values: &[Vec<u8>]) -> MemoryEngine {
+    let engine = MemoryEngine::new();
+    for (key, value) in keys.iter().zip(values) {
+        engine.set(key.clone(), value.clone()).unwrap();
+    }
+    engine
+}
+
+fn engine_benches(c: &mut Criterion) {
+    let (keys, values) = workload();
+    let mut group = c.benchmark_group("memory_engine");
+    group.throughput(Throughput::Elements(1));
+
+    let engine = MemoryEngine::new();
+    let mut rng = XorShift64::new(0x9E37_79B9_7F4A_7C15);
+    let mut latencies = Latencies::new();
+    group.bench_function("set", |b| {
+        b.iter_custom(|iters| {
+            measure(
+                iters,
+                &mut latencies,
+                || {
+                    let idx = rng.next_index();
+                    (keys[idx].clone(), values[idx].clone())
+                },
+                |(key, value)| engine.set(key, value).unwrap(),
+            )
+        })
+    });
+    latencies.report("set");
+
+    let engine = preloaded(&keys, &values);
+    let mut latencies = Latencies::new();
+    group.bench_function("get_hit", |b| {
+        b.iter_custom(|iters| {
+            measure(
+                iters,
+                &mut latencies,
+                || rng.next_index(),
+                |idx| {
+                    black_box(engine.get(&keys[idx]).unwrap().unwrap());
+                },
+            )
+        })
+    });
+    latencies.report("get_hit");
+
+    let mut latencies = Latencies::new();
+    group.bench_function("mixed_80_20", |b| {
+        b.iter_custom(|iters| {
+            measure(
+                iters,
+                &mut latencies,
+                || {
+                    let idx = rng.next_index();
+                    let write = rng.next_u64() % 10 < 2;
+                    (idx, write.then(|| (keys[idx].clone(), values[idx].clone())))
+                },
+                |(idx, write)| match write {
+                    Some((key, value)) => engine.set(key, value).unwrap(),
+                    None => {
+                        black_box(engine.get(&keys[idx]).unwrap());
+                    }
+                },
+            )
+        })
+    });
+    latencies.report("mixed_80_20");
+
+    // The engine has no multi-key read; this is the cost an MGET of
+    // `BATCH_SIZE` keys pays today.
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let mut latencies = Latencies::new();
+    group.bench_function("get_batch_8", |b| {
+        b.iter_custom(|iters| {
+            measure(
+                iters,
+                &mut latencies,
+                || [(); BATCH_SIZE].map(|()| rng.next_index()),
+                |batch| {
+                    for idx in batch {
+                        black_box(engine.get(&keys[idx]).unwrap());
+                    }
+                },
+            )
+        })
+    });
+    latencies.report("get_batch_8");
+    group.throughput(Throughput::Elements(1));
+
+    // Short TTLs so the expirer always has entries to purge; comparing the
+    // two runs gives the cost of sharing shards with it.
+    for (name, expirer) in [("set_ttl", false), ("set_ttl_with_expirer", true)] {
+        let engine = Arc::new(MemoryEngine::new());
+        let handle = expirer.then(|| engine.start_expirer(EXPIRER_INTERVAL));
+        let mut latencies = Latencies::new();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                measure(
+                    iters,
+                    &mut latencies,
+                    || {
+                        let idx = rng.next_index();
+                        (keys[idx].clone(), values[idx].clone())
+                    },
+                    |(key, value)| {
+                        engine
+                            .set_with_ttl(key, value, TtlAfter::from_millis(1))
+                            .unwrap()
+                    },
+                )
+            })
+        });
+        if let Some(handle) = handle {
+            handle.stop();
+        }
+        latencies.report(name);
+    }
+
+    group.finish();
+}
+
+fn config() -> Criterion {
+    Criterion::default().noise_threshold(0.10)
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = engine_benches
+}
+criterion_main!(benches);
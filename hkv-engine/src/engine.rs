@@ -7,13 +7,20 @@
 //! 2. **Binary-Safe API**: Keys/values are byte buffers to match Redis semantics.
 //! 3. **Zero-Cost Dispatch**: When used with generics, calls monomorphize to
 //!    avoid dynamic dispatch overhead.
-//! 4. **Explicit TTL**: Expose expiration via a dedicated method to keep the
-//!    hot read path minimal.
+//! 4. **Explicit TTL**: Expose expiration via dedicated methods to keep the
+//!    hot read path minimal. Relative TTLs are `TtlAfter`, absolute deadlines
+//!    `TtlAt`, so the two cannot be mixed up at a call site.
+//! 5. **Point-in-Time Snapshots**: Persistence copies shared buffers out of the
+//!    engine and serializes them without holding engine locks.
+//! 6. **Abortable Scans**: Whole-keyspace operations poll an optional abort
+//!    flag every `ABORT_CHECK_INTERVAL` entries and stop with `Interrupted`,
+//!    so a caller with a time budget can give up on them.
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use hkv_common::HkvResult;
+use hkv_common::{Clock, HkvError, HkvResult, SystemClock, TtlAfter, TtlAt};
 
 /// TTL query result for Redis-style semantics.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,6 +33,62 @@ pub enum TtlStatus {
     ExpiresIn(Duration),
 }
 
+/// Live entry captured by `KVEngine::snapshot`.
+///
+/// Buffers are shared with the engine, so taking a snapshot only bumps
+/// reference counts; serialization happens later without engine locks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    /// Entry key.
+    pub key: Arc<[u8]>,
+    /// Entry value at snapshot time.
+    pub value: Arc<[u8]>,
+    /// Remaining time to live, or `None` when the key never expires.
+    pub ttl: Option<Duration>,
+}
+
+/// `KVEngine::expire_at` against an explicit clock.
+pub fn expire_at_with<E>(
+    engine: &E,
+    key: &[u8],
+    deadline: TtlAt,
+    clock: &impl Clock,
+) -> HkvResult<()>
+where
+    E: KVEngine + ?Sized,
+{
+    let ttl = deadline.remaining(clock).ok_or(HkvError::InvalidInput)?;
+    engine.expire(key, ttl)
+}
+
+/// Entries a whole-keyspace operation visits between checks of its abort
+/// flag.
+pub const ABORT_CHECK_INTERVAL: usize = 1024;
+
+/// Fails with `Interrupted` if `should_abort` is set, checking only every
+/// `ABORT_CHECK_INTERVAL` entries; `visited` counts the entries so far.
+pub fn check_abort(should_abort: Option<&AtomicBool>, visited: usize) -> HkvResult<()> {
+    match should_abort {
+        Some(flag)
+            if visited.is_multiple_of(ABORT_CHECK_INTERVAL) && flag.load(Ordering::Relaxed) =>
+        {
+            Err(HkvError::Interrupted)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Size of an engine's contents, for monitoring.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EngineStats {
+    /// Stored entries, including expired ones not yet purged.
+    pub keys: usize,
+    /// Bytes of keys and values held.
+    pub used_bytes: usize,
+    /// Entries with a TTL, including expired ones not yet purged.
+    pub expires: usize,
+}
+
 /// Strategy pattern: defines the engine behavior surface for the server.
 ///
 /// Keys and values are treated as bulk strings (binary-safe) for Phase 1.
@@ -42,14 +105,77 @@ pub trait KVEngine: Send + Sync {
     ///
     /// Implementations must not expose a state where the value is visible
     /// before the TTL is attached.
-    fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: Duration) -> HkvResult<()>;
+    fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: TtlAfter) -> HkvResult<()>;
+
+    /// Inserts or replaces a key from borrowed bytes, attaching `ttl` if
+    /// given, for callers whose arguments live in a shared read buffer.
+    ///
+    /// The default copies into owned vectors for `set` or `set_with_ttl`;
+    /// engines that keep their own buffers override it to copy once.
+    fn set_borrowed(&self, key: &[u8], value: &[u8], ttl: Option<TtlAfter>) -> HkvResult<()> {
+        match ttl {
+            Some(ttl) => self.set_with_ttl(key.to_vec(), value.to_vec(), ttl),
+            None => self.set(key.to_vec(), value.to_vec()),
+        }
+    }
 
     /// Removes a key. Returns true if the key existed and was removed.
     fn delete(&self, key: &[u8]) -> HkvResult<bool>;
 
     /// Sets an expiration on a key. Returns `NotFound` if the key is missing.
-    fn expire(&self, key: &[u8], ttl: Duration) -> HkvResult<()>;
+    fn expire(&self, key: &[u8], ttl: TtlAfter) -> HkvResult<()>;
+
+    /// Expires a key at an absolute wall-clock deadline.
+    ///
+    /// The default converts `deadline` against `SystemClock` and delegates to
+    /// `expire`; a deadline in the past expires the key immediately.
+    /// `TtlAt::INFINITE` is rejected with `InvalidInput`, since clearing a TTL
+    /// is not an expiration.
+    fn expire_at(&self, key: &[u8], deadline: TtlAt) -> HkvResult<()> {
+        expire_at_with(self, key, deadline, &SystemClock)
+    }
 
     /// Returns the TTL state for a key.
     fn ttl(&self, key: &[u8]) -> HkvResult<TtlStatus>;
+
+    /// Returns a point-in-time copy of all live (non-expired) entries.
+    ///
+    /// Consistency is per shard; entries are returned in no particular order.
+    fn snapshot(&self) -> HkvResult<Vec<SnapshotEntry>>;
+
+    /// Returns every live key, in no particular order.
+    ///
+    /// Fails with `Interrupted` once `should_abort` is set. The default
+    /// takes a snapshot, which cannot be aborted, and checks the flag while
+    /// collecting its keys.
+    fn keys(&self, should_abort: Option<&AtomicBool>) -> HkvResult<Vec<Arc<[u8]>>> {
+        let mut keys = Vec::new();
+        for (visited, entry) in self.snapshot()?.into_iter().enumerate() {
+            check_abort(should_abort, visited)?;
+            keys.push(entry.key);
+        }
+        Ok(keys)
+    }
+
+    /// Removes every key and returns how many there were.
+    ///
+    /// Fails with `Interrupted` once `should_abort` is set; keys removed
+    /// before that stay removed. The default deletes the keys one by one.
+    fn flush(&self, should_abort: Option<&AtomicBool>) -> HkvResult<usize> {
+        let mut removed = 0;
+        for (visited, key) in self.keys(should_abort)?.iter().enumerate() {
+            check_abort(should_abort, visited)?;
+            if self.delete(key)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns the current entry count and memory use.
+    fn stats(&self) -> EngineStats;
+
+    /// Returns the mean remaining TTL of live keys that have one, in
+    /// milliseconds, or 0 when none do.
+    fn avg_ttl_ms(&self) -> u64;
 }
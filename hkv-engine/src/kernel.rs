@@ -0,0 +1,1188 @@
+//! # Kernel Cache Client
+//!
+//! Safe user-space access to the `/dev/hybridkv` hot-key cache. Connecting
+//! performs the HELLO handshake; each method then encodes one ioctl request
+//! with `WireFormat`, issues it, and decodes the response.
+//!
+//! ## Usage
+//!
+//! - Use `KernelCacheClient::open()` for the real device, or
+//!   `KernelCacheClient::with_device` for any clonable `KernelDevice`, such
+//!   as an `Arc<MockKernelDevice>` in tests.
+//! - Use `start_prober` to bring a degraded or disabled client back on its
+//!   own, or call `probe` directly.
+//! - Clone the client freely: clones share the device handle, the
+//!   negotiated limits, the health and the replay queue.
+//!
+//! ## Health
+//!
+//! ```text
+//!             hard failure                probe: HELLO succeeds
+//!   Healthy ───────────────▶ Degraded ──────────────────────────▶ Healthy
+//!                                                                    ▲
+//!   (startup) device missing or                                     │
+//!   version mismatch ───────▶ Disabled ─────────────────────────────┘
+//! ```
+//!
+//! Only a Healthy client issues ioctls. Otherwise reads and touches miss,
+//! stats read as zero, and promotes, invalidates and flushes are queued
+//! (up to `REPLAY_CAPACITY`) and replayed once a probe succeeds. Every
+//! change of state is logged once; `health_monitor` shares it with callers
+//! that report it, such as `INFO kernel_cache`.
+//!
+//! ## Design Principles
+//!
+//! 1. **Byte Encoding Only**: Requests and responses cross the device as
+//!    `WireFormat` bytes, so no `unsafe` struct casts are needed and a mock
+//!    device sees exactly what the module would.
+//! 2. **Errors as `HkvError`**: A failed ioctl's errno maps back through
+//!    `HkvError::from_errno`; errnos outside the table become
+//!    `InternalError`.
+//! 3. **Retry Interrupts**: `EINTR` is retried up to `EINTR_RETRIES` times
+//!    before it counts as a hard failure.
+//! 4. **Never Worse Than Memory**: A hard failure (`InternalError`,
+//!    `Timeout`, `Interrupted` or a protocol error) degrades the client
+//!    instead of surfacing, so callers see a miss and fall back to the
+//!    memory engine. Only answers from a working module (`Busy`, a full
+//!    cache, an oversized entry) are returned as errors.
+//! 5. **Backed-Off Probes**: Probes start `PROBE_MIN_DELAY` apart and double
+//!    up to `PROBE_MAX_DELAY`, so a missing module costs almost nothing.
+//! 6. **Safe Replay**: Queued writes replay in order on the new link, and
+//!    only an empty queue lets the probe publish `Healthy`, so no read sees
+//!    an entry a queued invalidate still has to remove. Writes carry their
+//!    versions, so the module's version admission keeps a replayed promote
+//!    from replacing anything newer. If writes were lost (the queue
+//!    overflowed, a FLUSH was queued, or a replayed invalidate was refused)
+//!    the cache is flushed first.
+//! 7. **Negotiated Limits**: Keys and values beyond the limits HELLO
+//!    reported are never sent; such reads miss and such promotes fail
+//!    locally with `KeyTooLong` or `ValueTooLong`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use hkv_common::{
+    CacheStats, DEVICE_PATH, FlushRequest, FlushResponse, HelloRequest, HelloResponse, HkvError,
+    HkvResult, InvalidateRequest, InvalidateResponse, Key, Limits, PromoteOutcome, PromoteRequest,
+    PromoteResponse, ReadRequest, ReadResponse, StatsRequest, StatsResponse, TtlAt, Value, Version,
+    WireFormat,
+};
+use hkv_kernel::dispatch::{
+    HKV_IOC_FLUSH, HKV_IOC_HELLO, HKV_IOC_INVALIDATE, HKV_IOC_PROMOTE, HKV_IOC_READ, HKV_IOC_STATS,
+    ioc_size,
+};
+use parking_lot::{Mutex, RwLock};
+
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockKernelDevice;
+
+/// How often an interrupted ioctl is reissued before giving up.
+pub const EINTR_RETRIES: u32 = 8;
+
+/// Most promotes and invalidates kept for replay while not Healthy.
+pub const REPLAY_CAPACITY: usize = 256;
+
+/// Delay before the first probe after the client leaves Healthy.
+pub const PROBE_MIN_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay between probes.
+pub const PROBE_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// `EINTR`, the only errno the client retries.
+const EINTR: i32 = 4;
+
+/// Largest ioctl argument; PROMOTE's request is the biggest payload.
+const MAX_ARG_SIZE: usize = PromoteRequest::SIZE;
+
+const _: () = assert!(ioc_size(HKV_IOC_PROMOTE) == MAX_ARG_SIZE);
+const _: () = assert!(ioc_size(HKV_IOC_READ) <= MAX_ARG_SIZE);
+
+/// A handle that issues `/dev/hybridkv` ioctls.
+pub trait KernelDevice: Send + Sync {
+    /// Issues ioctl `cmd` on `arg`, which holds the encoded request and
+    /// receives the encoded response in place.
+    ///
+    /// # Errors
+    /// Returns the positive errno of a failed call. The module copies the
+    /// response out before failing for a non-OK status, so `arg` may hold
+    /// a response even then.
+    fn ioctl(&self, cmd: u32, arg: &mut [u8]) -> Result<(), i32>;
+}
+
+impl<T: KernelDevice + ?Sized> KernelDevice for Arc<T> {
+    fn ioctl(&self, cmd: u32, arg: &mut [u8]) -> Result<(), i32> {
+        (**self).ioctl(cmd, arg)
+    }
+}
+
+/// The real device node.
+#[derive(Debug)]
+pub struct DeviceFile {
+    file: File,
+}
+
+impl DeviceFile {
+    /// Opens the device node at `path` for reading and writing.
+    ///
+    /// # Errors
+    /// Returns the `HkvError` for the open's errno (`NotFound` when the
+    /// module is not loaded), or `InternalError` if it has none.
+    pub fn open(path: impl AsRef<Path>) -> HkvResult<Self> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map(|file| DeviceFile { file })
+            .map_err(|err| {
+                err.raw_os_error()
+                    .and_then(HkvError::from_errno)
+                    .unwrap_or(HkvError::InternalError)
+            })
+    }
+}
+
+impl KernelDevice for DeviceFile {
+    fn ioctl(&self, cmd: u32, arg: &mut [u8]) -> Result<(), i32> {
+        debug_assert!(arg.len() >= ioc_size(cmd));
+        // SAFETY: `arg` is valid for `ioc_size(cmd)` bytes of reads and
+        // writes, which is all the module copies in either direction.
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), cmd as _, arg.as_mut_ptr()) };
+        if ret == 0 {
+            return Ok(());
+        }
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    }
+}
+
+/// Whether the client is using the kernel cache.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelHealth {
+    /// Calls reach the module.
+    Healthy = 0,
+    /// A call failed hard; calls short-circuit until a probe succeeds.
+    Degraded = 1,
+    /// The device was missing or spoke another protocol version at
+    /// startup; calls short-circuit until a probe succeeds.
+    Disabled = 2,
+}
+
+impl KernelHealth {
+    /// Lower-case name, as `INFO kernel_cache` reports it.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Disabled => "disabled",
+        }
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Healthy,
+            1 => Self::Degraded,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+impl fmt::Display for KernelHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The health of one client, shared with whatever reports it.
+#[derive(Debug)]
+pub struct HealthMonitor {
+    state: AtomicU8,
+}
+
+impl HealthMonitor {
+    /// A monitor for a client that has not connected, i.e. `Disabled`.
+    pub fn new() -> Self {
+        HealthMonitor {
+            state: AtomicU8::new(KernelHealth::Disabled as u8),
+        }
+    }
+
+    /// The current state.
+    pub fn get(&self) -> KernelHealth {
+        KernelHealth::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    /// Moves to `to`, logging the change once; returns false if the state
+    /// already was `to`.
+    fn transition(&self, to: KernelHealth, cause: Option<HkvError>) -> bool {
+        let from = KernelHealth::from_u8(self.state.swap(to as u8, Ordering::AcqRel));
+        if from == to {
+            return false;
+        }
+        match cause {
+            Some(error) => {
+                tracing::warn!(from = %from, to = %to, error = %error, "kernel cache health changed")
+            }
+            None => tracing::info!(from = %from, to = %to, "kernel cache health changed"),
+        }
+        true
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A write deferred while the client is not Healthy.
+#[derive(Debug)]
+enum Replay {
+    Promote {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        version: Version,
+        ttl: TtlAt,
+    },
+    Invalidate {
+        key: Vec<u8>,
+        version: Version,
+    },
+}
+
+/// Writes to replay on recovery.
+#[derive(Debug, Default)]
+struct ReplayQueue {
+    writes: VecDeque<Replay>,
+    /// Set when a write was dropped or a FLUSH deferred: the cache may
+    /// hold entries a lost write would have removed.
+    flush_first: bool,
+}
+
+impl ReplayQueue {
+    /// Queues `write`, dropping the oldest write and flushing on recovery
+    /// when the queue is full.
+    fn push(&mut self, write: Replay) {
+        if self.writes.len() == REPLAY_CAPACITY {
+            self.writes.pop_front();
+            self.flush_first = true;
+        }
+        self.writes.push_back(write);
+    }
+
+    /// Queues a FLUSH, which makes every earlier write moot.
+    fn push_flush(&mut self) {
+        self.writes.clear();
+        self.flush_first = true;
+    }
+
+    /// Puts `unfinished`, taken from the front of the queue, back in front
+    /// of whatever was queued since.
+    fn restore(&mut self, unfinished: ReplayQueue) {
+        if self.flush_first {
+            // A FLUSH queued since supersedes the unfinished writes.
+            return;
+        }
+        let newer = std::mem::replace(self, unfinished);
+        for write in newer.writes {
+            self.push(write);
+        }
+    }
+}
+
+/// When the next probe may run.
+struct Backoff {
+    delay: Duration,
+    next: Instant,
+}
+
+impl Backoff {
+    fn reset(&mut self, now: Instant) {
+        self.delay = PROBE_MIN_DELAY;
+        self.next = now + PROBE_MIN_DELAY;
+    }
+
+    fn back_off(&mut self, now: Instant) {
+        self.delay = (self.delay * 2).min(PROBE_MAX_DELAY);
+        self.next = now + self.delay;
+    }
+}
+
+/// An open device and the limits it reported.
+struct Link<D> {
+    device: D,
+    limits: Limits,
+}
+
+type Connector<D> = dyn Fn() -> HkvResult<D> + Send + Sync;
+
+/// State shared by every clone of a client.
+struct Shared<D> {
+    connect: Box<Connector<D>>,
+    link: RwLock<Option<Arc<Link<D>>>>,
+    health: Arc<HealthMonitor>,
+    replay: Mutex<ReplayQueue>,
+    backoff: Mutex<Backoff>,
+}
+
+/// Cheaply clonable client for the kernel hot-key cache.
+pub struct KernelCacheClient<D = DeviceFile> {
+    shared: Arc<Shared<D>>,
+}
+
+impl<D> Clone for KernelCacheClient<D> {
+    fn clone(&self) -> Self {
+        KernelCacheClient {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// Why a call did not produce a response.
+enum Failure<R> {
+    /// The client is not Healthy, or the call just failed hard.
+    Unavailable,
+    /// The module refused the request; it may have written a response.
+    Refused(HkvError, Option<R>),
+}
+
+impl KernelCacheClient {
+    /// Opens `DEVICE_PATH` and performs the HELLO handshake; probes reopen
+    /// it.
+    pub fn open() -> Self {
+        Self::connect(|| DeviceFile::open(DEVICE_PATH))
+    }
+}
+
+impl<D: KernelDevice + Clone + 'static> KernelCacheClient<D> {
+    /// Performs the HELLO handshake on `device`; probes reuse a clone of it.
+    pub fn with_device(device: D) -> Self {
+        Self::connect(move || Ok(device.clone()))
+    }
+}
+
+impl<D: KernelDevice + 'static> KernelCacheClient<D> {
+    /// Opens a device with `connect` and performs the HELLO handshake.
+    ///
+    /// Never fails: when the device cannot be opened or the handshake
+    /// fails (e.g. `VersionMismatch`), the client starts `Disabled` and
+    /// `connect` is retried by each probe.
+    pub fn connect<F>(connect: F) -> Self
+    where
+        F: Fn() -> HkvResult<D> + Send + Sync + 'static,
+    {
+        let now = Instant::now();
+        let client = KernelCacheClient {
+            shared: Arc::new(Shared {
+                connect: Box::new(connect),
+                link: RwLock::new(None),
+                health: Arc::new(HealthMonitor::new()),
+                replay: Mutex::new(ReplayQueue::default()),
+                backoff: Mutex::new(Backoff {
+                    delay: PROBE_MIN_DELAY,
+                    next: now + PROBE_MIN_DELAY,
+                }),
+            }),
+        };
+        match client.handshake() {
+            Ok(link) => {
+                *client.shared.link.write() = Some(Arc::new(link));
+                client.shared.health.transition(KernelHealth::Healthy, None);
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "kernel cache disabled at startup");
+            }
+        }
+        client
+    }
+
+    /// The current health.
+    pub fn health(&self) -> KernelHealth {
+        self.shared.health.get()
+    }
+
+    /// The health, for reporting it elsewhere.
+    pub fn health_monitor(&self) -> Arc<HealthMonitor> {
+        Arc::clone(&self.shared.health)
+    }
+
+    /// Key and value limits the module reported in HELLO; the compile-time
+    /// maxima before the first successful handshake.
+    pub fn limits(&self) -> Limits {
+        self.shared
+            .link
+            .read()
+            .as_ref()
+            .map_or(Limits::MAX, |link| link.limits)
+    }
+
+    /// Returns the cached value for `key`, or `None` on a miss.
+    ///
+    /// # Errors
+    /// Returns the module's refusal (such as `Busy`). A key the module
+    /// cannot hold, or a client that is not Healthy, is a miss.
+    pub fn read(&self, key: &[u8]) -> HkvResult<Option<Vec<u8>>> {
+        let Some(key) = self.cacheable_key(key) else {
+            return Ok(None);
+        };
+        match self.call::<_, ReadResponse>(HKV_IOC_READ, &ReadRequest::new(key)) {
+            Ok(response) => Ok(Some(response.value.as_bytes().to_vec())),
+            Err(Failure::Unavailable | Failure::Refused(HkvError::NotFound, _)) => Ok(None),
+            Err(Failure::Refused(err, _)) => Err(err),
+        }
+    }
+
+    /// Marks `key` recently used, returning whether it is cached.
+    ///
+    /// Issues a READ and discards the value: a hit sets the entry's
+    /// referenced bit, so the module's LRU keeps it.
+    ///
+    /// # Errors
+    /// As for `read`.
+    pub fn touch(&self, key: &[u8]) -> HkvResult<bool> {
+        self.read(key).map(|value| value.is_some())
+    }
+
+    /// Stores `value` under `key` at `version`, expiring at `ttl`.
+    ///
+    /// Returns the module's admission decision. `RejectedFull` and
+    /// `RejectedStale` are decisions too, not errors. A client that is not
+    /// Healthy queues the promote for replay and returns `Unspecified`.
+    ///
+    /// # Errors
+    /// Returns `KeyTooLong` or `ValueTooLong` for entries beyond the
+    /// negotiated limits, without issuing the ioctl, and the module's
+    /// refusal otherwise.
+    pub fn promote(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        version: Version,
+        ttl: TtlAt,
+    ) -> HkvResult<PromoteOutcome> {
+        let request = promote_request(self.limits(), key, value, version, ttl)?;
+        match self.call::<_, PromoteResponse>(HKV_IOC_PROMOTE, &request) {
+            Ok(response) => response.outcome(),
+            Err(Failure::Unavailable) => {
+                let queued = self.defer(|replay| {
+                    replay.push(Replay::Promote {
+                        key: key.to_vec(),
+                        value: value.to_vec(),
+                        version,
+                        ttl,
+                    })
+                });
+                if queued {
+                    Ok(PromoteOutcome::Unspecified)
+                } else {
+                    self.promote(key, value, version, ttl)
+                }
+            }
+            Err(Failure::Refused(err, response)) => {
+                match response.as_ref().map(PromoteResponse::outcome) {
+                    Some(Ok(
+                        outcome @ (PromoteOutcome::RejectedFull | PromoteOutcome::RejectedStale),
+                    )) => Ok(outcome),
+                    _ => Err(err),
+                }
+            }
+        }
+    }
+
+    /// Removes `key` unless the cached entry is newer than `version` (`ZERO`
+    /// removes it regardless), returning whether an entry was removed.
+    ///
+    /// A client that is not Healthy queues the invalidate for replay and
+    /// returns `false`.
+    ///
+    /// # Errors
+    /// Returns the module's refusal. A key the module cannot hold is never
+    /// cached, so nothing is removed.
+    pub fn invalidate(&self, key: &[u8], version: Version) -> HkvResult<bool> {
+        let Some(wire_key) = self.cacheable_key(key) else {
+            return Ok(false);
+        };
+        let request = InvalidateRequest::new(wire_key, version);
+        match self.call::<_, InvalidateResponse>(HKV_IOC_INVALIDATE, &request) {
+            Ok(response) => Ok(response.was_removed()),
+            Err(Failure::Unavailable) => {
+                let queued = self.defer(|replay| {
+                    replay.push(Replay::Invalidate {
+                        key: key.to_vec(),
+                        version,
+                    })
+                });
+                if queued {
+                    Ok(false)
+                } else {
+                    self.invalidate(key, version)
+                }
+            }
+            Err(Failure::Refused(err, _)) => Err(err),
+        }
+    }
+
+    /// Returns a snapshot of the module's counters; all zero when the client
+    /// is not Healthy.
+    ///
+    /// # Errors
+    /// Returns the module's refusal.
+    pub fn stats(&self) -> HkvResult<CacheStats> {
+        match self.call::<_, StatsResponse>(HKV_IOC_STATS, &StatsRequest::new()) {
+            Ok(response) => Ok(response.stats),
+            Err(Failure::Unavailable) => Ok(CacheStats::default()),
+            Err(Failure::Refused(err, _)) => Err(err),
+        }
+    }
+
+    /// Empties the module's cache, returning how many entries it removed.
+    ///
+    /// A client that is not Healthy flushes on recovery and returns 0.
+    ///
+    /// # Errors
+    /// Returns the module's refusal.
+    pub fn flush(&self) -> HkvResult<u64> {
+        match self.call::<_, FlushResponse>(HKV_IOC_FLUSH, &FlushRequest::new()) {
+            Ok(response) => Ok(response.removed),
+            Err(Failure::Unavailable) => {
+                if self.defer(ReplayQueue::push_flush) {
+                    Ok(0)
+                } else {
+                    self.flush()
+                }
+            }
+            Err(Failure::Refused(err, _)) => Err(err),
+        }
+    }
+
+    /// Reconnects unless Healthy: on a successful handshake, replays the
+    /// queued writes and returns `Healthy`; otherwise backs off the next
+    /// scheduled probe and returns the unchanged state.
+    pub fn probe(&self) -> KernelHealth {
+        if self.health() == KernelHealth::Healthy {
+            return KernelHealth::Healthy;
+        }
+        // One probe at a time; a concurrent caller sees the current state.
+        let Some(mut backoff) = self.shared.backoff.try_lock() else {
+            return self.health();
+        };
+        let link = match self.handshake() {
+            Ok(link) => link,
+            Err(_) => {
+                backoff.back_off(Instant::now());
+                return self.health();
+            }
+        };
+        // Drain the queue on the new link before publishing it; writes
+        // queued meanwhile are drained by the next round.
+        loop {
+            let batch = {
+                let mut replay = self.shared.replay.lock();
+                if replay.writes.is_empty() && !replay.flush_first {
+                    // Holding the queue lock, so no write can be queued
+                    // after this point and then be stranded.
+                    *self.shared.link.write() = Some(Arc::new(link));
+                    self.shared.health.transition(KernelHealth::Healthy, None);
+                    break;
+                }
+                std::mem::take(&mut *replay)
+            };
+            if let Err((err, unfinished)) = replay(&link, batch) {
+                self.shared.replay.lock().restore(unfinished);
+                self.shared
+                    .health
+                    .transition(KernelHealth::Degraded, Some(err));
+                backoff.back_off(Instant::now());
+                return self.health();
+            }
+        }
+        backoff.reset(Instant::now());
+        KernelHealth::Healthy
+    }
+
+    /// Runs `probe` if the backoff delay has passed.
+    pub fn probe_if_due(&self) -> KernelHealth {
+        let due = self
+            .shared
+            .backoff
+            .try_lock()
+            .is_some_and(|backoff| Instant::now() >= backoff.next);
+        if due { self.probe() } else { self.health() }
+    }
+
+    /// Probes in a background thread whenever the client is not Healthy,
+    /// at the backed-off intervals.
+    pub fn start_prober(&self) -> ProbeHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = Arc::clone(&stop);
+        let client = self.clone();
+        let join = std::thread::spawn(move || {
+            while !stop_thread.load(Ordering::Acquire) {
+                std::thread::sleep(PROBE_MIN_DELAY);
+                if client.health() != KernelHealth::Healthy {
+                    client.probe_if_due();
+                }
+            }
+        });
+        ProbeHandle {
+            stop,
+            join: Some(join),
+        }
+    }
+
+    /// Opens the device and performs the HELLO handshake.
+    fn handshake(&self) -> HkvResult<Link<D>> {
+        let device = (self.shared.connect)()?;
+        let mut arg = [0u8; MAX_ARG_SIZE];
+        let response: HelloResponse =
+            transact(&device, HKV_IOC_HELLO, &HelloRequest::new(), &mut arg)?;
+        let limits = response.negotiated_limits()?;
+        Ok(Link { device, limits })
+    }
+
+    /// `key` as a wire key, or `None` when the module cannot cache it.
+    fn cacheable_key(&self, key: &[u8]) -> Option<Key> {
+        self.limits().check_key(key).ok()?;
+        Key::new(key).ok()
+    }
+
+    /// Queues a write for replay with `queue`, unless a probe has made the
+    /// client Healthy since the call short-circuited; returns false then,
+    /// and the caller issues the write itself.
+    fn defer(&self, queue: impl FnOnce(&mut ReplayQueue)) -> bool {
+        let mut replay = self.shared.replay.lock();
+        if self.health() == KernelHealth::Healthy {
+            return false;
+        }
+        queue(&mut replay);
+        true
+    }
+
+    /// Issues `cmd` when Healthy, degrading the client on a hard failure.
+    fn call<Req: WireFormat, Resp: WireFormat>(
+        &self,
+        cmd: u32,
+        request: &Req,
+    ) -> Result<Resp, Failure<Resp>> {
+        if self.health() != KernelHealth::Healthy {
+            return Err(Failure::Unavailable);
+        }
+        let Some(link) = self.shared.link.read().clone() else {
+            return Err(Failure::Unavailable);
+        };
+        let mut arg = [0u8; MAX_ARG_SIZE];
+        match transact(&link.device, cmd, request, &mut arg) {
+            Ok(response) => Ok(response),
+            Err(err) if is_hard(err) => {
+                if self
+                    .shared
+                    .health
+                    .transition(KernelHealth::Degraded, Some(err))
+                {
+                    self.shared.backoff.lock().reset(Instant::now());
+                }
+                Err(Failure::Unavailable)
+            }
+            Err(err) => Err(Failure::Refused(err, Resp::decode(&arg[..Resp::SIZE]).ok())),
+        }
+    }
+}
+
+/// Stops a prober started by `KernelCacheClient::start_prober`.
+pub struct ProbeHandle {
+    stop: Arc<AtomicBool>,
+    join: Option<JoinHandle<()>>,
+}
+
+impl ProbeHandle {
+    /// Stops the prober and waits for the thread to finish.
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(join) = self.join.take() {
+            let _ = join.join();
+        }
+    }
+}
+
+/// Issues the writes in `batch` on `link`, flushing first if it asks to.
+///
+/// Refused promotes (say, a stale version) are final. A refused invalidate
+/// may leave a stale entry behind, so it flushes and replays the rest.
+///
+/// # Errors
+/// Returns the first hard failure, or a refused FLUSH, with the writes not
+/// yet issued.
+fn replay<D: KernelDevice>(
+    link: &Link<D>,
+    mut batch: ReplayQueue,
+) -> Result<(), (HkvError, ReplayQueue)> {
+    let mut arg = [0u8; MAX_ARG_SIZE];
+    'flush: loop {
+        if batch.flush_first {
+            let flushed: HkvResult<FlushResponse> =
+                transact(&link.device, HKV_IOC_FLUSH, &FlushRequest::new(), &mut arg);
+            if let Err(err) = flushed {
+                return Err((err, batch));
+            }
+            batch.flush_first = false;
+        }
+        while let Some(write) = batch.writes.pop_front() {
+            let result = match &write {
+                Replay::Promote {
+                    key,
+                    value,
+                    version,
+                    ttl,
+                } => match promote_request(link.limits, key, value, *version, *ttl) {
+                    Ok(request) => transact::<_, PromoteResponse>(
+                        &link.device,
+                        HKV_IOC_PROMOTE,
+                        &request,
+                        &mut arg,
+                    )
+                    .map(drop),
+                    // Beyond the module's new limits, so never cached.
+                    Err(_) => Ok(()),
+                },
+                Replay::Invalidate { key, version } => {
+                    match link.limits.check_key(key).and_then(|()| Key::new(key)) {
+                        Ok(key) => transact::<_, InvalidateResponse>(
+                            &link.device,
+                            HKV_IOC_INVALIDATE,
+                            &InvalidateRequest::new(key, *version),
+                            &mut arg,
+                        )
+                        .map(drop),
+                        Err(_) => Ok(()),
+                    }
+                }
+            };
+            match result {
+                Ok(()) => {}
+                Err(err) if is_hard(err) => {
+                    batch.writes.push_front(write);
+                    return Err((err, batch));
+                }
+                Err(_) if matches!(write, Replay::Invalidate { .. }) => {
+                    batch.flush_first = true;
+                    continue 'flush;
+                }
+                Err(_) => {}
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// A PROMOTE request for `key` and `value`, if `limits` admit them.
+fn promote_request(
+    limits: Limits,
+    key: &[u8],
+    value: &[u8],
+    version: Version,
+    ttl: TtlAt,
+) -> HkvResult<PromoteRequest> {
+    limits.check_key(key)?;
+    limits.check_value(value)?;
+    Ok(PromoteRequest::new(
+        Key::new(key)?,
+        Value::new(value)?,
+        version,
+        ttl,
+    ))
+}
+
+/// Failures that suggest the module itself is unhealthy, as opposed to a
+/// refused request.
+fn is_hard(err: HkvError) -> bool {
+    matches!(
+        err,
+        HkvError::InternalError
+            | HkvError::Timeout
+            | HkvError::Interrupted
+            | HkvError::ProtocolViolation
+            | HkvError::UnsupportedCommand
+    )
+}
+
+/// Encodes `request` into `arg`, issues `cmd` (retrying `EINTR`) and
+/// decodes the response.
+fn transact<Req: WireFormat, Resp: WireFormat>(
+    device: &impl KernelDevice,
+    cmd: u32,
+    request: &Req,
+    arg: &mut [u8; MAX_ARG_SIZE],
+) -> HkvResult<Resp> {
+    let arg = &mut arg[..ioc_size(cmd)];
+    let mut retries = 0;
+    loop {
+        arg.fill(0);
+        request.encode(arg)?;
+        match device.ioctl(cmd, arg) {
+            Ok(()) => break,
+            Err(EINTR) if retries < EINTR_RETRIES => retries += 1,
+            Err(errno) => {
+                return Err(HkvError::from_errno(errno).unwrap_or(HkvError::InternalError));
+            }
+        }
+    }
+    Resp::decode(&arg[..Resp::SIZE])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const EIO: i32 = 5;
+
+    type MockClient = KernelCacheClient<Arc<MockKernelDevice>>;
+
+    fn client() -> (MockClient, Arc<MockKernelDevice>) {
+        let device = Arc::new(MockKernelDevice::new());
+        (KernelCacheClient::with_device(Arc::clone(&device)), device)
+    }
+
+    /// Makes the next probe due without waiting out the backoff.
+    fn expire_backoff(client: &MockClient) {
+        client.shared.backoff.lock().next = Instant::now();
+    }
+
+    #[test]
+    fn promote_read_invalidate_round_trip() {
+        let (client, _device) = client();
+        assert_eq!(client.health(), KernelHealth::Healthy);
+        assert_eq!(client.limits(), Limits::MAX);
+        assert_eq!(client.read(b"k").unwrap(), None);
+
+        let outcome = client
+            .promote(b"k", b"v1", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+        assert_eq!(outcome, PromoteOutcome::Admitted);
+        assert_eq!(client.read(b"k").unwrap(), Some(b"v1".to_vec()));
+        assert!(client.touch(b"k").unwrap());
+
+        let outcome = client
+            .promote(b"k", b"v2", Version::new(2), TtlAt::INFINITE)
+            .unwrap();
+        assert_eq!(outcome, PromoteOutcome::ReplacedExisting);
+
+        // An older write neither replaces nor removes the newer entry.
+        let outcome = client
+            .promote(b"k", b"v0", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+        assert_eq!(outcome, PromoteOutcome::RejectedStale);
+        assert!(!client.invalidate(b"k", Version::new(1)).unwrap());
+        assert_eq!(client.read(b"k").unwrap(), Some(b"v2".to_vec()));
+
+        assert!(client.invalidate(b"k", Version::new(2)).unwrap());
+        assert!(!client.touch(b"k").unwrap());
+
+        let stats = client.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.promotions), (3, 2, 2));
+        assert_eq!(stats.entry_count, 0);
+    }
+
+    #[test]
+    fn flush_reports_removed_entries() {
+        let (client, device) = client();
+        for key in [&b"a"[..], b"b", b"c"] {
+            client
+                .promote(key, b"v", Version::ZERO, TtlAt::INFINITE)
+                .unwrap();
+        }
+        assert_eq!(client.flush().unwrap(), 3);
+        assert!(device.is_empty());
+        assert_eq!(client.flush().unwrap(), 0);
+    }
+
+    #[test]
+    fn entries_beyond_the_negotiated_limits_stay_local() {
+        let limits = Limits::new(4, 8).unwrap();
+        let device = Arc::new(MockKernelDevice::with_limits(limits));
+        let client = KernelCacheClient::with_device(Arc::clone(&device));
+        assert_eq!(client.limits(), limits);
+        let calls = device.calls();
+
+        let err = client.promote(b"12345", b"v", Version::ZERO, TtlAt::INFINITE);
+        assert_eq!(err, Err(HkvError::KeyTooLong));
+        let err = client.promote(b"k", b"123456789", Version::ZERO, TtlAt::INFINITE);
+        assert_eq!(err, Err(HkvError::ValueTooLong));
+        assert_eq!(client.read(b"12345").unwrap(), None);
+        assert!(!client.invalidate(&[b'k'; 300], Version::ZERO).unwrap());
+        assert_eq!(device.calls(), calls);
+    }
+
+    #[test]
+    fn interrupted_calls_are_retried() {
+        let (client, device) = client();
+        device.fail_next(EINTR, EINTR_RETRIES);
+        let outcome = client.promote(b"k", b"v", Version::ZERO, TtlAt::INFINITE);
+        assert_eq!(outcome, Ok(PromoteOutcome::Admitted));
+        assert_eq!(client.health(), KernelHealth::Healthy);
+
+        // Beyond the retries the call degrades the client, and misses.
+        device.fail_next(EINTR, EINTR_RETRIES + 1);
+        assert_eq!(client.read(b"k"), Ok(None));
+        assert_eq!(client.health(), KernelHealth::Degraded);
+    }
+
+    #[test]
+    fn errnos_map_to_hkv_errors() {
+        let (client, device) = client();
+        device.fail_next(11, 1);
+        assert_eq!(client.stats(), Err(HkvError::Busy));
+        device.fail_next(HkvError::CapacityExceeded.to_errno().abs(), 1);
+        assert_eq!(client.flush(), Err(HkvError::CapacityExceeded));
+        assert_eq!(client.health(), KernelHealth::Healthy);
+
+        // Unknown errnos are internal errors, which are hard.
+        device.fail_next(9999, 1);
+        assert_eq!(client.flush(), Ok(0));
+        assert_eq!(client.health(), KernelHealth::Degraded);
+    }
+
+    #[test]
+    fn a_vanished_device_degrades_every_clone_without_errors() {
+        let (client, device) = client();
+        let clone = client.clone();
+        let monitor = client.health_monitor();
+        client
+            .promote(b"k", b"v", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+
+        device.set_present(false);
+        assert_eq!(clone.read(b"k"), Ok(None));
+        assert_eq!(monitor.get(), KernelHealth::Degraded);
+
+        // Nothing reaches the device until a probe succeeds.
+        let calls = device.calls();
+        assert!(!client.touch(b"k").unwrap());
+        let outcome = client.promote(b"k", b"v2", Version::new(2), TtlAt::INFINITE);
+        assert_eq!(outcome, Ok(PromoteOutcome::Unspecified));
+        assert_eq!(clone.invalidate(b"gone", Version::ZERO), Ok(false));
+        assert_eq!(client.stats(), Ok(CacheStats::default()));
+        assert_eq!(device.calls(), calls);
+
+        assert_eq!(client.probe_if_due(), KernelHealth::Degraded);
+        assert_eq!(device.calls(), calls);
+        expire_backoff(&client);
+        assert_eq!(client.probe_if_due(), KernelHealth::Degraded);
+        assert_eq!(device.calls(), calls + 1);
+    }
+
+    #[test]
+    fn recovery_replays_queued_writes_in_order() {
+        let (client, device) = client();
+        client
+            .promote(b"old", b"v", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+        device.fail_next(EIO, 1);
+        assert_eq!(client.read(b"old"), Ok(None));
+        assert_eq!(client.health(), KernelHealth::Degraded);
+
+        for (key, version) in [(&b"a"[..], 1), (b"b", 1), (b"a", 2)] {
+            let value = [key[0], version as u8];
+            client
+                .promote(key, &value, Version::new(version), TtlAt::INFINITE)
+                .unwrap();
+        }
+        client.invalidate(b"b", Version::new(1)).unwrap();
+        client.invalidate(b"old", Version::ZERO).unwrap();
+
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        assert_eq!(client.read(b"a").unwrap(), Some(b"a\x02".to_vec()));
+        assert_eq!(client.read(b"b").unwrap(), None);
+        assert_eq!(client.read(b"old").unwrap(), None);
+        assert_eq!(device.len(), 1);
+    }
+
+    /// Records the health a client had whenever an invalidate or flush
+    /// reached the device, and fails promotes on request.
+    #[derive(Default)]
+    struct Watched {
+        device: Arc<MockKernelDevice>,
+        health: std::sync::OnceLock<Arc<HealthMonitor>>,
+        seen: Mutex<Vec<KernelHealth>>,
+        failing_promotes: std::sync::atomic::AtomicU32,
+    }
+
+    impl KernelDevice for Watched {
+        fn ioctl(&self, cmd: u32, arg: &mut [u8]) -> Result<(), i32> {
+            if let (HKV_IOC_INVALIDATE | HKV_IOC_FLUSH, Some(health)) = (cmd, self.health.get()) {
+                self.seen.lock().push(health.get());
+            }
+            if cmd == HKV_IOC_PROMOTE
+                && self
+                    .failing_promotes
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok()
+            {
+                return Err(EIO);
+            }
+            self.device.ioctl(cmd, arg)
+        }
+    }
+
+    #[test]
+    fn replay_finishes_before_reads_resume() {
+        let watched = Arc::new(Watched::default());
+        let device = Arc::clone(&watched.device);
+        let client = KernelCacheClient::with_device(Arc::clone(&watched));
+        watched.health.set(client.health_monitor()).unwrap();
+        client
+            .promote(b"k", b"v", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+
+        // The module survives the failure, so "k" is still cached while
+        // its invalidate waits in the queue.
+        device.fail_next(EIO, 1);
+        assert_eq!(client.read(b"k"), Ok(None));
+        client.invalidate(b"k", Version::new(2)).unwrap();
+        client.flush().unwrap();
+        client.invalidate(b"k", Version::new(3)).unwrap();
+        assert_eq!(device.len(), 1);
+
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        let seen = watched.seen.lock().clone();
+        assert_eq!(seen, [KernelHealth::Degraded; 2]);
+        assert_eq!(client.read(b"k").unwrap(), None);
+    }
+
+    #[test]
+    fn a_failed_replay_keeps_the_rest_queued() {
+        let watched = Arc::new(Watched::default());
+        let client = KernelCacheClient::with_device(Arc::clone(&watched));
+        watched.device.fail_next(EIO, 1);
+        client.stats().unwrap();
+        for key in [&b"a"[..], b"b", b"c"] {
+            client
+                .promote(key, key, Version::ZERO, TtlAt::INFINITE)
+                .unwrap();
+        }
+
+        // HELLO succeeds, then the first replayed promote fails hard.
+        watched.failing_promotes.store(1, Ordering::Relaxed);
+        assert_eq!(client.probe(), KernelHealth::Degraded);
+        assert_eq!(client.shared.replay.lock().writes.len(), 3);
+        assert!(watched.device.is_empty());
+
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        assert_eq!(watched.device.len(), 3);
+        assert_eq!(client.read(b"c").unwrap(), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn a_full_replay_queue_flushes_on_recovery() {
+        let (client, device) = client();
+        client
+            .promote(b"stale", b"v", Version::new(1), TtlAt::INFINITE)
+            .unwrap();
+        device.fail_next(EIO, 1);
+        client.stats().unwrap();
+
+        for i in 0..=REPLAY_CAPACITY as u32 {
+            let key = i.to_be_bytes();
+            client
+                .promote(&key, b"v", Version::ZERO, TtlAt::INFINITE)
+                .unwrap();
+        }
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        // The write that overflowed may have invalidated anything, so the
+        // old entries are gone and only the queued ones are back.
+        assert_eq!(client.read(b"stale").unwrap(), None);
+        assert_eq!(device.len(), REPLAY_CAPACITY);
+    }
+
+    #[test]
+    fn a_missing_device_starts_disabled_and_recovers() {
+        let device = Arc::new(MockKernelDevice::new());
+        device.set_present(false);
+        let client = KernelCacheClient::with_device(Arc::clone(&device));
+        assert_eq!(client.health(), KernelHealth::Disabled);
+        assert_eq!(client.read(b"k"), Ok(None));
+        assert_eq!(client.flush(), Ok(0));
+        client
+            .promote(b"k", b"v", Version::ZERO, TtlAt::INFINITE)
+            .unwrap();
+
+        assert_eq!(client.probe(), KernelHealth::Disabled);
+        device.set_present(true);
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        assert_eq!(client.read(b"k").unwrap(), Some(b"v".to_vec()));
+
+        // A module speaking another version is disabled too.
+        device.fail_next(HkvError::VersionMismatch.to_errno().abs(), 1);
+        let client = KernelCacheClient::with_device(Arc::clone(&device));
+        assert_eq!(client.health(), KernelHealth::Disabled);
+        assert_eq!(client.limits(), Limits::MAX);
+    }
+
+    #[test]
+    fn failed_probes_back_off_exponentially() {
+        let (client, device) = client();
+        device.set_present(false);
+        client.stats().unwrap();
+
+        let delay = || client.shared.backoff.lock().delay;
+        assert_eq!(delay(), PROBE_MIN_DELAY);
+        client.probe();
+        assert_eq!(delay(), PROBE_MIN_DELAY * 2);
+        client.probe();
+        assert_eq!(delay(), PROBE_MIN_DELAY * 4);
+        for _ in 0..16 {
+            client.probe();
+        }
+        assert_eq!(delay(), PROBE_MAX_DELAY);
+
+        device.set_present(true);
+        assert_eq!(client.probe(), KernelHealth::Healthy);
+        assert_eq!(delay(), PROBE_MIN_DELAY);
+    }
+
+    #[test]
+    fn the_prober_restores_health_in_the_background() {
+        let (client, device) = client();
+        let prober = client.start_prober();
+        device.set_present(false);
+        assert_eq!(client.read(b"k"), Ok(None));
+        device.set_present(true);
+        for _ in 0..100 {
+            if client.health() == KernelHealth::Healthy {
+                break;
+            }
+            thread::sleep(PROBE_MIN_DELAY / 5);
+        }
+        prober.stop();
+        assert_eq!(client.health(), KernelHealth::Healthy);
+    }
+
+    #[test]
+    fn clones_share_one_device_across_threads() {
+        let (client, _device) = client();
+        thread::scope(|scope| {
+            for t in 0..4u8 {
+                let client = client.clone();
+                scope.spawn(move || {
+                    for i in 0..64u8 {
+                        let key = [t, i];
+                        client
+                            .promote(&key, &key, Version::ZERO, TtlAt::INFINITE)
+                            .unwrap();
+                        assert_eq!(client.read(&key).unwrap(), Some(key.to_vec()));
+                    }
+                });
+            }
+        });
+        assert_eq!(client.stats().unwrap().entry_count, 256);
+    }
+}
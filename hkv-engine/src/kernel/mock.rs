@@ -0,0 +1,265 @@
+//! In-memory stand-in for the kernel module, so the client can be tested
+//! without loading it. Built for this crate's tests and behind the `mock`
+//! feature for other crates' tests.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use hkv_common::{
+    CacheStats, FlushRequest, FlushResponse, HelloRequest, HelloResponse, HkvError, HkvResult,
+    InvalidateRequest, InvalidateResponse, IoctlCommand, IoctlHeader, Limits, PromoteOutcome,
+    PromoteRequest, PromoteResponse, ReadRequest, ReadResponse, STATUS_OK, StatsRequest,
+    StatsResponse, Value, Version, WireFormat,
+};
+use hkv_kernel::dispatch::{
+    HKV_IOC_FLUSH, HKV_IOC_HELLO, HKV_IOC_INVALIDATE, HKV_IOC_PROMOTE, HKV_IOC_READ, HKV_IOC_STATS,
+    ioc_size,
+};
+use parking_lot::Mutex;
+
+use super::KernelDevice;
+
+/// `ENODEV`, which calls fail with once the module is unloaded.
+const ENODEV: i32 = 19;
+
+/// A cached value and the version it was promoted at.
+type MockEntry = (Vec<u8>, Version);
+
+/// In-memory stand-in for the kernel module, for tests.
+///
+/// Decodes each request from its wire bytes and answers as the module
+/// would: version admission on PROMOTE and INVALIDATE, a response copied
+/// out before a non-OK status fails the call. `fail_next` injects errnos
+/// and `set_present(false)` unloads the module.
+pub struct MockKernelDevice {
+    limits: Limits,
+    /// Cleared while the module is unloaded.
+    present: AtomicBool,
+    entries: Mutex<HashMap<Vec<u8>, MockEntry>>,
+    /// Errno returned, and decremented, by upcoming calls.
+    faults: Mutex<(i32, u32)>,
+    calls: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    promotions: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl MockKernelDevice {
+    /// A device reporting the compile-time maxima in HELLO.
+    pub fn new() -> Self {
+        Self::with_limits(Limits::MAX)
+    }
+
+    /// A device reporting `limits` in HELLO and enforcing them on PROMOTE.
+    pub fn with_limits(limits: Limits) -> Self {
+        MockKernelDevice {
+            limits,
+            present: AtomicBool::new(true),
+            entries: Mutex::new(HashMap::new()),
+            faults: Mutex::new((0, 0)),
+            calls: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            promotions: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Fails the next `count` calls with `errno`, without answering them.
+    pub fn fail_next(&self, errno: i32, count: u32) {
+        *self.faults.lock() = (errno, count);
+    }
+
+    /// Unloads (`false`) or reloads (`true`) the module. While unloaded
+    /// every call fails with `ENODEV`; a reload starts with an empty cache.
+    pub fn set_present(&self, present: bool) {
+        if !present {
+            self.entries.lock().clear();
+        }
+        self.present.store(present, Ordering::Release);
+    }
+
+    /// Number of ioctls issued so far, including failed ones.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// Number of cached entries.
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Returns true when nothing is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn injected_fault(&self) -> Option<i32> {
+        let mut faults = self.faults.lock();
+        let (errno, count) = &mut *faults;
+        if *count == 0 {
+            return None;
+        }
+        *count -= 1;
+        Some(*errno)
+    }
+
+    /// Answers one decoded request, returning its status.
+    fn answer(&self, cmd: u32, arg: &mut [u8]) -> HkvResult<u16> {
+        let request = &arg[..];
+        let (status, response) = match cmd {
+            HKV_IOC_HELLO => {
+                check_header(
+                    HelloRequest::decode(&request[..HelloRequest::SIZE])?.header,
+                    IoctlCommand::Hello,
+                )?;
+                (
+                    STATUS_OK,
+                    HelloResponse::new(STATUS_OK, self.limits).to_wire(),
+                )
+            }
+            HKV_IOC_READ => {
+                let request = ReadRequest::decode(&request[..ReadRequest::SIZE])?;
+                check_header(request.header, IoctlCommand::Read)?;
+                let entries = self.entries.lock();
+                let response = match entries.get(request.key.as_bytes()) {
+                    Some((value, _)) => {
+                        self.hits.fetch_add(1, Ordering::Relaxed);
+                        ReadResponse::new(STATUS_OK, Value::new(value)?)
+                    }
+                    None => {
+                        self.misses.fetch_add(1, Ordering::Relaxed);
+                        ReadResponse::new(HkvError::NotFound.code(), Value::new(b"")?)
+                    }
+                };
+                (response.status, response.to_wire())
+            }
+            HKV_IOC_PROMOTE => {
+                let request = PromoteRequest::decode(&request[..PromoteRequest::SIZE])?;
+                check_header(request.header, IoctlCommand::Promote)?;
+                let limits = self.limits;
+                let checked = limits
+                    .check_key(request.key.as_bytes())
+                    .and_then(|()| limits.check_value(request.value.as_bytes()));
+                let response = match checked {
+                    Err(err) => PromoteResponse::new(err.code()),
+                    Ok(()) => {
+                        let mut entries = self.entries.lock();
+                        let key = request.key.as_bytes();
+                        match entries.get(key) {
+                            Some((_, current)) if !request.version.may_replace(*current) => {
+                                PromoteResponse::with_outcome(
+                                    HkvError::VersionMismatch.code(),
+                                    PromoteOutcome::RejectedStale,
+                                    None,
+                                )
+                            }
+                            existing => {
+                                let outcome = match existing {
+                                    Some(_) => PromoteOutcome::ReplacedExisting,
+                                    None => PromoteOutcome::Admitted,
+                                };
+                                let value = request.value.as_bytes().to_vec();
+                                entries.insert(key.to_vec(), (value, request.version));
+                                self.promotions.fetch_add(1, Ordering::Relaxed);
+                                PromoteResponse::with_outcome(STATUS_OK, outcome, None)
+                            }
+                        }
+                    }
+                };
+                (response.status, response.to_wire())
+            }
+            HKV_IOC_INVALIDATE => {
+                let request = InvalidateRequest::decode(&request[..InvalidateRequest::SIZE])?;
+                check_header(request.header, IoctlCommand::Invalidate)?;
+                let mut entries = self.entries.lock();
+                let key = request.key.as_bytes();
+                let removed = match entries.get(key) {
+                    Some((_, current)) if request.version.may_replace(*current) => {
+                        entries.remove(key);
+                        self.invalidations.fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                    _ => false,
+                };
+                (
+                    STATUS_OK,
+                    InvalidateResponse::new(STATUS_OK, removed).to_wire(),
+                )
+            }
+            HKV_IOC_STATS => {
+                check_header(
+                    StatsRequest::decode(&request[..StatsRequest::SIZE])?.header,
+                    IoctlCommand::Stats,
+                )?;
+                let (hits, misses) = (
+                    self.hits.load(Ordering::Relaxed),
+                    self.misses.load(Ordering::Relaxed),
+                );
+                let stats = CacheStats {
+                    lookups: hits + misses,
+                    hits,
+                    misses,
+                    promotions: self.promotions.load(Ordering::Relaxed),
+                    invalidations: self.invalidations.load(Ordering::Relaxed),
+                    entry_count: self.len() as u64,
+                    ..CacheStats::default()
+                };
+                (STATUS_OK, StatsResponse::new(STATUS_OK, stats).to_wire())
+            }
+            HKV_IOC_FLUSH => {
+                check_header(
+                    FlushRequest::decode(&request[..FlushRequest::SIZE])?.header,
+                    IoctlCommand::Flush,
+                )?;
+                let removed = self.entries.lock().drain().count() as u64;
+                (STATUS_OK, FlushResponse::new(STATUS_OK, removed).to_wire())
+            }
+            _ => return Err(HkvError::UnsupportedCommand),
+        };
+        arg.fill(0);
+        arg[..response.len()].copy_from_slice(&response);
+        Ok(status)
+    }
+}
+
+impl Default for MockKernelDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KernelDevice for MockKernelDevice {
+    fn ioctl(&self, cmd: u32, arg: &mut [u8]) -> Result<(), i32> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if !self.present.load(Ordering::Acquire) {
+            return Err(ENODEV);
+        }
+        if let Some(errno) = self.injected_fault() {
+            return Err(errno);
+        }
+        if arg.len() < ioc_size(cmd) {
+            return Err(HkvError::InvalidInput.to_errno().abs());
+        }
+        match self.answer(cmd, arg) {
+            Ok(STATUS_OK) => Ok(()),
+            Ok(status) => Err(HkvError::from_code(status)
+                .unwrap_or(HkvError::InternalError)
+                .to_errno()
+                .abs()),
+            Err(err) => Err(err.to_errno().abs()),
+        }
+    }
+}
+
+/// Rejects a request header the module would refuse.
+fn check_header(header: IoctlHeader, command: IoctlCommand) -> HkvResult<()> {
+    if header.version != IoctlHeader::new(command).version {
+        return Err(HkvError::VersionMismatch);
+    }
+    if header != IoctlHeader::new(command).with_flags(header.flags()?) {
+        return Err(HkvError::ProtocolViolation);
+    }
+    Ok(())
+}
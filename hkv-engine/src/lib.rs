@@ -1,6 +1,12 @@
 pub mod engine;
+pub mod kernel;
 pub mod memory;
+pub mod snapshot;
+mod sync;
 
+pub use engine::EngineStats;
 pub use engine::KVEngine;
+pub use engine::SnapshotEntry;
 pub use engine::TtlStatus;
+pub use kernel::{HealthMonitor, KernelCacheClient, KernelHealth};
 pub use memory::MemoryEngine;
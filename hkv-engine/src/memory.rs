@@ -7,9 +7,11 @@
 //!
 //! - Use `MemoryEngine::new()` for a default sharded engine with unlimited
 //!   capacity (Phase 1 baseline).
-//! - Use `MemoryEngine::with_shard_count_and_capacity` to enforce a byte limit
-//!   and trigger LRU eviction.
-//! - Use `start_expirer` to enable active TTL cleanup in the background.
+//! - Use `MemoryEngine::with_capacity` (or `with_shard_count_and_capacity`
+//!   to also pick the shard count) to enforce a byte limit and trigger LRU
+//!   eviction.
+//! - Use `start_expirer` to enable active TTL cleanup in the background,
+//!   or `start_expirer_with` to also learn how long each cycle took.
 //!
 //! ## Design Principles
 //!
@@ -42,22 +44,25 @@ use std::collections::BinaryHeap;
 use std::hash::{BuildHasher, Hasher};
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    atomic::{AtomicBool, Ordering},
 };
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 use ahash::RandomState;
 use hashbrown::HashMap;
-use parking_lot::RwLock;
 
-use hkv_common::{HkvError, HkvResult};
+use hkv_common::{HkvError, HkvResult, TtlAfter};
 
-use crate::engine::{KVEngine, TtlStatus};
+use crate::engine::{EngineStats, KVEngine, SnapshotEntry, TtlStatus, check_abort};
+use crate::sync::{AtomicU64, AtomicUsize, RwLock};
 
 /// Default shards = CPU count * multiplier to reduce lock contention.
 const DEFAULT_SHARD_MULTIPLIER: usize = 4;
 
+/// A key and its value, as `MemoryEngine::iter` returns them.
+pub type KeyValue = (Arc<[u8]>, Arc<[u8]>);
+
 /// Internal node representing a single key/value entry.
 ///
 /// Uses an index-based intrusive list (pattern) for O(1) LRU updates without
@@ -142,6 +147,13 @@ struct ShardInner {
     /// LRU head (oldest) and tail (most recent).
     head: Option<usize>,
     tail: Option<usize>,
+    /// Nodes with a TTL, including expired ones not yet purged.
+    expiring: usize,
+    /// Sum of those nodes' deadlines, in nanoseconds after `epoch`, so the
+    /// mean remaining TTL needs no walk.
+    deadline_sum: u128,
+    /// Reference point for `deadline_sum`; no deadline precedes it.
+    epoch: Instant,
 }
 
 impl ShardInner {
@@ -157,6 +169,9 @@ impl ShardInner {
             ttl_heap: BinaryHeap::new(),
             head: None,
             tail: None,
+            expiring: 0,
+            deadline_sum: 0,
+            epoch: Instant::now(),
         }
     }
 
@@ -246,6 +261,30 @@ impl ShardInner {
         idx
     }
 
+    /// Replaces a node's TTL, keeping `expiring` and `deadline_sum` in
+    /// step, and schedules the new deadline.
+    fn set_expiry(&mut self, idx: usize, expires_at: Option<Instant>, token: u64) {
+        let Some(node) = self.nodes[idx].as_mut() else {
+            return;
+        };
+        match (node.expires_at.is_some(), expires_at.is_some()) {
+            (false, true) => self.expiring += 1,
+            (true, false) => self.expiring -= 1,
+            _ => {}
+        }
+        if let Some(old) = node.expires_at {
+            self.deadline_sum -= old.saturating_duration_since(self.epoch).as_nanos();
+        }
+        if let Some(new) = expires_at {
+            self.deadline_sum += new.saturating_duration_since(self.epoch).as_nanos();
+        }
+        node.expires_at = expires_at;
+        node.ttl_token = token;
+        if let Some(expires_at) = expires_at {
+            self.schedule_expiration(idx, expires_at, token);
+        }
+    }
+
     /// Adds or refreshes a node in the TTL heap.
     fn schedule_expiration(&mut self, idx: usize, expires_at: Instant, token: u64) {
         self.ttl_heap.push(Reverse(ExpirationEntry {
@@ -285,6 +324,10 @@ impl ShardInner {
         let node = self.nodes[idx].as_ref()?;
         let key = Arc::clone(&node.key);
         let size = node.size;
+        if let Some(deadline) = node.expires_at {
+            self.expiring -= 1;
+            self.deadline_sum -= deadline.saturating_duration_since(self.epoch).as_nanos();
+        }
 
         // Detach before clearing the slot so LRU pointers stay valid.
         self.lru_remove(idx);
@@ -359,11 +402,17 @@ impl MemoryEngine {
     ///
     /// Uses an effectively unbounded capacity to keep Phase 1 simple.
     pub fn new() -> Self {
+        Self::with_capacity(usize::MAX)
+    }
+
+    /// Creates an engine with the default shard count and a byte capacity
+    /// limit.
+    pub fn with_capacity(max_bytes: usize) -> Self {
         let threads = std::thread::available_parallelism()
             .map(|count| count.get())
             .unwrap_or(1);
         let shard_count = threads.saturating_mul(DEFAULT_SHARD_MULTIPLIER);
-        Self::with_shard_count(shard_count)
+        Self::with_shard_count_and_capacity(shard_count, max_bytes)
     }
 
     /// Creates a new engine with a caller-provided shard count.
@@ -414,10 +463,55 @@ impl MemoryEngine {
         removed
     }
 
+    /// Collects every live key/value pair, one shard's read lock at a time.
+    ///
+    /// Expired entries are skipped rather than removed, so iteration never
+    /// waits on a write lock; the order is unspecified.
+    pub fn iter(&self) -> Vec<KeyValue> {
+        let mut pairs = Vec::new();
+        for shard in &self.shards {
+            let inner = shard.inner.read();
+            let now = Instant::now();
+            pairs.reserve(inner.map.len());
+            pairs.extend(
+                inner
+                    .nodes
+                    .iter()
+                    .flatten()
+                    .filter(|node| !node.is_expired(now))
+                    .map(|node| (Arc::clone(&node.key), Arc::clone(&node.value))),
+            );
+        }
+        pairs
+    }
+
+    /// Counts keys with a TTL, including expired ones not yet purged.
+    ///
+    /// Each shard keeps its count up to date, so this only sums them.
+    pub fn expires_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.inner.read().expiring)
+            .sum()
+    }
+
     /// Starts a background thread that periodically removes expired entries.
     ///
     /// The returned handle must be stopped to avoid leaking the thread.
     pub fn start_expirer(self: &Arc<Self>, interval: Duration) -> ExpirationHandle {
+        self.start_expirer_with(interval, |_| {})
+    }
+
+    /// Like `start_expirer`, calling `on_cycle` on the sweeper thread with
+    /// the time each cycle took.
+    pub fn start_expirer_with<F>(
+        self: &Arc<Self>,
+        interval: Duration,
+        on_cycle: F,
+    ) -> ExpirationHandle
+    where
+        F: Fn(Duration) + Send + 'static,
+    {
         let interval = if interval.is_zero() {
             Duration::from_millis(1)
         } else {
@@ -431,7 +525,9 @@ impl MemoryEngine {
         let join = std::thread::spawn(move || {
             while !stop_thread.load(Ordering::Acquire) {
                 std::thread::sleep(interval);
-                engine.purge_expired(Instant::now());
+                let started = Instant::now();
+                engine.purge_expired(started);
+                on_cycle(started.elapsed());
             }
         });
 
@@ -464,12 +560,10 @@ impl MemoryEngine {
     ///
     /// When `ttl` is `Some`, the value and expiration become visible together
     /// under the same shard lock to avoid a half-written state.
-    fn write_value(&self, key: Vec<u8>, value: Vec<u8>, ttl: Option<Duration>) {
-        let shard = self.shard_for(&key);
+    fn write_value(&self, key_arc: Arc<[u8]>, value_arc: Arc<[u8]>, ttl: Option<Duration>) {
+        let shard = self.shard_for(&key_arc);
         let now = Instant::now();
         let mut inner = shard.inner.write();
-        let key_arc: Arc<[u8]> = Arc::from(key);
-        let value_arc: Arc<[u8]> = Arc::from(value);
         let new_size = Self::entry_size(key_arc.len(), value_arc.len());
         let expires_at = ttl.map(|ttl| now + ttl);
 
@@ -482,16 +576,12 @@ impl MemoryEngine {
             }
         }
 
-        let mut scheduled_expiration = None;
-
         if let Some(&idx) = inner.map.get(key_arc.as_ref()) {
             let token = self.next_ttl_token();
             if let Some(node) = inner.nodes[idx].as_mut() {
                 let old_size = node.size;
                 node.value = value_arc;
                 node.size = new_size;
-                node.expires_at = expires_at;
-                node.ttl_token = token;
                 inner.touch(idx);
 
                 if new_size > old_size {
@@ -502,28 +592,17 @@ impl MemoryEngine {
                         .fetch_sub(old_size - new_size, Ordering::Relaxed);
                 }
             }
-
-            if let Some(expires_at) = expires_at {
-                scheduled_expiration = Some((idx, expires_at, token));
-            }
+            inner.set_expiry(idx, expires_at, token);
         } else {
             let idx = inner.insert_new(Arc::clone(&key_arc), value_arc, new_size);
             self.used_bytes.fetch_add(new_size, Ordering::Relaxed);
 
-            if let Some(expires_at) = expires_at {
+            if expires_at.is_some() {
                 let token = self.next_ttl_token();
-                if let Some(node) = inner.nodes[idx].as_mut() {
-                    node.expires_at = Some(expires_at);
-                    node.ttl_token = token;
-                }
-                scheduled_expiration = Some((idx, expires_at, token));
+                inner.set_expiry(idx, expires_at, token);
             }
         }
 
-        if let Some((idx, expires_at, token)) = scheduled_expiration {
-            inner.schedule_expiration(idx, expires_at, token);
-        }
-
         drop(inner);
         self.evict_if_needed();
     }
@@ -620,13 +699,20 @@ impl KVEngine for MemoryEngine {
     ///
     /// This resets TTL to `None` and triggers eviction when over budget.
     fn set(&self, key: Vec<u8>, value: Vec<u8>) -> HkvResult<()> {
-        self.write_value(key, value, None);
+        self.write_value(Arc::from(key), Arc::from(value), None);
         Ok(())
     }
 
     /// Inserts or replaces a key and attaches an expiration atomically.
-    fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: Duration) -> HkvResult<()> {
-        self.write_value(key, value, Some(ttl));
+    fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: TtlAfter) -> HkvResult<()> {
+        self.write_value(Arc::from(key), Arc::from(value), Some(ttl.as_duration()));
+        Ok(())
+    }
+
+    /// Copies `key` and `value` straight into the entry's shared buffers.
+    fn set_borrowed(&self, key: &[u8], value: &[u8], ttl: Option<TtlAfter>) -> HkvResult<()> {
+        let ttl = ttl.map(|ttl| ttl.as_duration());
+        self.write_value(Arc::from(key), Arc::from(value), ttl);
         Ok(())
     }
 
@@ -658,7 +744,7 @@ impl KVEngine for MemoryEngine {
     /// Sets a TTL for an existing key.
     ///
     /// Missing or expired keys return `HkvError::NotFound`.
-    fn expire(&self, key: &[u8], ttl: Duration) -> HkvResult<()> {
+    fn expire(&self, key: &[u8], ttl: TtlAfter) -> HkvResult<()> {
         let shard = self.shard_for(key);
         let now = Instant::now();
         let mut inner = shard.inner.write();
@@ -681,12 +767,7 @@ impl KVEngine for MemoryEngine {
         }
 
         let token = self.next_ttl_token();
-        let expires_at = now + ttl;
-        if let Some(node) = inner.nodes[idx].as_mut() {
-            node.expires_at = Some(expires_at);
-            node.ttl_token = token;
-        }
-        inner.schedule_expiration(idx, expires_at, token);
+        inner.set_expiry(idx, Some(now + ttl.as_duration()), token);
 
         Ok(())
     }
@@ -730,6 +811,97 @@ impl KVEngine for MemoryEngine {
             }
         }
     }
+
+    /// Copies live entries shard by shard without touching LRU order.
+    ///
+    /// Only read locks are taken; expired entries are skipped rather than
+    /// removed so snapshots never mutate engine state.
+    fn snapshot(&self) -> HkvResult<Vec<SnapshotEntry>> {
+        let mut entries = Vec::new();
+        for shard in &self.shards {
+            let inner = shard.inner.read();
+            let now = Instant::now();
+            entries.reserve(inner.map.len());
+            for node in inner.nodes.iter().flatten() {
+                if node.is_expired(now) {
+                    continue;
+                }
+                entries.push(SnapshotEntry {
+                    key: Arc::clone(&node.key),
+                    value: Arc::clone(&node.value),
+                    ttl: node.expires_at.map(|deadline| deadline - now),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Collects keys shard by shard under read locks, checking
+    /// `should_abort` as it goes; expired entries are skipped.
+    fn keys(&self, should_abort: Option<&AtomicBool>) -> HkvResult<Vec<Arc<[u8]>>> {
+        let mut keys = Vec::new();
+        for shard in &self.shards {
+            let inner = shard.inner.read();
+            let now = Instant::now();
+            keys.reserve(inner.map.len());
+            for node in inner.nodes.iter().flatten() {
+                check_abort(should_abort, keys.len())?;
+                if !node.is_expired(now) {
+                    keys.push(Arc::clone(&node.key));
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Empties one shard at a time, swapping in a fresh shard under the
+    /// write lock and freeing the old entries after releasing it. An abort
+    /// is noticed between shards.
+    fn flush(&self, should_abort: Option<&AtomicBool>) -> HkvResult<usize> {
+        let mut removed = 0;
+        for shard in &self.shards {
+            check_abort(should_abort, 0)?;
+            let old = std::mem::replace(
+                &mut *shard.inner.write(),
+                ShardInner::new(self.hash_state.clone()),
+            );
+            let size: usize = old.nodes.iter().flatten().map(|node| node.size).sum();
+            self.used_bytes.fetch_sub(size, Ordering::Relaxed);
+            removed += old.map.len();
+        }
+        Ok(removed)
+    }
+
+    fn stats(&self) -> EngineStats {
+        let mut stats = EngineStats {
+            used_bytes: self.used_bytes.load(Ordering::Relaxed),
+            ..EngineStats::default()
+        };
+        for shard in &self.shards {
+            let inner = shard.inner.read();
+            stats.keys += inner.map.len();
+            stats.expires += inner.expiring;
+        }
+        stats
+    }
+
+    /// Derives the mean from each shard's deadline sum and count, so it
+    /// costs one read lock per shard. Expired entries not yet purged count
+    /// with a negative remaining TTL until the expirer removes them.
+    fn avg_ttl_ms(&self) -> u64 {
+        let (mut remaining, mut count) = (0i128, 0u128);
+        for shard in &self.shards {
+            let inner = shard.inner.read();
+            let elapsed = Instant::now()
+                .saturating_duration_since(inner.epoch)
+                .as_nanos();
+            let expiring = inner.expiring as u128;
+            remaining += inner.deadline_sum as i128 - (expiring * elapsed) as i128;
+            count += expiring;
+        }
+        let remaining = u128::try_from(remaining).unwrap_or(0);
+        (remaining.checked_div(count).unwrap_or(0) / 1_000_000) as u64
+    }
 }
 
 /// Normalizes shard counts to a power of two for fast masking.
@@ -743,6 +915,8 @@ fn normalize_shard_count(count: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::expire_at_with;
+    use hkv_common::{FixedClock, TtlAt};
     use std::sync::Barrier;
     use std::thread;
 
@@ -805,7 +979,7 @@ mod tests {
     fn expire_hides_value() {
         let engine = MemoryEngine::with_shard_count(2);
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
-        engine.expire(b"alpha", Duration::from_millis(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_millis(1)).unwrap();
         std::thread::sleep(Duration::from_millis(5));
         assert!(engine.get(b"alpha").unwrap().is_none());
     }
@@ -814,7 +988,7 @@ mod tests {
     fn purge_expired_removes_entries() {
         let engine = MemoryEngine::with_shard_count(2);
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
-        engine.expire(b"alpha", Duration::from_millis(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_millis(1)).unwrap();
         std::thread::sleep(Duration::from_millis(5));
 
         let removed = engine.purge_expired(Instant::now());
@@ -826,12 +1000,36 @@ mod tests {
     fn expire_tracks_entries_in_ttl_heap() {
         let engine = MemoryEngine::with_shard_count(1);
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
-        engine.expire(b"alpha", Duration::from_secs(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_secs(1)).unwrap();
 
         let inner = engine.shards[0].inner.read();
         assert_eq!(inner.ttl_heap.len(), 1);
     }
 
+    #[test]
+    fn expire_at_converts_deadline_against_clock() {
+        let engine = MemoryEngine::with_shard_count(1);
+        engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
+        engine.set(b"beta".to_vec(), b"value".to_vec()).unwrap();
+        let clock = FixedClock(1_700_000_000_000_000_000);
+
+        let deadline = TtlAfter::from_secs(60).deadline(&clock);
+        expire_at_with(&engine, b"alpha", deadline, &clock).unwrap();
+        let past = TtlAt::from_unix_nanos(1);
+        expire_at_with(&engine, b"beta", past, &clock).unwrap();
+
+        match engine.ttl(b"alpha").unwrap() {
+            TtlStatus::ExpiresIn(remaining) => assert!(remaining > Duration::from_secs(58)),
+            other => panic!("unexpected ttl status: {other:?}"),
+        }
+        assert_eq!(engine.get(b"beta").unwrap(), None);
+        assert_eq!(
+            engine.expire_at(b"alpha", TtlAt::INFINITE),
+            Err(HkvError::InvalidInput)
+        );
+        assert_eq!(engine.expire_at(b"missing", past), Err(HkvError::NotFound));
+    }
+
     #[test]
     fn set_with_ttl_stores_value_and_expiration_in_one_write_path() {
         let engine = MemoryEngine::with_shard_count(1);
@@ -839,7 +1037,10 @@ mod tests {
             .set_with_ttl(
                 b"alpha".to_vec(),
                 b"value".to_vec(),
-                miri_aware_ttl(Duration::from_millis(20), Duration::from_secs(2)),
+                TtlAfter::new(miri_aware_ttl(
+                    Duration::from_millis(20),
+                    Duration::from_secs(2),
+                )),
             )
             .unwrap();
 
@@ -854,10 +1055,18 @@ mod tests {
     fn set_with_ttl_refreshes_deadline_without_stale_heap_deleting_value() {
         let engine = MemoryEngine::with_shard_count(1);
         engine
-            .set_with_ttl(b"alpha".to_vec(), b"old".to_vec(), refreshed_ttl_short())
+            .set_with_ttl(
+                b"alpha".to_vec(),
+                b"old".to_vec(),
+                TtlAfter::new(refreshed_ttl_short()),
+            )
             .unwrap();
         engine
-            .set_with_ttl(b"alpha".to_vec(), b"new".to_vec(), refreshed_ttl_long())
+            .set_with_ttl(
+                b"alpha".to_vec(),
+                b"new".to_vec(),
+                TtlAfter::new(refreshed_ttl_long()),
+            )
             .unwrap();
         std::thread::sleep(refreshed_ttl_sleep());
 
@@ -874,8 +1083,12 @@ mod tests {
     fn purge_expired_ignores_stale_deadline_after_ttl_refresh() {
         let engine = MemoryEngine::with_shard_count(1);
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
-        engine.expire(b"alpha", refreshed_ttl_short()).unwrap();
-        engine.expire(b"alpha", refreshed_ttl_long()).unwrap();
+        engine
+            .expire(b"alpha", TtlAfter::new(refreshed_ttl_short()))
+            .unwrap();
+        engine
+            .expire(b"alpha", TtlAfter::new(refreshed_ttl_long()))
+            .unwrap();
         std::thread::sleep(refreshed_ttl_sleep());
 
         let removed = engine.purge_expired(Instant::now());
@@ -887,7 +1100,7 @@ mod tests {
     fn set_clears_pending_expiration_without_deleting_new_value() {
         let engine = MemoryEngine::with_shard_count(1);
         engine.set(b"alpha".to_vec(), b"old".to_vec()).unwrap();
-        engine.expire(b"alpha", Duration::from_millis(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_millis(1)).unwrap();
         engine.set(b"alpha".to_vec(), b"new".to_vec()).unwrap();
         std::thread::sleep(Duration::from_millis(5));
 
@@ -901,7 +1114,7 @@ mod tests {
     fn expirer_thread_clears_expired() {
         let engine = Arc::new(MemoryEngine::with_shard_count(2));
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
-        engine.expire(b"alpha", Duration::from_millis(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_millis(1)).unwrap();
 
         let handle = engine.start_expirer(Duration::from_millis(1));
         std::thread::sleep(Duration::from_millis(5));
@@ -910,6 +1123,20 @@ mod tests {
         assert!(engine.get(b"alpha").unwrap().is_none());
     }
 
+    #[test]
+    fn expirer_reports_each_cycle() {
+        let engine = Arc::new(MemoryEngine::with_shard_count(2));
+        let cycles = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&cycles);
+        let handle = engine.start_expirer_with(Duration::from_millis(1), move |_| {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        handle.stop();
+
+        assert!(cycles.load(Ordering::Relaxed) > 0);
+    }
+
     #[test]
     fn evicts_lru_by_bytes() {
         let engine = MemoryEngine::with_shard_count_and_capacity(1, 10);
@@ -931,7 +1158,7 @@ mod tests {
         engine.set(b"alpha".to_vec(), b"value".to_vec()).unwrap();
         assert_eq!(engine.ttl(b"alpha").unwrap(), TtlStatus::NoExpiry);
 
-        engine.expire(b"alpha", Duration::from_millis(1)).unwrap();
+        engine.expire(b"alpha", TtlAfter::from_millis(1)).unwrap();
         std::thread::sleep(Duration::from_millis(5));
         assert_eq!(engine.ttl(b"alpha").unwrap(), TtlStatus::Missing);
     }
@@ -979,4 +1206,147 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn stats_track_entries_and_bytes() {
+        let engine = MemoryEngine::with_shard_count(4);
+        assert_eq!(engine.stats(), EngineStats::default());
+
+        engine.set(b"a".to_vec(), b"123".to_vec()).unwrap();
+        engine.set(b"bb".to_vec(), b"45".to_vec()).unwrap();
+        assert_eq!(
+            engine.stats(),
+            EngineStats {
+                keys: 2,
+                used_bytes: 8,
+                expires: 0,
+            }
+        );
+
+        engine.delete(b"a").unwrap();
+        assert_eq!(
+            engine.stats(),
+            EngineStats {
+                keys: 1,
+                used_bytes: 4,
+                expires: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn expires_count_follows_every_ttl_change() {
+        let engine = MemoryEngine::with_shard_count(4);
+        let hour = TtlAfter::from_secs(3600);
+        for i in 0..10 {
+            let key = format!("ttl:{i}").into_bytes();
+            engine.set_with_ttl(key, b"v".to_vec(), hour).unwrap();
+        }
+        engine.set(b"plain".to_vec(), b"v".to_vec()).unwrap();
+        assert_eq!(engine.expires_count(), 10);
+        assert_eq!(engine.stats().expires, 10);
+        let avg = engine.avg_ttl_ms();
+        assert!((3_590_000..=3_600_000).contains(&avg), "{avg}");
+
+        // Refreshing a TTL keeps the count; plain SET clears one.
+        engine.expire(b"ttl:0", hour).unwrap();
+        engine.set(b"ttl:1".to_vec(), b"v".to_vec()).unwrap();
+        assert_eq!(engine.expires_count(), 9);
+        engine.delete(b"ttl:2").unwrap();
+        engine.expire(b"plain", hour).unwrap();
+        assert_eq!(engine.expires_count(), 9);
+        // The deadline sums follow every change: eight keys with an hour
+        // left and one with three average out at 11h / 9.
+        engine
+            .expire(b"ttl:3", TtlAfter::from_secs(3 * 3600))
+            .unwrap();
+        let avg = engine.avg_ttl_ms();
+        assert!((4_390_000..=4_400_000).contains(&avg), "{avg}");
+
+        // Lazy expiry on access removes the entry and its count.
+        engine
+            .set_with_ttl(b"short".to_vec(), b"v".to_vec(), TtlAfter::from_millis(1))
+            .unwrap();
+        assert_eq!(engine.expires_count(), 10);
+        thread::sleep(Duration::from_millis(5));
+        assert!(engine.get(b"short").unwrap().is_none());
+        assert_eq!(engine.expires_count(), 9);
+        assert_eq!(engine.purge_expired(Instant::now()), 0);
+    }
+
+    #[test]
+    fn snapshot_returns_live_entries_with_remaining_ttl() {
+        let engine = MemoryEngine::with_shard_count(4);
+        engine.set(b"plain".to_vec(), b"one".to_vec()).unwrap();
+        engine
+            .set_with_ttl(b"ttl".to_vec(), b"two".to_vec(), TtlAfter::from_secs(60))
+            .unwrap();
+        engine
+            .set_with_ttl(b"gone".to_vec(), b"three".to_vec(), TtlAfter::ZERO)
+            .unwrap();
+
+        let mut entries = engine.snapshot().unwrap();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(&*entries[0].key, b"plain");
+        assert_eq!(entries[0].ttl, None);
+        assert_eq!(&*entries[1].key, b"ttl");
+        assert_eq!(&*entries[1].value, b"two");
+        let remaining = entries[1].ttl.unwrap();
+        assert!(remaining > Duration::from_secs(58) && remaining <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn keys_and_flush_cover_every_shard() {
+        let engine = MemoryEngine::with_shard_count(4);
+        for i in 0..100 {
+            engine
+                .set(format!("key:{i}").into_bytes(), b"value".to_vec())
+                .unwrap();
+        }
+        engine
+            .set_with_ttl(b"gone".to_vec(), b"v".to_vec(), TtlAfter::ZERO)
+            .unwrap();
+
+        let keys = engine.keys(None).unwrap();
+        assert_eq!(keys.len(), 100);
+        assert!(keys.iter().all(|key| key.starts_with(b"key:")));
+
+        assert_eq!(engine.flush(None).unwrap(), 101);
+        assert_eq!(engine.stats(), EngineStats::default());
+        assert_eq!(engine.get(b"key:1").unwrap(), None);
+        engine.set(b"after".to_vec(), b"v".to_vec()).unwrap();
+        assert_eq!(engine.keys(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keys_and_flush_stop_once_aborted() {
+        let engine = MemoryEngine::with_shard_count(4);
+        engine.set(b"key".to_vec(), b"value".to_vec()).unwrap();
+        let abort = AtomicBool::new(true);
+
+        assert_eq!(engine.keys(Some(&abort)), Err(HkvError::Interrupted));
+        assert_eq!(engine.flush(Some(&abort)), Err(HkvError::Interrupted));
+        assert!(engine.get(b"key").unwrap().is_some());
+    }
+
+    #[test]
+    fn iter_skips_expired_entries_without_an_expirer() {
+        let engine = MemoryEngine::with_shard_count(4);
+        engine.set(b"plain".to_vec(), b"one".to_vec()).unwrap();
+        engine
+            .set_with_ttl(b"ttl".to_vec(), b"two".to_vec(), TtlAfter::from_secs(60))
+            .unwrap();
+        engine
+            .set_with_ttl(b"gone".to_vec(), b"three".to_vec(), TtlAfter::ZERO)
+            .unwrap();
+
+        let mut pairs = engine.iter();
+        pairs.sort();
+        let pairs: Vec<(&[u8], &[u8])> = pairs.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+        assert_eq!(pairs, [(&b"plain"[..], &b"one"[..]), (b"ttl", b"two")]);
+        // Skipped, not removed: the expired entry is still counted.
+        assert_eq!(engine.stats().keys, 3);
+    }
 }
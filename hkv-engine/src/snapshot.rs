@@ -0,0 +1,228 @@
+//! # Snapshot Format
+//!
+//! Encode and decode point-in-time engine snapshots (`dump.hkv`).
+//!
+//! ## Layout
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! magic    [u8; 7] = "HKVDUMP"
+//! version  u8      = SNAPSHOT_VERSION
+//! count    u64
+//! entries  count * { expires_at_ms: u64, key_len: u32, key, value_len: u32, value }
+//! checksum u64     = FNV-1a over every preceding byte
+//! ```
+//!
+//! ## Design Principles
+//!
+//! 1. **Wall-Clock Deadlines**: TTLs are stored as absolute Unix milliseconds
+//!    (0 = no expiry) so a reload honors time spent on disk.
+//! 2. **Self-Validating**: A trailing checksum rejects truncated or corrupted
+//!    files instead of loading partial data.
+//! 3. **No Dependencies**: A flat length-prefixed layout keeps the codec small.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hkv_common::{HkvError, HkvResult};
+
+use crate::engine::SnapshotEntry;
+
+/// Magic prefix identifying snapshot files.
+pub const SNAPSHOT_MAGIC: &[u8; 7] = b"HKVDUMP";
+
+/// Current snapshot format version.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = SNAPSHOT_MAGIC.len() + 1 + 8;
+const CHECKSUM_LEN: usize = 8;
+
+/// Serializes entries, converting remaining TTLs into deadlines relative to `now`.
+pub fn encode(entries: &[SnapshotEntry], now: SystemTime) -> Vec<u8> {
+    let now_ms = unix_millis(now);
+    let payload: usize = entries
+        .iter()
+        .map(|entry| 16 + entry.key.len() + entry.value.len())
+        .sum();
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload + CHECKSUM_LEN);
+
+    buf.extend_from_slice(SNAPSHOT_MAGIC);
+    buf.push(SNAPSHOT_VERSION);
+    buf.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+
+    for entry in entries {
+        let expires_at_ms = match entry.ttl {
+            // Clamp to 1 so a zero-length TTL is not confused with "no expiry".
+            Some(ttl) => now_ms.saturating_add(ttl.as_millis() as u64).max(1),
+            None => 0,
+        };
+        buf.extend_from_slice(&expires_at_ms.to_le_bytes());
+        buf.extend_from_slice(&(entry.key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&entry.key);
+        buf.extend_from_slice(&(entry.value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&entry.value);
+    }
+
+    let checksum = fnv1a(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// Parses a snapshot, dropping entries whose deadline has passed at `now`.
+///
+/// Returns `VersionMismatch` for unknown versions and `ProtocolViolation` for
+/// any malformed or corrupted input.
+pub fn decode(bytes: &[u8], now: SystemTime) -> HkvResult<Vec<SnapshotEntry>> {
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+        return Err(HkvError::ProtocolViolation);
+    }
+    if bytes[SNAPSHOT_MAGIC.len()] != SNAPSHOT_VERSION {
+        return Err(HkvError::VersionMismatch);
+    }
+
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if fnv1a(body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(HkvError::ProtocolViolation);
+    }
+
+    let now_ms = unix_millis(now);
+    let mut reader = Reader {
+        bytes: body,
+        pos: SNAPSHOT_MAGIC.len() + 1,
+    };
+    let count = reader.u64()?;
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        let expires_at_ms = reader.u64()?;
+        let key_len = reader.u32()? as usize;
+        let key = reader.take(key_len)?;
+        let value_len = reader.u32()? as usize;
+        let value = reader.take(value_len)?;
+
+        let ttl = match expires_at_ms {
+            0 => None,
+            deadline if deadline <= now_ms => continue,
+            deadline => Some(Duration::from_millis(deadline - now_ms)),
+        };
+        entries.push(SnapshotEntry {
+            key: Arc::from(key),
+            value: Arc::from(value),
+            ttl,
+        });
+    }
+
+    if reader.pos != body.len() {
+        return Err(HkvError::ProtocolViolation);
+    }
+    Ok(entries)
+}
+
+/// Bounds-checked cursor over the snapshot body.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> HkvResult<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(HkvError::ProtocolViolation)?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> HkvResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> HkvResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &[u8], value: &[u8], ttl: Option<Duration>) -> SnapshotEntry {
+        SnapshotEntry {
+            key: Arc::from(key),
+            value: Arc::from(value),
+            ttl,
+        }
+    }
+
+    #[test]
+    fn roundtrip_preserves_entries_and_ttls() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let entries = vec![
+            entry(b"a", b"1", None),
+            entry(b"", b"", None),
+            entry(b"b", b"2", Some(Duration::from_secs(30))),
+        ];
+
+        let decoded = decode(&encode(&entries, now), now).unwrap();
+
+        assert_eq!(decoded, entries);
+    }
+
+    #[test]
+    fn decode_drops_entries_expired_while_on_disk() {
+        let saved_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let entries = vec![
+            entry(b"short", b"1", Some(Duration::from_secs(1))),
+            entry(b"long", b"2", Some(Duration::from_secs(10))),
+        ];
+
+        let decoded = decode(
+            &encode(&entries, saved_at),
+            saved_at + Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(
+            decoded,
+            vec![entry(b"long", b"2", Some(Duration::from_secs(5)))]
+        );
+    }
+
+    #[test]
+    fn decode_rejects_corruption_and_unknown_versions() {
+        let now = SystemTime::now();
+        let mut bytes = encode(&[entry(b"k", b"v", None)], now);
+
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1], now),
+            Err(HkvError::ProtocolViolation)
+        );
+
+        let last = bytes.len() - CHECKSUM_LEN - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(decode(&bytes, now), Err(HkvError::ProtocolViolation));
+
+        let mut versioned = encode(&[], now);
+        versioned[SNAPSHOT_MAGIC.len()] = SNAPSHOT_VERSION + 1;
+        assert_eq!(decode(&versioned, now), Err(HkvError::VersionMismatch));
+    }
+}
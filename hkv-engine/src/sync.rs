@@ -0,0 +1,41 @@
+//! # Engine Synchronization Primitives
+//!
+//! The shard lock and atomics `MemoryEngine` shares between callers. The
+//! `loom` feature swaps them for loom's model-checked versions so
+//! `tests/loom_engine.rs` can explore every interleaving of concurrent calls.
+//!
+//! ## Design Principles
+//!
+//! 1. **One Import Site**: Engine code names these types only through this
+//!    module, so both builds run the same engine code.
+//! 2. **parking_lot Shape**: `RwLock::read`/`write` return guards directly,
+//!    as parking_lot does; loom's lock cannot be poisoned by a model that
+//!    runs to completion, so its results are unwrapped.
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use parking_lot::RwLock;
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::sync::atomic::{AtomicU64, AtomicUsize};
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::sync::atomic::{AtomicU64, AtomicUsize};
+
+/// loom's `RwLock` with parking_lot's guard-returning API.
+#[cfg(feature = "loom")]
+#[derive(Debug)]
+pub(crate) struct RwLock<T>(loom::sync::RwLock<T>);
+
+#[cfg(feature = "loom")]
+impl<T> RwLock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        RwLock(loom::sync::RwLock::new(value))
+    }
+
+    pub(crate) fn read(&self) -> loom::sync::RwLockReadGuard<'_, T> {
+        self.0.read().unwrap()
+    }
+
+    pub(crate) fn write(&self) -> loom::sync::RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap()
+    }
+}
@@ -0,0 +1,159 @@
+//! Loom model checks for `MemoryEngine` under concurrent callers.
+//!
+//! Run with:
+//!
+//! ```bash
+//! cargo test -p hkv-engine --features loom --test loom_engine --release
+//! ```
+//!
+//! Every test runs two threads on overlapping keys inside `loom::model`,
+//! which replays them under every interleaving of the shard lock and engine
+//! atomics. TTLs are an hour long so wall-clock expiry never interferes.
+
+#![cfg(feature = "loom")]
+
+use std::sync::Arc as StdArc;
+use std::time::Duration;
+
+use hkv_common::TtlAfter;
+use hkv_engine::{KVEngine, MemoryEngine, TtlStatus};
+use loom::sync::Arc;
+use loom::thread;
+
+const KEY: &[u8] = b"k";
+const TTL: TtlAfter = TtlAfter::from_secs(3600);
+
+fn value(engine: &MemoryEngine, key: &[u8]) -> Option<StdArc<[u8]>> {
+    engine.get(key).unwrap()
+}
+
+fn has_expiry(status: TtlStatus) -> bool {
+    match status {
+        TtlStatus::ExpiresIn(remaining) => remaining > Duration::ZERO,
+        TtlStatus::NoExpiry => false,
+        TtlStatus::Missing => panic!("key vanished"),
+    }
+}
+
+#[test]
+fn concurrent_sets_keep_the_last_write() {
+    loom::model(|| {
+        let engine = Arc::new(MemoryEngine::with_shard_count(1));
+
+        let writer = |own: &'static [u8]| {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || {
+                engine.set(KEY.to_vec(), own.to_vec()).unwrap();
+                value(&engine, KEY).expect("own write vanished")
+            })
+        };
+        let a = writer(b"a");
+        let b = writer(b"b");
+        let a_saw = a.join().unwrap();
+        let b_saw = b.join().unwrap();
+
+        // A thread that read the other's value was overwritten after its own
+        // set, so that value must be the one left; both cannot have been.
+        assert!(!(&*a_saw == b"b" && &*b_saw == b"a"), "lost update");
+        let last = value(&engine, KEY).unwrap();
+        if &*a_saw == b"b" {
+            assert_eq!(&*last, b"b");
+        }
+        if &*b_saw == b"a" {
+            assert_eq!(&*last, b"a");
+        }
+
+        let stats = engine.stats();
+        assert_eq!(stats.keys, 1);
+        assert_eq!(stats.used_bytes, KEY.len() + 1);
+    });
+}
+
+#[test]
+fn ttl_follows_the_last_set() {
+    loom::model(|| {
+        let engine = Arc::new(MemoryEngine::with_shard_count(1));
+
+        let with_ttl = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || {
+                engine
+                    .set_with_ttl(KEY.to_vec(), b"ttl".to_vec(), TTL)
+                    .unwrap()
+            })
+        };
+        let plain = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || engine.set(KEY.to_vec(), b"plain".to_vec()).unwrap())
+        };
+        with_ttl.join().unwrap();
+        plain.join().unwrap();
+
+        // The value and its expiry come from the same write.
+        let last = value(&engine, KEY).unwrap();
+        let expiring = has_expiry(engine.ttl(KEY).unwrap());
+        assert_eq!(expiring, &*last == b"ttl");
+    });
+}
+
+#[test]
+fn expire_and_set_agree_on_the_final_ttl() {
+    loom::model(|| {
+        let engine = Arc::new(MemoryEngine::with_shard_count(1));
+        engine.set(KEY.to_vec(), b"old".to_vec()).unwrap();
+
+        let expirer = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || engine.expire(KEY, TTL).unwrap())
+        };
+        let setter = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || {
+                engine.set(KEY.to_vec(), b"new".to_vec()).unwrap();
+                has_expiry(engine.ttl(KEY).unwrap())
+            })
+        };
+        expirer.join().unwrap();
+        let setter_saw_expiry = setter.join().unwrap();
+
+        // `set` clears the TTL, so an expiry the setter saw after its own
+        // write came from a later `expire` and must still be there.
+        assert_eq!(&*value(&engine, KEY).unwrap(), b"new");
+        if setter_saw_expiry {
+            assert!(has_expiry(engine.ttl(KEY).unwrap()));
+        }
+    });
+}
+
+#[test]
+fn byte_accounting_survives_concurrent_delete_and_eviction() {
+    loom::model(|| {
+        // Room for two 2-byte entries; a third forces an eviction.
+        let engine = Arc::new(MemoryEngine::with_shard_count_and_capacity(1, 4));
+        engine.set(b"a".to_vec(), b"1".to_vec()).unwrap();
+
+        let deleter = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || engine.delete(b"a").unwrap())
+        };
+        let writer = {
+            let engine = Arc::clone(&engine);
+            thread::spawn(move || {
+                engine.set(b"b".to_vec(), b"2".to_vec()).unwrap();
+                engine.set(b"c".to_vec(), b"3".to_vec()).unwrap();
+            })
+        };
+        deleter.join().unwrap();
+        writer.join().unwrap();
+
+        let live = [b"a", b"b", b"c"]
+            .iter()
+            .filter(|key| value(&engine, &key[..]).is_some())
+            .count();
+        let stats = engine.stats();
+        assert_eq!(stats.keys, live);
+        assert_eq!(stats.used_bytes, live * 2);
+        assert!(stats.used_bytes <= 4);
+        assert!(value(&engine, b"c").is_some(), "newest entry evicted");
+    });
+}
@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # debugfs Inspection
+//!
+//! `/sys/kernel/debug/hybridkv/` for kernel developers: `dump` lists every
+//! stored entry (`cat .../dump`) and writing anything to `clear` flushes the
+//! cache (`echo 1 > .../clear`).
+//!
+//! ## Design Principles
+//!
+//! 1. **Snapshot on Open**: `open` formats the whole cache into a buffer
+//!    owned by the open file, so `cat` reading in chunks through
+//!    `simple_read_from_buffer` sees one consistent snapshot.
+//! 2. **No Allocation Under the Lock**: The dump is formatted twice under the
+//!    read lock: once to measure it, then into a buffer allocated (and able to
+//!    sleep) in between. Entries promoted between the passes may be cut off.
+//! 3. **Best Effort**: debugfs failures are not errors; the directory is
+//!    optional and `debugfs_remove_recursive` tolerates an error pointer.
+
+use core::ffi::{c_char, c_int};
+use core::fmt::{self, Write};
+use core::ptr;
+
+use kernel::bindings;
+use kernel::c_str;
+use kernel::prelude::*;
+
+use crate::{CACHE, flush_in_steps};
+
+/// A `file_operations` table that can live in a `static`.
+struct FileOps(bindings::file_operations);
+
+// SAFETY: the tables are immutable and only hold function pointers.
+unsafe impl Sync for FileOps {}
+
+static DUMP_FOPS: FileOps = FileOps(bindings::file_operations {
+    open: Some(dump_open),
+    read: Some(dump_read),
+    release: Some(dump_release),
+    llseek: Some(bindings::default_llseek),
+    // SAFETY: a zeroed `file_operations` has every other callback unset.
+    ..unsafe { core::mem::zeroed() }
+});
+
+static CLEAR_FOPS: FileOps = FileOps(bindings::file_operations {
+    write: Some(clear_write),
+    llseek: Some(bindings::noop_llseek),
+    // SAFETY: as above.
+    ..unsafe { core::mem::zeroed() }
+});
+
+/// `/sys/kernel/debug/hybridkv` and its files.
+pub(crate) struct DebugFsDir {
+    dir: *mut bindings::dentry,
+}
+
+// SAFETY: the pointer is only passed to `debugfs_remove_recursive`, which may
+// be called from any thread.
+unsafe impl Send for DebugFsDir {}
+// SAFETY: no methods access the pointer through `&self`.
+unsafe impl Sync for DebugFsDir {}
+
+impl DebugFsDir {
+    /// Creates `dump` (mode 0444) and `clear` (mode 0200).
+    pub(crate) fn create() -> Self {
+        // SAFETY: the names are valid C strings, a null parent means the
+        // debugfs root, and the fops tables are `'static`.
+        unsafe {
+            let dir =
+                bindings::debugfs_create_dir(c_str!("hybridkv").as_char_ptr(), ptr::null_mut());
+            bindings::debugfs_create_file(
+                c_str!("dump").as_char_ptr(),
+                0o444,
+                dir,
+                ptr::null_mut(),
+                &DUMP_FOPS.0,
+            );
+            bindings::debugfs_create_file(
+                c_str!("clear").as_char_ptr(),
+                0o200,
+                dir,
+                ptr::null_mut(),
+                &CLEAR_FOPS.0,
+            );
+            DebugFsDir { dir }
+        }
+    }
+}
+
+impl Drop for DebugFsDir {
+    fn drop(&mut self) {
+        // SAFETY: `dir` came from `debugfs_create_dir` (possibly an error
+        // pointer, which is ignored) and is removed exactly once; removal
+        // waits for callbacks still running.
+        unsafe { bindings::debugfs_remove_recursive(self.dir) };
+    }
+}
+
+/// Counts formatted bytes without storing them.
+struct Measure(usize);
+
+impl Write for Measure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Appends to a pre-sized buffer, dropping whatever does not fit.
+struct Fill<'a>(&'a mut KVVec<u8>);
+
+impl Write for Fill<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.0.capacity() - self.0.len();
+        let take = s.len().min(room);
+        // Within capacity, so this never allocates.
+        self.0
+            .extend_from_slice(&s.as_bytes()[..take], GFP_ATOMIC)
+            .map_err(|_| fmt::Error)
+    }
+}
+
+/// Formats the cache into a new buffer.
+fn snapshot() -> Result<KVVec<u8>> {
+    let mut measure = Measure(0);
+    // `Measure` never fails, so neither does formatting into it.
+    let _ = CACHE.read().dump(&mut measure);
+
+    let mut buf = KVVec::with_capacity(measure.0, GFP_KERNEL)?;
+    // Stops quietly at capacity if entries were added since measuring.
+    let _ = CACHE.read().dump(&mut Fill(&mut buf));
+    Ok(buf)
+}
+
+unsafe extern "C" fn dump_open(_inode: *mut bindings::inode, file: *mut bindings::file) -> c_int {
+    let buf = match snapshot().and_then(|buf| Ok(KBox::new(buf, GFP_KERNEL)?)) {
+        Ok(buf) => buf,
+        Err(err) => return err.to_errno(),
+    };
+    // SAFETY: `file` is the file being opened; `dump_release` frees the box.
+    unsafe { (*file).private_data = KBox::into_raw(buf).cast() };
+    0
+}
+
+unsafe extern "C" fn dump_read(
+    file: *mut bindings::file,
+    to: *mut c_char,
+    count: usize,
+    ppos: *mut bindings::loff_t,
+) -> isize {
+    // SAFETY: `dump_open` stored the snapshot, which lives until release.
+    let buf = unsafe { &*(*file).private_data.cast::<KVVec<u8>>() };
+    // SAFETY: `to`/`count` describe the user buffer and `ppos` the file
+    // position, exactly as passed to this `read` callback.
+    unsafe {
+        bindings::simple_read_from_buffer(to.cast(), count, ppos, buf.as_ptr().cast(), buf.len())
+    }
+}
+
+unsafe extern "C" fn dump_release(
+    _inode: *mut bindings::inode,
+    file: *mut bindings::file,
+) -> c_int {
+    // SAFETY: `private_data` holds the box leaked by `dump_open`, freed once.
+    drop(unsafe { KBox::from_raw((*file).private_data.cast::<KVVec<u8>>()) });
+    0
+}
+
+unsafe extern "C" fn clear_write(
+    _file: *mut bindings::file,
+    _from: *const c_char,
+    count: usize,
+    _ppos: *mut bindings::loff_t,
+) -> isize {
+    if count > 0 {
+        // Waits for the lock, so every step succeeds.
+        let flushed = flush_in_steps(0).unwrap_or_else(|removed| removed);
+        pr_info!("hybridkv: debugfs clear flushed {} entries\n", flushed);
+    }
+    count as isize
+}
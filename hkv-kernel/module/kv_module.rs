@@ -0,0 +1,555 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # HybridKV Kernel Module
+//!
+//! Rust-for-Linux glue exposing the kernel hot-key cache as `/dev/hybridkv`.
+//!
+//! ## Build
+//!
+//! Requires a kernel built with `CONFIG_RUST=y` (6.13+ for the misc device
+//! bindings, 6.17+ for Rust module parameters). From this directory:
+//!
+//! ```text
+//! make KDIR=/lib/modules/$(uname -r)/build LLVM=1
+//! sudo insmod kv_module.ko
+//! ```
+//!
+//! Then promote a key through the device and read it back with:
+//!
+//! ```text
+//! cargo run -p hkv-kernel --features smoke --bin hkv-kernel-smoke
+//! ```
+//!
+//! ## Parameters
+//!
+//! All sizes are plain byte counts. A value outside its range fails the
+//! load with `EINVAL` and a `hybridkv:` line in the kernel log naming the
+//! parameter (see `params`); nothing is clamped.
+//!
+//! - `kv_max_entries` (default 4093, 1..=1048576): entry limit. The hash
+//!   table gets as many buckets (at least 16), allocated once at load;
+//!   entries themselves come from the `hybridkv_entry` slab (see `slab`) as
+//!   keys are promoted, e.g. `insmod kv_module.ko kv_max_entries=1024`.
+//!   Adjustable later, up to the bucket count, through
+//!   `/sys/kernel/hybridkv/max_entries`.
+//! - `kv_max_bytes` (default 0): memory budget for entries with their keys
+//!   and values, reported as `max_bytes` in STATS. Promotes beyond it evict
+//!   the least recently used entries; 0 leaves only the entry limit. When
+//!   set it must hold one entry of the largest key and value and at most a
+//!   full table of maximum-size entries, the range
+//!   `/sys/kernel/hybridkv/max_bytes` accepts later.
+//! - `kv_max_key_size` (default 256, 1..=256) and `kv_max_value_size`
+//!   (default 1024, 1..=1024): largest key and value PROMOTE accepts; larger
+//!   ones fail with `ERR_KEY_TOO_LONG` or `ERR_VALUE_TOO_LONG`. HELLO
+//!   reports these limits, so user space skips promoting what would not
+//!   fit.
+//!
+//! Read the values in effect back from
+//! `/sys/module/kv_module/parameters/`.
+//! - `kv_selftest` (default 0): when non-zero, promote, replace and read
+//!   back small and maximum-size values on a scratch cache before the device
+//!   is registered, and refuse to load if any check fails (see `selftest`).
+//!
+//! ## Concurrency Invariants
+//!
+//! These hold for every change to the cache or its callers:
+//!
+//! 1. **No Sleeping Lock on READ**: READ takes `CACHE` shared with
+//!    `read_lock_irqsave` (a spinning `rwlock_t`, see `rwlock`), never a
+//!    mutex, so lookups on different CPUs can hold it at the same time.
+//!    They still contend: every READ atomically updates the one lock word,
+//!    so concurrent readers bounce its cache line, and all of them spin
+//!    while a writer holds or waits for the lock. There is no RCU or
+//!    seqlock read path. Beyond the lock, the only writes a READ makes are
+//!    the per-CPU hit/miss counters and the entry's `referenced` bit, which
+//!    is only stored when it changes.
+//! 2. **One Writer**: PROMOTE, DEMOTE, INVALIDATE, FLUSH, the expiry sweep,
+//!    the shrinker and debugfs `clear` take `CACHE` exclusively. Only they
+//!    unlink or free entries, so an entry a reader found stays valid until
+//!    it drops the shared lock, and an INVALIDATE racing a READ of the same
+//!    key either happens before the copy or after it, never during.
+//! 3. **Copy Out, Then Unlock, Then Copy to User**: READ copies the value
+//!    into a stack `RawValue` under the shared lock and writes it to user
+//!    space after releasing it, so a concurrent replace can neither tear
+//!    the copy nor be held up by a page fault.
+//! 4. **Nothing Sleeps Under the Lock**: Keys and values are allocated with
+//!    `GFP_KERNEL` before the exclusive lock; only the fixed-size entry comes
+//!    from the slab under it, with `GFP_ATOMIC`. Frees never sleep.
+//! 5. **Bounded Writer Sections**: The expiry sweep and FLUSH release the
+//!    lock every `SWEEP_BATCH` or `FLUSH_BATCH` buckets, so a writer never
+//!    stalls readers for a whole table walk.
+//!
+//! `hkv-kernel-bench` measures READ throughput for 1, 2, 4 and 8 reader
+//! threads. Because of the shared lock word, the gain per added thread may
+//! flatten before the number of CPUs is reached. Run it against a build
+//! before and after any change to the locking, and compare the numbers:
+//!
+//! ```text
+//! cargo run --release -p hkv-kernel --features bench --bin hkv-kernel-bench
+//! ```
+//!
+//! ## Design Principles
+//!
+//! 1. **Misc Device**: `MiscDeviceRegistration` calls `misc_register`, so the
+//!    minor number is allocated dynamically and udev creates the node.
+//! 2. **Shared Core**: ABI definitions and ioctl decoding come from
+//!    `../src` so the logic is unit-tested on the host.
+//! 3. **No Locks Across Copies**: The cache lock is taken inside each cache
+//!    operation, never while copying to or from user space.
+//! 4. **Timed Expiry**: The `kv_expiry` kthread sweeps expired entries once
+//!    a second in bounded steps (see `expiry`); reads also treat due entries
+//!    as misses in between sweeps.
+//! 5. **Observable**: Counters are readable from `/proc/hybridkv/stats` (see
+//!    `procfs`) as well as through the STATS ioctl. Hot counters are per-CPU
+//!    (see `percpu`) and summed when read.
+//! 6. **Concurrent Readers**: The cache sits behind an interrupt-safe
+//!    `rwlock_t` (see `rwlock`): READ and STATS share it; PROMOTE, DEMOTE,
+//!    INVALIDATE, FLUSH and the expiry thread take it exclusively. Requests flagged
+//!    `FLAG_NOWAIT` only try the lock and report `ERR_BUSY` when it is held.
+//! 7. **Memory Pressure**: A shrinker named `hybridkv` (see `shrinker`)
+//!    evicts the least recently used entries when reclaim asks for memory
+//!    back, the same order promotes use once `kv_max_bytes` or the table is
+//!    exhausted. READ only sets an entry's atomic `referenced` bit under the
+//!    shared lock; the recency list itself is reordered by writers.
+//! 8. **Debuggable**: `/sys/kernel/debug/hybridkv/dump` lists every entry
+//!    and writing to `clear` flushes the cache like FLUSH (see `debugfs`).
+//! 9. **Batched Transport**: A `NETLINK_HYBRIDKV` socket accepts the same
+//!    request structs as the ioctls, many per `sendmsg` (see `netlink`).
+//! 10. **Live Tunables**: `/sys/kernel/hybridkv/` exposes the memory budget,
+//!     entry limit and TTL override for writing and the counters for
+//!     reading (see `sysfs`), so limits change without a reload.
+
+use core::fmt;
+
+use kernel::ioctl::_IOC_SIZE;
+use kernel::miscdevice::{MiscDevice, MiscDeviceOptions, MiscDeviceRegistration};
+use kernel::prelude::*;
+use kernel::uaccess::{UserPtr, UserSlice};
+use kernel::{c_str, fs::File};
+
+#[path = "../src/abi.rs"]
+mod abi;
+#[path = "../src/cache.rs"]
+mod cache;
+mod debugfs;
+#[path = "../src/dispatch.rs"]
+mod dispatch;
+mod expiry;
+mod netlink;
+#[path = "../src/params.rs"]
+mod params;
+mod percpu;
+mod procfs;
+mod rwlock;
+mod selftest;
+mod shrinker;
+mod slab;
+mod sysfs;
+#[path = "../src/table.rs"]
+mod table;
+
+use abi::{
+    CacheStats, ERR_BUSY, ERR_CAPACITY_EXCEEDED, ERR_NOT_FOUND, ERR_OUT_OF_MEMORY,
+    FLAG_NO_PROMOTE_STATS, FLAG_NOWAIT, FlushResponse, InvalidateResponse, Limits, MAX_KEY_SIZE,
+    MAX_VALUE_SIZE, MODULE_LIMITS, OUTCOME_REJECTED_FULL, PromoteResponse, RawValue, STATUS_OK,
+};
+use cache::{KvCache, Tunable};
+use debugfs::DebugFsDir;
+use dispatch::{CacheOps, UserArg};
+use expiry::ExpirySweep;
+use netlink::NetlinkSocket;
+use params::LoadParams;
+use percpu::PerCpuCounters;
+use procfs::ProcStatsFile;
+use rwlock::{IrqRwLock, ReadGuard, WriteGuard};
+use shrinker::CacheShrinker;
+use slab::{EntrySlab, SlabEntry};
+use sysfs::SysfsDir;
+use table::{Bucket, DEFAULT_CAPACITY};
+
+module! {
+    type: HybridKvModule,
+    name: "kv_module",
+    authors: ["Chiicake"],
+    description: "HybridKV in-kernel hot key cache",
+    license: "GPL",
+    params: {
+        kv_max_entries: u32 {
+            default: 4093,
+            description: "Maximum cache entries, 1..=1048576",
+        },
+        kv_max_bytes: u64 {
+            default: 0,
+            description: "Memory budget in bytes for cached entries; 0 means the entry limit is the only limit",
+        },
+        kv_max_key_size: u32 {
+            default: 256,
+            description: "Largest key in bytes PROMOTE accepts, 1..=256",
+        },
+        kv_max_value_size: u32 {
+            default: 1024,
+            description: "Largest value in bytes PROMOTE accepts, 1..=1024",
+        },
+        kv_selftest: u32 {
+            default: 0,
+            description: "Run the cache self-test at load when non-zero",
+        },
+    },
+}
+
+// Parameter defaults must be literals; keep them in sync with the core.
+const _: () = assert!(DEFAULT_CAPACITY == 4093);
+const _: () = assert!(MAX_KEY_SIZE == 256 && MAX_VALUE_SIZE == 1024);
+
+/// Global cache; filled in `HybridKvModule::init` before the device is
+/// registered.
+static CACHE: IrqRwLock<CacheState> = IrqRwLock::new(CacheState::new());
+
+/// Buckets FLUSH empties per write-lock hold.
+const FLUSH_BATCH: usize = 1024;
+
+/// Empties the cache `FLUSH_BATCH` buckets per write-lock hold, returning
+/// how many entries were removed.
+///
+/// With `FLAG_NOWAIT` a step that finds the lock held stops the flush and
+/// reports `Err` with the count so far. Entries promoted into buckets
+/// already passed survive; FLUSH is not atomic with respect to PROMOTE.
+/// Must run in process context with no locks held.
+fn flush_in_steps(flags: u8) -> core::result::Result<u64, u64> {
+    let capacity = GlobalCache::shared(flags).ok_or(0u64)?.capacity();
+    let mut removed = 0;
+    for start in (0..capacity).step_by(FLUSH_BATCH) {
+        let Some(mut cache) = GlobalCache::exclusive(flags) else {
+            return Err(removed);
+        };
+        removed += cache.flush_range(start, FLUSH_BATCH) as u64;
+        drop(cache);
+        // SAFETY: process context with no locks held.
+        unsafe { kernel::bindings::__cond_resched() };
+    }
+    Ok(removed)
+}
+
+#[pin_data]
+struct HybridKvModule {
+    #[pin]
+    _miscdev: MiscDeviceRegistration<HybridKvDevice>,
+    _expiry: ExpirySweep,
+    _proc: ProcStatsFile,
+    _shrinker: CacheShrinker,
+    _debugfs: DebugFsDir,
+    _netlink: NetlinkSocket,
+    _sysfs: SysfsDir,
+    // Declared last so it drops after the device, expiry thread, proc file,
+    // shrinker, debugfs files, netlink socket and sysfs directory are gone;
+    // initialized first (below) so the cache exists before them.
+    _cache: CacheRelease,
+}
+
+impl kernel::InPlaceModule for HybridKvModule {
+    fn init(_module: &'static ThisModule) -> impl PinInit<Self, Error> {
+        try_pin_init!(Self {
+            _cache: {
+                if *module_parameters::kv_selftest.value() != 0 {
+                    selftest::run()?;
+                }
+                CACHE.init();
+                let params = LoadParams {
+                    max_entries: *module_parameters::kv_max_entries.value(),
+                    max_bytes: *module_parameters::kv_max_bytes.value(),
+                    max_key_size: *module_parameters::kv_max_key_size.value(),
+                    max_value_size: *module_parameters::kv_max_value_size.value(),
+                };
+                let cache = CacheState::allocate(&params)?;
+                pr_info!("hybridkv: allocated {} buckets\n", cache.capacity());
+                CACHE.write().cache = Some(cache);
+                CacheRelease
+            },
+            _miscdev <- {
+                pr_info!("hybridkv: registering /dev/hybridkv\n");
+                MiscDeviceRegistration::register(MiscDeviceOptions {
+                    name: c_str!("hybridkv"),
+                })
+            },
+            _expiry: ExpirySweep::start()?,
+            _proc: ProcStatsFile::create()?,
+            _shrinker: CacheShrinker::register()?,
+            _debugfs: DebugFsDir::create(),
+            _netlink: NetlinkSocket::create()?,
+            _sysfs: SysfsDir::create()?,
+        })
+    }
+}
+
+/// Per-open file state; all cache state is global.
+struct HybridKvDevice;
+
+#[vtable]
+impl MiscDevice for HybridKvDevice {
+    type Ptr = KBox<Self>;
+
+    fn open(_file: &File, _misc: &MiscDeviceRegistration<Self>) -> Result<KBox<Self>> {
+        Ok(KBox::new(HybridKvDevice, GFP_KERNEL)?)
+    }
+
+    fn release(device: KBox<Self>, _file: &File) {
+        drop(device);
+    }
+
+    fn ioctl(_device: &HybridKvDevice, _file: &File, cmd: u32, arg: usize) -> Result<isize> {
+        let mut user = KernelUserArg {
+            ptr: UserPtr::from_addr(arg),
+            len: _IOC_SIZE(cmd),
+        };
+
+        match dispatch::dispatch(&GlobalCache, cmd, &mut user) {
+            Ok(()) => Ok(0),
+            Err(errno) => Err(Error::from_errno(-errno)),
+        }
+    }
+}
+
+/// `UserArg` backed by `copy_from_user`/`copy_to_user` via `UserSlice`.
+struct KernelUserArg {
+    ptr: UserPtr,
+    len: usize,
+}
+
+impl UserArg for KernelUserArg {
+    fn read_into(&mut self, dst: &mut [u8]) -> core::result::Result<(), i32> {
+        UserSlice::new(self.ptr, self.len)
+            .reader()
+            .read_slice(dst)
+            .map_err(|err| -err.to_errno())
+    }
+
+    fn write_from(&mut self, src: &[u8]) -> core::result::Result<(), i32> {
+        UserSlice::new(self.ptr, self.len)
+            .writer()
+            .write_slice(src)
+            .map_err(|err| -err.to_errno())
+    }
+}
+
+/// Locks the global store for the duration of a single cache operation.
+struct GlobalCache;
+
+impl GlobalCache {
+    /// Shared lock, or `None` if `FLAG_NOWAIT` is set and a writer holds it.
+    fn shared(flags: u8) -> Option<ReadGuard<'static, CacheState>> {
+        match flags & FLAG_NOWAIT {
+            0 => Some(CACHE.read()),
+            _ => CACHE.try_read(),
+        }
+    }
+
+    /// Exclusive lock, or `None` if `FLAG_NOWAIT` is set and it is held.
+    fn exclusive(flags: u8) -> Option<WriteGuard<'static, CacheState>> {
+        match flags & FLAG_NOWAIT {
+            0 => Some(CACHE.write()),
+            _ => CACHE.try_write(),
+        }
+    }
+}
+
+impl CacheOps for GlobalCache {
+    fn read(&self, key: &[u8], flags: u8, out: &mut RawValue) -> u16 {
+        Self::shared(flags).map_or(ERR_BUSY, |cache| cache.read(key, flags, out))
+    }
+
+    fn promote(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        version: u64,
+        ttl: u64,
+        flags: u8,
+        out: &mut PromoteResponse,
+    ) -> u16 {
+        // Allocate while sleeping is still allowed; the cache lock spins.
+        let (Some(key), Some(value)) = (copy_bytes(key), copy_bytes(value)) else {
+            out.outcome = OUTCOME_REJECTED_FULL;
+            return ERR_OUT_OF_MEMORY;
+        };
+        Self::exclusive(flags).map_or(ERR_BUSY, |mut cache| {
+            cache.promote(key, value, version, ttl, out)
+        })
+    }
+
+    fn demote(&self, key: &[u8], flags: u8) -> u16 {
+        Self::exclusive(flags).map_or(ERR_BUSY, |mut cache| cache.demote(key))
+    }
+
+    fn invalidate(&self, key: &[u8], version: u64, flags: u8, out: &mut InvalidateResponse) -> u16 {
+        Self::exclusive(flags).map_or(ERR_BUSY, |mut cache| {
+            out.removed = cache.invalidate(key, version) as u16;
+            STATUS_OK
+        })
+    }
+
+    fn flush(&self, flags: u8, out: &mut FlushResponse) -> u16 {
+        match flush_in_steps(flags) {
+            Ok(removed) => {
+                out.removed = removed;
+                STATUS_OK
+            }
+            Err(removed) => {
+                out.removed = removed;
+                ERR_BUSY
+            }
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        CACHE.read().stats()
+    }
+
+    fn limits(&self) -> Limits {
+        CACHE.read().limits()
+    }
+}
+
+/// Copies a key or value into a buffer of exactly its length, or returns
+/// `None` when memory is exhausted. May sleep, so call it before taking the
+/// cache lock.
+fn copy_bytes(bytes: &[u8]) -> Option<KVec<u8>> {
+    let mut buf = KVec::with_capacity(bytes.len(), GFP_KERNEL).ok()?;
+    buf.extend_from_slice(bytes, GFP_KERNEL).ok()?;
+    Some(buf)
+}
+
+/// Frees every entry and destroys the slab when the module unloads.
+struct CacheRelease;
+
+impl Drop for CacheRelease {
+    fn drop(&mut self) {
+        let cache = CACHE.write().cache.take();
+        // `kmem_cache_destroy` may sleep, so drop outside the spinning lock.
+        drop(cache);
+    }
+}
+
+type Cache = KvCache<KVVec<Bucket<SlabEntry>>, EntrySlab, PerCpuCounters>;
+
+/// Global cache state; buckets are allocated once in module init, entries
+/// on promote.
+struct CacheState {
+    cache: Option<Cache>,
+}
+
+impl CacheState {
+    const fn new() -> Self {
+        CacheState { cache: None }
+    }
+
+    /// Validates `params`, then creates the entry slab, the per-CPU
+    /// counters and the buckets (`kvmalloc`, falling back to `vmalloc` for
+    /// large tables) with the requested limits applied.
+    ///
+    /// Runs before the lock is taken, since both allocations may sleep.
+    fn allocate(params: &LoadParams) -> Result<Cache> {
+        let capacity = params.capacity().map_err(invalid_param)?;
+        // Fail on bad sizes before allocating anything.
+        params.limits().map_err(invalid_param)?;
+        let slab = EntrySlab::create(c_str!("hybridkv_entry"))?;
+        let mut buckets = KVVec::with_capacity(capacity, GFP_KERNEL)?;
+        for _ in 0..capacity {
+            buckets.push(Bucket::Empty, GFP_KERNEL)?;
+        }
+        let mut cache = KvCache::new(buckets, slab, PerCpuCounters::new()?);
+        params.apply(&mut cache).map_err(invalid_param)?;
+        Ok(cache)
+    }
+
+    fn read(&self, key: &[u8], flags: u8, out: &mut RawValue) -> u16 {
+        match self.cache.as_ref() {
+            Some(cache) if flags & FLAG_NO_PROMOTE_STATS != 0 => {
+                cache.peek(key, expiry::now_ns(), out)
+            }
+            Some(cache) => cache.read(key, expiry::now_ns(), out),
+            None => ERR_NOT_FOUND,
+        }
+    }
+
+    fn promote(
+        &mut self,
+        key: KVec<u8>,
+        value: KVec<u8>,
+        version: u64,
+        ttl: u64,
+        out: &mut PromoteResponse,
+    ) -> u16 {
+        match self.cache.as_mut() {
+            Some(cache) => cache.promote(key, value, version, ttl, expiry::now_ns(), out),
+            None => ERR_CAPACITY_EXCEEDED,
+        }
+    }
+
+    fn demote(&mut self, key: &[u8]) -> u16 {
+        match self.cache.as_mut() {
+            Some(cache) => cache.demote(key),
+            None => STATUS_OK,
+        }
+    }
+
+    fn invalidate(&mut self, key: &[u8], version: u64) -> bool {
+        self.cache
+            .as_mut()
+            .is_some_and(|cache| cache.invalidate(key, version))
+    }
+
+    fn capacity(&self) -> usize {
+        self.cache.as_ref().map_or(0, |cache| cache.capacity())
+    }
+
+    fn evict_expired_range(&mut self, start: usize, count: usize, now_ns: i64) -> usize {
+        self.cache
+            .as_mut()
+            .map_or(0, |cache| cache.evict_expired_range(start, count, now_ns))
+    }
+
+    fn tunable(&self, tunable: Tunable) -> u64 {
+        self.cache
+            .as_ref()
+            .map_or(0, |cache| cache.tunable(tunable))
+    }
+
+    fn set_tunable(&mut self, tunable: Tunable, value: u64) -> core::result::Result<usize, u16> {
+        match self.cache.as_mut() {
+            Some(cache) => cache.set_tunable(tunable, value),
+            None => Err(ERR_NOT_FOUND),
+        }
+    }
+
+    fn shrink(&mut self, count: usize) -> usize {
+        self.cache.as_mut().map_or(0, |cache| cache.shrink(count))
+    }
+
+    fn flush_range(&mut self, start: usize, count: usize) -> usize {
+        self.cache
+            .as_mut()
+            .map_or(0, |cache| cache.flush_range(start, count))
+    }
+
+    fn dump(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        self.cache.as_ref().map_or(Ok(()), |cache| cache.dump(out))
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.cache
+            .as_ref()
+            .map_or_else(CacheStats::default, |cache| cache.stats())
+    }
+
+    fn limits(&self) -> Limits {
+        self.cache
+            .as_ref()
+            .map_or(MODULE_LIMITS, |cache| cache.limits())
+    }
+}
+
+/// Logs a rejected module parameter and fails the load with `EINVAL`.
+fn invalid_param(err: params::ParamError) -> Error {
+    pr_err!("hybridkv: {}\n", err);
+    EINVAL
+}
@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # Netlink Socket
+//!
+//! A `NETLINK_HYBRIDKV` socket family carrying the ioctl payloads, so user
+//! space can queue many requests with one `sendmsg` and collect the replies
+//! with `recvmsg` instead of making one ioctl per request.
+//!
+//! ## Design Principles
+//!
+//! 1. **Same Payloads**: A message body is an `IoctlHeader`-prefixed request
+//!    struct; `dispatch::dispatch_message` runs it through the ioctl
+//!    handlers, and the reply body is the response laid out as the ioctl
+//!    would leave it (`ioc_size` bytes, zero-padded).
+//! 2. **One Reply per Message**: `netlink_rcv_skb` walks every message in a
+//!    `sendmsg`, acking control types below `NLMSG_MIN_TYPE` itself. Each
+//!    request gets a reply that echoes its `nlmsg_type` and
+//!    `nlmsg_seq`, unicast to the sender's port. Cache failures travel in
+//!    the reply status; malformed messages get an `NLMSG_ERROR` ack instead.
+//! 3. **Process Context**: The input callback runs in the sender's
+//!    `sendmsg`, so it may sleep: the reply is built with `GFP_KERNEL` and
+//!    the cache lock is taken exactly as for an ioctl.
+
+use core::ffi::c_int;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use kernel::bindings;
+use kernel::prelude::*;
+
+use crate::GlobalCache;
+use crate::abi::NETLINK_HYBRIDKV;
+use crate::dispatch::{self, ioc_size};
+
+/// `NLMSG_HDRLEN`: the aligned `nlmsghdr` that precedes each payload.
+const NLMSG_HDRLEN: usize = nlmsg_align(size_of::<bindings::nlmsghdr>());
+
+/// `NUMA_NO_NODE`: allocate replies on any node.
+const NUMA_NO_NODE: c_int = -1;
+
+/// Kernel-side socket, read by the input callback to send replies.
+static SOCKET: AtomicPtr<bindings::sock> = AtomicPtr::new(ptr::null_mut());
+
+/// `NLMSG_ALIGN`.
+const fn nlmsg_align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Registration of the `NETLINK_HYBRIDKV` family.
+pub(crate) struct NetlinkSocket;
+
+impl NetlinkSocket {
+    /// Creates the kernel socket (`netlink_kernel_create`).
+    pub(crate) fn create() -> Result<Self> {
+        let mut cfg = bindings::netlink_kernel_cfg {
+            input: Some(kv_netlink_recv),
+            // SAFETY: a zeroed `netlink_kernel_cfg` leaves every other option
+            // at its default.
+            ..unsafe { core::mem::zeroed() }
+        };
+        // SAFETY: `init_net` outlives the module, `THIS_MODULE` pins the
+        // module while the socket exists, and `cfg` is only read during the
+        // call. `netlink_kernel_create` is an inline wrapper for this.
+        let sock = unsafe {
+            bindings::__netlink_kernel_create(
+                ptr::addr_of_mut!(bindings::init_net),
+                NETLINK_HYBRIDKV,
+                crate::THIS_MODULE.as_ptr(),
+                &mut cfg,
+            )
+        };
+        if sock.is_null() {
+            // Either the unit is already taken or allocation failed.
+            return Err(EBUSY);
+        }
+        SOCKET.store(sock, Ordering::Release);
+        Ok(NetlinkSocket)
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        let sock = SOCKET.swap(ptr::null_mut(), Ordering::AcqRel);
+        // SAFETY: `sock` came from `__netlink_kernel_create` and is released
+        // exactly once.
+        unsafe { bindings::netlink_kernel_release(sock) };
+    }
+}
+
+/// Input callback: handles every message in `skb`.
+unsafe extern "C" fn kv_netlink_recv(skb: *mut bindings::sk_buff) {
+    // SAFETY: `skb` is the buffer handed to the input callback.
+    unsafe { bindings::netlink_rcv_skb(skb, Some(kv_netlink_msg)) };
+}
+
+/// Handles one message; a negative return makes `netlink_rcv_skb` send an
+/// `NLMSG_ERROR` ack carrying that errno.
+unsafe extern "C" fn kv_netlink_msg(
+    skb: *mut bindings::sk_buff,
+    nlh: *mut bindings::nlmsghdr,
+    _extack: *mut bindings::netlink_ext_ack,
+) -> c_int {
+    // SAFETY: `netlink_rcv_skb` checked that `nlmsg_len` covers the header
+    // and lies within `skb`.
+    let (request, kind, seq) = unsafe {
+        let len = (*nlh).nlmsg_len as usize - NLMSG_HDRLEN;
+        let data = nlh.cast::<u8>().add(NLMSG_HDRLEN);
+        (
+            core::slice::from_raw_parts(data, len),
+            (*nlh).nlmsg_type,
+            (*nlh).nlmsg_seq,
+        )
+    };
+
+    let cmd = match dispatch::message_cmd(request) {
+        Ok(cmd) => cmd,
+        Err(errno) => return -errno,
+    };
+    let mut response = match KVVec::from_elem(0u8, ioc_size(cmd), GFP_KERNEL) {
+        Ok(response) => response,
+        Err(_) => return ENOMEM.to_errno(),
+    };
+    if let Err(errno) = dispatch::dispatch_message(&GlobalCache, cmd, request, &mut response) {
+        return -errno;
+    }
+
+    // SAFETY: `cb` of a received netlink skb holds its `netlink_skb_parms`.
+    let portid = unsafe { (*(*skb).cb.as_ptr().cast::<bindings::netlink_skb_parms>()).portid };
+    // SAFETY: this is the input callback, which only runs while the socket
+    // is registered.
+    unsafe { send_reply(portid, kind, seq, &response) }
+}
+
+/// Unicasts one reply message (`nlmsg_new`, `nlmsg_put`, `nlmsg_unicast`).
+///
+/// # Safety
+///
+/// Must be called from the input callback, while `SOCKET` is live.
+unsafe fn send_reply(portid: u32, kind: u16, seq: u32, payload: &[u8]) -> c_int {
+    let sock = SOCKET.load(Ordering::Acquire);
+    if sock.is_null() {
+        return EAGAIN.to_errno();
+    }
+
+    let size = NLMSG_HDRLEN + nlmsg_align(payload.len());
+    // SAFETY: plain allocation; `alloc_skb` is an inline wrapper for this.
+    let reply = unsafe { bindings::__alloc_skb(size as _, GFP_KERNEL.as_raw(), 0, NUMA_NO_NODE) };
+    if reply.is_null() {
+        return ENOMEM.to_errno();
+    }
+    // SAFETY: `reply` has room for one message with `payload.len()` bytes,
+    // so `__nlmsg_put` cannot fail; `netlink_unicast` consumes `reply` on
+    // success and failure alike.
+    unsafe {
+        let nlh = bindings::__nlmsg_put(reply, portid, seq, kind.into(), payload.len() as _, 0);
+        let data = nlh.cast::<u8>().add(NLMSG_HDRLEN);
+        ptr::copy_nonoverlapping(payload.as_ptr(), data, payload.len());
+        match bindings::netlink_unicast(sock, reply, portid, bindings::MSG_DONTWAIT as _) {
+            ret if ret < 0 => ret,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # Per-CPU Counters
+//!
+//! `StatCounters` backed by one `KvStats` slot per possible CPU, so hits,
+//! misses, sets and evictions never bounce a shared cache line.
+//!
+//! ## Design Principles
+//!
+//! 1. **Dynamic Per-CPU Area**: The Rust kernel crate cannot express
+//!    `DEFINE_PER_CPU`, so the slots come from `__alloc_percpu` (zeroed) and
+//!    go back with `free_percpu`. `per_cpu_ptr` is spelled out as the
+//!    allocation address plus `__per_cpu_offset[cpu]`.
+//! 2. **Local Writes Only**: `add` runs with preemption disabled (every
+//!    caller holds the cache lock), so it always touches the current CPU's
+//!    slot. That makes a relaxed load + store enough, the same non-atomic
+//!    read-modify-write `this_cpu_add` performs. An interrupt-context
+//!    caller would have to use `try_write` (see `rwlock`), which cannot
+//!    succeed while this CPU's process context holds the lock.
+//! 3. **Summed on Demand**: `total` walks `cpu_possible_mask` the way
+//!    `for_each_possible_cpu` does; a concurrent `add` may or may not be
+//!    included, but nothing is ever lost. The `hybridkv_rwlock` KUnit suite
+//!    checks the sums after concurrent readers and a writer.
+
+use core::ffi::c_ulong;
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use kernel::bindings;
+use kernel::prelude::*;
+
+use crate::cache::{Counter, StatCounters};
+
+/// One CPU's counters (`struct kv_stats`).
+#[repr(C)]
+struct KvStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    sets: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl KvStats {
+    fn get(&self, counter: Counter) -> &AtomicU64 {
+        match counter {
+            Counter::Hits => &self.hits,
+            Counter::Misses => &self.misses,
+            Counter::Sets => &self.sets,
+            Counter::Evictions => &self.evictions,
+        }
+    }
+}
+
+/// Per-CPU `KvStats` allocation.
+pub(crate) struct PerCpuCounters {
+    /// Per-CPU address; only valid through `slot`.
+    stats: NonNull<KvStats>,
+}
+
+// SAFETY: every CPU writes only its own slot; reads of other slots are
+// relaxed atomic loads.
+unsafe impl Send for PerCpuCounters {}
+// SAFETY: see `Send`.
+unsafe impl Sync for PerCpuCounters {}
+
+impl PerCpuCounters {
+    /// Allocates zeroed counters for every possible CPU; may sleep.
+    pub(crate) fn new() -> Result<Self> {
+        // SAFETY: plain allocation; the per-CPU allocator zeroes the area.
+        let stats =
+            unsafe { bindings::__alloc_percpu(size_of::<KvStats>(), align_of::<KvStats>()) };
+        NonNull::new(stats.cast::<KvStats>())
+            .map(|stats| PerCpuCounters { stats })
+            .ok_or(ENOMEM)
+    }
+
+    /// `per_cpu_ptr(stats, cpu)`.
+    fn slot(&self, cpu: u32) -> &KvStats {
+        // SAFETY: `cpu` is a possible CPU, so its offset is initialized, and
+        // the allocation holds one zeroed `KvStats` per possible CPU.
+        unsafe {
+            let offset = bindings::__per_cpu_offset[cpu as usize];
+            &*self.stats.as_ptr().byte_add(offset as usize)
+        }
+    }
+}
+
+impl Drop for PerCpuCounters {
+    fn drop(&mut self) {
+        // SAFETY: the area came from `__alloc_percpu` and is freed once.
+        unsafe { bindings::free_percpu(self.stats.as_ptr().cast()) };
+    }
+}
+
+impl StatCounters for PerCpuCounters {
+    fn add(&self, counter: Counter, n: u64) {
+        // SAFETY: callers hold the cache lock, which disables preemption, so
+        // the CPU cannot change before the store below.
+        let cpu = unsafe { bindings::raw_smp_processor_id() };
+        let slot = self.slot(cpu).get(counter);
+        slot.store(slot.load(Ordering::Relaxed) + n, Ordering::Relaxed);
+    }
+
+    fn total(&self, counter: Counter) -> u64 {
+        possible_cpus()
+            .map(|cpu| self.slot(cpu).get(counter).load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+/// `for_each_possible_cpu` as an iterator.
+fn possible_cpus() -> impl Iterator<Item = u32> {
+    // SAFETY: `nr_cpu_ids` is fixed after boot.
+    let nr = unsafe { bindings::nr_cpu_ids } as c_ulong;
+    let mask = &raw const bindings::__cpu_possible_mask;
+    let next = move |from: c_ulong| {
+        // SAFETY: the possible mask is a static bitmap of `nr_cpu_ids` bits.
+        let cpu = unsafe { bindings::_find_next_bit((*mask).bits.as_ptr(), nr, from) };
+        (cpu < nr).then_some(cpu as u32)
+    };
+    core::iter::successors(next(0), move |&cpu| next(cpu as c_ulong + 1))
+}
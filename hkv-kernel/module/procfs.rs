@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # /proc Statistics
+//!
+//! Read-only `/proc/hybridkv/stats` exposing cache counters, the table size,
+//! the memory budget and the ABI version as `name: value` lines, so they can
+//! be inspected with `cat` instead of a STATS ioctl.
+//!
+//! ## Design Principles
+//!
+//! 1. **Single-Show File**: `proc_create_single_data` wires `single_open` and
+//!    `seq_read`, so only the `show` callback is implemented here.
+//! 2. **Shared Formatter**: The text comes from `cache::ProcStats`, which is
+//!    unit-tested on the host.
+//! 3. **Scoped Lifetime**: `ProcStatsFile` owns the directory; dropping it
+//!    calls `proc_remove`, which also removes `stats`.
+//! 4. **Snapshot, Then Format**: `show` holds the cache lock shared only for
+//!    the `stats` snapshot; formatting and any `seq_file` buffer regrowth
+//!    happen after it is released, so readers never delay writers for the
+//!    length of a dump.
+
+use core::ffi::{c_int, c_void};
+use core::ptr;
+
+use kernel::bindings;
+use kernel::c_str;
+use kernel::prelude::*;
+use kernel::seq_file::SeqFile;
+use kernel::seq_print;
+
+use crate::CACHE;
+use crate::cache::ProcStats;
+
+/// `/proc/hybridkv` and its `stats` entry.
+pub(crate) struct ProcStatsFile {
+    dir: *mut bindings::proc_dir_entry,
+}
+
+// SAFETY: the pointer is only passed to `proc_remove`, which may be called
+// from any thread.
+unsafe impl Send for ProcStatsFile {}
+// SAFETY: no methods access the pointer through `&self`.
+unsafe impl Sync for ProcStatsFile {}
+
+impl ProcStatsFile {
+    /// Creates `/proc/hybridkv/stats` (mode 0444).
+    pub(crate) fn create() -> Result<Self> {
+        // SAFETY: the name is a valid C string; a null parent means `/proc`.
+        let dir =
+            unsafe { bindings::proc_mkdir(c_str!("hybridkv").as_char_ptr(), ptr::null_mut()) };
+        if dir.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // SAFETY: `dir` was just created, and `show_stats` matches the
+        // `int (*)(struct seq_file *, void *)` signature.
+        let entry = unsafe {
+            bindings::proc_create_single_data(
+                c_str!("stats").as_char_ptr(),
+                0o444,
+                dir,
+                Some(show_stats),
+                ptr::null_mut(),
+            )
+        };
+        if entry.is_null() {
+            // SAFETY: `dir` is a live entry owned by this function.
+            unsafe { bindings::proc_remove(dir) };
+            return Err(ENOMEM);
+        }
+
+        Ok(ProcStatsFile { dir })
+    }
+}
+
+impl Drop for ProcStatsFile {
+    fn drop(&mut self) {
+        // SAFETY: `dir` came from `proc_mkdir` and is removed exactly once;
+        // `proc_remove` waits for open readers to finish.
+        unsafe { bindings::proc_remove(self.dir) };
+    }
+}
+
+/// `seq_file` show callback for `/proc/hybridkv/stats`.
+unsafe extern "C" fn show_stats(seq: *mut bindings::seq_file, _data: *mut c_void) -> c_int {
+    let (stats, capacity) = {
+        let cache = CACHE.read();
+        (cache.stats(), cache.capacity())
+    };
+    // SAFETY: `seq` is the live seq_file passed to this show callback.
+    let seq = unsafe { SeqFile::from_raw(seq) };
+    seq_print!(
+        seq,
+        "{}",
+        ProcStats {
+            stats: &stats,
+            capacity
+        }
+    );
+    0
+}
@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # Memory Shrinker
+//!
+//! Registers the cache with the kernel's shrinker framework so memory
+//! pressure (or `echo 3 > /proc/sys/vm/drop_caches`) evicts entries.
+//!
+//! ## Design Principles
+//!
+//! 1. **Count and Scan**: `count_objects` reports live entries;
+//!    `scan_objects` evicts up to `nr_to_scan` of them, least recently
+//!    promoted first (see `KvCache::shrink`), and the frees show up in the
+//!    `evictions` counter.
+//! 2. **Dynamic Shrinker API**: `register_shrinker` was replaced in 6.7 by
+//!    `shrinker_alloc` + `shrinker_register`; `shrinker_free` unregisters and
+//!    waits for running callbacks.
+//! 3. **Reclaim-Safe Locking**: Callbacks take the cache lock like any other
+//!    operation. Nothing under that lock allocates with a reclaiming GFP
+//!    mask, so reclaim can never re-enter the cache while it is held.
+
+use core::ffi::c_ulong;
+
+use kernel::bindings;
+use kernel::c_str;
+use kernel::prelude::*;
+
+use crate::CACHE;
+
+/// `SHRINK_STOP`: no progress possible, stop scanning.
+const SHRINK_STOP: c_ulong = !0;
+
+/// `SHRINK_EMPTY`: nothing to reclaim.
+const SHRINK_EMPTY: c_ulong = !0 - 1;
+
+/// The registered `hybridkv` shrinker.
+pub(crate) struct CacheShrinker {
+    shrinker: *mut bindings::shrinker,
+}
+
+// SAFETY: the pointer is only passed to `shrinker_free`, which may be called
+// from any thread.
+unsafe impl Send for CacheShrinker {}
+// SAFETY: no methods access the pointer through `&self`.
+unsafe impl Sync for CacheShrinker {}
+
+impl CacheShrinker {
+    /// Allocates and registers the shrinker; the equivalent of
+    /// `register_shrinker(&kv_shrinker, "hybridkv")` on older kernels.
+    pub(crate) fn register() -> Result<Self> {
+        // SAFETY: the name is a static C string with no format specifiers.
+        let shrinker = unsafe { bindings::shrinker_alloc(0, c_str!("hybridkv").as_char_ptr()) };
+        if shrinker.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // SAFETY: `shrinker` was just allocated and is not registered yet, so
+        // nothing else reads the callbacks while they are set.
+        unsafe {
+            (*shrinker).count_objects = Some(count_objects);
+            (*shrinker).scan_objects = Some(scan_objects);
+            bindings::shrinker_register(shrinker);
+        }
+        Ok(CacheShrinker { shrinker })
+    }
+}
+
+impl Drop for CacheShrinker {
+    fn drop(&mut self) {
+        // SAFETY: `shrinker` came from `shrinker_alloc` and is freed exactly
+        // once; `shrinker_free` unregisters it and waits for callbacks.
+        unsafe { bindings::shrinker_free(self.shrinker) };
+    }
+}
+
+/// Reports how many entries a scan could free.
+unsafe extern "C" fn count_objects(
+    _shrinker: *mut bindings::shrinker,
+    _sc: *mut bindings::shrink_control,
+) -> c_ulong {
+    match CACHE.read().stats().entry_count {
+        0 => SHRINK_EMPTY,
+        entries => entries as c_ulong,
+    }
+}
+
+/// Evicts up to `nr_to_scan` entries, least recently used first.
+unsafe extern "C" fn scan_objects(
+    _shrinker: *mut bindings::shrinker,
+    sc: *mut bindings::shrink_control,
+) -> c_ulong {
+    // SAFETY: reclaim passes a valid `shrink_control` for this call.
+    let nr_to_scan = unsafe { (*sc).nr_to_scan };
+    let freed = CACHE.write().shrink(nr_to_scan as usize);
+    if freed > 0 {
+        pr_debug!("hybridkv: shrinker evicted {} entries\n", freed);
+    }
+    match freed {
+        0 => SHRINK_STOP,
+        freed => freed as c_ulong,
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # Entry Slab
+//!
+//! Dedicated `kmem_cache` for `KvEntry` objects, listed as `hybridkv_entry`
+//! in `/proc/slabinfo` and `slabtop`. Keys and values are `KVec<u8>` buffers
+//! sized to fit and owned by their entry.
+//!
+//! ## Design Principles
+//!
+//! 1. **Fixed-Size Objects**: Every entry is `size_of::<KvEntry>()` bytes, so
+//!    a slab cache packs them tighter than the nearest `kmalloc` size class
+//!    and keeps them together in memory.
+//! 2. **Reclaim Accounting**: `SLAB_RECLAIM_ACCOUNT` reports the pages as
+//!    reclaimable, since everything here can be refilled from user space;
+//!    `SLAB_HWCACHE_ALIGN` keeps entries off shared cache lines.
+//! 3. **Scoped Lifetime**: `EntrySlab` owns the cache and destroys it on drop;
+//!    the table frees its entries before dropping the allocator. Freeing an
+//!    entry drops its key and value buffers first.
+
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use kernel::bindings;
+use kernel::prelude::*;
+use kernel::str::CStr;
+
+use crate::table::{EntryAlloc, KvEntry};
+
+/// Entry as stored in the slab.
+type SlabKvEntry = KvEntry<KVec<u8>>;
+
+/// `kmem_cache` named `hybridkv_entry`.
+pub(crate) struct EntrySlab {
+    cache: NonNull<bindings::kmem_cache>,
+}
+
+// SAFETY: slab caches may be used and destroyed from any thread.
+unsafe impl Send for EntrySlab {}
+// SAFETY: `&EntrySlab` only allows `kmem_cache_alloc`, which is thread-safe.
+unsafe impl Sync for EntrySlab {}
+
+impl EntrySlab {
+    /// Creates the cache; the equivalent of
+    /// `kmem_cache_create(name, sizeof(struct kv_entry), 0,
+    /// SLAB_HWCACHE_ALIGN | SLAB_RECLAIM_ACCOUNT, NULL)`.
+    pub(crate) fn create(name: &'static CStr) -> Result<Self> {
+        // `kmem_cache_create` is a C macro; this is the function it expands
+        // to. Zeroed args mean default alignment and no constructor.
+        let mut args = bindings::kmem_cache_args::default();
+        // SAFETY: the name is a static C string and `args` outlives the call.
+        let cache = unsafe {
+            bindings::__kmem_cache_create_args(
+                name.as_char_ptr(),
+                size_of::<SlabKvEntry>() as u32,
+                &mut args,
+                bindings::SLAB_HWCACHE_ALIGN | bindings::SLAB_RECLAIM_ACCOUNT,
+            )
+        };
+        NonNull::new(cache)
+            .map(|cache| EntrySlab { cache })
+            .ok_or(ENOMEM)
+    }
+}
+
+impl Drop for EntrySlab {
+    fn drop(&mut self) {
+        // SAFETY: `cache` came from `__kmem_cache_create_args`, and every
+        // `SlabEntry` borrowed from it has been freed (see `KvHashTable`).
+        unsafe { bindings::kmem_cache_destroy(self.cache.as_ptr()) };
+    }
+}
+
+impl EntryAlloc for EntrySlab {
+    type Bytes = KVec<u8>;
+    type Entry = SlabEntry;
+
+    fn alloc(&self) -> Option<SlabEntry> {
+        // SAFETY: `cache` is live. Allocations happen under the cache's
+        // spinning write lock with interrupts off, so they must not sleep.
+        let ptr =
+            unsafe { bindings::kmem_cache_alloc_noprof(self.cache.as_ptr(), GFP_ATOMIC.as_raw()) };
+        let ptr = NonNull::new(ptr.cast::<SlabKvEntry>())?;
+        // SAFETY: the object is `size_of::<SlabKvEntry>()` bytes and at least
+        // word-aligned, which covers `KvEntry`'s alignment.
+        unsafe { ptr.write(SlabKvEntry::empty()) };
+        Some(SlabEntry {
+            ptr,
+            cache: self.cache,
+        })
+    }
+}
+
+/// One `KvEntry` in the slab; returned to it on drop.
+pub(crate) struct SlabEntry {
+    ptr: NonNull<SlabKvEntry>,
+    cache: NonNull<bindings::kmem_cache>,
+}
+
+// SAFETY: the entry is exclusively owned and `kmem_cache_free` may be called
+// from any thread.
+unsafe impl Send for SlabEntry {}
+// SAFETY: `&SlabEntry` only exposes `&KvEntry`, which is plain data plus
+// two `KVec<u8>`s.
+unsafe impl Sync for SlabEntry {}
+
+impl Deref for SlabEntry {
+    type Target = SlabKvEntry;
+
+    fn deref(&self) -> &SlabKvEntry {
+        // SAFETY: `ptr` is initialized and exclusively owned by `self`.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl DerefMut for SlabEntry {
+    fn deref_mut(&mut self) -> &mut SlabKvEntry {
+        // SAFETY: `ptr` is initialized and exclusively owned by `self`.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl Drop for SlabEntry {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` is initialized and exclusively owned, so dropping the
+        // entry (and with it the key and value buffers) in place is sound; it was
+        // allocated from `cache`, which is still alive.
+        unsafe {
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+            bindings::kmem_cache_free(self.cache.as_ptr(), self.ptr.as_ptr().cast());
+        }
+    }
+}
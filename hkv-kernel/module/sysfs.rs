@@ -0,0 +1,238 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! # sysfs Tunables
+//!
+//! `/sys/kernel/hybridkv/` lets operators resize the cache without
+//! reloading the module (and losing its contents):
+//!
+//! - `max_bytes`, `max_entries`, `default_ttl_override` (mode 0644): the
+//!   `cache::Tunable` limits. Writing one applies it at once, evicting least
+//!   recently used entries down to a lowered limit; out-of-range values fail
+//!   the write with `EINVAL`, e.g. `echo 1024 > .../max_entries`.
+//! - `entry_count`, `used_bytes`, `hits`, `misses`, `promotions`,
+//!   `demotions`, `evictions`, `expired` (mode 0444): the STATS counters,
+//!   one value per file.
+//!
+//! ## Design Principles
+//!
+//! 1. **One Value per File**: Each attribute shows a single decimal number
+//!    and a newline, the sysfs convention, so scripts need no parsing.
+//! 2. **Ordered with Promotes**: `store` applies a tunable under the cache
+//!    write lock, so a concurrent PROMOTE sees either the old or the new
+//!    limit, never a mix, and is never admitted past a limit that was
+//!    already lowered.
+//! 3. **Static Tables**: Attributes, the pointer array and the group are
+//!    `static`s; the `kobj_attribute` is the first field of `Attr`, so the
+//!    callbacks recover which value to show from the pointer sysfs passes.
+//! 4. **Scoped Lifetime**: `SysfsDir` owns the kobject; dropping it calls
+//!    `kobject_put`, which removes the directory with its files and waits
+//!    for callbacks still running.
+
+use core::ffi::c_char;
+use core::fmt::{self, Write};
+use core::ptr;
+
+use kernel::bindings;
+use kernel::c_str;
+use kernel::page::PAGE_SIZE;
+use kernel::prelude::*;
+use kernel::str::CStr;
+
+use crate::CACHE;
+use crate::abi::CacheStats;
+use crate::cache::{Tunable, parse_tunable};
+
+/// What an attribute shows.
+#[derive(Clone, Copy)]
+enum Field {
+    Tunable(Tunable),
+    Stat(fn(&CacheStats) -> u64),
+}
+
+/// A `kobj_attribute` plus the value it maps to.
+#[repr(C)]
+struct Attr {
+    kobj: bindings::kobj_attribute,
+    field: Field,
+}
+
+/// `__ATTR(name, 0644, show, store)` for tunables, `__ATTR_RO` for stats.
+const fn attr(name: &'static CStr, field: Field) -> Attr {
+    let writable = matches!(field, Field::Tunable(_));
+    Attr {
+        kobj: bindings::kobj_attribute {
+            attr: bindings::attribute {
+                name: name.as_char_ptr(),
+                mode: if writable { 0o644 } else { 0o444 },
+                // SAFETY: zeroed lockdep fields are what `__ATTR` leaves.
+                ..unsafe { core::mem::zeroed() }
+            },
+            show: Some(kv_sysfs_show),
+            store: if writable { Some(kv_sysfs_store) } else { None },
+        },
+        field,
+    }
+}
+
+const ATTR_COUNT: usize = 11;
+
+/// Attribute tables that can live in `static`s.
+struct Attrs([Attr; ATTR_COUNT]);
+struct AttrPtrs([*mut bindings::attribute; ATTR_COUNT + 1]);
+struct Group(bindings::attribute_group);
+
+// SAFETY: the tables are never written after initialization; sysfs only
+// reads them.
+unsafe impl Sync for Attrs {}
+// SAFETY: as above.
+unsafe impl Sync for AttrPtrs {}
+// SAFETY: as above.
+unsafe impl Sync for Group {}
+
+static ATTRS: Attrs = Attrs([
+    attr(c_str!("max_bytes"), Field::Tunable(Tunable::MaxBytes)),
+    attr(c_str!("max_entries"), Field::Tunable(Tunable::MaxEntries)),
+    attr(
+        c_str!("default_ttl_override"),
+        Field::Tunable(Tunable::DefaultTtlOverride),
+    ),
+    attr(c_str!("entry_count"), Field::Stat(|s| s.entry_count)),
+    attr(c_str!("used_bytes"), Field::Stat(|s| s.used_bytes)),
+    attr(c_str!("hits"), Field::Stat(|s| s.hits)),
+    attr(c_str!("misses"), Field::Stat(|s| s.misses)),
+    attr(c_str!("promotions"), Field::Stat(|s| s.promotions)),
+    attr(c_str!("demotions"), Field::Stat(|s| s.demotions)),
+    attr(c_str!("evictions"), Field::Stat(|s| s.evictions)),
+    attr(c_str!("expired"), Field::Stat(|s| s.expired)),
+]);
+
+/// Null-terminated, as `attribute_group::attrs` requires.
+static ATTR_PTRS: AttrPtrs = {
+    let mut ptrs = [ptr::null_mut(); ATTR_COUNT + 1];
+    let mut i = 0;
+    while i < ATTR_COUNT {
+        ptrs[i] = ptr::addr_of!(ATTRS.0[i].kobj.attr).cast_mut();
+        i += 1;
+    }
+    AttrPtrs(ptrs)
+};
+
+static GROUP: Group = Group(bindings::attribute_group {
+    attrs: ptr::addr_of!(ATTR_PTRS.0).cast_mut().cast(),
+    // SAFETY: a zeroed group has no name (files go in the kobject's own
+    // directory) and no visibility callbacks.
+    ..unsafe { core::mem::zeroed() }
+});
+
+/// `/sys/kernel/hybridkv` and its attributes.
+pub(crate) struct SysfsDir {
+    kobj: *mut bindings::kobject,
+}
+
+// SAFETY: the pointer is only passed to `kobject_put`, which may be called
+// from any thread.
+unsafe impl Send for SysfsDir {}
+// SAFETY: no methods access the pointer through `&self`.
+unsafe impl Sync for SysfsDir {}
+
+impl SysfsDir {
+    /// Creates the directory under `/sys/kernel` and its attributes.
+    pub(crate) fn create() -> Result<Self> {
+        // SAFETY: the name is a valid C string and `kernel_kobj` is the
+        // live `/sys/kernel` kobject.
+        let kobj = unsafe {
+            bindings::kobject_create_and_add(
+                c_str!("hybridkv").as_char_ptr(),
+                bindings::kernel_kobj,
+            )
+        };
+        if kobj.is_null() {
+            return Err(ENOMEM);
+        }
+        let dir = SysfsDir { kobj };
+
+        // SAFETY: `kobj` is live and the group and its attributes are
+        // `'static`.
+        let ret = unsafe { bindings::sysfs_create_group(kobj, &GROUP.0) };
+        if ret != 0 {
+            // `dir` drops here and puts the kobject.
+            return Err(Error::from_errno(ret));
+        }
+        Ok(dir)
+    }
+}
+
+impl Drop for SysfsDir {
+    fn drop(&mut self) {
+        // SAFETY: `kobj` came from `kobject_create_and_add` and this drops
+        // the only reference; the last put removes its sysfs directory.
+        unsafe { bindings::kobject_put(self.kobj) };
+    }
+}
+
+/// Recovers the `Field` of an attribute registered from `ATTRS`.
+///
+/// # Safety
+///
+/// `attr` must point at the `kobj` field of an `Attr`.
+unsafe fn field(attr: *mut bindings::kobj_attribute) -> Field {
+    // SAFETY: `kobj` is the first field of the `repr(C)` `Attr`.
+    unsafe { (*attr.cast::<Attr>()).field }
+}
+
+/// Formats into the page sysfs hands to `show`.
+struct Page<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Page<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn kv_sysfs_show(
+    _kobj: *mut bindings::kobject,
+    attr: *mut bindings::kobj_attribute,
+    buf: *mut c_char,
+) -> isize {
+    // SAFETY: only attributes from `ATTRS` are registered.
+    let value = match unsafe { field(attr) } {
+        Field::Tunable(tunable) => CACHE.read().tunable(tunable),
+        Field::Stat(get) => get(&CACHE.read().stats()),
+    };
+    // SAFETY: sysfs passes a `PAGE_SIZE` buffer owned by this call.
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf.cast::<u8>(), PAGE_SIZE) };
+    let mut page = Page { buf, len: 0 };
+    // A number and a newline always fit in a page.
+    let _ = writeln!(page, "{value}");
+    page.len as isize
+}
+
+unsafe extern "C" fn kv_sysfs_store(
+    _kobj: *mut bindings::kobject,
+    attr: *mut bindings::kobj_attribute,
+    buf: *const c_char,
+    count: usize,
+) -> isize {
+    // SAFETY: only attributes from `ATTRS` are registered.
+    let Field::Tunable(tunable) = (unsafe { field(attr) }) else {
+        return EPERM.to_errno() as isize;
+    };
+    // SAFETY: sysfs passes `count` bytes written by user space.
+    let text = unsafe { core::slice::from_raw_parts(buf.cast::<u8>(), count) };
+    let Some(value) = parse_tunable(text) else {
+        return EINVAL.to_errno() as isize;
+    };
+    match CACHE.write().set_tunable(tunable, value) {
+        Ok(_) => count as isize,
+        Err(_) => EINVAL.to_errno() as isize,
+    }
+}
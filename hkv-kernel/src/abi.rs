@@ -0,0 +1,728 @@
+//! # Kernel-Side ABI Mirror
+//!
+//! Plain-old-data copies of the hkv-common ioctl definitions used by the
+//! `/dev/hybridkv` module.
+//!
+//! ## Design Principles
+//!
+//! 1. **Single Crate**: hkv-common builds with `core` alone
+//!    (`default-features = false`), but Kbuild compiles an out-of-tree module
+//!    as one crate, so the definitions the module needs are copied here.
+//! 2. **Pinned Layouts**: Tests compare every size, offset, and constant with
+//!    the hkv-common originals so the two copies cannot drift.
+//! 3. **Validate at the Edge**: Raw buffers expose their bytes only after the
+//!    user-supplied length has been bounds-checked.
+
+/// ioctl magic number ('H').
+pub const IOCTL_MAGIC: u8 = b'H';
+
+/// Protocol version understood by the module.
+pub const PROTOCOL_VERSION: u8 = 4;
+
+/// Status code indicating success in ioctl responses.
+pub const STATUS_OK: u16 = 0;
+
+/// Maximum key size in bytes.
+pub const MAX_KEY_SIZE: usize = 256;
+
+/// Maximum value size in bytes.
+pub const MAX_VALUE_SIZE: usize = 1024;
+
+/// Netlink protocol number of the HybridKV socket family.
+pub const NETLINK_HYBRIDKV: i32 = 31;
+
+/// Command numbers (`_IOC_NR`), matching `hkv_common::ioctl`.
+pub const CMD_READ: u8 = 0;
+pub const CMD_PROMOTE: u8 = 1;
+pub const CMD_BATCH_PROMOTE: u8 = 2;
+pub const CMD_DEMOTE: u8 = 3;
+pub const CMD_INVALIDATE: u8 = 4;
+pub const CMD_STATS: u8 = 5;
+pub const CMD_CONFIG: u8 = 6;
+pub const CMD_FLUSH: u8 = 7;
+pub const CMD_HELLO: u8 = 8;
+
+/// Status codes, matching `hkv_common::HkvError::code()`.
+pub const ERR_INVALID_INPUT: u16 = 1;
+pub const ERR_NOT_FOUND: u16 = 2;
+pub const ERR_KEY_TOO_LONG: u16 = 3;
+pub const ERR_VALUE_TOO_LONG: u16 = 4;
+pub const ERR_OUT_OF_MEMORY: u16 = 10;
+pub const ERR_CAPACITY_EXCEEDED: u16 = 11;
+pub const ERR_INTERNAL: u16 = 12;
+pub const ERR_BUSY: u16 = 20;
+pub const ERR_TIMEOUT: u16 = 21;
+pub const ERR_INTERRUPTED: u16 = 22;
+pub const ERR_VERSION_MISMATCH: u16 = 30;
+pub const ERR_PROTOCOL_VIOLATION: u16 = 31;
+pub const ERR_UNSUPPORTED_COMMAND: u16 = 32;
+
+/// Key/value limits reported by HELLO (`hkv_common::Limits`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_key: u16,
+    pub max_value: u16,
+}
+
+/// Limits this build enforces; the table stores entries up to these sizes.
+pub const MODULE_LIMITS: Limits = Limits {
+    max_key: MAX_KEY_SIZE as u16,
+    max_value: MAX_VALUE_SIZE as u16,
+};
+
+/// Request flags in `IoctlHeader::reserved` (`hkv_common::RequestFlags`).
+///
+/// Fail with `ERR_BUSY` instead of spinning on a contended cache lock.
+pub const FLAG_NOWAIT: u8 = 1 << 0;
+/// Leave the lookup/hit/miss counters untouched.
+pub const FLAG_NO_PROMOTE_STATS: u8 = 1 << 1;
+/// Suppress logging of expected failures; the module logs none per request.
+pub const FLAG_QUIET: u8 = 1 << 2;
+/// Every flag this build understands; other bits are a protocol violation.
+pub const FLAGS_KNOWN: u8 = FLAG_NOWAIT | FLAG_NO_PROMOTE_STATS | FLAG_QUIET;
+
+/// Marker for types that are valid for every bit pattern and have no padding.
+///
+/// # Safety
+///
+/// Implementors must be `repr(C)`, contain only integers or arrays of
+/// integers, and have no padding bytes, so they can be filled from and
+/// copied to user memory byte-for-byte.
+pub unsafe trait Pod: Copy {}
+
+/// Common ioctl header.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlHeader {
+    pub magic: u8,
+    pub version: u8,
+    pub command: u8,
+    pub reserved: u8,
+}
+
+impl IoctlHeader {
+    /// Builds a response header for `command`.
+    pub const fn new(command: u8) -> Self {
+        IoctlHeader {
+            magic: IOCTL_MAGIC,
+            version: PROTOCOL_VERSION,
+            command,
+            reserved: 0,
+        }
+    }
+
+    /// Checks magic, version, command and flags, returning a status code on
+    /// mismatch.
+    pub const fn validate(&self, command: u8) -> Result<(), u16> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(ERR_VERSION_MISMATCH);
+        }
+        if self.magic != IOCTL_MAGIC || self.command != command {
+            return Err(ERR_PROTOCOL_VIOLATION);
+        }
+        if self.reserved & !FLAGS_KNOWN != 0 {
+            return Err(ERR_PROTOCOL_VIOLATION);
+        }
+        Ok(())
+    }
+
+    /// Request flags (`FLAG_*` bits); meaningful once `validate` passed.
+    pub const fn flags(&self) -> u8 {
+        self.reserved
+    }
+}
+
+/// Length-prefixed key buffer (layout of `hkv_common::Key`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawKey {
+    pub len: u16,
+    pub data: [u8; MAX_KEY_SIZE],
+}
+
+impl RawKey {
+    /// Empty key with a zeroed buffer.
+    pub const EMPTY: RawKey = RawKey {
+        len: 0,
+        data: [0u8; MAX_KEY_SIZE],
+    };
+
+    /// Copies `bytes` into the buffer; the caller guarantees the bound.
+    pub fn fill(&mut self, bytes: &[u8]) {
+        self.data[..bytes.len()].copy_from_slice(bytes);
+        self.len = bytes.len() as u16;
+    }
+
+    /// Returns the used bytes, or `None` when `len` is out of range.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.data.get(..self.len as usize)
+    }
+}
+
+/// Length-prefixed value buffer (layout of `hkv_common::Value`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawValue {
+    pub len: u16,
+    pub data: [u8; MAX_VALUE_SIZE],
+}
+
+impl RawValue {
+    /// Empty value with a zeroed buffer.
+    pub const EMPTY: RawValue = RawValue {
+        len: 0,
+        data: [0u8; MAX_VALUE_SIZE],
+    };
+
+    /// Returns the used bytes, or `None` when `len` is out of range.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.data.get(..self.len as usize)
+    }
+
+    /// Copies `bytes` into the buffer; the caller guarantees the bound.
+    pub fn fill(&mut self, bytes: &[u8]) {
+        self.data[..bytes.len()].copy_from_slice(bytes);
+        self.len = bytes.len() as u16;
+    }
+}
+
+/// READ request (`hkv_common::ReadRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ReadRequest {
+    pub header: IoctlHeader,
+    pub key: RawKey,
+}
+
+/// READ response (`hkv_common::ReadResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ReadResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub value: RawValue,
+}
+
+/// PROMOTE request (`hkv_common::PromoteRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PromoteRequest {
+    pub header: IoctlHeader,
+    pub key: RawKey,
+    pub value: RawValue,
+    pub version: u64,
+    pub ttl: u64,
+}
+
+/// PROMOTE outcomes (`hkv_common::PromoteOutcome`).
+pub const OUTCOME_UNSPECIFIED: u16 = 0;
+pub const OUTCOME_ADMITTED: u16 = 1;
+pub const OUTCOME_REPLACED_EXISTING: u16 = 2;
+pub const OUTCOME_EVICTED_OTHER: u16 = 3;
+pub const OUTCOME_REJECTED_FULL: u16 = 4;
+pub const OUTCOME_REJECTED_STALE: u16 = 5;
+
+/// PROMOTE response (`hkv_common::PromoteResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PromoteResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub outcome: u16,
+    /// Key removed to make room; empty unless `outcome` is
+    /// `OUTCOME_EVICTED_OTHER`.
+    pub evicted: RawKey,
+}
+
+impl PromoteResponse {
+    /// OK response with no outcome yet; the cache fills in the rest.
+    pub const fn new() -> Self {
+        PromoteResponse {
+            header: IoctlHeader::new(CMD_PROMOTE),
+            status: STATUS_OK,
+            outcome: OUTCOME_UNSPECIFIED,
+            evicted: RawKey::EMPTY,
+        }
+    }
+}
+
+impl Default for PromoteResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DEMOTE (delete) request (`hkv_common::DemoteRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DemoteRequest {
+    pub header: IoctlHeader,
+    pub key: RawKey,
+}
+
+/// DEMOTE response (`hkv_common::DemoteResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DemoteResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub reserved: u16,
+}
+
+/// INVALIDATE request (`hkv_common::InvalidateRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InvalidateRequest {
+    pub header: IoctlHeader,
+    pub key: RawKey,
+    /// Explicit padding before the 8-byte-aligned `version`.
+    pub pad: u16,
+    pub version: u64,
+}
+
+/// INVALIDATE response (`hkv_common::InvalidateResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InvalidateResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    /// 1 if an entry was removed.
+    pub removed: u16,
+}
+
+/// Cache telemetry (`hkv_common::CacheStats`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub lookups: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub stale_hits: u64,
+    pub promotions: u64,
+    pub demotions: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+    pub used_bytes: u64,
+    pub max_bytes: u64,
+    pub entry_count: u64,
+    pub lock_contentions: u64,
+    pub rcu_grace_periods: u64,
+    pub expired: u64,
+}
+
+/// STATS request (`hkv_common::StatsRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct StatsRequest {
+    pub header: IoctlHeader,
+}
+
+/// STATS response (`hkv_common::StatsResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct StatsResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub reserved: u16,
+    pub stats: CacheStats,
+}
+
+/// FLUSH request (`hkv_common::FlushRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FlushRequest {
+    pub header: IoctlHeader,
+}
+
+/// FLUSH response (`hkv_common::FlushResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FlushResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub reserved: u16,
+    pub removed: u64,
+}
+
+/// HELLO request (`hkv_common::HelloRequest`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HelloRequest {
+    pub header: IoctlHeader,
+}
+
+/// HELLO response (`hkv_common::HelloResponse`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HelloResponse {
+    pub header: IoctlHeader,
+    pub status: u16,
+    pub reserved: u16,
+    pub limits: Limits,
+}
+
+// SAFETY: all types below are repr(C), integer-only, and padding-free; the
+// layout tests assert that field sizes sum to the struct size.
+unsafe impl Pod for IoctlHeader {}
+unsafe impl Pod for ReadRequest {}
+unsafe impl Pod for ReadResponse {}
+unsafe impl Pod for PromoteRequest {}
+unsafe impl Pod for PromoteResponse {}
+unsafe impl Pod for DemoteRequest {}
+unsafe impl Pod for DemoteResponse {}
+unsafe impl Pod for InvalidateRequest {}
+unsafe impl Pod for InvalidateResponse {}
+unsafe impl Pod for CacheStats {}
+unsafe impl Pod for StatsRequest {}
+unsafe impl Pod for StatsResponse {}
+unsafe impl Pod for FlushRequest {}
+unsafe impl Pod for FlushResponse {}
+unsafe impl Pod for Limits {}
+unsafe impl Pod for HelloRequest {}
+unsafe impl Pod for HelloResponse {}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::{offset_of, size_of};
+
+    use super::*;
+
+    #[test]
+    fn constants_match_hkv_common() {
+        assert_eq!(IOCTL_MAGIC, hkv_common::IOCTL_MAGIC);
+        assert_eq!(PROTOCOL_VERSION, hkv_common::PROTOCOL_VERSION);
+        assert_eq!(STATUS_OK, hkv_common::STATUS_OK);
+        assert_eq!(MAX_KEY_SIZE, hkv_common::MAX_KEY_SIZE);
+        assert_eq!(MAX_VALUE_SIZE, hkv_common::MAX_VALUE_SIZE);
+        assert_eq!(NETLINK_HYBRIDKV, hkv_common::NETLINK_HYBRIDKV);
+
+        assert_eq!(CMD_READ, hkv_common::CMD_READ);
+        assert_eq!(CMD_PROMOTE, hkv_common::CMD_PROMOTE);
+        assert_eq!(CMD_BATCH_PROMOTE, hkv_common::CMD_BATCH_PROMOTE);
+        assert_eq!(CMD_DEMOTE, hkv_common::CMD_DEMOTE);
+        assert_eq!(CMD_INVALIDATE, hkv_common::CMD_INVALIDATE);
+        assert_eq!(CMD_STATS, hkv_common::CMD_STATS);
+        assert_eq!(CMD_CONFIG, hkv_common::CMD_CONFIG);
+        assert_eq!(CMD_FLUSH, hkv_common::CMD_FLUSH);
+        assert_eq!(CMD_HELLO, hkv_common::CMD_HELLO);
+        assert_eq!(MODULE_LIMITS.max_key, hkv_common::Limits::MAX.max_key);
+        assert_eq!(MODULE_LIMITS.max_value, hkv_common::Limits::MAX.max_value);
+
+        use hkv_common::RequestFlags;
+        assert_eq!(FLAG_NOWAIT, RequestFlags::NOWAIT.bits());
+        assert_eq!(FLAG_NO_PROMOTE_STATS, RequestFlags::NO_PROMOTE_STATS.bits());
+        assert_eq!(FLAG_QUIET, RequestFlags::QUIET.bits());
+        assert_eq!(FLAGS_KNOWN, RequestFlags::ALL.bits());
+
+        use hkv_common::PromoteOutcome;
+        assert_eq!(OUTCOME_UNSPECIFIED, PromoteOutcome::Unspecified.as_u16());
+        assert_eq!(OUTCOME_ADMITTED, PromoteOutcome::Admitted.as_u16());
+        assert_eq!(
+            OUTCOME_REPLACED_EXISTING,
+            PromoteOutcome::ReplacedExisting.as_u16()
+        );
+        assert_eq!(OUTCOME_EVICTED_OTHER, PromoteOutcome::EvictedOther.as_u16());
+        assert_eq!(OUTCOME_REJECTED_FULL, PromoteOutcome::RejectedFull.as_u16());
+        assert_eq!(
+            OUTCOME_REJECTED_STALE,
+            PromoteOutcome::RejectedStale.as_u16()
+        );
+
+        assert_eq!(ERR_INVALID_INPUT, hkv_common::HkvError::InvalidInput.code());
+        assert_eq!(ERR_NOT_FOUND, hkv_common::HkvError::NotFound.code());
+        assert_eq!(ERR_KEY_TOO_LONG, hkv_common::HkvError::KeyTooLong.code());
+        assert_eq!(
+            ERR_VALUE_TOO_LONG,
+            hkv_common::HkvError::ValueTooLong.code()
+        );
+        assert_eq!(ERR_OUT_OF_MEMORY, hkv_common::HkvError::OutOfMemory.code());
+        assert_eq!(
+            ERR_CAPACITY_EXCEEDED,
+            hkv_common::HkvError::CapacityExceeded.code()
+        );
+        assert_eq!(ERR_INTERNAL, hkv_common::HkvError::InternalError.code());
+        assert_eq!(ERR_BUSY, hkv_common::HkvError::Busy.code());
+        assert_eq!(ERR_TIMEOUT, hkv_common::HkvError::Timeout.code());
+        assert_eq!(ERR_INTERRUPTED, hkv_common::HkvError::Interrupted.code());
+        assert_eq!(
+            ERR_VERSION_MISMATCH,
+            hkv_common::HkvError::VersionMismatch.code()
+        );
+        assert_eq!(
+            ERR_PROTOCOL_VIOLATION,
+            hkv_common::HkvError::ProtocolViolation.code()
+        );
+        assert_eq!(
+            ERR_UNSUPPORTED_COMMAND,
+            hkv_common::HkvError::UnsupportedCommand.code()
+        );
+    }
+
+    #[test]
+    fn layouts_match_hkv_common() {
+        assert_eq!(
+            size_of::<IoctlHeader>(),
+            size_of::<hkv_common::IoctlHeader>()
+        );
+        assert_eq!(size_of::<RawKey>(), size_of::<hkv_common::Key>());
+        assert_eq!(size_of::<RawValue>(), size_of::<hkv_common::Value>());
+        assert_eq!(
+            size_of::<ReadRequest>(),
+            size_of::<hkv_common::ReadRequest>()
+        );
+        assert_eq!(
+            size_of::<ReadResponse>(),
+            size_of::<hkv_common::ReadResponse>()
+        );
+        assert_eq!(
+            size_of::<PromoteRequest>(),
+            size_of::<hkv_common::PromoteRequest>()
+        );
+        assert_eq!(
+            size_of::<PromoteResponse>(),
+            size_of::<hkv_common::PromoteResponse>()
+        );
+        assert_eq!(
+            offset_of!(PromoteResponse, evicted),
+            offset_of!(hkv_common::PromoteResponse, evicted)
+        );
+        assert_eq!(
+            size_of::<DemoteRequest>(),
+            size_of::<hkv_common::DemoteRequest>()
+        );
+        assert_eq!(
+            size_of::<DemoteResponse>(),
+            size_of::<hkv_common::DemoteResponse>()
+        );
+        assert_eq!(
+            size_of::<InvalidateRequest>(),
+            size_of::<hkv_common::InvalidateRequest>()
+        );
+        assert_eq!(
+            offset_of!(InvalidateRequest, version),
+            offset_of!(hkv_common::InvalidateRequest, version)
+        );
+        assert_eq!(
+            size_of::<InvalidateResponse>(),
+            size_of::<hkv_common::InvalidateResponse>()
+        );
+        assert_eq!(size_of::<CacheStats>(), size_of::<hkv_common::CacheStats>());
+        assert_eq!(
+            size_of::<StatsRequest>(),
+            size_of::<hkv_common::StatsRequest>()
+        );
+        assert_eq!(
+            size_of::<StatsResponse>(),
+            size_of::<hkv_common::StatsResponse>()
+        );
+
+        assert_eq!(
+            offset_of!(ReadResponse, value),
+            offset_of!(hkv_common::ReadResponse, value)
+        );
+        assert_eq!(
+            offset_of!(PromoteRequest, version),
+            offset_of!(hkv_common::PromoteRequest, version)
+        );
+        assert_eq!(
+            offset_of!(PromoteRequest, ttl),
+            offset_of!(hkv_common::PromoteRequest, ttl)
+        );
+        assert_eq!(
+            offset_of!(CacheStats, evictions),
+            offset_of!(hkv_common::CacheStats, evictions)
+        );
+        assert_eq!(
+            offset_of!(CacheStats, expired),
+            offset_of!(hkv_common::CacheStats, expired)
+        );
+        assert_eq!(
+            offset_of!(StatsResponse, stats),
+            offset_of!(hkv_common::StatsResponse, stats)
+        );
+        assert_eq!(
+            size_of::<FlushResponse>(),
+            size_of::<hkv_common::FlushResponse>()
+        );
+        assert_eq!(
+            offset_of!(FlushResponse, removed),
+            offset_of!(hkv_common::FlushResponse, removed)
+        );
+        assert_eq!(size_of::<Limits>(), size_of::<hkv_common::Limits>());
+        assert_eq!(
+            size_of::<HelloResponse>(),
+            size_of::<hkv_common::HelloResponse>()
+        );
+        assert_eq!(
+            offset_of!(HelloResponse, limits),
+            offset_of!(hkv_common::HelloResponse, limits)
+        );
+    }
+
+    #[test]
+    fn pod_types_have_no_padding() {
+        let key = size_of::<u16>() + MAX_KEY_SIZE;
+        let value = size_of::<u16>() + MAX_VALUE_SIZE;
+        let header = size_of::<IoctlHeader>();
+
+        assert_eq!(header, 4);
+        assert_eq!(size_of::<ReadRequest>(), header + key);
+        assert_eq!(size_of::<ReadResponse>(), header + 2 + value);
+        assert_eq!(size_of::<PromoteRequest>(), header + key + value + 16);
+        assert_eq!(size_of::<PromoteResponse>(), header + 4 + key);
+        assert_eq!(size_of::<DemoteResponse>(), header + 4);
+        assert_eq!(size_of::<DemoteRequest>(), header + key);
+        assert_eq!(size_of::<InvalidateRequest>(), header + key + 2 + 8);
+        assert_eq!(size_of::<InvalidateResponse>(), header + 4);
+        assert_eq!(size_of::<CacheStats>(), 14 * 8);
+        assert_eq!(size_of::<StatsResponse>(), header + 4 + 14 * 8);
+        assert_eq!(size_of::<FlushRequest>(), header);
+        assert_eq!(size_of::<FlushResponse>(), header + 4 + 8);
+        assert_eq!(size_of::<HelloRequest>(), header);
+        assert_eq!(size_of::<HelloResponse>(), header + 4 + 4);
+    }
+
+    /// Reads `bytes` as `T`, checks `check`, and writes it back unchanged.
+    fn mirror<T: Pod>(bytes: &[u8], check: impl FnOnce(&T)) {
+        assert_eq!(bytes.len(), size_of::<T>());
+        // SAFETY: the length matches and every bit pattern is a valid `Pod`.
+        let value = unsafe { bytes.as_ptr().cast::<T>().read_unaligned() };
+        check(&value);
+        // SAFETY: `Pod` types have no padding, so every byte is initialized.
+        let back =
+            unsafe { core::slice::from_raw_parts((&raw const value).cast::<u8>(), size_of::<T>()) };
+        assert_eq!(back, bytes);
+    }
+
+    fn header_of(message: &hkv_common::IoctlHeader) -> IoctlHeader {
+        IoctlHeader {
+            magic: message.magic,
+            version: message.version,
+            command: message.command,
+            reserved: message.reserved,
+        }
+    }
+
+    #[test]
+    fn golden_vectors_match_kernel_layouts() {
+        use hkv_common::test_vectors::{Message, test_vectors};
+
+        let mut checked = 0;
+        for vector in test_vectors() {
+            let bytes = &vector.bytes[..];
+            match &vector.message {
+                Message::ReadRequest(m) => mirror(bytes, |r: &ReadRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.header.validate(CMD_READ), Ok(()));
+                    assert_eq!(r.key.as_bytes(), Some(m.key.as_bytes()));
+                }),
+                Message::ReadResponse(m) => mirror(bytes, |r: &ReadResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.status, m.status);
+                    assert_eq!(r.value.as_bytes(), Some(m.value.as_bytes()));
+                }),
+                Message::PromoteRequest(m) => mirror(bytes, |r: &PromoteRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.key.as_bytes(), Some(m.key.as_bytes()));
+                    assert_eq!(r.value.as_bytes(), Some(m.value.as_bytes()));
+                    assert_eq!(r.version, m.version.get());
+                    assert_eq!(r.ttl, m.ttl.as_nanos());
+                }),
+                Message::PromoteResponse(m) => mirror(bytes, |r: &PromoteResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.status, m.status);
+                    assert_eq!(r.outcome, m.outcome);
+                    assert_eq!(r.evicted.as_bytes(), Some(m.evicted.as_bytes()));
+                }),
+                Message::DemoteRequest(m) => mirror(bytes, |r: &DemoteRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.header.validate(CMD_DEMOTE), Ok(()));
+                    assert_eq!(r.key.as_bytes(), Some(m.key.as_bytes()));
+                }),
+                Message::DemoteResponse(m) => mirror(bytes, |r: &DemoteResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.status, m.status);
+                }),
+                Message::InvalidateRequest(m) => mirror(bytes, |r: &InvalidateRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.header.validate(CMD_INVALIDATE), Ok(()));
+                    assert_eq!(r.key.as_bytes(), Some(m.key.as_bytes()));
+                    assert_eq!(r.version, m.version.get());
+                }),
+                Message::InvalidateResponse(m) => mirror(bytes, |r: &InvalidateResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!((r.status, r.removed), (m.status, m.removed));
+                }),
+                Message::StatsRequest(m) => mirror(bytes, |r: &StatsRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                }),
+                Message::StatsResponse(m) => mirror(bytes, |r: &StatsResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.status, m.status);
+                    assert_eq!(r.stats.lookups, m.stats.lookups);
+                    assert_eq!(r.stats.rcu_grace_periods, m.stats.rcu_grace_periods);
+                }),
+                Message::FlushRequest(m) => mirror(bytes, |r: &FlushRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.header.validate(CMD_FLUSH), Ok(()));
+                }),
+                Message::FlushResponse(m) => mirror(bytes, |r: &FlushResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!((r.status, r.removed), (m.status, m.removed));
+                }),
+                Message::HelloRequest(m) => mirror(bytes, |r: &HelloRequest| {
+                    assert_eq!(r.header, header_of(&m.header));
+                }),
+                Message::HelloResponse(m) => mirror(bytes, |r: &HelloResponse| {
+                    assert_eq!(r.header, header_of(&m.header));
+                    assert_eq!(r.limits.max_key, m.limits.max_key);
+                    assert_eq!(r.limits.max_value, m.limits.max_value);
+                }),
+                // CONFIG is not implemented by the module.
+                _ => continue,
+            }
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn raw_buffers_reject_out_of_range_lengths() {
+        let mut key = RawKey {
+            len: MAX_KEY_SIZE as u16 + 1,
+            data: [0u8; MAX_KEY_SIZE],
+        };
+        assert!(key.as_bytes().is_none());
+
+        key.len = 3;
+        key.data[..3].copy_from_slice(b"abc");
+        assert_eq!(key.as_bytes(), Some(b"abc".as_slice()));
+    }
+
+    #[test]
+    fn header_validation_reports_status_codes() {
+        let header = IoctlHeader::new(CMD_READ);
+        assert_eq!(header.validate(CMD_READ), Ok(()));
+        assert_eq!(header.validate(CMD_PROMOTE), Err(ERR_PROTOCOL_VIOLATION));
+
+        let stale = IoctlHeader {
+            version: PROTOCOL_VERSION + 1,
+            ..header
+        };
+        assert_eq!(stale.validate(CMD_READ), Err(ERR_VERSION_MISMATCH));
+
+        let flagged = IoctlHeader {
+            reserved: FLAGS_KNOWN,
+            ..header
+        };
+        assert_eq!(flagged.validate(CMD_READ), Ok(()));
+        assert_eq!(flagged.flags(), FLAGS_KNOWN);
+
+        let unknown = IoctlHeader {
+            reserved: 1 << 7,
+            ..header
+        };
+        assert_eq!(unknown.validate(CMD_READ), Err(ERR_PROTOCOL_VIOLATION));
+    }
+}
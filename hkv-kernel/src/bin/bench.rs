@@ -0,0 +1,165 @@
+//! # Kernel Module Read Benchmark
+//!
+//! Promotes a working set through `/dev/hybridkv`, then has 1, 2, 4 and 8
+//! threads issue READ ioctls for a fixed time and prints reads per second
+//! for each thread count. Each thread opens its own descriptor, like
+//! separate client processes would. Needs the module loaded and access to
+//! the device node.
+//!
+//! ```text
+//! cargo run --release -p hkv-kernel --features bench --bin hkv-kernel-bench -- [seconds] [keys]
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hkv_common::{
+    DEVICE_PATH, HkvError, Key, PromoteRequest, PromoteResponse, ReadRequest, ReadResponse,
+    STATUS_OK, TtlAt, Value, Version,
+};
+use hkv_kernel::dispatch::{HKV_IOC_PROMOTE, HKV_IOC_READ};
+
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+const DEFAULT_SECONDS: u64 = 2;
+const DEFAULT_KEYS: usize = 1024;
+
+/// Issues an `_IOWR` ioctl whose response overwrites the request in place.
+fn transact<Req, Resp>(device: &File, cmd: u32, request: Req) -> Result<Resp, String> {
+    let words = size_of::<Req>().max(size_of::<Resp>()).div_ceil(8);
+    let mut buf = vec![0u64; words];
+    let ptr = buf.as_mut_ptr().cast::<u8>();
+    // SAFETY: `buf` is 8-byte aligned and large enough for either type; both
+    // are plain `repr(C)` data, so reading the response bytes back is sound.
+    unsafe {
+        std::ptr::write(ptr.cast::<Req>(), request);
+        if libc::ioctl(device.as_raw_fd(), cmd as _, ptr) != 0 {
+            let os = std::io::Error::last_os_error();
+            return Err(match os.raw_os_error().and_then(HkvError::from_errno) {
+                Some(err) => format!("{err} ({os})"),
+                None => os.to_string(),
+            });
+        }
+        Ok(std::ptr::read(ptr.cast::<Resp>()))
+    }
+}
+
+fn open() -> Result<File, String> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEVICE_PATH)
+        .map_err(|err| format!("cannot open {DEVICE_PATH}: {err}"))
+}
+
+fn key(i: usize) -> Key {
+    Key::new(format!("hkv-kernel-bench-{i}").as_bytes()).expect("bench keys fit")
+}
+
+/// Promotes keys `0..keys` with 64-byte values.
+fn populate(keys: usize) -> Result<(), String> {
+    let device = open()?;
+    let value = Value::new(&[b'v'; 64]).map_err(|err| err.to_string())?;
+    for i in 0..keys {
+        let request = PromoteRequest::new(key(i), value.clone(), Version::new(1), TtlAt::INFINITE);
+        let response: PromoteResponse = transact(&device, HKV_IOC_PROMOTE, request)
+            .map_err(|err| format!("PROMOTE failed: {err}"))?;
+        if response.status != STATUS_OK {
+            return Err(format!("PROMOTE returned status {}", response.status));
+        }
+    }
+    Ok(())
+}
+
+/// Reads round-robin over the working set until `deadline`, returning the
+/// number of reads issued.
+fn read_until(
+    device: &File,
+    keys: &[Key],
+    offset: usize,
+    deadline: Instant,
+) -> Result<u64, String> {
+    let mut reads = 0u64;
+    let mut i = offset;
+    while Instant::now() < deadline {
+        // Check the clock every 256 reads; `Instant::now` is not free.
+        for _ in 0..256 {
+            let request = ReadRequest::new(keys[i % keys.len()].clone());
+            transact::<_, ReadResponse>(device, HKV_IOC_READ, request)
+                .map_err(|err| format!("READ failed: {err}"))?;
+            i += 1;
+        }
+        reads += 256;
+    }
+    Ok(reads)
+}
+
+/// Runs `threads` readers for `duration`, returning total reads per second.
+fn measure(threads: usize, keys: &[Key], duration: Duration) -> Result<f64, String> {
+    let devices = (0..threads)
+        .map(|_| open())
+        .collect::<Result<Vec<_>, _>>()?;
+    let start = Instant::now();
+    let deadline = start + duration;
+    let reads = thread::scope(|scope| {
+        let workers: Vec<_> = devices
+            .iter()
+            .enumerate()
+            .map(|(n, device)| {
+                let offset = n * keys.len() / threads;
+                scope.spawn(move || read_until(device, keys, offset, deadline))
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("reader panicked"))
+            .sum::<Result<u64, String>>()
+    })?;
+    Ok(reads as f64 / start.elapsed().as_secs_f64())
+}
+
+fn arg<T: std::str::FromStr>(args: &[String], index: usize, default: T) -> Result<T, String> {
+    match args.get(index) {
+        Some(arg) => arg.parse().map_err(|_| format!("invalid argument {arg:?}")),
+        None => Ok(default),
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let duration = Duration::from_secs(arg(&args, 0, DEFAULT_SECONDS)?);
+    let count = arg(&args, 1, DEFAULT_KEYS)?.max(1);
+
+    populate(count)?;
+    let keys: Vec<Key> = (0..count).map(key).collect();
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{count} keys, {}s per run, {cpus} CPUs", duration.as_secs());
+    println!(
+        "{:>7}  {:>12}  {:>12}  {:>7}",
+        "threads", "reads/s", "per thread", "scaling"
+    );
+
+    let mut single = None;
+    for threads in THREADS {
+        let rate = measure(threads, &keys, duration)?;
+        let base = *single.get_or_insert(rate);
+        println!(
+            "{threads:>7}  {rate:>12.0}  {:>12.0}  {:>6.2}x",
+            rate / threads as f64,
+            rate / base
+        );
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("hkv-kernel-bench: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! # Kernel Module Smoke Test
+//!
+//! Promotes one key through `/dev/hybridkv`, reads it back, and exits
+//! non-zero if the value does not survive the round trip. Needs the module
+//! loaded and access to the device node.
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::process::ExitCode;
+
+use hkv_common::{
+    DEVICE_PATH, HkvError, Key, PromoteRequest, PromoteResponse, ReadRequest, ReadResponse,
+    STATUS_OK, TtlAt, Value, Version,
+};
+use hkv_kernel::dispatch::{HKV_IOC_PROMOTE, HKV_IOC_READ};
+
+const KEY: &[u8] = b"hkv-kernel-smoke";
+const VALUE: &[u8] = b"round trip";
+
+/// Issues an `_IOWR` ioctl whose response overwrites the request in place.
+fn transact<Req, Resp>(device: &File, cmd: u32, request: Req) -> Result<Resp, String> {
+    let words = size_of::<Req>().max(size_of::<Resp>()).div_ceil(8);
+    let mut buf = vec![0u64; words];
+    let ptr = buf.as_mut_ptr().cast::<u8>();
+    // SAFETY: `buf` is 8-byte aligned and large enough for either type; both
+    // are plain `repr(C)` data, so reading the response bytes back is sound.
+    unsafe {
+        std::ptr::write(ptr.cast::<Req>(), request);
+        if libc::ioctl(device.as_raw_fd(), cmd as _, ptr) != 0 {
+            let os = std::io::Error::last_os_error();
+            return Err(match os.raw_os_error().and_then(HkvError::from_errno) {
+                Some(err) => format!("{err} ({os})"),
+                None => os.to_string(),
+            });
+        }
+        Ok(std::ptr::read(ptr.cast::<Resp>()))
+    }
+}
+
+fn run() -> Result<(), String> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEVICE_PATH)
+        .map_err(|err| format!("cannot open {DEVICE_PATH}: {err}"))?;
+    let key = Key::new(KEY).map_err(|err| err.to_string())?;
+
+    let promote = PromoteRequest::new(
+        key.clone(),
+        Value::new(VALUE).map_err(|err| err.to_string())?,
+        Version::new(1),
+        TtlAt::INFINITE,
+    );
+    let response: PromoteResponse = transact(&device, HKV_IOC_PROMOTE, promote)
+        .map_err(|err| format!("PROMOTE failed: {err}"))?;
+    if response.status != STATUS_OK {
+        return Err(format!("PROMOTE returned status {}", response.status));
+    }
+
+    let response: ReadResponse = transact(&device, HKV_IOC_READ, ReadRequest::new(key))
+        .map_err(|err| format!("READ failed: {err}"))?;
+    if response.status != STATUS_OK {
+        return Err(format!("READ returned status {}", response.status));
+    }
+    if response.value.as_bytes() != VALUE {
+        return Err(format!(
+            "READ returned {:?}, expected {:?}",
+            String::from_utf8_lossy(response.value.as_bytes()),
+            String::from_utf8_lossy(VALUE)
+        ));
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => {
+            println!("ok: promoted and read back {DEVICE_PATH}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("hkv-kernel-smoke: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
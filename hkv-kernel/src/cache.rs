@@ -0,0 +1,1346 @@
+//! # Kernel Cache
+//!
+//! Hash table plus the counters reported through the STATS ioctl.
+//!
+//! ## Design Principles
+//!
+//! 1. **Caller-Supplied Clock**: Every time-dependent method takes `now_ns`
+//!    (wall-clock nanoseconds, the same clock as `TtlAt`), so expiry is tested
+//!    on the host with a simulated clock.
+//! 2. **Lazy and Periodic Expiry**: Reads treat due entries as misses; they
+//!    only hold a shared lock, so the memory is reclaimed by the module's
+//!    expiry thread, which walks the table in bounded `evict_expired_range`
+//!    steps. Reclaimed entries count as `expired`, not `evictions`.
+//! 3. **Bounded Memory**: `promote` keeps `used_bytes` within `max_bytes`
+//!    and the entry count within `max_entries` by evicting least recently
+//!    used entries first (see `KvHashTable::evict_lru`); an entry that could
+//!    never fit is rejected as `ERR_CAPACITY_EXCEEDED`. Under memory
+//!    pressure `shrink` drops entries in the same order. Both count as
+//!    evictions.
+//!
+//!    Locking: readers hold the shared lock and record use only through the
+//!    entry's atomic `referenced` bit, never by relinking the recency list,
+//!    so concurrent reads stay write-free apart from that bit and the hot
+//!    counters. Everything that moves or frees entries (`promote`, `shrink`,
+//!    `set_limits`, expiry) needs `&mut self`, i.e. the exclusive lock, so
+//!    a reader never sees an entry freed under it.
+//! 4. **Shared Reads**: `read` takes `&self`, so the module can serve lookups
+//!    under a read lock.
+//! 5. **Pluggable Hot Counters**: Hits, misses, sets and evictions go through
+//!    `StatCounters`, which the module backs with per-CPU slots so CPUs never
+//!    write a shared cache line; `stats` sums them. Rarer counters
+//!    (demotions) stay plain integers updated by the write-lock holder.
+//! 6. **Inspectable**: `dump` writes every stored entry as text for the
+//!    module's debugfs `dump` file; `flush` backs its `clear` file.
+//! 7. **Live Tunables**: `set_tunable` validates and applies the memory
+//!    budget, entry limit and TTL override while the cache is in use, for
+//!    the module's sysfs attributes. It needs `&mut self`, so a change and a
+//!    concurrent promote are ordered by the write lock, and a lower limit
+//!    evicts down to it before the lock is released.
+//! 8. **Version Admission**: A promote never replaces a live entry with a
+//!    strictly newer version (`version_admits`, the kernel copy of
+//!    `hkv_common::Version::may_replace`), so a delayed retry of old data
+//!    cannot overwrite what a later promote stored. Version 0 is
+//!    unconditional. The check and the replace happen under one write lock.
+//!
+//!    `invalidate` applies the same rule: it removes an entry only if it is
+//!    not newer than the invalidating write, and remembers that version for
+//!    the key (see `INVALIDATION_SLOTS`), so a promote of older data racing
+//!    with the write is rejected rather than re-caching a stale value.
+//! 9. **Allocation Outside the Lock**: `promote` takes the key and value
+//!    already copied into `EntryAlloc::Bytes` buffers, so the module can
+//!    allocate them with `GFP_KERNEL` before taking the cache lock.
+
+use core::fmt;
+use core::mem::size_of;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::abi::{
+    CacheStats, ERR_CAPACITY_EXCEEDED, ERR_INVALID_INPUT, ERR_KEY_TOO_LONG, ERR_NOT_FOUND,
+    ERR_OUT_OF_MEMORY, ERR_VALUE_TOO_LONG, ERR_VERSION_MISMATCH, Limits, MAX_KEY_SIZE,
+    MAX_VALUE_SIZE, MODULE_LIMITS, OUTCOME_ADMITTED, OUTCOME_EVICTED_OTHER, OUTCOME_REJECTED_FULL,
+    OUTCOME_REJECTED_STALE, OUTCOME_REPLACED_EXISTING, OUTCOME_UNSPECIFIED, PROTOCOL_VERSION,
+    PromoteResponse, RawKey, RawValue, STATUS_OK,
+};
+use crate::table::{Bucket, EntryAlloc, Inserted, KvEntry, KvHashTable, hash_key};
+
+/// `PromoteRequest::ttl` value meaning "never expires".
+pub const TTL_INFINITE: u64 = u64::MAX;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Largest `Tunable::DefaultTtlOverride`, in seconds: the most that still
+/// fits an `i64` nanosecond deadline.
+pub const MAX_TTL_OVERRIDE_SECS: u64 = i64::MAX as u64 / NANOS_PER_SEC;
+
+/// Recently invalidated keys remembered for promote admission, one per slot
+/// by key hash. A key whose slot is taken over by another loses its entry,
+/// so the protection covers invalidations still racing with a promote, not
+/// arbitrarily old ones.
+const INVALIDATION_SLOTS: usize = 64;
+
+/// Highest version a key was invalidated at.
+#[derive(Clone, Copy)]
+struct Invalidation {
+    hash: u64,
+    version: u64,
+}
+
+/// Limits that can be changed while the cache is live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tunable {
+    /// Memory budget (`CacheStats::max_bytes`). At least one entry with a
+    /// key and value at the size limits (`KvCache::limits`), so every legal
+    /// promote still fits, and at most what the full table can hold.
+    MaxBytes,
+    /// Entry limit, `1..=capacity`.
+    MaxEntries,
+    /// Longest lifetime in seconds of entries promoted from now on: later
+    /// deadlines, including "never", are cut to it. 0 disables.
+    DefaultTtlOverride,
+}
+
+/// Whether a write at version `incoming` may replace an entry at `current`:
+/// the entry is not strictly newer, or `incoming` is 0 (unversioned).
+pub const fn version_admits(incoming: u64, current: u64) -> bool {
+    incoming == 0 || incoming >= current
+}
+
+/// Parses a tunable as written to sysfs: decimal, optionally followed by a
+/// newline as `echo` adds.
+pub fn parse_tunable(text: &[u8]) -> Option<u64> {
+    core::str::from_utf8(text).ok()?.trim_end().parse().ok()
+}
+
+/// Counters updated on every lookup or promote.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Hits,
+    Misses,
+    /// Successful promotes (`CacheStats::promotions`).
+    Sets,
+    Evictions,
+}
+
+/// Storage for the hot counters.
+///
+/// `add` may be called concurrently from readers holding only `&self`.
+pub trait StatCounters {
+    /// Adds `n` to `counter`.
+    fn add(&self, counter: Counter, n: u64);
+
+    /// Current total of `counter`.
+    fn total(&self, counter: Counter) -> u64;
+}
+
+/// Single set of relaxed atomics; the host default.
+#[derive(Default)]
+pub struct AtomicCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    sets: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl AtomicCounters {
+    fn get(&self, counter: Counter) -> &AtomicU64 {
+        match counter {
+            Counter::Hits => &self.hits,
+            Counter::Misses => &self.misses,
+            Counter::Sets => &self.sets,
+            Counter::Evictions => &self.evictions,
+        }
+    }
+}
+
+impl StatCounters for AtomicCounters {
+    fn add(&self, counter: Counter, n: u64) {
+        self.get(counter).fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn total(&self, counter: Counter) -> u64 {
+        self.get(counter).load(Ordering::Relaxed)
+    }
+}
+
+/// Hash table with hit/miss/eviction accounting.
+pub struct KvCache<S, A, C = AtomicCounters> {
+    table: KvHashTable<S, A>,
+    /// Cold counters; the hot ones live in `counters`.
+    stats: CacheStats,
+    counters: C,
+    /// Entry count limit, at most the table capacity.
+    max_entries: usize,
+    /// `Tunable::DefaultTtlOverride` in nanoseconds; 0 when off.
+    ttl_override_ns: i64,
+    invalidated: [Invalidation; INVALIDATION_SLOTS],
+    /// Largest key and value `promote` accepts; reported by HELLO.
+    limits: Limits,
+}
+
+impl<S, A, C> KvCache<S, A, C>
+where
+    A: EntryAlloc,
+    S: DerefMut<Target = [Bucket<A::Entry>]>,
+    C: StatCounters,
+{
+    /// Wraps `buckets` in an empty cache whose entries come from `alloc`.
+    pub fn new(buckets: S, alloc: A, counters: C) -> Self {
+        let table = KvHashTable::new(buckets, alloc);
+        let stats = CacheStats {
+            max_bytes: (table.capacity()
+                * (size_of::<KvEntry<A::Bytes>>() + MAX_KEY_SIZE + MAX_VALUE_SIZE))
+                as u64,
+            ..CacheStats::default()
+        };
+        KvCache {
+            max_entries: table.capacity(),
+            ttl_override_ns: 0,
+            limits: MODULE_LIMITS,
+            invalidated: [Invalidation {
+                hash: 0,
+                version: 0,
+            }; INVALIDATION_SLOTS],
+            table,
+            stats,
+            counters,
+        }
+    }
+
+    /// Copies the value for `key` into `out` unless it is missing or expired,
+    /// marking the entry as recently used.
+    pub fn read(&self, key: &[u8], now_ns: i64, out: &mut RawValue) -> u16 {
+        let (counter, status) = match self.live(key, now_ns) {
+            Some(entry) => {
+                entry.mark_referenced();
+                out.fill(entry.value());
+                (Counter::Hits, STATUS_OK)
+            }
+            None => (Counter::Misses, ERR_NOT_FOUND),
+        };
+        self.counters.add(counter, 1);
+        status
+    }
+
+    /// Like `read`, but leaves the lookup counters and recency untouched.
+    pub fn peek(&self, key: &[u8], now_ns: i64, out: &mut RawValue) -> u16 {
+        match self.live(key, now_ns) {
+            Some(entry) => {
+                out.fill(entry.value());
+                STATUS_OK
+            }
+            None => ERR_NOT_FOUND,
+        }
+    }
+
+    fn live(&self, key: &[u8], now_ns: i64) -> Option<&KvEntry<A::Bytes>> {
+        self.table
+            .get(key)
+            .filter(|entry| !entry.is_expired(now_ns))
+    }
+
+    /// Memory charged for an entry with `key_len` and `value_len` bytes.
+    fn entry_bytes(key_len: usize, value_len: usize) -> u64 {
+        (size_of::<KvEntry<A::Bytes>>() + key_len + value_len) as u64
+    }
+
+    fn used_bytes(&self) -> u64 {
+        (self.table.len() * size_of::<KvEntry<A::Bytes>>() + self.table.data_bytes()) as u64
+    }
+
+    /// Sets the memory budget and entry limit, evicting least recently used
+    /// entries until both hold; returns how many were evicted.
+    ///
+    /// `max_entries` is clamped to the table capacity.
+    pub fn set_limits(&mut self, max_bytes: u64, max_entries: usize) -> usize {
+        self.stats.max_bytes = max_bytes;
+        self.max_entries = max_entries.min(self.table.capacity());
+        let mut evicted = 0;
+        while self.used_bytes() > self.stats.max_bytes || self.table.len() > self.max_entries {
+            if self.table.evict_lru(None).is_none() {
+                break;
+            }
+            evicted += 1;
+        }
+        self.counters.add(Counter::Evictions, evicted as u64);
+        evicted
+    }
+
+    /// Entry count limit set by `set_limits`.
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Key and value size limits set by `set_size_limits`.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Lowers the largest key and value later promotes accept; entries
+    /// already stored are kept.
+    ///
+    /// `limits` must be non-zero and within `MODULE_LIMITS`, the sizes the
+    /// table can store (see `params::LoadParams::limits`).
+    pub fn set_size_limits(&mut self, limits: Limits) {
+        debug_assert!(limits.max_key <= MODULE_LIMITS.max_key);
+        debug_assert!(limits.max_value <= MODULE_LIMITS.max_value);
+        self.limits = limits;
+    }
+
+    /// Checks a promote against `limits`.
+    fn check_limits(&self, key: &[u8], value: &[u8]) -> Result<(), u16> {
+        if key.len() > self.limits.max_key as usize {
+            return Err(ERR_KEY_TOO_LONG);
+        }
+        if value.len() > self.limits.max_value as usize {
+            return Err(ERR_VALUE_TOO_LONG);
+        }
+        Ok(())
+    }
+
+    /// Memory a table full of maximum-size entries would use: the default
+    /// and largest budget.
+    fn full_bytes(&self) -> u64 {
+        self.table.capacity() as u64 * Self::entry_bytes(MAX_KEY_SIZE, MAX_VALUE_SIZE)
+    }
+
+    /// Current value of `tunable`.
+    pub fn tunable(&self, tunable: Tunable) -> u64 {
+        match tunable {
+            Tunable::MaxBytes => self.stats.max_bytes,
+            Tunable::MaxEntries => self.max_entries as u64,
+            Tunable::DefaultTtlOverride => self.ttl_override_ns as u64 / NANOS_PER_SEC,
+        }
+    }
+
+    /// Sets `tunable` to `value`, evicting least recently used entries down
+    /// to a lowered limit; returns how many were evicted.
+    ///
+    /// Fails with `ERR_INVALID_INPUT`, changing nothing, when `value` is
+    /// outside the range documented on `Tunable`.
+    pub fn set_tunable(&mut self, tunable: Tunable, value: u64) -> Result<usize, u16> {
+        match tunable {
+            Tunable::MaxBytes => {
+                let min =
+                    Self::entry_bytes(self.limits.max_key as usize, self.limits.max_value as usize);
+                if !(min..=self.full_bytes()).contains(&value) {
+                    return Err(ERR_INVALID_INPUT);
+                }
+                Ok(self.set_limits(value, self.max_entries))
+            }
+            Tunable::MaxEntries => {
+                if !(1..=self.table.capacity() as u64).contains(&value) {
+                    return Err(ERR_INVALID_INPUT);
+                }
+                Ok(self.set_limits(self.stats.max_bytes, value as usize))
+            }
+            Tunable::DefaultTtlOverride => {
+                if value > MAX_TTL_OVERRIDE_SECS {
+                    return Err(ERR_INVALID_INPUT);
+                }
+                self.ttl_override_ns = (value * NANOS_PER_SEC) as i64;
+                Ok(0)
+            }
+        }
+    }
+
+    /// Evicts least recently used entries until one of `key_len + value_len`
+    /// bytes fits the limits, returning the first victim's key.
+    fn make_room(&mut self, key: &[u8], value_len: usize) -> Result<Option<RawKey>, u16> {
+        let needed = Self::entry_bytes(key.len(), value_len);
+        if needed > self.stats.max_bytes {
+            return Err(ERR_CAPACITY_EXCEEDED);
+        }
+        // A replace frees the old entry's bytes and slot.
+        let (freed, slots) = match self.table.get(key) {
+            Some(old) => (Self::entry_bytes(old.key().len(), old.value().len()), 0),
+            None => (0, 1),
+        };
+        let mut first = None;
+        while self.used_bytes() - freed + needed > self.stats.max_bytes
+            || self.table.len() + slots > self.max_entries
+        {
+            let victim = self
+                .table
+                .evict_lru(Some(key))
+                .ok_or(ERR_CAPACITY_EXCEEDED)?;
+            self.counters.add(Counter::Evictions, 1);
+            first.get_or_insert(victim);
+        }
+        Ok(first)
+    }
+
+    /// Inserts or replaces an entry, taking ownership of `key` and `value`;
+    /// `ttl` is an absolute deadline in nanoseconds or `TTL_INFINITE`.
+    ///
+    /// Fills `out.outcome` and `out.evicted` with the admission decision; an
+    /// entry larger than the memory budget, a full table or an exhausted slab
+    /// reports `OUTCOME_REJECTED_FULL`, and a live entry or a remembered
+    /// invalidation with a newer version fails with `ERR_VERSION_MISMATCH`
+    /// and `OUTCOME_REJECTED_STALE`.
+    pub fn promote(
+        &mut self,
+        key: A::Bytes,
+        value: A::Bytes,
+        version: u64,
+        ttl: u64,
+        now_ns: i64,
+        out: &mut PromoteResponse,
+    ) -> u16 {
+        let mut expires_ns = match ttl {
+            TTL_INFINITE => 0,
+            // Clamp so a zero deadline is not mistaken for "no expiry".
+            ttl => (ttl.min(i64::MAX as u64) as i64).max(1),
+        };
+        if self.ttl_override_ns != 0 {
+            let cap = now_ns.saturating_add(self.ttl_override_ns).max(1);
+            expires_ns = match expires_ns {
+                0 => cap,
+                deadline => deadline.min(cap),
+            };
+        }
+        out.evicted = RawKey::EMPTY;
+        let mut victim = None;
+        let inserted = self
+            .check_limits(&key, &value)
+            .and_then(|()| {
+                let cached = self.live(&key, now_ns).map_or(0, |entry| entry.version);
+                let newest = cached.max(self.invalidated_version(&key));
+                if version_admits(version, newest) {
+                    Ok(())
+                } else {
+                    Err(ERR_VERSION_MISMATCH)
+                }
+            })
+            .and_then(|()| self.make_room(&key, value.len()))
+            .and_then(|first| {
+                victim = first;
+                self.table.insert(key, value, version, expires_ns, now_ns)
+            });
+        match inserted {
+            Ok(inserted) => {
+                self.counters.add(Counter::Sets, 1);
+                out.outcome = match (inserted, victim) {
+                    (Inserted::Evicted(evicted), _) => {
+                        self.counters.add(Counter::Evictions, 1);
+                        out.evicted = evicted;
+                        OUTCOME_EVICTED_OTHER
+                    }
+                    (_, Some(evicted)) => {
+                        out.evicted = evicted;
+                        OUTCOME_EVICTED_OTHER
+                    }
+                    (Inserted::New, None) => OUTCOME_ADMITTED,
+                    (Inserted::Replaced, None) => OUTCOME_REPLACED_EXISTING,
+                };
+                STATUS_OK
+            }
+            Err(status) => {
+                out.outcome = match status {
+                    ERR_CAPACITY_EXCEEDED | ERR_OUT_OF_MEMORY => OUTCOME_REJECTED_FULL,
+                    ERR_VERSION_MISMATCH => OUTCOME_REJECTED_STALE,
+                    _ => OUTCOME_UNSPECIFIED,
+                };
+                status
+            }
+        }
+    }
+
+    /// Removes an entry; succeeds even when the key is absent.
+    pub fn demote(&mut self, key: &[u8]) -> u16 {
+        if self.table.remove(key) {
+            self.stats.demotions += 1;
+        }
+        STATUS_OK
+    }
+
+    /// Removes `key` unless its entry is newer than `version` (0 removes it
+    /// unconditionally), returning whether an entry was removed.
+    ///
+    /// A non-zero `version` is remembered even when nothing is removed, so
+    /// a promote of an older version arriving afterwards is rejected.
+    pub fn invalidate(&mut self, key: &[u8], version: u64) -> bool {
+        if version != 0 {
+            let hash = hash_key(key);
+            let slot = &mut self.invalidated[hash as usize % INVALIDATION_SLOTS];
+            if slot.hash != hash {
+                *slot = Invalidation { hash, version: 0 };
+            }
+            slot.version = slot.version.max(version);
+        }
+        let stale = self
+            .table
+            .get(key)
+            .is_some_and(|entry| version_admits(version, entry.version));
+        if stale {
+            self.table.remove(key);
+            self.stats.invalidations += 1;
+        }
+        stale
+    }
+
+    /// Version `key` was last invalidated at, or 0 if it is not remembered.
+    fn invalidated_version(&self, key: &[u8]) -> u64 {
+        let hash = hash_key(key);
+        let slot = &self.invalidated[hash as usize % INVALIDATION_SLOTS];
+        if slot.hash == hash { slot.version } else { 0 }
+    }
+
+    /// Number of hash table buckets, the range `evict_expired_range` walks.
+    pub fn capacity(&self) -> usize {
+        self.table.capacity()
+    }
+
+    /// Removes entries due at `now_ns`, returning how many expired.
+    pub fn evict_expired(&mut self, now_ns: i64) -> usize {
+        self.evict_expired_range(0, self.capacity(), now_ns)
+    }
+
+    /// Removes entries due at `now_ns` from `count` buckets starting at
+    /// `start`, returning how many expired.
+    pub fn evict_expired_range(&mut self, start: usize, count: usize, now_ns: i64) -> usize {
+        let expired = self.table.evict_expired_range(start, count, now_ns);
+        self.stats.expired += expired as u64;
+        expired
+    }
+
+    /// Evicts up to `count` entries, least recently used first, returning
+    /// how many were freed.
+    pub fn shrink(&mut self, count: usize) -> usize {
+        let mut evicted = 0;
+        while evicted < count && self.table.evict_lru(None).is_some() {
+            evicted += 1;
+        }
+        self.counters.add(Counter::Evictions, evicted as u64);
+        evicted
+    }
+
+    /// Removes every entry, returning how many were freed.
+    ///
+    /// A flush is an administrative reset, so no counter records it.
+    pub fn flush(&mut self) -> usize {
+        let flushed = self.table.len();
+        self.table.clear();
+        flushed
+    }
+
+    /// Like `flush`, but only empties `count` buckets from `start`, so a
+    /// flush can release the lock between steps.
+    pub fn flush_range(&mut self, start: usize, count: usize) -> usize {
+        self.table.remove_range(start, count)
+    }
+
+    /// Writes one `key=value expire_ns=N` line per stored entry, including
+    /// expired entries the expiry thread has not swept yet.
+    ///
+    /// Key and value bytes are ASCII-escaped; `expire_ns` is 0 for entries
+    /// that never expire.
+    pub fn dump(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for entry in self.table.iter() {
+            writeln!(
+                out,
+                "{}={} expire_ns={}",
+                entry.key().escape_ascii(),
+                entry.value().escape_ascii(),
+                entry.expires_ns
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot of the counters with current occupancy filled in.
+    ///
+    /// `lookups` is `hits + misses`. Byte counts are entry memory:
+    /// `used_bytes` covers allocated entries with their keys and values,
+    /// `max_bytes` is the budget from `set_limits`, by default what a
+    /// completely full table of maximum-size keys and values would hold.
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.table.len();
+        let hits = self.counters.total(Counter::Hits);
+        let misses = self.counters.total(Counter::Misses);
+        CacheStats {
+            lookups: hits + misses,
+            hits,
+            misses,
+            promotions: self.counters.total(Counter::Sets),
+            evictions: self.counters.total(Counter::Evictions),
+            entry_count: entries as u64,
+            used_bytes: self.used_bytes(),
+            ..self.stats
+        }
+    }
+}
+
+/// `/proc/hybridkv/stats` text: one `name: value` line per counter, then
+/// the table size, memory budget and ABI version.
+///
+/// The first five lines predate the rest and keep their order; new lines
+/// are only ever appended.
+pub struct ProcStats<'a> {
+    pub stats: &'a CacheStats,
+    /// Hash table buckets, the most entries the cache can hold.
+    pub capacity: usize,
+}
+
+impl fmt::Display for ProcStats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.stats;
+        writeln!(f, "entry_count: {}", stats.entry_count)?;
+        writeln!(f, "hit_count: {}", stats.hits)?;
+        writeln!(f, "miss_count: {}", stats.misses)?;
+        writeln!(f, "evictions: {}", stats.evictions)?;
+        writeln!(f, "memory_bytes: {}", stats.used_bytes)?;
+        writeln!(f, "capacity: {}", self.capacity)?;
+        writeln!(f, "max_bytes: {}", stats.max_bytes)?;
+        writeln!(f, "promotions: {}", stats.promotions)?;
+        writeln!(f, "demotions: {}", stats.demotions)?;
+        writeln!(f, "expired: {}", stats.expired)?;
+        writeln!(f, "protocol_version: {PROTOCOL_VERSION}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::format;
+    use std::string::String;
+    use std::vec;
+    use std::vec::Vec;
+
+    use hkv_common::Version;
+
+    use super::*;
+    use crate::testing::{HeapAlloc, HeapEntry, buckets};
+
+    const SECOND: i64 = 1_000_000_000;
+
+    fn cache() -> KvCache<Box<[Bucket<HeapEntry>]>, HeapAlloc> {
+        KvCache::new(buckets(17), HeapAlloc::default(), AtomicCounters::default())
+    }
+
+    #[test]
+    fn expired_entries_miss_and_are_evicted_by_the_timer() {
+        let mut cache = cache();
+        let start = 1_700_000_000 * SECOND;
+        let mut out = RawValue::EMPTY;
+
+        cache.promote(
+            b"ttl".to_vec(),
+            b"v".to_vec(),
+            1,
+            (start + SECOND) as u64,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        cache.promote(
+            b"forever".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        assert_eq!(cache.read(b"ttl", start, &mut out), STATUS_OK);
+
+        // 1.1s later the read misses even before the timer runs.
+        let later = start + SECOND + SECOND / 10;
+        assert_eq!(cache.read(b"ttl", later, &mut out), ERR_NOT_FOUND);
+        assert_eq!(cache.stats().entry_count, 2);
+
+        assert_eq!(cache.evict_expired(later), 1);
+        assert_eq!(cache.evict_expired(later), 0);
+
+        let stats = cache.stats();
+        assert_eq!((stats.expired, stats.evictions), (1, 0));
+        assert_eq!(stats.entry_count, 1);
+        assert_eq!((stats.lookups, stats.hits, stats.misses), (2, 1, 1));
+        assert_eq!(cache.read(b"forever", i64::MAX, &mut out), STATUS_OK);
+    }
+
+    #[test]
+    fn dump_lists_entries_and_flush_empties_the_cache() {
+        let mut cache = cache();
+        cache.promote(
+            b"a".to_vec(),
+            b"1".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        cache.promote(
+            b"b\n".to_vec(),
+            b"x y".to_vec(),
+            1,
+            5,
+            0,
+            &mut PromoteResponse::new(),
+        );
+
+        let mut text = String::new();
+        cache.dump(&mut text).unwrap();
+        let mut lines: Vec<_> = text.lines().collect();
+        lines.sort_unstable();
+        assert_eq!(lines, ["a=1 expire_ns=0", "b\\n=x y expire_ns=5"]);
+
+        assert_eq!(cache.flush(), 2);
+        assert_eq!(cache.stats().entry_count, 0);
+        let mut out = RawValue::EMPTY;
+        assert_eq!(cache.read(b"a", 0, &mut out), ERR_NOT_FOUND);
+
+        text.clear();
+        cache.dump(&mut text).unwrap();
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn counters_track_promotions_and_demotions() {
+        let mut cache = cache();
+
+        cache.promote(
+            b"a".to_vec(),
+            b"1".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        cache.promote(
+            b"a".to_vec(),
+            b"2".to_vec(),
+            2,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        cache.demote(b"a");
+        cache.demote(b"a");
+
+        let stats = cache.stats();
+        assert_eq!(stats.promotions, 2);
+        assert_eq!(stats.demotions, 1);
+        assert_eq!(stats.entry_count, 0);
+        assert_eq!(
+            stats.max_bytes,
+            17 * (size_of::<KvEntry<Vec<u8>>>() + MAX_KEY_SIZE + MAX_VALUE_SIZE) as u64
+        );
+    }
+
+    #[test]
+    fn shrink_evicts_least_recently_used() {
+        let mut cache = cache();
+        let mut out = RawValue::EMPTY;
+        for key in [b"a", b"b", b"c"] {
+            cache.promote(
+                key.to_vec(),
+                b"v".to_vec(),
+                1,
+                TTL_INFINITE,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+
+        assert_eq!(cache.shrink(2), 2);
+        assert_eq!(cache.read(b"c", 0, &mut out), STATUS_OK);
+        assert_eq!(cache.read(b"a", 0, &mut out), ERR_NOT_FOUND);
+        assert_eq!(cache.shrink(8), 1);
+
+        let stats = cache.stats();
+        assert_eq!((stats.evictions, stats.entry_count), (3, 0));
+    }
+
+    #[test]
+    fn proc_stats_lists_every_counter() {
+        let mut cache = cache();
+        let mut out = RawValue::EMPTY;
+        cache.promote(
+            b"k".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        cache.read(b"k", 0, &mut out);
+        cache.read(b"missing", 0, &mut out);
+
+        cache.demote(b"k");
+        let text = format!(
+            "{}",
+            ProcStats {
+                stats: &cache.stats(),
+                capacity: cache.capacity(),
+            }
+        );
+
+        let entry = size_of::<KvEntry<Vec<u8>>>();
+        assert_eq!(
+            text,
+            format!(
+                "entry_count: 0\nhit_count: 1\nmiss_count: 1\nevictions: 0\nmemory_bytes: 0\n\
+                 capacity: 17\nmax_bytes: {}\npromotions: 1\ndemotions: 1\nexpired: 0\n\
+                 protocol_version: {PROTOCOL_VERSION}\n",
+                17 * (entry + MAX_KEY_SIZE + MAX_VALUE_SIZE)
+            )
+        );
+    }
+
+    #[test]
+    fn concurrent_readers_and_a_writer_keep_counters_consistent() {
+        use std::sync::RwLock;
+        use std::thread;
+
+        const ROUNDS: u64 = 1_000;
+        let cache = RwLock::new(cache());
+        cache.write().unwrap().promote(
+            b"hot".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut out = RawValue::EMPTY;
+                    for _ in 0..ROUNDS {
+                        let status = cache.read().unwrap().read(b"hot", 0, &mut out);
+                        assert_eq!(status, STATUS_OK);
+                        assert_eq!(out.as_bytes(), Some(b"v".as_slice()));
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for version in 0..ROUNDS {
+                    let mut cache = cache.write().unwrap();
+                    cache.promote(
+                        b"cold".to_vec(),
+                        b"v".to_vec(),
+                        version,
+                        TTL_INFINITE,
+                        0,
+                        &mut PromoteResponse::new(),
+                    );
+                    cache.demote(b"cold");
+                }
+            });
+        });
+
+        let stats = cache.read().unwrap().stats();
+        assert_eq!(stats.lookups, 4 * ROUNDS);
+        assert_eq!(stats.hits, 4 * ROUNDS);
+        assert_eq!(stats.promotions, ROUNDS + 1);
+        assert_eq!(stats.demotions, ROUNDS);
+    }
+
+    #[test]
+    fn promote_reports_admission_and_evicted_key() {
+        let mut cache = KvCache::new(
+            buckets(2),
+            HeapAlloc::with_limit(2),
+            AtomicCounters::default(),
+        );
+        let mut out = PromoteResponse::new();
+
+        let status = cache.promote(b"a".to_vec(), b"1".to_vec(), 1, 100, 0, &mut out);
+        assert_eq!((status, out.outcome), (STATUS_OK, OUTCOME_ADMITTED));
+        cache.promote(b"a".to_vec(), b"2".to_vec(), 2, 100, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_REPLACED_EXISTING);
+        cache.promote(b"b".to_vec(), b"1".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_ADMITTED);
+
+        // The table is full, so "c" takes the least recently used slot.
+        let status = cache.promote(b"c".to_vec(), b"1".to_vec(), 1, TTL_INFINITE, 50, &mut out);
+        assert_eq!((status, out.outcome), (STATUS_OK, OUTCOME_EVICTED_OTHER));
+        assert_eq!(out.evicted.as_bytes(), Some(b"a".as_slice()));
+
+        // Nothing but the key being replaced could make room for this one.
+        let huge = vec![0; MAX_VALUE_SIZE];
+        cache.set_limits(cache.stats().used_bytes, 2);
+        let status = cache.promote(b"d".to_vec(), huge, 1, TTL_INFINITE, 50, &mut out);
+        assert_eq!(
+            (status, out.outcome),
+            (ERR_CAPACITY_EXCEEDED, OUTCOME_REJECTED_FULL)
+        );
+        assert_eq!(out.evicted, RawKey::EMPTY);
+
+        let stats = cache.stats();
+        assert_eq!((stats.promotions, stats.evictions), (4, 1));
+        assert_eq!(stats.entry_count, 2);
+    }
+
+    #[test]
+    fn promote_rejects_versions_older_than_the_cached_entry() {
+        let mut cache = cache();
+        let mut out = PromoteResponse::new();
+        let mut value = RawValue::EMPTY;
+
+        cache.promote(b"k".to_vec(), b"v5".to_vec(), 5, TTL_INFINITE, 0, &mut out);
+        cache.promote(b"k".to_vec(), b"v6".to_vec(), 6, 100, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_REPLACED_EXISTING);
+
+        // A retried promote of the older version loses to the newer entry.
+        let status = cache.promote(b"k".to_vec(), b"v5".to_vec(), 5, TTL_INFINITE, 0, &mut out);
+        assert_eq!(
+            (status, out.outcome),
+            (ERR_VERSION_MISMATCH, OUTCOME_REJECTED_STALE)
+        );
+        assert_eq!(cache.read(b"k", 0, &mut value), STATUS_OK);
+        assert_eq!(value.as_bytes(), Some(b"v6".as_slice()));
+
+        // Equal versions replace; so does an expired newer entry.
+        cache.promote(b"k".to_vec(), b"v6'".to_vec(), 6, 100, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_REPLACED_EXISTING);
+        cache.promote(
+            b"k".to_vec(),
+            b"v4".to_vec(),
+            4,
+            TTL_INFINITE,
+            200,
+            &mut out,
+        );
+        assert_eq!(out.outcome, OUTCOME_REPLACED_EXISTING);
+
+        // Version 0 is unconditional.
+        cache.promote(b"k".to_vec(), b"v9".to_vec(), 9, TTL_INFINITE, 0, &mut out);
+        let status = cache.promote(b"k".to_vec(), b"v0".to_vec(), 0, TTL_INFINITE, 0, &mut out);
+        assert_eq!(
+            (status, out.outcome),
+            (STATUS_OK, OUTCOME_REPLACED_EXISTING)
+        );
+
+        let stats = cache.stats();
+        assert_eq!((stats.promotions, stats.entry_count), (6, 1));
+    }
+
+    #[test]
+    fn invalidate_removes_entries_no_newer_than_the_write() {
+        let mut cache = cache();
+        let mut out = PromoteResponse::new();
+        let mut value = RawValue::EMPTY;
+        cache.promote(b"k".to_vec(), b"v5".to_vec(), 5, TTL_INFINITE, 0, &mut out);
+
+        // The cached entry is newer than the write being invalidated.
+        assert!(!cache.invalidate(b"k", 4));
+        assert_eq!(cache.read(b"k", 0, &mut value), STATUS_OK);
+
+        assert!(cache.invalidate(b"k", 6));
+        assert_eq!(cache.read(b"k", 0, &mut value), ERR_NOT_FOUND);
+        assert!(!cache.invalidate(b"k", 6));
+
+        // A promote of the data the write replaced arrives late.
+        let status = cache.promote(b"k".to_vec(), b"v5".to_vec(), 5, TTL_INFINITE, 0, &mut out);
+        assert_eq!(
+            (status, out.outcome),
+            (ERR_VERSION_MISMATCH, OUTCOME_REJECTED_STALE)
+        );
+        let status = cache.promote(b"k".to_vec(), b"v6".to_vec(), 6, TTL_INFINITE, 0, &mut out);
+        assert_eq!((status, out.outcome), (STATUS_OK, OUTCOME_ADMITTED));
+
+        // Version 0 removes unconditionally and remembers nothing.
+        assert!(cache.invalidate(b"k", 0));
+        cache.promote(b"o".to_vec(), b"v".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+        assert!(cache.invalidate(b"o", 0));
+        cache.promote(b"o".to_vec(), b"v".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_ADMITTED);
+
+        let stats = cache.stats();
+        assert_eq!((stats.invalidations, stats.entry_count), (3, 1));
+    }
+
+    #[test]
+    fn flush_in_steps_empties_the_table() {
+        let mut cache = cache();
+        for i in 0..10u8 {
+            cache.promote(
+                [b'k', i].to_vec(),
+                b"v".to_vec(),
+                1,
+                TTL_INFINITE,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+
+        let mut flushed = 0;
+        for start in (0..cache.capacity()).step_by(4) {
+            let step = cache.flush_range(start, 4);
+            assert!(step <= 4);
+            flushed += step;
+        }
+        assert_eq!(flushed, 10);
+        assert_eq!(cache.flush_range(usize::MAX, 4), 0);
+        let stats = cache.stats();
+        assert_eq!((stats.entry_count, stats.used_bytes), (0, 0));
+
+        // Flushing is not invalidating: nothing is remembered.
+        let mut out = PromoteResponse::new();
+        cache.promote(b"k\0".to_vec(), b"v".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_ADMITTED);
+        assert_eq!(cache.stats().invalidations, 0);
+    }
+
+    #[test]
+    fn version_admission_matches_the_protocol_rule() {
+        for incoming in [0, 1, 5, 6, u64::MAX] {
+            for current in [0, 1, 5, 6, u64::MAX] {
+                assert_eq!(
+                    version_admits(incoming, current),
+                    Version::new(incoming).may_replace(Version::new(current)),
+                    "{incoming} over {current}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn memory_budget_evicts_least_recently_read_entries() {
+        type Cache = KvCache<Box<[Bucket<HeapEntry>]>, HeapAlloc>;
+        let entry = Cache::entry_bytes(1, 1);
+        let mut cache = cache();
+        let mut value = RawValue::EMPTY;
+        let mut out = PromoteResponse::new();
+        cache.set_limits(3 * entry, 17);
+        for key in [b"a", b"b", b"c"] {
+            cache.promote(key.to_vec(), b"v".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+            assert_eq!(out.outcome, OUTCOME_ADMITTED);
+        }
+
+        // "a" was read, so the oldest unread "b" makes room for "d".
+        assert_eq!(cache.read(b"a", 0, &mut value), STATUS_OK);
+        let status = cache.promote(b"d".to_vec(), b"v".to_vec(), 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!((status, out.outcome), (STATUS_OK, OUTCOME_EVICTED_OTHER));
+        assert_eq!(out.evicted.as_bytes(), Some(b"b".as_slice()));
+        // Peeking does not count as use: "c" is the next victim.
+        assert_eq!(cache.peek(b"c", 0, &mut value), STATUS_OK);
+
+        // A same-size replace reuses the old entry's bytes.
+        cache.promote(b"a".to_vec(), b"w".to_vec(), 2, TTL_INFINITE, 0, &mut out);
+        assert_eq!(out.outcome, OUTCOME_REPLACED_EXISTING);
+        // A bigger one needs room: "c" and then "d" go.
+        cache.promote(
+            b"a".to_vec(),
+            vec![0; 2 * entry as usize],
+            3,
+            TTL_INFINITE,
+            0,
+            &mut out,
+        );
+        assert_eq!(out.outcome, OUTCOME_EVICTED_OTHER);
+        assert_eq!(out.evicted.as_bytes(), Some(b"c".as_slice()));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entry_count, stats.evictions), (1, 3));
+        assert_eq!(stats.max_bytes, 3 * entry);
+        assert!(stats.used_bytes <= stats.max_bytes);
+
+        // An entry over the whole budget is rejected without evicting.
+        let status = cache.promote(
+            b"e".to_vec(),
+            vec![0; 3 * entry as usize],
+            1,
+            TTL_INFINITE,
+            0,
+            &mut out,
+        );
+        assert_eq!(
+            (status, out.outcome),
+            (ERR_CAPACITY_EXCEEDED, OUTCOME_REJECTED_FULL)
+        );
+        assert_eq!(cache.stats().entry_count, 1);
+    }
+
+    #[test]
+    fn set_limits_trims_immediately() {
+        let mut cache = cache();
+        let mut value = RawValue::EMPTY;
+        for key in [b"a", b"b", b"c", b"d"] {
+            cache.promote(
+                key.to_vec(),
+                b"v".to_vec(),
+                1,
+                TTL_INFINITE,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+        cache.read(b"a", 0, &mut value);
+
+        assert_eq!(cache.set_limits(u64::MAX, 2), 2);
+        assert_eq!(cache.read(b"a", 0, &mut value), STATUS_OK);
+        assert_eq!(cache.read(b"d", 0, &mut value), STATUS_OK);
+        // The entry limit never exceeds the table.
+        cache.set_limits(u64::MAX, usize::MAX);
+        assert_eq!(cache.max_entries(), 17);
+        assert_eq!(cache.set_limits(0, 17), 2);
+        assert_eq!(cache.stats().evictions, 4);
+    }
+
+    #[test]
+    fn readers_see_whole_values_while_the_budget_evicts() {
+        use std::sync::RwLock;
+        use std::thread;
+
+        const KEYS: u8 = 64;
+        const ROUNDS: usize = 2_000;
+        type Cache = KvCache<Box<[Bucket<HeapEntry>]>, HeapAlloc>;
+        let mut cache = KvCache::new(
+            buckets(127),
+            HeapAlloc::default(),
+            AtomicCounters::default(),
+        );
+        cache.set_limits(16 * Cache::entry_bytes(2, 8), 127);
+        let cache = RwLock::new(cache);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut out = RawValue::EMPTY;
+                    for round in 0..ROUNDS {
+                        let key = [b'k', round as u8 % KEYS];
+                        if cache.read().unwrap().read(&key, 0, &mut out) == STATUS_OK {
+                            // Values are the key repeated, so a torn or
+                            // freed value would show up here.
+                            let value = out.as_bytes().unwrap();
+                            assert!(!value.is_empty());
+                            assert!(value.chunks(2).all(|chunk| chunk == key));
+                        }
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for round in 0..ROUNDS {
+                    let key = [b'k', round as u8 % KEYS];
+                    let value = key.repeat(round % 4 + 1);
+                    let mut cache = cache.write().unwrap();
+                    let status = cache.promote(
+                        key.to_vec(),
+                        value,
+                        round as u64,
+                        TTL_INFINITE,
+                        0,
+                        &mut PromoteResponse::new(),
+                    );
+                    assert_eq!(status, STATUS_OK);
+                    let stats = cache.stats();
+                    assert!(stats.used_bytes <= stats.max_bytes);
+                }
+            });
+        });
+
+        let stats = cache.read().unwrap().stats();
+        assert_eq!(stats.lookups, 4 * ROUNDS as u64);
+        assert_eq!(stats.promotions, ROUNDS as u64);
+        assert!(stats.evictions > 0);
+        assert!(stats.entry_count <= 16);
+    }
+
+    #[test]
+    fn tunables_are_validated_and_applied_immediately() {
+        type Cache = KvCache<Box<[Bucket<HeapEntry>]>, HeapAlloc>;
+        let mut cache = cache();
+        for key in [b"a", b"b", b"c"] {
+            cache.promote(
+                key.to_vec(),
+                b"v".to_vec(),
+                1,
+                TTL_INFINITE,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+        let full = cache.tunable(Tunable::MaxBytes);
+        let min = Cache::entry_bytes(MAX_KEY_SIZE, MAX_VALUE_SIZE);
+
+        for (tunable, value) in [
+            (Tunable::MaxBytes, min - 1),
+            (Tunable::MaxBytes, full + 1),
+            (Tunable::MaxEntries, 0),
+            (Tunable::MaxEntries, 18),
+            (Tunable::DefaultTtlOverride, MAX_TTL_OVERRIDE_SECS + 1),
+        ] {
+            assert_eq!(cache.set_tunable(tunable, value), Err(ERR_INVALID_INPUT));
+        }
+        assert_eq!(cache.tunable(Tunable::MaxBytes), full);
+        assert_eq!(cache.tunable(Tunable::MaxEntries), 17);
+
+        assert_eq!(cache.set_tunable(Tunable::MaxEntries, 1), Ok(2));
+        assert_eq!(cache.stats().entry_count, 1);
+        assert_eq!(cache.set_tunable(Tunable::MaxBytes, min), Ok(0));
+        assert_eq!(cache.stats().max_bytes, min);
+        assert_eq!(cache.tunable(Tunable::MaxEntries), 1);
+        assert_eq!(cache.set_tunable(Tunable::DefaultTtlOverride, 30), Ok(0));
+        assert_eq!(cache.tunable(Tunable::DefaultTtlOverride), 30);
+    }
+
+    #[test]
+    fn ttl_override_caps_later_deadlines() {
+        let mut cache = cache();
+        let mut out = RawValue::EMPTY;
+        cache.set_tunable(Tunable::DefaultTtlOverride, 10).unwrap();
+        for (key, ttl) in [
+            (b"forever", TTL_INFINITE),
+            (b"later..", (60 * SECOND) as u64),
+            (b"sooner.", (5 * SECOND) as u64),
+        ] {
+            cache.promote(
+                key.to_vec(),
+                b"v".to_vec(),
+                1,
+                ttl,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+
+        assert_eq!(cache.read(b"sooner.", 5 * SECOND, &mut out), ERR_NOT_FOUND);
+        assert_eq!(cache.read(b"forever", 10 * SECOND - 1, &mut out), STATUS_OK);
+        assert_eq!(cache.evict_expired(10 * SECOND), 3);
+
+        // Turning it off only affects later promotes.
+        cache.set_tunable(Tunable::DefaultTtlOverride, 0).unwrap();
+        cache.promote(
+            b"forever".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+        assert_eq!(cache.read(b"forever", i64::MAX, &mut out), STATUS_OK);
+    }
+
+    #[test]
+    fn parse_tunable_accepts_echo_output() {
+        assert_eq!(parse_tunable(b"4096\n"), Some(4096));
+        assert_eq!(parse_tunable(b"0"), Some(0));
+        assert_eq!(parse_tunable(b"18446744073709551615\n"), Some(u64::MAX));
+        for bad in [&b""[..], b"\n", b"-1\n", b"12k\n", b" 12", b"\xff"] {
+            assert_eq!(parse_tunable(bad), None, "{:?}", bad.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn peek_skips_read_counters() {
+        let mut cache = cache();
+        let mut out = RawValue::EMPTY;
+        cache.promote(
+            b"k".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+
+        assert_eq!(cache.peek(b"k", 0, &mut out), STATUS_OK);
+        assert_eq!(out.as_bytes(), Some(b"v".as_slice()));
+        assert_eq!(cache.peek(b"missing", 0, &mut out), ERR_NOT_FOUND);
+
+        let stats = cache.stats();
+        assert_eq!((stats.lookups, stats.hits, stats.misses), (0, 0, 0));
+    }
+
+    #[test]
+    fn sharded_counters_sum_exactly_under_concurrency() {
+        use std::sync::RwLock;
+        use std::thread;
+
+        use crate::testing::ShardedCounters;
+
+        const THREADS: u64 = 8;
+        const ROUNDS: u64 = 2_000;
+        let cache = RwLock::new(KvCache::new(
+            buckets(17),
+            HeapAlloc::default(),
+            ShardedCounters::new(4),
+        ));
+        cache.write().unwrap().promote(
+            b"hot".to_vec(),
+            b"v".to_vec(),
+            1,
+            TTL_INFINITE,
+            0,
+            &mut PromoteResponse::new(),
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    let mut out = RawValue::EMPTY;
+                    for _ in 0..ROUNDS {
+                        let cache = cache.read().unwrap();
+                        assert_eq!(cache.read(b"hot", 0, &mut out), STATUS_OK);
+                        assert_eq!(cache.read(b"cold", 0, &mut out), ERR_NOT_FOUND);
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for version in 0..ROUNDS {
+                    let mut cache = cache.write().unwrap();
+                    let mut out = PromoteResponse::new();
+                    cache.promote(b"tmp".to_vec(), b"v".to_vec(), version, 1, 0, &mut out);
+                    cache.evict_expired(1);
+                }
+            });
+        });
+
+        let stats = cache.read().unwrap().stats();
+        assert_eq!(stats.hits, THREADS * ROUNDS);
+        assert_eq!(stats.misses, THREADS * ROUNDS);
+        assert_eq!(stats.lookups, 2 * THREADS * ROUNDS);
+        assert_eq!(stats.promotions, ROUNDS + 1);
+        assert_eq!(stats.expired, ROUNDS);
+    }
+
+    #[test]
+    fn bounded_sweep_steps_cover_the_whole_table() {
+        let mut cache = cache();
+        for i in 0..10u8 {
+            let ttl = if i % 2 == 0 { 5 } else { TTL_INFINITE };
+            cache.promote(
+                [b'k', i].to_vec(),
+                b"v".to_vec(),
+                1,
+                ttl,
+                0,
+                &mut PromoteResponse::new(),
+            );
+        }
+
+        let mut expired = 0;
+        for start in (0..cache.capacity()).step_by(4) {
+            let step = cache.evict_expired_range(start, 4, 5);
+            assert!(step <= 4);
+            expired += step;
+        }
+        assert_eq!(expired, 5);
+        // Ranges past the end are clipped.
+        assert_eq!(cache.evict_expired_range(usize::MAX, 4, 5), 0);
+
+        let stats = cache.stats();
+        assert_eq!((stats.expired, stats.entry_count), (5, 5));
+    }
+
+    #[test]
+    fn zero_deadline_is_already_expired() {
+        let mut cache = cache();
+        let mut out = RawValue::EMPTY;
+
+        cache.promote(
+            b"k".to_vec(),
+            b"v".to_vec(),
+            1,
+            0,
+            0,
+            &mut PromoteResponse::new(),
+        );
+
+        assert_eq!(cache.read(b"k", 1, &mut out), ERR_NOT_FOUND);
+        assert_eq!(cache.evict_expired(1), 1);
+    }
+}
@@ -0,0 +1,1035 @@
+//! # ioctl Dispatch
+//!
+//! Decode `/dev/hybridkv` ioctl numbers, copy request structs in from user
+//! memory, run the cache operation, and copy the response back out.
+//!
+//! ## Design Principles
+//!
+//! 1. **Kernel-Agnostic Core**: User copies and cache storage are traits so
+//!    the same dispatcher runs inside the module and in host unit tests.
+//! 2. **In/Out Buffers**: Each command uses `_IOWR('H', nr, size)` where `size`
+//!    covers the larger of request and response; the response overwrites the
+//!    request in place.
+//! 3. **Errno and Status**: Transport problems (bad command, faulting copy)
+//!    surface as errno values alone. Cache outcomes travel in the response
+//!    status, and a non-OK status also fails the ioctl with the errno from
+//!    `HkvError::to_errno`, mirrored here as `errno::from_status`.
+//! 4. **No Locks Across Copies**: Cache operations are invoked between the
+//!    copy-in and copy-out so implementations never fault under a lock.
+//! 5. **Request Flags**: Validated header flags are handed to READ, PROMOTE,
+//!    DEMOTE, INVALIDATE and FLUSH; `FLAG_NOWAIT` makes a contended cache
+//!    report `ERR_BUSY` instead of waiting. STATS always waits.
+//! 6. **Message Transport**: Netlink messages carry the same request structs.
+//!    `message_cmd` picks the ioctl number from the payload header and
+//!    `dispatch_message` runs it through `dispatch` with byte buffers standing
+//!    in for user memory, so both transports share every handler.
+
+use core::mem::{MaybeUninit, size_of};
+
+use crate::abi::{
+    CMD_BATCH_PROMOTE, CMD_CONFIG, CMD_DEMOTE, CMD_FLUSH, CMD_HELLO, CMD_INVALIDATE, CMD_PROMOTE,
+    CMD_READ, CMD_STATS, CacheStats, DemoteRequest, DemoteResponse, ERR_KEY_TOO_LONG,
+    ERR_VALUE_TOO_LONG, FlushRequest, FlushResponse, HelloRequest, HelloResponse, IOCTL_MAGIC,
+    InvalidateRequest, InvalidateResponse, IoctlHeader, Limits, MAX_VALUE_SIZE, Pod,
+    PromoteRequest, PromoteResponse, RawValue, ReadRequest, ReadResponse, STATUS_OK, StatsRequest,
+    StatsResponse,
+};
+
+/// Positive errno values returned by the dispatcher (negated by the caller).
+pub mod errno {
+    use crate::abi::{
+        ERR_BUSY, ERR_CAPACITY_EXCEEDED, ERR_INTERNAL, ERR_INTERRUPTED, ERR_INVALID_INPUT,
+        ERR_KEY_TOO_LONG, ERR_NOT_FOUND, ERR_OUT_OF_MEMORY, ERR_PROTOCOL_VIOLATION, ERR_TIMEOUT,
+        ERR_UNSUPPORTED_COMMAND, ERR_VALUE_TOO_LONG, ERR_VERSION_MISMATCH,
+    };
+
+    pub const ENOENT: i32 = 2;
+    pub const EINTR: i32 = 4;
+    pub const EIO: i32 = 5;
+    pub const E2BIG: i32 = 7;
+    pub const EAGAIN: i32 = 11;
+    pub const ENOMEM: i32 = 12;
+    /// Bad address: a user copy faulted.
+    pub const EFAULT: i32 = 14;
+    pub const EINVAL: i32 = 22;
+    /// Inappropriate ioctl for device: unknown command or size.
+    pub const ENOTTY: i32 = 25;
+    pub const ENOSPC: i32 = 28;
+    pub const ENAMETOOLONG: i32 = 36;
+    pub const EPROTO: i32 = 71;
+    pub const EBADMSG: i32 = 74;
+    /// Operation not supported: known command without a handler yet.
+    pub const EOPNOTSUPP: i32 = 95;
+    pub const ETIMEDOUT: i32 = 110;
+
+    /// Errno for a non-OK status; the positive form of
+    /// `hkv_common::HkvError::to_errno`. Unknown codes map to `EIO`.
+    pub const fn from_status(status: u16) -> i32 {
+        match status {
+            ERR_INVALID_INPUT => EINVAL,
+            ERR_NOT_FOUND => ENOENT,
+            ERR_KEY_TOO_LONG => ENAMETOOLONG,
+            ERR_VALUE_TOO_LONG => E2BIG,
+            ERR_OUT_OF_MEMORY => ENOMEM,
+            ERR_CAPACITY_EXCEEDED => ENOSPC,
+            ERR_INTERNAL => EIO,
+            ERR_BUSY => EAGAIN,
+            ERR_TIMEOUT => ETIMEDOUT,
+            ERR_INTERRUPTED => EINTR,
+            ERR_VERSION_MISMATCH => EPROTO,
+            ERR_PROTOCOL_VIOLATION => EBADMSG,
+            ERR_UNSUPPORTED_COMMAND => EOPNOTSUPP,
+            _ => EIO,
+        }
+    }
+}
+
+const IOC_NRBITS: u32 = 8;
+const IOC_TYPEBITS: u32 = 8;
+const IOC_SIZEBITS: u32 = 14;
+const IOC_NRSHIFT: u32 = 0;
+const IOC_TYPESHIFT: u32 = IOC_NRSHIFT + IOC_NRBITS;
+const IOC_SIZESHIFT: u32 = IOC_TYPESHIFT + IOC_TYPEBITS;
+const IOC_DIRSHIFT: u32 = IOC_SIZESHIFT + IOC_SIZEBITS;
+
+/// `_IOC_WRITE | _IOC_READ` direction bits.
+pub const IOC_READ_WRITE: u32 = 0b11;
+
+/// Encodes an ioctl number (`_IOC` in `asm-generic/ioctl.h`).
+pub const fn ioc(dir: u32, kind: u8, nr: u8, size: usize) -> u32 {
+    (dir << IOC_DIRSHIFT)
+        | ((kind as u32) << IOC_TYPESHIFT)
+        | ((nr as u32) << IOC_NRSHIFT)
+        | ((size as u32) << IOC_SIZESHIFT)
+}
+
+/// Encodes a bidirectional HybridKV ioctl (`_IOWR('H', nr, size)`).
+pub const fn iowr(nr: u8, size: usize) -> u32 {
+    ioc(IOC_READ_WRITE, IOCTL_MAGIC, nr, size)
+}
+
+/// Extracts the command number (`_IOC_NR`).
+pub const fn ioc_nr(cmd: u32) -> u8 {
+    (cmd >> IOC_NRSHIFT) as u8
+}
+
+/// Extracts the driver magic (`_IOC_TYPE`).
+pub const fn ioc_type(cmd: u32) -> u8 {
+    (cmd >> IOC_TYPESHIFT) as u8
+}
+
+/// Extracts the argument size (`_IOC_SIZE`).
+pub const fn ioc_size(cmd: u32) -> usize {
+    ((cmd >> IOC_SIZESHIFT) & ((1 << IOC_SIZEBITS) - 1)) as usize
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+/// Full ioctl number for READ.
+pub const HKV_IOC_READ: u32 = iowr(
+    CMD_READ,
+    max(size_of::<ReadRequest>(), size_of::<ReadResponse>()),
+);
+
+/// Full ioctl number for PROMOTE.
+pub const HKV_IOC_PROMOTE: u32 = iowr(
+    CMD_PROMOTE,
+    max(size_of::<PromoteRequest>(), size_of::<PromoteResponse>()),
+);
+
+/// Full ioctl number for DEMOTE.
+pub const HKV_IOC_DEMOTE: u32 = iowr(
+    CMD_DEMOTE,
+    max(size_of::<DemoteRequest>(), size_of::<DemoteResponse>()),
+);
+
+/// Full ioctl number for INVALIDATE.
+pub const HKV_IOC_INVALIDATE: u32 = iowr(
+    CMD_INVALIDATE,
+    max(
+        size_of::<InvalidateRequest>(),
+        size_of::<InvalidateResponse>(),
+    ),
+);
+
+/// Full ioctl number for STATS.
+pub const HKV_IOC_STATS: u32 = iowr(
+    CMD_STATS,
+    max(size_of::<StatsRequest>(), size_of::<StatsResponse>()),
+);
+
+/// Full ioctl number for FLUSH.
+pub const HKV_IOC_FLUSH: u32 = iowr(
+    CMD_FLUSH,
+    max(size_of::<FlushRequest>(), size_of::<FlushResponse>()),
+);
+
+/// Full ioctl number for HELLO.
+pub const HKV_IOC_HELLO: u32 = iowr(
+    CMD_HELLO,
+    max(size_of::<HelloRequest>(), size_of::<HelloResponse>()),
+);
+
+/// Access to the user buffer behind the ioctl `arg` pointer.
+///
+/// The module implements this with `UserSlice`; tests use a byte vector.
+pub trait UserArg {
+    /// Copies `dst.len()` bytes from the start of the user buffer.
+    fn read_into(&mut self, dst: &mut [u8]) -> Result<(), i32>;
+
+    /// Copies `src` to the start of the user buffer.
+    fn write_from(&mut self, src: &[u8]) -> Result<(), i32>;
+}
+
+/// Cache operations invoked by the dispatcher.
+///
+/// Keys and values are already bounds-checked and `flags` holds only known
+/// `FLAG_*` bits; methods return a status code (`STATUS_OK` or an `HkvError`
+/// code) for the response, `ERR_BUSY` when `FLAG_NOWAIT` is set and the cache
+/// is contended.
+pub trait CacheOps {
+    /// Copies the cached value for `key` into `out`.
+    fn read(&self, key: &[u8], flags: u8, out: &mut RawValue) -> u16;
+
+    /// Inserts or replaces an entry, recording the admission decision (and
+    /// any displaced key) in `out`.
+    fn promote(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        version: u64,
+        ttl: u64,
+        flags: u8,
+        out: &mut PromoteResponse,
+    ) -> u16;
+
+    /// Removes an entry; succeeds even when the key is absent.
+    fn demote(&self, key: &[u8], flags: u8) -> u16;
+
+    /// Removes `key` unless its entry is newer than `version`, setting
+    /// `out.removed`; succeeds even when nothing was removed.
+    fn invalidate(&self, key: &[u8], version: u64, flags: u8, out: &mut InvalidateResponse) -> u16;
+
+    /// Removes every entry, counting them in `out.removed`.
+    fn flush(&self, flags: u8, out: &mut FlushResponse) -> u16;
+
+    /// Returns a snapshot of the cache counters.
+    fn stats(&self) -> CacheStats;
+
+    /// Largest key and value the cache accepts, as set at module load.
+    fn limits(&self) -> Limits;
+}
+
+/// Handles one ioctl call.
+///
+/// Returns `Err(errno)` (positive) for transport errors. Cache outcomes are
+/// reported in the response status; once the response is copied out, a
+/// non-OK status also yields `Err(errno::from_status(status))`.
+pub fn dispatch(cache: &impl CacheOps, cmd: u32, arg: &mut impl UserArg) -> Result<(), i32> {
+    if ioc_type(cmd) != IOCTL_MAGIC {
+        return Err(errno::ENOTTY);
+    }
+
+    match ioc_nr(cmd) {
+        CMD_READ if cmd == HKV_IOC_READ => handle_read(cache, arg),
+        CMD_PROMOTE if cmd == HKV_IOC_PROMOTE => handle_promote(cache, arg),
+        CMD_DEMOTE if cmd == HKV_IOC_DEMOTE => handle_demote(cache, arg),
+        CMD_INVALIDATE if cmd == HKV_IOC_INVALIDATE => handle_invalidate(cache, arg),
+        CMD_STATS if cmd == HKV_IOC_STATS => handle_stats(cache, arg),
+        CMD_FLUSH if cmd == HKV_IOC_FLUSH => handle_flush(cache, arg),
+        CMD_HELLO if cmd == HKV_IOC_HELLO => handle_hello(cache, arg),
+        CMD_READ | CMD_PROMOTE | CMD_DEMOTE | CMD_INVALIDATE | CMD_STATS | CMD_FLUSH
+        | CMD_HELLO => Err(errno::ENOTTY),
+        CMD_BATCH_PROMOTE | CMD_CONFIG => Err(errno::EOPNOTSUPP),
+        _ => Err(errno::ENOTTY),
+    }
+}
+
+/// Full ioctl number for the command named in a message payload's header.
+///
+/// The message transport sizes its response buffer with `ioc_size` of the
+/// result. Truncated headers fail with `EBADMSG`.
+pub fn message_cmd(payload: &[u8]) -> Result<u32, i32> {
+    let header: IoctlHeader = read_pod(&mut MessageArg::new(payload, &mut []))?;
+    match header.command {
+        CMD_READ => Ok(HKV_IOC_READ),
+        CMD_PROMOTE => Ok(HKV_IOC_PROMOTE),
+        CMD_DEMOTE => Ok(HKV_IOC_DEMOTE),
+        CMD_INVALIDATE => Ok(HKV_IOC_INVALIDATE),
+        CMD_STATS => Ok(HKV_IOC_STATS),
+        CMD_FLUSH => Ok(HKV_IOC_FLUSH),
+        CMD_HELLO => Ok(HKV_IOC_HELLO),
+        CMD_BATCH_PROMOTE | CMD_CONFIG => Err(errno::EOPNOTSUPP),
+        _ => Err(errno::EBADMSG),
+    }
+}
+
+/// Handles one message-transport request.
+///
+/// `response` must be `ioc_size(cmd)` bytes. Unlike `dispatch`, a non-OK
+/// status is not an error here: the response was written and carries it.
+/// `Err(errno)` means no response was produced.
+pub fn dispatch_message(
+    cache: &impl CacheOps,
+    cmd: u32,
+    request: &[u8],
+    response: &mut [u8],
+) -> Result<(), i32> {
+    let mut arg = MessageArg::new(request, response);
+    match dispatch(cache, cmd, &mut arg) {
+        Err(_) if arg.written => Ok(()),
+        result => result,
+    }
+}
+
+/// `UserArg` over a received message and its reply buffer.
+struct MessageArg<'a> {
+    request: &'a [u8],
+    response: &'a mut [u8],
+    written: bool,
+}
+
+impl<'a> MessageArg<'a> {
+    fn new(request: &'a [u8], response: &'a mut [u8]) -> Self {
+        MessageArg {
+            request,
+            response,
+            written: false,
+        }
+    }
+}
+
+impl UserArg for MessageArg<'_> {
+    fn read_into(&mut self, dst: &mut [u8]) -> Result<(), i32> {
+        let src = self.request.get(..dst.len()).ok_or(errno::EBADMSG)?;
+        dst.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_from(&mut self, src: &[u8]) -> Result<(), i32> {
+        let dst = self.response.get_mut(..src.len()).ok_or(errno::EFAULT)?;
+        dst.copy_from_slice(src);
+        self.written = true;
+        Ok(())
+    }
+}
+
+fn handle_read(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: ReadRequest = read_pod(arg)?;
+    let mut response = ReadResponse {
+        header: IoctlHeader::new(CMD_READ),
+        status: STATUS_OK,
+        value: RawValue::EMPTY,
+    };
+
+    response.status = match request.header.validate(CMD_READ) {
+        Err(status) => status,
+        Ok(()) => match request.key.as_bytes() {
+            Some(key) => cache.read(key, request.header.flags(), &mut response.value),
+            None => ERR_KEY_TOO_LONG,
+        },
+    };
+    if response.status != STATUS_OK {
+        response.value = RawValue::EMPTY;
+    }
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_promote(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: PromoteRequest = read_pod(arg)?;
+    let mut response = PromoteResponse::new();
+
+    response.status = match request.header.validate(CMD_PROMOTE) {
+        Err(status) => status,
+        Ok(()) => match (request.key.as_bytes(), request.value.as_bytes()) {
+            (None, _) => ERR_KEY_TOO_LONG,
+            (_, None) => ERR_VALUE_TOO_LONG,
+            (Some(key), Some(value)) => {
+                debug_assert!(value.len() <= MAX_VALUE_SIZE);
+                let flags = request.header.flags();
+                cache.promote(
+                    key,
+                    value,
+                    request.version,
+                    request.ttl,
+                    flags,
+                    &mut response,
+                )
+            }
+        },
+    };
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_demote(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: DemoteRequest = read_pod(arg)?;
+    let mut response = DemoteResponse {
+        header: IoctlHeader::new(CMD_DEMOTE),
+        status: STATUS_OK,
+        reserved: 0,
+    };
+
+    response.status = match request.header.validate(CMD_DEMOTE) {
+        Err(status) => status,
+        Ok(()) => match request.key.as_bytes() {
+            Some(key) => cache.demote(key, request.header.flags()),
+            None => ERR_KEY_TOO_LONG,
+        },
+    };
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_invalidate(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: InvalidateRequest = read_pod(arg)?;
+    let mut response = InvalidateResponse {
+        header: IoctlHeader::new(CMD_INVALIDATE),
+        status: STATUS_OK,
+        removed: 0,
+    };
+
+    response.status = match request.header.validate(CMD_INVALIDATE) {
+        Err(status) => status,
+        Ok(()) => match request.key.as_bytes() {
+            Some(key) => {
+                cache.invalidate(key, request.version, request.header.flags(), &mut response)
+            }
+            None => ERR_KEY_TOO_LONG,
+        },
+    };
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_flush(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: FlushRequest = read_pod(arg)?;
+    let mut response = FlushResponse {
+        header: IoctlHeader::new(CMD_FLUSH),
+        status: STATUS_OK,
+        reserved: 0,
+        removed: 0,
+    };
+
+    response.status = match request.header.validate(CMD_FLUSH) {
+        Err(status) => status,
+        Ok(()) => cache.flush(request.header.flags(), &mut response),
+    };
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_stats(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: StatsRequest = read_pod(arg)?;
+    let mut response = StatsResponse {
+        header: IoctlHeader::new(CMD_STATS),
+        status: STATUS_OK,
+        reserved: 0,
+        stats: CacheStats::default(),
+    };
+
+    match request.header.validate(CMD_STATS) {
+        Err(status) => response.status = status,
+        Ok(()) => response.stats = cache.stats(),
+    }
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+fn handle_hello(cache: &impl CacheOps, arg: &mut impl UserArg) -> Result<(), i32> {
+    let request: HelloRequest = read_pod(arg)?;
+    let mut response = HelloResponse {
+        header: IoctlHeader::new(CMD_HELLO),
+        status: STATUS_OK,
+        reserved: 0,
+        limits: cache.limits(),
+    };
+    if let Err(status) = request.header.validate(CMD_HELLO) {
+        response.status = status;
+    }
+
+    write_pod(arg, &response)?;
+    status_result(response.status)
+}
+
+/// Fails the ioctl for a non-OK status after the response is copied out.
+fn status_result(status: u16) -> Result<(), i32> {
+    match status {
+        STATUS_OK => Ok(()),
+        status => Err(errno::from_status(status)),
+    }
+}
+
+fn read_pod<T: Pod>(arg: &mut impl UserArg) -> Result<T, i32> {
+    let mut value = MaybeUninit::<T>::zeroed();
+    // SAFETY: the buffer is zero-initialized and spans exactly one `T`.
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), size_of::<T>()) };
+    arg.read_into(bytes)?;
+    // SAFETY: `T: Pod` is valid for every bit pattern.
+    Ok(unsafe { value.assume_init() })
+}
+
+fn write_pod<T: Pod>(arg: &mut impl UserArg, value: &T) -> Result<(), i32> {
+    // SAFETY: `T: Pod` has no padding, so every byte is initialized.
+    let bytes =
+        unsafe { core::slice::from_raw_parts((value as *const T).cast::<u8>(), size_of::<T>()) };
+    arg.write_from(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::abi::{
+        ERR_BUSY, ERR_NOT_FOUND, ERR_PROTOCOL_VIOLATION, FLAG_NO_PROMOTE_STATS, FLAG_NOWAIT,
+        MAX_KEY_SIZE, MODULE_LIMITS, OUTCOME_ADMITTED, OUTCOME_REPLACED_EXISTING, RawKey,
+    };
+
+    /// User buffer backed by a byte vector, optionally faulting on copies.
+    struct FakeUser {
+        buf: Vec<u8>,
+        fault: bool,
+    }
+
+    impl FakeUser {
+        fn with<T: Pod>(request: &T, cmd: u32) -> Self {
+            let mut buf = vec![0u8; ioc_size(cmd)];
+            let bytes = unsafe {
+                core::slice::from_raw_parts((request as *const T).cast::<u8>(), size_of::<T>())
+            };
+            buf[..bytes.len()].copy_from_slice(bytes);
+            FakeUser { buf, fault: false }
+        }
+
+        fn response<T: Pod>(&self) -> T {
+            assert!(self.buf.len() >= size_of::<T>());
+            unsafe { core::ptr::read_unaligned(self.buf.as_ptr().cast::<T>()) }
+        }
+    }
+
+    impl UserArg for FakeUser {
+        fn read_into(&mut self, dst: &mut [u8]) -> Result<(), i32> {
+            if self.fault || dst.len() > self.buf.len() {
+                return Err(errno::EFAULT);
+            }
+            dst.copy_from_slice(&self.buf[..dst.len()]);
+            Ok(())
+        }
+
+        fn write_from(&mut self, src: &[u8]) -> Result<(), i32> {
+            if self.fault || src.len() > self.buf.len() {
+                return Err(errno::EFAULT);
+            }
+            self.buf[..src.len()].copy_from_slice(src);
+            Ok(())
+        }
+    }
+
+    type FakeEntry = (Vec<u8>, Vec<u8>, u64);
+
+    /// Lowered at load, as with `kv_max_key_size=64`.
+    const FAKE_LIMITS: Limits = Limits {
+        max_key: 64,
+        ..MODULE_LIMITS
+    };
+
+    #[derive(Default)]
+    struct FakeCache {
+        entries: RefCell<Vec<FakeEntry>>,
+        /// Simulates another CPU holding the cache lock.
+        contended: Cell<bool>,
+        /// Flags seen by the last operation.
+        last_flags: Cell<u8>,
+    }
+
+    impl FakeCache {
+        /// Returns `ERR_BUSY` for a NOWAIT call on a contended cache.
+        fn busy(&self, flags: u8) -> Option<u16> {
+            self.last_flags.set(flags);
+            if !self.contended.get() {
+                return None;
+            }
+            assert!(flags & FLAG_NOWAIT != 0, "call would spin on the lock");
+            Some(ERR_BUSY)
+        }
+    }
+
+    impl CacheOps for FakeCache {
+        fn read(&self, key: &[u8], flags: u8, out: &mut RawValue) -> u16 {
+            if let Some(status) = self.busy(flags) {
+                return status;
+            }
+            match self.entries.borrow().iter().find(|(k, _, _)| k == key) {
+                Some((_, value, _)) => {
+                    out.fill(value);
+                    STATUS_OK
+                }
+                None => ERR_NOT_FOUND,
+            }
+        }
+
+        fn promote(
+            &self,
+            key: &[u8],
+            value: &[u8],
+            version: u64,
+            _ttl: u64,
+            flags: u8,
+            out: &mut PromoteResponse,
+        ) -> u16 {
+            if let Some(status) = self.busy(flags) {
+                return status;
+            }
+            let mut entries = self.entries.borrow_mut();
+            let before = entries.len();
+            entries.retain(|(k, _, _)| k != key);
+            out.outcome = if entries.len() < before {
+                OUTCOME_REPLACED_EXISTING
+            } else {
+                OUTCOME_ADMITTED
+            };
+            entries.push((key.to_vec(), value.to_vec(), version));
+            STATUS_OK
+        }
+
+        fn demote(&self, key: &[u8], flags: u8) -> u16 {
+            if let Some(status) = self.busy(flags) {
+                return status;
+            }
+            self.entries.borrow_mut().retain(|(k, _, _)| k != key);
+            STATUS_OK
+        }
+
+        fn invalidate(
+            &self,
+            key: &[u8],
+            version: u64,
+            flags: u8,
+            out: &mut InvalidateResponse,
+        ) -> u16 {
+            if let Some(status) = self.busy(flags) {
+                return status;
+            }
+            let mut entries = self.entries.borrow_mut();
+            let before = entries.len();
+            entries.retain(|(k, _, v)| k != key || (version != 0 && *v > version));
+            out.removed = (entries.len() < before) as u16;
+            STATUS_OK
+        }
+
+        fn flush(&self, flags: u8, out: &mut FlushResponse) -> u16 {
+            if let Some(status) = self.busy(flags) {
+                return status;
+            }
+            out.removed = self.entries.borrow_mut().drain(..).count() as u64;
+            STATUS_OK
+        }
+
+        fn stats(&self) -> CacheStats {
+            CacheStats {
+                entry_count: self.entries.borrow().len() as u64,
+                ..CacheStats::default()
+            }
+        }
+
+        fn limits(&self) -> Limits {
+            FAKE_LIMITS
+        }
+    }
+
+    fn raw_key(bytes: &[u8]) -> RawKey {
+        let mut key = RawKey {
+            len: bytes.len() as u16,
+            data: [0u8; MAX_KEY_SIZE],
+        };
+        key.data[..bytes.len()].copy_from_slice(bytes);
+        key
+    }
+
+    fn raw_value(bytes: &[u8]) -> RawValue {
+        let mut value = RawValue::EMPTY;
+        value.fill(bytes);
+        value
+    }
+
+    fn read(cache: &FakeCache, key: &[u8]) -> ReadResponse {
+        read_with_flags(cache, key, 0)
+    }
+
+    fn read_with_flags(cache: &FakeCache, key: &[u8], flags: u8) -> ReadResponse {
+        let request = ReadRequest {
+            header: IoctlHeader {
+                reserved: flags,
+                ..IoctlHeader::new(CMD_READ)
+            },
+            key: raw_key(key),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_READ);
+        let result = dispatch(cache, HKV_IOC_READ, &mut user);
+        let response: ReadResponse = user.response();
+        assert_eq!(result, status_result(response.status));
+        response
+    }
+
+    fn promote(cache: &FakeCache, key: &[u8], value: &[u8]) -> PromoteResponse {
+        let request = PromoteRequest {
+            header: IoctlHeader::new(CMD_PROMOTE),
+            key: raw_key(key),
+            value: raw_value(value),
+            version: 1,
+            ttl: u64::MAX,
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_PROMOTE);
+        let result = dispatch(cache, HKV_IOC_PROMOTE, &mut user);
+        let response: PromoteResponse = user.response();
+        assert_eq!(result, status_result(response.status));
+        response
+    }
+
+    #[test]
+    fn ioctl_numbers_encode_magic_nr_and_size() {
+        assert_eq!(ioc_type(HKV_IOC_READ), b'H');
+        assert_eq!(ioc_nr(HKV_IOC_READ), CMD_READ);
+        assert_eq!(ioc_size(HKV_IOC_READ), size_of::<ReadResponse>());
+        assert_eq!(ioc_nr(HKV_IOC_PROMOTE), CMD_PROMOTE);
+        assert_eq!(ioc_size(HKV_IOC_PROMOTE), size_of::<PromoteRequest>());
+        assert!(size_of::<PromoteResponse>() < size_of::<PromoteRequest>());
+        assert_eq!(HKV_IOC_READ >> 30, IOC_READ_WRITE);
+    }
+
+    #[test]
+    fn promote_read_demote_roundtrip() {
+        let cache = FakeCache::default();
+
+        let promoted = promote(&cache, b"hot", b"value");
+        assert_eq!(promoted.status, STATUS_OK);
+        assert_eq!(promoted.header.command, CMD_PROMOTE);
+        assert_eq!(promoted.outcome, OUTCOME_ADMITTED);
+        assert_eq!(promoted.evicted.len, 0);
+        let replaced = promote(&cache, b"hot", b"value");
+        assert_eq!(replaced.outcome, OUTCOME_REPLACED_EXISTING);
+
+        let hit = read(&cache, b"hot");
+        assert_eq!(hit.status, STATUS_OK);
+        assert_eq!(hit.value.as_bytes(), Some(b"value".as_slice()));
+
+        let request = DemoteRequest {
+            header: IoctlHeader::new(CMD_DEMOTE),
+            key: raw_key(b"hot"),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_DEMOTE);
+        dispatch(&cache, HKV_IOC_DEMOTE, &mut user).unwrap();
+        let demoted: DemoteResponse = user.response();
+        assert_eq!(demoted.status, STATUS_OK);
+        assert_eq!(demoted.header.command, CMD_DEMOTE);
+        assert_eq!(ioc_size(HKV_IOC_DEMOTE), size_of::<DemoteRequest>());
+
+        let miss = read(&cache, b"hot");
+        assert_eq!(miss.status, ERR_NOT_FOUND);
+        assert_eq!(miss.value.len, 0);
+    }
+
+    #[test]
+    fn nowait_read_reports_busy_on_a_contended_cache() {
+        let cache = FakeCache::default();
+        promote(&cache, b"hot", b"value");
+
+        let hit = read_with_flags(&cache, b"hot", FLAG_NOWAIT | FLAG_NO_PROMOTE_STATS);
+        assert_eq!(hit.status, STATUS_OK);
+        assert_eq!(cache.last_flags.get(), FLAG_NOWAIT | FLAG_NO_PROMOTE_STATS);
+
+        cache.contended.set(true);
+        let request = ReadRequest {
+            header: IoctlHeader {
+                reserved: FLAG_NOWAIT,
+                ..IoctlHeader::new(CMD_READ)
+            },
+            key: raw_key(b"hot"),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_READ);
+        assert_eq!(
+            dispatch(&cache, HKV_IOC_READ, &mut user),
+            Err(errno::EAGAIN)
+        );
+        let busy: ReadResponse = user.response();
+        assert_eq!(busy.status, ERR_BUSY);
+        assert_eq!(busy.value.len, 0);
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected_before_the_cache() {
+        let cache = FakeCache::default();
+        // Contended, so reaching the cache without NOWAIT would panic.
+        cache.contended.set(true);
+
+        let response = read_with_flags(&cache, b"k", 1 << 7);
+        assert_eq!(response.status, ERR_PROTOCOL_VIOLATION);
+    }
+
+    #[test]
+    fn status_errnos_match_hkv_common() {
+        for code in 0..=u16::MAX {
+            if let Some(err) = hkv_common::HkvError::from_code(code) {
+                assert_eq!(-errno::from_status(code), err.to_errno(), "{err:?}");
+            }
+        }
+        assert_eq!(errno::from_status(999), errno::EIO);
+    }
+
+    #[test]
+    fn miss_fails_with_enoent_and_still_writes_status() {
+        let cache = FakeCache::default();
+        let request = ReadRequest {
+            header: IoctlHeader::new(CMD_READ),
+            key: raw_key(b"absent"),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_READ);
+
+        assert_eq!(
+            dispatch(&cache, HKV_IOC_READ, &mut user),
+            Err(errno::ENOENT)
+        );
+        assert_eq!(user.response::<ReadResponse>().status, ERR_NOT_FOUND);
+    }
+
+    #[test]
+    fn unknown_commands_return_enotty() {
+        let cache = FakeCache::default();
+        let mut user = FakeUser {
+            buf: vec![0u8; 16],
+            fault: false,
+        };
+
+        assert_eq!(
+            dispatch(&cache, iowr(99, 16), &mut user),
+            Err(errno::ENOTTY)
+        );
+        assert_eq!(
+            dispatch(&cache, ioc(IOC_READ_WRITE, b'X', CMD_READ, 1032), &mut user),
+            Err(errno::ENOTTY)
+        );
+        // Right command number, wrong struct size.
+        assert_eq!(
+            dispatch(&cache, iowr(CMD_READ, 16), &mut user),
+            Err(errno::ENOTTY)
+        );
+        assert_eq!(
+            dispatch(&cache, iowr(CMD_CONFIG, 40), &mut user),
+            Err(errno::EOPNOTSUPP)
+        );
+    }
+
+    fn invalidate(cache: &FakeCache, key: &[u8], version: u64, flags: u8) -> InvalidateResponse {
+        let request = InvalidateRequest {
+            header: IoctlHeader {
+                reserved: flags,
+                ..IoctlHeader::new(CMD_INVALIDATE)
+            },
+            key: raw_key(key),
+            pad: 0,
+            version,
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_INVALIDATE);
+        let result = dispatch(cache, HKV_IOC_INVALIDATE, &mut user);
+        let response: InvalidateResponse = user.response();
+        assert_eq!(result, status_result(response.status));
+        assert_eq!(response.header.command, CMD_INVALIDATE);
+        response
+    }
+
+    #[test]
+    fn invalidate_reports_whether_an_entry_was_removed() {
+        let cache = FakeCache::default();
+        assert_eq!(ioc_size(HKV_IOC_INVALIDATE), size_of::<InvalidateRequest>());
+        promote(&cache, b"hot", b"value");
+
+        // An absent key is not an error, just nothing removed.
+        let kept = invalidate(&cache, b"absent", 5, 0);
+        assert_eq!((kept.status, kept.removed), (STATUS_OK, 0));
+        let removed = invalidate(&cache, b"hot", 1, FLAG_NOWAIT);
+        assert_eq!((removed.status, removed.removed), (STATUS_OK, 1));
+        assert_eq!(cache.last_flags.get(), FLAG_NOWAIT);
+        assert_eq!(read(&cache, b"hot").status, ERR_NOT_FOUND);
+
+        cache.contended.set(true);
+        let busy = invalidate(&cache, b"hot", 1, FLAG_NOWAIT);
+        assert_eq!((busy.status, busy.removed), (ERR_BUSY, 0));
+    }
+
+    #[test]
+    fn flush_reports_the_removed_count() {
+        let cache = FakeCache::default();
+        promote(&cache, b"a", b"1");
+        promote(&cache, b"b", b"2");
+
+        let request = FlushRequest {
+            header: IoctlHeader::new(CMD_FLUSH),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_FLUSH);
+        dispatch(&cache, HKV_IOC_FLUSH, &mut user).unwrap();
+        let response: FlushResponse = user.response();
+        assert_eq!((response.status, response.removed), (STATUS_OK, 2));
+        assert_eq!(response.header.command, CMD_FLUSH);
+        assert_eq!(ioc_size(HKV_IOC_FLUSH), size_of::<FlushResponse>());
+        assert!(cache.entries.borrow().is_empty());
+    }
+
+    #[test]
+    fn stats_reports_cache_counters() {
+        let cache = FakeCache::default();
+        promote(&cache, b"a", b"1");
+        promote(&cache, b"b", b"2");
+
+        let request = StatsRequest {
+            header: IoctlHeader::new(CMD_STATS),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_STATS);
+        dispatch(&cache, HKV_IOC_STATS, &mut user).unwrap();
+
+        let response: StatsResponse = user.response();
+        assert_eq!(response.status, STATUS_OK);
+        assert_eq!(response.header.command, CMD_STATS);
+        assert_eq!(response.stats.entry_count, 2);
+        assert_eq!(ioc_size(HKV_IOC_STATS), size_of::<StatsResponse>());
+    }
+
+    #[test]
+    fn hello_reports_the_loaded_limits() {
+        let request = HelloRequest {
+            header: IoctlHeader::new(CMD_HELLO),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_HELLO);
+        dispatch(&FakeCache::default(), HKV_IOC_HELLO, &mut user).unwrap();
+
+        let response: HelloResponse = user.response();
+        assert_eq!(response.status, STATUS_OK);
+        assert_eq!(response.header.command, CMD_HELLO);
+        assert_eq!(response.limits, FAKE_LIMITS);
+    }
+
+    #[test]
+    fn faulting_user_copies_return_efault() {
+        let cache = FakeCache::default();
+        let request = ReadRequest {
+            header: IoctlHeader::new(CMD_READ),
+            key: raw_key(b"k"),
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_READ);
+        user.fault = true;
+
+        assert_eq!(
+            dispatch(&cache, HKV_IOC_READ, &mut user),
+            Err(errno::EFAULT)
+        );
+    }
+
+    #[test]
+    fn malformed_requests_report_status_without_touching_cache() {
+        let cache = FakeCache::default();
+
+        let mut request = PromoteRequest {
+            header: IoctlHeader::new(CMD_READ),
+            key: raw_key(b"k"),
+            value: raw_value(b"v"),
+            version: 1,
+            ttl: 0,
+        };
+        let mut user = FakeUser::with(&request, HKV_IOC_PROMOTE);
+        assert_eq!(
+            dispatch(&cache, HKV_IOC_PROMOTE, &mut user),
+            Err(errno::EBADMSG)
+        );
+        assert_eq!(
+            user.response::<PromoteResponse>().status,
+            ERR_PROTOCOL_VIOLATION
+        );
+
+        request.header = IoctlHeader::new(CMD_PROMOTE);
+        request.key.len = MAX_KEY_SIZE as u16 + 1;
+        let mut user = FakeUser::with(&request, HKV_IOC_PROMOTE);
+        assert_eq!(
+            dispatch(&cache, HKV_IOC_PROMOTE, &mut user),
+            Err(errno::ENAMETOOLONG)
+        );
+        assert_eq!(user.response::<PromoteResponse>().status, ERR_KEY_TOO_LONG);
+
+        assert!(cache.entries.borrow().is_empty());
+    }
+
+    fn message<T: Pod>(request: &T) -> Vec<u8> {
+        let bytes = unsafe {
+            core::slice::from_raw_parts((request as *const T).cast::<u8>(), size_of::<T>())
+        };
+        bytes.to_vec()
+    }
+
+    #[test]
+    fn messages_run_through_the_ioctl_handlers() {
+        let cache = FakeCache::default();
+        promote(&cache, b"hot", b"value");
+
+        let hello = message(&HelloRequest {
+            header: IoctlHeader::new(CMD_HELLO),
+        });
+        let cmd = message_cmd(&hello).unwrap();
+        assert_eq!(cmd, HKV_IOC_HELLO);
+        let mut response = vec![0u8; ioc_size(cmd)];
+        dispatch_message(&cache, cmd, &hello, &mut response).unwrap();
+        let hello = FakeUser {
+            buf: response,
+            fault: false,
+        }
+        .response::<HelloResponse>();
+        assert_eq!(hello.status, STATUS_OK);
+        assert_eq!(hello.limits, FAKE_LIMITS);
+
+        // A miss is a normal reply, not a transport error.
+        let read = message(&ReadRequest {
+            header: IoctlHeader::new(CMD_READ),
+            key: raw_key(b"absent"),
+        });
+        let cmd = message_cmd(&read).unwrap();
+        let mut response = vec![0u8; ioc_size(cmd)];
+        dispatch_message(&cache, cmd, &read, &mut response).unwrap();
+        let read = FakeUser {
+            buf: response,
+            fault: false,
+        }
+        .response::<ReadResponse>();
+        assert_eq!(read.status, ERR_NOT_FOUND);
+    }
+
+    #[test]
+    fn malformed_messages_produce_no_response() {
+        let cache = FakeCache::default();
+        let mut response = vec![0u8; ioc_size(HKV_IOC_READ)];
+
+        assert_eq!(message_cmd(&[b'H', 3]), Err(errno::EBADMSG));
+        assert_eq!(message_cmd(&[b'H', 3, 99, 0]), Err(errno::EBADMSG));
+        assert_eq!(
+            message_cmd(&[b'H', 3, CMD_CONFIG, 0]),
+            Err(errno::EOPNOTSUPP)
+        );
+
+        // Header says READ but the key was cut off.
+        let truncated = message(&IoctlHeader::new(CMD_READ));
+        let cmd = message_cmd(&truncated).unwrap();
+        assert_eq!(
+            dispatch_message(&cache, cmd, &truncated, &mut response),
+            Err(errno::EBADMSG)
+        );
+        assert!(response.iter().all(|&b| b == 0));
+    }
+}
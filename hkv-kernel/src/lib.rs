@@ -1,14 +1,24 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! # HybridKV Kernel Cache Core
+//!
+//! Portable pieces of the `/dev/hybridkv` kernel module that do not depend on
+//! kernel bindings, so they can be unit-tested on the host.
+//!
+//! ## Design Principles
+//!
+//! 1. **Shared Source**: The Rust-for-Linux module in `module/` includes these
+//!    files directly with `#[path]`; cargo only builds them for tests.
+//! 2. **Core Only**: No `std` and no allocation outside tests, matching what
+//!    is available in kernel context.
+//! 3. **Thin Glue**: Kernel-specific code (misc device, user copies, locks)
+//!    stays in `module/` behind small traits defined here.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#![cfg_attr(not(test), no_std)]
+
+pub mod abi;
+pub mod cache;
+pub mod dispatch;
+pub mod params;
+pub mod table;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+#[cfg(test)]
+mod testing;
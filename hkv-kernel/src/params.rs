@@ -0,0 +1,260 @@
+//! # Module Parameters
+//!
+//! Validation of the `insmod` parameters, kept out of `module/` so every
+//! reject is unit-tested on the host.
+//!
+//! ## Design Principles
+//!
+//! 1. **Refuse, Don't Guess**: A value outside its range fails module init
+//!    with `EINVAL` and a message naming the parameter, instead of being
+//!    clamped to something the operator did not ask for.
+//! 2. **Same Limits as sysfs**: `apply` goes through `KvCache::set_tunable`,
+//!    so a load-time `kv_max_entries` or `kv_max_bytes` obeys exactly the
+//!    range a later write to `/sys/kernel/hybridkv` does, and both adjust
+//!    the same limits.
+//! 3. **Plain Integers**: Rust module parameters are integers, so
+//!    `kv_max_bytes` takes a byte count (`67108864`, not `64M`).
+
+use core::fmt;
+use core::ops::DerefMut;
+
+use crate::abi::{Limits, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use crate::cache::{KvCache, StatCounters, Tunable};
+use crate::table::{Bucket, DEFAULT_CAPACITY, EntryAlloc, MAX_CAPACITY, MIN_CAPACITY};
+
+/// Parameter values as given to `insmod`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadParams {
+    /// `kv_max_entries`: most entries the cache holds, `1..=MAX_CAPACITY`.
+    pub max_entries: u32,
+    /// `kv_max_bytes`: memory budget, or 0 to leave the entry count as the
+    /// only limit.
+    pub max_bytes: u64,
+    /// `kv_max_key_size`: largest key accepted, `1..=MAX_KEY_SIZE`.
+    pub max_key_size: u32,
+    /// `kv_max_value_size`: largest value accepted, `1..=MAX_VALUE_SIZE`.
+    pub max_value_size: u32,
+}
+
+/// The parameter that failed validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamError {
+    MaxEntries,
+    MaxBytes,
+    MaxKeySize,
+    MaxValueSize,
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::MaxEntries => write!(f, "kv_max_entries must be 1..={MAX_CAPACITY}"),
+            ParamError::MaxBytes => write!(
+                f,
+                "kv_max_bytes must fit one entry of the maximum key and value size \
+                 and at most a full table, or be 0"
+            ),
+            ParamError::MaxKeySize => write!(f, "kv_max_key_size must be 1..={MAX_KEY_SIZE}"),
+            ParamError::MaxValueSize => {
+                write!(f, "kv_max_value_size must be 1..={MAX_VALUE_SIZE}")
+            }
+        }
+    }
+}
+
+impl LoadParams {
+    /// Hash table buckets to allocate: `max_entries`, raised to
+    /// `MIN_CAPACITY` so small tables still probe well.
+    pub fn capacity(&self) -> Result<usize, ParamError> {
+        match self.max_entries as usize {
+            0 => Err(ParamError::MaxEntries),
+            n if n > MAX_CAPACITY => Err(ParamError::MaxEntries),
+            n => Ok(n.max(MIN_CAPACITY)),
+        }
+    }
+
+    /// Key and value limits, which HELLO reports to user space.
+    pub fn limits(&self) -> Result<Limits, ParamError> {
+        let max_key = match self.max_key_size as usize {
+            n @ 1..=MAX_KEY_SIZE => n as u16,
+            _ => return Err(ParamError::MaxKeySize),
+        };
+        let max_value = match self.max_value_size as usize {
+            n @ 1..=MAX_VALUE_SIZE => n as u16,
+            _ => return Err(ParamError::MaxValueSize),
+        };
+        Ok(Limits { max_key, max_value })
+    }
+
+    /// Applies every limit to a fresh cache of `capacity()` buckets.
+    ///
+    /// Size limits go first, since they set the smallest valid budget.
+    pub fn apply<S, A, C>(&self, cache: &mut KvCache<S, A, C>) -> Result<(), ParamError>
+    where
+        A: EntryAlloc,
+        S: DerefMut<Target = [Bucket<A::Entry>]>,
+        C: StatCounters,
+    {
+        cache.set_size_limits(self.limits()?);
+        cache
+            .set_tunable(Tunable::MaxEntries, self.max_entries.into())
+            .map_err(|_| ParamError::MaxEntries)?;
+        if self.max_bytes != 0 {
+            cache
+                .set_tunable(Tunable::MaxBytes, self.max_bytes)
+                .map_err(|_| ParamError::MaxBytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for LoadParams {
+    /// The module parameter defaults.
+    fn default() -> Self {
+        LoadParams {
+            max_entries: DEFAULT_CAPACITY as u32,
+            max_bytes: 0,
+            max_key_size: MAX_KEY_SIZE as u32,
+            max_value_size: MAX_VALUE_SIZE as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::format;
+    use std::vec;
+
+    use super::*;
+    use crate::abi::{MODULE_LIMITS, PromoteResponse};
+    use crate::cache::{AtomicCounters, TTL_INFINITE};
+    use crate::testing::{HeapAlloc, HeapEntry, buckets};
+
+    type TestCache = KvCache<Box<[Bucket<HeapEntry>]>, HeapAlloc>;
+
+    fn load(params: &LoadParams) -> Result<TestCache, ParamError> {
+        let capacity = params.capacity()?;
+        let mut cache = KvCache::new(
+            buckets(capacity),
+            HeapAlloc::default(),
+            AtomicCounters::default(),
+        );
+        params.apply(&mut cache)?;
+        Ok(cache)
+    }
+
+    #[test]
+    fn defaults_load_with_the_module_limits() {
+        let cache = load(&LoadParams::default()).unwrap();
+        assert_eq!(cache.limits(), MODULE_LIMITS);
+        assert_eq!(cache.capacity(), DEFAULT_CAPACITY);
+        assert_eq!(cache.max_entries(), DEFAULT_CAPACITY);
+    }
+
+    #[test]
+    fn limits_apply_to_promotes_and_the_budget() {
+        let params = LoadParams {
+            max_entries: 100,
+            max_bytes: 64 << 10,
+            max_key_size: 32,
+            max_value_size: 512,
+        };
+        let mut cache = load(&params).unwrap();
+        assert_eq!(
+            cache.limits(),
+            Limits {
+                max_key: 32,
+                max_value: 512
+            }
+        );
+        assert_eq!((cache.capacity(), cache.max_entries()), (100, 100));
+        assert_eq!(cache.stats().max_bytes, 64 << 10);
+
+        let mut out = PromoteResponse::new();
+        let status = cache.promote(vec![b'k'; 33], vec![0; 1], 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(status, crate::abi::ERR_KEY_TOO_LONG);
+        let status = cache.promote(vec![b'k'; 32], vec![0; 513], 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(status, crate::abi::ERR_VALUE_TOO_LONG);
+        let status = cache.promote(vec![b'k'; 32], vec![0; 512], 1, TTL_INFINITE, 0, &mut out);
+        assert_eq!(status, crate::abi::STATUS_OK);
+
+        // A small table still gets enough buckets to probe.
+        let small = LoadParams {
+            max_entries: 3,
+            max_bytes: 0,
+            ..params
+        };
+        let cache = load(&small).unwrap();
+        assert_eq!((cache.capacity(), cache.max_entries()), (MIN_CAPACITY, 3));
+    }
+
+    #[test]
+    fn out_of_range_params_are_rejected() {
+        let ok = LoadParams::default();
+        let cases = [
+            (
+                LoadParams {
+                    max_entries: 0,
+                    ..ok
+                },
+                ParamError::MaxEntries,
+            ),
+            (
+                LoadParams {
+                    max_entries: MAX_CAPACITY as u32 + 1,
+                    ..ok
+                },
+                ParamError::MaxEntries,
+            ),
+            (
+                LoadParams {
+                    max_key_size: 0,
+                    ..ok
+                },
+                ParamError::MaxKeySize,
+            ),
+            (
+                LoadParams {
+                    max_key_size: MAX_KEY_SIZE as u32 + 1,
+                    ..ok
+                },
+                ParamError::MaxKeySize,
+            ),
+            (
+                LoadParams {
+                    max_value_size: 0,
+                    ..ok
+                },
+                ParamError::MaxValueSize,
+            ),
+            (
+                LoadParams {
+                    max_value_size: MAX_VALUE_SIZE as u32 + 1,
+                    ..ok
+                },
+                ParamError::MaxValueSize,
+            ),
+            // Too small for one entry at the size limits, and beyond a full
+            // table.
+            (
+                LoadParams {
+                    max_bytes: 64,
+                    ..ok
+                },
+                ParamError::MaxBytes,
+            ),
+            (
+                LoadParams {
+                    max_bytes: u64::MAX,
+                    ..ok
+                },
+                ParamError::MaxBytes,
+            ),
+        ];
+        for (params, expected) in cases {
+            assert_eq!(load(&params).err(), Some(expected), "{params:?}");
+            assert!(format!("{expected}").starts_with("kv_"));
+        }
+    }
+}
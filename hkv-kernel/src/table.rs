@@ -0,0 +1,914 @@
+//! # Open-Addressing Hash Table
+//!
+//! Fixed-capacity key/value table used by the kernel cache.
+//!
+//! ## Design Principles
+//!
+//! 1. **Pluggable Entry Memory**: Buckets are provided by the caller
+//!    (allocated at module init) and hold pointers; each entry comes from an
+//!    `EntryAlloc` (a `kmem_cache` in the module) and is freed by dropping it,
+//!    so memory scales with live entries rather than bucket count.
+//! 2. **Keys and Values Sized to Fit**: Key and value bytes live in owned
+//!    buffers of exactly their length (`EntryAlloc::Bytes`, a `KVec` in the
+//!    module) that the caller allocates before taking the cache lock, so
+//!    the slab object is only a small header. Keys are binary-safe byte
+//!    strings compared by content; lookups hash and compare the caller's
+//!    borrowed bytes without allocating. Replacing, removing or evicting an
+//!    entry drops its buffers.
+//! 3. **Linear Probing**: Collisions walk to the next bucket, keeping probes
+//!    sequential and cache-friendly.
+//! 4. **Backward-Shift Deletion**: A delete pulls later entries of the same
+//!    probe run back into the hole instead of leaving a tombstone, so every
+//!    free bucket is `Empty` and a miss stops at the end of its run no
+//!    matter how much churn the table has seen.
+//! 5. **Expired Entries Make Room**: When no empty slot is left,
+//!    an insert takes over the first expired entry on its probe path and
+//!    reports the displaced key; live entries are never evicted.
+//! 6. **Recency Order**: Live entries are threaded on a doubly linked list
+//!    of bucket indices, least recently used first. A promote makes an entry
+//!    the newest; a read only sets the entry's atomic `referenced` bit,
+//!    because reads hold a shared lock and must not relink the list.
+//!    `evict_lru` then runs the CLOCK (second chance) algorithm from the
+//!    oldest end under the write lock: a referenced entry has its bit
+//!    cleared and moves to the newest end, the first unreferenced one is
+//!    evicted. When a delete shifts an entry to another bucket, its list
+//!    neighbours are repointed at the new index.
+//! 7. **Prime Default**: `DEFAULT_CAPACITY` is prime so `hash % capacity`
+//!    spreads keys evenly even when hashes share low bits. Load-time
+//!    overrides are validated in `params` and raised to `MIN_CAPACITY` but
+//!    otherwise used as given.
+//!
+//! ## Layout
+//!
+//! ```text
+//! KvHashTable
+//!   ├── buckets: [Bucket; capacity]
+//!   │     └── Empty | Occupied(entry)
+//!   │                                  └── KvEntry { key, value, version,
+//!   │                                        expires_ns, referenced,
+//!   │                                        prev, next }
+//!   │                                               │     └── [u8; value len]
+//!   │                                               └── [u8; key len]
+//!   ├── alloc: EntryAlloc
+//!   ├── size: live entry count
+//!   ├── data_bytes: total length of the live keys and values
+//!   └── head, tail: least and most recently used entry
+//! ```
+
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::abi::{
+    ERR_CAPACITY_EXCEEDED, ERR_KEY_TOO_LONG, ERR_OUT_OF_MEMORY, ERR_VALUE_TOO_LONG, MAX_KEY_SIZE,
+    MAX_VALUE_SIZE, RawKey,
+};
+
+/// Default bucket count (prime).
+pub const DEFAULT_CAPACITY: usize = 4093;
+
+/// Smallest accepted bucket count.
+pub const MIN_CAPACITY: usize = 16;
+
+/// Largest accepted bucket count (about 1.3 GiB of buckets).
+pub const MAX_CAPACITY: usize = 1_048_576;
+
+/// End of the promotion-order list.
+const NIL: u32 = u32::MAX;
+
+/// One cache entry, allocated individually through `EntryAlloc`; `B` is the
+/// allocator's byte buffer.
+pub struct KvEntry<B> {
+    /// Key bytes; `None` only before the entry is first filled.
+    pub key: Option<B>,
+    /// Value bytes; `None` only before the entry is first filled.
+    pub value: Option<B>,
+    pub version: u64,
+    /// Absolute expiry in nanoseconds; 0 means no expiry.
+    pub expires_ns: i64,
+    /// Read since `evict_lru` last passed the entry.
+    referenced: AtomicBool,
+    /// Bucket of the previous (less recently used) entry, or `NIL`.
+    prev: u32,
+    /// Bucket of the next (more recently used) entry, or `NIL`.
+    next: u32,
+}
+
+impl<B> KvEntry<B> {
+    /// Entry with no key or value.
+    pub const fn empty() -> Self {
+        KvEntry {
+            key: None,
+            value: None,
+            version: 0,
+            expires_ns: 0,
+            referenced: AtomicBool::new(false),
+            prev: NIL,
+            next: NIL,
+        }
+    }
+
+    /// Records a read for `evict_lru`; safe under a shared lock.
+    #[inline]
+    pub fn mark_referenced(&self) {
+        // Skip the store when already set to keep hot entries' cache lines
+        // shared between CPUs.
+        if !self.referenced.load(Ordering::Relaxed) {
+            self.referenced.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns true when the entry has a deadline at or before `now_ns`.
+    #[inline]
+    pub fn is_expired(&self, now_ns: i64) -> bool {
+        self.expires_ns > 0 && now_ns >= self.expires_ns
+    }
+}
+
+impl<B: Deref<Target = [u8]>> KvEntry<B> {
+    /// Returns the key bytes.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        self.key.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the value bytes.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        self.value.as_deref().unwrap_or_default()
+    }
+}
+
+/// Source of `KvEntry` memory.
+///
+/// Dropping an `Entry` returns it to the allocator, so the allocator must
+/// outlive every entry it hands out.
+pub trait EntryAlloc {
+    /// Owned key or value bytes, allocated by the caller of
+    /// `KvHashTable::insert`.
+    type Bytes: Deref<Target = [u8]>;
+
+    /// Owning pointer to one entry.
+    type Entry: DerefMut<Target = KvEntry<Self::Bytes>>;
+
+    /// Allocates an entry, or returns `None` when memory is exhausted.
+    fn alloc(&self) -> Option<Self::Entry>;
+}
+
+/// One table slot.
+pub enum Bucket<E> {
+    /// Free; ends a probe chain.
+    Empty,
+    /// Live entry.
+    Occupied(E),
+}
+
+/// How a successful insert placed its entry.
+// Returned by value once per insert; the no_std core cannot box the key.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inserted {
+    /// The key was new and took a free slot.
+    New,
+    /// The key was present; its value was overwritten.
+    Replaced,
+    /// The table was full; an expired entry with this key was displaced.
+    Evicted(RawKey),
+}
+
+/// Linear-probing hash table over caller-provided buckets.
+///
+/// `buckets` is declared before `alloc` so entries are freed before the
+/// allocator is dropped.
+pub struct KvHashTable<S, A> {
+    buckets: S,
+    alloc: A,
+    size: usize,
+    /// Sum of the live entries' key and value lengths.
+    data_bytes: usize,
+    /// Oldest entry in promotion order.
+    head: u32,
+    /// Newest entry in promotion order.
+    tail: u32,
+}
+
+impl<S, A> KvHashTable<S, A>
+where
+    A: EntryAlloc,
+    S: DerefMut<Target = [Bucket<A::Entry>]>,
+{
+    /// Wraps `buckets`, resetting every slot to empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buckets` is empty or has more than `MAX_CAPACITY` slots.
+    pub fn new(mut buckets: S, alloc: A) -> Self {
+        assert!(!buckets.is_empty(), "hash table needs at least one bucket");
+        assert!(buckets.len() <= MAX_CAPACITY, "too many buckets");
+        buckets
+            .iter_mut()
+            .for_each(|bucket| *bucket = Bucket::Empty);
+        KvHashTable {
+            buckets,
+            alloc,
+            size: 0,
+            data_bytes: 0,
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    /// Number of live entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true when no entries are stored.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Number of buckets.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Total length of the live entries' keys and values.
+    #[inline]
+    pub fn data_bytes(&self) -> usize {
+        self.data_bytes
+    }
+
+    /// Looks up a live entry.
+    pub fn get(&self, key: &[u8]) -> Option<&KvEntry<A::Bytes>> {
+        match &self.buckets[self.find(key)?] {
+            Bucket::Occupied(entry) => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Live entries in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = &KvEntry<A::Bytes>> {
+        self.buckets.iter().filter_map(|bucket| match bucket {
+            Bucket::Occupied(entry) => Some(&**entry),
+            _ => None,
+        })
+    }
+
+    /// Inserts or replaces an entry, displacing an entry already expired at
+    /// `now_ns` when the table is full. The entry takes ownership of `key`
+    /// and `value`; the buffers it held before are freed.
+    ///
+    /// Returns an `HkvError` status code (`CapacityExceeded`, `OutOfMemory`,
+    /// `KeyTooLong`, `ValueTooLong`) on failure, dropping `value`.
+    pub fn insert(
+        &mut self,
+        key: A::Bytes,
+        value: A::Bytes,
+        version: u64,
+        expires_ns: i64,
+        now_ns: i64,
+    ) -> Result<Inserted, u16> {
+        check_sizes(&key, &value)?;
+
+        let capacity = self.capacity();
+        let start = bucket_index(&key, capacity);
+        let mut expired = None;
+        let mut target = None;
+
+        for probe in 0..capacity {
+            let idx = (start + probe) % capacity;
+            match &self.buckets[idx] {
+                Bucket::Occupied(entry) => {
+                    if entry.key() == &*key {
+                        target = Some(idx);
+                        break;
+                    }
+                    if entry.is_expired(now_ns) {
+                        expired.get_or_insert(idx);
+                    }
+                }
+                Bucket::Empty => {
+                    target = Some(idx);
+                    break;
+                }
+            }
+        }
+
+        let (idx, inserted) = match (target, expired) {
+            (Some(idx), _) => match &self.buckets[idx] {
+                Bucket::Occupied(_) => (idx, Inserted::Replaced),
+                Bucket::Empty => (idx, Inserted::New),
+            },
+            (None, Some(idx)) => {
+                let Bucket::Occupied(entry) = &self.buckets[idx] else {
+                    unreachable!("expired slot is occupied");
+                };
+                let mut evicted = RawKey::EMPTY;
+                evicted.fill(entry.key());
+                (idx, Inserted::Evicted(evicted))
+            }
+            (None, None) => return Err(ERR_CAPACITY_EXCEEDED),
+        };
+
+        if inserted == Inserted::New {
+            let entry = self.alloc.alloc().ok_or(ERR_OUT_OF_MEMORY)?;
+            self.buckets[idx] = Bucket::Occupied(entry);
+            self.size += 1;
+        } else {
+            self.unlink(idx);
+        }
+        // Every promote, including a replace, makes the entry the newest.
+        self.link_newest(idx);
+        let Bucket::Occupied(entry) = &mut self.buckets[idx] else {
+            unreachable!("bucket was just filled");
+        };
+        // An evicted entry's header is reused for the new key.
+        self.data_bytes =
+            self.data_bytes + key.len() + value.len() - entry.key().len() - entry.value().len();
+        entry.key = Some(key);
+        entry.value = Some(value);
+        entry.version = version;
+        entry.expires_ns = expires_ns;
+        *entry.referenced.get_mut() = false;
+        Ok(inserted)
+    }
+
+    /// Removes an entry. Returns true if it existed.
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        match self.find(key) {
+            Some(idx) => {
+                self.remove_at(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every entry whose deadline is at or before `now_ns`.
+    ///
+    /// Returns the number of evicted entries.
+    pub fn evict_expired(&mut self, now_ns: i64) -> usize {
+        self.evict_expired_range(0, self.capacity(), now_ns)
+    }
+
+    /// Like `evict_expired`, but only looks at `count` buckets from `start`,
+    /// so a sweep can be split into bounded steps. The range is clipped to
+    /// the table.
+    pub fn evict_expired_range(&mut self, start: usize, count: usize, now_ns: i64) -> usize {
+        self.remove_range_where(start, count, |entry| entry.is_expired(now_ns))
+    }
+
+    /// Removes every entry in `count` buckets from `start`, so the table can
+    /// be emptied in bounded steps. Returns the number of removed entries.
+    pub fn remove_range(&mut self, start: usize, count: usize) -> usize {
+        self.remove_range_where(start, count, |_| true)
+    }
+
+    /// Evicts the least recently used entry other than `keep`, giving
+    /// entries read since the last pass a second chance.
+    ///
+    /// Returns the evicted key, or `None` when nothing but `keep` is left.
+    /// `keep` is an `Option` because the empty key is a valid key.
+    pub fn evict_lru(&mut self, keep: Option<&[u8]>) -> Option<RawKey> {
+        // The first lap clears every bit, so the second finds a victim.
+        for _ in 0..=2 * self.size {
+            let idx = self.head;
+            if idx == NIL {
+                return None;
+            }
+            let entry = self.entry_mut(idx);
+            if Some(entry.key()) == keep || core::mem::take(entry.referenced.get_mut()) {
+                self.unlink(idx as usize);
+                self.link_newest(idx as usize);
+                continue;
+            }
+            let mut evicted = RawKey::EMPTY;
+            evicted.fill(entry.key());
+            self.remove_at(idx as usize);
+            return Some(evicted);
+        }
+        None
+    }
+
+    /// Frees every entry.
+    pub fn clear(&mut self) {
+        self.buckets
+            .iter_mut()
+            .for_each(|bucket| *bucket = Bucket::Empty);
+        self.size = 0;
+        self.data_bytes = 0;
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// Removes the entries matching `pred` in `count` buckets from `start`,
+    /// clipped to the table.
+    fn remove_range_where(
+        &mut self,
+        start: usize,
+        count: usize,
+        pred: impl Fn(&KvEntry<A::Bytes>) -> bool,
+    ) -> usize {
+        let end = start.saturating_add(count).min(self.capacity());
+        let mut removed = 0;
+        let mut idx = start.min(end);
+        while idx < end {
+            if matches!(&self.buckets[idx], Bucket::Occupied(entry) if pred(entry)) {
+                // A shift only moves entries forward in the scan, or into
+                // `idx` itself, which is looked at again.
+                self.remove_at(idx);
+                removed += 1;
+            } else {
+                idx += 1;
+            }
+        }
+        removed
+    }
+
+    /// Frees the entry at `idx`, then shifts later entries of its probe run
+    /// back so no lookup needs to walk past the freed bucket.
+    fn remove_at(&mut self, idx: usize) {
+        self.unlink(idx);
+        let entry = self.entry_mut(idx as u32);
+        self.data_bytes -= entry.key().len() + entry.value().len();
+        self.buckets[idx] = Bucket::Empty;
+        self.size -= 1;
+
+        let capacity = self.capacity();
+        let mut hole = idx;
+        let mut next = (idx + 1) % capacity;
+        while next != idx {
+            let Bucket::Occupied(entry) = &self.buckets[next] else {
+                break;
+            };
+            // The entry may fill the hole if the hole lies between its home
+            // bucket and where it sits now.
+            let home = bucket_index(entry.key(), capacity);
+            let from_home = (next + capacity - home) % capacity;
+            let from_hole = (next + capacity - hole) % capacity;
+            if from_home >= from_hole {
+                self.move_entry(next, hole);
+                hole = next;
+            }
+            next = (next + 1) % capacity;
+        }
+    }
+
+    /// Moves the entry at `from` into the empty bucket `to`, keeping the
+    /// recency list pointing at it.
+    fn move_entry(&mut self, from: usize, to: usize) {
+        self.buckets.swap(from, to);
+        let entry = self.entry_mut(to as u32);
+        let (prev, next) = (entry.prev, entry.next);
+        match prev {
+            NIL => self.head = to as u32,
+            prev => self.entry_mut(prev).next = to as u32,
+        }
+        match next {
+            NIL => self.tail = to as u32,
+            next => self.entry_mut(next).prev = to as u32,
+        }
+    }
+
+    /// Returns the live entry at `idx`.
+    fn entry_mut(&mut self, idx: u32) -> &mut KvEntry<A::Bytes> {
+        match &mut self.buckets[idx as usize] {
+            Bucket::Occupied(entry) => entry,
+            _ => unreachable!("recency list points at a free bucket"),
+        }
+    }
+
+    /// Appends the entry at `idx` as the most recently used.
+    fn link_newest(&mut self, idx: usize) {
+        let idx = idx as u32;
+        let tail = self.tail;
+        let entry = self.entry_mut(idx);
+        entry.prev = tail;
+        entry.next = NIL;
+        match tail {
+            NIL => self.head = idx,
+            tail => self.entry_mut(tail).next = idx,
+        }
+        self.tail = idx;
+    }
+
+    /// Takes the entry at `idx` off the recency list.
+    fn unlink(&mut self, idx: usize) {
+        let entry = self.entry_mut(idx as u32);
+        let (prev, next) = (entry.prev, entry.next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.entry_mut(prev).next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.entry_mut(next).prev = prev,
+        }
+    }
+
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        if key.len() > MAX_KEY_SIZE {
+            return None;
+        }
+
+        let capacity = self.capacity();
+        let start = bucket_index(key, capacity);
+        for probe in 0..capacity {
+            let idx = (start + probe) % capacity;
+            match &self.buckets[idx] {
+                Bucket::Occupied(entry) if entry.key() == key => return Some(idx),
+                Bucket::Empty => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Returns the `HkvError` status for a key or value over the size limits.
+pub fn check_sizes(key: &[u8], value: &[u8]) -> Result<(), u16> {
+    if key.len() > MAX_KEY_SIZE {
+        return Err(ERR_KEY_TOO_LONG);
+    }
+    if value.len() > MAX_VALUE_SIZE {
+        return Err(ERR_VALUE_TOO_LONG);
+    }
+    Ok(())
+}
+
+/// FNV-1a: cheap, deterministic, and allocation-free.
+pub fn hash_key(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in key {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[inline]
+fn bucket_index(key: &[u8], capacity: usize) -> usize {
+    (hash_key(key) % capacity as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::format;
+    use std::vec;
+
+    use super::*;
+    use crate::testing::{HeapAlloc, TestTable, buckets};
+
+    fn table(capacity: usize) -> TestTable {
+        KvHashTable::new(buckets(capacity), HeapAlloc::default())
+    }
+
+    #[test]
+    fn capacity_is_prime() {
+        assert!(
+            (2..DEFAULT_CAPACITY)
+                .take_while(|d| d * d <= DEFAULT_CAPACITY)
+                .all(|d| !DEFAULT_CAPACITY.is_multiple_of(d))
+        );
+    }
+
+    #[test]
+    fn insert_get_update_remove() {
+        let mut table = table(7);
+
+        table.insert(b"a".to_vec(), b"1".to_vec(), 1, 0, 0).unwrap();
+        table
+            .insert(b"a".to_vec(), b"22".to_vec(), 2, 0, 0)
+            .unwrap();
+        assert_eq!(table.len(), 1);
+
+        let entry = table.get(b"a").unwrap();
+        assert_eq!(entry.value(), b"22");
+        assert_eq!(entry.version, 2);
+
+        assert!(table.remove(b"a"));
+        assert!(!table.remove(b"a"));
+        assert!(table.get(b"a").is_none());
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn deletes_shift_later_entries_back() {
+        // One bucket per key forces every insert onto the same probe chain.
+        let mut table = table(3);
+        table.insert(b"x".to_vec(), b"1".to_vec(), 1, 0, 0).unwrap();
+        table.insert(b"y".to_vec(), b"2".to_vec(), 1, 0, 0).unwrap();
+        table.insert(b"z".to_vec(), b"3".to_vec(), 1, 0, 0).unwrap();
+
+        assert!(table.remove(b"x"));
+        assert_eq!(table.get(b"y").unwrap().value(), b"2");
+        assert_eq!(table.get(b"z").unwrap().value(), b"3");
+
+        // The freed bucket is reused, and the full table still updates in place.
+        table.insert(b"w".to_vec(), b"4".to_vec(), 1, 0, 0).unwrap();
+        table.insert(b"y".to_vec(), b"5".to_vec(), 2, 0, 0).unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get(b"y").unwrap().value(), b"5");
+    }
+
+    #[test]
+    fn full_table_reports_capacity_exceeded() {
+        let mut table = table(5);
+        for i in 0..5 {
+            table
+                .insert(format!("k{i}").into_bytes(), b"v".to_vec(), 1, 0, 0)
+                .unwrap();
+        }
+
+        assert_eq!(
+            table.insert(b"overflow".to_vec(), b"v".to_vec(), 1, 0, 0),
+            Err(ERR_CAPACITY_EXCEEDED)
+        );
+        assert!(table.get(b"overflow").is_none());
+
+        table.clear();
+        assert!(table.is_empty());
+        table
+            .insert(b"overflow".to_vec(), b"v".to_vec(), 1, 0, 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn full_table_displaces_the_first_expired_entry() {
+        let mut table = table(3);
+        assert_eq!(
+            table.insert(b"x".to_vec(), b"1".to_vec(), 1, 0, 0),
+            Ok(Inserted::New)
+        );
+        assert_eq!(
+            table.insert(b"y".to_vec(), b"2".to_vec(), 1, 100, 0),
+            Ok(Inserted::New)
+        );
+        assert_eq!(
+            table.insert(b"z".to_vec(), b"3".to_vec(), 1, 200, 0),
+            Ok(Inserted::New)
+        );
+        assert_eq!(
+            table.insert(b"x".to_vec(), b"4".to_vec(), 2, 0, 0),
+            Ok(Inserted::Replaced)
+        );
+
+        // Nothing has expired yet, so the full table rejects new keys.
+        assert_eq!(
+            table.insert(b"w".to_vec(), b"5".to_vec(), 1, 0, 99),
+            Err(ERR_CAPACITY_EXCEEDED)
+        );
+
+        let Ok(Inserted::Evicted(evicted)) = table.insert(b"w".to_vec(), b"5".to_vec(), 1, 0, 300)
+        else {
+            panic!("expected an eviction");
+        };
+        let evicted = evicted.as_bytes().unwrap();
+        assert!(evicted == b"y" || evicted == b"z");
+        assert!(table.get(evicted).is_none());
+        assert_eq!(table.get(b"w").unwrap().value(), b"5");
+        assert_eq!(table.get(b"x").unwrap().value(), b"4");
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn evict_lru_gives_read_entries_a_second_chance() {
+        let mut table = table(11);
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key.to_vec(), b"v".to_vec(), 1, 0, 0).unwrap();
+        }
+        // Re-promoting "a" makes it the newest; reading "b" marks it.
+        table.insert(b"a".to_vec(), b"v".to_vec(), 2, 0, 0).unwrap();
+        table.get(b"b").unwrap().mark_referenced();
+
+        assert_eq!(table.evict_lru(None).unwrap().as_bytes(), Some(&b"c"[..]));
+        // "b" moved behind "a", so "d" is next, then the kept "a" is skipped.
+        assert_eq!(table.evict_lru(None).unwrap().as_bytes(), Some(&b"d"[..]));
+        assert_eq!(
+            table.evict_lru(Some(&b"a"[..])).unwrap().as_bytes(),
+            Some(&b"b"[..])
+        );
+        assert!(table.evict_lru(Some(&b"a"[..])).is_none());
+        assert_eq!(table.len(), 1);
+
+        // Every entry referenced: one lap clears the bits, then evicts.
+        table.insert(b"e".to_vec(), b"v".to_vec(), 1, 0, 0).unwrap();
+        for key in [b"a", b"e"] {
+            table.get(key).unwrap().mark_referenced();
+        }
+        assert_eq!(table.evict_lru(None).unwrap().as_bytes(), Some(&b"a"[..]));
+        assert_eq!(table.evict_lru(None).unwrap().as_bytes(), Some(&b"e"[..]));
+        assert!(table.evict_lru(None).is_none());
+
+        // The empty key is a key like any other, not "keep nothing".
+        table.insert(Vec::new(), b"v".to_vec(), 1, 0, 0).unwrap();
+        assert!(table.evict_lru(Some(b"")).is_none());
+        assert_eq!(table.evict_lru(None).unwrap().as_bytes(), Some(&b""[..]));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn evict_expired_removes_only_due_entries() {
+        let mut table = table(11);
+        table
+            .insert(b"forever".to_vec(), b"v".to_vec(), 1, 0, 0)
+            .unwrap();
+        table
+            .insert(b"soon".to_vec(), b"v".to_vec(), 1, 1_000, 0)
+            .unwrap();
+        table
+            .insert(b"later".to_vec(), b"v".to_vec(), 1, 5_000, 0)
+            .unwrap();
+
+        assert!(!table.get(b"soon").unwrap().is_expired(999));
+        assert!(table.get(b"soon").unwrap().is_expired(1_000));
+
+        assert_eq!(table.evict_expired(1_000), 1);
+        assert!(table.get(b"soon").is_none());
+        assert_eq!(table.len(), 2);
+
+        assert_eq!(table.evict_expired(i64::MAX), 1);
+        assert!(table.get(b"forever").is_some());
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn oversized_inputs_are_rejected() {
+        let mut table = table(3);
+        let long_key = vec![b'k'; MAX_KEY_SIZE + 1];
+        let long_value = vec![b'v'; MAX_VALUE_SIZE + 1];
+
+        assert_eq!(
+            table.insert(long_key, b"v".to_vec(), 1, 0, 0),
+            Err(ERR_KEY_TOO_LONG)
+        );
+        assert_eq!(
+            table.insert(b"k".to_vec(), long_value, 1, 0, 0),
+            Err(ERR_VALUE_TOO_LONG)
+        );
+
+        let max_key = vec![b'k'; MAX_KEY_SIZE];
+        let max_value = vec![b'v'; MAX_VALUE_SIZE];
+        table
+            .insert(max_key.clone(), max_value.clone(), 1, 0, 0)
+            .unwrap();
+        assert_eq!(table.get(&max_key).unwrap().value(), max_value.as_slice());
+    }
+
+    #[test]
+    fn entries_are_allocated_on_insert_and_freed_on_remove() {
+        let alloc = HeapAlloc::default();
+        let mut table = KvHashTable::new(buckets(7), alloc.clone());
+
+        table.insert(b"a".to_vec(), b"1".to_vec(), 1, 0, 0).unwrap();
+        table.insert(b"a".to_vec(), b"2".to_vec(), 2, 0, 0).unwrap();
+        table
+            .insert(b"b".to_vec(), b"1".to_vec(), 1, 10, 0)
+            .unwrap();
+        assert_eq!(alloc.live(), 2);
+
+        table.remove(b"a");
+        assert_eq!(alloc.live(), 1);
+        table.evict_expired(10);
+        assert_eq!(alloc.live(), 0);
+
+        table.insert(b"c".to_vec(), b"1".to_vec(), 1, 0, 0).unwrap();
+        drop(table);
+        assert_eq!(alloc.live(), 0);
+    }
+
+    #[test]
+    fn keys_are_binary_safe_and_compared_by_content() {
+        let mut table = table(7);
+        for (key, value) in [(&b"a\0b"[..], b"1"), (b"a\0c", b"2"), (b"a", b"3")] {
+            table.insert(key.to_vec(), value.to_vec(), 1, 0, 0).unwrap();
+        }
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get(b"a\0b").unwrap().value(), b"1");
+        assert_eq!(table.get(b"a\0c").unwrap().value(), b"2");
+        assert_eq!(table.get(b"a").unwrap().value(), b"3");
+        assert!(table.get(b"a\0").is_none());
+        assert!(table.get(b"").is_none());
+
+        // A replace keeps one key buffer for the entry.
+        table
+            .insert(b"a\0b".to_vec(), b"4".to_vec(), 2, 0, 0)
+            .unwrap();
+        assert_eq!(table.get(b"a\0b").unwrap().key(), b"a\0b");
+        assert_eq!(table.data_bytes(), 3 + 3 + 1 + 3);
+    }
+
+    #[test]
+    fn data_bytes_follow_replace_remove_and_eviction() {
+        let mut table = table(3);
+        table
+            .insert(b"a".to_vec(), b"12345".to_vec(), 1, 0, 0)
+            .unwrap();
+        table
+            .insert(b"b".to_vec(), b"1".to_vec(), 1, 10, 0)
+            .unwrap();
+        assert_eq!(table.data_bytes(), 8);
+
+        // A shorter replacement frees the longer buffer.
+        table
+            .insert(b"a".to_vec(), b"12".to_vec(), 2, 0, 0)
+            .unwrap();
+        assert_eq!(table.get(b"a").unwrap().value(), b"12");
+        assert_eq!(table.data_bytes(), 5);
+
+        table
+            .insert(b"c".to_vec(), vec![b'v'; MAX_VALUE_SIZE], 1, 0, 0)
+            .unwrap();
+        assert_eq!(table.data_bytes(), 6 + MAX_VALUE_SIZE);
+        // The full table displaces the expired "b".
+        table
+            .insert(b"d".to_vec(), b"1234".to_vec(), 1, 0, 10)
+            .unwrap();
+        assert_eq!(table.data_bytes(), 9 + MAX_VALUE_SIZE);
+
+        assert!(table.remove(b"c"));
+        assert_eq!(table.data_bytes(), 8);
+        assert!(table.evict_lru(None).is_some());
+        assert_eq!(table.data_bytes(), 5);
+        table.clear();
+        assert_eq!(table.data_bytes(), 0);
+    }
+
+    /// Longest walk a miss can take: the longest run of buckets before an
+    /// `Empty` one.
+    fn longest_probe(table: &TestTable) -> usize {
+        let capacity = table.capacity();
+        (0..capacity)
+            .map(|start| {
+                (0..capacity)
+                    .take_while(|probe| {
+                        !matches!(table.buckets[(start + probe) % capacity], Bucket::Empty)
+                    })
+                    .count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn churn_keeps_probes_bounded() {
+        let mut table = table(64);
+        // Keep 16 keys live while 10k others come and go through every
+        // kind of removal.
+        for round in 0..10_000u32 {
+            let key = round.to_be_bytes().to_vec();
+            let expires_ns = if round % 3 == 0 { 1 } else { 0 };
+            table.insert(key, b"v".to_vec(), 1, expires_ns, 0).unwrap();
+            if table.len() > 16 {
+                match round % 4 {
+                    0 if table.remove(&(round - 16).to_be_bytes()) => {}
+                    0 | 1 => {
+                        table.evict_lru(None).unwrap();
+                    }
+                    2 => {
+                        table.evict_expired(2);
+                    }
+                    _ => {
+                        table.remove_range(round as usize % 64, 8);
+                    }
+                }
+            }
+            assert!(longest_probe(&table) <= table.len(), "round {round}");
+        }
+        for key in 0..10_000u32 {
+            if let Some(entry) = table.get(&key.to_be_bytes()) {
+                assert_eq!(entry.key(), key.to_be_bytes());
+            }
+        }
+        // The recency list still reaches every live entry.
+        let live = table.len();
+        assert_eq!(
+            (0..live)
+                .filter(|_| table.evict_lru(None).is_some())
+                .count(),
+            live
+        );
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn allocation_failure_reports_out_of_memory() {
+        let alloc = HeapAlloc::with_limit(1);
+        let mut table = KvHashTable::new(buckets(7), alloc.clone());
+
+        table.insert(b"a".to_vec(), b"1".to_vec(), 1, 0, 0).unwrap();
+        assert_eq!(
+            table.insert(b"b".to_vec(), b"1".to_vec(), 1, 0, 0),
+            Err(ERR_OUT_OF_MEMORY)
+        );
+        assert!(table.get(b"b").is_none());
+        assert_eq!(table.len(), 1);
+
+        // Updating an existing key needs no new entry.
+        table.insert(b"a".to_vec(), b"2".to_vec(), 2, 0, 0).unwrap();
+        assert_eq!(table.get(b"a").unwrap().value(), b"2");
+    }
+}
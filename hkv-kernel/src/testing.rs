@@ -0,0 +1,118 @@
+//! Host-side helpers shared by the unit tests.
+
+use std::boxed::Box;
+use std::hash::{BuildHasher, RandomState};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::vec::Vec;
+
+use crate::cache::{AtomicCounters, Counter, StatCounters};
+use crate::table::{Bucket, EntryAlloc, KvEntry, KvHashTable};
+
+/// Table over boxed buckets and heap entries.
+pub type TestTable = KvHashTable<Box<[Bucket<HeapEntry>]>, HeapAlloc>;
+
+/// Returns `capacity` empty buckets.
+pub fn buckets(capacity: usize) -> Box<[Bucket<HeapEntry>]> {
+    (0..capacity).map(|_| Bucket::Empty).collect()
+}
+
+/// `EntryAlloc` backed by `Box` that counts live entries; keys and values are `Vec`s.
+///
+/// Clones share the counter, so a test can keep one to observe frees.
+#[derive(Clone, Default)]
+pub struct HeapAlloc {
+    live: Arc<AtomicUsize>,
+    limit: Option<usize>,
+}
+
+impl HeapAlloc {
+    /// Fails allocations once `limit` entries are live.
+    pub fn with_limit(limit: usize) -> Self {
+        HeapAlloc {
+            live: Arc::default(),
+            limit: Some(limit),
+        }
+    }
+
+    /// Number of entries allocated and not yet dropped.
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+}
+
+impl EntryAlloc for HeapAlloc {
+    type Bytes = Vec<u8>;
+    type Entry = HeapEntry;
+
+    fn alloc(&self) -> Option<HeapEntry> {
+        if self.limit.is_some_and(|limit| self.live() >= limit) {
+            return None;
+        }
+        self.live.fetch_add(1, Ordering::Relaxed);
+        Some(HeapEntry {
+            entry: Box::new(KvEntry::empty()),
+            live: Arc::clone(&self.live),
+        })
+    }
+}
+
+/// Entry handed out by `HeapAlloc`.
+pub struct HeapEntry {
+    entry: Box<KvEntry<Vec<u8>>>,
+    live: Arc<AtomicUsize>,
+}
+
+impl Deref for HeapEntry {
+    type Target = KvEntry<Vec<u8>>;
+
+    fn deref(&self) -> &KvEntry<Vec<u8>> {
+        &self.entry
+    }
+}
+
+impl DerefMut for HeapEntry {
+    fn deref_mut(&mut self) -> &mut KvEntry<Vec<u8>> {
+        &mut self.entry
+    }
+}
+
+impl Drop for HeapEntry {
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// `StatCounters` with one slot per thread (by id hash), standing in for the
+/// module's per-CPU counters: writers spread over slots, `total` sums them.
+pub struct ShardedCounters {
+    slots: Box<[AtomicCounters]>,
+    hasher: RandomState,
+}
+
+impl ShardedCounters {
+    /// Creates `slots` independent counter sets.
+    pub fn new(slots: usize) -> Self {
+        ShardedCounters {
+            slots: (0..slots).map(|_| AtomicCounters::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn local(&self) -> &AtomicCounters {
+        let hash = self.hasher.hash_one(thread::current().id());
+        &self.slots[hash as usize % self.slots.len()]
+    }
+}
+
+impl StatCounters for ShardedCounters {
+    fn add(&self, counter: Counter, n: u64) {
+        self.local().add(counter, n);
+    }
+
+    fn total(&self, counter: Counter) -> u64 {
+        self.slots.iter().map(|slot| slot.total(counter)).sum()
+    }
+}
@@ -0,0 +1,377 @@
+//! Exercises a loaded `kv_module` through `/dev/hybridkv`.
+//!
+//! Skips when the device node is absent (module not loaded). Tests hold
+//! `SERIAL` because the shrinker, FLUSH and debugfs `clear` tests empty the
+//! shared cache.
+
+use std::fs::{self, File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use hkv_common::{
+    CacheStats, Clock, DEVICE_PATH, DemoteRequest, DemoteResponse, FlushRequest, FlushResponse,
+    HkvError, InvalidateRequest, InvalidateResponse, Key, PromoteOutcome, PromoteRequest,
+    PromoteResponse, ReadRequest, ReadResponse, RequestFlags, STATUS_OK, StatsRequest,
+    StatsResponse, SystemClock, TtlAfter, TtlAt, Value, Version,
+};
+use hkv_kernel::dispatch::{
+    HKV_IOC_DEMOTE, HKV_IOC_FLUSH, HKV_IOC_INVALIDATE, HKV_IOC_PROMOTE, HKV_IOC_READ, HKV_IOC_STATS,
+};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn open_device() -> Option<File> {
+    match OpenOptions::new().read(true).write(true).open(DEVICE_PATH) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            eprintln!("skipping: cannot open {DEVICE_PATH}: {err}");
+            None
+        }
+    }
+}
+
+/// Issues an `_IOWR` ioctl whose response overwrites the request in place.
+///
+/// A non-OK status also fails the ioctl; the errno is mapped back with
+/// `HkvError::from_errno`.
+fn transact<Req, Resp>(device: &File, cmd: u32, request: Req) -> (Resp, Option<HkvError>) {
+    let words = size_of::<Req>().max(size_of::<Resp>()).div_ceil(8);
+    let mut buf = vec![0u64; words];
+    let ptr = buf.as_mut_ptr().cast::<u8>();
+    // SAFETY: `buf` is 8-byte aligned and large enough for either type; both
+    // are plain `repr(C)` data, so reading the response bytes back is sound.
+    unsafe {
+        std::ptr::write(ptr.cast::<Req>(), request);
+        let ret = libc::ioctl(device.as_raw_fd(), cmd as _, ptr);
+        let err = (ret != 0).then(|| {
+            let os = std::io::Error::last_os_error();
+            HkvError::from_errno(os.raw_os_error().unwrap_or(0))
+                .unwrap_or_else(|| panic!("ioctl failed: {os}"))
+        });
+        (std::ptr::read(ptr.cast::<Resp>()), err)
+    }
+}
+
+fn read_status(device: &File, key: &[u8]) -> u16 {
+    read_status_with(device, key, RequestFlags::NONE)
+}
+
+fn read_status_with(device: &File, key: &[u8], flags: RequestFlags) -> u16 {
+    let request = ReadRequest::new(Key::new(key).unwrap()).with_flags(flags);
+    let (response, err): (ReadResponse, _) = transact(device, HKV_IOC_READ, request);
+    assert_eq!(err.map_or(STATUS_OK, HkvError::code), response.status);
+    response.status
+}
+
+fn promote(device: &File, key: &[u8], ttl: TtlAt) {
+    let request = PromoteRequest::new(
+        Key::new(key).unwrap(),
+        Value::new(b"value").unwrap(),
+        Version::new(1),
+        ttl,
+    );
+    let (response, err): (PromoteResponse, _) = transact(device, HKV_IOC_PROMOTE, request);
+    assert_eq!(err, None);
+    assert_eq!(response.status, STATUS_OK);
+    assert!(response.outcome().unwrap().is_stored());
+}
+
+fn stats(device: &File) -> CacheStats {
+    let (response, err): (StatsResponse, _) = transact(device, HKV_IOC_STATS, StatsRequest::new());
+    assert_eq!(err, None);
+    assert_eq!(response.status, STATUS_OK);
+    response.stats
+}
+
+#[test]
+fn promoted_key_expires_after_ttl() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let before = stats(&device);
+
+    promote(
+        &device,
+        b"ttl-test",
+        TtlAfter::from_secs(1).deadline(&SystemClock),
+    );
+    assert_eq!(read_status(&device, b"ttl-test"), STATUS_OK);
+
+    thread::sleep(Duration::from_millis(1100));
+
+    assert_eq!(read_status(&device, b"ttl-test"), HkvError::NotFound.code());
+
+    // The sweep runs once a second; give it one more period to catch up.
+    thread::sleep(Duration::from_millis(1100));
+    let after = stats(&device);
+    assert!(after.expired > before.expired);
+    assert_eq!(after.evictions, before.evictions);
+}
+
+#[test]
+fn expiry_thread_is_running() {
+    if open_device().is_none() {
+        return;
+    }
+
+    let running = fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("comm")).ok())
+        .any(|comm| comm.trim_end() == "kv_expiry");
+    assert!(running, "no kv_expiry thread in /proc");
+}
+
+#[test]
+fn drop_caches_runs_the_shrinker() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+
+    for i in 0..16 {
+        promote(&device, format!("shrink-{i}").as_bytes(), TtlAt::INFINITE);
+    }
+    let before = stats(&device);
+
+    if let Err(err) = fs::write("/proc/sys/vm/drop_caches", "3") {
+        eprintln!("skipping: cannot write drop_caches: {err}");
+        return;
+    }
+
+    let after = stats(&device);
+    assert!(after.evictions > before.evictions);
+    assert!(after.entry_count < before.entry_count);
+}
+
+#[test]
+fn flagged_reads_behave_like_plain_reads_when_uncontended() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+
+    promote(&device, b"flags-test", TtlAt::INFINITE);
+    let before = stats(&device);
+
+    let nowait = read_status_with(&device, b"flags-test", RequestFlags::NOWAIT);
+    assert_eq!(nowait, STATUS_OK);
+    let probe = read_status_with(
+        &device,
+        b"flags-test",
+        RequestFlags::NOWAIT | RequestFlags::NO_PROMOTE_STATS,
+    );
+    assert_eq!(probe, STATUS_OK);
+
+    // Only the first read counts as a lookup.
+    assert_eq!(stats(&device).lookups, before.lookups + 1);
+}
+
+const DEBUGFS_DIR: &str = "/sys/kernel/debug/hybridkv";
+
+#[test]
+fn debugfs_dump_lists_entries_and_clear_flushes() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+
+    promote(&device, b"debugfs-test", TtlAt::INFINITE);
+    let dump = match fs::read_to_string(format!("{DEBUGFS_DIR}/dump")) {
+        Ok(dump) => dump,
+        Err(err) => {
+            eprintln!("skipping: cannot read {DEBUGFS_DIR}/dump: {err}");
+            return;
+        }
+    };
+    assert!(
+        dump.lines()
+            .any(|line| line == "debugfs-test=value expire_ns=0"),
+        "missing entry in:\n{dump}"
+    );
+
+    fs::write(format!("{DEBUGFS_DIR}/clear"), "1").unwrap();
+    assert_eq!(
+        read_status(&device, b"debugfs-test"),
+        HkvError::NotFound.code()
+    );
+    assert_eq!(stats(&device).entry_count, 0);
+}
+
+#[test]
+fn reads_stay_consistent_while_promotes_evict() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let capacity: usize = fs::read_to_string("/sys/module/kv_module/parameters/kv_max_entries")
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(4093);
+    let keys = capacity + 256;
+    let before = stats(&device);
+
+    thread::scope(|scope| {
+        for reader in 0..4 {
+            let device = &device;
+            scope.spawn(move || {
+                for i in (reader..keys).step_by(3) {
+                    let key = format!("lru-{i}");
+                    let request = ReadRequest::new(Key::new(key.as_bytes()).unwrap());
+                    let (response, _): (ReadResponse, _) = transact(device, HKV_IOC_READ, request);
+                    // Every value is its own key, so a torn or freed entry
+                    // shows up as a mismatch.
+                    if response.status == STATUS_OK {
+                        assert_eq!(response.value.as_bytes(), key.as_bytes());
+                    }
+                }
+            });
+        }
+        for i in 0..keys {
+            let key = format!("lru-{i}");
+            let request = PromoteRequest::new(
+                Key::new(key.as_bytes()).unwrap(),
+                Value::new(key.as_bytes()).unwrap(),
+                Version::new(1),
+                TtlAt::INFINITE,
+            );
+            let (response, err): (PromoteResponse, _) = transact(&device, HKV_IOC_PROMOTE, request);
+            assert_eq!((err, response.status), (None, STATUS_OK));
+        }
+    });
+
+    // The table never overflows: the least recently used entries made room.
+    let after = stats(&device);
+    assert!(after.evictions >= before.evictions + 256);
+    assert!(after.entry_count as usize <= capacity);
+    assert!(after.used_bytes <= after.max_bytes);
+}
+
+#[test]
+fn stale_promote_retry_keeps_the_newer_value() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let key = Key::new(b"stale-retry").unwrap();
+    let promote_version = |value: &[u8], version| {
+        let request = PromoteRequest::new(
+            key.clone(),
+            Value::new(value).unwrap(),
+            Version::new(version),
+            TtlAt::INFINITE,
+        );
+        let (response, err): (PromoteResponse, _) = transact(&device, HKV_IOC_PROMOTE, request);
+        (err, response.outcome().unwrap())
+    };
+    let (_, err): (DemoteResponse, _) =
+        transact(&device, HKV_IOC_DEMOTE, DemoteRequest::new(key.clone()));
+    assert_eq!(err, None);
+
+    assert_eq!(promote_version(b"v5", 5).0, None);
+    assert_eq!(promote_version(b"v6", 6).0, None);
+    assert_eq!(
+        promote_version(b"v5", 5),
+        (
+            Some(HkvError::VersionMismatch),
+            PromoteOutcome::RejectedStale
+        )
+    );
+
+    let (response, err): (ReadResponse, _) = transact(&device, HKV_IOC_READ, ReadRequest::new(key));
+    assert_eq!(err, None);
+    assert_eq!(response.value.as_bytes(), b"v6");
+}
+
+/// A value whose 8-byte words all hold `version`, so a torn copy shows.
+fn versioned_value(version: u64) -> Value {
+    Value::new(&version.to_le_bytes().repeat(32)).unwrap()
+}
+
+#[test]
+fn invalidate_races_with_reads_and_promotes() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    const ROUNDS: u64 = 2000;
+    let key = Key::new(b"invalidate-race").unwrap();
+    // Clock-based versions stay newer than what earlier runs invalidated.
+    let base = SystemClock.now_unix_nanos();
+    let before = stats(&device);
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            let (device, key, done) = (&device, &key, &done);
+            scope.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let request = ReadRequest::new(key.clone());
+                    let (response, _): (ReadResponse, _) = transact(device, HKV_IOC_READ, request);
+                    if response.status == STATUS_OK {
+                        let bytes = response.value.as_bytes();
+                        assert_eq!(bytes.len(), 256);
+                        assert!(bytes.chunks(8).all(|word| word == &bytes[..8]));
+                    }
+                }
+            });
+        }
+
+        let promote = |version: u64| {
+            let request = PromoteRequest::new(
+                key.clone(),
+                versioned_value(version),
+                Version::new(version),
+                TtlAt::INFINITE,
+            );
+            let (response, err): (PromoteResponse, _) = transact(&device, HKV_IOC_PROMOTE, request);
+            (err, response.outcome().unwrap())
+        };
+        for round in 1..=ROUNDS {
+            let version = base + 2 * round;
+            assert_eq!(promote(version).0, None);
+
+            let request = InvalidateRequest::new(key.clone(), Version::new(version));
+            let (response, err): (InvalidateResponse, _) =
+                transact(&device, HKV_IOC_INVALIDATE, request);
+            assert_eq!((err, response.was_removed()), (None, true));
+
+            // The data the write replaced must not come back.
+            assert_eq!(
+                promote(version - 1),
+                (
+                    Some(HkvError::VersionMismatch),
+                    PromoteOutcome::RejectedStale
+                )
+            );
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert_eq!(
+        read_status(&device, b"invalidate-race"),
+        HkvError::NotFound.code()
+    );
+    assert!(stats(&device).invalidations >= before.invalidations + ROUNDS);
+}
+
+#[test]
+fn flush_removes_every_entry() {
+    let Some(device) = open_device() else {
+        return;
+    };
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+
+    for i in 0..64 {
+        promote(&device, format!("flush-{i}").as_bytes(), TtlAt::INFINITE);
+    }
+    let (response, err): (FlushResponse, _) = transact(&device, HKV_IOC_FLUSH, FlushRequest::new());
+    assert_eq!((err, response.status), (None, STATUS_OK));
+    assert!(response.removed >= 64);
+
+    assert_eq!(read_status(&device, b"flush-0"), HkvError::NotFound.code());
+    assert_eq!(stats(&device).entry_count, 0);
+}
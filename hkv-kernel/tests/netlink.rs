@@ -0,0 +1,127 @@
+//! Exercises a loaded `kv_module` through its `NETLINK_HYBRIDKV` socket.
+//!
+//! Skips when the family is not registered (module not loaded).
+
+use std::io;
+use std::mem::{size_of, zeroed};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use hkv_common::{
+    CMD_HELLO, CMD_STATS, HelloRequest, HelloResponse, NETLINK_HYBRIDKV, STATUS_OK, StatsRequest,
+    StatsResponse,
+};
+
+const HDRLEN: usize = size_of::<libc::nlmsghdr>();
+
+fn open_socket() -> Option<OwnedFd> {
+    // SAFETY: plain socket creation; the fd is owned below.
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW, NETLINK_HYBRIDKV) };
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        eprintln!("skipping: no NETLINK_HYBRIDKV socket: {err}");
+        return None;
+    }
+    // SAFETY: `fd` is a fresh, valid descriptor.
+    Some(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Appends one request message carrying `payload` with sequence `seq`.
+fn push_message<T>(buf: &mut Vec<u8>, seq: u32, payload: &T) {
+    let header = libc::nlmsghdr {
+        nlmsg_len: (HDRLEN + size_of::<T>()) as u32,
+        nlmsg_type: libc::NLMSG_MIN_TYPE as u16,
+        nlmsg_flags: libc::NLM_F_REQUEST as u16,
+        nlmsg_seq: seq,
+        nlmsg_pid: 0,
+    };
+    // SAFETY: both are plain `repr(C)` structs read as bytes.
+    unsafe {
+        buf.extend_from_slice(std::slice::from_raw_parts(
+            (&header as *const libc::nlmsghdr).cast::<u8>(),
+            HDRLEN,
+        ));
+        buf.extend_from_slice(std::slice::from_raw_parts(
+            (payload as *const T).cast::<u8>(),
+            size_of::<T>(),
+        ));
+    }
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// Splits a received datagram into `(header, payload)` pairs.
+fn messages(mut buf: &[u8]) -> Vec<(libc::nlmsghdr, &[u8])> {
+    let mut out = Vec::new();
+    while buf.len() >= HDRLEN {
+        // SAFETY: at least `HDRLEN` bytes remain.
+        let header = unsafe { std::ptr::read_unaligned(buf.as_ptr().cast::<libc::nlmsghdr>()) };
+        let len = header.nlmsg_len as usize;
+        out.push((header, &buf[HDRLEN..len]));
+        buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+    }
+    out
+}
+
+fn payload<T>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    // SAFETY: the module replies with the plain `repr(C)` response struct.
+    unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<T>()) }
+}
+
+#[test]
+fn batched_requests_each_get_a_reply() {
+    let Some(socket) = open_socket() else {
+        return;
+    };
+    let fd = socket.as_raw_fd();
+
+    // SAFETY: `sockaddr_nl` is plain data; pid 0 targets the kernel.
+    let mut kernel: libc::sockaddr_nl = unsafe { zeroed() };
+    kernel.nl_family = libc::AF_NETLINK as u16;
+
+    let mut request = Vec::new();
+    push_message(&mut request, 1, &HelloRequest::new());
+    push_message(&mut request, 2, &StatsRequest::new());
+    // SAFETY: `request` and `kernel` are valid for the given lengths.
+    let sent = unsafe {
+        libc::sendto(
+            fd,
+            request.as_ptr().cast(),
+            request.len(),
+            0,
+            (&kernel as *const libc::sockaddr_nl).cast(),
+            size_of::<libc::sockaddr_nl>() as u32,
+        )
+    };
+    assert_eq!(
+        sent,
+        request.len() as isize,
+        "{}",
+        io::Error::last_os_error()
+    );
+
+    let mut replies = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    while replies.len() < 2 {
+        // SAFETY: `buf` is writable for its full length.
+        let len = unsafe { libc::recv(fd, buf.as_mut_ptr().cast(), buf.len(), 0) };
+        assert!(len > 0, "{}", io::Error::last_os_error());
+        for (header, body) in messages(&buf[..len as usize]) {
+            assert_ne!(
+                header.nlmsg_type,
+                libc::NLMSG_ERROR as u16,
+                "request rejected"
+            );
+            replies.push((header.nlmsg_seq, body.to_vec()));
+        }
+    }
+
+    assert_eq!(replies[0].0, 1);
+    let hello: HelloResponse = payload(&replies[0].1);
+    assert_eq!(hello.header.command, CMD_HELLO);
+    assert_eq!(hello.status, STATUS_OK);
+
+    assert_eq!(replies[1].0, 2);
+    let stats: StatsResponse = payload(&replies[1].1);
+    assert_eq!(stats.header.command, CMD_STATS);
+    assert_eq!(stats.status, STATUS_OK);
+}
@@ -0,0 +1,62 @@
+//! Loads `kv_module` with out-of-range parameters and checks that init fails.
+//!
+//! Needs root, the built module in `HKV_KV_MODULE` (a path to
+//! `kv_module.ko`) and no module already loaded; skips otherwise, so it
+//! never disturbs a running cache.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+const DEVICE: &str = "/dev/hybridkv";
+
+#[test]
+fn out_of_range_params_fail_the_load() {
+    let Some(module) = env::var_os("HKV_KV_MODULE") else {
+        eprintln!("skipping: HKV_KV_MODULE not set");
+        return;
+    };
+    // SAFETY: `geteuid` has no preconditions.
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping: loading modules needs root");
+        return;
+    }
+    if Path::new("/sys/module/kv_module").exists() {
+        eprintln!("skipping: kv_module is already loaded");
+        return;
+    }
+
+    for param in [
+        "kv_max_value_size=2048",
+        "kv_max_value_size=0",
+        "kv_max_key_size=0",
+        "kv_max_key_size=257",
+        "kv_max_entries=0",
+        "kv_max_entries=1048577",
+        "kv_max_bytes=1",
+    ] {
+        let output = Command::new("insmod")
+            .arg(&module)
+            .arg(param)
+            .output()
+            .expect("run insmod");
+        assert!(!output.status.success(), "{param} was accepted");
+        // init reports EINVAL, which insmod prints as "Invalid argument".
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Invalid"), "{param}: {stderr}");
+        assert!(!Path::new(DEVICE).exists(), "{param} left {DEVICE} behind");
+    }
+
+    // The limits themselves load fine.
+    let status = Command::new("insmod")
+        .arg(&module)
+        .args(["kv_max_key_size=64", "kv_max_value_size=512"])
+        .status()
+        .expect("run insmod");
+    assert!(status.success(), "lowered limits were rejected");
+    let status = Command::new("rmmod")
+        .arg("kv_module")
+        .status()
+        .expect("run rmmod");
+    assert!(status.success());
+}
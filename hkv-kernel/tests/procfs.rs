@@ -0,0 +1,58 @@
+//! Reads `/proc/hybridkv/stats` from a loaded `kv_module`.
+//!
+//! Skips when the file is absent (module not loaded).
+
+use std::collections::HashMap;
+use std::fs;
+
+const STATS_PATH: &str = "/proc/hybridkv/stats";
+
+const KEYS: [&str; 11] = [
+    "entry_count",
+    "hit_count",
+    "miss_count",
+    "evictions",
+    "memory_bytes",
+    "capacity",
+    "max_bytes",
+    "promotions",
+    "demotions",
+    "expired",
+    "protocol_version",
+];
+
+fn parse(text: &str) -> HashMap<&str, u64> {
+    text.lines()
+        .map(|line| {
+            let (name, value) = line
+                .split_once(": ")
+                .unwrap_or_else(|| panic!("malformed line {line:?}"));
+            let value = value
+                .parse()
+                .unwrap_or_else(|_| panic!("{name} is not a non-negative integer: {value:?}"));
+            (name, value)
+        })
+        .collect()
+}
+
+#[test]
+fn proc_stats_lists_all_counters() {
+    let text = match fs::read_to_string(STATS_PATH) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("skipping: cannot read {STATS_PATH}: {err}");
+            return;
+        }
+    };
+
+    let stats = parse(&text);
+
+    for key in KEYS {
+        assert!(stats.contains_key(key), "missing {key} in:\n{text}");
+    }
+    assert!(stats["entry_count"] <= stats["capacity"]);
+    assert_eq!(
+        stats["protocol_version"],
+        u64::from(hkv_common::PROTOCOL_VERSION)
+    );
+}
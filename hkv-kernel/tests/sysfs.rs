@@ -0,0 +1,68 @@
+//! Reads and writes `/sys/kernel/hybridkv` on a loaded `kv_module`.
+//!
+//! Skips when the directory is absent (module not loaded). Writes need root
+//! and are skipped otherwise; they only rewrite current values or try
+//! rejected ones, so tests running against the same module are undisturbed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use hkv_common::sysfs::{KernelTunables, SYSFS_DIR, read_attribute};
+
+const STATS: [&str; 8] = [
+    "entry_count",
+    "used_bytes",
+    "hits",
+    "misses",
+    "promotions",
+    "demotions",
+    "evictions",
+    "expired",
+];
+
+#[test]
+fn tunables_and_counters_are_readable() {
+    let dir = Path::new(SYSFS_DIR);
+    let tunables = match KernelTunables::read_from(dir) {
+        Ok(tunables) => tunables,
+        Err(err) => {
+            eprintln!("skipping: cannot read {SYSFS_DIR}: {err}");
+            return;
+        }
+    };
+    assert!(tunables.max_entries > 0);
+    assert!(tunables.max_bytes > 0);
+
+    for name in STATS {
+        read_attribute(dir, name).unwrap_or_else(|err| panic!("{name}: {err}"));
+    }
+    assert!(read_attribute(dir, "entry_count").unwrap() <= tunables.max_entries);
+}
+
+#[test]
+fn out_of_range_writes_are_rejected() {
+    let dir = Path::new(SYSFS_DIR);
+    let Ok(before) = KernelTunables::read_from(dir) else {
+        eprintln!("skipping: {SYSFS_DIR} not present");
+        return;
+    };
+
+    let path = dir.join("max_entries");
+    if let Err(err) = fs::write(&path, before.max_entries.to_string()) {
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "{err}");
+        eprintln!("skipping: cannot write {}: {err}", path.display());
+        return;
+    }
+
+    for (name, value) in [
+        ("max_entries", "0"),
+        ("max_bytes", "1"),
+        ("default_ttl_override", "18446744073709551615"),
+        ("max_entries", "many"),
+    ] {
+        let err = fs::write(dir.join(name), value).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL), "{name}={value}");
+    }
+    assert_eq!(KernelTunables::read_from(dir).unwrap(), before);
+}
@@ -0,0 +1,114 @@
+//! # Accept Throughput Benchmarks
+//!
+//! Criterion benchmarks for connection churn: clients connect, send one
+//! `PING`, read the reply and disconnect, against a server with one accept
+//! loop and one with `ACCEPTORS` loops on `SO_REUSEPORT` listeners.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-server --bench accept
+//! ```
+//!
+//! More acceptors only help with worker threads to run them on; on a
+//! single core both servers accept one connection at a time.
+//!
+//! ## Design Principles
+//! 1. **Accept Cost Dominates**: Each connection runs a single `PING`, so
+//!    the time goes to the handshake, the accept and the close.
+//! 2. **Concurrent Clients**: `CLIENTS` tasks connect at once, so a single
+//!    accept loop has a queue to fall behind on.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{Criterion, Throughput, criterion_group};
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+
+const PING: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+const PONG: &[u8] = b"+PONG\r\n";
+
+/// Accept loops of the `SO_REUSEPORT` server.
+const ACCEPTORS: usize = 4;
+
+/// Tasks connecting at once.
+const CLIENTS: usize = 64;
+
+/// Connections each task opens per iteration.
+const CONNECTIONS_PER_CLIENT: usize = 16;
+
+/// Starts a server with `acceptors` listeners on one loopback port.
+fn start_server(acceptors: usize) -> (SocketAddr, ShutdownController) {
+    let addr = "127.0.0.1:0".parse().unwrap();
+    let listeners = if acceptors == 1 {
+        vec![server::bind_listener(addr, DEFAULT_TCP_BACKLOG).unwrap()]
+    } else {
+        server::bind_reuseport_listeners(addr, DEFAULT_TCP_BACKLOG, acceptors).unwrap()
+    };
+    let addr = listeners[0].local_addr().unwrap();
+    let runtime = RuntimeConfig::new();
+    runtime.set_acceptors(acceptors);
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        listeners,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+async fn churn(addr: SocketAddr) {
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..CLIENTS {
+        tasks.spawn(async move {
+            for _ in 0..CONNECTIONS_PER_CLIENT {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                let mut reply = [0; PONG.len()];
+                stream.write_all(PING).await.unwrap();
+                stream.read_exact(&mut reply).await.unwrap();
+                assert_eq!(reply, PONG);
+            }
+        });
+    }
+    while let Some(task) = tasks.join_next().await {
+        task.unwrap();
+    }
+}
+
+fn accept_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("accept");
+    let runtime = Runtime::new().unwrap();
+
+    group.throughput(Throughput::Elements(
+        (CLIENTS * CONNECTIONS_PER_CLIENT) as u64,
+    ));
+    for acceptors in [1, ACCEPTORS] {
+        let (addr, shutdown) = runtime.block_on(async { start_server(acceptors) });
+        group.bench_function(format!("churn_{acceptors}_acceptors"), |b| {
+            b.iter(|| runtime.block_on(churn(addr)))
+        });
+        shutdown.trigger();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, accept_benches);
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -0,0 +1,149 @@
+//! # Metrics Benchmarks
+//!
+//! Criterion benchmarks for `LatencyHistogram`, which records a sample after
+//! every command and so sits on the request path.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-server --bench metrics
+//! ```
+//!
+//! Before benchmarking, `record` is timed single-threaded for the first and
+//! the overflow bucket. The run prints the mean cost of each and aborts if
+//! either exceeds `RECORD_BUDGET`.
+//!
+//! ## Design Principles
+//! 1. **Both Ends of the Scan**: Bucket selection is a linear scan, so the
+//!    first bucket is the fastest path and the overflow bucket the slowest.
+//! 2. **Contention Measured, Not Budgeted**: Concurrent recording shares
+//!    cache lines between threads; it is benchmarked for regressions but is
+//!    not held to the single-threaded budget.
+
+use std::hint::black_box;
+use std::sync::Barrier;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{Criterion, Throughput, criterion_group};
+use hkv_server::metrics::{DEFAULT_LATENCY_BUCKETS_US, LatencyHistogram};
+
+/// Longest a single uncontended `record` may take on average.
+const RECORD_BUDGET: Duration = Duration::from_nanos(50);
+
+/// Samples timed per round when checking `RECORD_BUDGET`.
+const BUDGET_SAMPLES: u32 = 1_000_000;
+
+/// Rounds timed per check; the fastest counts, so a descheduled round does
+/// not fail the run.
+const BUDGET_ROUNDS: usize = 5;
+
+const CONTENDING_THREADS: usize = 100;
+const SAMPLES_PER_THREAD: usize = 10_000;
+
+/// Threads recording in the background while snapshots are taken.
+const BACKGROUND_RECORDERS: usize = 4;
+
+/// Lands in the first bucket.
+const FAST_LATENCY: Duration = Duration::from_nanos(500);
+
+/// Lands past the last bound, in the overflow bucket.
+const OVERFLOW_LATENCY: Duration = Duration::from_millis(10);
+
+fn histogram() -> LatencyHistogram {
+    LatencyHistogram::new(DEFAULT_LATENCY_BUCKETS_US.to_vec())
+}
+
+/// Checks and prints the mean uncontended cost of `record`.
+fn check_record_budget() {
+    let histogram = histogram();
+    for (name, latency) in [
+        ("first_bucket", FAST_LATENCY),
+        ("overflow", OVERFLOW_LATENCY),
+    ] {
+        let fastest = (0..BUDGET_ROUNDS)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..BUDGET_SAMPLES {
+                    histogram.record(black_box(latency));
+                }
+                start.elapsed() / BUDGET_SAMPLES
+            })
+            .min()
+            .unwrap();
+        println!("record {name}: {fastest:?} per sample");
+        assert!(
+            fastest <= RECORD_BUDGET,
+            "record {name}: {fastest:?} exceeds the {RECORD_BUDGET:?} budget"
+        );
+    }
+}
+
+/// Records `SAMPLES_PER_THREAD` samples on each of `CONTENDING_THREADS`
+/// threads, timed from a common start.
+fn record_contended(histogram: &LatencyHistogram) -> Duration {
+    let start_line = Barrier::new(CONTENDING_THREADS + 1);
+    let start = thread::scope(|scope| {
+        for _ in 0..CONTENDING_THREADS {
+            scope.spawn(|| {
+                start_line.wait();
+                for _ in 0..SAMPLES_PER_THREAD {
+                    histogram.record(black_box(FAST_LATENCY));
+                }
+            });
+        }
+        start_line.wait();
+        // Leaving the scope joins every thread.
+        Instant::now()
+    });
+    start.elapsed()
+}
+
+fn metrics_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("latency_histogram");
+    let histogram = histogram();
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("record_first_bucket", |b| {
+        b.iter(|| histogram.record(black_box(FAST_LATENCY)))
+    });
+    group.bench_function("record_overflow", |b| {
+        b.iter(|| histogram.record(black_box(OVERFLOW_LATENCY)))
+    });
+
+    group.throughput(Throughput::Elements(
+        (CONTENDING_THREADS * SAMPLES_PER_THREAD) as u64,
+    ));
+    group.sample_size(10);
+    group.bench_function("record_100_threads", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| record_contended(&histogram)).sum())
+    });
+
+    group.sample_size(100);
+    group.throughput(Throughput::Elements(1));
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..BACKGROUND_RECORDERS {
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    histogram.record(black_box(FAST_LATENCY));
+                }
+            });
+        }
+        group.bench_function("snapshot_while_recording", |b| {
+            b.iter(|| black_box(histogram.snapshot()))
+        });
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, metrics_benches);
+
+fn main() {
+    check_record_budget();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
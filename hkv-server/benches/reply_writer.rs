@@ -0,0 +1,118 @@
+//! # Reply Writer Benchmarks
+//!
+//! Criterion benchmarks for writing an `MGET` reply of 100 values of 4 KiB,
+//! copied into one buffer as the server used to, and vectored by
+//! `write_reply`.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-server --bench reply_writer
+//! ```
+//!
+//! Before benchmarking, the run prints the bytes each path copies per reply
+//! and aborts if the vectored path copies more than `MAX_COPIED_FRACTION`
+//! of what the copying path does.
+//!
+//! ## Design Principles
+//! 1. **Writer Cost Only**: Replies go to a sink that discards them, so the
+//!    socket does not dominate the measurement.
+//! 2. **Same Input**: Both paths write the same `Reply`, built once outside
+//!    timing.
+
+use std::hint::black_box;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use criterion::{Criterion, Throughput, criterion_group};
+use hkv_server::reply::{Reply, write_reply};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::runtime::Runtime;
+
+const VALUES: usize = 100;
+const VALUE_LEN: usize = 4096;
+
+/// Share of the copying path's bytes the vectored path may copy.
+const MAX_COPIED_FRACTION: f64 = 0.05;
+
+/// Accepts and discards every write, vectored or not.
+struct Sink;
+
+impl AsyncWrite for Sink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(black_box(buf).len()))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(black_box(bufs).iter().map(|buf| buf.len()).sum()))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn mget_reply() -> Reply {
+    Reply::bulk_array((0..VALUES).map(|i| {
+        let value: Arc<[u8]> = Arc::from(vec![b'a' + (i % 26) as u8; VALUE_LEN]);
+        Some(value)
+    }))
+}
+
+/// Checks and prints the bytes copied per reply by each path.
+fn check_copies(reply: &Reply) {
+    let copied = reply.len();
+    let vectored = reply.copied_len();
+    println!("mget_100x4k: copied path {copied} bytes, vectored path {vectored} bytes");
+    assert!(
+        (vectored as f64) <= copied as f64 * MAX_COPIED_FRACTION,
+        "vectored path copies {vectored} of {copied} bytes"
+    );
+}
+
+fn reply_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reply_writer");
+    let runtime = Runtime::new().unwrap();
+    let reply = mget_reply();
+
+    group.throughput(Throughput::Bytes(reply.len() as u64));
+    group.bench_function("mget_100x4k_copied", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let buf = reply.clone().into_vec();
+                Sink.write_all(&buf).await.unwrap();
+            })
+        })
+    });
+    group.bench_function("mget_100x4k_vectored", |b| {
+        b.iter(|| runtime.block_on(async { write_reply(&mut Sink, &reply).await.unwrap() }))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, reply_benches);
+
+fn main() {
+    check_copies(&mget_reply());
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -0,0 +1,210 @@
+//! # RESP Parser Benchmarks
+//!
+//! Criterion benchmarks for `RespParser`, plus an allocation count per
+//! parse that keeps the parser's **Low Allocation** principle honest.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-server --bench resp_parser
+//! ```
+//!
+//! Before benchmarking, each frame is parsed once under a counting global
+//! allocator. The run prints allocations per parse and aborts if a frame
+//! needs more than `ALLOCATIONS_PER_ARG` per argument plus one for the
+//! argument list, beyond `PER_BUFFER_ALLOCATIONS` per input buffer.
+//!
+//! ## Design Principles
+//! 1. **Input Setup Outside Timing**: Frames are copied into a fresh
+//!    `BytesMut` by `iter_batched`, so only parsing is measured.
+//! 2. **Counted, Not Sampled**: The allocator counts every allocation made
+//!    on the benchmark thread while a parse runs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+
+use bytes::BytesMut;
+use criterion::{BatchSize, Criterion, Throughput, criterion_group};
+use hkv_server::protocol::RespParser;
+
+/// Allocations allowed per argument: the copy-out of a small one.
+const ALLOCATIONS_PER_ARG: usize = 1;
+
+/// One-off allocations per input buffer: `BytesMut` moves its storage to a
+/// shared header on the first `split_to`.
+const PER_BUFFER_ALLOCATIONS: usize = 1;
+
+const PIPELINE_DEPTH: usize = 100;
+
+/// Length of the frame split in two for the partial-frame benchmark.
+const PARTIAL_FRAME_LEN: usize = 100;
+
+/// System allocator that counts allocations on the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: every call is forwarded unchanged to `System`.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        // SAFETY: the caller upholds `GlobalAlloc::alloc`'s contract.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `System` with `layout`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        // SAFETY: the caller upholds `GlobalAlloc::realloc`'s contract.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Allocations made by `f` on this thread.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut frame = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        frame.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        frame.extend_from_slice(arg);
+        frame.extend_from_slice(b"\r\n");
+    }
+    frame
+}
+
+fn ping_frame() -> Vec<u8> {
+    command(&[b"PING"])
+}
+
+fn set_frame() -> Vec<u8> {
+    command(&[b"SET", b"k", b"v"])
+}
+
+fn pipeline_frames() -> Vec<u8> {
+    (0..PIPELINE_DEPTH)
+        .flat_map(|i| {
+            let key = format!("key:{i}");
+            command(&[b"SET", key.as_bytes(), b"value"])
+        })
+        .collect()
+}
+
+/// A `SET` frame of exactly `PARTIAL_FRAME_LEN` bytes.
+fn partial_frame() -> Vec<u8> {
+    let value = vec![b'v'; 66];
+    let frame = command(&[b"SET", b"key:0001", &value]);
+    assert_eq!(frame.len(), PARTIAL_FRAME_LEN);
+    frame
+}
+
+/// The first half of `frame` in a buffer with room for the rest, as left by
+/// a read that ended mid-frame.
+fn first_half(frame: &[u8]) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(PARTIAL_FRAME_LEN);
+    buf.extend_from_slice(&frame[..PARTIAL_FRAME_LEN / 2]);
+    buf
+}
+
+/// Parses every command in `frame`, returning how many there were.
+fn parse_all(parser: &mut RespParser, buf: &mut BytesMut) -> usize {
+    let mut commands = 0;
+    while let Some(args) = parser.parse(buf).unwrap() {
+        black_box(args);
+        commands += 1;
+    }
+    commands
+}
+
+/// Parses the first half of `frame`, then the rest, as a server does when
+/// a frame arrives in two reads.
+fn parse_split(parser: &mut RespParser, frame: &[u8], mut buf: BytesMut) {
+    assert!(parser.parse(&mut buf).unwrap().is_none());
+    buf.extend_from_slice(&frame[PARTIAL_FRAME_LEN / 2..]);
+    black_box(parser.parse(&mut buf).unwrap().unwrap());
+}
+
+/// Checks and prints allocations per parse for each benchmarked frame.
+fn check_allocation_budget() {
+    let budget = |args: usize| args * ALLOCATIONS_PER_ARG + 1;
+    let mut parser = RespParser::new();
+
+    let mut report = |name: &str, frame: &[u8], commands: usize, args: usize| {
+        let mut buf = BytesMut::from(frame);
+        let allocations = count_allocations(|| {
+            assert_eq!(parse_all(&mut parser, &mut buf), commands);
+        });
+        let per_parse = allocations as f64 / commands as f64;
+        println!("{name}: {per_parse:.1} allocations per parse");
+        assert!(
+            allocations <= commands * budget(args) + PER_BUFFER_ALLOCATIONS,
+            "{name}: {allocations} allocations for {commands} parses exceeds the budget"
+        );
+    };
+    report("ping", &ping_frame(), 1, 1);
+    report("set", &set_frame(), 1, 3);
+    report("pipeline_100_set", &pipeline_frames(), PIPELINE_DEPTH, 3);
+
+    let frame = partial_frame();
+    let buf = first_half(&frame);
+    let allocations = count_allocations(|| parse_split(&mut parser, &frame, buf));
+    println!("partial_frame: {allocations} allocations per parse");
+    assert!(
+        allocations <= budget(3) + PER_BUFFER_ALLOCATIONS,
+        "partial_frame: {allocations}"
+    );
+}
+
+fn parser_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resp_parser");
+    let mut parser = RespParser::new();
+
+    for (name, frame, commands) in [
+        ("ping", ping_frame(), 1),
+        ("set", set_frame(), 1),
+        ("pipeline_100_set", pipeline_frames(), PIPELINE_DEPTH),
+    ] {
+        group.throughput(Throughput::Elements(commands as u64));
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || BytesMut::from(frame.as_slice()),
+                |mut buf| parse_all(&mut parser, &mut buf),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    let frame = partial_frame();
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("partial_frame", |b| {
+        b.iter_batched(
+            || first_half(&frame),
+            |buf| parse_split(&mut parser, &frame, buf),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parser_benches);
+
+fn main() {
+    check_allocation_budget();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -0,0 +1,129 @@
+//! # Network Transport Benchmarks
+//!
+//! Criterion benchmarks for `PING` round trips over loopback, served once
+//! through the standard tokio network path and once through io_uring.
+//!
+//! ## Usage
+//!
+//! ```bash
+//! cargo bench -p hkv-server --features uring --bench transport
+//! ```
+//!
+//! The ring falls back to the standard path on kernels without io_uring,
+//! which the server logs at startup; both results are then the same path.
+//!
+//! ## Design Principles
+//! 1. **Transport Cost Only**: `PING` touches no keys, so the engine does
+//!    not dominate the measurement.
+//! 2. **Same Server Otherwise**: Both servers differ only in the `io-uring`
+//!    parameter.
+//! 3. **Concurrency Matters**: One client measures latency per round trip;
+//!    many clients at once measure how each path shares its threads.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{Criterion, Throughput, criterion_group};
+use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+
+const PING: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+const PONG: &[u8] = b"+PONG\r\n";
+
+/// Clients in the concurrent benchmark.
+const CLIENTS: usize = 32;
+
+/// Round trips each client makes per iteration of the concurrent benchmark.
+const ROUND_TRIPS: usize = 16;
+
+/// Starts a server with the `io-uring` parameter set to `io_uring`.
+async fn start_server(io_uring: bool) -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let runtime = Arc::new(RuntimeConfig::new());
+    runtime.set_io_uring(io_uring);
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        runtime,
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+async fn ping(stream: &mut TcpStream) {
+    let mut reply = [0; PONG.len()];
+    stream.write_all(PING).await.unwrap();
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, PONG);
+}
+
+async fn connect(addr: SocketAddr, count: usize) -> Vec<TcpStream> {
+    let mut clients = Vec::with_capacity(count);
+    for _ in 0..count {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        stream.set_nodelay(true).unwrap();
+        clients.push(stream);
+    }
+    clients
+}
+
+fn transport_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transport");
+    let runtime = Runtime::new().unwrap();
+
+    for (label, io_uring) in [("standard", false), ("io_uring", true)] {
+        let (addr, shutdown) = runtime.block_on(start_server(io_uring));
+        let mut single = runtime.block_on(connect(addr, 1));
+        let mut many = runtime.block_on(connect(addr, CLIENTS));
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(format!("ping_1_client_{label}"), |b| {
+            b.iter(|| runtime.block_on(ping(&mut single[0])))
+        });
+
+        group.throughput(Throughput::Elements((CLIENTS * ROUND_TRIPS) as u64));
+        group.bench_function(format!("ping_{CLIENTS}_clients_{label}"), |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let mut tasks = tokio::task::JoinSet::new();
+                    for mut stream in many.drain(..) {
+                        tasks.spawn(async move {
+                            for _ in 0..ROUND_TRIPS {
+                                ping(&mut stream).await;
+                            }
+                            stream
+                        });
+                    }
+                    while let Some(stream) = tasks.join_next().await {
+                        many.push(stream.unwrap());
+                    }
+                })
+            })
+        });
+
+        drop((single, many));
+        shutdown.trigger();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, transport_benches);
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -0,0 +1,347 @@
+//! # Access Log
+//!
+//! An optional audit trail with one line per sampled command: when it ran,
+//! which client sent it, the command name and first key, how long it took
+//! and whether it failed.
+//!
+//! ## Design Principles
+//!
+//! 1. **Never Stall Requests**: Connections format the line and hand it to a
+//!    bounded queue; a dedicated task does the writing. When the queue is
+//!    full the line is dropped and counted rather than waited for.
+//! 2. **No Values, No Secrets**: Only the command's canonical name and, for
+//!    keyed commands, its first key are logged. Values, `AUTH` passwords and
+//!    unknown commands' arguments never reach the log.
+//! 3. **Sampled**: The `accesslog-sample-rate` parameter logs one command in
+//!    N, and 0 turns logging off, both adjustable with `CONFIG SET`.
+//! 4. **logrotate Friendly**: The file is opened in append mode and
+//!    reopened on `reopen` (SIGHUP/SIGUSR1 through `reopen_on_signals`);
+//!    rotation itself is left to the platform.
+//!
+//! ## Line Format
+//!
+//! ```text
+//! ts=2026-10-17T09:30:00.123456Z id=7 addr=127.0.0.1:50412 name=- db=0 cmd=get key="user:1" duration_us=15 outcome=ok
+//! ```
+//!
+//! `addr` is `-` for connections without a peer address, `key` is `-` for
+//! commands without one, and `name` is `-` until clients can be named.
+
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Notify, mpsc};
+
+use crate::logging::LoggedArg;
+use crate::metrics::{UNKNOWN_COMMAND, command_name};
+use crate::server::command_spec;
+
+/// Lines that may wait for the writer before new ones are dropped.
+pub const ACCESS_LOG_QUEUE_LEN: usize = 8192;
+
+/// Where access log lines are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessLogTarget {
+    Stdout,
+    /// Appended to; reopened on `AccessLog::reopen`.
+    File(PathBuf),
+}
+
+impl AccessLogTarget {
+    /// Parses a path, with `-` meaning stdout.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "-" => AccessLogTarget::Stdout,
+            path => AccessLogTarget::File(PathBuf::from(path)),
+        }
+    }
+}
+
+/// One executed command, as seen by the access log.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessEntry<'a> {
+    pub client_id: u64,
+    pub addr: Option<SocketAddr>,
+    pub args: &'a [Bytes],
+    pub elapsed: Duration,
+    pub failed: bool,
+}
+
+/// Handle for queuing access log lines; the writer task exits once every
+/// handle is dropped.
+#[derive(Debug)]
+pub struct AccessLog {
+    lines: mpsc::Sender<String>,
+    reopen: Arc<Notify>,
+    /// Commands offered to `record`, for sampling.
+    seen: AtomicU64,
+    /// Lines dropped because the queue was full.
+    dropped: AtomicU64,
+}
+
+impl AccessLog {
+    /// Opens `target` and starts the writer task.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn open(target: AccessLogTarget) -> io::Result<Arc<Self>> {
+        let out = open_target(&target).await?;
+        let (lines, queued) = mpsc::channel(ACCESS_LOG_QUEUE_LEN);
+        let reopen = Arc::new(Notify::new());
+        tokio::spawn(write_lines(queued, target, Arc::clone(&reopen), out));
+        Ok(Arc::new(AccessLog {
+            lines,
+            reopen,
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }))
+    }
+
+    /// Queues a line for `entry` if it is sampled at one in `sample_rate`;
+    /// 0 logs nothing.
+    pub fn record(&self, sample_rate: u64, entry: AccessEntry<'_>) {
+        if sample_rate == 0
+            || !self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(sample_rate)
+        {
+            return;
+        }
+        let line = format_line(SystemTime::now(), &entry);
+        if self.lines.try_send(line).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Lines dropped because the writer fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Asks the writer to reopen the file, after a rotation moved it.
+    pub fn reopen(&self) {
+        self.reopen.notify_one();
+    }
+
+    /// Reopens the file on every SIGHUP and SIGUSR1.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(unix)]
+    pub fn reopen_on_signals(self: &Arc<Self>) -> io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut user1 = signal(SignalKind::user_defined1())?;
+        let reopen = Arc::clone(&self.reopen);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(()) = hangup.recv() => reopen.notify_one(),
+                    Some(()) = user1.recv() => reopen.notify_one(),
+                    else => break,
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+type Output = Box<dyn AsyncWrite + Send + Unpin>;
+
+async fn open_target(target: &AccessLogTarget) -> io::Result<Output> {
+    Ok(match target {
+        AccessLogTarget::Stdout => Box::new(tokio::io::stdout()),
+        AccessLogTarget::File(path) => Box::new(
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        ),
+    })
+}
+
+/// Writes queued lines until every `AccessLog` handle is dropped, flushing
+/// whenever the queue runs empty.
+async fn write_lines(
+    mut queued: mpsc::Receiver<String>,
+    target: AccessLogTarget,
+    reopen: Arc<Notify>,
+    mut out: Output,
+) {
+    loop {
+        tokio::select! {
+            line = queued.recv() => {
+                let Some(line) = line else { break };
+                let mut result = out.write_all(line.as_bytes()).await;
+                if result.is_ok() && queued.is_empty() {
+                    result = out.flush().await;
+                }
+                if let Err(err) = result {
+                    tracing::warn!(error = %err, "access log write failed");
+                }
+            }
+            () = reopen.notified() => {
+                let _ = out.flush().await;
+                match open_target(&target).await {
+                    Ok(reopened) => out = reopened,
+                    Err(err) => {
+                        tracing::warn!(error = %err, "access log reopen failed, keeping old file");
+                    }
+                }
+            }
+        }
+    }
+    let _ = out.flush().await;
+}
+
+/// Formats `entry` as one newline-terminated line; see the module docs.
+pub fn format_line(at: SystemTime, entry: &AccessEntry<'_>) -> String {
+    let cmd = entry
+        .args
+        .first()
+        .map_or(UNKNOWN_COMMAND, |name| command_name(name));
+    let mut line = String::with_capacity(160);
+    let _ = write!(line, "ts={} id={} addr=", Rfc3339(at), entry.client_id);
+    match entry.addr {
+        Some(addr) => {
+            let _ = write!(line, "{addr}");
+        }
+        None => line.push('-'),
+    }
+    let _ = write!(line, " name=- db=0 cmd={cmd} key=");
+    let key = entry
+        .args
+        .first()
+        .and_then(|name| command_spec(name))
+        .and_then(|spec| spec.keys_of(entry.args).next());
+    match key {
+        Some(key) => {
+            let _ = write!(line, "\"{}\"", LoggedArg(key));
+        }
+        None => line.push('-'),
+    }
+    let _ = writeln!(
+        line,
+        " duration_us={} outcome={}",
+        entry.elapsed.as_micros(),
+        if entry.failed { "error" } else { "ok" }
+    );
+    line
+}
+
+/// Formats a time as RFC 3339 in UTC with microseconds.
+struct Rfc3339(SystemTime);
+
+impl std::fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let since_epoch = self.0.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let secs_of_day = secs % 86_400;
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_micros()
+        )
+    }
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's algorithm, counting 400-year eras from 0000-03-01.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&[u8]]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+    }
+
+    fn entry(args: &[Bytes]) -> AccessEntry<'_> {
+        AccessEntry {
+            client_id: 7,
+            addr: Some("127.0.0.1:50412".parse().unwrap()),
+            args,
+            elapsed: Duration::from_micros(15),
+            failed: false,
+        }
+    }
+
+    #[test]
+    fn lines_follow_the_documented_format() {
+        let at = UNIX_EPOCH + Duration::from_micros(1_792_229_400_123_456);
+        let get = args(&[b"GET", b"user:1"]);
+        assert_eq!(
+            format_line(at, &entry(&get)),
+            "ts=2026-10-17T09:30:00.123456Z id=7 addr=127.0.0.1:50412 name=- db=0 \
+             cmd=get key=\"user:1\" duration_us=15 outcome=ok\n"
+        );
+
+        let ping = args(&[b"PING"]);
+        let line = format_line(
+            UNIX_EPOCH,
+            &AccessEntry {
+                addr: None,
+                failed: true,
+                ..entry(&ping)
+            },
+        );
+        assert!(
+            line.starts_with("ts=1970-01-01T00:00:00.000000Z id=7 addr=- "),
+            "{line}"
+        );
+        assert!(
+            line.ends_with("cmd=ping key=- duration_us=15 outcome=error\n"),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn values_and_auth_arguments_are_never_logged() {
+        let set = args(&[b"SET", b"k \"q\"", b"secret-value"]);
+        let line = format_line(UNIX_EPOCH, &entry(&set));
+        assert!(line.contains("cmd=set key=\"k \\\"q\\\"\" "), "{line}");
+        assert!(!line.contains("secret-value"));
+
+        let auth = args(&[b"AUTH", b"hunter2"]);
+        let line = format_line(UNIX_EPOCH, &entry(&auth));
+        assert!(line.contains("cmd=auth key=- "), "{line}");
+        assert!(!line.contains("hunter2"));
+    }
+
+    #[test]
+    fn civil_dates_cover_leap_years() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_743), (2026, 10, 17));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
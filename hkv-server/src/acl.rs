@@ -0,0 +1,803 @@
+//! # Access Control Lists
+//!
+//! Users with passwords, allowed commands and allowed key patterns, managed
+//! with `ACL SETUSER` and checked before each command runs.
+//!
+//! ## Design Principles
+//!
+//! 1. **Redis Rules**: Users are described by Redis's ACL rules (`on`,
+//!    `>password`, `~pattern`, `+@read`, ...) in `ACL SETUSER`, `ACL LIST`
+//!    and the ACL file, so existing files and tooling carry over.
+//! 2. **Categories From the Table**: `@read`, `@write`, `@admin` and
+//!    `@pubsub` are the commands carrying the matching `CommandFlag`, so a
+//!    new command joins its categories by being flagged.
+//! 3. **One Bit per Command**: A user's allowed commands are a bitmap
+//!    indexed by command table position, so the check before each command
+//!    is a shift and a mask; key patterns are matched only for commands
+//!    that declare key positions.
+//! 4. **Hashed Passwords**: Only SHA-256 digests are kept, and they are
+//!    compared in constant time.
+//! 5. **Copy on Write**: Users are immutable behind `Arc`s. Each change
+//!    swaps in a new table and bumps a generation, which connections compare
+//!    before each command to pick up the change without taking a lock.
+//! 6. **Always a Default User**: `default` cannot be deleted. It starts as
+//!    `on nopass ~* +@all`, `requirepass` sets its password, and an ACL file
+//!    that does not mention it keeps the current one.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::config_file::{ConfigFileError, split_args};
+use crate::glob::glob_match;
+use crate::metrics::TRACKED_COMMANDS;
+use crate::persistence::write_atomically;
+use crate::server::{CommandFlag, CommandSpec, command_index, commands};
+
+/// The user connections run as before `AUTH`.
+pub const DEFAULT_USER: &str = "default";
+
+const _: () = assert!(
+    TRACKED_COMMANDS.len() <= u64::BITS as usize,
+    "command bitmaps hold one bit per command"
+);
+
+/// Every command in the table.
+const ALL_COMMANDS: u64 = u64::MAX >> (u64::BITS as usize - TRACKED_COMMANDS.len());
+
+/// Command categories and the flag selecting their commands; `all` selects
+/// every command.
+const CATEGORIES: [(&str, Option<CommandFlag>); 5] = [
+    ("all", None),
+    ("read", Some(CommandFlag::Readonly)),
+    ("write", Some(CommandFlag::Write)),
+    ("admin", Some(CommandFlag::Admin)),
+    ("pubsub", Some(CommandFlag::Pubsub)),
+];
+
+/// Names of the command categories, for `ACL CAT`.
+pub fn categories() -> impl Iterator<Item = &'static str> {
+    CATEGORIES.iter().map(|&(name, _)| name)
+}
+
+/// The commands in category `name` (any case), or `None` if there is no
+/// such category.
+pub fn category_commands(name: &str) -> Option<impl Iterator<Item = &'static str>> {
+    let mask = category_mask(name)?;
+    Some(
+        commands()
+            .iter()
+            .enumerate()
+            .filter(move |&(index, _)| mask & (1 << index) != 0)
+            .map(|(_, spec)| spec.name),
+    )
+}
+
+fn category_mask(name: &str) -> Option<u64> {
+    let &(_, flag) = CATEGORIES
+        .iter()
+        .find(|(category, _)| category.eq_ignore_ascii_case(name))?;
+    Some(match flag {
+        None => ALL_COMMANDS,
+        Some(flag) => commands()
+            .iter()
+            .enumerate()
+            .filter(|(_, spec)| spec.has(flag))
+            .fold(0, |mask, (index, _)| mask | 1 << index),
+    })
+}
+
+fn command_mask(name: &str) -> Option<u64> {
+    commands()
+        .iter()
+        .position(|spec| spec.name.eq_ignore_ascii_case(name))
+        .map(|index| 1 << index)
+}
+
+/// A named set of credentials and permissions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    name: String,
+    enabled: bool,
+    /// Accepts any password, or none.
+    nopass: bool,
+    /// SHA-256 digests of the accepted passwords.
+    passwords: Vec<[u8; 32]>,
+    /// One bit per command table entry.
+    commands: u64,
+    /// Accepts every key; `key_patterns` is then empty.
+    all_keys: bool,
+    key_patterns: Vec<String>,
+}
+
+impl User {
+    /// A user that is off and may do nothing, as `ACL SETUSER` creates.
+    pub fn new(name: &str) -> Self {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: Vec::new(),
+            commands: 0,
+            all_keys: false,
+            key_patterns: Vec::new(),
+        }
+    }
+
+    /// The default user as the server starts: `on nopass ~* +@all`.
+    fn default_user() -> Self {
+        User {
+            enabled: true,
+            nopass: true,
+            commands: ALL_COMMANDS,
+            all_keys: true,
+            ..User::new(DEFAULT_USER)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the user may log in (`on`).
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether the user accepts any password (`nopass`).
+    pub fn nopass(&self) -> bool {
+        self.nopass
+    }
+
+    /// Applies one ACL rule, such as `on`, `>secret`, `~cache:*` or
+    /// `-@admin`.
+    pub fn apply(&mut self, rule: &str) -> Result<(), String> {
+        if let Some(password) = rule.strip_prefix('>') {
+            self.add_password(sha256(password.as_bytes()));
+        } else if let Some(password) = rule.strip_prefix('<') {
+            self.remove_password(&sha256(password.as_bytes()))?;
+        } else if let Some(hash) = rule.strip_prefix('#') {
+            self.add_password(parse_hash(hash)?);
+        } else if let Some(hash) = rule.strip_prefix('!') {
+            self.remove_password(&parse_hash(hash)?)?;
+        } else if let Some(pattern) = rule.strip_prefix('~') {
+            self.add_key_pattern(pattern)?;
+        } else if let Some(category) = rule.strip_prefix("+@") {
+            self.commands |= category_mask(category).ok_or(UNKNOWN_COMMAND)?;
+        } else if let Some(category) = rule.strip_prefix("-@") {
+            self.commands &= !category_mask(category).ok_or(UNKNOWN_COMMAND)?;
+        } else if let Some(command) = rule.strip_prefix('+') {
+            self.commands |= command_mask(command).ok_or(UNKNOWN_COMMAND)?;
+        } else if let Some(command) = rule.strip_prefix('-') {
+            self.commands &= !command_mask(command).ok_or(UNKNOWN_COMMAND)?;
+        } else {
+            match rule.to_ascii_lowercase().as_str() {
+                "on" => self.enabled = true,
+                "off" => self.enabled = false,
+                "nopass" => {
+                    self.nopass = true;
+                    self.passwords.clear();
+                }
+                "resetpass" => {
+                    self.nopass = false;
+                    self.passwords.clear();
+                }
+                "allkeys" => self.add_key_pattern("*")?,
+                "resetkeys" => {
+                    self.all_keys = false;
+                    self.key_patterns.clear();
+                }
+                "allcommands" => self.commands = ALL_COMMANDS,
+                "nocommands" => self.commands = 0,
+                "reset" => *self = User::new(&self.name),
+                _ => return Err("Syntax error".to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn add_password(&mut self, hash: [u8; 32]) {
+        self.nopass = false;
+        if !self.passwords.contains(&hash) {
+            self.passwords.push(hash);
+        }
+    }
+
+    fn remove_password(&mut self, hash: &[u8; 32]) -> Result<(), String> {
+        let before = self.passwords.len();
+        self.passwords.retain(|password| password != hash);
+        if self.passwords.len() == before {
+            return Err(
+                "The password you are trying to remove from the user does not exist".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, pattern: &str) -> Result<(), String> {
+        if pattern == "*" {
+            self.all_keys = true;
+            self.key_patterns.clear();
+            return Ok(());
+        }
+        if self.all_keys {
+            return Err(
+                "Adding a pattern after the * pattern (or the 'allkeys' flag) is not \
+                        valid and does not have any effect. Try 'resetkeys' to start with an \
+                        empty list of patterns"
+                    .to_string(),
+            );
+        }
+        if pattern.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err("Key patterns can't contain spaces or control characters".to_string());
+        }
+        if !self.key_patterns.iter().any(|known| known == pattern) {
+            self.key_patterns.push(pattern.to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether `password` is one of the user's; any password is when the
+    /// user is `nopass`.
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        let hash = sha256(password);
+        // Check every digest so the time taken does not tell which matched.
+        let matched = self.passwords.iter().fold(false, |matched, known| {
+            matched | constant_time_eq(known, &hash)
+        });
+        self.nopass || matched
+    }
+
+    /// Whether the user may run the command at table position `index`.
+    pub fn may_run(&self, index: usize) -> bool {
+        self.commands & (1 << index) != 0
+    }
+
+    /// Whether one of the user's key patterns matches `key`.
+    pub fn may_access(&self, key: &[u8]) -> bool {
+        self.all_keys
+            || self
+                .key_patterns
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), key))
+    }
+
+    /// Checks that the user may run `args`, a call to `spec`, and touch
+    /// each key it declares. The error is a `NOPERM` message naming the
+    /// command or the first key refused.
+    pub(crate) fn permit(&self, spec: &CommandSpec, args: &[Bytes]) -> Result<(), String> {
+        if !self.may_run(command_index(spec)) {
+            return Err(format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                self.name, spec.name
+            ));
+        }
+        if self.all_keys {
+            return Ok(());
+        }
+        match spec.keys_of(args).find(|key| !self.may_access(key)) {
+            Some(key) => Err(format!(
+                "NOPERM User {} has no permissions to access the '{}' key",
+                self.name,
+                printable(key)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The rules that recreate the user from `User::new`, as `ACL LIST` and
+    /// the ACL file show them.
+    pub fn describe(&self) -> String {
+        let mut rules = String::from(if self.enabled { "on" } else { "off" });
+        if self.nopass {
+            rules.push_str(" nopass");
+        }
+        for hash in &self.passwords {
+            let _ = write!(rules, " #{}", hex(hash));
+        }
+        if self.all_keys {
+            rules.push_str(" ~*");
+        }
+        for pattern in &self.key_patterns {
+            let _ = write!(rules, " ~{pattern}");
+        }
+        rules.push(' ');
+        rules.push_str(&self.describe_commands());
+        rules
+    }
+
+    /// The allowed commands as `+@all`, or as `-@all` and each command
+    /// added back.
+    pub fn describe_commands(&self) -> String {
+        if self.commands == ALL_COMMANDS {
+            return "+@all".to_string();
+        }
+        let mut rules = String::from("-@all");
+        for (index, spec) in commands().iter().enumerate() {
+            if self.may_run(index) {
+                let _ = write!(rules, " +{}", spec.name);
+            }
+        }
+        rules
+    }
+
+    /// The key patterns as `~pattern` rules separated by spaces.
+    pub fn describe_keys(&self) -> String {
+        if self.all_keys {
+            return "~*".to_string();
+        }
+        let patterns: Vec<String> = self
+            .key_patterns
+            .iter()
+            .map(|pattern| format!("~{pattern}"))
+            .collect();
+        patterns.join(" ")
+    }
+
+    /// The hex SHA-256 digests of the user's passwords.
+    pub fn password_hashes(&self) -> impl Iterator<Item = String> + '_ {
+        self.passwords.iter().map(|hash| hex(hash))
+    }
+
+    /// Whether the user may run every command.
+    pub fn all_commands(&self) -> bool {
+        self.commands == ALL_COMMANDS
+    }
+
+    /// Whether the user may access every key.
+    pub fn all_keys(&self) -> bool {
+        self.all_keys
+    }
+}
+
+const UNKNOWN_COMMAND: &str = "Unknown command or category name in ACL";
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+fn parse_hash(hex: &str) -> Result<[u8; 32], String> {
+    let invalid = || {
+        "The password hash must be exactly 64 characters and contain only lowercase \
+         hexadecimal characters"
+            .to_string()
+    };
+    if hex.len() != 64 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return Err(invalid());
+    }
+    let mut hash = [0; 32];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
+
+/// Compares two secrets in time that depends only on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `key` for an error line: lossy UTF-8 with line breaks escaped.
+fn printable(key: &[u8]) -> String {
+    String::from_utf8_lossy(key)
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// Whether `name` may name a user: nonempty, without spaces or control
+/// characters, so it fits on an ACL file line and in error replies.
+fn valid_user_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+type Users = BTreeMap<String, Arc<User>>;
+
+/// Every user, shared by all connections.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<Arc<Users>>,
+    /// Bumped by every change, so connections know to look their user up
+    /// again.
+    generation: AtomicU64,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Acl::new()
+    }
+}
+
+impl Acl {
+    /// Only the default user, as `on nopass ~* +@all`.
+    pub fn new() -> Self {
+        let default = Arc::new(User::default_user());
+        Acl {
+            users: RwLock::new(Arc::new(Users::from([(DEFAULT_USER.to_string(), default)]))),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Changes with every update to the users.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// The user called `name`.
+    pub fn user(&self, name: &str) -> Option<Arc<User>> {
+        self.snapshot().get(name).cloned()
+    }
+
+    /// Every user, ordered by name.
+    pub fn users(&self) -> Vec<Arc<User>> {
+        self.snapshot().values().cloned().collect()
+    }
+
+    /// The default user, if it is on and needs no password, so connections
+    /// may run commands as it without `AUTH`.
+    pub fn open_default_user(&self) -> Option<Arc<User>> {
+        self.user(DEFAULT_USER)
+            .filter(|user| user.enabled && user.nopass)
+    }
+
+    /// Applies `rules` to the user called `name`, creating it if needed. A
+    /// rule that fails leaves the user unchanged; the error names it.
+    pub fn set_user<'a>(
+        &self,
+        name: &str,
+        rules: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), String> {
+        if !valid_user_name(name) {
+            return Err("Usernames can't contain spaces or control characters".to_string());
+        }
+        let mut user = self
+            .user(name)
+            .map_or_else(|| User::new(name), |user| User::clone(&user));
+        for rule in rules {
+            user.apply(rule)
+                .map_err(|err| format!("Error in ACL SETUSER modifier '{rule}': {err}"))?;
+        }
+        self.update(|users| {
+            users.insert(name.to_string(), Arc::new(user));
+        });
+        Ok(())
+    }
+
+    /// Deletes the user called `name`, returning whether it existed. The
+    /// default user cannot be deleted.
+    pub fn delete_user(&self, name: &str) -> Result<bool, String> {
+        if name == DEFAULT_USER {
+            return Err("The 'default' user cannot be removed".to_string());
+        }
+        let mut deleted = false;
+        self.update(|users| deleted = users.remove(name).is_some());
+        Ok(deleted)
+    }
+
+    /// Sets the default user's only password, as `requirepass` does; an
+    /// empty one makes it `nopass`.
+    pub fn set_default_password(&self, password: &[u8]) {
+        self.update(|users| {
+            let mut default = users
+                .get(DEFAULT_USER)
+                .map_or_else(User::default_user, |user| User::clone(user));
+            default.passwords.clear();
+            default.nopass = password.is_empty();
+            if !password.is_empty() {
+                default.passwords.push(sha256(password));
+            }
+            users.insert(DEFAULT_USER.to_string(), Arc::new(default));
+        });
+    }
+
+    /// Replaces every user with those in the ACL file at `path`, keeping the
+    /// current default user if the file does not define one. Nothing changes
+    /// if any line is invalid.
+    pub fn load(&self, path: &Path) -> Result<(), ConfigFileError> {
+        let error = |line: usize, message: String| ConfigFileError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+        let text = fs::read_to_string(path).map_err(|err| error(0, err.to_string()))?;
+        let mut loaded = Users::new();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let args = split_args(line).map_err(|message| error(number, message))?;
+            let [directive, name, rules @ ..] = &args[..] else {
+                if args.is_empty() {
+                    continue;
+                }
+                return Err(error(
+                    number,
+                    "expected 'user <name> [rule...]'".to_string(),
+                ));
+            };
+            if !directive.eq_ignore_ascii_case("user") {
+                return Err(error(
+                    number,
+                    format!("unknown directive '{directive}'; expected 'user'"),
+                ));
+            }
+            if !valid_user_name(name) {
+                return Err(error(number, format!("invalid user name '{name}'")));
+            }
+            if loaded.contains_key(name) {
+                return Err(error(number, format!("duplicate user '{name}'")));
+            }
+            let mut user = User::new(name);
+            for rule in rules {
+                user.apply(rule)
+                    .map_err(|err| error(number, format!("rule '{rule}': {err}")))?;
+            }
+            loaded.insert(name.clone(), Arc::new(user));
+        }
+        self.update(|users| {
+            if !loaded.contains_key(DEFAULT_USER)
+                && let Some(default) = users.get(DEFAULT_USER)
+            {
+                loaded.insert(DEFAULT_USER.to_string(), Arc::clone(default));
+            }
+            *users = loaded;
+        });
+        Ok(())
+    }
+
+    /// Writes every user to the ACL file at `path` as `user <name> <rules>`
+    /// lines, replacing it atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = String::new();
+        for user in self.snapshot().values() {
+            out.push_str(&format!("user {} {}\n", user.name, user.describe()));
+        }
+        write_atomically(path, out.as_bytes())
+    }
+
+    fn snapshot(&self) -> Arc<Users> {
+        Arc::clone(&self.users.read().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Users)) {
+        let mut users = self.users.write().unwrap_or_else(|err| err.into_inner());
+        change(Arc::make_mut(&mut users));
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::command_spec;
+
+    fn user(rules: &str) -> User {
+        let mut user = User::new("alice");
+        for rule in rules.split_whitespace() {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    fn may_run(user: &User, name: &str) -> bool {
+        user.may_run(command_index(command_spec(name.as_bytes()).unwrap()))
+    }
+
+    fn permit(user: &User, args: &[&str]) -> Result<(), String> {
+        let args: Vec<Bytes> = args
+            .iter()
+            .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+            .collect();
+        user.permit(command_spec(&args[0]).unwrap(), &args)
+    }
+
+    #[test]
+    fn categories_follow_command_flags() {
+        let read: Vec<_> = category_commands("read").unwrap().collect();
+        assert_eq!(read, ["get", "ttl", "lolwut", "keys", "mget"]);
+        let write: Vec<_> = category_commands("WRITE").unwrap().collect();
+        assert!(write.contains(&"set") && write.contains(&"flushall"));
+        assert!(!write.contains(&"get"));
+        let admin: Vec<_> = category_commands("admin").unwrap().collect();
+        assert!(admin.contains(&"config") && admin.contains(&"acl"));
+        assert_eq!(
+            category_commands("all").unwrap().count(),
+            TRACKED_COMMANDS.len()
+        );
+        assert!(category_commands("dangerous").is_none());
+    }
+
+    #[test]
+    fn category_rules_add_and_subtract_in_order() {
+        let reader = user("+@read");
+        assert!(may_run(&reader, "get") && may_run(&reader, "mget"));
+        assert!(!may_run(&reader, "set") && !may_run(&reader, "config"));
+
+        let most = user("+@all -@admin +config");
+        assert!(may_run(&most, "set") && may_run(&most, "config"));
+        assert!(!may_run(&most, "bgsave") && !may_run(&most, "acl"));
+
+        let rewritten = user("+@write +get -@write");
+        assert!(may_run(&rewritten, "get"));
+        assert!(!may_run(&rewritten, "set") && !may_run(&rewritten, "del"));
+
+        let none = user("allcommands nocommands");
+        assert!(TRACKED_COMMANDS.iter().all(|name| !may_run(&none, name)));
+        assert!(user("allcommands").all_commands());
+        assert!(user("+@read +@write +@admin -@all +@all").all_commands());
+    }
+
+    #[test]
+    fn unknown_commands_categories_and_rules_are_errors() {
+        let mut alice = User::new("alice");
+        for rule in ["+nosuch", "-@nosuch", "+@", "bogus", "#abc"] {
+            assert!(alice.apply(rule).is_err(), "{rule}");
+        }
+        assert_eq!(alice, User::new("alice"));
+    }
+
+    #[test]
+    fn key_patterns_match_declared_keys_only() {
+        let cache = user("+@all ~cache:* ~session:?");
+        assert_eq!(permit(&cache, &["GET", "cache:1"]), Ok(()));
+        assert_eq!(permit(&cache, &["SET", "session:a", "v"]), Ok(()));
+        assert_eq!(
+            permit(&cache, &["MGET", "cache:1", "session:ab", "other"]),
+            Err("NOPERM User alice has no permissions to access the 'session:ab' key".into())
+        );
+        // Values are not keys.
+        assert_eq!(permit(&cache, &["SET", "cache:1", "other"]), Ok(()));
+        // Keyless commands need no pattern.
+        assert_eq!(permit(&cache, &["PING"]), Ok(()));
+        assert_eq!(
+            permit(&user("+get"), &["GET", "k\r\n"]),
+            Err("NOPERM User alice has no permissions to access the 'k\\r\\n' key".into())
+        );
+    }
+
+    #[test]
+    fn allkeys_and_resetkeys() {
+        let all = user("+@all ~*");
+        assert!(all.may_access(b"anything") && all.all_keys());
+        assert_eq!(all.describe_keys(), "~*");
+        let mut again = all.clone();
+        assert!(again.apply("~cache:*").is_err());
+        again.apply("resetkeys").unwrap();
+        again.apply("~cache:*").unwrap();
+        assert!(!again.may_access(b"other") && again.may_access(b"cache:x"));
+        assert!(user("allkeys").all_keys());
+        assert!(User::new("alice").apply("~has space").is_err());
+    }
+
+    #[test]
+    fn command_denial_names_the_command() {
+        assert_eq!(
+            permit(&user("+@read ~*"), &["SET", "k", "v"]),
+            Err("NOPERM User alice has no permissions to run the 'set' command".into())
+        );
+    }
+
+    #[test]
+    fn passwords_are_hashed_and_removable() {
+        let mut alice = user(">one >two");
+        assert!(alice.check_password(b"one") && alice.check_password(b"two"));
+        assert!(!alice.check_password(b"three") && !alice.check_password(b""));
+        alice.apply("<one").unwrap();
+        assert!(!alice.check_password(b"one"));
+        assert!(alice.apply("<one").is_err());
+
+        let hash = alice.password_hashes().next().unwrap();
+        assert_eq!(hash.len(), 64);
+        let mut bob = User::new("bob");
+        bob.apply(&format!("#{hash}")).unwrap();
+        assert!(bob.check_password(b"two"));
+        bob.apply(&format!("!{hash}")).unwrap();
+        assert!(!bob.check_password(b"two"));
+
+        bob.apply("nopass").unwrap();
+        assert!(bob.check_password(b"anything"));
+        bob.apply("resetpass").unwrap();
+        assert!(!bob.check_password(b"anything"));
+    }
+
+    #[test]
+    fn describe_round_trips_through_rules() {
+        for rules in [
+            "on nopass ~* +@all",
+            "off >secret ~cache:* ~s? +@read -keys +set",
+            "on -@all",
+        ] {
+            let original = user(rules);
+            let recreated = user(&original.describe());
+            assert_eq!(recreated, original, "{rules} -> {}", original.describe());
+        }
+        assert_eq!(User::default_user().describe(), "on nopass ~* +@all");
+        assert_eq!(user("+get +set").describe(), "off -@all +get +set");
+    }
+
+    #[test]
+    fn reset_returns_to_a_new_user() {
+        assert_eq!(user("on >pw ~* +@all reset"), User::new("alice"));
+    }
+
+    #[test]
+    fn acl_updates_bump_the_generation_and_keep_default() {
+        let acl = Acl::new();
+        let start = acl.generation();
+        acl.set_user("alice", ["on", ">pw", "+get"]).unwrap();
+        assert!(acl.generation() > start);
+        assert!(acl.user("alice").unwrap().check_password(b"pw"));
+
+        let failed = acl.set_user("alice", ["+set", "bogus"]);
+        assert_eq!(
+            failed,
+            Err("Error in ACL SETUSER modifier 'bogus': Syntax error".into())
+        );
+        assert!(
+            !acl.user("alice")
+                .unwrap()
+                .may_run(command_index(command_spec(b"set").unwrap()))
+        );
+        assert!(acl.set_user("bad name", ["on"]).is_err());
+
+        assert!(acl.delete_user(DEFAULT_USER).is_err());
+        assert_eq!(acl.delete_user("alice"), Ok(true));
+        assert_eq!(acl.delete_user("alice"), Ok(false));
+
+        assert!(acl.open_default_user().is_some());
+        acl.set_default_password(b"secret");
+        assert!(acl.open_default_user().is_none());
+        assert!(acl.user(DEFAULT_USER).unwrap().check_password(b"secret"));
+        acl.set_default_password(b"");
+        assert!(acl.open_default_user().is_some());
+    }
+
+    #[test]
+    fn acl_file_loads_whole_and_saves_back() {
+        let dir = std::env::temp_dir().join(format!("hkv-acl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.acl");
+        fs::write(
+            &path,
+            "# users\nuser alice on >pw ~cache:* +@read\n\nuser bob off -@all\n",
+        )
+        .unwrap();
+
+        let acl = Acl::new();
+        acl.set_default_password(b"secret");
+        acl.set_user("carol", ["on"]).unwrap();
+        acl.load(&path).unwrap();
+        let names: Vec<_> = acl.users().iter().map(|user| user.name.clone()).collect();
+        assert_eq!(names, ["alice", "bob", "default"]);
+        // The file did not define default, so the password set stays.
+        assert!(acl.open_default_user().is_none());
+
+        fs::write(&path, "user alice on\nuser alice off\n").unwrap();
+        let err = acl.load(&path).unwrap_err();
+        assert_eq!(err.line, 2);
+        fs::write(&path, "user alice +nosuch\n").unwrap();
+        assert!(acl.load(&path).is_err());
+        assert!(acl.user("bob").is_some(), "a failed load changes nothing");
+
+        acl.save(&path).unwrap();
+        let reloaded = Acl::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.users(), acl.users());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,950 @@
+//! # Command Line
+//!
+//! Parse the server's flags and optional configuration file into a typed
+//! `ServerConfig`, with the `HKV_*` environment variables as fallbacks.
+//!
+//! ## Design Principles
+//!
+//! 1. **Redis Flag Names**: Flags mirror `redis-server` options (`--port`,
+//!    `--maxmemory 100mb`, `--loglevel notice`) so existing scripts carry
+//!    over.
+//! 2. **One Precedence Order**: A flag beats the configuration file, which
+//!    beats the `HKV_*` variable, which beats the default. File directives
+//!    use the flag names, so every flag is also a directive.
+//! 3. **Fail at Startup**: Malformed values are rejected with a message
+//!    naming the flag, or the file and line, and the process exits nonzero
+//!    before binding anything. Directives the server does not know are
+//!    kept in `ServerConfig::ignored_directives` so a stock `redis.conf`
+//!    still loads.
+//! 4. **Seed, Don't Duplicate**: Settings `CONFIG` exposes are copied into
+//!    the `RuntimeConfig` once; afterwards that registry is the only copy.
+
+use std::ffi::OsString;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::builder::BoolishValueParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+
+use crate::access_log::AccessLogTarget;
+use crate::config::{
+    DEFAULT_ACCESS_LOG_SAMPLE_RATE, DEFAULT_MAX_CLIENTS, DEFAULT_REPL_BACKLOG_SIZE,
+    DEFAULT_TCP_BACKLOG, DEFAULT_TCP_KEEPALIVE_SECS, RuntimeConfig,
+};
+use crate::config_file::{self, Directive};
+#[cfg(feature = "otel")]
+use crate::otel::{self, DEFAULT_OTEL_INTERVAL_SECS, OtelConfig};
+use crate::protocol::{
+    DEFAULT_MAX_ARRAY_LEN, DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_REQUEST_LEN, RespLimits,
+};
+
+/// Listen address when neither flags nor `HKV_ADDR` give one.
+pub const DEFAULT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 6379);
+
+/// Seconds open connections may take to finish after a shutdown signal.
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 5;
+
+/// Flags accepted by `hkv-server`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "hkv-server",
+    version,
+    about = "Redis-compatible key-value server"
+)]
+pub struct Cli {
+    /// redis.conf-style configuration file; CONFIG REWRITE updates it
+    #[arg(value_name = "CONFIG_FILE")]
+    config_file: Option<PathBuf>,
+
+    /// IP addresses to listen on, separated by spaces or commas; a leading
+    /// `-` lets startup continue if that one cannot be bound
+    /// [default: 127.0.0.1]
+    #[arg(
+        long,
+        value_name = "IPS",
+        allow_hyphen_values = true,
+        value_parser = parse_bind_list
+    )]
+    bind: Option<BindList>,
+
+    /// TCP port to listen on [default: 6379]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Listen address; --bind and --port override its parts
+    #[arg(long, env = "HKV_ADDR", value_name = "IP:PORT")]
+    addr: Option<SocketAddr>,
+
+    /// Engine memory limit in bytes, with an optional unit (k, kb, m, mb,
+    /// g, gb); 0 means unlimited
+    #[arg(
+        long,
+        env = "HKV_MAXMEMORY",
+        value_name = "BYTES",
+        value_parser = parse_memory_size,
+        default_value = "0"
+    )]
+    maxmemory: u64,
+
+    /// What to evict once maxmemory is reached
+    #[arg(
+        long,
+        env = "HKV_MAXMEMORY_POLICY",
+        value_enum,
+        default_value_t = MaxMemoryPolicy::AllkeysLru
+    )]
+    maxmemory_policy: MaxMemoryPolicy,
+
+    /// Connections served at once
+    #[arg(long, env = "HKV_MAXCLIENTS", default_value_t = DEFAULT_MAX_CLIENTS)]
+    maxclients: u64,
+
+    /// Close connections idle for this many seconds; 0 disables
+    #[arg(
+        long,
+        env = "HKV_IDLE_TIMEOUT_SECS",
+        value_name = "SECS",
+        default_value_t = 0
+    )]
+    timeout: u64,
+
+    /// Directory for dump.hkv; when given, a snapshot is also saved on
+    /// shutdown [default: working directory, without the exit save]
+    #[arg(long, env = "HKV_DATA_DIR")]
+    dir: Option<PathBuf>,
+
+    /// Log verbosity; RUST_LOG applies when this is not given
+    #[arg(long, env = "HKV_LOGLEVEL", value_enum)]
+    loglevel: Option<LogLevel>,
+
+    /// Seconds open connections may take to finish after SIGINT/SIGTERM
+    #[arg(
+        long,
+        env = "HKV_SHUTDOWN_GRACE_SECS",
+        value_name = "SECS",
+        default_value_t = DEFAULT_SHUTDOWN_GRACE_SECS
+    )]
+    shutdown_grace: u64,
+
+    /// Serve Prometheus metrics over HTTP at /metrics on this address
+    #[arg(long, env = "HKV_METRICS_ADDR", value_name = "IP:PORT")]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Longest bulk string a client may send
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_memory_size,
+        default_value_t = DEFAULT_MAX_BULK_LEN as u64
+    )]
+    proto_max_bulk_len: u64,
+
+    /// Most arguments a client command may have
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_MAX_ARRAY_LEN as u64)]
+    proto_max_multibulk_len: u64,
+
+    /// Largest total size of one client command
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_memory_size,
+        default_value_t = DEFAULT_MAX_REQUEST_LEN as u64
+    )]
+    client_query_buffer_limit: u64,
+
+    /// Write one line per sampled command to this file, or `-` for stdout
+    #[arg(
+        long,
+        env = "HKV_ACCESSLOG",
+        value_name = "PATH",
+        value_parser = |value: &str| Ok::<_, String>(AccessLogTarget::parse(value))
+    )]
+    accesslog: Option<AccessLogTarget>,
+
+    /// Log one command in N to the access log; 0 logs none
+    #[arg(
+        long,
+        env = "HKV_ACCESSLOG_SAMPLE_RATE",
+        value_name = "N",
+        default_value_t = DEFAULT_ACCESS_LOG_SAMPLE_RATE
+    )]
+    accesslog_sample_rate: u64,
+
+    /// Refuse write commands with -READONLY; CONFIG SET read-only toggles it
+    #[arg(long, env = "HKV_READ_ONLY", value_parser = BoolishValueParser::new())]
+    read_only: bool,
+
+    /// Recent replication stream kept for replicas that reconnect
+    #[arg(
+        long,
+        env = "HKV_REPL_BACKLOG_SIZE",
+        value_name = "BYTES",
+        value_parser = parse_memory_size,
+        default_value_t = DEFAULT_REPL_BACKLOG_SIZE
+    )]
+    repl_backlog_size: u64,
+
+    /// Disable Nagle's algorithm on client sockets (yes or no)
+    #[arg(
+        long,
+        env = "HKV_TCP_NODELAY",
+        value_name = "yes|no",
+        action = ArgAction::Set,
+        value_parser = parse_flag,
+        default_value = "yes"
+    )]
+    tcp_nodelay: bool,
+
+    /// Seconds of silence before keepalive probes are sent; 0 disables
+    #[arg(
+        long,
+        env = "HKV_TCP_KEEPALIVE",
+        value_name = "SECS",
+        default_value_t = DEFAULT_TCP_KEEPALIVE_SECS
+    )]
+    tcp_keepalive: u64,
+
+    /// Pending connections the listener queues before refusing more
+    #[arg(
+        long,
+        env = "HKV_TCP_BACKLOG",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_TCP_BACKLOG
+    )]
+    tcp_backlog: u32,
+
+    /// Accept loops per listen address, each on its own SO_REUSEPORT
+    /// socket; 0 or no value for one per worker thread [default: one per
+    /// worker thread]
+    #[arg(
+        long,
+        env = "HKV_ACCEPTORS",
+        value_name = "COUNT",
+        num_args = 0..=1,
+        default_value_t = 0,
+        hide_default_value = true,
+        default_missing_value = "0"
+    )]
+    acceptors: usize,
+
+    /// Refuse non-loopback clients while no password is set (yes or no)
+    #[arg(
+        long,
+        env = "HKV_PROTECTED_MODE",
+        value_name = "yes|no",
+        action = ArgAction::Set,
+        value_parser = parse_flag,
+        default_value = "yes"
+    )]
+    protected_mode: bool,
+
+    /// Password clients must give with AUTH before running commands
+    #[arg(long, env = "HKV_REQUIREPASS", value_name = "PASSWORD")]
+    requirepass: Option<String>,
+
+    /// ACL file of `user <name> <rules>` lines, loaded at startup and by
+    /// ACL LOAD and written by ACL SAVE
+    #[arg(long, env = "HKV_ACLFILE", value_name = "PATH")]
+    aclfile: Option<PathBuf>,
+
+    /// Serve connections through io_uring on Linux 5.19 or later, in
+    /// builds with the uring feature (yes or no) [default: no]
+    #[arg(
+        long,
+        env = "HKV_IO_URING",
+        value_name = "yes|no",
+        action = ArgAction::Set,
+        value_parser = parse_flag
+    )]
+    io_uring: Option<bool>,
+
+    /// Export metrics to this OTLP/HTTP collector, such as
+    /// http://localhost:4318
+    #[cfg(feature = "otel")]
+    #[arg(long, env = "HKV_OTEL_ENDPOINT", value_name = "URL")]
+    otel_endpoint: Option<String>,
+
+    /// Headers sent with each export, as name=value pairs separated by
+    /// commas
+    #[cfg(feature = "otel")]
+    #[arg(
+        long,
+        env = "HKV_OTEL_HEADERS",
+        value_name = "HEADERS",
+        value_parser = parse_otel_headers
+    )]
+    otel_headers: Option<OtelHeaders>,
+
+    /// Seconds between metric exports
+    #[cfg(feature = "otel")]
+    #[arg(
+        long,
+        env = "HKV_OTEL_INTERVAL",
+        value_name = "SECS",
+        value_parser = parse_otel_interval,
+        default_value_t = DEFAULT_OTEL_INTERVAL_SECS
+    )]
+    otel_interval: u64,
+
+    /// Export command spans as well as metrics (yes or no)
+    #[cfg(feature = "otel")]
+    #[arg(
+        long,
+        env = "HKV_OTEL_TRACES",
+        value_name = "yes|no",
+        action = ArgAction::Set,
+        value_parser = parse_flag,
+        default_value = "no"
+    )]
+    otel_traces: bool,
+}
+
+impl Cli {
+    /// Applies a configuration file directive named like a flag; `Ok(false)`
+    /// if no flag has that name.
+    fn apply(&mut self, directive: &Directive) -> Result<bool, String> {
+        let value = || match &directive.args[..] {
+            [value] => Ok(value.as_str()),
+            _ => Err("expected exactly one argument".to_string()),
+        };
+        match directive.name.as_str() {
+            "bind" => self.bind = Some(parse_bind_list(&directive.args.join(" "))?),
+            "port" => self.port = Some(parse_value(value()?)?),
+            "addr" => self.addr = Some(parse_value(value()?)?),
+            "maxmemory" => self.maxmemory = parse_memory_size(value()?)?,
+            "maxmemory-policy" => {
+                self.maxmemory_policy = MaxMemoryPolicy::from_str(value()?, true)?
+            }
+            "maxclients" => self.maxclients = parse_value(value()?)?,
+            "timeout" => self.timeout = parse_value(value()?)?,
+            "dir" => self.dir = Some(PathBuf::from(value()?)),
+            "loglevel" => self.loglevel = Some(LogLevel::from_str(value()?, true)?),
+            "shutdown-grace" => self.shutdown_grace = parse_value(value()?)?,
+            "metrics-addr" => self.metrics_addr = Some(parse_value(value()?)?),
+            "proto-max-bulk-len" => self.proto_max_bulk_len = parse_memory_size(value()?)?,
+            "proto-max-multibulk-len" => self.proto_max_multibulk_len = parse_value(value()?)?,
+            "client-query-buffer-limit" => {
+                self.client_query_buffer_limit = parse_memory_size(value()?)?
+            }
+            "accesslog" => self.accesslog = Some(AccessLogTarget::parse(value()?)),
+            "accesslog-sample-rate" => self.accesslog_sample_rate = parse_value(value()?)?,
+            "read-only" => self.read_only = parse_flag(value()?)?,
+            "repl-backlog-size" => self.repl_backlog_size = parse_memory_size(value()?)?,
+            "tcp-nodelay" => self.tcp_nodelay = parse_flag(value()?)?,
+            "tcp-keepalive" => self.tcp_keepalive = parse_value(value()?)?,
+            "tcp-backlog" => self.tcp_backlog = parse_value(value()?)?,
+            "acceptors" => self.acceptors = parse_value(value()?)?,
+            "protected-mode" => self.protected_mode = parse_flag(value()?)?,
+            "io-uring" => self.io_uring = Some(parse_flag(value()?)?),
+            "requirepass" => self.requirepass = Some(value()?.to_string()),
+            "aclfile" => self.aclfile = Some(PathBuf::from(value()?)),
+            #[cfg(feature = "otel")]
+            "otel-endpoint" => self.otel_endpoint = Some(value()?.to_string()),
+            #[cfg(feature = "otel")]
+            "otel-headers" => self.otel_headers = Some(parse_otel_headers(value()?)?),
+            #[cfg(feature = "otel")]
+            "otel-interval" => self.otel_interval = parse_otel_interval(value()?)?,
+            #[cfg(feature = "otel")]
+            "otel-traces" => self.otel_traces = parse_flag(value()?)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("invalid value '{value}': {err}"))
+}
+
+/// Parses an on/off directive: `yes`/`no` as in redis.conf, or the `1`/`0`
+/// that `CONFIG REWRITE` writes.
+fn parse_flag(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" | "1" => Ok(true),
+        "no" | "0" => Ok(false),
+        _ => Err(format!("invalid value '{value}': expected yes or no")),
+    }
+}
+
+/// The pairs of one `--otel-headers` flag or `otel-headers` directive.
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OtelHeaders(Vec<(String, String)>);
+
+#[cfg(feature = "otel")]
+fn parse_otel_headers(input: &str) -> Result<OtelHeaders, String> {
+    otel::parse_headers(input).map(OtelHeaders)
+}
+
+/// Parses `--otel-interval`, which must be at least a second.
+#[cfg(feature = "otel")]
+fn parse_otel_interval(input: &str) -> Result<u64, String> {
+    match parse_value(input)? {
+        0 => Err("expected at least 1 second".to_string()),
+        secs => Ok(secs),
+    }
+}
+
+/// Eviction policies for `--maxmemory-policy`.
+///
+/// The engine always evicts least-recently-used keys, so that is the only
+/// policy offered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MaxMemoryPolicy {
+    /// Evict the least recently used keys, with or without a TTL.
+    AllkeysLru,
+}
+
+/// `redis-server` log levels for `--loglevel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    /// Everything, including each command's arguments.
+    Debug,
+    /// Per-connection and per-command spans.
+    Verbose,
+    /// Lifecycle events and warnings.
+    Notice,
+    /// Warnings only.
+    Warning,
+    /// No logging.
+    Nothing,
+}
+
+impl LogLevel {
+    /// The equivalent `tracing_subscriber::EnvFilter` directive.
+    pub fn filter(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "trace",
+            LogLevel::Verbose => "debug",
+            LogLevel::Notice => "info",
+            LogLevel::Warning => "warn",
+            LogLevel::Nothing => "off",
+        }
+    }
+}
+
+/// One `--bind` address, before the port is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BindIp {
+    ip: IpAddr,
+    optional: bool,
+}
+
+/// The addresses of one `--bind` flag or `bind` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BindList(Vec<BindIp>);
+
+/// Parses a `bind` list as in redis.conf: addresses separated by spaces
+/// (or commas), each optionally prefixed with `-`.
+fn parse_bind_list(input: &str) -> Result<BindList, String> {
+    let ips = input
+        .split(|c: char| c == ',' || c.is_ascii_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (optional, ip) = match item.strip_prefix('-') {
+                Some(ip) => (true, ip),
+                None => (false, item),
+            };
+            Ok(BindIp {
+                ip: parse_value(ip)?,
+                optional,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if ips.is_empty() {
+        return Err("expected at least one address".to_string());
+    }
+    Ok(BindList(ips))
+}
+
+/// An address the RESP server listens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenAddr {
+    /// Address to bind.
+    pub addr: SocketAddr,
+    /// Whether startup goes on without it if it cannot be bound, as for a
+    /// `-`-prefixed `bind` address in Redis.
+    pub optional: bool,
+}
+
+/// Startup configuration, resolved from flags and environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// Addresses the RESP listeners bind, one listener each.
+    pub listen_addrs: Vec<ListenAddr>,
+    /// Engine memory limit in bytes; 0 for unlimited.
+    pub max_memory: u64,
+    /// Eviction policy applied at `max_memory`.
+    pub max_memory_policy: MaxMemoryPolicy,
+    /// Initial `maxclients` setting.
+    pub max_clients: u64,
+    /// Initial `timeout` setting in seconds.
+    pub idle_timeout_secs: u64,
+    /// Snapshot directory; `None` uses the working directory and skips the
+    /// save on exit.
+    pub data_dir: Option<PathBuf>,
+    /// Log level; `None` defers to `RUST_LOG`, then `notice`.
+    pub log_level: Option<LogLevel>,
+    /// Grace period for open connections after a shutdown signal.
+    pub shutdown_grace: Duration,
+    /// Address of the Prometheus exporter, if enabled.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Initial RESP parser limits.
+    pub resp_limits: RespLimits,
+    /// Where the access log is written, if enabled.
+    pub access_log: Option<AccessLogTarget>,
+    /// Initial `accesslog-sample-rate` setting.
+    pub access_log_sample_rate: u64,
+    /// Whether to start in read-only mode.
+    pub read_only: bool,
+    /// Initial `repl-backlog-size` setting.
+    pub repl_backlog_size: u64,
+    /// Initial `tcp-nodelay` setting.
+    pub tcp_nodelay: bool,
+    /// Initial `tcp-keepalive` setting in seconds.
+    pub tcp_keepalive_secs: u64,
+    /// Listen backlog the RESP listener is bound with.
+    pub tcp_backlog: u32,
+    /// Accept loops per listen address as given; 0 for one per worker
+    /// thread. See `acceptor_count`.
+    pub acceptors: usize,
+    /// Initial `protected-mode` setting.
+    pub protected_mode: bool,
+    /// Initial `requirepass` setting.
+    pub requirepass: Option<String>,
+    /// ACL file to load users from at startup.
+    pub aclfile: Option<PathBuf>,
+    /// Whether to serve through io_uring, if given; see
+    /// `RuntimeConfig::io_uring` for the default.
+    pub io_uring: Option<bool>,
+    /// Where to export metrics and spans, if enabled.
+    #[cfg(feature = "otel")]
+    pub otel: Option<OtelConfig>,
+    /// Configuration file the settings were read from.
+    pub config_file: Option<PathBuf>,
+    /// Directives in the configuration file that the server does not know.
+    pub ignored_directives: Vec<Directive>,
+}
+
+impl ServerConfig {
+    /// Parses the process arguments and configuration file, printing usage
+    /// and exiting on error.
+    pub fn from_args() -> Self {
+        Self::try_from_args(std::env::args_os()).unwrap_or_else(|err| err.exit())
+    }
+
+    /// Parses `args` (program name first) and the configuration file they
+    /// name.
+    pub fn try_from_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Cli::command();
+        let matches = command.try_get_matches_from_mut(args)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        let mut ignored_directives = Vec::new();
+        if let Some(path) = cli.config_file.clone() {
+            let directives =
+                config_file::load(&path).map_err(|err| command.error(ErrorKind::Io, err))?;
+            for directive in directives {
+                if set_on_command_line(&matches, &directive.name) {
+                    continue;
+                }
+                match cli.apply(&directive) {
+                    Ok(true) => {}
+                    Ok(false) => ignored_directives.push(directive),
+                    Err(message) => {
+                        let location = format!("{}:{}", directive.path.display(), directive.line);
+                        return Err(command.error(
+                            ErrorKind::InvalidValue,
+                            format!("{location}: {}: {message}", directive.name),
+                        ));
+                    }
+                }
+            }
+        }
+        let mut config = ServerConfig::from(cli);
+        config.ignored_directives = ignored_directives;
+        Ok(config)
+    }
+
+    /// A `RuntimeConfig` seeded with the startup values, so `CONFIG GET`
+    /// reports them and `CONFIG REWRITE` knows the file.
+    pub fn runtime_config(&self) -> RuntimeConfig {
+        let mut runtime = RuntimeConfig::new();
+        if let Some(path) = &self.config_file {
+            runtime = runtime.with_config_file(path);
+        }
+        if let Some(path) = &self.aclfile {
+            runtime = runtime.with_aclfile(path);
+        }
+        runtime.set_max_clients(self.max_clients);
+        runtime.set_idle_timeout_secs(self.idle_timeout_secs);
+        runtime.set_max_memory(self.max_memory);
+        runtime.set_resp_limits(self.resp_limits);
+        runtime.set_access_log_sample_rate(self.access_log_sample_rate);
+        runtime.set_read_only(self.read_only);
+        runtime.set_repl_backlog_size(self.repl_backlog_size);
+        runtime.set_tcp_nodelay(self.tcp_nodelay);
+        runtime.set_tcp_keepalive_secs(self.tcp_keepalive_secs);
+        runtime.set_tcp_backlog(self.tcp_backlog);
+        runtime.set_protected_mode(self.protected_mode);
+        if let Some(password) = &self.requirepass {
+            runtime.set_requirepass(password.as_bytes());
+        }
+        if let Some(enabled) = self.io_uring {
+            runtime.set_io_uring(enabled);
+        }
+        runtime
+    }
+
+    /// Listeners to bind per address: `acceptors`, or `workers` when it
+    /// is 0. Without `SO_REUSEPORT` the default is a single listener.
+    pub fn acceptor_count(&self, workers: usize) -> usize {
+        match self.acceptors {
+            0 if cfg!(unix) => workers.max(1),
+            0 => 1,
+            count => count,
+        }
+    }
+
+    /// Byte capacity to create the engine with.
+    pub fn engine_capacity(&self) -> usize {
+        match self.max_memory {
+            0 => usize::MAX,
+            bytes => usize::try_from(bytes).unwrap_or(usize::MAX),
+        }
+    }
+}
+
+impl From<Cli> for ServerConfig {
+    fn from(cli: Cli) -> Self {
+        let base = cli.addr.unwrap_or(DEFAULT_ADDR);
+        ServerConfig {
+            listen_addrs: cli
+                .bind
+                .map_or_else(
+                    || {
+                        vec![BindIp {
+                            ip: base.ip(),
+                            optional: false,
+                        }]
+                    },
+                    |BindList(ips)| ips,
+                )
+                .into_iter()
+                .map(|bind| ListenAddr {
+                    addr: SocketAddr::new(bind.ip, cli.port.unwrap_or(base.port())),
+                    optional: bind.optional,
+                })
+                .collect(),
+            max_memory: cli.maxmemory,
+            max_memory_policy: cli.maxmemory_policy,
+            max_clients: cli.maxclients,
+            idle_timeout_secs: cli.timeout,
+            data_dir: cli.dir,
+            log_level: cli.loglevel,
+            shutdown_grace: Duration::from_secs(cli.shutdown_grace),
+            metrics_addr: cli.metrics_addr,
+            resp_limits: RespLimits {
+                max_array_len: to_usize(cli.proto_max_multibulk_len),
+                max_bulk_len: to_usize(cli.proto_max_bulk_len),
+                max_request_len: to_usize(cli.client_query_buffer_limit),
+            },
+            access_log: cli.accesslog,
+            access_log_sample_rate: cli.accesslog_sample_rate,
+            read_only: cli.read_only,
+            repl_backlog_size: cli.repl_backlog_size,
+            tcp_nodelay: cli.tcp_nodelay,
+            tcp_keepalive_secs: cli.tcp_keepalive,
+            tcp_backlog: cli.tcp_backlog,
+            acceptors: cli.acceptors,
+            protected_mode: cli.protected_mode,
+            requirepass: cli.requirepass,
+            aclfile: cli.aclfile,
+            io_uring: cli.io_uring,
+            #[cfg(feature = "otel")]
+            otel: cli.otel_endpoint.map(|endpoint| OtelConfig {
+                endpoint,
+                headers: cli
+                    .otel_headers
+                    .map(|OtelHeaders(headers)| headers)
+                    .unwrap_or_default(),
+                interval: Duration::from_secs(cli.otel_interval),
+                traces: cli.otel_traces,
+            }),
+            config_file: cli.config_file,
+            ignored_directives: Vec::new(),
+        }
+    }
+}
+
+/// Whether the flag a directive is named after was given on the command
+/// line, which takes precedence over the file.
+fn set_on_command_line(matches: &ArgMatches, directive: &str) -> bool {
+    let id = directive.replace('-', "_");
+    matches.ids().any(|known| {
+        known.as_str() == id && matches.value_source(&id) == Some(ValueSource::CommandLine)
+    })
+}
+
+fn to_usize(value: u64) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+/// Parses a `redis.conf`-style memory size: a byte count with an optional,
+/// case-insensitive unit. `k`, `m` and `g` are powers of 1000; `kb`, `mb`
+/// and `gb` powers of 1024.
+pub fn parse_memory_size(input: &str) -> Result<u64, String> {
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+    if digits.is_empty() {
+        return Err(format!("expected a number of bytes, got '{input}'"));
+    }
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "unknown unit '{unit}' (expected b, k, kb, m, mb, g or gb)"
+            ));
+        }
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{input}' does not fit in 64 bits"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ServerConfig, clap::Error> {
+        ServerConfig::try_from_args(std::iter::once("hkv-server").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn memory_sizes_follow_redis_units() {
+        assert_eq!(parse_memory_size("0"), Ok(0));
+        assert_eq!(parse_memory_size("1024"), Ok(1024));
+        assert_eq!(parse_memory_size("1k"), Ok(1000));
+        assert_eq!(parse_memory_size("100mb"), Ok(100 << 20));
+        assert_eq!(parse_memory_size("2GB"), Ok(2 << 30));
+        assert!(parse_memory_size("mb").is_err());
+        assert!(parse_memory_size("100xb").unwrap_err().contains("'xb'"));
+        assert!(parse_memory_size("-1").is_err());
+        assert!(parse_memory_size("99999999999gb").is_err());
+    }
+
+    #[test]
+    fn flags_build_the_server_config() {
+        let config = parse(&[
+            "--bind",
+            "0.0.0.0",
+            "--port",
+            "7000",
+            "--maxmemory",
+            "64mb",
+            "--maxclients",
+            "50",
+            "--timeout",
+            "30",
+            "--dir",
+            "/var/lib/hkv",
+            "--loglevel",
+            "warning",
+            "--accesslog",
+            "-",
+            "--accesslog-sample-rate",
+            "10",
+            "--repl-backlog-size",
+            "64kb",
+            "--tcp-nodelay",
+            "no",
+            "--tcp-keepalive",
+            "60",
+            "--tcp-backlog",
+            "1024",
+            "--io-uring",
+            "yes",
+            "--acceptors",
+            "4",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.listen_addrs,
+            [ListenAddr {
+                addr: "0.0.0.0:7000".parse().unwrap(),
+                optional: false,
+            }]
+        );
+        assert_eq!(config.max_memory, 64 << 20);
+        assert_eq!(config.engine_capacity(), 64 << 20);
+        assert_eq!(config.data_dir, Some(PathBuf::from("/var/lib/hkv")));
+        assert_eq!(config.log_level.map(LogLevel::filter), Some("warn"));
+        assert_eq!(config.access_log, Some(AccessLogTarget::Stdout));
+
+        let runtime = config.runtime_config();
+        assert_eq!(runtime.get("maxclients"), Some(("maxclients", 50)));
+        assert_eq!(runtime.get("timeout"), Some(("timeout", 30)));
+        assert_eq!(runtime.get("maxmemory"), Some(("maxmemory", 64 << 20)));
+        assert_eq!(runtime.access_log_sample_rate(), 10);
+        assert_eq!(runtime.repl_backlog_size(), 64 << 10);
+        assert_eq!(runtime.get("tcp-nodelay"), Some(("tcp-nodelay", 0)));
+        assert_eq!(runtime.get("tcp-keepalive"), Some(("tcp-keepalive", 60)));
+        assert_eq!(runtime.get("tcp-backlog"), Some(("tcp-backlog", 1024)));
+        assert!(runtime.io_uring());
+        assert_eq!(config.acceptor_count(8), 4);
+    }
+
+    #[test]
+    fn port_and_bind_override_parts_of_addr() {
+        let addrs = |config: ServerConfig| -> Vec<_> {
+            config
+                .listen_addrs
+                .iter()
+                .map(|listen| (listen.addr.to_string(), listen.optional))
+                .collect()
+        };
+        let config = parse(&["--addr", "10.0.0.1:7000", "--port", "7001"]).unwrap();
+        assert_eq!(addrs(config), [("10.0.0.1:7001".to_string(), false)]);
+        assert_eq!(parse(&[]).unwrap().engine_capacity(), usize::MAX);
+        assert_eq!(parse(&[]).unwrap().acceptor_count(6), 6);
+        assert_eq!(parse(&["--acceptors"]).unwrap().acceptor_count(6), 6);
+        assert_eq!(parse(&["--acceptors", "1"]).unwrap().acceptor_count(6), 1);
+
+        let config = parse(&["--bind", "127.0.0.1, -::1", "--port", "7002"]).unwrap();
+        assert_eq!(
+            addrs(config),
+            [
+                ("127.0.0.1:7002".to_string(), false),
+                ("[::1]:7002".to_string(), true)
+            ]
+        );
+        let err = parse(&["--bind", " , "]).unwrap_err().to_string();
+        assert!(err.contains("expected at least one address"), "{err}");
+    }
+
+    #[test]
+    fn protected_mode_holds_until_a_password_is_set() {
+        assert!(parse(&[]).unwrap().runtime_config().is_protected());
+        let config = parse(&["--bind", "0.0.0.0"]).unwrap();
+        assert!(config.runtime_config().is_protected());
+        let config = parse(&["--requirepass", "hunter2"]).unwrap();
+        let runtime = config.runtime_config();
+        assert!(!runtime.is_protected());
+        assert_eq!(runtime.requirepass().as_deref(), Some(&b"hunter2"[..]));
+        let config = parse(&["--protected-mode", "no"]).unwrap();
+        assert!(!config.runtime_config().is_protected());
+    }
+
+    #[test]
+    fn aclfile_is_recorded_for_acl_load_and_save() {
+        assert_eq!(parse(&[]).unwrap().runtime_config().aclfile(), None);
+        let config = parse(&["--aclfile", "users.acl"]).unwrap();
+        assert_eq!(
+            config.aclfile.as_deref(),
+            Some(std::path::Path::new("users.acl"))
+        );
+        assert_eq!(
+            config.runtime_config().aclfile(),
+            Some(std::path::Path::new("users.acl"))
+        );
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn otel_flags_build_the_exporter_config() {
+        assert_eq!(parse(&[]).unwrap().otel, None);
+        let config = parse(&[
+            "--otel-endpoint",
+            "http://collector:4318",
+            "--otel-headers",
+            "x-api-key=secret",
+            "--otel-traces",
+            "yes",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.otel,
+            Some(OtelConfig {
+                endpoint: "http://collector:4318".to_string(),
+                headers: vec![("x-api-key".to_string(), "secret".to_string())],
+                interval: Duration::from_secs(DEFAULT_OTEL_INTERVAL_SECS),
+                traces: true,
+            })
+        );
+        let err = parse(&["--otel-interval", "0"]).unwrap_err().to_string();
+        assert!(err.contains("at least 1 second"), "{err}");
+    }
+
+    #[test]
+    fn flags_override_the_file_which_overrides_defaults() {
+        let path = std::env::temp_dir().join(format!("hkv-cli-{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            "port 7000\nmaxclients 50\nmaxmemory 1mb\nsave 900 1\nproto-max-bulk-len 4kb\n\
+             read-only yes\nbind 127.0.0.1 -::1\n",
+        )
+        .unwrap();
+        let file = path.to_str().unwrap();
+
+        let config = parse(&[file, "--port", "7100"]).unwrap();
+        let bound: Vec<_> = config.listen_addrs.iter().map(|l| l.addr).collect();
+        assert_eq!(
+            bound,
+            [
+                "127.0.0.1:7100".parse().unwrap(),
+                "[::1]:7100".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.max_clients, 50);
+        assert_eq!(config.max_memory, 1 << 20);
+        assert_eq!(config.resp_limits.max_bulk_len, 4096);
+        assert!(config.runtime_config().read_only());
+        assert_eq!(config.config_file.as_deref(), Some(path.as_path()));
+        let ignored: Vec<_> = config.ignored_directives.iter().map(|d| d.line).collect();
+        assert_eq!(ignored, [4]);
+        assert_eq!(config.runtime_config().config_file(), Some(path.as_path()));
+
+        std::fs::write(&path, "port 7000\nmaxclients lots\n").unwrap();
+        let err = parse(&[file]).unwrap_err().to_string();
+        assert!(
+            err.contains(&format!("{file}:2: maxclients: invalid value 'lots'")),
+            "{err}"
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(parse(&[file]).is_err());
+    }
+
+    #[test]
+    fn invalid_values_name_the_flag() {
+        let err = parse(&["--maxmemory", "100xb"]).unwrap_err().to_string();
+        assert!(err.contains("--maxmemory"), "{err}");
+        assert!(err.contains("unknown unit 'xb'"), "{err}");
+
+        let err = parse(&["--maxmemory-policy", "volatile-lru"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("allkeys-lru"), "{err}");
+    }
+}
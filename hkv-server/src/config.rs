@@ -0,0 +1,1003 @@
+//! # Runtime Configuration
+//!
+//! Server settings that clients can read and change while the server runs,
+//! through `CONFIG GET` and `CONFIG SET`.
+//!
+//! ## Design Principles
+//!
+//! 1. **Shared, Lock-Free**: Every connection holds the same `RuntimeConfig`
+//!    and reads it with relaxed atomics, so a `CONFIG SET` on one connection
+//!    applies to all of them without coordination.
+//! 2. **Redis Names and Units**: Parameters keep their Redis names, units and
+//!    defaults (`timeout` in seconds with `0` = disabled, `maxclients`
+//!    10000, `proto-max-bulk-len` 512 MiB) so existing tooling works.
+//! 3. **Read Where Applied**: `timeout` is read each time a connection starts
+//!    waiting, `maxclients`, `maxconn-per-ip-per-sec`, `tcp-nodelay` and
+//!    `tcp-keepalive` at each accept, `maxcmd-per-sec` before each command,
+//!    `lua-time-limit` as each script starts, `command-time-limit` as each
+//!    `KEYS` starts, `read-buffer-shrink-after` after each read,
+//!    `latency-monitor-threshold` as each command or background cycle ends
+//!    and the parser limits before each batch of input is parsed, so changes
+//!    apply from then on.
+//! 4. **One Registry**: `PARAMETERS` maps each `CONFIG` name to its field, so
+//!    adding a setting is one table row plus typed accessors.
+//! 5. **Startup-Only Settings**: Parameters fixed at startup, such as
+//!    `maxmemory` and `tcp-backlog`, are readable through `CONFIG GET` but
+//!    rejected by `CONFIG SET`.
+//! 6. **Numeric Flags**: On/off settings such as `read-only` are stored as
+//!    `0`/`1` like every other parameter; any nonzero value turns them on.
+//! 7. **Compound Limits**: `client-output-buffer-limit` holds three numbers
+//!    per `ClientClass`, so it lives outside `PARAMETERS` and is read and
+//!    written whole in Redis's `<class> <hard> <soft> <seconds>` format.
+//! 8. **Bytes Beside Numbers**: `requirepass` is a byte string, so it too
+//!    lives outside `PARAMETERS`, behind a lock read at accept and by
+//!    connections that have not authenticated. Setting it also sets the
+//!    password of the ACL's default user.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use hkv_engine::{HealthMonitor, KernelHealth};
+
+use crate::access_log::AccessLog;
+use crate::acl::Acl;
+use crate::lifecycle::ServerLifecycle;
+use crate::protocol::{
+    DEFAULT_MAX_ARRAY_LEN, DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_REQUEST_LEN, RespLimits,
+};
+
+/// Default `maxclients`, as in Redis.
+pub const DEFAULT_MAX_CLIENTS: u64 = 10_000;
+
+/// Default `accesslog-sample-rate`: every command is logged.
+pub const DEFAULT_ACCESS_LOG_SAMPLE_RATE: u64 = 1;
+
+/// Default `repl-backlog-size`, as in Redis.
+pub const DEFAULT_REPL_BACKLOG_SIZE: u64 = 1 << 20;
+
+/// Default `tracking-table-max-keys`, as in Redis.
+pub const DEFAULT_TRACKING_TABLE_MAX_KEYS: u64 = 1_000_000;
+
+/// Default `tcp-keepalive` in seconds, as in Redis.
+pub const DEFAULT_TCP_KEEPALIVE_SECS: u64 = 300;
+
+/// Default `read-buffer-shrink-after`: reads in a row that fill a small
+/// share of a connection's read buffer before it is swapped for a smaller
+/// one.
+pub const DEFAULT_READ_BUFFER_SHRINK_AFTER: u64 = 32;
+
+/// Default `tcp-backlog`, as in Redis.
+pub const DEFAULT_TCP_BACKLOG: u32 = 511;
+
+/// Default `lua-time-limit` in milliseconds, as in Redis.
+pub const DEFAULT_LUA_TIME_LIMIT_MS: u64 = 5000;
+
+/// Connections `client-output-buffer-limit` sets separate limits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientClass {
+    /// Connections answering their own commands.
+    Normal,
+    /// Replicas streaming the replication feed.
+    Replica,
+    /// Connections receiving pushes they did not ask for; here, tracking
+    /// invalidations.
+    Pubsub,
+}
+
+impl ClientClass {
+    /// Every class, in `client-output-buffer-limit` order.
+    pub const ALL: [ClientClass; 3] = [
+        ClientClass::Normal,
+        ClientClass::Replica,
+        ClientClass::Pubsub,
+    ];
+
+    /// The class name `CONFIG GET` reports, as Redis does.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientClass::Normal => "normal",
+            ClientClass::Replica => "slave",
+            ClientClass::Pubsub => "pubsub",
+        }
+    }
+
+    /// Parses a class name (any case); `replica` and `slave` are the same.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "normal" => Some(ClientClass::Normal),
+            "replica" | "slave" => Some(ClientClass::Replica),
+            "pubsub" => Some(ClientClass::Pubsub),
+            _ => None,
+        }
+    }
+}
+
+/// One class's output buffer limits; 0 disables a limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputBufferLimit {
+    /// Bytes queued that close the connection at once.
+    pub hard_bytes: u64,
+    /// Bytes queued that close the connection if they stay queued for
+    /// `soft_seconds`.
+    pub soft_bytes: u64,
+    /// Seconds past `soft_bytes` that close the connection.
+    pub soft_seconds: u64,
+}
+
+impl OutputBufferLimit {
+    /// Whether `queued` bytes break the limit at `now`: past the hard
+    /// limit, or past the soft limit ever since `soft_since`, which this
+    /// sets when the soft limit is first passed and clears once back under.
+    pub fn exceeded(&self, queued: u64, soft_since: &mut Option<Instant>, now: Instant) -> bool {
+        if self.hard_bytes > 0 && queued > self.hard_bytes {
+            return true;
+        }
+        if self.soft_bytes == 0 || queued <= self.soft_bytes {
+            *soft_since = None;
+            return false;
+        }
+        let since = *soft_since.get_or_insert(now);
+        now.duration_since(since) >= Duration::from_secs(self.soft_seconds)
+    }
+}
+
+/// Default `client-output-buffer-limit` per `ClientClass::ALL`, as in Redis.
+pub const DEFAULT_OUTPUT_BUFFER_LIMITS: [OutputBufferLimit; 3] = [
+    OutputBufferLimit {
+        hard_bytes: 0,
+        soft_bytes: 0,
+        soft_seconds: 0,
+    },
+    OutputBufferLimit {
+        hard_bytes: 256 << 20,
+        soft_bytes: 64 << 20,
+        soft_seconds: 60,
+    },
+    OutputBufferLimit {
+        hard_bytes: 32 << 20,
+        soft_bytes: 8 << 20,
+        soft_seconds: 60,
+    },
+];
+
+/// Settings adjustable at runtime.
+#[derive(Debug)]
+pub struct RuntimeConfig {
+    idle_timeout_secs: AtomicU64,
+    max_clients: AtomicU64,
+    max_bulk_len: AtomicU64,
+    max_array_len: AtomicU64,
+    max_request_len: AtomicU64,
+    max_memory: AtomicU64,
+    access_log_sample_rate: AtomicU64,
+    read_only: AtomicU64,
+    repl_backlog_size: AtomicU64,
+    tracking_table_max_keys: AtomicU64,
+    tcp_nodelay: AtomicU64,
+    tcp_keepalive_secs: AtomicU64,
+    tcp_backlog: AtomicU64,
+    acceptors: AtomicU64,
+    max_cmd_per_sec: AtomicU64,
+    max_conn_per_ip_per_sec: AtomicU64,
+    lua_time_limit_ms: AtomicU64,
+    command_time_limit_ms: AtomicU64,
+    latency_monitor_threshold_ms: AtomicU64,
+    read_buffer_shrink_after: AtomicU64,
+    protected_mode: AtomicU64,
+    io_uring: AtomicU64,
+    /// Password clients must `AUTH` with; `None` when unset.
+    requirepass: RwLock<Option<Arc<[u8]>>>,
+    /// Users and their permissions.
+    acl: Acl,
+    /// File `ACL LOAD` and `ACL SAVE` use, if the server was started with one.
+    aclfile: Option<PathBuf>,
+    /// Hard bytes, soft bytes and soft seconds per `ClientClass::ALL`.
+    output_buffer_limits: [[AtomicU64; 3]; 3],
+    /// File `CONFIG REWRITE` writes to, if the server was started with one.
+    config_file: Option<PathBuf>,
+    /// Where sampled commands are logged, if enabled at startup.
+    access_log: Option<Arc<AccessLog>>,
+    /// Health of the kernel cache client, if the server started one.
+    kernel_health: Option<Arc<HealthMonitor>>,
+    /// Startup, readiness and shutdown state, for HTTP probes.
+    lifecycle: Arc<ServerLifecycle>,
+}
+
+/// A `CONFIG` parameter name and the field holding it.
+struct Parameter {
+    name: &'static str,
+    field: fn(&RuntimeConfig) -> &AtomicU64,
+    /// Set once at startup; `CONFIG SET` refuses it.
+    read_only: bool,
+}
+
+/// Every parameter `CONFIG GET`/`CONFIG SET` accept.
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "timeout",
+        field: |config| &config.idle_timeout_secs,
+        read_only: false,
+    },
+    Parameter {
+        name: "maxclients",
+        field: |config| &config.max_clients,
+        read_only: false,
+    },
+    Parameter {
+        name: "proto-max-bulk-len",
+        field: |config| &config.max_bulk_len,
+        read_only: false,
+    },
+    Parameter {
+        name: "proto-max-multibulk-len",
+        field: |config| &config.max_array_len,
+        read_only: false,
+    },
+    Parameter {
+        name: "client-query-buffer-limit",
+        field: |config| &config.max_request_len,
+        read_only: false,
+    },
+    Parameter {
+        name: "accesslog-sample-rate",
+        field: |config| &config.access_log_sample_rate,
+        read_only: false,
+    },
+    Parameter {
+        name: "read-only",
+        field: |config| &config.read_only,
+        read_only: false,
+    },
+    Parameter {
+        name: "repl-backlog-size",
+        field: |config| &config.repl_backlog_size,
+        read_only: false,
+    },
+    Parameter {
+        name: "tracking-table-max-keys",
+        field: |config| &config.tracking_table_max_keys,
+        read_only: false,
+    },
+    Parameter {
+        name: "tcp-nodelay",
+        field: |config| &config.tcp_nodelay,
+        read_only: false,
+    },
+    Parameter {
+        name: "tcp-keepalive",
+        field: |config| &config.tcp_keepalive_secs,
+        read_only: false,
+    },
+    Parameter {
+        name: "maxcmd-per-sec",
+        field: |config| &config.max_cmd_per_sec,
+        read_only: false,
+    },
+    Parameter {
+        name: "maxconn-per-ip-per-sec",
+        field: |config| &config.max_conn_per_ip_per_sec,
+        read_only: false,
+    },
+    Parameter {
+        name: "protected-mode",
+        field: |config| &config.protected_mode,
+        read_only: false,
+    },
+    Parameter {
+        name: "lua-time-limit",
+        field: |config| &config.lua_time_limit_ms,
+        read_only: false,
+    },
+    Parameter {
+        name: "command-time-limit",
+        field: |config| &config.command_time_limit_ms,
+        read_only: false,
+    },
+    Parameter {
+        name: "latency-monitor-threshold",
+        field: |config| &config.latency_monitor_threshold_ms,
+        read_only: false,
+    },
+    Parameter {
+        name: "read-buffer-shrink-after",
+        field: |config| &config.read_buffer_shrink_after,
+        read_only: false,
+    },
+    Parameter {
+        name: "maxmemory",
+        field: |config| &config.max_memory,
+        read_only: true,
+    },
+    Parameter {
+        name: "tcp-backlog",
+        field: |config| &config.tcp_backlog,
+        read_only: true,
+    },
+    Parameter {
+        name: "acceptors",
+        field: |config| &config.acceptors,
+        read_only: true,
+    },
+    Parameter {
+        name: "io-uring",
+        field: |config| &config.io_uring,
+        read_only: true,
+    },
+];
+
+impl RuntimeConfig {
+    /// Creates a config with every setting at its default.
+    pub fn new() -> Self {
+        RuntimeConfig {
+            idle_timeout_secs: AtomicU64::new(0),
+            max_clients: AtomicU64::new(DEFAULT_MAX_CLIENTS),
+            max_bulk_len: AtomicU64::new(DEFAULT_MAX_BULK_LEN as u64),
+            max_array_len: AtomicU64::new(DEFAULT_MAX_ARRAY_LEN as u64),
+            max_request_len: AtomicU64::new(DEFAULT_MAX_REQUEST_LEN as u64),
+            max_memory: AtomicU64::new(0),
+            access_log_sample_rate: AtomicU64::new(DEFAULT_ACCESS_LOG_SAMPLE_RATE),
+            read_only: AtomicU64::new(0),
+            repl_backlog_size: AtomicU64::new(DEFAULT_REPL_BACKLOG_SIZE),
+            tracking_table_max_keys: AtomicU64::new(DEFAULT_TRACKING_TABLE_MAX_KEYS),
+            tcp_nodelay: AtomicU64::new(1),
+            tcp_keepalive_secs: AtomicU64::new(DEFAULT_TCP_KEEPALIVE_SECS),
+            tcp_backlog: AtomicU64::new(DEFAULT_TCP_BACKLOG as u64),
+            acceptors: AtomicU64::new(1),
+            max_cmd_per_sec: AtomicU64::new(0),
+            max_conn_per_ip_per_sec: AtomicU64::new(0),
+            lua_time_limit_ms: AtomicU64::new(DEFAULT_LUA_TIME_LIMIT_MS),
+            command_time_limit_ms: AtomicU64::new(0),
+            latency_monitor_threshold_ms: AtomicU64::new(0),
+            read_buffer_shrink_after: AtomicU64::new(DEFAULT_READ_BUFFER_SHRINK_AFTER),
+            protected_mode: AtomicU64::new(1),
+            io_uring: AtomicU64::new(0),
+            requirepass: RwLock::new(None),
+            acl: Acl::new(),
+            aclfile: None,
+            output_buffer_limits: DEFAULT_OUTPUT_BUFFER_LIMITS.map(|limit| {
+                [limit.hard_bytes, limit.soft_bytes, limit.soft_seconds].map(AtomicU64::new)
+            }),
+            config_file: None,
+            access_log: None,
+            kernel_health: None,
+            lifecycle: Arc::new(ServerLifecycle::new()),
+        }
+    }
+
+    /// Logs sampled commands to `access_log`.
+    pub fn with_access_log(mut self, access_log: Arc<AccessLog>) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// The access log, if the server was started with one.
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_deref()
+    }
+
+    /// Reports the kernel cache client's health in `INFO kernel_cache`.
+    pub fn with_kernel_health(mut self, monitor: Arc<HealthMonitor>) -> Self {
+        self.kernel_health = Some(monitor);
+        self
+    }
+
+    /// The kernel cache client's health; `Disabled` without a client.
+    pub fn kernel_health(&self) -> KernelHealth {
+        self.kernel_health
+            .as_ref()
+            .map_or(KernelHealth::Disabled, |monitor| monitor.get())
+    }
+
+    /// The server's lifecycle, which every component sharing this config
+    /// reports to.
+    pub fn lifecycle(&self) -> &Arc<ServerLifecycle> {
+        &self.lifecycle
+    }
+
+    /// Records the configuration file the server was started with.
+    pub fn with_config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// The configuration file for `CONFIG REWRITE`, if any.
+    pub fn config_file(&self) -> Option<&Path> {
+        self.config_file.as_deref()
+    }
+
+    /// Records the ACL file the server was started with.
+    pub fn with_aclfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.aclfile = Some(path.into());
+        self
+    }
+
+    /// The ACL file for `ACL LOAD` and `ACL SAVE`, if any.
+    pub fn aclfile(&self) -> Option<&Path> {
+        self.aclfile.as_deref()
+    }
+
+    /// Users and their permissions.
+    pub fn acl(&self) -> &Acl {
+        &self.acl
+    }
+
+    /// Every parameter's canonical name and current value.
+    pub fn parameters(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        PARAMETERS
+            .iter()
+            .map(|param| (param.name, (param.field)(self).load(Ordering::Relaxed)))
+    }
+
+    /// Reads a parameter by its case-insensitive `CONFIG` name, returning
+    /// the canonical name with the value.
+    pub fn get(&self, name: &str) -> Option<(&'static str, u64)> {
+        let param = Self::parameter(name)?;
+        Some((param.name, (param.field)(self).load(Ordering::Relaxed)))
+    }
+
+    /// Sets a parameter by its `CONFIG` name; false if there is no such
+    /// parameter or it is read-only.
+    pub fn set(&self, name: &str, value: u64) -> bool {
+        match Self::parameter(name) {
+            Some(param) if !param.read_only => {
+                (param.field)(self).store(value, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parameter(name: &str) -> Option<&'static Parameter> {
+        PARAMETERS
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Seconds a connection may stay silent before it is closed; 0 disables
+    /// the timeout (the `timeout` parameter).
+    pub fn idle_timeout_secs(&self) -> u64 {
+        self.idle_timeout_secs.load(Ordering::Relaxed)
+    }
+
+    /// Sets the idle timeout in seconds; 0 disables it.
+    pub fn set_idle_timeout_secs(&self, secs: u64) {
+        self.idle_timeout_secs.store(secs, Ordering::Relaxed);
+    }
+
+    /// The idle timeout, or `None` when disabled.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.idle_timeout_secs() {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Connections served at once; later ones are refused with an error
+    /// (the `maxclients` parameter).
+    pub fn max_clients(&self) -> u64 {
+        self.max_clients.load(Ordering::Relaxed)
+    }
+
+    /// Sets the connection limit; already-open connections are kept.
+    pub fn set_max_clients(&self, max_clients: u64) {
+        self.max_clients.store(max_clients, Ordering::Relaxed);
+    }
+
+    /// Engine memory limit in bytes, 0 for unlimited (the read-only
+    /// `maxmemory` parameter).
+    pub fn max_memory(&self) -> u64 {
+        self.max_memory.load(Ordering::Relaxed)
+    }
+
+    /// Records the memory limit the engine was created with.
+    pub fn set_max_memory(&self, bytes: u64) {
+        self.max_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Logs one command in this many to the access log; 0 disables it (the
+    /// `accesslog-sample-rate` parameter, default 1).
+    pub fn access_log_sample_rate(&self) -> u64 {
+        self.access_log_sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Sets the access log sample rate.
+    pub fn set_access_log_sample_rate(&self, rate: u64) {
+        self.access_log_sample_rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Whether write commands are refused with `-READONLY` (the `read-only`
+    /// parameter).
+    pub fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed) != 0
+    }
+
+    /// Turns read-only mode on or off; it applies from each connection's
+    /// next command.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only
+            .store(u64::from(read_only), Ordering::Relaxed);
+    }
+
+    /// Whether the `protected-mode` parameter is on.
+    pub fn protected_mode(&self) -> bool {
+        self.protected_mode.load(Ordering::Relaxed) != 0
+    }
+
+    /// Turns the `protected-mode` parameter on or off; it applies from the
+    /// next accept.
+    pub fn set_protected_mode(&self, enabled: bool) {
+        self.protected_mode
+            .store(u64::from(enabled), Ordering::Relaxed);
+    }
+
+    /// Whether connections from outside the loopback interface are refused:
+    /// protected mode is on and the default user needs no password. Only
+    /// listeners on other addresses ever see such connections.
+    pub fn is_protected(&self) -> bool {
+        self.protected_mode() && self.acl.open_default_user().is_some()
+    }
+
+    /// The password clients must `AUTH` with, if one is set.
+    pub fn requirepass(&self) -> Option<Arc<[u8]>> {
+        self.requirepass
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Sets the password, and makes it the default user's only one; an
+    /// empty one removes it. Open connections keep their authentication
+    /// state.
+    pub fn set_requirepass(&self, password: &[u8]) {
+        self.acl.set_default_password(password);
+        let password = (!password.is_empty()).then(|| Arc::from(password));
+        *self
+            .requirepass
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = password;
+    }
+
+    /// Bytes of recent replication stream a master keeps for replicas
+    /// resuming with a partial resync (the `repl-backlog-size` parameter);
+    /// a smaller value applies from the next write.
+    pub fn repl_backlog_size(&self) -> u64 {
+        self.repl_backlog_size.load(Ordering::Relaxed)
+    }
+
+    /// Sets the replication backlog size.
+    pub fn set_repl_backlog_size(&self, bytes: u64) {
+        self.repl_backlog_size.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Keys `CLIENT TRACKING` remembers readers for before evicting some
+    /// (the `tracking-table-max-keys` parameter); 0 for no limit.
+    pub fn tracking_table_max_keys(&self) -> u64 {
+        self.tracking_table_max_keys.load(Ordering::Relaxed)
+    }
+
+    /// Sets the tracking table limit; it applies from the next tracked read.
+    pub fn set_tracking_table_max_keys(&self, keys: u64) {
+        self.tracking_table_max_keys.store(keys, Ordering::Relaxed);
+    }
+
+    /// Whether accepted sockets disable Nagle's algorithm (the `tcp-nodelay`
+    /// parameter, on by default); read at each accept.
+    pub fn tcp_nodelay(&self) -> bool {
+        self.tcp_nodelay.load(Ordering::Relaxed) != 0
+    }
+
+    /// Turns `TCP_NODELAY` on or off for connections accepted from now on.
+    pub fn set_tcp_nodelay(&self, nodelay: bool) {
+        self.tcp_nodelay
+            .store(u64::from(nodelay), Ordering::Relaxed);
+    }
+
+    /// Idle seconds before an accepted socket sends keepalive probes; 0
+    /// disables them (the `tcp-keepalive` parameter, read at each accept).
+    pub fn tcp_keepalive_secs(&self) -> u64 {
+        self.tcp_keepalive_secs.load(Ordering::Relaxed)
+    }
+
+    /// Sets the keepalive time for connections accepted from now on.
+    pub fn set_tcp_keepalive_secs(&self, secs: u64) {
+        self.tcp_keepalive_secs.store(secs, Ordering::Relaxed);
+    }
+
+    /// The keepalive time, or `None` when keepalive is disabled.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        match self.tcp_keepalive_secs() {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Length of the listener's pending-connection queue (the read-only
+    /// `tcp-backlog` parameter).
+    pub fn tcp_backlog(&self) -> u32 {
+        u32::try_from(self.tcp_backlog.load(Ordering::Relaxed)).unwrap_or(u32::MAX)
+    }
+
+    /// Records the backlog the listener was bound with.
+    pub fn set_tcp_backlog(&self, backlog: u32) {
+        self.tcp_backlog
+            .store(u64::from(backlog), Ordering::Relaxed);
+    }
+
+    /// Accept loops per listen address, each on its own `SO_REUSEPORT`
+    /// socket when more than one (the read-only `acceptors` parameter).
+    pub fn acceptors(&self) -> usize {
+        usize::try_from(self.acceptors.load(Ordering::Relaxed)).unwrap_or(usize::MAX)
+    }
+
+    /// Records how many listeners each address was bound with.
+    pub fn set_acceptors(&self, acceptors: usize) {
+        self.acceptors.store(acceptors as u64, Ordering::Relaxed);
+    }
+
+    /// Whether connections are to be served through io_uring (the read-only
+    /// `io-uring` parameter); off by default, since the ring path copies
+    /// every write and measures slower than the standard one.
+    pub fn io_uring(&self) -> bool {
+        self.io_uring.load(Ordering::Relaxed) != 0
+    }
+
+    /// Chooses the io_uring or the standard network path; read when the
+    /// server starts accepting.
+    pub fn set_io_uring(&self, enabled: bool) {
+        self.io_uring.store(u64::from(enabled), Ordering::Relaxed);
+    }
+
+    /// Commands per second each connection may run before the rest wait
+    /// their turn; 0 disables the limit (the `maxcmd-per-sec` parameter).
+    pub fn max_cmd_per_sec(&self) -> u64 {
+        self.max_cmd_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Sets the per-connection command rate; it applies from the next
+    /// command.
+    pub fn set_max_cmd_per_sec(&self, rate: u64) {
+        self.max_cmd_per_sec.store(rate, Ordering::Relaxed);
+    }
+
+    /// Connections per second accepted from one IP address before the rest
+    /// are refused; 0 disables the limit (the `maxconn-per-ip-per-sec`
+    /// parameter).
+    pub fn max_conn_per_ip_per_sec(&self) -> u64 {
+        self.max_conn_per_ip_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Sets the per-IP connection rate; it applies from the next accept.
+    pub fn set_max_conn_per_ip_per_sec(&self, rate: u64) {
+        self.max_conn_per_ip_per_sec.store(rate, Ordering::Relaxed);
+    }
+
+    /// How long a script runs before other clients get `-BUSY` and it may
+    /// be stopped with `SCRIPT KILL` (the `lua-time-limit` parameter, in
+    /// milliseconds).
+    pub fn lua_time_limit(&self) -> Duration {
+        Duration::from_millis(self.lua_time_limit_ms.load(Ordering::Relaxed))
+    }
+
+    /// Sets the script time limit; it applies from the next script.
+    pub fn set_lua_time_limit(&self, limit: Duration) {
+        let millis = u64::try_from(limit.as_millis()).unwrap_or(u64::MAX);
+        self.lua_time_limit_ms.store(millis, Ordering::Relaxed);
+    }
+
+    /// How long `KEYS` may run before it is abandoned with `-BUSY` (the
+    /// `command-time-limit` parameter, in milliseconds); `None` while it is
+    /// 0, unlimited. `FLUSHALL` and `FLUSHDB` always run to the end.
+    pub fn command_time_limit(&self) -> Option<Duration> {
+        match self.command_time_limit_ms.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    /// Sets the command time limit; `Duration::ZERO` removes it.
+    pub fn set_command_time_limit(&self, limit: Duration) {
+        let millis = u64::try_from(limit.as_millis()).unwrap_or(u64::MAX);
+        self.command_time_limit_ms.store(millis, Ordering::Relaxed);
+    }
+
+    /// How long a command or background cycle must take to be recorded for
+    /// `LATENCY` (the `latency-monitor-threshold` parameter, in
+    /// milliseconds); `None` while it is 0, disabled.
+    pub fn latency_monitor_threshold(&self) -> Option<Duration> {
+        match self.latency_monitor_threshold_ms.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    /// Sets the latency threshold; `Duration::ZERO` stops recording.
+    pub fn set_latency_monitor_threshold(&self, threshold: Duration) {
+        let millis = u64::try_from(threshold.as_millis()).unwrap_or(u64::MAX);
+        self.latency_monitor_threshold_ms
+            .store(millis, Ordering::Relaxed);
+    }
+
+    /// Reads in a row that fill at most a small share of a connection's
+    /// read buffer before it is shrunk (the `read-buffer-shrink-after`
+    /// parameter); 0 never shrinks.
+    pub fn read_buffer_shrink_after(&self) -> u64 {
+        self.read_buffer_shrink_after.load(Ordering::Relaxed)
+    }
+
+    /// Sets the shrink threshold; it applies from each connection's next
+    /// read.
+    pub fn set_read_buffer_shrink_after(&self, reads: u64) {
+        self.read_buffer_shrink_after
+            .store(reads, Ordering::Relaxed);
+    }
+
+    /// Output buffer limits for `class` (the `client-output-buffer-limit`
+    /// parameter); each connection reads them as it queues output.
+    pub fn output_buffer_limit(&self, class: ClientClass) -> OutputBufferLimit {
+        let [hard, soft, seconds] = &self.output_buffer_limits[class as usize];
+        OutputBufferLimit {
+            hard_bytes: hard.load(Ordering::Relaxed),
+            soft_bytes: soft.load(Ordering::Relaxed),
+            soft_seconds: seconds.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets the output buffer limits for `class`.
+    pub fn set_output_buffer_limit(&self, class: ClientClass, limit: OutputBufferLimit) {
+        let [hard, soft, seconds] = &self.output_buffer_limits[class as usize];
+        hard.store(limit.hard_bytes, Ordering::Relaxed);
+        soft.store(limit.soft_bytes, Ordering::Relaxed);
+        seconds.store(limit.soft_seconds, Ordering::Relaxed);
+    }
+
+    /// Every class's limits in `CONFIG GET client-output-buffer-limit`
+    /// form, with sizes in bytes.
+    pub fn client_output_buffer_limit(&self) -> String {
+        ClientClass::ALL
+            .iter()
+            .map(|&class| {
+                let limit = self.output_buffer_limit(class);
+                format!(
+                    "{} {} {} {}",
+                    class.name(),
+                    limit.hard_bytes,
+                    limit.soft_bytes,
+                    limit.soft_seconds
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Applies a `CONFIG SET client-output-buffer-limit` value: one or more
+    /// `<class> <hard> <soft> <seconds>` groups, sizes optionally with a
+    /// `k`/`kb`/`m`/`mb`/`g`/`gb` unit. Nothing changes unless every group
+    /// parses.
+    pub fn set_client_output_buffer_limit(&self, value: &str) -> Result<(), String> {
+        let words: Vec<&str> = value.split_whitespace().collect();
+        if words.is_empty() || !words.len().is_multiple_of(4) {
+            return Err("wrong number of arguments".to_string());
+        }
+        let mut limits = Vec::new();
+        for group in words.chunks(4) {
+            let class = ClientClass::parse(group[0])
+                .ok_or_else(|| format!("invalid client class '{}'", group[0]))?;
+            let number =
+                |word: &str| parse_memory(word).ok_or_else(|| format!("invalid limit '{word}'"));
+            let seconds = group[3]
+                .parse()
+                .map_err(|_| format!("invalid seconds '{}'", group[3]))?;
+            limits.push((
+                class,
+                OutputBufferLimit {
+                    hard_bytes: number(group[1])?,
+                    soft_bytes: number(group[2])?,
+                    soft_seconds: seconds,
+                },
+            ));
+        }
+        for (class, limit) in limits {
+            self.set_output_buffer_limit(class, limit);
+        }
+        Ok(())
+    }
+
+    /// Request size bounds for the RESP parser (`proto-max-bulk-len`,
+    /// `proto-max-multibulk-len`, `client-query-buffer-limit`).
+    pub fn resp_limits(&self) -> RespLimits {
+        let load = |field: &AtomicU64| {
+            usize::try_from(field.load(Ordering::Relaxed)).unwrap_or(usize::MAX)
+        };
+        RespLimits {
+            max_array_len: load(&self.max_array_len),
+            max_bulk_len: load(&self.max_bulk_len),
+            max_request_len: load(&self.max_request_len),
+        }
+    }
+
+    /// Sets the RESP parser bounds.
+    pub fn set_resp_limits(&self, limits: RespLimits) {
+        self.max_array_len
+            .store(limits.max_array_len as u64, Ordering::Relaxed);
+        self.max_bulk_len
+            .store(limits.max_bulk_len as u64, Ordering::Relaxed);
+        self.max_request_len
+            .store(limits.max_request_len as u64, Ordering::Relaxed);
+    }
+}
+
+/// Parses a byte count with an optional redis.conf unit: `k`, `m` and `g`
+/// are powers of 1000, `kb`, `mb` and `gb` powers of 1024.
+fn parse_memory(word: &str) -> Option<u64> {
+    let lower = word.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let scale: u64 = match &lower[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(scale)
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_disables_the_idle_timeout() {
+        let config = RuntimeConfig::new();
+        assert_eq!(config.idle_timeout(), None);
+
+        config.set_idle_timeout_secs(30);
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(config.idle_timeout_secs(), 30);
+
+        config.set_idle_timeout_secs(0);
+        assert_eq!(config.idle_timeout(), None);
+    }
+
+    #[test]
+    fn max_clients_defaults_to_redis_value() {
+        let config = RuntimeConfig::default();
+        assert_eq!(config.max_clients(), DEFAULT_MAX_CLIENTS);
+
+        config.set_max_clients(2);
+        assert_eq!(config.max_clients(), 2);
+    }
+
+    #[test]
+    fn parameters_are_reachable_by_config_name() {
+        let config = RuntimeConfig::new();
+        assert_eq!(
+            config.get("MAXCLIENTS"),
+            Some(("maxclients", DEFAULT_MAX_CLIENTS))
+        );
+        assert_eq!(config.get("no-such-param"), None);
+        assert!(!config.set("no-such-param", 1));
+
+        assert!(config.set("proto-max-bulk-len", 1024));
+        assert!(config.set("Proto-Max-Multibulk-Len", 8));
+        assert!(config.set("client-query-buffer-limit", 4096));
+        assert_eq!(
+            config.resp_limits(),
+            RespLimits {
+                max_array_len: 8,
+                max_bulk_len: 1024,
+                max_request_len: 4096,
+            }
+        );
+        assert_eq!(
+            config.get("proto-max-bulk-len"),
+            Some(("proto-max-bulk-len", 1024))
+        );
+    }
+
+    #[test]
+    fn protected_mode_needs_no_password() {
+        let config = RuntimeConfig::new();
+        assert_eq!(config.get("protected-mode"), Some(("protected-mode", 1)));
+        assert!(config.is_protected());
+
+        config.set_requirepass(b"secret");
+        assert_eq!(config.requirepass().as_deref(), Some(&b"secret"[..]));
+        assert!(!config.is_protected());
+        config.set_requirepass(b"");
+        assert_eq!(config.requirepass(), None);
+        assert!(config.is_protected());
+
+        assert!(config.set("protected-mode", 0));
+        assert!(!config.is_protected());
+    }
+
+    #[test]
+    fn maxmemory_is_readable_but_not_settable() {
+        let config = RuntimeConfig::new();
+        config.set_max_memory(1 << 20);
+        assert_eq!(config.get("maxmemory"), Some(("maxmemory", 1 << 20)));
+        assert!(!config.set("maxmemory", 1));
+        assert_eq!(config.max_memory(), 1 << 20);
+    }
+
+    #[test]
+    fn output_buffer_limits_use_the_redis_format() {
+        let config = RuntimeConfig::new();
+        assert_eq!(
+            config.client_output_buffer_limit(),
+            "normal 0 0 0 slave 268435456 67108864 60 pubsub 33554432 8388608 60"
+        );
+
+        config
+            .set_client_output_buffer_limit("PUBSUB 64kb 1m 10 replica 1gb 0 0")
+            .unwrap();
+        assert_eq!(
+            config.output_buffer_limit(ClientClass::Pubsub),
+            OutputBufferLimit {
+                hard_bytes: 64 * 1024,
+                soft_bytes: 1_000_000,
+                soft_seconds: 10,
+            }
+        );
+        assert_eq!(
+            config.client_output_buffer_limit(),
+            "normal 0 0 0 slave 1073741824 0 0 pubsub 65536 1000000 10"
+        );
+
+        for bad in [
+            "",
+            "pubsub 1 2",
+            "clients 1 2 3",
+            "normal 1 2 3 pubsub 1xb 0 0",
+        ] {
+            assert!(
+                config.set_client_output_buffer_limit(bad).is_err(),
+                "{bad:?}"
+            );
+        }
+        assert_eq!(
+            config.output_buffer_limit(ClientClass::Normal),
+            OutputBufferLimit::default()
+        );
+    }
+
+    #[test]
+    fn soft_limits_must_be_held_for_their_seconds() {
+        let limit = OutputBufferLimit {
+            hard_bytes: 100,
+            soft_bytes: 10,
+            soft_seconds: 5,
+        };
+        let start = Instant::now();
+        let mut since = None;
+        assert!(!limit.exceeded(50, &mut since, start));
+        assert!(!limit.exceeded(50, &mut since, start + Duration::from_secs(4)));
+        assert!(limit.exceeded(50, &mut since, start + Duration::from_secs(5)));
+        // Dropping under the soft limit restarts the clock.
+        assert!(!limit.exceeded(5, &mut since, start + Duration::from_secs(6)));
+        assert!(!limit.exceeded(50, &mut since, start + Duration::from_secs(7)));
+        assert!(limit.exceeded(101, &mut since, start + Duration::from_secs(7)));
+        assert!(!OutputBufferLimit::default().exceeded(u64::MAX, &mut None, start));
+    }
+
+    #[test]
+    fn read_only_is_a_numeric_flag() {
+        let config = RuntimeConfig::new();
+        assert!(!config.read_only());
+        assert!(config.set("read-only", 2));
+        assert!(config.read_only());
+        config.set_read_only(false);
+        assert_eq!(config.get("read-only"), Some(("read-only", 0)));
+    }
+}
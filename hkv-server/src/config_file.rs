@@ -0,0 +1,302 @@
+//! # Configuration File
+//!
+//! Read a `redis.conf`-compatible configuration file and write the runtime
+//! settings back to it for `CONFIG REWRITE`.
+//!
+//! ## Design Principles
+//!
+//! 1. **redis.conf Syntax**: One directive per line, `#` comments, double
+//!    quotes with backslash escapes, single quotes with `\'`, and `include`,
+//!    so existing files load unchanged.
+//! 2. **Located Errors**: Every syntax error names the file and line; what a
+//!    directive's arguments mean is checked by the caller, which reports the
+//!    same location.
+//! 3. **Rewrite in Place**: `rewrite` replaces the lines of the parameters
+//!    it manages and keeps every other line, comment and include. Settings
+//!    the file never mentioned and that differ from their defaults are
+//!    appended under `REWRITE_HEADER`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::RuntimeConfig;
+use crate::persistence::write_atomically;
+
+/// Comment heading the lines `rewrite` appends.
+pub const REWRITE_HEADER: &str = "# Generated by CONFIG REWRITE";
+
+/// Nesting limit for `include`, which also stops include cycles.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A directive and its arguments, with where it was read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    /// Lowercase directive name.
+    pub name: String,
+    /// The arguments, unquoted.
+    pub args: Vec<String>,
+    /// File the directive came from, which may be an included file.
+    pub path: PathBuf,
+    /// 1-based line number in `path`.
+    pub line: usize,
+}
+
+/// A configuration file that could not be read or parsed.
+#[derive(Debug)]
+pub struct ConfigFileError {
+    pub path: PathBuf,
+    /// 1-based line number; 0 when the file itself could not be read.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}: {}", self.path.display(), self.message),
+            line => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {}
+
+/// Reads `path` and the files it includes, returning their directives in
+/// order; later directives override earlier ones.
+///
+/// Relative `include` paths are resolved against the including file's
+/// directory.
+pub fn load(path: &Path) -> Result<Vec<Directive>, ConfigFileError> {
+    let mut directives = Vec::new();
+    load_into(path, 0, &mut directives)?;
+    Ok(directives)
+}
+
+fn load_into(
+    path: &Path,
+    depth: usize,
+    directives: &mut Vec<Directive>,
+) -> Result<(), ConfigFileError> {
+    let error = |line: usize, message: String| ConfigFileError {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|err| error(0, err.to_string()))?;
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut args = split_args(line).map_err(|message| error(number, message))?;
+        if args.is_empty() {
+            continue;
+        }
+        let name = args.remove(0).to_ascii_lowercase();
+        if name == "include" {
+            let [included] = &args[..] else {
+                return Err(error(
+                    number,
+                    "'include' takes exactly one path".to_string(),
+                ));
+            };
+            if depth == MAX_INCLUDE_DEPTH {
+                return Err(error(number, "includes nested too deeply".to_string()));
+            }
+            let included = path.parent().unwrap_or(Path::new("")).join(included);
+            load_into(&included, depth + 1, directives)?;
+            continue;
+        }
+        directives.push(Directive {
+            name,
+            args,
+            path: path.to_path_buf(),
+            line: number,
+        });
+    }
+    Ok(())
+}
+
+/// Splits a line into arguments the way Redis does: on whitespace, with
+/// double-quoted arguments supporting `\n`, `\r`, `\t`, `\b`, `\a`, `\xHH`
+/// and `\<char>` escapes, and single-quoted ones supporting `\'`.
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+
+        let mut arg = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err("unbalanced quotes".to_string()),
+                    Some(c) if c == first => break,
+                    Some('\\') if first == '"' => arg.push(unescape(&mut chars)?),
+                    Some('\\') if chars.peek() == Some(&'\'') => {
+                        arg.push('\'');
+                        chars.next();
+                    }
+                    Some(c) => arg.push(c),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("closing quote must be followed by a space".to_string());
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Decodes the escape after a `\` inside double quotes.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<char, String> {
+    Ok(match chars.next() {
+        None => return Err("unbalanced quotes".to_string()),
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('b') => '\u{8}',
+        Some('a') => '\u{7}',
+        Some('x') => {
+            let hex: String = chars.by_ref().take(2).collect();
+            u8::from_str_radix(&hex, 16)
+                .map(char::from)
+                .map_err(|_| format!("invalid escape '\\x{hex}'"))?
+        }
+        Some(c) => c,
+    })
+}
+
+/// Writes `runtime`'s parameters to the configuration file at `path`,
+/// keeping its other lines; see the module docs.
+pub fn rewrite(path: &Path, runtime: &RuntimeConfig) -> io::Result<()> {
+    let original = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    let mut written = HashSet::new();
+    let mut out = String::with_capacity(original.len());
+    for line in original.lines() {
+        let param = split_args(line)
+            .ok()
+            .and_then(|args| args.into_iter().next())
+            .and_then(|name| runtime.get(&name));
+        match param {
+            // Later duplicates would override the rewritten value; drop them.
+            Some((name, value)) => {
+                if written.insert(name) {
+                    out.push_str(&format!("{name} {value}\n"));
+                }
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    let defaults = RuntimeConfig::new();
+    let appended: Vec<_> = runtime
+        .parameters()
+        .filter(|&(name, value)| {
+            !written.contains(name) && defaults.get(name).map(|(_, default)| default) != Some(value)
+        })
+        .collect();
+    if !appended.is_empty() {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(REWRITE_HEADER);
+        out.push('\n');
+        for (name, value) in appended {
+            out.push_str(&format!("{name} {value}\n"));
+        }
+    }
+
+    write_atomically(path, out.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_args_handles_redis_quoting() {
+        assert_eq!(split_args("  port   6380 ").unwrap(), ["port", "6380"]);
+        assert_eq!(
+            split_args(r#"dir "/var/lib/my dir" 'it\'s' "\x41\t\"""#).unwrap(),
+            ["dir", "/var/lib/my dir", "it's", "A\t\""]
+        );
+        assert_eq!(split_args("").unwrap(), Vec::<String>::new());
+        assert!(split_args(r#"dir "/tmp"#).is_err());
+        assert!(split_args(r#"dir "/tmp"x"#).is_err());
+    }
+
+    #[test]
+    fn load_follows_includes_and_reports_lines() {
+        let dir = std::env::temp_dir().join(format!("hkv-conf-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("extra.conf"), "maxclients 50\n").unwrap();
+        let main = dir.join("hkv.conf");
+        fs::write(&main, "# don't\nport 7000\ninclude extra.conf\nTimeout 5\n").unwrap();
+
+        let directives = load(&main).unwrap();
+        let names: Vec<_> = directives.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["port", "maxclients", "timeout"]);
+        assert_eq!(directives[1].path, dir.join("extra.conf"));
+        assert_eq!(directives[2].line, 4);
+
+        assert_eq!(directives[2].args, ["5"]);
+
+        fs::write(&main, "port 7000\ndir \"/tmp\n").unwrap();
+        let err = load(&main).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.to_string().ends_with("hkv.conf:2: unbalanced quotes"));
+
+        fs::write(&main, "include hkv.conf\n").unwrap();
+        assert!(load(&main).unwrap_err().message.contains("nested"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewrite_keeps_other_lines_and_appends_changed_settings() {
+        let dir = std::env::temp_dir().join(format!("hkv-conf-rewrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hkv.conf");
+        fs::write(&path, "# ops notes\ntimeout 10\nsave 900 1\ntimeout 20\n").unwrap();
+
+        let runtime = RuntimeConfig::new();
+        runtime.set_idle_timeout_secs(30);
+        runtime.set_max_clients(77);
+        rewrite(&path, &runtime).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("# ops notes\ntimeout 30\nsave 900 1\n\n{REWRITE_HEADER}\nmaxclients 77\n")
+        );
+
+        // A second rewrite updates the appended lines in place.
+        runtime.set_max_clients(78);
+        rewrite(&path, &runtime).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(
+            text.ends_with(&format!("{REWRITE_HEADER}\nmaxclients 78\n")),
+            "{text}"
+        );
+        assert_eq!(text.matches(REWRITE_HEADER).count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
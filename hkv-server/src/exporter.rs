@@ -0,0 +1,499 @@
+//! # Prometheus Exporter
+//!
+//! Serve server metrics and engine size at `/metrics` in the Prometheus text
+//! exposition format (0.0.4), on a listener separate from the RESP port,
+//! along with `/healthz` and `/readyz` probes for orchestrators.
+//!
+//! ## Design Principles
+//!
+//! 1. **Few Endpoints, No Framework**: A hand-rolled HTTP/1.x responder
+//!    reads the request head, answers `GET` (or `HEAD`) for its paths and
+//!    closes; other paths get 404 and other methods 405.
+//! 2. **Render per Scrape**: Each scrape renders one `Metrics::snapshot` and
+//!    one `KVEngine::stats`; the exporter keeps no state of its own.
+//! 3. **Real Histograms**: Request and per-command latency keep the existing
+//!    bucket bounds, converted to seconds, as cumulative `_bucket{le}` series
+//!    with `_sum` and `_count`. `+Inf` and `_count` come from the same bucket
+//!    total, so they agree even while requests are being recorded.
+//! 4. **Bounded Labels**: The `command` label only takes the metrics
+//!    module's fixed command names, so clients cannot add series.
+//! 5. **Bounded Requests**: Heads over `MAX_REQUEST_HEAD` bytes, or not
+//!    complete within `REQUEST_TIMEOUT`, are dropped without a reply.
+//! 6. **Probes Read the Lifecycle**: `/healthz` answers 200 whenever the
+//!    exporter can answer at all; `/readyz` answers 200 only while
+//!    `ServerLifecycle::is_ready`, and 503 while starting, loading, waiting
+//!    for a replica's first sync or draining. Both describe the lifecycle in
+//!    a small JSON body.
+
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+use hkv_engine::{EngineStats, KVEngine};
+
+use crate::lifecycle::ServerLifecycle;
+use crate::metrics::{LatencySnapshot, Metrics, MetricsSnapshot};
+
+/// Largest request head (request line and headers) read from a scraper.
+pub const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a scraper has to send its request head.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Content type of the probe bodies.
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Serves `/metrics`, `/healthz` and `/readyz` on `listener` until
+/// `shutdown` resolves.
+///
+/// To keep answering `/readyz` with 503 while connections drain, resolve
+/// `shutdown` only after the RESP server has returned.
+pub async fn serve_metrics<E, F>(
+    listener: TcpListener,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    lifecycle: Arc<ServerLifecycle>,
+    shutdown: F,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    let mut scrapes = JoinSet::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            Some(_) = scrapes.join_next(), if !scrapes.is_empty() => {}
+            accept = listener.accept() => {
+                let (stream, _) = accept?;
+                let engine = Arc::clone(&engine);
+                let metrics = Arc::clone(&metrics);
+                let lifecycle = Arc::clone(&lifecycle);
+                scrapes.spawn(async move {
+                    // A scraper that disconnects early is not an error.
+                    let _ = handle_scrape(stream, engine.as_ref(), &metrics, &lifecycle).await;
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_scrape<E>(
+    mut stream: TcpStream,
+    engine: &E,
+    metrics: &Metrics,
+    lifecycle: &ServerLifecycle,
+) -> std::io::Result<()>
+where
+    E: KVEngine,
+{
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(head) => head?,
+        Err(_) => None,
+    };
+    let Some(head) = head else {
+        return Ok(());
+    };
+    let response = respond(&head, lifecycle, || {
+        render(&metrics.snapshot(), engine.stats())
+    });
+    stream.write_all(&response).await?;
+    stream.shutdown().await
+}
+
+/// Reads up to the blank line ending the request head; `None` if the
+/// scraper closes first or the head exceeds `MAX_REQUEST_HEAD`.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut head = Vec::with_capacity(1024);
+    loop {
+        if head.windows(4).any(|window| window == b"\r\n\r\n") {
+            return Ok(Some(head));
+        }
+        if head.len() > MAX_REQUEST_HEAD || stream.read_buf(&mut head).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+/// Builds the HTTP response to the request `head`, rendering the metrics
+/// only for a `/metrics` scrape.
+fn respond(head: &[u8], lifecycle: &ServerLifecycle, render: impl FnOnce() -> String) -> Vec<u8> {
+    let request_line = head.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    let request_line = String::from_utf8_lossy(request_line);
+    let mut parts = request_line.trim_end().split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return http_response("400 Bad Request", "", "bad request\n", true);
+    };
+    if !version.starts_with("HTTP/1.") {
+        return http_response("400 Bad Request", "", "bad request\n", true);
+    }
+
+    let with_body = match method {
+        "GET" => true,
+        "HEAD" => false,
+        _ => {
+            return http_response(
+                "405 Method Not Allowed",
+                "Allow: GET, HEAD\r\n",
+                "method not allowed\n",
+                true,
+            );
+        }
+    };
+    let json = format!("Content-Type: {JSON_CONTENT_TYPE}\r\n");
+    match target.split('?').next().unwrap_or_default() {
+        "/metrics" => {
+            let content_type = format!("Content-Type: {CONTENT_TYPE}\r\n");
+            http_response("200 OK", &content_type, &render(), with_body)
+        }
+        "/healthz" => http_response("200 OK", &json, &lifecycle.to_json(), with_body),
+        "/readyz" => {
+            let status = if lifecycle.is_ready() {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            http_response(status, &json, &lifecycle.to_json(), with_body)
+        }
+        _ => http_response("404 Not Found", "", "not found\n", with_body),
+    }
+}
+
+fn http_response(status: &str, headers: &str, body: &str, with_body: bool) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    if with_body {
+        response.push_str(body);
+    }
+    response.into_bytes()
+}
+
+/// Renders every series in the text exposition format.
+pub fn render(snapshot: &MetricsSnapshot, engine: EngineStats) -> String {
+    let mut out = String::with_capacity(4096);
+
+    let counters = [
+        (
+            "hkv_requests_total",
+            "Requests received, including protocol errors.",
+            snapshot.requests_total,
+        ),
+        (
+            "hkv_errors_total",
+            "Requests answered with an error.",
+            snapshot.errors_total,
+        ),
+        (
+            "hkv_accepted_connections_total",
+            "Connections accepted, including ones then refused.",
+            snapshot.accepted_connections_total,
+        ),
+        (
+            "hkv_rejected_connections_total",
+            "Connections refused at the maxclients limit.",
+            snapshot.rejected_connections_total,
+        ),
+        (
+            "hkv_idle_disconnects_total",
+            "Connections closed by the idle timeout.",
+            snapshot.idle_disconnects_total,
+        ),
+        (
+            "hkv_output_buffer_limit_disconnects_total",
+            "Connections closed by client-output-buffer-limit.",
+            snapshot.output_buffer_limit_disconnects_total,
+        ),
+        (
+            "hkv_tls_handshake_failures_total",
+            "Connections dropped during the TLS handshake.",
+            snapshot.tls_handshake_failures_total,
+        ),
+        (
+            "hkv_throttled_commands_total",
+            "Commands delayed by maxcmd-per-sec.",
+            snapshot.throttled_commands_total,
+        ),
+        (
+            "hkv_rate_limited_connections_total",
+            "Connections refused by maxconn-per-ip-per-sec.",
+            snapshot.rate_limited_connections_total,
+        ),
+    ];
+    for (name, help, value) in counters {
+        family(&mut out, name, "counter", help);
+        let _ = writeln!(out, "{name} {value}");
+    }
+
+    family(
+        &mut out,
+        "hkv_commands_total",
+        "counter",
+        "Commands executed, by lowercase name.",
+    );
+    for command in &snapshot.commands {
+        let _ = writeln!(
+            out,
+            "hkv_commands_total{{command=\"{}\"}} {}",
+            command.name, command.calls
+        );
+    }
+    family(
+        &mut out,
+        "hkv_command_errors_total",
+        "counter",
+        "Commands answered with an error, by lowercase name.",
+    );
+    for command in &snapshot.commands {
+        let _ = writeln!(
+            out,
+            "hkv_command_errors_total{{command=\"{}\"}} {}",
+            command.name, command.failed_calls
+        );
+    }
+    family(
+        &mut out,
+        "hkv_error_replies_total",
+        "counter",
+        "Error replies, by prefix such as ERR or OOM.",
+    );
+    for (prefix, count) in &snapshot.errors {
+        let _ = writeln!(
+            out,
+            "hkv_error_replies_total{{prefix=\"{prefix}\"}} {count}"
+        );
+    }
+
+    let gauges = [
+        (
+            "hkv_inflight_requests",
+            "Requests received but not yet answered.",
+            snapshot.inflight as f64,
+        ),
+        (
+            "hkv_connected_clients",
+            "Connections currently served.",
+            snapshot.connected_clients as f64,
+        ),
+        (
+            "hkv_keys",
+            "Entries stored, including expired ones not yet purged.",
+            engine.keys as f64,
+        ),
+        (
+            "hkv_expiring_keys",
+            "Entries with a TTL, including expired ones not yet purged.",
+            engine.expires as f64,
+        ),
+        (
+            "hkv_used_memory_bytes",
+            "Bytes of keys and values stored.",
+            engine.used_bytes as f64,
+        ),
+        (
+            "hkv_read_buffer_bytes",
+            "Bytes of read buffer held by connections.",
+            snapshot.read_buffer_bytes as f64,
+        ),
+        (
+            "hkv_read_buffer_pool_bytes",
+            "Bytes of read buffer pooled for new connections.",
+            snapshot.read_buffer_pool_bytes as f64,
+        ),
+        (
+            "hkv_uptime_seconds",
+            "Seconds since the server started.",
+            snapshot.uptime.as_secs_f64(),
+        ),
+    ];
+    for (name, help, value) in gauges {
+        family(&mut out, name, "gauge", help);
+        let _ = writeln!(out, "{name} {value}");
+    }
+
+    let name = "hkv_command_duration_seconds";
+    family(
+        &mut out,
+        name,
+        "histogram",
+        "Time spent executing a command, by lowercase name.",
+    );
+    for command in &snapshot.commands {
+        let labels = format!("command=\"{}\"", command.name);
+        histogram(&mut out, name, &labels, &command.latency);
+    }
+
+    let name = "hkv_request_duration_seconds";
+    family(
+        &mut out,
+        name,
+        "histogram",
+        "Time from parsing a request to writing its reply.",
+    );
+    histogram(&mut out, name, "", &snapshot.latency);
+
+    out
+}
+
+/// Writes the `_bucket`, `_sum` and `_count` series of one histogram, with
+/// `labels` (comma-separated, possibly empty) on every series.
+fn histogram(out: &mut String, name: &str, labels: &str, latency: &LatencySnapshot) {
+    let (bucket_prefix, braced) = if labels.is_empty() {
+        (String::new(), String::new())
+    } else {
+        (format!("{labels},"), format!("{{{labels}}}"))
+    };
+    let mut cumulative = 0u64;
+    for (bound_us, count) in latency.bounds_us.iter().zip(&latency.buckets) {
+        cumulative += count;
+        let le = *bound_us as f64 / 1e6;
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{bucket_prefix}le=\"{le}\"}} {cumulative}"
+        );
+    }
+    let total: u64 = latency.buckets.iter().sum();
+    let _ = writeln!(out, "{name}_bucket{{{bucket_prefix}le=\"+Inf\"}} {total}");
+    let _ = writeln!(out, "{name}_sum{braced} {}", latency.sum_us as f64 / 1e6);
+    let _ = writeln!(out, "{name}_count{braced} {total}");
+}
+
+/// Writes the `# HELP` and `# TYPE` lines of a metric family.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecyclePhase;
+
+    #[test]
+    fn histogram_buckets_are_cumulative_seconds() {
+        let metrics = Metrics::with_latency_buckets(vec![10, 500]);
+        metrics.record_request_start();
+        metrics.record_request_end(Duration::from_micros(4));
+        metrics.record_request_start();
+        metrics.record_request_end(Duration::from_micros(200));
+        metrics.record_request_start();
+        metrics.record_request_end(Duration::from_micros(900));
+
+        let text = render(&metrics.snapshot(), EngineStats::default());
+        let expected = concat!(
+            "# TYPE hkv_request_duration_seconds histogram\n",
+            "hkv_request_duration_seconds_bucket{le=\"0.00001\"} 1\n",
+            "hkv_request_duration_seconds_bucket{le=\"0.0005\"} 2\n",
+            "hkv_request_duration_seconds_bucket{le=\"+Inf\"} 3\n",
+            "hkv_request_duration_seconds_sum 0.001104\n",
+            "hkv_request_duration_seconds_count 3\n",
+        );
+        assert!(text.ends_with(expected), "{text}");
+    }
+
+    #[test]
+    fn every_series_has_help_and_type() {
+        let text = render(
+            &Metrics::new().snapshot(),
+            EngineStats {
+                keys: 3,
+                used_bytes: 42,
+                expires: 2,
+            },
+        );
+        assert!(text.contains("hkv_keys 3\n"), "{text}");
+        assert!(text.contains("hkv_expiring_keys 2\n"), "{text}");
+        assert!(text.contains("hkv_used_memory_bytes 42\n"), "{text}");
+        assert!(
+            text.contains("hkv_commands_total{command=\"unknown\"} 0\n"),
+            "{text}"
+        );
+
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let family = ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|suffix| {
+                    name.strip_suffix(suffix)
+                        .filter(|base| text.contains(&format!("# TYPE {base} histogram")))
+                })
+                .unwrap_or(name);
+            assert!(text.contains(&format!("# TYPE {family} ")), "{line}");
+            assert!(text.contains(&format!("# HELP {family} ")), "{line}");
+        }
+    }
+
+    #[test]
+    fn only_known_paths_are_served() {
+        let lifecycle = ServerLifecycle::new();
+        let status = |head: &[u8]| {
+            let response = respond(head, &lifecycle, || "body\n".to_string());
+            let response = String::from_utf8(response).unwrap();
+            response.lines().next().unwrap().to_string()
+        };
+        assert_eq!(status(b"GET /metrics HTTP/1.1\r\n\r\n"), "HTTP/1.1 200 OK");
+        assert_eq!(
+            status(b"GET /metrics?name[]=x HTTP/1.0\r\n\r\n"),
+            "HTTP/1.1 200 OK"
+        );
+        assert_eq!(status(b"GET / HTTP/1.1\r\n\r\n"), "HTTP/1.1 404 Not Found");
+        assert_eq!(
+            status(b"POST /metrics HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+        assert_eq!(status(b"garbage\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+
+        let head = respond(b"HEAD /metrics HTTP/1.1\r\n\r\n", &lifecycle, || {
+            "body\n".to_string()
+        });
+        assert!(head.ends_with(b"Content-Length: 5\r\nConnection: close\r\n\r\n"));
+    }
+
+    #[test]
+    fn readiness_follows_the_lifecycle() {
+        let lifecycle = ServerLifecycle::new();
+        let probe = |path: &str| {
+            let head = format!("GET {path} HTTP/1.1\r\n\r\n");
+            let response = respond(head.as_bytes(), &lifecycle, String::new);
+            let response = String::from_utf8(response).unwrap();
+            let status = response.lines().next().unwrap().to_string();
+            let body = response.split("\r\n\r\n").nth(1).unwrap().to_string();
+            (status, body)
+        };
+        assert_eq!(probe("/healthz").0, "HTTP/1.1 200 OK");
+        assert_eq!(
+            probe("/readyz"),
+            (
+                "HTTP/1.1 503 Service Unavailable".to_string(),
+                "{\"phase\":\"starting\",\"ready\":false,\"awaiting_sync\":false}\n".to_string()
+            )
+        );
+        lifecycle.advance(LifecyclePhase::Loading);
+        assert_eq!(probe("/readyz").0, "HTTP/1.1 503 Service Unavailable");
+        lifecycle.advance(LifecyclePhase::Ready);
+        assert_eq!(probe("/readyz").0, "HTTP/1.1 200 OK");
+        lifecycle.set_awaiting_sync(true);
+        assert_eq!(probe("/readyz").0, "HTTP/1.1 503 Service Unavailable");
+        lifecycle.set_awaiting_sync(false);
+        lifecycle.advance(LifecyclePhase::Draining);
+        let (status, body) = probe("/readyz");
+        assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+        assert!(body.contains("\"phase\":\"draining\""), "{body}");
+        assert_eq!(probe("/healthz").0, "HTTP/1.1 200 OK");
+    }
+}
@@ -0,0 +1,123 @@
+//! # Glob Patterns
+//!
+//! Match keys against Redis glob-style patterns, as `KEYS` takes them.
+//!
+//! ## Design Principles
+//!
+//! 1. **Redis Semantics**: `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\`
+//!    escapes behave as in Redis's `stringmatchlen`, byte by byte.
+//! 2. **Linear Backtracking**: Only the most recent `*` is retried, so a
+//!    pattern with many stars cannot make matching exponential.
+
+/// Whether `subject` matches the glob `pattern`.
+pub(crate) fn glob_match(pattern: &[u8], subject: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Pattern and subject positions to resume from after the last `*`.
+    let mut star: Option<(usize, usize)> = None;
+    while s < subject.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p + 1, subject[s]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == subject[s]).then_some(p + 2),
+            Some(&literal) => (literal == subject[s]).then_some(p + 1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star_p, star_s))) => {
+                // Let the last `*` swallow one more byte and retry.
+                star = Some((star_p, star_s + 1));
+                p = star_p;
+                s = star_s + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+/// Matches `byte` against the class starting after `[` at `start`; returns
+/// the position after the closing `]` if it matches.
+fn match_class(pattern: &[u8], start: usize, byte: u8) -> Option<usize> {
+    let mut p = start;
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    loop {
+        match pattern.get(p) {
+            // An unterminated class ends with the pattern, as in Redis.
+            None => break,
+            Some(b']') => {
+                p += 1;
+                break;
+            }
+            Some(b'\\') if p + 1 < pattern.len() => {
+                matched |= pattern[p + 1] == byte;
+                p += 2;
+            }
+            Some(&low) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                let high = pattern[p + 2];
+                let (low, high) = if low <= high {
+                    (low, high)
+                } else {
+                    (high, low)
+                };
+                matched |= (low..=high).contains(&byte);
+                p += 3;
+            }
+            Some(&literal) => {
+                matched |= literal == byte;
+                p += 1;
+            }
+        }
+    }
+    (matched != negated).then_some(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, subject: &str) -> bool {
+        glob_match(pattern.as_bytes(), subject.as_bytes())
+    }
+
+    #[test]
+    fn wildcards_match_like_redis() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("user:*", "user:1"));
+        assert!(!matches("user:*", "users"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("*a*b*c", "xxaxxbxxc"));
+        assert!(!matches("*a*b*c", "xxaxxcxxb"));
+        assert!(matches("a**", "a"));
+        assert!(!matches("abc", "abcd"));
+    }
+
+    #[test]
+    fn classes_and_escapes_match_like_redis() {
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+        assert!(matches("[abc", "a"));
+    }
+}
@@ -0,0 +1,211 @@
+//! # Latency Monitor
+//!
+//! Record commands and background cycles that took at least
+//! `latency-monitor-threshold`, for `LATENCY LATEST`, `LATENCY HISTORY`
+//! and `LATENCY RESET`.
+//!
+//! ## Design Principles
+//!
+//! 1. **Redis Events**: Each slow command is an event named after the
+//!    command; the expirer reports `expire-cycle` and background saves
+//!    `bgsave`. Replies have Redis's shapes, so `redis-cli` and dashboards
+//!    read them unchanged.
+//! 2. **Off by Default**: With the threshold at 0, the only cost after each
+//!    command is one relaxed load of the threshold.
+//! 3. **Bounded History**: Each event keeps its last `LATENCY_HISTORY_LEN`
+//!    samples, at most one per second; a second sample in the same second
+//!    keeps the slower of the two, as Redis does.
+//! 4. **Lock Off the Fast Path**: Samples sit behind a mutex taken only when
+//!    something was slow or `LATENCY` is called.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Samples kept per event, as in Redis.
+pub const LATENCY_HISTORY_LEN: usize = 160;
+
+/// Event the expirer reports each slow sweep as.
+pub const EXPIRE_CYCLE_EVENT: &str = "expire-cycle";
+
+/// Event a slow `BGSAVE` write is reported as.
+pub const BGSAVE_EVENT: &str = "bgsave";
+
+/// One slow occurrence of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    /// Unix time in seconds.
+    pub time: u64,
+    /// Duration in milliseconds.
+    pub millis: u64,
+}
+
+/// The newest sample of an event and the slowest since it was last reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatestLatency {
+    pub event: &'static str,
+    pub latest: LatencySample,
+    pub max_millis: u64,
+}
+
+#[derive(Default)]
+struct EventSeries {
+    samples: VecDeque<LatencySample>,
+    max_millis: u64,
+}
+
+/// Slow events by name.
+#[derive(Default)]
+pub struct LatencyMonitor {
+    events: Mutex<BTreeMap<&'static str, EventSeries>>,
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        LatencyMonitor::default()
+    }
+
+    /// Records `event` if it took at least `threshold`; `None` records
+    /// nothing.
+    pub fn observe(&self, threshold: Option<Duration>, event: &'static str, elapsed: Duration) {
+        if let Some(threshold) = threshold
+            && elapsed >= threshold
+        {
+            let millis = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+            self.record(event, unix_now_secs(), millis);
+        }
+    }
+
+    /// Adds a sample of `event` taken at Unix time `time`.
+    pub fn record(&self, event: &'static str, time: u64, millis: u64) {
+        let mut events = self.events.lock().unwrap();
+        let series = events.entry(event).or_default();
+        series.max_millis = series.max_millis.max(millis);
+        match series.samples.back_mut() {
+            Some(last) if last.time == time => last.millis = last.millis.max(millis),
+            _ => {
+                if series.samples.len() == LATENCY_HISTORY_LEN {
+                    series.samples.pop_front();
+                }
+                series.samples.push_back(LatencySample { time, millis });
+            }
+        }
+    }
+
+    /// The newest sample of every event, ordered by name.
+    pub fn latest(&self) -> Vec<LatestLatency> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter_map(|(&event, series)| {
+                Some(LatestLatency {
+                    event,
+                    latest: *series.samples.back()?,
+                    max_millis: series.max_millis,
+                })
+            })
+            .collect()
+    }
+
+    /// The samples of `event`, oldest first; empty for an unknown event.
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        let events = self.events.lock().unwrap();
+        events
+            .get(event)
+            .map(|series| series.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the samples of `events`, or of every event when empty.
+    /// Returns how many events had samples.
+    pub fn reset<'a>(&self, events: impl IntoIterator<Item = &'a str>) -> usize {
+        let mut all = self.events.lock().unwrap();
+        let mut events = events.into_iter().peekable();
+        if events.peek().is_none() {
+            let count = all.len();
+            all.clear();
+            return count;
+        }
+        events.filter(|event| all.remove(*event).is_some()).count()
+    }
+}
+
+fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_in_one_second_keep_the_slowest() {
+        let monitor = LatencyMonitor::new();
+        monitor.record("get", 100, 5);
+        monitor.record("get", 100, 9);
+        monitor.record("get", 100, 7);
+        monitor.record("get", 101, 3);
+        assert_eq!(
+            monitor.history("get"),
+            [
+                LatencySample {
+                    time: 100,
+                    millis: 9
+                },
+                LatencySample {
+                    time: 101,
+                    millis: 3
+                },
+            ]
+        );
+        assert_eq!(
+            monitor.latest(),
+            [LatestLatency {
+                event: "get",
+                latest: LatencySample {
+                    time: 101,
+                    millis: 3
+                },
+                max_millis: 9,
+            }]
+        );
+    }
+
+    #[test]
+    fn history_keeps_the_newest_samples() {
+        let monitor = LatencyMonitor::new();
+        for time in 0..LATENCY_HISTORY_LEN as u64 + 10 {
+            monitor.record("set", time, time);
+        }
+        let history = monitor.history("set");
+        assert_eq!(history.len(), LATENCY_HISTORY_LEN);
+        assert_eq!(history[0].time, 10);
+        assert!(monitor.history("unknown").is_empty());
+    }
+
+    #[test]
+    fn observe_respects_the_threshold() {
+        let monitor = LatencyMonitor::new();
+        let threshold = Some(Duration::from_millis(10));
+        monitor.observe(None, "get", Duration::from_secs(1));
+        monitor.observe(threshold, "get", Duration::from_millis(9));
+        assert!(monitor.latest().is_empty());
+        monitor.observe(threshold, "get", Duration::from_millis(10));
+        assert_eq!(monitor.history("get")[0].millis, 10);
+    }
+
+    #[test]
+    fn reset_counts_the_events_forgotten() {
+        let monitor = LatencyMonitor::new();
+        for event in ["get", "set", EXPIRE_CYCLE_EVENT] {
+            monitor.record(event, 1, 1);
+        }
+        assert_eq!(monitor.reset(["get", "nosuch"]), 1);
+        assert!(monitor.history("get").is_empty());
+        assert_eq!(monitor.reset([]), 2);
+        assert!(monitor.latest().is_empty());
+    }
+}
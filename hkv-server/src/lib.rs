@@ -1,7 +1,32 @@
+pub mod access_log;
+pub mod acl;
+pub mod cli;
+pub mod config;
+pub mod config_file;
+pub mod exporter;
+pub mod latency;
+pub mod lifecycle;
 pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod persistence;
 pub mod protocol;
+pub mod rate_limit;
+pub mod read_buffer;
+pub mod replication;
+pub mod reply;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod server;
+pub mod shutdown;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod tracking;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
 
+mod glob;
+mod logging;
 mod observation;
 
 pub mod phase2a_testing {
@@ -0,0 +1,160 @@
+//! # Server Lifecycle
+//!
+//! Track where the server is between process start and exit, so HTTP
+//! probes can tell a live process from one ready to take traffic.
+//!
+//! ## Design Principles
+//!
+//! 1. **Forward Only**: The phase moves `Starting` → `Loading` → `Ready` →
+//!    `Draining` and never back; `advance` ignores a phase the server is
+//!    already past, so components can report without coordinating.
+//! 2. **Owners Report Their Own Work**: `main` reports loading, the accept
+//!    loop reports when it starts serving and when it starts draining, and
+//!    replication holds readiness back until a replica's first sync from its
+//!    master has loaded.
+//! 3. **Lock-Free Reads**: Probes read two atomics; reporting a transition
+//!    never waits for a probe.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// A stage of the server's life, in the order they happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LifecyclePhase {
+    /// The process is parsing its configuration.
+    Starting,
+    /// Startup work, such as building the engine, is in progress.
+    Loading,
+    /// Connections are being accepted and served.
+    Ready,
+    /// Shutdown has begun; open connections are finishing.
+    Draining,
+}
+
+impl LifecyclePhase {
+    const ALL: [LifecyclePhase; 4] = [
+        LifecyclePhase::Starting,
+        LifecyclePhase::Loading,
+        LifecyclePhase::Ready,
+        LifecyclePhase::Draining,
+    ];
+
+    /// The lowercase name probes report.
+    pub fn name(self) -> &'static str {
+        match self {
+            LifecyclePhase::Starting => "starting",
+            LifecyclePhase::Loading => "loading",
+            LifecyclePhase::Ready => "ready",
+            LifecyclePhase::Draining => "draining",
+        }
+    }
+}
+
+/// Shared lifecycle state; see the module docs.
+pub struct ServerLifecycle {
+    phase: AtomicU8,
+    /// Set while a replica has not yet loaded its first sync.
+    awaiting_sync: AtomicBool,
+}
+
+impl ServerLifecycle {
+    /// A lifecycle in `Starting`.
+    pub fn new() -> Self {
+        ServerLifecycle {
+            phase: AtomicU8::new(LifecyclePhase::Starting as u8),
+            awaiting_sync: AtomicBool::new(false),
+        }
+    }
+
+    /// The current phase.
+    pub fn phase(&self) -> LifecyclePhase {
+        LifecyclePhase::ALL[usize::from(self.phase.load(Ordering::Acquire))]
+    }
+
+    /// Moves to `phase` unless the server is already there or past it.
+    pub fn advance(&self, phase: LifecyclePhase) {
+        let previous = self.phase.fetch_max(phase as u8, Ordering::AcqRel);
+        if previous < phase as u8 {
+            tracing::info!(phase = phase.name(), "lifecycle phase changed");
+        }
+    }
+
+    /// Records whether this server is a replica still waiting for its first
+    /// sync from a master.
+    pub fn set_awaiting_sync(&self, awaiting: bool) {
+        self.awaiting_sync.store(awaiting, Ordering::Release);
+    }
+
+    /// Whether a replica is still waiting for its first sync.
+    pub fn awaiting_sync(&self) -> bool {
+        self.awaiting_sync.load(Ordering::Acquire)
+    }
+
+    /// Whether the server should receive traffic: serving, and not a replica
+    /// that has yet to sync.
+    pub fn is_ready(&self) -> bool {
+        self.phase() == LifecyclePhase::Ready && !self.awaiting_sync()
+    }
+
+    /// The state as a small JSON object for HTTP probes.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"phase\":\"{}\",\"ready\":{},\"awaiting_sync\":{}}}\n",
+            self.phase().name(),
+            self.is_ready(),
+            self.awaiting_sync()
+        )
+    }
+}
+
+impl Default for ServerLifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ServerLifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerLifecycle")
+            .field("phase", &self.phase())
+            .field("awaiting_sync", &self.awaiting_sync())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_only_move_forward() {
+        let lifecycle = ServerLifecycle::new();
+        assert_eq!(lifecycle.phase(), LifecyclePhase::Starting);
+        lifecycle.advance(LifecyclePhase::Ready);
+        lifecycle.advance(LifecyclePhase::Loading);
+        assert_eq!(lifecycle.phase(), LifecyclePhase::Ready);
+        lifecycle.advance(LifecyclePhase::Draining);
+        lifecycle.advance(LifecyclePhase::Ready);
+        assert_eq!(lifecycle.phase(), LifecyclePhase::Draining);
+    }
+
+    #[test]
+    fn readiness_waits_for_a_replica_to_sync() {
+        let lifecycle = ServerLifecycle::new();
+        lifecycle.advance(LifecyclePhase::Loading);
+        assert!(!lifecycle.is_ready());
+
+        lifecycle.advance(LifecyclePhase::Ready);
+        lifecycle.set_awaiting_sync(true);
+        assert!(!lifecycle.is_ready());
+        assert_eq!(
+            lifecycle.to_json(),
+            "{\"phase\":\"ready\",\"ready\":false,\"awaiting_sync\":true}\n"
+        );
+
+        lifecycle.set_awaiting_sync(false);
+        assert!(lifecycle.is_ready());
+        lifecycle.advance(LifecyclePhase::Draining);
+        assert!(!lifecycle.is_ready());
+    }
+}
@@ -0,0 +1,130 @@
+//! # Log Formatting
+//!
+//! Helpers for putting client input into `tracing` events without leaking
+//! secrets or flooding the log.
+//!
+//! ## Design Principles
+//!
+//! 1. **Lazy**: `LoggedArgs` formats only when a subscriber records it, and
+//!    callers check `tracing::enabled!` before building one.
+//! 2. **Redacted**: Everything after `AUTH` and the user name of
+//!    `ACL SETUSER` is replaced, so passwords never reach the log.
+//! 3. **Bounded**: Arguments longer than `MAX_LOGGED_ARG_LEN` are cut, with
+//!    their full length noted, so a large value costs one line.
+
+use std::fmt;
+
+use bytes::Bytes;
+
+/// Bytes of a single argument written before it is truncated.
+pub const MAX_LOGGED_ARG_LEN: usize = 64;
+
+/// Stands in for redacted arguments.
+const REDACTED: &str = "(redacted)";
+
+/// Formats a command's arguments for logging, escaping non-printable
+/// bytes, redacting `AUTH` credentials, the rules of `ACL SETUSER` and the
+/// password `CONFIG SET requirepass` sets, and truncating long arguments.
+pub struct LoggedArgs<'a>(pub &'a [Bytes]);
+
+impl fmt::Display for LoggedArgs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redact_from = match self.0.first() {
+            Some(cmd) if cmd.eq_ignore_ascii_case(b"AUTH") => 1,
+            Some(cmd)
+                if cmd.eq_ignore_ascii_case(b"CONFIG")
+                    && self
+                        .0
+                        .get(2)
+                        .is_some_and(|p| p.eq_ignore_ascii_case(b"requirepass")) =>
+            {
+                3
+            }
+            Some(cmd)
+                if cmd.eq_ignore_ascii_case(b"ACL")
+                    && self
+                        .0
+                        .get(1)
+                        .is_some_and(|sub| sub.eq_ignore_ascii_case(b"SETUSER")) =>
+            {
+                3
+            }
+            _ => self.0.len(),
+        };
+        for (i, arg) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            if i >= redact_from {
+                f.write_str(REDACTED)?;
+            } else {
+                write!(f, "{}", LoggedArg(arg))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats one argument for logging, escaped and truncated like
+/// `LoggedArgs`.
+pub struct LoggedArg<'a>(pub &'a [u8]);
+
+impl fmt::Display for LoggedArg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.len() > MAX_LOGGED_ARG_LEN {
+            write!(
+                f,
+                "{}... ({} bytes)",
+                self.0[..MAX_LOGGED_ARG_LEN].escape_ascii(),
+                self.0.len()
+            )
+        } else {
+            write!(f, "{}", self.0.escape_ascii())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logged(args: &[&[u8]]) -> String {
+        let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+        LoggedArgs(&args).to_string()
+    }
+
+    #[test]
+    fn auth_arguments_are_redacted() {
+        assert_eq!(logged(&[b"auth", b"hunter2"]), "auth (redacted)");
+        assert_eq!(
+            logged(&[b"AUTH", b"user", b"hunter2"]),
+            "AUTH (redacted) (redacted)"
+        );
+        assert_eq!(logged(&[b"GET", b"auth"]), "GET auth");
+        assert_eq!(
+            logged(&[b"CONFIG", b"SET", b"REQUIREPASS", b"hunter2"]),
+            "CONFIG SET REQUIREPASS (redacted)"
+        );
+        assert_eq!(
+            logged(&[b"CONFIG", b"GET", b"requirepass"]),
+            "CONFIG GET requirepass"
+        );
+        assert_eq!(
+            logged(&[b"acl", b"setuser", b"alice", b"on", b">hunter2"]),
+            "acl setuser alice (redacted) (redacted)"
+        );
+    }
+
+    #[test]
+    fn long_arguments_are_truncated_and_bytes_escaped() {
+        let value = vec![b'x'; MAX_LOGGED_ARG_LEN + 10];
+        let line = logged(&[b"SET", b"k\r\n", &value]);
+        assert_eq!(
+            line,
+            format!(
+                "SET k\\r\\n {}... (74 bytes)",
+                "x".repeat(MAX_LOGGED_ARG_LEN)
+            )
+        );
+    }
+}
@@ -9,46 +9,388 @@
 //! 2. **Async First**: Tokio handles concurrent connections efficiently.
 //! 3. **Fail-Open Defaults**: Protocol errors are localized to the connection.
 //! 4. **Performance Focus**: Reuse buffers and avoid unnecessary allocations.
+//! 5. **Graceful Exit**: SIGINT/SIGTERM stop accepts, let connections answer
+//!    what they already received, stop the expirer, and save a snapshot
+//!    before exiting 0.
+//!
+//! ## Configuration
+//!
+//! Settings come from `redis-server`-style flags; run with `--help` for the
+//! full list. `hkv-server /path/to/hkv.conf` also reads a redis.conf-style
+//! file whose directives are named like the flags; `CONFIG REWRITE` saves
+//! runtime changes back to it. Flags take precedence over the file, the
+//! file over the environment variables each flag falls back to:
+//!
+//! - `--bind`, `--port` / `HKV_ADDR`: listen address (default
+//!   `127.0.0.1:6379`). `--bind` takes several addresses separated by
+//!   spaces or commas, such as `"127.0.0.1 ::1"`, and listens on each;
+//!   startup fails if one cannot be bound unless it is prefixed with `-`.
+//! - `--dir` / `HKV_DATA_DIR`: directory for `dump.hkv`; when set, a
+//!   snapshot is also saved on shutdown. Defaults to the working directory,
+//!   without the exit save.
+//! - `--shutdown-grace` / `HKV_SHUTDOWN_GRACE_SECS`: how long open
+//!   connections may take to finish after a shutdown signal (default 5).
+//! - `--timeout` / `HKV_IDLE_TIMEOUT_SECS`: initial `timeout` setting;
+//!   connections silent this long are closed (default 0, disabled).
+//!   `CONFIG SET timeout` changes it at runtime.
+//! - `--maxclients` / `HKV_MAXCLIENTS`: initial `maxclients` setting
+//!   (default 10000); further connections get
+//!   `-ERR max number of clients reached` and are closed.
+//! - `--maxmemory` / `HKV_MAXMEMORY`: engine byte limit such as `100mb`
+//!   (default 0, unlimited); least recently used keys are evicted past it.
+//! - `--metrics-addr` / `HKV_METRICS_ADDR`: when set (e.g. `0.0.0.0:9121`),
+//!   serve Prometheus metrics over HTTP at `/metrics` on this address, and
+//!   the `/healthz` and `/readyz` probes.
+//! - `--loglevel` / `HKV_LOGLEVEL`: `debug`, `verbose`, `notice`, `warning`
+//!   or `nothing`. Without it, `RUST_LOG` is read as a
+//!   `tracing_subscriber::EnvFilter` (default `info`). `verbose` adds
+//!   per-connection and per-command spans, `debug` the redacted arguments of
+//!   every command.
+//! - `--accesslog` / `HKV_ACCESSLOG`: file, or `-` for stdout, receiving
+//!   one line per command with its client, name, first key, duration and
+//!   outcome. The file is reopened on SIGHUP and SIGUSR1 for log rotation.
+//! - `--accesslog-sample-rate` / `HKV_ACCESSLOG_SAMPLE_RATE`: log one
+//!   command in N (default 1, every command; 0 logs none).
+//!   `CONFIG SET accesslog-sample-rate` changes it at runtime.
+//! - `--read-only` / `HKV_READ_ONLY`: refuse write commands with
+//!   `-READONLY` while reads continue; `CONFIG SET read-only 1`/`0` toggles
+//!   it at runtime.
+//! - `--repl-backlog-size` / `HKV_REPL_BACKLOG_SIZE`: bytes of recent writes
+//!   a master keeps so reconnecting replicas can catch up without a full
+//!   sync (default `1mb`). `CONFIG SET repl-backlog-size` changes it at
+//!   runtime.
+//! - `--tcp-nodelay` / `HKV_TCP_NODELAY`, `--tcp-keepalive` /
+//!   `HKV_TCP_KEEPALIVE`: `TCP_NODELAY` (default `yes`) and the keepalive
+//!   time in seconds (default 300, 0 disables) for accepted sockets; both
+//!   can be changed with `CONFIG SET`.
+//! - `--tcp-backlog` / `HKV_TCP_BACKLOG`: the listen backlog (default 511).
+//! - `--acceptors` / `HKV_ACCEPTORS`: accept loops per listen address
+//!   (default one per worker thread, or `0`). With more than one, each
+//!   address gets that many `SO_REUSEPORT` listeners and the kernel spreads
+//!   new connections across them; `--acceptors 1` keeps a single listener.
+//!   Platforms without `SO_REUSEPORT` default to one.
+//! - `--io-uring` / `HKV_IO_URING`: accept, read and write through io_uring
+//!   (default `no`; needs a build with the `uring` feature). On kernels
+//!   older than 5.19, or where a ring cannot be set up, the server logs why
+//!   and uses the standard path.
+//! - `--requirepass` / `HKV_REQUIREPASS`: password clients must give with
+//!   `AUTH` before other commands; it is the ACL default user's password.
+//! - `--aclfile` / `HKV_ACLFILE`: file of `user <name> <rules>` lines, as
+//!   `ACL LIST` prints them, loaded at startup; the server does not start
+//!   if it cannot be read. `ACL LOAD` reloads it and `ACL SAVE` writes the
+//!   current users to it. Users created with `ACL SETUSER` log in with
+//!   `AUTH <user> <password>`; commands they may not run, or keys outside
+//!   their `~patterns`, are refused with `-NOPERM`.
+//! - `--protected-mode` / `HKV_PROTECTED_MODE`: while `yes` (the default)
+//!   and no password is set, connections from non-loopback addresses are
+//!   refused with an explanation, whatever `--bind` covers.
+//!   `CONFIG SET protected-mode no` or `CONFIG SET requirepass` lifts it at
+//!   runtime.
+//!
+//! `CONFIG SET maxcmd-per-sec N` delays each connection's commands past N
+//! per second, and `CONFIG SET maxconn-per-ip-per-sec N` refuses connections
+//! from one address past N per second; both default to 0, unlimited.
+//!
+//! Whole-keyspace commands such as `KEYS` and `FLUSHALL` run on tokio's
+//! blocking pool, so they never stall other connections.
+//! `CONFIG SET command-time-limit MS` bounds `KEYS`: past it the scan stops
+//! and replies `-BUSY`. The default, 0, leaves it unbounded. `FLUSHALL` and
+//! `FLUSHDB` always finish, so replicas and tracking clients hear about
+//! every flush.
+//!
+//! `CONFIG SET latency-monitor-threshold MS` records commands, expirer
+//! sweeps (`expire-cycle`) and background saves (`bgsave`) taking at least
+//! that long, for `LATENCY LATEST`, `LATENCY HISTORY event` and
+//! `LATENCY RESET`. The default, 0, records nothing.
+//!
+//! Connection read buffers start at 4 KiB and grow to fit the requests
+//! read; `CONFIG SET read-buffer-shrink-after N` shrinks one again after N
+//! reads in a row that fill at most a quarter of it (default 32, 0 never
+//! shrinks). `INFO memory` reports the bytes they hold.
+//!
+//! With the `scripting` feature, `EVAL`, `EVALSHA` and `SCRIPT` run Lua
+//! scripts; `CONFIG SET lua-time-limit` sets how many milliseconds a script
+//! runs before other clients get `-BUSY` and `SCRIPT KILL` may stop it
+//! (default 5000).
+//!
+//! With the `tls` feature:
+//!
+//! - `HKV_TLS_CERT_FILE`, `HKV_TLS_KEY_FILE`: PEM certificate chain and key;
+//!   setting both serves TLS only. SIGHUP rereads them.
+//! - `HKV_TLS_CA_CERT_FILE`: PEM CA bundle for verifying client
+//!   certificates.
+//! - `HKV_TLS_AUTH_CLIENTS`: `yes` (default when a CA is set) requires a
+//!   client certificate, `optional` verifies one only if offered.
+//!
+//! With the `otel` feature:
+//!
+//! - `--otel-endpoint` / `HKV_OTEL_ENDPOINT`: base URL of an OTLP/HTTP
+//!   collector, such as `http://localhost:4318`; setting it exports the
+//!   metrics `INFO` reports and per-command durations.
+//! - `--otel-headers` / `HKV_OTEL_HEADERS`: `name=value` pairs separated by
+//!   commas, sent with every export.
+//! - `--otel-interval` / `HKV_OTEL_INTERVAL`: seconds between metric
+//!   exports (default 60).
+//! - `--otel-traces` / `HKV_OTEL_TRACES`: `yes` also exports the
+//!   `connection` and `command` spans. Shutdown flushes both.
 
 use std::sync::Arc;
 use std::time::Duration;
 
 use tokio::net::TcpListener;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, fmt};
 
 use hkv_engine::MemoryEngine;
+use hkv_server::access_log::AccessLog;
+use hkv_server::cli::{ListenAddr, ServerConfig};
+use hkv_server::exporter;
+use hkv_server::latency::EXPIRE_CYCLE_EVENT;
+use hkv_server::lifecycle::LifecyclePhase;
 use hkv_server::metrics::Metrics;
+#[cfg(feature = "otel")]
+use hkv_server::otel::Telemetry;
+use hkv_server::persistence::Persistence;
 use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+#[cfg(feature = "tls")]
+use hkv_server::tls::{TlsConfig, TlsState};
+
+/// How often the expirer sweeps for expired keys.
+const EXPIRER_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let addr = std::env::var("HKV_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
-    let listener = TcpListener::bind(&addr).await?;
+    let config = ServerConfig::from_args();
+    let filter = match config.log_level {
+        Some(level) => EnvFilter::new(level.filter()),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+    };
+    let subscriber = tracing_subscriber::registry().with(fmt::layer().with_filter(filter));
+    #[cfg(feature = "otel")]
+    let telemetry = config
+        .otel
+        .as_ref()
+        .map(Telemetry::otlp)
+        .transpose()
+        .map_err(std::io::Error::other)?;
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(telemetry.as_ref().and_then(Telemetry::tracing_layer));
+    subscriber.init();
+    if let Some(path) = &config.config_file {
+        tracing::info!(path = %path.display(), "loaded configuration file");
+    }
+    for directive in &config.ignored_directives {
+        tracing::warn!(
+            path = %directive.path.display(),
+            line = directive.line,
+            directive = %directive.name,
+            "ignoring unsupported configuration directive"
+        );
+    }
+
+    let mut runtime = config.runtime_config();
+    if let Some(path) = runtime.aclfile() {
+        runtime.acl().load(path).map_err(std::io::Error::other)?;
+        tracing::info!(path = %path.display(), "loaded ACL file");
+    }
+    let acceptors =
+        config.acceptor_count(tokio::runtime::Handle::current().metrics().num_workers());
+    runtime.set_acceptors(acceptors);
+    if let Some(target) = config.access_log.clone() {
+        let access_log = AccessLog::open(target).await?;
+        #[cfg(unix)]
+        access_log.reopen_on_signals()?;
+        runtime = runtime.with_access_log(access_log);
+    }
+    let runtime = Arc::new(runtime);
+    let lifecycle = Arc::clone(runtime.lifecycle());
+    lifecycle.advance(LifecyclePhase::Loading);
+    let listeners = bind_listeners(&config.listen_addrs, config.tcp_backlog, acceptors)?;
+    let shutdown = ShutdownController::new();
+    shutdown.listen_for_signals()?;
 
-    let engine = Arc::new(MemoryEngine::new());
+    let engine = Arc::new(MemoryEngine::with_capacity(config.engine_capacity()));
     let metrics = Arc::new(Metrics::new());
-    let expirer = engine.start_expirer(Duration::from_secs(1));
+    #[cfg(feature = "otel")]
+    if let (Some(telemetry), Some(otel)) = (&telemetry, &config.otel) {
+        telemetry.instrument(&metrics);
+        tracing::info!(endpoint = %otel.endpoint, traces = otel.traces, "exporting telemetry");
+    }
+    let persistence = Arc::new(
+        config
+            .data_dir
+            .as_ref()
+            .map_or_else(Persistence::default, Persistence::new),
+    );
+    let expirer = {
+        let latency = Arc::clone(metrics.latency_monitor());
+        let runtime = Arc::clone(&runtime);
+        engine.start_expirer_with(EXPIRER_INTERVAL, move |elapsed| {
+            latency.observe(
+                runtime.latency_monitor_threshold(),
+                EXPIRE_CYCLE_EVENT,
+                elapsed,
+            );
+        })
+    };
+    tracing::info!(
+        interval_ms = EXPIRER_INTERVAL.as_millis() as u64,
+        "expirer started"
+    );
 
-    let result = server::serve_with_shutdown(listener, engine, metrics, shutdown_signal()?).await;
+    // Probes keep answering, with /readyz at 503, until the snapshot below
+    // is saved.
+    let exporter_stop = ShutdownController::new();
+    if let Some(metrics_addr) = config.metrics_addr {
+        let metrics_listener = TcpListener::bind(metrics_addr).await?;
+        tracing::info!(addr = %metrics_listener.local_addr()?, "serving metrics");
+        tokio::spawn(exporter::serve_metrics(
+            metrics_listener,
+            Arc::clone(&engine),
+            Arc::clone(&metrics),
+            lifecycle,
+            exporter_stop.wait(),
+        ));
+    }
+
+    #[cfg(feature = "tls")]
+    let result = match tls_from_env()? {
+        Some(tls) => {
+            tls.reload_on_sighup()?;
+            server::serve_tls_listeners_with_runtime_config(
+                listeners,
+                Arc::clone(&engine),
+                metrics,
+                Arc::clone(&persistence),
+                runtime,
+                tls,
+                shutdown.wait(),
+                config.shutdown_grace,
+            )
+            .await
+        }
+        None => {
+            server::serve_listeners_with_runtime_config(
+                listeners,
+                Arc::clone(&engine),
+                metrics,
+                Arc::clone(&persistence),
+                runtime,
+                shutdown.wait(),
+                config.shutdown_grace,
+            )
+            .await
+        }
+    };
+    #[cfg(not(feature = "tls"))]
+    let result = server::serve_listeners_with_runtime_config(
+        listeners,
+        Arc::clone(&engine),
+        metrics,
+        Arc::clone(&persistence),
+        runtime,
+        shutdown.wait(),
+        config.shutdown_grace,
+    )
+    .await;
     expirer.stop();
+    tracing::info!("shutdown: expirer stopped");
+
+    // Connections are closed, so this snapshot includes every acknowledged write.
+    if config.data_dir.is_some() {
+        persistence.save(engine.as_ref())?;
+        tracing::info!(path = %persistence.snapshot_path().display(), "shutdown: snapshot saved");
+    }
+    exporter_stop.trigger();
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        // Exports block on the collector, so they stay off the runtime.
+        match tokio::task::spawn_blocking(move || telemetry.shutdown()).await? {
+            Ok(()) => tracing::info!("shutdown: telemetry flushed"),
+            Err(err) => tracing::warn!(error = %err, "shutdown: telemetry flush failed"),
+        }
+    }
     result
 }
 
-#[cfg(unix)]
-fn shutdown_signal() -> std::io::Result<impl std::future::Future<Output = ()>> {
-    use tokio::signal::unix::{SignalKind, signal};
-
-    let mut terminate = signal(SignalKind::terminate())?;
-    Ok(async move {
-        tokio::select! {
-            _ = tokio::signal::ctrl_c() => {}
-            _ = terminate.recv() => {}
+/// Binds `acceptors` listeners per address. Startup fails naming the
+/// address that could not be bound, unless that address is optional; then
+/// it is skipped with a warning, as long as at least one listener is left.
+fn bind_listeners(
+    addrs: &[ListenAddr],
+    backlog: u32,
+    acceptors: usize,
+) -> std::io::Result<Vec<TcpListener>> {
+    let mut listeners = Vec::with_capacity(addrs.len() * acceptors);
+    for listen in addrs {
+        match bind_address(listen, backlog, acceptors) {
+            Ok(bound) => {
+                tracing::info!(addr = %bound[0].local_addr()?, acceptors = bound.len(), "listening");
+                listeners.extend(bound);
+            }
+            Err(err) if listen.optional => {
+                tracing::warn!(addr = %listen.addr, error = %err, "skipping optional bind address");
+            }
+            Err(err) => {
+                return Err(std::io::Error::new(
+                    err.kind(),
+                    format!("could not listen on {}: {err}", listen.addr),
+                ));
+            }
         }
-    })
+    }
+    if listeners.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            "could not listen on any bind address",
+        ));
+    }
+    Ok(listeners)
+}
+
+/// Binds one listener on `listen`, or `acceptors` sharing it through
+/// `SO_REUSEPORT`.
+fn bind_address(
+    listen: &ListenAddr,
+    backlog: u32,
+    acceptors: usize,
+) -> std::io::Result<Vec<TcpListener>> {
+    if acceptors <= 1 {
+        return server::bind_listener(listen.addr, backlog).map(|listener| vec![listener]);
+    }
+    #[cfg(unix)]
+    return server::bind_reuseport_listeners(listen.addr, backlog, acceptors);
+    #[cfg(not(unix))]
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "more than one acceptor needs SO_REUSEPORT",
+    ))
 }
 
-#[cfg(not(unix))]
-fn shutdown_signal() -> std::io::Result<impl std::future::Future<Output = ()>> {
-    Ok(async {
-        let _ = tokio::signal::ctrl_c().await;
-    })
+/// Loads TLS settings from `HKV_TLS_*`; `None` when no certificate is set.
+#[cfg(feature = "tls")]
+fn tls_from_env() -> std::io::Result<Option<Arc<TlsState>>> {
+    let (Some(cert_file), Some(key_file)) = (
+        std::env::var_os("HKV_TLS_CERT_FILE"),
+        std::env::var_os("HKV_TLS_KEY_FILE"),
+    ) else {
+        return Ok(None);
+    };
+    let mut config = TlsConfig::new(cert_file, key_file);
+    if let Some(ca_file) = std::env::var_os("HKV_TLS_CA_CERT_FILE") {
+        let require = match std::env::var("HKV_TLS_AUTH_CLIENTS").as_deref() {
+            Ok("optional") => false,
+            Ok("yes") | Err(_) => true,
+            Ok(other) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("HKV_TLS_AUTH_CLIENTS must be yes or optional, got {other}"),
+                ));
+            }
+        };
+        config = config.with_client_ca(ca_file, require);
+    }
+    TlsState::load(config).map(|state| Some(Arc::new(state)))
 }
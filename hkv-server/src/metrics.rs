@@ -13,16 +13,112 @@
 //! - Metrics are intentionally decoupled from the request path to keep the
 //!   server fast; wiring and sampling policy are left to the caller.
 //! - Bucket boundaries are expressed in microseconds and can be tuned later.
+//! - Rates come from diffing snapshots (`delta_since`); `sample_rates`
+//!   keeps the last sample so INFO can report `instantaneous_ops_per_sec`.
+//! - Slow events for `LATENCY` are kept by the `LatencyMonitor` here, apart
+//!   from the histograms; `CONFIG RESETSTAT` leaves them, as in Redis.
 
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use crate::latency::LatencyMonitor;
+
 /// Default latency bucket boundaries in microseconds.
 ///
 /// These are coarse on purpose to keep bucket scans short (performance-first).
 pub const DEFAULT_LATENCY_BUCKETS_US: [u64; 12] =
     [1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000];
 
+/// Interval at which the server calls `Metrics::sample_rates`.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Commands counted individually: the dispatcher's command table, as
+/// lowercase names. Every other name is counted as `UNKNOWN_COMMAND`, so
+/// client input cannot add entries.
+pub const TRACKED_COMMANDS: [&str; 33] = [
+    "ping",
+    "get",
+    "set",
+    "del",
+    "expire",
+    "ttl",
+    "info",
+    "save",
+    "bgsave",
+    "lastsave",
+    "config",
+    "lolwut",
+    "eval",
+    "evalsha",
+    "script",
+    "function",
+    "cluster",
+    "replicaof",
+    "slaveof",
+    "replconf",
+    "psync",
+    "hello",
+    "client",
+    "command",
+    "reset",
+    "auth",
+    "keys",
+    "flushall",
+    "flushdb",
+    "mget",
+    "acl",
+    "latency",
+    "debug",
+];
+
+/// Entry counting every command name outside `TRACKED_COMMANDS`.
+pub const UNKNOWN_COMMAND: &str = "unknown";
+
+/// Error reply prefixes counted individually, as in Redis's `errorstat_*`
+/// lines. Every other prefix, such as one a script chose, is counted as
+/// `OTHER_ERROR`.
+pub const TRACKED_ERRORS: [&str; 12] = [
+    "ERR",
+    "OOM",
+    "BUSY",
+    "LOADING",
+    "WRONGTYPE",
+    "READONLY",
+    "NOAUTH",
+    "WRONGPASS",
+    "NOSCRIPT",
+    "NOPROTO",
+    "DENIED",
+    "NOPERM",
+];
+
+/// Entry counting every error prefix outside `TRACKED_ERRORS`.
+pub const OTHER_ERROR: &str = "OTHER";
+
+fn error_index(prefix: &[u8]) -> usize {
+    TRACKED_ERRORS
+        .iter()
+        .position(|tracked| tracked.as_bytes() == prefix)
+        .unwrap_or(TRACKED_ERRORS.len())
+}
+
+/// Returns the `TRACKED_COMMANDS` entry for `name` (any case), or
+/// `UNKNOWN_COMMAND`.
+pub fn command_name(name: &[u8]) -> &'static str {
+    TRACKED_COMMANDS
+        .get(command_index(name))
+        .copied()
+        .unwrap_or(UNKNOWN_COMMAND)
+}
+
+fn command_index(name: &[u8]) -> usize {
+    TRACKED_COMMANDS
+        .iter()
+        .position(|tracked| tracked.as_bytes().eq_ignore_ascii_case(name))
+        .unwrap_or(TRACKED_COMMANDS.len())
+}
+
 /// Snapshot of all server metrics at a point in time.
 #[derive(Debug, Clone)]
 pub struct MetricsSnapshot {
@@ -32,12 +128,55 @@ pub struct MetricsSnapshot {
     pub errors_total: u64,
     /// Current in-flight requests.
     pub inflight: u64,
+    /// Connections closed for exceeding the idle timeout.
+    pub idle_disconnects_total: u64,
+    /// Connections closed for exceeding `client-output-buffer-limit`.
+    pub output_buffer_limit_disconnects_total: u64,
+    /// Connections currently being served.
+    pub connected_clients: u64,
+    /// Connections accepted by every accept loop, including refused ones.
+    pub accepted_connections_total: u64,
+    /// Bytes of read buffer held by connections.
+    pub read_buffer_bytes: u64,
+    /// Bytes of read buffer pooled for new connections.
+    pub read_buffer_pool_bytes: u64,
+    /// Connections refused because `maxclients` was reached.
+    pub rejected_connections_total: u64,
+    /// Connections closed because their TLS handshake failed or timed out.
+    pub tls_handshake_failures_total: u64,
+    /// Commands delayed by `maxcmd-per-sec`.
+    pub throttled_commands_total: u64,
+    /// Connections refused by `maxconn-per-ip-per-sec`.
+    pub rate_limited_connections_total: u64,
+    /// Per-command stats: `TRACKED_COMMANDS` in order, then
+    /// `UNKNOWN_COMMAND`.
+    pub commands: Vec<CommandSnapshot>,
+    /// Error replies by prefix: `TRACKED_ERRORS` in order, then
+    /// `OTHER_ERROR`.
+    pub errors: Vec<(&'static str, u64)>,
     /// Time since the metrics instance was created.
     pub uptime: Duration,
+    /// Requests per second over the last `sample_rates` interval.
+    pub instantaneous_ops_per_sec: f64,
     /// Latency histogram snapshot.
     pub latency: LatencySnapshot,
 }
 
+/// Change in the counters between two snapshots.
+///
+/// Counters that went backwards (a reset in between) count as zero.
+#[derive(Debug, Clone)]
+pub struct MetricsDelta {
+    /// Time between the two snapshots.
+    pub interval: Duration,
+    /// Requests received in the interval.
+    pub requests: u64,
+    /// Error responses in the interval.
+    pub errors: u64,
+    /// Latency samples recorded in the interval, bucket by bucket.
+    pub latency: LatencySnapshot,
+}
+
 /// Snapshot of the latency histogram.
 #[derive(Debug, Clone)]
 pub struct LatencySnapshot {
@@ -53,6 +192,31 @@ pub struct LatencySnapshot {
     pub max_us: u64,
 }
 
+/// Snapshot of one command's calls and execution time.
+#[derive(Debug, Clone)]
+pub struct CommandSnapshot {
+    /// Lowercase command name, or `UNKNOWN_COMMAND`.
+    pub name: &'static str,
+    /// Calls executed.
+    pub calls: u64,
+    /// Calls answered with an error.
+    pub failed_calls: u64,
+    /// Total execution time in microseconds.
+    pub usec: u64,
+    /// Execution time histogram, with the global histogram's bounds.
+    pub latency: LatencySnapshot,
+}
+
+/// Common latency percentiles and mean, in microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub mean_us: f64,
+}
+
 /// Thread-safe metrics aggregator for the server.
 ///
 /// The struct is intentionally small and uses `AtomicU64` so record calls are
@@ -62,25 +226,49 @@ pub struct Metrics {
     requests_total: AtomicU64,
     errors_total: AtomicU64,
     inflight: AtomicU64,
+    idle_disconnects_total: AtomicU64,
+    output_buffer_limit_disconnects_total: AtomicU64,
+    connected_clients: AtomicU64,
+    accepted_connections_total: AtomicU64,
+    read_buffer_bytes: AtomicU64,
+    read_buffer_pool_bytes: AtomicU64,
+    rejected_connections_total: AtomicU64,
+    tls_handshake_failures_total: AtomicU64,
+    throttled_commands_total: AtomicU64,
+    rate_limited_connections_total: AtomicU64,
+    commands: [CommandStats; TRACKED_COMMANDS.len() + 1],
+    errors: [AtomicU64; TRACKED_ERRORS.len() + 1],
     latency: LatencyHistogram,
     started_at: Instant,
+    /// Snapshot taken by the previous `sample_rates` call.
+    last_sample: Mutex<Option<MetricsSnapshot>>,
+    /// `f64` bits of the requests per second at the last sample.
+    instantaneous_ops_per_sec: AtomicU64,
+    /// Commands and background cycles slower than
+    /// `latency-monitor-threshold`.
+    latency_monitor: Arc<LatencyMonitor>,
+    /// OpenTelemetry instruments `record_command` also feeds, once set.
+    #[cfg(feature = "otel")]
+    otel: std::sync::OnceLock<crate::otel::CommandInstruments>,
+}
+
+/// Counters for one entry of `Metrics::commands`; calls and total time come
+/// from the histogram.
+struct CommandStats {
+    failed_calls: AtomicU64,
+    latency: LatencyHistogram,
 }
 
 impl Metrics {
     /// Creates a new metrics aggregator with the default latency buckets.
     pub fn new() -> Self {
-        Metrics {
-            requests_total: AtomicU64::new(0),
-            errors_total: AtomicU64::new(0),
-            inflight: AtomicU64::new(0),
-            latency: LatencyHistogram::new(DEFAULT_LATENCY_BUCKETS_US.to_vec()),
-            started_at: Instant::now(),
-        }
+        Self::with_latency_buckets(DEFAULT_LATENCY_BUCKETS_US.to_vec())
     }
 
     /// Creates a new metrics aggregator with custom latency bucket boundaries.
     ///
-    /// The boundaries must be sorted ascending and represent microseconds.
+    /// The boundaries must be sorted ascending and represent microseconds;
+    /// per-command histograms use the same ones.
     ///
     /// **Input**: `bounds_us` (ascending microsecond thresholds).
     /// **Output**: a `Metrics` instance configured with those buckets.
@@ -89,11 +277,37 @@ impl Metrics {
             requests_total: AtomicU64::new(0),
             errors_total: AtomicU64::new(0),
             inflight: AtomicU64::new(0),
+            idle_disconnects_total: AtomicU64::new(0),
+            output_buffer_limit_disconnects_total: AtomicU64::new(0),
+            connected_clients: AtomicU64::new(0),
+            accepted_connections_total: AtomicU64::new(0),
+            read_buffer_bytes: AtomicU64::new(0),
+            read_buffer_pool_bytes: AtomicU64::new(0),
+            rejected_connections_total: AtomicU64::new(0),
+            tls_handshake_failures_total: AtomicU64::new(0),
+            throttled_commands_total: AtomicU64::new(0),
+            rate_limited_connections_total: AtomicU64::new(0),
+            commands: std::array::from_fn(|_| CommandStats {
+                failed_calls: AtomicU64::new(0),
+                latency: LatencyHistogram::new(bounds_us.clone()),
+            }),
+            errors: std::array::from_fn(|_| AtomicU64::new(0)),
             latency: LatencyHistogram::new(bounds_us),
             started_at: Instant::now(),
+            last_sample: Mutex::new(None),
+            instantaneous_ops_per_sec: AtomicU64::new(0),
+            latency_monitor: Arc::new(LatencyMonitor::new()),
+            #[cfg(feature = "otel")]
+            otel: std::sync::OnceLock::new(),
         }
     }
 
+    /// Slow events for `LATENCY`, shared with background tasks that report
+    /// to it after the call that started them returns.
+    pub fn latency_monitor(&self) -> &Arc<LatencyMonitor> {
+        &self.latency_monitor
+    }
+
     /// Records the start of a request.
     ///
     /// Call this when a request is accepted to increment totals and in-flight.
@@ -110,9 +324,101 @@ impl Metrics {
         self.latency.record(latency);
     }
 
-    /// Records an error response.
-    pub fn record_error(&self) {
+    /// Records an error response whose first word, after the `-`, is
+    /// `prefix` (`ERR`, `OOM`, ...).
+    pub fn record_error(&self, prefix: &[u8]) {
         self.errors_total.fetch_add(1, Ordering::Relaxed);
+        self.errors[error_index(prefix)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection closed by the idle timeout.
+    pub fn record_idle_disconnect(&self) {
+        self.idle_disconnects_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection closed for exceeding its output buffer limit.
+    pub fn record_output_buffer_limit_disconnect(&self) {
+        self.output_buffer_limit_disconnects_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a newly accepted connection.
+    pub fn record_client_connected(&self) {
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a closed connection.
+    pub fn record_client_disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of connections currently being served.
+    pub fn connected_clients(&self) -> u64 {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    /// Records a connection taken off a listener, before it is admitted
+    /// or refused.
+    pub fn record_accepted_connection(&self) {
+        self.accepted_connections_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection's read buffer changing from `from` to `to`
+    /// bytes; 0 for one created or dropped.
+    pub fn resize_read_buffers(&self, from: usize, to: usize) {
+        resize_gauge(&self.read_buffer_bytes, from, to);
+    }
+
+    /// Records the buffers pooled for new connections changing from `from`
+    /// to `to` bytes in total.
+    pub fn resize_read_buffer_pool(&self, from: usize, to: usize) {
+        resize_gauge(&self.read_buffer_pool_bytes, from, to);
+    }
+
+    /// Records a connection refused at the `maxclients` limit.
+    pub fn record_rejected_connection(&self) {
+        self.rejected_connections_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection dropped during its TLS handshake.
+    pub fn record_tls_handshake_failure(&self) {
+        self.tls_handshake_failures_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a command delayed by the connection's command rate limit.
+    pub fn record_throttled_command(&self) {
+        self.throttled_commands_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection refused by the per-IP connection rate limit.
+    pub fn record_rate_limited_connection(&self) {
+        self.rate_limited_connections_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a call of the command named `name` (any case) that executed
+    /// in `elapsed`, answering with an error when `failed`.
+    pub fn record_command(&self, name: &[u8], elapsed: Duration, failed: bool) {
+        let stats = &self.commands[command_index(name)];
+        if failed {
+            stats.failed_calls.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.latency.record(elapsed);
+        #[cfg(feature = "otel")]
+        if let Some(instruments) = self.otel.get() {
+            instruments.record(command_name(name), elapsed, failed);
+        }
+    }
+
+    /// Has `record_command` also feed `instruments`; only the first set
+    /// takes effect.
+    #[cfg(feature = "otel")]
+    pub(crate) fn set_command_instruments(&self, instruments: crate::otel::CommandInstruments) {
+        let _ = self.otel.set(instruments);
     }
 
     /// Returns a snapshot of all counters and histogram buckets.
@@ -121,10 +427,102 @@ impl Metrics {
             requests_total: self.requests_total.load(Ordering::Relaxed),
             errors_total: self.errors_total.load(Ordering::Relaxed),
             inflight: self.inflight.load(Ordering::Relaxed),
+            idle_disconnects_total: self.idle_disconnects_total.load(Ordering::Relaxed),
+            output_buffer_limit_disconnects_total: self
+                .output_buffer_limit_disconnects_total
+                .load(Ordering::Relaxed),
+            connected_clients: self.connected_clients.load(Ordering::Relaxed),
+            accepted_connections_total: self.accepted_connections_total.load(Ordering::Relaxed),
+            read_buffer_bytes: self.read_buffer_bytes.load(Ordering::Relaxed),
+            read_buffer_pool_bytes: self.read_buffer_pool_bytes.load(Ordering::Relaxed),
+            rejected_connections_total: self.rejected_connections_total.load(Ordering::Relaxed),
+            tls_handshake_failures_total: self.tls_handshake_failures_total.load(Ordering::Relaxed),
+            throttled_commands_total: self.throttled_commands_total.load(Ordering::Relaxed),
+            rate_limited_connections_total: self
+                .rate_limited_connections_total
+                .load(Ordering::Relaxed),
+            commands: TRACKED_COMMANDS
+                .iter()
+                .copied()
+                .chain([UNKNOWN_COMMAND])
+                .zip(&self.commands)
+                .map(|(name, stats)| {
+                    let latency = stats.latency.snapshot();
+                    CommandSnapshot {
+                        name,
+                        calls: latency.samples,
+                        failed_calls: stats.failed_calls.load(Ordering::Relaxed),
+                        usec: latency.sum_us,
+                        latency,
+                    }
+                })
+                .collect(),
+            errors: TRACKED_ERRORS
+                .iter()
+                .copied()
+                .chain([OTHER_ERROR])
+                .zip(&self.errors)
+                .map(|(prefix, count)| (prefix, count.load(Ordering::Relaxed)))
+                .collect(),
             uptime: self.started_at.elapsed(),
+            instantaneous_ops_per_sec: f64::from_bits(
+                self.instantaneous_ops_per_sec.load(Ordering::Relaxed),
+            ),
             latency: self.latency.snapshot(),
         }
     }
+
+    /// Returns what changed since `previous`, a snapshot of this instance.
+    pub fn delta_since(&self, previous: &MetricsSnapshot) -> MetricsDelta {
+        self.snapshot().delta_since(previous)
+    }
+
+    /// Updates `instantaneous_ops_per_sec` from the requests since the
+    /// previous call; the server calls this every `SAMPLE_INTERVAL`.
+    pub fn sample_rates(&self) {
+        let current = self.snapshot();
+        let mut last = self
+            .last_sample
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Some(previous) = last.as_ref() {
+            let ops = current.delta_since(previous).ops_per_sec();
+            self.instantaneous_ops_per_sec
+                .store(ops.to_bits(), Ordering::Relaxed);
+        }
+        *last = Some(current);
+    }
+
+    /// Zeroes the counters and histograms, as `CONFIG RESETSTAT` does.
+    /// Gauges (in-flight requests, connected clients) and uptime are kept.
+    ///
+    /// Fields are cleared one after another, not atomically: a request
+    /// recorded during the reset may survive in some counters but not
+    /// others (say in `requests_total` but not the histogram). The skew is
+    /// bounded by the requests recorded while `reset` runs.
+    pub fn reset(&self) {
+        for counter in [
+            &self.requests_total,
+            &self.errors_total,
+            &self.idle_disconnects_total,
+            &self.output_buffer_limit_disconnects_total,
+            &self.accepted_connections_total,
+            &self.rejected_connections_total,
+            &self.tls_handshake_failures_total,
+            &self.throttled_commands_total,
+            &self.rate_limited_connections_total,
+        ]
+        .into_iter()
+        .chain(&self.errors)
+        {
+            counter.store(0, Ordering::Relaxed);
+        }
+        for stats in &self.commands {
+            stats.failed_calls.store(0, Ordering::Relaxed);
+            stats.latency.reset();
+        }
+        self.latency.reset();
+    }
 }
 
 impl Default for Metrics {
@@ -154,6 +552,51 @@ impl MetricsSnapshot {
     }
 }
 
+impl MetricsSnapshot {
+    /// Returns what changed between `previous` and this snapshot.
+    pub fn delta_since(&self, previous: &MetricsSnapshot) -> MetricsDelta {
+        MetricsDelta {
+            interval: self.uptime.saturating_sub(previous.uptime),
+            requests: self.requests_total.saturating_sub(previous.requests_total),
+            errors: self.errors_total.saturating_sub(previous.errors_total),
+            latency: self.latency.delta_since(&previous.latency),
+        }
+    }
+}
+
+impl MetricsDelta {
+    /// Returns requests per second over the interval.
+    pub fn ops_per_sec(&self) -> f64 {
+        let secs = self.interval.as_secs_f64();
+        if secs <= f64::EPSILON {
+            0.0
+        } else {
+            self.requests as f64 / secs
+        }
+    }
+
+    /// Returns the fraction of the interval's requests answered with an
+    /// error.
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+impl CommandSnapshot {
+    /// Returns the mean execution time per call in microseconds.
+    pub fn usec_per_call(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.usec as f64 / self.calls as f64
+        }
+    }
+}
+
 /// Fixed-bucket latency histogram.
 ///
 /// Uses a linear scan to pick buckets; this is O(buckets) but the list is small
@@ -203,6 +646,16 @@ impl LatencyHistogram {
         self.buckets[bucket_idx].fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Zeroes every bucket and total.
+    pub fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.sum_us.store(0, Ordering::Relaxed);
+        self.samples.store(0, Ordering::Relaxed);
+        self.max_us.store(0, Ordering::Relaxed);
+    }
+
     /// Returns a point-in-time snapshot of the histogram.
     pub fn snapshot(&self) -> LatencySnapshot {
         let buckets: Vec<u64> = self
@@ -222,8 +675,26 @@ impl LatencyHistogram {
 }
 
 impl LatencySnapshot {
+    /// Returns the samples recorded since `previous`, bucket by bucket;
+    /// buckets that went backwards count as zero. `max_us` cannot be
+    /// differenced and stays this snapshot's maximum.
+    pub fn delta_since(&self, previous: &LatencySnapshot) -> LatencySnapshot {
+        LatencySnapshot {
+            bounds_us: self.bounds_us.clone(),
+            buckets: self
+                .buckets
+                .iter()
+                .zip(previous.buckets.iter().chain(std::iter::repeat(&0)))
+                .map(|(current, previous)| current.saturating_sub(*previous))
+                .collect(),
+            samples: self.samples.saturating_sub(previous.samples),
+            sum_us: self.sum_us.saturating_sub(previous.sum_us),
+            max_us: self.max_us,
+        }
+    }
+
     /// Returns the arithmetic mean latency in microseconds.
-    pub fn average_us(&self) -> Option<f64> {
+    pub fn mean_us(&self) -> Option<f64> {
         if self.samples == 0 {
             None
         } else {
@@ -231,30 +702,64 @@ impl LatencySnapshot {
         }
     }
 
-    /// Returns the histogram upper bound that satisfies the requested percentile.
-    pub fn percentile_us(&self, percentile: f64) -> Option<u64> {
-        if self.samples == 0 || !(0.0..=100.0).contains(&percentile) || percentile == 0.0 {
+    /// Returns the largest finite bucket bound; latencies above it are only
+    /// known to exceed it.
+    pub fn max_bound_us(&self) -> Option<u64> {
+        self.bounds_us.last().copied()
+    }
+
+    /// Returns the latency at quantile `q` (0.0 to 1.0) in microseconds.
+    ///
+    /// Samples are assumed spread evenly across their bucket, from the
+    /// previous bound (or 0) to its own, and the quantile is interpolated
+    /// between them. A quantile that lands in the overflow bucket returns
+    /// `max_bound_us` as a floor. Returns `None` without samples or for `q`
+    /// outside 0.0 to 1.0.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        let total: u64 = self.buckets.iter().sum();
+        if total == 0 || !(0.0..=1.0).contains(&q) {
             return None;
         }
 
-        let rank = ((self.samples as f64) * (percentile / 100.0)).ceil() as u64;
-        let target = rank.max(1);
-        let mut cumulative = 0u64;
-
-        for (idx, count) in self.buckets.iter().copied().enumerate() {
-            cumulative = cumulative.saturating_add(count);
-            if cumulative < target {
+        let target = q * total as f64;
+        let mut below = 0u64;
+        for (idx, &count) in self.buckets.iter().enumerate() {
+            if count == 0 {
                 continue;
             }
-
-            return if idx < self.bounds_us.len() {
-                Some(self.bounds_us[idx])
-            } else {
-                Some(self.max_us)
+            let Some(&upper) = self.bounds_us.get(idx) else {
+                break;
             };
+            if (below + count) as f64 >= target {
+                let lower = idx.checked_sub(1).map_or(0, |prev| self.bounds_us[prev]);
+                let fraction = ((target - below as f64) / count as f64).max(0.0);
+                let value = lower as f64 + fraction * (upper - lower) as f64;
+                return Some(value.round() as u64);
+            }
+            below += count;
         }
 
-        Some(self.max_us)
+        Some(self.max_bound_us().unwrap_or(0))
+    }
+
+    /// Returns the percentiles and mean INFO reports; all zero without
+    /// samples.
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            p50_us: self.percentile(0.50).unwrap_or(0),
+            p90_us: self.percentile(0.90).unwrap_or(0),
+            p99_us: self.percentile(0.99).unwrap_or(0),
+            p999_us: self.percentile(0.999).unwrap_or(0),
+            mean_us: self.mean_us().unwrap_or(0.0),
+        }
+    }
+}
+
+fn resize_gauge(gauge: &AtomicU64, from: usize, to: usize) {
+    if to > from {
+        gauge.fetch_add((to - from) as u64, Ordering::Relaxed);
+    } else {
+        gauge.fetch_sub((from - to) as u64, Ordering::Relaxed);
     }
 }
 
@@ -282,26 +787,246 @@ mod tests {
         metrics.record_request_end(Duration::from_micros(12));
         metrics.record_request_start();
         metrics.record_request_end(Duration::from_micros(80));
-        metrics.record_error();
+        metrics.record_error(b"ERR");
 
         let snapshot = metrics.snapshot();
         assert_eq!(snapshot.requests_total, 3);
         assert_eq!(snapshot.errors_total, 1);
         assert_eq!(snapshot.inflight, 0);
         assert_eq!(snapshot.latency.samples, 3);
-        assert_eq!(snapshot.latency.percentile_us(50.0), Some(20));
-        assert_eq!(snapshot.latency.percentile_us(90.0), Some(100));
+        // Buckets (0,10]:1 (10,20]:1 (50,100]:1; p50 is halfway through the
+        // second sample's bucket, p90 70% through the third's.
+        assert_eq!(snapshot.latency.percentile(0.50), Some(15));
+        assert_eq!(snapshot.latency.percentile(0.90), Some(85));
         assert_eq!(snapshot.latency.max_us, 80);
-        assert_eq!(snapshot.latency.average_us(), Some(33.666_666_666_666_664));
+        assert_eq!(snapshot.latency.mean_us(), Some(33.666_666_666_666_664));
         assert!((snapshot.error_rate() - (1.0 / 3.0)).abs() < 1e-12);
         assert!(snapshot.qps() >= 0.0);
     }
 
+    fn record_requests(metrics: &Metrics, latencies_us: &[u64]) {
+        for &latency in latencies_us {
+            metrics.record_request_start();
+            metrics.record_request_end(Duration::from_micros(latency));
+        }
+    }
+
+    #[test]
+    fn delta_counts_requests_errors_and_buckets_since_previous() {
+        let metrics = Metrics::with_latency_buckets(vec![10, 100]);
+        record_requests(&metrics, &[5, 50]);
+        metrics.record_error(b"ERR");
+        let previous = metrics.snapshot();
+
+        record_requests(&metrics, &[5, 50, 500, 500]);
+        metrics.record_error(b"ERR");
+        metrics.record_error(b"ERR");
+        let delta = metrics.delta_since(&previous);
+
+        assert_eq!(delta.requests, 4);
+        assert_eq!(delta.errors, 2);
+        assert_eq!(delta.error_rate(), 0.5);
+        assert_eq!(delta.latency.buckets, vec![1, 1, 2]);
+        assert_eq!(delta.latency.samples, 4);
+        assert_eq!(delta.latency.sum_us, 1055);
+        assert!(delta.interval <= metrics.snapshot().uptime);
+    }
+
+    #[test]
+    fn delta_across_a_reset_saturates_at_zero() {
+        let metrics = Metrics::with_latency_buckets(vec![10, 100]);
+        record_requests(&metrics, &[5, 5, 50, 500]);
+        metrics.record_error(b"ERR");
+        let previous = metrics.snapshot();
+
+        metrics.reset();
+        record_requests(&metrics, &[50, 50]);
+        let delta = metrics.delta_since(&previous);
+
+        // requests 4 -> 2 and errors 1 -> 0 went backwards; so did every
+        // bucket except (10,100], which went 1 -> 2.
+        assert_eq!(delta.requests, 0);
+        assert_eq!(delta.errors, 0);
+        assert_eq!(delta.ops_per_sec(), 0.0);
+        assert_eq!(delta.latency.buckets, vec![0, 1, 0]);
+        assert_eq!(delta.latency.samples, 0);
+        assert_eq!(delta.latency.sum_us, 0);
+    }
+
+    #[test]
+    fn delta_rates_use_the_interval() {
+        let previous = Metrics::new().snapshot();
+        let mut current = previous.clone();
+        current.uptime += Duration::from_secs(2);
+        current.requests_total += 300;
+        current.errors_total += 3;
+
+        let delta = current.delta_since(&previous);
+        assert_eq!(delta.interval, Duration::from_secs(2));
+        assert_eq!(delta.ops_per_sec(), 150.0);
+        assert_eq!(delta.error_rate(), 0.01);
+
+        let same = previous.delta_since(&previous);
+        assert_eq!(same.ops_per_sec(), 0.0);
+    }
+
+    #[test]
+    fn reset_zeroes_counters_and_keeps_gauges() {
+        let metrics = Metrics::new();
+        record_requests(&metrics, &[5]);
+        metrics.record_request_start();
+        metrics.record_error(b"ERR");
+        metrics.record_client_connected();
+        metrics.record_rejected_connection();
+        metrics.record_command(b"GET", Duration::from_micros(3), true);
+
+        metrics.reset();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.requests_total, 0);
+        assert_eq!(snapshot.errors_total, 0);
+        assert_eq!(snapshot.rejected_connections_total, 0);
+        assert_eq!(snapshot.latency.samples, 0);
+        assert_eq!(snapshot.latency.max_us, 0);
+        assert!(snapshot.latency.buckets.iter().all(|&count| count == 0));
+        assert!(
+            snapshot
+                .commands
+                .iter()
+                .all(|command| command.calls == 0 && command.failed_calls == 0)
+        );
+        assert!(snapshot.errors.iter().all(|&(_, count)| count == 0));
+        assert_eq!(snapshot.inflight, 1);
+        assert_eq!(snapshot.connected_clients, 1);
+    }
+
+    #[test]
+    fn sample_rates_reports_requests_per_second_between_samples() {
+        let metrics = Metrics::new();
+        metrics.sample_rates();
+        assert_eq!(metrics.snapshot().instantaneous_ops_per_sec, 0.0);
+
+        record_requests(&metrics, &[1; 10]);
+        std::thread::sleep(Duration::from_millis(20));
+        metrics.sample_rates();
+
+        // 10 requests over at least 20ms.
+        let ops = metrics.snapshot().instantaneous_ops_per_sec;
+        assert!(ops > 0.0 && ops <= 500.0, "{ops}");
+    }
+
+    #[test]
+    fn commands_are_counted_by_name() {
+        let metrics = Metrics::with_latency_buckets(vec![10, 100]);
+        metrics.record_command(b"GET", Duration::from_micros(4), false);
+        metrics.record_command(b"get", Duration::from_micros(50), false);
+        metrics.record_command(b"CONFIG", Duration::from_micros(3), true);
+        metrics.record_command(b"NOSUCHCMD", Duration::ZERO, true);
+
+        let commands = metrics.snapshot().commands;
+        assert_eq!(commands.len(), TRACKED_COMMANDS.len() + 1);
+        let stats = |name| commands.iter().find(|cmd| cmd.name == name).unwrap();
+        let get = stats("get");
+        assert_eq!((get.calls, get.failed_calls, get.usec), (2, 0, 54));
+        assert_eq!(get.latency.bounds_us, vec![10, 100]);
+        assert_eq!(get.latency.buckets, vec![1, 1, 0]);
+        assert_eq!(
+            (stats("config").calls, stats("config").failed_calls),
+            (1, 1)
+        );
+        assert_eq!(stats("set").calls, 0);
+        assert_eq!(stats(UNKNOWN_COMMAND).calls, 1);
+    }
+
+    #[test]
+    fn errors_are_counted_by_prefix() {
+        let metrics = Metrics::new();
+        metrics.record_error(b"OOM");
+        metrics.record_error(b"OOM");
+        metrics.record_error(b"ERR");
+        metrics.record_error(b"oom");
+        metrics.record_error(b"MYSCRIPTERR");
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.errors_total, 5);
+        assert_eq!(snapshot.errors.len(), TRACKED_ERRORS.len() + 1);
+        let count = |prefix| snapshot.errors.iter().find(|e| e.0 == prefix).unwrap().1;
+        assert_eq!(count("OOM"), 2);
+        assert_eq!(count("ERR"), 1);
+        assert_eq!(count("BUSY"), 0);
+        assert_eq!(count(OTHER_ERROR), 2);
+    }
+
     #[test]
     fn percentile_returns_none_without_samples() {
         let histogram = LatencyHistogram::new(vec![10, 20, 50]);
         let snapshot = histogram.snapshot();
-        assert_eq!(snapshot.average_us(), None);
-        assert_eq!(snapshot.percentile_us(50.0), None);
+        assert_eq!(snapshot.mean_us(), None);
+        assert_eq!(snapshot.percentile(0.5), None);
+        assert_eq!(snapshot.percentile(0.0), None);
+        assert_eq!(snapshot.summary(), LatencySummary::default());
+    }
+
+    /// A snapshot with `counts` in the buckets bounded by 100, 200, 400 and
+    /// 800, then overflow.
+    fn distribution(counts: [u64; 5]) -> LatencySnapshot {
+        LatencySnapshot {
+            bounds_us: vec![100, 200, 400, 800],
+            buckets: counts.to_vec(),
+            samples: counts.iter().sum(),
+            sum_us: 0,
+            max_us: 0,
+        }
+    }
+
+    #[test]
+    fn percentile_interpolates_within_buckets() {
+        // 10 samples in (0,100], 30 in (100,200], 60 in (200,400].
+        let snapshot = distribution([10, 30, 60, 0, 0]);
+        // Rank 5 of the first 10: halfway to 100.
+        assert_eq!(snapshot.percentile(0.05), Some(50));
+        // Rank 25 is 15 of 30 into (100,200].
+        assert_eq!(snapshot.percentile(0.25), Some(150));
+        // Rank 50 is 10 of 60 into (200,400]: 200 + 200/6.
+        assert_eq!(snapshot.percentile(0.50), Some(233));
+        // Rank 99 is 59 of 60 into (200,400]: 200 + 200*59/60.
+        assert_eq!(snapshot.percentile(0.99), Some(397));
+    }
+
+    #[test]
+    fn percentile_edges_are_the_occupied_range() {
+        let snapshot = distribution([0, 4, 0, 4, 0]);
+        // q=0.0 is the lower bound of the first occupied bucket and q=1.0
+        // the upper bound of the last one; empty buckets are skipped.
+        assert_eq!(snapshot.percentile(0.0), Some(100));
+        assert_eq!(snapshot.percentile(0.5), Some(200));
+        assert_eq!(snapshot.percentile(1.0), Some(800));
+        assert_eq!(snapshot.percentile(-0.1), None);
+        assert_eq!(snapshot.percentile(1.1), None);
+        assert_eq!(snapshot.percentile(f64::NAN), None);
+    }
+
+    #[test]
+    fn overflow_percentiles_floor_at_the_last_bound() {
+        let snapshot = distribution([1, 0, 0, 0, 9]);
+        assert_eq!(snapshot.max_bound_us(), Some(800));
+        assert_eq!(snapshot.percentile(0.05), Some(50));
+        assert_eq!(snapshot.percentile(0.5), Some(800));
+
+        let all_overflow = distribution([0, 0, 0, 0, 5]);
+        assert_eq!(all_overflow.percentile(0.0), Some(800));
+        assert_eq!(all_overflow.percentile(1.0), Some(800));
+        assert_eq!(all_overflow.summary().p50_us, 800);
+
+        let no_bounds = LatencySnapshot {
+            bounds_us: Vec::new(),
+            buckets: vec![3],
+            samples: 3,
+            sum_us: 30,
+            max_us: 20,
+        };
+        assert_eq!(no_bounds.max_bound_us(), None);
+        assert_eq!(no_bounds.percentile(0.5), Some(0));
+        assert_eq!(no_bounds.summary().mean_us, 10.0);
     }
 }
@@ -0,0 +1,319 @@
+//! # OpenTelemetry Export
+//!
+//! Publish server metrics and command spans to an OTLP collector over
+//! HTTP, for deployments that already run one instead of scraping the
+//! Prometheus exporter.
+//!
+//! ## Design Principles
+//!
+//! 1. **Same Instrumentation Points**: Counters and gauges are observed from
+//!    `Metrics::snapshot` at each export, and command durations are recorded
+//!    from `Metrics::record_command`; spans are the existing `connection`
+//!    and `command` spans, bridged by `tracing-opentelemetry`. Nothing in the
+//!    request path knows about OpenTelemetry.
+//! 2. **Bounded Attributes**: `command` takes the metrics module's fixed
+//!    command names and `prefix` its fixed error prefixes, so clients cannot
+//!    add series.
+//! 3. **Flushed on Shutdown**: `Telemetry::shutdown` exports what is still
+//!    buffered before the providers stop, so the final interval is not lost.
+//! 4. **Injectable Providers**: `Telemetry::new` takes providers built by
+//!    the caller, so tests can export to memory.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Histogram, Meter, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{
+    ExporterBuildError, MetricExporter, SpanExporter, WithExportConfig, WithHttpConfig,
+};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::metrics::{DEFAULT_LATENCY_BUCKETS_US, Metrics, MetricsSnapshot};
+
+/// Seconds between metric exports when `--otel-interval` is not given.
+pub const DEFAULT_OTEL_INTERVAL_SECS: u64 = 60;
+
+/// Instrumentation scope and `service.name` of everything exported.
+const SERVICE_NAME: &str = "hkv-server";
+
+/// Where and how often to export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtelConfig {
+    /// Base OTLP/HTTP URL of the collector, such as
+    /// `http://localhost:4318`; `/v1/metrics` and `/v1/traces` are appended.
+    pub endpoint: String,
+    /// Headers sent with every export, such as an API key.
+    pub headers: Vec<(String, String)>,
+    /// Time between metric exports.
+    pub interval: Duration,
+    /// Whether command spans are exported as well as metrics.
+    pub traces: bool,
+}
+
+/// Parses `--otel-headers`: `name=value` pairs separated by commas, as in
+/// `OTEL_EXPORTER_OTLP_HEADERS`.
+pub fn parse_headers(input: &str) -> Result<Vec<(String, String)>, String> {
+    input
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("invalid header '{pair}': expected name=value")),
+        })
+        .collect()
+}
+
+/// The meter and tracer providers, and the instruments fed from `Metrics`.
+pub struct Telemetry {
+    meters: SdkMeterProvider,
+    tracer: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Exports to the OTLP/HTTP collector `config` names.
+    pub fn otlp(config: &OtelConfig) -> Result<Self, ExporterBuildError> {
+        let headers: HashMap<String, String> = config.headers.iter().cloned().collect();
+        let endpoint = config.endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+
+        let exporter = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{endpoint}/v1/metrics"))
+            .with_headers(headers.clone())
+            .build()?;
+        let reader = PeriodicReader::builder(exporter)
+            .with_interval(config.interval)
+            .build();
+        let meters = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(resource.clone())
+            .build();
+
+        let tracer = if config.traces {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{endpoint}/v1/traces"))
+                .with_headers(headers)
+                .build()?;
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(resource)
+                    .build(),
+            )
+        } else {
+            None
+        };
+        Ok(Telemetry::new(meters, tracer))
+    }
+
+    /// Exports through `meters`, and spans through `tracer` if given.
+    pub fn new(meters: SdkMeterProvider, tracer: Option<SdkTracerProvider>) -> Self {
+        Telemetry { meters, tracer }
+    }
+
+    /// Publishes the counters and gauges of `metrics`, and the durations
+    /// it records for commands from now on.
+    pub fn instrument(&self, metrics: &Arc<Metrics>) {
+        let meter = self.meters.meter(SERVICE_NAME);
+        observe_counters(&meter, metrics);
+        observe_gauges(&meter, metrics);
+        metrics.set_command_instruments(CommandInstruments::new(&meter));
+    }
+
+    /// A layer exporting the server's `connection` and `command` spans, if
+    /// span export is enabled; add it to the subscriber once.
+    pub fn tracing_layer<S>(&self) -> Option<impl Layer<S> + use<S>>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let tracer = self.tracer.as_ref()?.tracer(SERVICE_NAME);
+        // Command spans are at debug level, below the usual log filter.
+        let spans = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG);
+        Some(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(spans),
+        )
+    }
+
+    /// Exports what is still buffered, then stops both providers.
+    pub fn shutdown(&self) -> OTelSdkResult {
+        let traces = self
+            .tracer
+            .as_ref()
+            .map_or(Ok(()), SdkTracerProvider::shutdown);
+        self.meters.shutdown().and(traces)
+    }
+}
+
+/// The histogram `Metrics::record_command` feeds once attached.
+pub(crate) struct CommandInstruments {
+    duration: Histogram<f64>,
+}
+
+impl CommandInstruments {
+    fn new(meter: &Meter) -> Self {
+        // The same bounds as INFO and the Prometheus exporter, in seconds.
+        let bounds = DEFAULT_LATENCY_BUCKETS_US
+            .iter()
+            .map(|&us| us as f64 / 1e6)
+            .collect();
+        CommandInstruments {
+            duration: meter
+                .f64_histogram("hkv.command.duration")
+                .with_description("Command execution time, by lowercase name and outcome.")
+                .with_unit("s")
+                .with_boundaries(bounds)
+                .build(),
+        }
+    }
+
+    /// Records a call of `command`, a `TRACKED_COMMANDS` name or
+    /// `UNKNOWN_COMMAND`.
+    pub(crate) fn record(&self, command: &'static str, elapsed: Duration, failed: bool) {
+        self.duration.record(
+            elapsed.as_secs_f64(),
+            &[
+                KeyValue::new("command", command),
+                KeyValue::new("outcome", if failed { "error" } else { "ok" }),
+            ],
+        );
+    }
+}
+
+type Observed = fn(&MetricsSnapshot) -> u64;
+
+fn observe_counters(meter: &Meter, metrics: &Arc<Metrics>) {
+    let counters: [(&'static str, &'static str, Observed); 9] = [
+        ("hkv.requests", "Requests received.", |snapshot| {
+            snapshot.requests_total
+        }),
+        (
+            "hkv.connections.accepted",
+            "Connections accepted, including ones then refused.",
+            |snapshot| snapshot.accepted_connections_total,
+        ),
+        (
+            "hkv.connections.rejected",
+            "Connections refused at the maxclients limit.",
+            |snapshot| snapshot.rejected_connections_total,
+        ),
+        (
+            "hkv.connections.idle_disconnects",
+            "Connections closed by the idle timeout.",
+            |snapshot| snapshot.idle_disconnects_total,
+        ),
+        (
+            "hkv.connections.output_buffer_limit_disconnects",
+            "Connections closed by client-output-buffer-limit.",
+            |snapshot| snapshot.output_buffer_limit_disconnects_total,
+        ),
+        (
+            "hkv.connections.tls_handshake_failures",
+            "Connections dropped during the TLS handshake.",
+            |snapshot| snapshot.tls_handshake_failures_total,
+        ),
+        (
+            "hkv.connections.rate_limited",
+            "Connections refused by maxconn-per-ip-per-sec.",
+            |snapshot| snapshot.rate_limited_connections_total,
+        ),
+        (
+            "hkv.commands.throttled",
+            "Commands delayed by maxcmd-per-sec.",
+            |snapshot| snapshot.throttled_commands_total,
+        ),
+        ("hkv.errors", "Error replies.", |snapshot| {
+            snapshot.errors_total
+        }),
+    ];
+    for (name, description, observed) in counters {
+        let metrics = Arc::clone(metrics);
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |counter| counter.observe(observed(&metrics.snapshot()), &[]))
+            .build();
+    }
+
+    let metrics = Arc::clone(metrics);
+    meter
+        .u64_observable_counter("hkv.error_replies")
+        .with_description("Error replies, by prefix such as ERR or OOM.")
+        .with_callback(move |counter| {
+            for (prefix, count) in metrics.snapshot().errors {
+                counter.observe(count, &[KeyValue::new("prefix", prefix)]);
+            }
+        })
+        .build();
+}
+
+fn observe_gauges(meter: &Meter, metrics: &Arc<Metrics>) {
+    let gauges: [(&'static str, &'static str, &'static str, Observed); 4] = [
+        (
+            "hkv.connected_clients",
+            "Connections currently served.",
+            "{connection}",
+            |snapshot| snapshot.connected_clients,
+        ),
+        (
+            "hkv.inflight_requests",
+            "Requests received but not yet answered.",
+            "{request}",
+            |snapshot| snapshot.inflight,
+        ),
+        (
+            "hkv.read_buffer.size",
+            "Bytes of read buffer held by connections.",
+            "By",
+            |snapshot| snapshot.read_buffer_bytes,
+        ),
+        (
+            "hkv.read_buffer.pool_size",
+            "Bytes of read buffer pooled for new connections.",
+            "By",
+            |snapshot| snapshot.read_buffer_pool_bytes,
+        ),
+    ];
+    for (name, description, unit, observed) in gauges {
+        let metrics = Arc::clone(metrics);
+        meter
+            .u64_observable_gauge(name)
+            .with_description(description)
+            .with_unit(unit)
+            .with_callback(move |gauge| gauge.observe(observed(&metrics.snapshot()), &[]))
+            .build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_parse_as_comma_separated_pairs() {
+        assert_eq!(
+            parse_headers("x-api-key=secret, tenant = a=b").unwrap(),
+            [
+                ("x-api-key".to_string(), "secret".to_string()),
+                ("tenant".to_string(), "a=b".to_string()),
+            ]
+        );
+        assert_eq!(parse_headers("").unwrap(), []);
+        assert!(parse_headers("novalue").is_err());
+        assert!(parse_headers("=value").is_err());
+    }
+}
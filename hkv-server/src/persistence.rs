@@ -0,0 +1,148 @@
+//! # Snapshot Persistence
+//!
+//! Write point-in-time engine snapshots to `dump.hkv` for `SAVE`/`BGSAVE`
+//! and track the last successful save for `LASTSAVE`.
+//!
+//! ## Design Principles
+//!
+//! 1. **Off the Connection**: `BGSAVE` captures, serializes and writes the
+//!    snapshot on Tokio's blocking pool, and `SAVE` runs on it as a blocking
+//!    command, so neither walks the keyspace on a worker thread.
+//! 2. **Atomic Replace**: Files are written to a temporary path and renamed so
+//!    readers never observe a partially written dump.
+//! 3. **Single Background Writer**: At most one `BGSAVE` runs at a time.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hkv_engine::{KVEngine, SnapshotEntry, snapshot};
+
+/// File name used for snapshots inside the persistence directory.
+pub const SNAPSHOT_FILE_NAME: &str = "dump.hkv";
+
+/// Outcome of a `BGSAVE` request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgsaveStatus {
+    /// A background writer was spawned.
+    Started,
+    /// Another background save has not finished yet.
+    AlreadyInProgress,
+}
+
+/// Shared snapshot state for a server instance.
+#[derive(Debug)]
+pub struct Persistence {
+    /// Directory holding `dump.hkv`.
+    dir: PathBuf,
+    /// Unix timestamp (seconds) of the last successful save.
+    last_save: AtomicU64,
+    /// Set while a background save is running.
+    bgsave_in_progress: AtomicBool,
+}
+
+impl Persistence {
+    /// Creates persistence state rooted at `dir`.
+    ///
+    /// `LASTSAVE` reports the creation time until the first save completes.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Persistence {
+            dir: dir.into(),
+            last_save: AtomicU64::new(unix_now_secs()),
+            bgsave_in_progress: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the full path of the snapshot file.
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join(SNAPSHOT_FILE_NAME)
+    }
+
+    /// Returns the Unix timestamp of the last successful save.
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Acquire)
+    }
+
+    /// Returns true while a background save is running.
+    pub fn bgsave_in_progress(&self) -> bool {
+        self.bgsave_in_progress.load(Ordering::Acquire)
+    }
+
+    /// Captures and writes a snapshot synchronously.
+    pub fn save(&self, engine: &(impl KVEngine + ?Sized)) -> io::Result<()> {
+        let entries = engine
+            .snapshot()
+            .map_err(|err| io::Error::other(format!("snapshot failed: {err}")))?;
+        self.write_entries(&entries)
+    }
+
+    /// Captures and writes a snapshot on Tokio's blocking pool, then calls
+    /// `on_written` with how long that took, whether or not it succeeded.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn start_bgsave<E>(
+        self: &Arc<Self>,
+        engine: Arc<E>,
+        on_written: impl FnOnce(Duration) + Send + 'static,
+    ) -> BgsaveStatus
+    where
+        E: KVEngine + ?Sized + 'static,
+    {
+        if self
+            .bgsave_in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return BgsaveStatus::AlreadyInProgress;
+        }
+
+        let persistence = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            // Failures leave `last_save` untouched, which is what LASTSAVE
+            // pollers use to detect completion.
+            if let Err(err) = persistence.save(engine.as_ref()) {
+                tracing::warn!(error = %err, "background save failed");
+            }
+            on_written(started.elapsed());
+            persistence
+                .bgsave_in_progress
+                .store(false, Ordering::Release);
+        });
+
+        BgsaveStatus::Started
+    }
+
+    fn write_entries(&self, entries: &[SnapshotEntry]) -> io::Result<()> {
+        let bytes = snapshot::encode(entries, SystemTime::now());
+        write_atomically(&self.snapshot_path(), &bytes)?;
+        self.last_save.store(unix_now_secs(), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Default for Persistence {
+    /// Stores snapshots in the current working directory.
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("hkv.tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)
+}
+
+fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -8,25 +8,103 @@
 //!    keep control flow predictable.
 //! 2. **Streaming Friendly**: The parser consumes from a mutable buffer and
 //!    returns `None` when more data is needed.
-//! 3. **Low Allocation**: Only bulk string arguments are copied into `Vec<u8>`.
+//! 3. **Zero-Copy Arguments**: Bulk arguments are `Bytes` split off the
+//!    read buffer, so a large value is never copied by the parser. An
+//!    argument under `1 / COPY_OUT_RATIO` of its buffer is copied out
+//!    instead, so keeping a small argument cannot pin a large buffer.
 //! 4. **Fail Fast**: Malformed frames return a protocol error immediately.
+//! 5. **Bounded Requests**: `RespLimits` caps the array count, each bulk
+//!    length, the total bulk bytes of one command, and an unterminated line.
+//!    Declared lengths are checked before any data is buffered, so a client
+//!    cannot make the server wait for (and hold) a gigabyte it never sends.
+//!    Length prefixes are plain decimal of at most `MAX_LENGTH_DIGITS`
+//!    digits, and array counts never exceed `MAX_ARRAY_LEN_CEILING`,
+//!    whatever the configured limits say.
+//! 6. **Inline Commands**: A frame that does not start with `*` is one
+//!    line of whitespace-separated arguments, as telnet or netcat users
+//!    type them. Arguments may be quoted as in `redis-cli`, and empty lines
+//!    are skipped, as in Redis.
+//! 7. **Null Lengths**: `$-1` is the one negative length accepted and reads
+//!    as an empty argument, which commands then validate like any other
+//!    value. `*-1` reads as an empty command, which the server rejects with
+//!    an error reply while keeping the connection. Any other negative
+//!    length is a protocol error.
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
+
+/// Default largest bulk string (Redis `proto-max-bulk-len`, 512 MiB).
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Default largest array count (1M arguments).
+pub const DEFAULT_MAX_ARRAY_LEN: usize = 1024 * 1024;
+
+/// Default largest total of bulk bytes in one command (Redis
+/// `client-query-buffer-limit`, 1 GiB).
+pub const DEFAULT_MAX_REQUEST_LEN: usize = 1024 * 1024 * 1024;
+
+/// Most digits accepted in a length prefix; longer ones are protocol
+/// errors rather than values to saturate.
+pub const MAX_LENGTH_DIGITS: usize = 10;
+
+/// Largest array count accepted under any `RespLimits`, as Redis's
+/// `INT_MAX` bound on multibulk lengths.
+pub const MAX_ARRAY_LEN_CEILING: usize = i32::MAX as usize;
+
+/// An argument shorter than `1 / COPY_OUT_RATIO` of the buffer it was read
+/// into is copied out rather than shared.
+pub const COPY_OUT_RATIO: usize = 4;
+
+/// Longest line (inline command or length header) buffered while waiting
+/// for its `\r\n`, as Redis's `PROTO_INLINE_MAX_SIZE`.
+pub const MAX_LINE_LEN: usize = 64 * 1024;
 
 /// RESP parser errors surfaced to the server for client responses.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RespError {
     /// The input is not valid RESP2 for the supported subset.
     Protocol,
+    /// The request exceeds a `RespLimits` bound.
+    TooLarge,
+}
+
+/// Size bounds enforced while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespLimits {
+    /// Maximum number of array elements in one command.
+    pub max_array_len: usize,
+    /// Maximum length of one bulk string.
+    pub max_bulk_len: usize,
+    /// Maximum sum of bulk string lengths in one command.
+    pub max_request_len: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        RespLimits {
+            max_array_len: DEFAULT_MAX_ARRAY_LEN,
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            max_request_len: DEFAULT_MAX_REQUEST_LEN,
+        }
+    }
 }
 
 /// RESP2 parser for arrays of bulk strings.
 #[derive(Debug)]
 pub struct RespParser {
     state: ParseState,
-    args: Vec<Vec<u8>>,
+    args: Vec<Bytes>,
     remaining: usize,
     bulk_len: usize,
+    request_len: usize,
+    limits: RespLimits,
+    /// Set by the first error; the stream cannot be resynchronized.
+    failed: Option<RespError>,
+    /// Bytes at the front of the buffer already searched for the `\n`
+    /// ending the current line, so trickled input is scanned once.
+    scanned: usize,
+    /// Bytes examined by line scans, to check they stay linear.
+    #[cfg(test)]
+    scan_work: usize,
 }
 
 /// Parser states for the RESP2 array of bulk strings.
@@ -41,33 +119,75 @@ enum ParseState {
 }
 
 impl RespParser {
-    /// Creates a new parser in the initial state.
+    /// Creates a new parser in the initial state with default limits.
     pub fn new() -> Self {
+        Self::with_limits(RespLimits::default())
+    }
+
+    /// Creates a new parser enforcing `limits`.
+    pub fn with_limits(limits: RespLimits) -> Self {
         RespParser {
             state: ParseState::ArrayLen,
             args: Vec::new(),
             remaining: 0,
             bulk_len: 0,
+            request_len: 0,
+            limits,
+            failed: None,
+            scanned: 0,
+            #[cfg(test)]
+            scan_work: 0,
         }
     }
 
+    /// Replaces the limits; they apply from the next length checked.
+    pub fn set_limits(&mut self, limits: RespLimits) {
+        self.limits = limits;
+    }
+
     /// Attempts to parse a single command from the buffer.
     ///
-    /// Returns `Ok(None)` if more data is required.
-    pub fn parse(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Vec<u8>>>, RespError> {
+    /// Returns `Ok(None)` if more data is required. After any error the
+    /// stream cannot be resynchronized: every later call returns the same
+    /// error, and the connection should close.
+    pub fn parse(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>, RespError> {
+        if let Some(err) = self.failed {
+            return Err(err);
+        }
+        let result = self.parse_frame(buf);
+        if let Err(err) = result {
+            self.failed = Some(err);
+        }
+        result
+    }
+
+    fn parse_frame(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>, RespError> {
         loop {
             match self.state {
                 ParseState::ArrayLen => {
-                    let line = match read_line(buf) {
+                    match buf.first() {
+                        None => return Ok(None),
+                        Some(b'*') => {}
+                        Some(_) => match self.read_line(buf, LineEnd::Lf)? {
+                            Some(line) => match split_inline_args(&line)? {
+                                args if args.is_empty() => continue,
+                                args => {
+                                    return Ok(Some(args.into_iter().map(Bytes::from).collect()));
+                                }
+                            },
+                            None => return Ok(None),
+                        },
+                    }
+                    let line = match self.read_line(buf, LineEnd::Crlf)? {
                         Some(line) => line,
                         None => return Ok(None),
                     };
-                    if line.first() != Some(&b'*') {
-                        let args = parse_inline_command(&line)?;
-                        return Ok(Some(args));
+                    let count = parse_length(&line[1..])?.unwrap_or(0);
+                    if count > self.limits.max_array_len || count > MAX_ARRAY_LEN_CEILING {
+                        return Err(RespError::TooLarge);
                     }
-                    let count = parse_usize(&line[1..])?;
                     self.args.clear();
+                    self.request_len = 0;
                     self.remaining = count;
                     if self.remaining == 0 {
                         self.state = ParseState::ArrayLen;
@@ -76,22 +196,36 @@ impl RespParser {
                     self.state = ParseState::BulkLen;
                 }
                 ParseState::BulkLen => {
-                    let line = match read_line(buf) {
+                    let line = match self.read_line(buf, LineEnd::Crlf)? {
                         Some(line) => line,
                         None => return Ok(None),
                     };
                     if line.first() != Some(&b'$') {
                         return Err(RespError::Protocol);
                     }
-                    let len = parse_usize(&line[1..])?;
+                    let Some(len) = parse_length(&line[1..])? else {
+                        self.args.push(Bytes::new());
+                        self.remaining -= 1;
+                        if self.remaining == 0 {
+                            self.state = ParseState::ArrayLen;
+                            return Ok(Some(std::mem::take(&mut self.args)));
+                        }
+                        continue;
+                    };
+                    self.request_len = self.request_len.saturating_add(len);
+                    if len > self.limits.max_bulk_len
+                        || self.request_len > self.limits.max_request_len
+                    {
+                        return Err(RespError::TooLarge);
+                    }
                     self.bulk_len = len;
                     self.state = ParseState::BulkData;
                 }
                 ParseState::BulkData => {
-                    if buf.len() < self.bulk_len + 2 {
+                    if buf.len() < self.bulk_len.saturating_add(2) {
                         return Ok(None);
                     }
-                    let data = buf.split_to(self.bulk_len).to_vec();
+                    let data = take_arg(buf, self.bulk_len);
                     if buf.get_u8() != b'\r' || buf.get_u8() != b'\n' {
                         return Err(RespError::Protocol);
                     }
@@ -108,51 +242,178 @@ impl RespParser {
     }
 }
 
+impl RespParser {
+    /// Splits off the next line without its terminator, or fails once more
+    /// than `MAX_LINE_LEN` bytes arrive without one.
+    ///
+    /// The search resumes where the previous call stopped, so a line that
+    /// arrives a byte at a time is scanned once overall, and a `\r\n` split
+    /// across reads is found once its `\n` lands.
+    fn read_line(
+        &mut self,
+        buf: &mut BytesMut,
+        end: LineEnd,
+    ) -> Result<Option<BytesMut>, RespError> {
+        let from = self.scanned.min(buf.len());
+        #[cfg(test)]
+        {
+            self.scan_work += buf.len() - from;
+        }
+        let Some(offset) = buf[from..].iter().position(|&byte| byte == b'\n') else {
+            self.scanned = buf.len();
+            if buf.len() > MAX_LINE_LEN {
+                return Err(RespError::TooLarge);
+            }
+            return Ok(None);
+        };
+        let newline = from + offset;
+        self.scanned = 0;
+        let crlf = newline > 0 && buf[newline - 1] == b'\r';
+        if end == LineEnd::Crlf && !crlf {
+            return Err(RespError::Protocol);
+        }
+        let line = buf.split_to(if crlf { newline - 1 } else { newline });
+        buf.advance(if crlf { 2 } else { 1 });
+        Ok(Some(line))
+    }
+}
+
+/// How a line must end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEnd {
+    /// `\r\n`, as RESP requires.
+    Crlf,
+    /// `\n` with an optional `\r` before it, as inline commands from
+    /// netcat end.
+    Lf,
+}
+
 impl Default for RespParser {
     fn default() -> Self {
         Self::new()
     }
 }
 
-fn read_line(buf: &mut BytesMut) -> Option<BytesMut> {
-    let mut idx = 1;
-    while idx < buf.len() {
-        if buf[idx] == b'\n' && buf[idx - 1] == b'\r' {
-            let line = buf.split_to(idx - 1);
-            buf.advance(2);
-            return Some(line);
-        }
-        idx += 1;
+/// Takes the next `len` bytes of `buf` as an argument: a slice sharing the
+/// buffer when it is a large share of it, otherwise a copy.
+fn take_arg(buf: &mut BytesMut, len: usize) -> Bytes {
+    if len.saturating_mul(COPY_OUT_RATIO) < buf.capacity() {
+        let arg = Bytes::copy_from_slice(&buf[..len]);
+        buf.advance(len);
+        arg
+    } else {
+        buf.split_to(len).freeze()
     }
-    None
 }
 
+/// Parses a length prefix that may be the null length `-1`, returned as
+/// `None`. Other negative lengths are protocol errors.
+fn parse_length(data: &[u8]) -> Result<Option<usize>, RespError> {
+    match data {
+        b"-1" => Ok(None),
+        _ => parse_usize(data).map(Some),
+    }
+}
+
+/// Parses a length prefix: 1 to `MAX_LENGTH_DIGITS` ASCII digits, with no
+/// sign. Anything else, including a value that overflows `usize`, is a
+/// protocol error.
 fn parse_usize(data: &[u8]) -> Result<usize, RespError> {
-    if data.is_empty() {
+    if data.is_empty() || data.len() > MAX_LENGTH_DIGITS {
         return Err(RespError::Protocol);
     }
-    let mut value: usize = 0;
-    for &b in data {
+    data.iter().try_fold(0usize, |value, &b| {
         if !b.is_ascii_digit() {
             return Err(RespError::Protocol);
         }
-        value = value.saturating_mul(10).saturating_add((b - b'0') as usize);
-    }
-    Ok(value)
+        value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add((b - b'0') as usize))
+            .ok_or(RespError::Protocol)
+    })
 }
 
-fn parse_inline_command(data: &[u8]) -> Result<Vec<Vec<u8>>, RespError> {
-    let args: Vec<Vec<u8>> = data
-        .split(|byte| *byte == b' ')
-        .filter(|segment| !segment.is_empty())
-        .map(|segment| segment.to_vec())
-        .collect();
+/// Splits an inline command into arguments the way Redis's `sdssplitargs`
+/// does: `"..."` takes `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes,
+/// `'...'` takes only `\'`, and a closing quote must end the argument.
+/// Unbalanced quotes are a protocol error.
+fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RespError> {
+    let mut args = Vec::new();
+    let mut rest = line;
+    loop {
+        rest = trim_inline_space(rest);
+        if rest.is_empty() {
+            return Ok(args);
+        }
+        let mut arg = Vec::new();
+        let mut quote = None;
+        loop {
+            let Some((&byte, after)) = rest.split_first() else {
+                if quote.is_some() {
+                    return Err(RespError::Protocol);
+                }
+                break;
+            };
+            rest = after;
+            match (quote, byte) {
+                (None, b' ' | b'\t' | b'\r' | b'\n' | 0) => break,
+                (None, b'"' | b'\'') => quote = Some(byte),
+                (None, _) => arg.push(byte),
+                (Some(q), _) if byte == q => {
+                    // The closing quote must be followed by a space or the end.
+                    if rest.first().is_some_and(|next| !is_inline_space(*next)) {
+                        return Err(RespError::Protocol);
+                    }
+                    break;
+                }
+                (Some(b'"'), b'\\') => {
+                    let (unescaped, after) = unescape(rest).ok_or(RespError::Protocol)?;
+                    arg.push(unescaped);
+                    rest = after;
+                }
+                (Some(_), b'\\') if rest.first() == Some(&b'\'') => {
+                    arg.push(b'\'');
+                    rest = &rest[1..];
+                }
+                (Some(_), _) => arg.push(byte),
+            }
+        }
+        args.push(arg);
+    }
+}
 
-    if args.is_empty() {
-        return Err(RespError::Protocol);
+/// Decodes the escape after a `\` inside double quotes.
+fn unescape(rest: &[u8]) -> Option<(u8, &[u8])> {
+    match rest {
+        [b'x', hi, lo, after @ ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+            let digit = |byte: u8| (byte as char).to_digit(16).unwrap_or(0) as u8;
+            Some((digit(*hi) << 4 | digit(*lo), after))
+        }
+        [escaped, after @ ..] => {
+            let value = match escaped {
+                b'n' => b'\n',
+                b'r' => b'\r',
+                b't' => b'\t',
+                b'b' => 0x08,
+                b'a' => 0x07,
+                other => *other,
+            };
+            Some((value, after))
+        }
+        [] => None,
     }
+}
+
+fn is_inline_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | 0x0b | 0x0c)
+}
 
-    Ok(args)
+fn trim_inline_space(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|&byte| !is_inline_space(byte))
+        .unwrap_or(data.len());
+    &data[start..]
 }
 
 #[cfg(test)]
@@ -165,8 +426,8 @@ mod tests {
         let mut parser = RespParser::new();
         let cmd = parser.parse(&mut buf).unwrap().unwrap();
         assert_eq!(cmd.len(), 2);
-        assert_eq!(cmd[0], b"GET");
-        assert_eq!(cmd[1], b"key");
+        assert_eq!(&cmd[0][..], b"GET");
+        assert_eq!(&cmd[1][..], b"key");
     }
 
     #[test]
@@ -176,7 +437,7 @@ mod tests {
         assert!(parser.parse(&mut buf).unwrap().is_none());
         buf.extend_from_slice(b"G\r\n");
         let cmd = parser.parse(&mut buf).unwrap().unwrap();
-        assert_eq!(cmd[0], b"PING");
+        assert_eq!(&cmd[0][..], b"PING");
     }
 
     #[test]
@@ -186,4 +447,268 @@ mod tests {
         let cmd = parser.parse(&mut buf).unwrap().unwrap();
         assert_eq!(cmd, vec![b"PING".to_vec()]);
     }
+
+    #[test]
+    fn inline_arguments_may_be_quoted() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("SET  \"two words\" 'it\\'s' \"tab\\there\\x41\" \"\"\n");
+        let cmd = parser.parse(&mut buf).unwrap().unwrap();
+        assert_eq!(
+            cmd,
+            vec![
+                b"SET".to_vec(),
+                b"two words".to_vec(),
+                b"it's".to_vec(),
+                b"tab\there\x41".to_vec(),
+                Vec::new(),
+            ]
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn unbalanced_inline_quotes_are_protocol_errors() {
+        for line in ["GET \"key\r\n", "GET 'key\r\n", "GET \"key\"x\r\n"] {
+            let mut buf = BytesMut::from(line);
+            let mut parser = RespParser::new();
+            assert_eq!(parser.parse(&mut buf), Err(RespError::Protocol), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn inline_and_resp_frames_interleave() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("\r\n  \nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\nECHO hi\n");
+        let mut commands = Vec::new();
+        while let Some(cmd) = parser.parse(&mut buf).unwrap() {
+            commands.push(cmd);
+        }
+        assert_eq!(
+            commands,
+            vec![
+                vec![b"PING".to_vec()],
+                vec![b"GET".to_vec(), b"k".to_vec()],
+                vec![b"ECHO".to_vec(), b"hi".to_vec()],
+            ]
+        );
+        assert!(buf.is_empty());
+    }
+
+    fn small_limits() -> RespLimits {
+        RespLimits {
+            max_array_len: 4,
+            max_bulk_len: 16,
+            max_request_len: 24,
+        }
+    }
+
+    #[test]
+    fn rejects_declared_bulk_length_before_the_data_arrives() {
+        let mut buf = BytesMut::from("*1\r\n$9999999999\r\n");
+        let mut parser = RespParser::new();
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+
+        let mut buf = BytesMut::from("*1\r\n$999999999\r\n");
+        let mut parser = RespParser::with_limits(small_limits());
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_huge_array_counts() {
+        let mut buf = BytesMut::from("*9999999999\r\n");
+        let mut parser = RespParser::new();
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+
+        // The ceiling holds even when the configured limit is higher.
+        let mut parser = RespParser::with_limits(RespLimits {
+            max_array_len: usize::MAX,
+            ..RespLimits::default()
+        });
+        let mut buf = BytesMut::from(format!("*{}\r\n", MAX_ARRAY_LEN_CEILING + 1).as_str());
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+
+        let mut buf = BytesMut::from("*5\r\n");
+        let mut parser = RespParser::with_limits(small_limits());
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_commands_whose_bulks_add_up_past_the_limit() {
+        let mut parser = RespParser::with_limits(small_limits());
+        let mut buf = BytesMut::from("*2\r\n$12\r\naaaaaaaaaaaa\r\n$12\r\nbbbbbbbbbbbb\r\n");
+        assert_eq!(parser.parse(&mut buf).unwrap().unwrap().len(), 2);
+
+        let mut buf = BytesMut::from("*2\r\n$16\r\naaaaaaaaaaaaaaaa\r\n$16\r\n");
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_unterminated_lines_past_the_limit() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from(vec![b'a'; MAX_LINE_LEN].as_slice());
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"a");
+        assert_eq!(parser.parse(&mut buf), Err(RespError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_malformed_length_prefixes() {
+        for frame in [
+            // Too many digits, even ones that would fit after saturating.
+            "*1\r\n$18446744073709551615\r\n",
+            "*1\r\n$00000000001\r\n",
+            "*18446744073709551616\r\n",
+            // Signs, blanks and other non-digits.
+            "*1\r\n$+3\r\nfoo\r\n",
+            "*+1\r\n$3\r\nfoo\r\n",
+            "*1\r\n$ 3\r\nfoo\r\n",
+            "*1\r\n$3 \r\nfoo\r\n",
+            "*1\r\n$0x3\r\nfoo\r\n",
+            "*1\r\n$\r\n",
+            "*\r\n",
+        ] {
+            let mut buf = BytesMut::from(frame);
+            let mut parser = RespParser::new();
+            assert_eq!(
+                parser.parse(&mut buf),
+                Err(RespError::Protocol),
+                "{frame:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn null_bulk_strings_read_as_empty_arguments() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$-1\r\n*1\r\n$-1\r\n");
+        assert_eq!(
+            parser.parse(&mut buf).unwrap().unwrap(),
+            vec![b"SET".to_vec(), b"k".to_vec(), Vec::new()]
+        );
+        assert_eq!(parser.parse(&mut buf).unwrap().unwrap(), vec![Bytes::new()]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn null_arrays_read_as_empty_commands() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("*-1\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(parser.parse(&mut buf), Ok(Some(Vec::new())));
+        assert_eq!(
+            parser.parse(&mut buf).unwrap().unwrap(),
+            vec![b"PING".to_vec()]
+        );
+    }
+
+    #[test]
+    fn rejects_other_negative_lengths() {
+        for frame in [
+            "*1\r\n$-2\r\n",
+            "*1\r\n$-0\r\n",
+            "*-2\r\n",
+            "*1\r\n$-1x\r\n",
+        ] {
+            let mut buf = BytesMut::from(frame);
+            let mut parser = RespParser::new();
+            assert_eq!(
+                parser.parse(&mut buf),
+                Err(RespError::Protocol),
+                "{frame:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn large_arguments_share_the_read_buffer() {
+        let value = vec![b'v'; 4096];
+        let mut frame = b"*2\r\n$3\r\nSET\r\n$4096\r\n".to_vec();
+        frame.extend_from_slice(&value);
+        frame.extend_from_slice(b"\r\n");
+        let mut buf = BytesMut::from(frame.as_slice());
+        let storage = buf.as_ptr_range();
+        let cmd = RespParser::new().parse(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd[1], value);
+        assert!(storage.contains(&cmd[1].as_ptr()));
+        // "SET" is under a quarter of the buffer, so it was copied out.
+        assert!(!storage.contains(&cmd[0].as_ptr()));
+    }
+
+    #[test]
+    fn errors_are_sticky() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("*1\r\n$99999999999\r\n");
+        assert_eq!(parser.parse(&mut buf), Err(RespError::Protocol));
+
+        // A well-formed frame afterwards is not parsed: the parser cannot
+        // know where the bad frame ended.
+        let mut buf = BytesMut::from("*1\r\n$4\r\nPING\r\n");
+        assert_eq!(parser.parse(&mut buf), Err(RespError::Protocol));
+        assert_eq!(buf.len(), 14);
+    }
+
+    /// Feeds `input` one byte per `parse` call, returning every command.
+    fn trickle(parser: &mut RespParser, input: &[u8]) -> Vec<Vec<Bytes>> {
+        let mut buf = BytesMut::new();
+        let mut commands = Vec::new();
+        for &byte in input {
+            buf.extend_from_slice(&[byte]);
+            while let Some(cmd) = parser.parse(&mut buf).unwrap() {
+                commands.push(cmd);
+            }
+        }
+        assert!(buf.is_empty());
+        commands
+    }
+
+    #[test]
+    fn parses_commands_fed_a_byte_at_a_time() {
+        let mut parser = RespParser::new();
+        let input = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\nPING\r\n";
+        assert_eq!(
+            trickle(&mut parser, input),
+            vec![
+                vec![b"GET".to_vec(), b"key".to_vec()],
+                vec![b"PING".to_vec()],
+            ]
+        );
+        assert!(parser.scan_work <= input.len(), "{}", parser.scan_work);
+    }
+
+    #[test]
+    fn long_trickled_lines_are_scanned_once() {
+        let mut parser = RespParser::new();
+        let mut input = format!("*1\r\n${}\r\n", 4000).into_bytes();
+        input.extend_from_slice(&[b'v'; 4000]);
+        input.extend_from_slice(b"\r\nSET k ");
+        input.extend_from_slice(&[b'w'; 4000]);
+        input.extend_from_slice(b"\r\n");
+        let commands = trickle(&mut parser, &input);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1][2].len(), 4000);
+        // Bulk data is not scanned, and each line byte is looked at once.
+        assert!(parser.scan_work <= 4100, "{}", parser.scan_work);
+    }
+
+    #[test]
+    fn crlf_split_across_reads_ends_the_line() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from("*1\r");
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"\n$4\r");
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"\nPING\r");
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"\n");
+        assert_eq!(
+            parser.parse(&mut buf).unwrap().unwrap(),
+            vec![b"PING".to_vec()]
+        );
+    }
+
+    #[test]
+    fn resp_lines_need_a_carriage_return() {
+        let mut buf = BytesMut::from("*1\n$4\r\nPING\r\n");
+        let mut parser = RespParser::new();
+        assert_eq!(parser.parse(&mut buf), Err(RespError::Protocol));
+    }
 }
@@ -0,0 +1,169 @@
+//! # Rate Limiting
+//!
+//! Token buckets that keep one client from saturating the server: a command
+//! rate per connection (`maxcmd-per-sec`) and a connection rate per peer IP
+//! (`maxconn-per-ip-per-sec`).
+//!
+//! ## Design Principles
+//!
+//! 1. **Delay Commands, Refuse Connections**: A connection over its command
+//!    rate waits for its next token instead of getting an error, so a
+//!    pipelining client still gets every reply, only slower. An IP over its
+//!    connection rate has the extra connections refused at accept.
+//! 2. **One Second of Burst**: A bucket holds at most `rate` tokens and
+//!    starts full, so a client may burst for a second's worth before the
+//!    limit applies.
+//! 3. **Rates Read per Use**: Buckets take the rate on every call, so
+//!    `CONFIG SET` applies from the next command or accept; 0 disables a
+//!    limit and its buckets are not touched.
+//! 4. **Bounded Peer Table**: Per-IP buckets idle long enough to have
+//!    refilled are indistinguishable from new ones, so they are dropped once
+//!    the table passes `PEER_TABLE_PRUNE_LEN` entries.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Peer buckets kept before idle ones are pruned.
+pub const PEER_TABLE_PRUNE_LEN: usize = 1024;
+
+/// A token bucket refilled at a caller-supplied rate per second.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenBucket {
+    /// Tokens available; negative while callers wait on tokens taken ahead.
+    tokens: f64,
+    /// When `tokens` was last brought up to date; `None` while full.
+    updated: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub const fn new() -> Self {
+        TokenBucket {
+            tokens: 0.0,
+            updated: None,
+        }
+    }
+
+    /// Takes a token at `rate` per second, even one that has yet to
+    /// refill, and returns how long to wait until it has; zero when a token
+    /// was available.
+    pub fn take(&mut self, rate: u64, now: Instant) -> Duration {
+        self.refill(rate, now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate as f64)
+        }
+    }
+
+    /// Takes a token at `rate` per second if one is available.
+    pub fn try_take(&mut self, rate: u64, now: Instant) -> bool {
+        self.refill(rate, now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn refill(&mut self, rate: u64, now: Instant) {
+        let rate = rate as f64;
+        self.tokens = match self.updated {
+            Some(updated) => (self.tokens
+                + now.saturating_duration_since(updated).as_secs_f64() * rate)
+                .min(rate),
+            None => rate,
+        };
+        self.updated = Some(now);
+    }
+
+    /// Whether the bucket has had time to refill completely.
+    fn is_full_at(&self, now: Instant) -> bool {
+        self.updated
+            .is_none_or(|updated| now.saturating_duration_since(updated) >= Duration::from_secs(1))
+    }
+}
+
+/// Per-IP connection buckets shared by the accept loop.
+#[derive(Debug, Default)]
+pub struct PeerRateLimiter {
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl PeerRateLimiter {
+    /// An empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `ip` may open another connection at `rate` per second.
+    pub fn allow(&self, ip: IpAddr, rate: u64, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        if buckets.len() >= PEER_TABLE_PRUNE_LEN && !buckets.contains_key(&ip) {
+            buckets.retain(|_, bucket| !bucket.is_full_at(now));
+        }
+        buckets.entry(ip).or_default().try_take(rate, now)
+    }
+
+    /// Peers with a bucket.
+    pub fn len(&self) -> usize {
+        self.buckets
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Whether no peer has a bucket.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_past_the_burst_wait_for_their_token() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new();
+        for _ in 0..10 {
+            assert_eq!(bucket.take(10, start), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(10, start), Duration::from_millis(100));
+        assert_eq!(bucket.take(10, start), Duration::from_millis(200));
+        // Waiting pays the debt back before new tokens accrue.
+        let later = start + Duration::from_millis(200);
+        assert_eq!(bucket.take(10, later), Duration::from_millis(100));
+        let idle = later + Duration::from_secs(5);
+        assert_eq!(bucket.take(10, idle), Duration::ZERO);
+    }
+
+    #[test]
+    fn peers_are_limited_independently() {
+        let limiter = PeerRateLimiter::new();
+        let start = Instant::now();
+        let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        assert!(limiter.allow(a, 2, start));
+        assert!(limiter.allow(a, 2, start));
+        assert!(!limiter.allow(a, 2, start));
+        assert!(limiter.allow(b, 2, start));
+        assert!(limiter.allow(a, 2, start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn idle_peers_are_pruned() {
+        let limiter = PeerRateLimiter::new();
+        let start = Instant::now();
+        for n in 0..PEER_TABLE_PRUNE_LEN as u32 {
+            assert!(limiter.allow(IpAddr::from(n.to_be_bytes()), 1, start));
+        }
+        assert_eq!(limiter.len(), PEER_TABLE_PRUNE_LEN);
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.allow(IpAddr::from([192, 168, 0, 1]), 1, later));
+        assert_eq!(limiter.len(), 1);
+    }
+}
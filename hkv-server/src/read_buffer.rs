@@ -0,0 +1,316 @@
+//! # Read Buffers
+//!
+//! Per-connection read buffers that start small, grow to fit the largest
+//! request in flight and shrink again once the connection goes back to
+//! small requests, plus a pool of small buffers shared by every connection.
+//!
+//! ## Design Principles
+//!
+//! 1. **Start Small**: A connection starts with `READ_BUFFER_MIN` bytes;
+//!    `BytesMut` grows the buffer on demand as reads and requests need.
+//! 2. **Shrink on Small Use**: A read that leaves the buffer at most
+//!    `1 / SMALL_READ_FRACTION` full is small. After `read-buffer-shrink-after`
+//!    small reads in a row, the buffer is swapped for one sized to its
+//!    contents, so one burst of large requests does not pin memory for the
+//!    life of the connection.
+//! 3. **Swap Between Requests Only**: Buffers are shrunk after each batch of
+//!    parsed requests, and the partial request left over is copied to the
+//!    new buffer, so pipelined requests parse the same across the swap.
+//! 4. **Pool the Small Ones**: Closed connections return buffers of at most
+//!    `POOLED_MAX` bytes to a shared pool of at most `POOL_CAPACITY`, so
+//!    connection churn does not allocate a buffer per connection.
+//! 5. **Accounted in INFO**: Held and pooled bytes are tracked in `Metrics`
+//!    for `INFO memory`.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+
+use crate::metrics::Metrics;
+
+/// Capacity a connection's read buffer starts at and shrinks back to.
+pub const READ_BUFFER_MIN: usize = 4 * 1024;
+
+/// Reads leaving the buffer at most this fraction full count as small.
+pub const SMALL_READ_FRACTION: usize = 4;
+
+/// Largest buffer returned to the pool when a connection closes.
+pub const POOLED_MAX: usize = 2 * READ_BUFFER_MIN;
+
+/// Buffers the pool keeps for new connections.
+pub const POOL_CAPACITY: usize = 256;
+
+/// Small read buffers kept for new connections.
+pub struct BufferPool {
+    metrics: Arc<Metrics>,
+    buffers: Mutex<Pooled>,
+}
+
+#[derive(Default)]
+struct Pooled {
+    buffers: Vec<BytesMut>,
+    bytes: usize,
+}
+
+impl BufferPool {
+    /// An empty pool accounting its bytes in `metrics`.
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        BufferPool {
+            metrics,
+            buffers: Mutex::new(Pooled::default()),
+        }
+    }
+
+    /// A read buffer for a new connection, pooled when one is available.
+    pub fn checkout(self: &Arc<Self>) -> ReadBuffer {
+        let buf = {
+            let mut pooled = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+            let buf = pooled.buffers.pop();
+            if let Some(buf) = &buf {
+                let before = pooled.bytes;
+                pooled.bytes -= buf.capacity();
+                self.metrics.resize_read_buffer_pool(before, pooled.bytes);
+            }
+            buf
+        };
+        let buf = buf.unwrap_or_else(|| BytesMut::with_capacity(READ_BUFFER_MIN));
+        self.metrics.resize_read_buffers(0, buf.capacity());
+        ReadBuffer {
+            held: buf.capacity(),
+            buf,
+            pool: Arc::clone(self),
+            small_reads: 0,
+        }
+    }
+
+    /// Buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .buffers
+            .len()
+    }
+
+    /// Whether the pool has no buffers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keeps `buf` for a later connection if it is small and the pool has
+    /// room; drops it otherwise.
+    fn checkin(&self, mut buf: BytesMut) {
+        buf.clear();
+        // Parsing splits requests off the front; this reclaims their room.
+        buf.reserve(READ_BUFFER_MIN);
+        if buf.capacity() > POOLED_MAX {
+            return;
+        }
+        let mut pooled = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+        if pooled.buffers.len() >= POOL_CAPACITY {
+            return;
+        }
+        let before = pooled.bytes;
+        pooled.bytes += buf.capacity();
+        pooled.buffers.push(buf);
+        self.metrics.resize_read_buffer_pool(before, pooled.bytes);
+    }
+}
+
+/// A connection's read buffer, shrunk once it sees only small reads and
+/// returned to its pool on drop.
+pub struct ReadBuffer {
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+    /// Capacity last accounted in `Metrics`.
+    held: usize,
+    /// Small reads since the last large one or shrink.
+    small_reads: u64,
+}
+
+impl ReadBuffer {
+    /// Accounts a read into the buffer and counts it toward a shrink when
+    /// it left the buffer mostly empty.
+    pub fn record_read(&mut self) {
+        self.account();
+        if self.buf.len() <= self.buf.capacity() / SMALL_READ_FRACTION {
+            self.small_reads += 1;
+        } else {
+            self.small_reads = 0;
+        }
+    }
+
+    /// Swaps the buffer for one sized to its contents after `shrink_after`
+    /// small reads in a row; 0 never shrinks. Returns whether it shrank.
+    ///
+    /// Call between parsed requests: the partial request left in the buffer
+    /// is copied to the new one.
+    pub fn maybe_shrink(&mut self, shrink_after: u64) -> bool {
+        if shrink_after == 0 || self.small_reads < shrink_after {
+            return false;
+        }
+        self.small_reads = 0;
+        let capacity = self.buf.len().next_power_of_two().max(READ_BUFFER_MIN);
+        if capacity >= self.buf.capacity() {
+            return false;
+        }
+        let mut buf = BytesMut::with_capacity(capacity);
+        buf.extend_from_slice(&self.buf);
+        // The old allocation is freed here, or once the last value parsed
+        // from it is dropped.
+        self.buf = buf;
+        self.account();
+        true
+    }
+
+    /// Brings the accounted capacity up to date.
+    fn account(&mut self) {
+        let capacity = self.buf.capacity();
+        if capacity != self.held {
+            self.pool.metrics.resize_read_buffers(self.held, capacity);
+            self.held = capacity;
+        }
+    }
+}
+
+impl Deref for ReadBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.buf
+    }
+}
+
+impl DerefMut for ReadBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+}
+
+impl Drop for ReadBuffer {
+    fn drop(&mut self) {
+        self.pool.metrics.resize_read_buffers(self.held, 0);
+        self.pool.checkin(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RespParser;
+
+    fn pool() -> (Arc<Metrics>, Arc<BufferPool>) {
+        let metrics = Arc::new(Metrics::new());
+        (Arc::clone(&metrics), Arc::new(BufferPool::new(metrics)))
+    }
+
+    fn set_command(value_len: usize) -> Vec<u8> {
+        let mut command = format!("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n${value_len}\r\n").into_bytes();
+        command.extend(std::iter::repeat_n(b'v', value_len));
+        command.extend_from_slice(b"\r\n");
+        command
+    }
+
+    #[test]
+    fn buffer_shrinks_after_enough_small_reads() {
+        let (metrics, pool) = pool();
+        let mut buffer = pool.checkout();
+        assert_eq!(buffer.capacity(), READ_BUFFER_MIN);
+
+        buffer.extend_from_slice(&set_command(1 << 20));
+        buffer.record_read();
+        let grown = buffer.capacity();
+        assert!(grown > 1 << 20);
+        assert_eq!(metrics.snapshot().read_buffer_bytes, grown as u64);
+        buffer.clear();
+
+        for _ in 0..3 {
+            buffer.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+            buffer.record_read();
+            assert!(!buffer.maybe_shrink(4));
+            buffer.clear();
+        }
+        buffer.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        buffer.record_read();
+        assert!(buffer.maybe_shrink(4));
+        assert_eq!(buffer.capacity(), READ_BUFFER_MIN);
+        assert_eq!(&buffer[..], b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(metrics.snapshot().read_buffer_bytes, READ_BUFFER_MIN as u64);
+    }
+
+    #[test]
+    fn large_read_resets_the_small_read_streak() {
+        let (_, pool) = pool();
+        let mut buffer = pool.checkout();
+        buffer.reserve(1 << 16);
+        for _ in 0..3 {
+            buffer.record_read();
+        }
+        let half = vec![b'x'; buffer.capacity() / 2];
+        buffer.extend_from_slice(&half);
+        buffer.record_read();
+        buffer.clear();
+        buffer.record_read();
+        assert!(!buffer.maybe_shrink(4));
+        assert!(!buffer.maybe_shrink(0));
+    }
+
+    #[test]
+    fn pipelined_large_request_parses_across_a_shrink() {
+        let (_, pool) = pool();
+        let mut buffer = pool.checkout();
+        let mut parser = RespParser::new();
+        buffer.reserve(1 << 20);
+        let large = set_command(64 * 1024);
+
+        // Small reads leave only the start of a large request behind.
+        for _ in 0..2 {
+            buffer.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+            buffer.extend_from_slice(&large[..100]);
+            buffer.record_read();
+            assert!(parser.parse(&mut buffer).unwrap().is_some());
+            assert!(parser.parse(&mut buffer).unwrap().is_none());
+            assert!(!buffer.maybe_shrink(3));
+            buffer.extend_from_slice(&large[100..]);
+            let args = parser.parse(&mut buffer).unwrap().unwrap();
+            assert_eq!(args[2].len(), 64 * 1024);
+        }
+        buffer.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        buffer.extend_from_slice(&large[..100]);
+        buffer.record_read();
+        assert!(parser.parse(&mut buffer).unwrap().is_some());
+        assert!(parser.parse(&mut buffer).unwrap().is_none());
+        assert!(buffer.maybe_shrink(3));
+        assert_eq!(buffer.capacity(), READ_BUFFER_MIN);
+
+        buffer.extend_from_slice(&large[100..]);
+        buffer.extend_from_slice(&large);
+        for _ in 0..2 {
+            let args = parser.parse(&mut buffer).unwrap().unwrap();
+            assert_eq!(args[0], "SET");
+            assert_eq!(args[2], vec![b'v'; 64 * 1024]);
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn closed_connections_return_small_buffers_to_the_pool() {
+        let (metrics, pool) = pool();
+        let small = pool.checkout();
+        let mut large = pool.checkout();
+        large.reserve(1 << 20);
+        large.record_read();
+        drop((small, large));
+
+        assert_eq!(pool.len(), 1);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.read_buffer_bytes, 0);
+        assert_eq!(snapshot.read_buffer_pool_bytes, READ_BUFFER_MIN as u64);
+
+        let reused = pool.checkout();
+        assert!(pool.is_empty());
+        assert_eq!(reused.capacity(), READ_BUFFER_MIN);
+        assert_eq!(metrics.snapshot().read_buffer_pool_bytes, 0);
+    }
+}
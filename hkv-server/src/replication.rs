@@ -0,0 +1,775 @@
+//! # Replication
+//!
+//! Keep a hot standby in sync: `REPLICAOF host port` makes this server a
+//! replica that loads a full snapshot from its master and then applies the
+//! master's stream of write commands; the master side answers `PSYNC`.
+//!
+//! ## Design Principles
+//!
+//! 1. **Resume When Possible**: A replica remembers its master's replication
+//!    id and how far it got, and reconnects with `PSYNC <replid> <offset>`.
+//!    If the backlog still holds everything after that offset the master
+//!    answers `+CONTINUE <replid>` and sends just the missed tail; otherwise
+//!    `+FULLRESYNC <replid> <offset>` and the `dump.hkv` codec's bytes as one
+//!    bulk payload. A first connection sends `PSYNC ? -1`.
+//! 2. **Subscribe, Then Catch Up**: A replica's feed is subscribed and its
+//!    snapshot or backlog tail taken while writes are held back, so every
+//!    write is either in what it catches up with or in the feed, never lost
+//!    between them.
+//! 3. **Ordered Propagation**: Once a replica has connected, write commands
+//!    execute, enter the backlog and publish one at a time, so replicas
+//!    apply them in the order the master did. Before that writes are not
+//!    serialized or recorded.
+//! 4. **Bounded History**: The backlog keeps the last `repl-backlog-size`
+//!    bytes of the stream and the feed is a bounded broadcast; a replica that
+//!    falls `REPLICA_FEED_LEN` commands behind, or further behind the stream
+//!    than the replica `client-output-buffer-limit` allows, is disconnected
+//!    and resumes from the backlog rather than being buffered without limit.
+//! 5. **Retry Forever**: A replica reconnects `REPLICA_RETRY_DELAY` after any
+//!    failure until `REPLICAOF NO ONE` or a new master replaces the link.
+//! 6. **Acknowledged Offsets**: Replicas send `REPLCONF ACK <offset>` every
+//!    `REPLICA_ACK_INTERVAL`; the master reports each one's offset and the
+//!    seconds since its last ack in `INFO replication`.
+//!
+//! Offsets count the bytes of the command stream. As in Redis, `PSYNC`
+//! names the first byte the replica is missing, one past what it applied.
+//!
+//! A replica reports itself not ready (see `ServerLifecycle`) from
+//! `REPLICAOF host port` until its first full sync has loaded.
+//!
+//! TTLs travel with the snapshot and with `SET ... EX`/`EXPIRE`, so replicas
+//! expire keys on their own clock; the master's expirer is not replicated.
+//! `FLUSHALL` and `FLUSHDB` replicate as written.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use hkv_engine::{KVEngine, snapshot};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::config::{ClientClass, RuntimeConfig};
+use crate::metrics::Metrics;
+use crate::protocol::RespParser;
+use crate::shutdown::ShutdownToken;
+
+/// Write commands a replica may fall behind by before it is disconnected.
+pub const REPLICA_FEED_LEN: usize = 16 * 1024;
+
+/// Wait between a failed replication attempt and the next.
+pub const REPLICA_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Interval between a replica's `REPLCONF ACK`s.
+pub const REPLICA_ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest a replica waits for each step of the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Replication state for one listening server.
+pub struct Replication {
+    engine: Arc<dyn KVEngine>,
+    /// Source of `repl-backlog-size`.
+    runtime: Arc<RuntimeConfig>,
+    /// Port announced to masters with `REPLCONF listening-port`.
+    listening_port: u16,
+    /// Identifies this server's history in `+FULLRESYNC` and `+CONTINUE`.
+    replid: String,
+    feed: broadcast::Sender<Arc<[u8]>>,
+    /// The stream's offset and recent bytes; held while a write executes
+    /// and publishes so replicas see writes in execution order.
+    backlog: Mutex<Backlog>,
+    /// Set when the first replica connects; writes are recorded from then.
+    recording: AtomicBool,
+    /// Read-held by every write; write-held while a replica subscribes and
+    /// catches up.
+    sync_gate: RwLock<()>,
+    /// Connected replicas by client id.
+    replicas: Mutex<BTreeMap<u64, ReplicaState>>,
+    sync_full: AtomicU64,
+    sync_partial_ok: AtomicU64,
+    sync_partial_err: AtomicU64,
+    /// The master this server replicates, if any.
+    link: Mutex<Option<MasterLink>>,
+}
+
+/// The replication stream's offset and its most recent bytes.
+#[derive(Debug, Default)]
+struct Backlog {
+    /// Bytes recorded since the first replica connected.
+    offset: u64,
+    /// The last bytes recorded, at most `repl-backlog-size` of them.
+    history: VecDeque<u8>,
+}
+
+impl Backlog {
+    /// Records `bytes`, forgetting the oldest beyond `limit`.
+    fn append(&mut self, bytes: &[u8], limit: usize) {
+        self.offset += bytes.len() as u64;
+        self.history.extend(bytes);
+        let excess = self.history.len().saturating_sub(limit);
+        self.history.drain(..excess);
+    }
+
+    /// Offset of the oldest byte still held.
+    fn first_offset(&self) -> u64 {
+        self.offset - self.history.len() as u64
+    }
+
+    /// The bytes recorded after `offset`, or `None` if some have already
+    /// been forgotten or `offset` is ahead of the stream.
+    fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset > self.offset {
+            return None;
+        }
+        let skip = usize::try_from(offset.checked_sub(self.first_offset())?).ok()?;
+        Some(self.history.range(skip..).copied().collect())
+    }
+}
+
+/// A connected replica, as reported by `INFO replication`.
+struct ReplicaState {
+    addr: Option<SocketAddr>,
+    /// Offset from its last `REPLCONF ACK`.
+    ack_offset: u64,
+    last_ack: Instant,
+}
+
+/// A running connection to this server's master.
+struct MasterLink {
+    host: String,
+    port: u16,
+    status: Arc<LinkStatus>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct LinkStatus {
+    up: AtomicBool,
+    sync_in_progress: AtomicBool,
+    /// Master offset of the last applied command.
+    offset: AtomicU64,
+    /// The master's replication id once a full sync has loaded, so
+    /// reconnects can ask to resume from `offset`.
+    master_replid: Mutex<Option<String>>,
+}
+
+impl Replication {
+    /// Creates master-role state for `engine`, served on `listening_port`.
+    pub fn new(
+        engine: Arc<dyn KVEngine>,
+        runtime: Arc<RuntimeConfig>,
+        listening_port: u16,
+    ) -> Self {
+        let (feed, _) = broadcast::channel(REPLICA_FEED_LEN);
+        let seed = format!("{:?}-{}", SystemTime::now(), std::process::id());
+        Replication {
+            engine,
+            runtime,
+            listening_port,
+            replid: sha1_smol::Sha1::from(seed).digest().to_string(),
+            feed,
+            backlog: Mutex::new(Backlog::default()),
+            recording: AtomicBool::new(false),
+            sync_gate: RwLock::new(()),
+            replicas: Mutex::new(BTreeMap::new()),
+            sync_full: AtomicU64::new(0),
+            sync_partial_ok: AtomicU64::new(0),
+            sync_partial_err: AtomicU64::new(0),
+            link: Mutex::new(None),
+        }
+    }
+
+    /// Runs `execute` for a write command and, if it succeeded and a
+    /// replica has ever connected, records and publishes `args`.
+    pub fn propagate_with(&self, args: &[Bytes], execute: impl FnOnce() -> Vec<u8>) -> Vec<u8> {
+        let _gate = self.sync_gate.read().unwrap_or_else(|err| err.into_inner());
+        if !self.recording.load(Ordering::Relaxed) {
+            return execute();
+        }
+        let mut backlog = self.backlog.lock().unwrap_or_else(|err| err.into_inner());
+        let response = execute();
+        if response.first() != Some(&b'-') {
+            let command: Arc<[u8]> = encode_command(args).into();
+            let limit = usize::try_from(self.runtime.repl_backlog_size()).unwrap_or(usize::MAX);
+            backlog.append(&command, limit);
+            // Every replica may be disconnected; the backlog has it anyway.
+            let _ = self.feed.send(command);
+        }
+        response
+    }
+
+    /// Serves a replica that sent `psync` on `stream`: the missed backlog
+    /// tail or a full snapshot, then every propagated write until the
+    /// replica disconnects, lags behind or the server shuts down.
+    pub async fn serve_replica<S>(
+        &self,
+        mut stream: S,
+        psync: &[Bytes],
+        client_id: u64,
+        addr: Option<SocketAddr>,
+        metrics: &Metrics,
+        mut shutdown: ShutdownToken,
+    ) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let resume_from = self.resume_point(psync);
+        let (mut commands, offset, catch_up) = {
+            let _gate = self
+                .sync_gate
+                .write()
+                .unwrap_or_else(|err| err.into_inner());
+            let backlog = self.backlog.lock().unwrap_or_else(|err| err.into_inner());
+            self.recording.store(true, Ordering::Relaxed);
+            let commands = self.feed.subscribe();
+            let catch_up = match resume_from.and_then(|offset| backlog.since(offset)) {
+                Some(missed) => CatchUp::Partial(missed),
+                None => CatchUp::Full(self.engine.snapshot().map_err(io::Error::other)?),
+            };
+            (commands, backlog.offset, catch_up)
+        };
+        let _connected = self.register_replica(client_id, addr);
+
+        match catch_up {
+            CatchUp::Partial(missed) => {
+                self.sync_partial_ok.fetch_add(1, Ordering::Relaxed);
+                tracing::info!(
+                    offset,
+                    missed = missed.len(),
+                    "replica connected, resuming from the backlog"
+                );
+                let header = format!("+CONTINUE {}\r\n", self.replid);
+                stream.write_all(header.as_bytes()).await?;
+                stream.write_all(&missed).await?;
+            }
+            CatchUp::Full(entries) => {
+                self.sync_full.fetch_add(1, Ordering::Relaxed);
+                if psync.get(1).is_some_and(|replid| replid.as_ref() != b"?") {
+                    self.sync_partial_err.fetch_add(1, Ordering::Relaxed);
+                }
+                tracing::info!(
+                    offset,
+                    keys = entries.len(),
+                    "replica connected, starting full sync"
+                );
+                let payload = snapshot::encode(&entries, SystemTime::now());
+                let header = format!(
+                    "+FULLRESYNC {} {offset}\r\n${}\r\n",
+                    self.replid,
+                    payload.len()
+                );
+                stream.write_all(header.as_bytes()).await?;
+                stream.write_all(&payload).await?;
+            }
+        }
+        stream.flush().await?;
+
+        let mut input = BytesMut::with_capacity(1024);
+        let mut parser = RespParser::new();
+        // Stream offset written to this replica, and when the bytes it has
+        // yet to be sent first passed the replica soft limit.
+        let mut sent = offset;
+        let mut soft_since = None;
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Ok(command) => {
+                        let queued = self
+                            .backlog
+                            .lock()
+                            .unwrap_or_else(|err| err.into_inner())
+                            .offset
+                            .saturating_sub(sent);
+                        let limit = self.runtime.output_buffer_limit(ClientClass::Replica);
+                        if limit.exceeded(queued, &mut soft_since, Instant::now()) {
+                            tracing::warn!(
+                                queued,
+                                "replica past its output buffer limit, disconnecting"
+                            );
+                            metrics.record_output_buffer_limit_disconnect();
+                            return Ok(());
+                        }
+                        stream.write_all(&command).await?;
+                        sent += command.len() as u64;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "replica fell behind the feed, disconnecting");
+                        return Ok(());
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                read = stream.read_buf(&mut input) => {
+                    if read? == 0 {
+                        tracing::info!("replica disconnected");
+                        return Ok(());
+                    }
+                    while let Some(args) = parser
+                        .parse(&mut input)
+                        .map_err(|err| invalid_data(format!("{err:?}")))?
+                    {
+                        if let Some(offset) = parse_ack(&args) {
+                            self.record_ack(client_id, offset);
+                        }
+                    }
+                }
+                () = shutdown.wait() => return Ok(()),
+            }
+        }
+    }
+
+    /// The offset a `PSYNC <replid> <offset>` asks to resume after, if it
+    /// names this server's history.
+    fn resume_point(&self, psync: &[Bytes]) -> Option<u64> {
+        match psync {
+            [_, replid, offset] if replid.as_ref() == self.replid.as_bytes() => {
+                std::str::from_utf8(offset)
+                    .ok()?
+                    .parse::<u64>()
+                    .ok()?
+                    .checked_sub(1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Lists a replica in `INFO` until the returned guard drops.
+    fn register_replica(&self, client_id: u64, addr: Option<SocketAddr>) -> ReplicaGuard<'_> {
+        let state = ReplicaState {
+            addr,
+            ack_offset: 0,
+            last_ack: Instant::now(),
+        };
+        self.replicas
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(client_id, state);
+        ReplicaGuard {
+            replicas: &self.replicas,
+            client_id,
+        }
+    }
+
+    fn record_ack(&self, client_id: u64, offset: u64) {
+        let mut replicas = self.replicas.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(replica) = replicas.get_mut(&client_id) {
+            replica.ack_offset = offset;
+            replica.last_ack = Instant::now();
+        }
+    }
+
+    /// Starts replicating `host:port`, replacing any previous master.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn replicate_from(self: &Arc<Self>, host: String, port: u16) {
+        let mut link = self.link.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(previous) = link.take() {
+            previous.task.abort();
+        }
+        let status = Arc::new(LinkStatus::default());
+        self.runtime.lifecycle().set_awaiting_sync(true);
+        let task =
+            tokio::spawn(Arc::clone(self).run_replica(host.clone(), port, Arc::clone(&status)));
+        tracing::info!(%host, port, "replicating from master");
+        *link = Some(MasterLink {
+            host,
+            port,
+            status,
+            task,
+        });
+    }
+
+    /// Stops replicating and keeps the data set as it is; returns false if
+    /// this server was not a replica. The server calls this when it stops,
+    /// since the replica task would otherwise outlive it.
+    pub fn stop_replicating(&self) -> bool {
+        let link = self
+            .link
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        match link {
+            Some(link) => {
+                link.task.abort();
+                self.runtime.lifecycle().set_awaiting_sync(false);
+                tracing::info!(host = %link.host, port = link.port, "stopped replicating");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether this server replicates a master.
+    pub fn is_replica(&self) -> bool {
+        self.link
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .is_some()
+    }
+
+    /// The `# Replication` section of `INFO`.
+    pub fn info(&self) -> String {
+        let mut info = String::from("# Replication\r\n");
+        let link = self.link.lock().unwrap_or_else(|err| err.into_inner());
+        match link.as_ref() {
+            Some(link) => {
+                let up = link.status.up.load(Ordering::Relaxed);
+                info.push_str(&format!(
+                    concat!(
+                        "role:slave\r\n",
+                        "master_host:{}\r\n",
+                        "master_port:{}\r\n",
+                        "master_link_status:{}\r\n",
+                        "master_sync_in_progress:{}\r\n",
+                        "slave_repl_offset:{}\r\n",
+                    ),
+                    link.host,
+                    link.port,
+                    if up { "up" } else { "down" },
+                    u8::from(link.status.sync_in_progress.load(Ordering::Relaxed)),
+                    link.status.offset.load(Ordering::Relaxed),
+                ));
+            }
+            None => info.push_str("role:master\r\n"),
+        }
+        drop(link);
+
+        let replicas = self.replicas.lock().unwrap_or_else(|err| err.into_inner());
+        info.push_str(&format!("connected_slaves:{}\r\n", replicas.len()));
+        for (index, replica) in replicas.values().enumerate() {
+            let addr = replica
+                .addr
+                .map_or_else(|| "-".to_string(), |addr| addr.to_string());
+            info.push_str(&format!(
+                "slave{index}:addr={addr},state=online,offset={},lag={}\r\n",
+                replica.ack_offset,
+                replica.last_ack.elapsed().as_secs(),
+            ));
+        }
+        drop(replicas);
+
+        let backlog = self.backlog.lock().unwrap_or_else(|err| err.into_inner());
+        info.push_str(&format!(
+            concat!(
+                "master_replid:{}\r\n",
+                "master_repl_offset:{}\r\n",
+                "repl_backlog_active:{}\r\n",
+                "repl_backlog_size:{}\r\n",
+                "repl_backlog_first_byte_offset:{}\r\n",
+                "repl_backlog_histlen:{}\r\n",
+                "sync_full:{}\r\n",
+                "sync_partial_ok:{}\r\n",
+                "sync_partial_err:{}\r\n",
+            ),
+            self.replid,
+            backlog.offset,
+            u8::from(self.recording.load(Ordering::Relaxed)),
+            self.runtime.repl_backlog_size(),
+            backlog.first_offset() + 1,
+            backlog.history.len(),
+            self.sync_full.load(Ordering::Relaxed),
+            self.sync_partial_ok.load(Ordering::Relaxed),
+            self.sync_partial_err.load(Ordering::Relaxed),
+        ));
+        info
+    }
+
+    /// Replicates `host:port` until aborted, reconnecting after failures.
+    async fn run_replica(self: Arc<Self>, host: String, port: u16, status: Arc<LinkStatus>) {
+        loop {
+            let result = self.sync_once(&host, port, &status).await;
+            status.up.store(false, Ordering::Relaxed);
+            status.sync_in_progress.store(false, Ordering::Relaxed);
+            match result {
+                Ok(()) => tracing::warn!(%host, port, "master closed the replication link"),
+                Err(err) => tracing::warn!(%host, port, error = %err, "replication failed"),
+            }
+            tokio::time::sleep(REPLICA_RETRY_DELAY).await;
+        }
+    }
+
+    /// One connection to the master: handshake, full sync, then the
+    /// command stream until the connection ends.
+    async fn sync_once(&self, host: &str, port: u16, status: &LinkStatus) -> io::Result<()> {
+        let stream = with_timeout(TcpStream::connect((host, port))).await?;
+        let mut master = BufReader::new(stream);
+
+        request(&mut master, &[b"PING"]).await?;
+        expect_simple(&mut master, "+PONG").await?;
+        let listening_port = self.listening_port.to_string();
+        request(
+            &mut master,
+            &[b"REPLCONF", b"listening-port", listening_port.as_bytes()],
+        )
+        .await?;
+        expect_simple(&mut master, "+OK").await?;
+
+        status.sync_in_progress.store(true, Ordering::Relaxed);
+        let resume = status
+            .master_replid
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
+        match &resume {
+            Some(replid) => {
+                let next = (status.offset.load(Ordering::Relaxed) + 1).to_string();
+                request(&mut master, &[b"PSYNC", replid.as_bytes(), next.as_bytes()]).await?;
+            }
+            None => request(&mut master, &[b"PSYNC", b"?", b"-1"]).await?,
+        }
+        let reply = read_line(&mut master).await?;
+        let mut offset = match reply.split(' ').collect::<Vec<_>>()[..] {
+            ["+FULLRESYNC", replid, offset] => {
+                let offset = offset.parse::<u64>().map_err(invalid_data)?;
+                // A failed load leaves a partial data set to start over from.
+                *status
+                    .master_replid
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner()) = None;
+                let keys = self.full_sync(&mut master).await?;
+                status.offset.store(offset, Ordering::Relaxed);
+                *status
+                    .master_replid
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner()) = Some(replid.to_string());
+                self.runtime.lifecycle().set_awaiting_sync(false);
+                tracing::info!(keys, offset, "full sync from master complete");
+                offset
+            }
+            ["+CONTINUE", ..] if resume.is_some() => {
+                let offset = status.offset.load(Ordering::Relaxed);
+                tracing::info!(offset, "resuming from the master's backlog");
+                offset
+            }
+            _ => return Err(invalid_data(format!("unexpected PSYNC reply '{reply}'"))),
+        };
+        status.sync_in_progress.store(false, Ordering::Relaxed);
+        status.up.store(true, Ordering::Relaxed);
+
+        let mut buffer = BytesMut::with_capacity(8 * 1024);
+        let mut parser = RespParser::new();
+        let mut ack = tokio::time::interval(REPLICA_ACK_INTERVAL);
+        loop {
+            tokio::select! {
+                read = master.read_buf(&mut buffer) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                }
+                _ = ack.tick() => {
+                    let acked = offset.to_string();
+                    request(&mut master, &[b"REPLCONF", b"ACK", acked.as_bytes()]).await?;
+                    continue;
+                }
+            }
+            loop {
+                let before = buffer.len();
+                let Some(args) = parser
+                    .parse(&mut buffer)
+                    .map_err(|err| invalid_data(format!("{err:?}")))?
+                else {
+                    break;
+                };
+                offset += (before - buffer.len()) as u64;
+                let response = self.propagate_with(&args, || {
+                    crate::server::apply_replicated_command(&args, self.engine.as_ref())
+                });
+                if response.first() == Some(&b'-') {
+                    tracing::warn!(
+                        response = %String::from_utf8_lossy(&response).trim_end(),
+                        "replicated command failed"
+                    );
+                }
+                status.offset.store(offset, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Reads a `+FULLRESYNC` payload and loads it, returning the key count.
+    async fn full_sync(&self, master: &mut BufReader<TcpStream>) -> io::Result<usize> {
+        let header = read_line(master).await?;
+        let len = header
+            .strip_prefix('$')
+            .and_then(|len| len.parse::<usize>().ok())
+            .ok_or_else(|| invalid_data(format!("unexpected snapshot header '{header}'")))?;
+        let mut payload = vec![0; len];
+        master.read_exact(&mut payload).await?;
+        let entries = snapshot::decode(&payload, SystemTime::now()).map_err(invalid_data)?;
+        self.load(&entries)?;
+        Ok(entries.len())
+    }
+
+    /// Replaces the data set with a master's snapshot.
+    fn load(&self, entries: &[hkv_engine::SnapshotEntry]) -> io::Result<()> {
+        let stale = self.engine.snapshot().map_err(io::Error::other)?;
+        for entry in stale {
+            self.engine.delete(&entry.key).map_err(io::Error::other)?;
+        }
+        for entry in entries {
+            let (key, value) = (entry.key.to_vec(), entry.value.to_vec());
+            match entry.ttl {
+                Some(ttl) => self
+                    .engine
+                    .set_with_ttl(key, value, hkv_common::TtlAfter::new(ttl)),
+                None => self.engine.set(key, value),
+            }
+            .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a connecting replica catches up before following the feed.
+enum CatchUp {
+    /// The backlog bytes after its offset.
+    Partial(Vec<u8>),
+    /// A snapshot of every key.
+    Full(Vec<hkv_engine::SnapshotEntry>),
+}
+
+/// Lists a connected replica until dropped.
+struct ReplicaGuard<'a> {
+    replicas: &'a Mutex<BTreeMap<u64, ReplicaState>>,
+    client_id: u64,
+}
+
+impl Drop for ReplicaGuard<'_> {
+    fn drop(&mut self) {
+        self.replicas
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&self.client_id);
+    }
+}
+
+/// The offset in a `REPLCONF ACK <offset>`.
+fn parse_ack(args: &[Bytes]) -> Option<u64> {
+    match args {
+        [cmd, sub, offset]
+            if cmd.eq_ignore_ascii_case(b"REPLCONF") && sub.eq_ignore_ascii_case(b"ACK") =>
+        {
+            std::str::from_utf8(offset).ok()?.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Encodes a command as a RESP array of bulk strings.
+fn encode_command<A: AsRef<[u8]>>(args: &[A]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        let arg = arg.as_ref();
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+async fn request(master: &mut BufReader<TcpStream>, args: &[&[u8]]) -> io::Result<()> {
+    with_timeout(master.get_mut().write_all(&encode_command(args))).await
+}
+
+async fn expect_simple(master: &mut BufReader<TcpStream>, expected: &str) -> io::Result<()> {
+    let reply = read_line(master).await?;
+    if reply != expected {
+        return Err(invalid_data(format!(
+            "expected '{expected}' from master, got '{reply}'"
+        )));
+    }
+    Ok(())
+}
+
+/// Reads one `\r\n`-terminated line, without the terminator.
+async fn read_line(master: &mut BufReader<TcpStream>) -> io::Result<String> {
+    let mut line = String::new();
+    if with_timeout(master.read_line(&mut line)).await? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+async fn with_timeout<T>(step: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, step)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "master did not answer in time"))?
+}
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hkv_engine::MemoryEngine;
+
+    fn args(args: &[&[u8]]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+    }
+
+    fn replication() -> Replication {
+        Replication::new(
+            Arc::new(MemoryEngine::new()),
+            Arc::new(RuntimeConfig::new()),
+            0,
+        )
+    }
+
+    #[test]
+    fn only_successful_writes_are_published_and_counted() {
+        let replication = replication();
+        let set = args(&[b"SET", b"k", b"v"]);
+
+        // Until a replica connects nothing is encoded and the offset stays put.
+        replication.propagate_with(&set, || b"+OK\r\n".to_vec());
+        assert_eq!(replication.backlog.lock().unwrap().offset, 0);
+
+        replication.recording.store(true, Ordering::Relaxed);
+        let mut feed = replication.feed.subscribe();
+        let response = replication.propagate_with(&set, || b"+OK\r\n".to_vec());
+        assert_eq!(response, b"+OK\r\n");
+        replication.propagate_with(&set, || b"-ERR engine error\r\n".to_vec());
+
+        let published = feed.try_recv().unwrap();
+        assert_eq!(&published[..], b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n");
+        assert!(feed.try_recv().is_err());
+        let backlog = replication.backlog.lock().unwrap();
+        assert_eq!(backlog.offset, published.len() as u64);
+        assert_eq!(backlog.since(0).unwrap(), &published[..]);
+        drop(backlog);
+        assert!(replication.info().contains("role:master\r\n"));
+    }
+
+    #[test]
+    fn backlog_serves_tails_still_inside_its_window() {
+        let mut backlog = Backlog::default();
+        backlog.append(b"abcd", 6);
+        backlog.append(b"efgh", 6);
+        assert_eq!(backlog.offset, 8);
+        assert_eq!(backlog.first_offset(), 2);
+        assert_eq!(backlog.since(2).unwrap(), b"cdefgh");
+        assert_eq!(backlog.since(8).unwrap(), b"");
+        assert_eq!(backlog.since(1), None);
+        assert_eq!(backlog.since(9), None);
+    }
+
+    #[test]
+    fn psync_resumes_only_this_servers_history() {
+        let replication = replication();
+        let replid = replication.replid.clone().into_bytes();
+        let psync = |replid: &[u8], offset: &[u8]| args(&[b"PSYNC", replid, offset]);
+        assert_eq!(replication.resume_point(&psync(&replid, b"11")), Some(10));
+        assert_eq!(replication.resume_point(&psync(b"?", b"-1")), None);
+        assert_eq!(replication.resume_point(&psync(b"other", b"11")), None);
+        assert_eq!(replication.resume_point(&psync(&replid, b"0")), None);
+        assert_eq!(parse_ack(&args(&[b"replconf", b"ack", b"42"])), Some(42));
+        assert_eq!(parse_ack(&args(&[b"REPLCONF", b"GETACK", b"*"])), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! # Replies
+//!
+//! A reply as RESP bytes, with large bulk values kept as the engine's own
+//! refcounted slices instead of being copied into the output buffer. The
+//! writer hands the framing bytes and those slices to `write_vectored`
+//! together.
+//!
+//! ## Design Principles
+//!
+//! 1. **Zero-Copy Values**: Bulk values of at least `VECTORED_VALUE_MIN`
+//!    bytes are held as `Arc<[u8]>` and written straight from storage.
+//! 2. **Copy When Small**: Smaller values are copied inline; below that size
+//!    an extra `IoSlice` costs more than the copy it saves, so a reply of
+//!    many tiny elements is written as one buffer.
+//! 3. **Same Bytes Either Way**: However a reply is split, it writes the
+//!    bytes the flattened reply from `into_vec` holds, and a writer without
+//!    vectored support is given exactly that.
+
+use std::io::{self, IoSlice};
+use std::sync::Arc;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Smallest bulk value written from its own slice rather than copied.
+pub const VECTORED_VALUE_MIN: usize = 1024;
+
+/// Most slices passed to one `write_vectored` call, Linux's `IOV_MAX`.
+const MAX_IO_SLICES: usize = 1024;
+
+/// RESP bytes, split into an inline buffer and shared values written in
+/// between.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reply {
+    inline: Vec<u8>,
+    /// Values in order, each written before `inline[offset..]`.
+    shared: Vec<(usize, Arc<[u8]>)>,
+}
+
+impl Reply {
+    /// An array reply of bulk values, with `None` as a null bulk string.
+    pub fn bulk_array<I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = Option<Arc<[u8]>>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        let mut reply = Reply::from(format!("*{}\r\n", values.len()).into_bytes());
+        for value in values {
+            match value {
+                Some(value) => reply.push_bulk(value),
+                None => reply.inline.extend_from_slice(b"$-1\r\n"),
+            }
+        }
+        reply
+    }
+
+    /// A single bulk string reply.
+    pub fn bulk(value: Arc<[u8]>) -> Self {
+        let mut reply = Reply::default();
+        reply.push_bulk(value);
+        reply
+    }
+
+    fn push_bulk(&mut self, value: Arc<[u8]>) {
+        self.inline
+            .extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+        if value.len() >= VECTORED_VALUE_MIN {
+            self.shared.push((self.inline.len(), value));
+        } else {
+            self.inline.extend_from_slice(&value);
+        }
+        self.inline.extend_from_slice(b"\r\n");
+    }
+
+    /// Appends `other`, keeping its shared values shared.
+    pub fn append(&mut self, other: Reply) {
+        let base = self.inline.len();
+        self.shared.extend(
+            other
+                .shared
+                .into_iter()
+                .map(|(offset, value)| (base + offset, value)),
+        );
+        self.inline.extend_from_slice(&other.inline);
+    }
+
+    /// Total bytes the reply writes.
+    pub fn len(&self) -> usize {
+        self.inline.len()
+            + self
+                .shared
+                .iter()
+                .map(|(_, value)| value.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inline.is_empty() && self.shared.is_empty()
+    }
+
+    /// Bytes copied into the inline buffer rather than shared.
+    pub fn copied_len(&self) -> usize {
+        self.inline.len()
+    }
+
+    /// Whether the reply is a RESP error.
+    pub fn is_error(&self) -> bool {
+        // A shared value always follows a bulk header, so never leads.
+        self.inline.first() == Some(&b'-')
+    }
+
+    /// The leading inline bytes, which hold the whole first line.
+    pub(crate) fn head(&self) -> &[u8] {
+        let end = self
+            .shared
+            .first()
+            .map_or(self.inline.len(), |(offset, _)| *offset);
+        &self.inline[..end]
+    }
+
+    pub fn clear(&mut self) {
+        self.inline.clear();
+        self.shared.clear();
+    }
+
+    /// The reply's bytes in order, as slices of the inline buffer and the
+    /// shared values.
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        let tail = self.shared.last().map_or(0, |(offset, _)| *offset);
+        let mut start = 0;
+        self.shared
+            .iter()
+            .flat_map(move |(offset, value)| {
+                let inline = &self.inline[start..*offset];
+                start = *offset;
+                [inline, &value[..]]
+            })
+            .chain(std::iter::once(&self.inline[tail..]))
+            .filter(|segment| !segment.is_empty())
+    }
+
+    /// The reply as one contiguous buffer.
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.shared.is_empty() {
+            return self.inline;
+        }
+        let mut buf = Vec::with_capacity(self.len());
+        for segment in self.segments() {
+            buf.extend_from_slice(segment);
+        }
+        buf
+    }
+}
+
+impl From<Vec<u8>> for Reply {
+    fn from(inline: Vec<u8>) -> Self {
+        Reply {
+            inline,
+            shared: Vec::new(),
+        }
+    }
+}
+
+/// Writes every byte of `reply` to `out`, vectored when it holds shared
+/// values and `out` supports it, and copied into one buffer otherwise.
+pub async fn write_reply<W>(out: &mut W, reply: &Reply) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if reply.shared.is_empty() {
+        return out.write_all(&reply.inline).await;
+    }
+    if !out.is_write_vectored() {
+        return out.write_all(&reply.clone().into_vec()).await;
+    }
+    let mut slices: Vec<IoSlice<'_>> = reply.segments().map(IoSlice::new).collect();
+    let mut remaining = &mut slices[..];
+    while !remaining.is_empty() {
+        let batch = remaining.len().min(MAX_IO_SLICES);
+        let written = out.write_vectored(&remaining[..batch]).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        // A partial write may stop inside a slice; resume from that byte.
+        IoSlice::advance_slices(&mut remaining, written);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use super::*;
+
+    /// The reply the server built before replies were vectored: every
+    /// value copied into one buffer.
+    fn flattened(values: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut buf = format!("*{}\r\n", values.len()).into_bytes();
+        for value in values {
+            match value {
+                Some(value) => {
+                    buf.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+                    buf.extend_from_slice(value);
+                    buf.extend_from_slice(b"\r\n");
+                }
+                None => buf.extend_from_slice(b"$-1\r\n"),
+            }
+        }
+        buf
+    }
+
+    /// Accepts at most `limit` bytes per write, vectored or not.
+    struct Trickle {
+        written: Vec<u8>,
+        limit: usize,
+        vectored: bool,
+        vectored_calls: usize,
+    }
+
+    impl Trickle {
+        fn new(limit: usize, vectored: bool) -> Self {
+            Trickle {
+                written: Vec::new(),
+                limit,
+                vectored,
+                vectored_calls: 0,
+            }
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let len = buf.len().min(self.limit);
+            self.written.extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.vectored_calls += 1;
+            let mut budget = self.limit;
+            for buf in bufs {
+                let len = buf.len().min(budget);
+                self.written.extend_from_slice(&buf[..len]);
+                budget -= len;
+            }
+            Poll::Ready(Ok(self.limit - budget))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.vectored
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn sample_values() -> Vec<Option<Vec<u8>>> {
+        (0..100)
+            .map(|i| match i % 5 {
+                0 => None,
+                1 => Some(b"small".to_vec()),
+                2 => Some(Vec::new()),
+                _ => Some(vec![b'a' + (i % 26) as u8; 4096 + i]),
+            })
+            .collect()
+    }
+
+    fn sample_reply(values: &[Option<Vec<u8>>]) -> Reply {
+        Reply::bulk_array(values.iter().map(|value| value.as_deref().map(Arc::from)))
+    }
+
+    #[tokio::test]
+    async fn vectored_writes_emit_the_flattened_bytes() {
+        let values = sample_values();
+        let expected = flattened(&values.iter().map(Option::as_deref).collect::<Vec<_>>());
+        let reply = sample_reply(&values);
+        assert!(reply.copied_len() < expected.len() / 10);
+        assert_eq!(reply.len(), expected.len());
+        assert_eq!(reply.clone().into_vec(), expected);
+
+        // Partial writes that stop mid-header and mid-value resume exactly.
+        for limit in [1, 7, 4096, 100_000, usize::MAX] {
+            let mut out = Trickle::new(limit, true);
+            write_reply(&mut out, &reply).await.unwrap();
+            assert_eq!(out.written, expected, "limit {limit}");
+            assert!(out.vectored_calls > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn writers_without_vectored_support_get_one_buffer() {
+        let values = sample_values();
+        let reply = sample_reply(&values);
+        let mut out = Trickle::new(1000, false);
+        write_reply(&mut out, &reply).await.unwrap();
+        assert_eq!(out.written, reply.clone().into_vec());
+        assert_eq!(out.vectored_calls, 0);
+    }
+
+    #[test]
+    fn small_values_are_copied_and_appends_keep_offsets() {
+        let small = Reply::bulk(Arc::from(&b"v"[..]));
+        assert!(small.shared.is_empty());
+        assert_eq!(small.clone().into_vec(), b"$1\r\nv\r\n");
+
+        let large: Arc<[u8]> = Arc::from(vec![b'x'; VECTORED_VALUE_MIN]);
+        let mut batch = Reply::from(b"+OK\r\n".to_vec());
+        batch.append(Reply::bulk(large.clone()));
+        batch.append(small);
+        let mut expected = b"+OK\r\n$1024\r\n".to_vec();
+        expected.extend_from_slice(&large);
+        expected.extend_from_slice(b"\r\n$1\r\nv\r\n");
+        assert_eq!(batch.head(), b"+OK\r\n$1024\r\n");
+        assert_eq!(batch.into_vec(), expected);
+        assert!(Reply::from(b"-ERR x\r\n".to_vec()).is_error());
+    }
+}
@@ -0,0 +1,846 @@
+//! # Lua Scripting
+//!
+//! `EVAL`, `EVALSHA` and `SCRIPT` backed by Lua 5.4, built with the
+//! `scripting` feature.
+//!
+//! ## Design Principles
+//!
+//! 1. **One Script at a Time**: Client commands hold the script gate shared
+//!    while they run and a script holds it exclusively, so no other
+//!    connection sees a script half done.
+//! 2. **Busy, Not Stuck**: Once a script passes `lua-time-limit`, commands
+//!    waiting on the gate get `-BUSY` instead of waiting, and `SCRIPT KILL`
+//!    stops the script unless it has already written.
+//! 3. **Fresh State per Script**: Each run gets a new Lua state with only the
+//!    base, table, string and math libraries and no file access, so scripts
+//!    cannot leak globals into each other or reach the host.
+//! 4. **Commands Through the Table**: `redis.call` and `redis.pcall` run
+//!    commands like a client would, minus `noscript` ones, so read-only mode,
+//!    metrics and replication apply to each call.
+//! 5. **Redis Conversions**: Replies become Lua values and return values
+//!    become replies by Redis's rules: integers and bulk strings map to
+//!    numbers and strings, arrays to sequences, nil to `false`, and status
+//!    and error replies to `{ok=...}` and `{err=...}` tables.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, TryLockError};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use mlua::{Function, HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+
+use crate::server::{CommandFlag, command_spec};
+
+/// Reply to commands arriving while a script is past its time limit.
+const BUSY_REPLY: &[u8] =
+    b"-BUSY HybridKV is busy running a script. You can only call SCRIPT KILL.\r\n";
+
+/// Reply to `EVALSHA` with a digest that was never loaded.
+const NOSCRIPT_REPLY: &[u8] = b"-NOSCRIPT No matching script. Please use EVAL.\r\n";
+
+/// Reply to `SCRIPT KILL` when no script runs.
+const NOTBUSY_REPLY: &[u8] = b"-NOTBUSY No scripts in execution right now.\r\n";
+
+/// Reply to `SCRIPT KILL` once the script has written.
+const UNKILLABLE_REPLY: &[u8] = b"-UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.\r\n";
+
+/// Error a killed script fails with.
+const KILLED_ERROR: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+/// Lua instructions between checks of the time limit and `SCRIPT KILL`.
+const HOOK_INSTRUCTIONS: u32 = 10_000;
+
+/// Pause before a command tries the gate again while a script runs.
+const GATE_RETRY: Duration = Duration::from_millis(1);
+
+/// Defines `redis.call` on top of `redis.pcall`, raising the errors that
+/// `redis.pcall` returns.
+const PRELUDE: &str = r#"
+redis.call = function(...)
+    local reply = redis.pcall(...)
+    if type(reply) == "table" and reply.err ~= nil then
+        error(reply)
+    end
+    return reply
+end
+"#;
+
+/// State of the script holding the gate.
+#[derive(Debug, Default)]
+struct RunState {
+    running: AtomicBool,
+    /// Set once the script passes its time limit.
+    busy: AtomicBool,
+    /// Set by `SCRIPT KILL`; the script fails at its next check.
+    killed: AtomicBool,
+    /// Set before the script's first write command.
+    wrote: AtomicBool,
+}
+
+/// The script cache and gate shared by every connection.
+#[derive(Debug, Default)]
+pub struct Scripting {
+    /// Bodies by lowercase hex SHA-1; kept until `SCRIPT FLUSH`.
+    scripts: Mutex<HashMap<String, Arc<[u8]>>>,
+    gate: RwLock<()>,
+    run: Arc<RunState>,
+}
+
+impl Scripting {
+    /// An empty cache with no script running.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the client command `name` with `execute` once no script holds
+    /// the gate; `-BUSY` instead while a script is past its time limit.
+    ///
+    /// `EVAL` and `EVALSHA` take the gate themselves, and `SCRIPT` runs
+    /// without it so `SCRIPT KILL` reaches a running script.
+    pub fn run_command<R: From<Vec<u8>>>(&self, name: &[u8], execute: impl FnOnce() -> R) -> R {
+        if ["eval", "evalsha", "script"]
+            .iter()
+            .any(|bypass| bypass.as_bytes().eq_ignore_ascii_case(name))
+        {
+            return execute();
+        }
+        loop {
+            match self.gate.try_read() {
+                Ok(_turn) => return execute(),
+                Err(TryLockError::Poisoned(err)) => {
+                    let _turn = err.into_inner();
+                    return execute();
+                }
+                Err(TryLockError::WouldBlock) if self.run.busy.load(Ordering::Acquire) => {
+                    return BUSY_REPLY.to_vec().into();
+                }
+                Err(TryLockError::WouldBlock) => std::thread::sleep(GATE_RETRY),
+            }
+        }
+    }
+
+    /// `EVAL body numkeys key... arg...`: caches `body`, then runs it with
+    /// `call` executing each `redis.call`.
+    pub fn eval(
+        &self,
+        body: &[u8],
+        args: &[Bytes],
+        time_limit: Duration,
+        call: &dyn Fn(&[Bytes]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let sha = self.load(body);
+        self.run(&sha, body, args, time_limit, call)
+    }
+
+    /// `EVALSHA sha numkeys key... arg...`: runs a cached script.
+    pub fn evalsha(
+        &self,
+        sha: &[u8],
+        args: &[Bytes],
+        time_limit: Duration,
+        call: &dyn Fn(&[Bytes]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let sha = String::from_utf8_lossy(sha).to_ascii_lowercase();
+        let body = self.lock_scripts().get(&sha).cloned();
+        match body {
+            Some(body) => self.run(&sha, &body, args, time_limit, call),
+            None => NOSCRIPT_REPLY.to_vec(),
+        }
+    }
+
+    /// Caches `body` and returns its SHA-1 as lowercase hex.
+    pub fn load(&self, body: &[u8]) -> String {
+        let sha = sha1_smol::Sha1::from(body).digest().to_string();
+        self.lock_scripts()
+            .entry(sha.clone())
+            .or_insert_with(|| body.into());
+        sha
+    }
+
+    /// Whether a script with this SHA-1 is cached.
+    pub fn exists(&self, sha: &[u8]) -> bool {
+        let sha = String::from_utf8_lossy(sha).to_ascii_lowercase();
+        self.lock_scripts().contains_key(&sha)
+    }
+
+    /// Empties the cache.
+    pub fn flush(&self) {
+        self.lock_scripts().clear();
+    }
+
+    /// `SCRIPT KILL`: stops the running script unless it has written.
+    pub fn kill(&self) -> Vec<u8> {
+        if !self.run.running.load(Ordering::Acquire) {
+            return NOTBUSY_REPLY.to_vec();
+        }
+        if self.run.wrote.load(Ordering::Acquire) {
+            return UNKILLABLE_REPLY.to_vec();
+        }
+        self.run.killed.store(true, Ordering::Release);
+        b"+OK\r\n".to_vec()
+    }
+
+    fn lock_scripts(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<[u8]>>> {
+        self.scripts.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn run(
+        &self,
+        sha: &str,
+        body: &[u8],
+        args: &[Bytes],
+        time_limit: Duration,
+        call: &dyn Fn(&[Bytes]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let (keys, argv) = match split_keys(args) {
+            Ok(split) => split,
+            Err(reply) => return reply,
+        };
+        if self.run.busy.load(Ordering::Acquire) {
+            return BUSY_REPLY.to_vec();
+        }
+        let _turn = self.gate.write().unwrap_or_else(|err| err.into_inner());
+        for flag in [&self.run.busy, &self.run.killed, &self.run.wrote] {
+            flag.store(false, Ordering::Release);
+        }
+        self.run.running.store(true, Ordering::Release);
+
+        let result = self.execute(sha, body, keys, argv, time_limit, call);
+        let reply = if self.run.killed.load(Ordering::Acquire) {
+            error_reply(KILLED_ERROR.as_bytes())
+        } else {
+            result.unwrap_or_else(|err| {
+                error_reply(format!("ERR Error running script: {}", root_message(&err)).as_bytes())
+            })
+        };
+
+        self.run.running.store(false, Ordering::Release);
+        self.run.busy.store(false, Ordering::Release);
+        reply
+    }
+
+    fn execute(
+        &self,
+        sha: &str,
+        body: &[u8],
+        keys: &[Bytes],
+        argv: &[Bytes],
+        time_limit: Duration,
+        call: &dyn Fn(&[Bytes]) -> Vec<u8>,
+    ) -> mlua::Result<Vec<u8>> {
+        let lua = Lua::new_with(
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+            LuaOptions::new(),
+        )?;
+        let globals = lua.globals();
+        for unsafe_global in ["dofile", "loadfile"] {
+            globals.set(unsafe_global, Value::Nil)?;
+        }
+        globals.set("KEYS", string_sequence(&lua, keys)?)?;
+        globals.set("ARGV", string_sequence(&lua, argv)?)?;
+
+        let run = Arc::clone(&self.run);
+        let started = Instant::now();
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+            move |_, _| {
+                if run.killed.load(Ordering::Acquire) {
+                    return Err(mlua::Error::runtime(KILLED_ERROR));
+                }
+                if started.elapsed() >= time_limit && !run.busy.swap(true, Ordering::AcqRel) {
+                    tracing::warn!(
+                        limit_ms = time_limit.as_millis() as u64,
+                        "script is past lua-time-limit; other clients now get -BUSY"
+                    );
+                }
+                Ok(())
+            },
+        );
+
+        let function = match lua.load(body).set_name("@user_script").into_function() {
+            Ok(function) => function,
+            Err(err) => {
+                let message = format!("ERR Error compiling script: {}", root_message(&err));
+                return Ok(error_reply(message.as_bytes()));
+            }
+        };
+
+        lua.scope(|scope| {
+            let redis = lua.create_table()?;
+            redis.set(
+                "pcall",
+                scope.create_function(|lua, args: MultiValue| self.call(lua, args, call))?,
+            )?;
+            redis.set(
+                "error_reply",
+                lua.create_function(|lua, message: mlua::String| {
+                    reply_table(lua, "err", message.as_bytes())
+                })?,
+            )?;
+            redis.set(
+                "status_reply",
+                lua.create_function(|lua, message: mlua::String| {
+                    reply_table(lua, "ok", message.as_bytes())
+                })?,
+            )?;
+            globals.set("redis", redis)?;
+            lua.load(PRELUDE).set_name("@prelude").exec()?;
+
+            // Lua's own pcall keeps `{err=...}` tables raised by
+            // `redis.call` intact, where an mlua error would stringify them.
+            let pcall: Function = globals.get("pcall")?;
+            let mut results = pcall.call::<_, MultiValue>(function)?.into_iter();
+            let outcome = results.next();
+            let value = results.next().unwrap_or(Value::Nil);
+            Ok(match outcome {
+                Some(Value::Boolean(true)) => lua_to_resp(&value),
+                _ => script_error(sha, &value),
+            })
+        })
+    }
+
+    /// `redis.pcall`: runs one command and returns its reply as a Lua value,
+    /// with failures as `{err=...}` tables.
+    fn call<'lua>(
+        &self,
+        lua: &'lua Lua,
+        args: MultiValue<'lua>,
+        call: &dyn Fn(&[Bytes]) -> Vec<u8>,
+    ) -> mlua::Result<Value<'lua>> {
+        let mut command = Vec::with_capacity(args.len());
+        for arg in args {
+            let arg = match arg {
+                Value::String(arg) => Bytes::copy_from_slice(arg.as_bytes()),
+                Value::Integer(arg) => Bytes::from(arg.to_string()),
+                Value::Number(arg) => Bytes::from(format_number(arg)),
+                _ => {
+                    return reply_table(
+                        lua,
+                        "err",
+                        b"ERR Lua redis lib command arguments must be strings or integers",
+                    );
+                }
+            };
+            command.push(arg);
+        }
+        let Some(name) = command.first() else {
+            return reply_table(
+                lua,
+                "err",
+                b"ERR Please specify at least one argument for this redis lib call",
+            );
+        };
+        let Some(spec) = command_spec(name) else {
+            return reply_table(lua, "err", b"ERR Unknown Redis command called from script");
+        };
+        if spec.has(CommandFlag::Noscript) {
+            return reply_table(
+                lua,
+                "err",
+                b"ERR This Redis command is not allowed from script",
+            );
+        }
+        if spec.has(CommandFlag::Write) {
+            if self.run.killed.load(Ordering::Acquire) {
+                return Err(mlua::Error::runtime(KILLED_ERROR));
+            }
+            self.run.wrote.store(true, Ordering::Release);
+        }
+        resp_to_lua(lua, &call(&command))
+    }
+}
+
+/// Splits `numkeys key... arg...` into keys and arguments.
+fn split_keys(args: &[Bytes]) -> Result<(&[Bytes], &[Bytes]), Vec<u8>> {
+    let Some((numkeys, rest)) = args.split_first() else {
+        return Err(error_reply(b"ERR wrong number of arguments"));
+    };
+    let numkeys = std::str::from_utf8(numkeys)
+        .ok()
+        .and_then(|numkeys| numkeys.parse::<i64>().ok())
+        .ok_or_else(|| error_reply(b"ERR value is not an integer or out of range"))?;
+    if numkeys < 0 {
+        return Err(error_reply(b"ERR Number of keys can't be negative"));
+    }
+    match usize::try_from(numkeys) {
+        Ok(numkeys) if numkeys <= rest.len() => Ok(rest.split_at(numkeys)),
+        _ => Err(error_reply(
+            b"ERR Number of keys can't be greater than number of args",
+        )),
+    }
+}
+
+/// Renders a Lua float argument as Redis does: whole numbers without a
+/// fraction.
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < 1e17 {
+        format!("{}", number as i64)
+    } else {
+        number.to_string()
+    }
+}
+
+fn string_sequence<'lua>(lua: &'lua Lua, items: &[Bytes]) -> mlua::Result<Table<'lua>> {
+    let strings = items
+        .iter()
+        .map(|item| lua.create_string(item))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    lua.create_sequence_from(strings)
+}
+
+/// `{field=message}`, the shape of status and error replies in Lua.
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, message: &[u8]) -> mlua::Result<Value<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, lua.create_string(message)?)?;
+    Ok(Value::Table(table))
+}
+
+/// The reply for a script that raised `value`.
+fn script_error(sha: &str, value: &Value<'_>) -> Vec<u8> {
+    if let Value::Table(table) = value
+        && let Ok(Value::String(err)) = table.raw_get::<_, Value>("err")
+    {
+        return error_reply(err.as_bytes());
+    }
+    let message = match value {
+        Value::String(message) => message.to_string_lossy().into_owned(),
+        Value::Error(err) => root_message(err),
+        other => format!("{other:?}"),
+    };
+    error_reply(format!("ERR {message} script: {sha}").as_bytes())
+}
+
+/// The first line of the innermost cause of `err`, without mlua's
+/// wrapping and traceback.
+fn root_message(err: &mlua::Error) -> String {
+    let message = match err {
+        mlua::Error::CallbackError { cause, .. } => return root_message(cause),
+        mlua::Error::RuntimeError(message) | mlua::Error::SyntaxError { message, .. } => {
+            message.clone()
+        }
+        other => other.to_string(),
+    };
+    message.lines().next().unwrap_or_default().to_owned()
+}
+
+/// An error reply carrying `message`, with line breaks replaced so it stays
+/// one RESP line.
+fn error_reply(message: &[u8]) -> Vec<u8> {
+    let mut reply = Vec::with_capacity(message.len() + 3);
+    reply.push(b'-');
+    reply.extend(message.iter().map(|&byte| match byte {
+        b'\r' | b'\n' => b' ',
+        byte => byte,
+    }));
+    reply.extend_from_slice(b"\r\n");
+    reply
+}
+
+/// Converts a script's return value to a reply.
+fn lua_to_resp(value: &Value<'_>) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_lua_value(value, &mut out);
+    out
+}
+
+fn write_lua_value(value: &Value<'_>, out: &mut Vec<u8>) {
+    match value {
+        Value::Boolean(true) => out.extend_from_slice(b":1\r\n"),
+        Value::Integer(number) => out.extend_from_slice(format!(":{number}\r\n").as_bytes()),
+        // Truncated as in Redis; scripts return strings to keep fractions.
+        Value::Number(number) => {
+            out.extend_from_slice(format!(":{}\r\n", *number as i64).as_bytes())
+        }
+        Value::String(string) => {
+            let bytes = string.as_bytes();
+            out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+            out.extend_from_slice(bytes);
+            out.extend_from_slice(b"\r\n");
+        }
+        Value::Table(table) => write_lua_table(table, out),
+        _ => out.extend_from_slice(b"$-1\r\n"),
+    }
+}
+
+/// A table with an `err` or `ok` string is an error or status reply;
+/// otherwise its sequence part, up to the first nil, is an array.
+fn write_lua_table(table: &Table<'_>, out: &mut Vec<u8>) {
+    if let Ok(Value::String(err)) = table.raw_get::<_, Value>("err") {
+        out.extend_from_slice(&error_reply(err.as_bytes()));
+        return;
+    }
+    if let Ok(Value::String(ok)) = table.raw_get::<_, Value>("ok") {
+        out.push(b'+');
+        out.extend(ok.as_bytes().iter().map(|&byte| match byte {
+            b'\r' | b'\n' => b' ',
+            byte => byte,
+        }));
+        out.extend_from_slice(b"\r\n");
+        return;
+    }
+    let mut items = Vec::new();
+    for index in 1.. {
+        match table.raw_get::<_, Value>(index) {
+            Ok(Value::Nil) | Err(_) => break,
+            Ok(item) => items.push(item),
+        }
+    }
+    out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+    for item in &items {
+        write_lua_value(item, out);
+    }
+}
+
+/// Converts a command reply to the Lua value `redis.pcall` returns.
+fn resp_to_lua<'lua>(lua: &'lua Lua, reply: &[u8]) -> mlua::Result<Value<'lua>> {
+    let mut rest = reply;
+    read_reply(lua, &mut rest)
+}
+
+fn read_reply<'lua>(lua: &'lua Lua, input: &mut &[u8]) -> mlua::Result<Value<'lua>> {
+    let malformed = || mlua::Error::runtime("ERR malformed reply from command");
+    let line_end = input
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .ok_or_else(malformed)?;
+    let (&kind, line) = input[..line_end].split_first().ok_or_else(malformed)?;
+    *input = &input[line_end + 2..];
+    let number = || {
+        std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.parse::<i64>().ok())
+            .ok_or_else(malformed)
+    };
+    match kind {
+        b'+' => reply_table(lua, "ok", line),
+        b'-' => reply_table(lua, "err", line),
+        b':' => Ok(Value::Integer(number()?)),
+        b'$' => {
+            let Ok(len) = usize::try_from(number()?) else {
+                return Ok(Value::Boolean(false));
+            };
+            if input.len() < len + 2 {
+                return Err(malformed());
+            }
+            let string = lua.create_string(&input[..len])?;
+            *input = &input[len + 2..];
+            Ok(Value::String(string))
+        }
+        b'*' => {
+            let Ok(len) = usize::try_from(number()?) else {
+                return Ok(Value::Boolean(false));
+            };
+            let table = lua.create_table_with_capacity(len, 0)?;
+            for index in 1..=len {
+                table.raw_set(index, read_reply(lua, input)?)?;
+            }
+            Ok(Value::Table(table))
+        }
+        _ => Err(malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(args: &[&[u8]]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+    }
+
+    /// Evaluates `body` with `args`, answering every call with `reply`.
+    fn eval_with(body: &str, args: &[&[u8]], reply: &[u8]) -> Vec<u8> {
+        Scripting::new().eval(
+            body.as_bytes(),
+            &bytes(args),
+            Duration::from_secs(5),
+            &|_| reply.to_vec(),
+        )
+    }
+
+    fn eval(body: &str) -> Vec<u8> {
+        eval_with(body, &[b"0"], b"+OK\r\n")
+    }
+
+    #[test]
+    fn lua_values_convert_to_replies() {
+        assert_eq!(eval("return 42"), b":42\r\n");
+        assert_eq!(eval("return 3.99"), b":3\r\n");
+        assert_eq!(eval("return -2.5"), b":-2\r\n");
+        assert_eq!(eval("return 'hi'"), b"$2\r\nhi\r\n");
+        assert_eq!(eval("return true"), b":1\r\n");
+        assert_eq!(eval("return false"), b"$-1\r\n");
+        assert_eq!(eval("return nil"), b"$-1\r\n");
+        assert_eq!(eval("return"), b"$-1\r\n");
+        assert_eq!(eval("return {}"), b"*0\r\n");
+        assert_eq!(
+            eval("return {1, 'two', {3}, true}"),
+            b"*4\r\n:1\r\n$3\r\ntwo\r\n*1\r\n:3\r\n:1\r\n"
+        );
+        // The array stops at the first nil.
+        assert_eq!(eval("return {1, nil, 3}"), b"*1\r\n:1\r\n");
+        assert_eq!(eval("return {ok = 'FINE'}"), b"+FINE\r\n");
+        assert_eq!(eval("return {err = 'BAD thing'}"), b"-BAD thing\r\n");
+        assert_eq!(eval("return redis.status_reply('PONG')"), b"+PONG\r\n");
+        assert_eq!(
+            eval("return redis.error_reply('ERR my\\nerror')"),
+            b"-ERR my error\r\n"
+        );
+        assert_eq!(
+            eval("return {redis.status_reply('A'), redis.error_reply('E')}"),
+            b"*2\r\n+A\r\n-E\r\n"
+        );
+        assert_eq!(eval("return function() end"), b"$-1\r\n");
+    }
+
+    #[test]
+    fn replies_convert_to_lua_values() {
+        let kind = |reply: &[u8]| {
+            eval_with(
+                "local r = redis.pcall('GET', 'k') \
+                 if type(r) == 'table' then \
+                   return {type(r), r.ok or r.err or #r} \
+                 end \
+                 return {type(r), tostring(r)}",
+                &[b"0"],
+                reply,
+            )
+        };
+        assert_eq!(kind(b":7\r\n"), b"*2\r\n$6\r\nnumber\r\n$1\r\n7\r\n");
+        assert_eq!(
+            kind(b"$3\r\nabc\r\n"),
+            b"*2\r\n$6\r\nstring\r\n$3\r\nabc\r\n"
+        );
+        assert_eq!(kind(b"$-1\r\n"), b"*2\r\n$7\r\nboolean\r\n$5\r\nfalse\r\n");
+        assert_eq!(kind(b"*-1\r\n"), b"*2\r\n$7\r\nboolean\r\n$5\r\nfalse\r\n");
+        assert_eq!(kind(b"+OK\r\n"), b"*2\r\n$5\r\ntable\r\n$2\r\nOK\r\n");
+        assert_eq!(
+            kind(b"-ERR nope\r\n"),
+            b"*2\r\n$5\r\ntable\r\n$8\r\nERR nope\r\n"
+        );
+        assert_eq!(
+            kind(b"*2\r\n:1\r\n$1\r\nx\r\n"),
+            b"*2\r\n$5\r\ntable\r\n:2\r\n"
+        );
+        // Replies round-trip through a script unchanged.
+        for reply in [
+            &b":-3\r\n"[..],
+            b"$0\r\n\r\n",
+            b"*3\r\n$1\r\na\r\n*1\r\n:2\r\n$-1\r\n",
+            b"+QUEUED\r\n",
+        ] {
+            assert_eq!(
+                eval_with("return redis.call('GET', 'k')", &[b"0"], reply),
+                reply
+            );
+        }
+    }
+
+    #[test]
+    fn keys_and_argv_are_passed_as_strings() {
+        assert_eq!(
+            eval_with(
+                "return {#KEYS, #ARGV, KEYS[1], ARGV[2]}",
+                &[b"1", b"k", b"a", b"b"],
+                b"+OK\r\n"
+            ),
+            b"*4\r\n:1\r\n:2\r\n$1\r\nk\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            eval_with("return 1", &[b"-1"], b"+OK\r\n"),
+            b"-ERR Number of keys can't be negative\r\n"
+        );
+        assert_eq!(
+            eval_with("return 1", &[b"2", b"k"], b"+OK\r\n"),
+            b"-ERR Number of keys can't be greater than number of args\r\n"
+        );
+        assert_eq!(
+            eval_with("return 1", &[b"x"], b"+OK\r\n"),
+            b"-ERR value is not an integer or out of range\r\n"
+        );
+    }
+
+    #[test]
+    fn call_raises_errors_and_pcall_returns_them() {
+        let scripting = Scripting::new();
+        let calls = Mutex::new(Vec::new());
+        let call = |args: &[Bytes]| {
+            calls.lock().unwrap().push(args.to_vec());
+            b"-ERR boom\r\n".to_vec()
+        };
+        let eval = |body: &str| {
+            scripting.eval(
+                body.as_bytes(),
+                &bytes(&[b"0"]),
+                Duration::from_secs(5),
+                &call,
+            )
+        };
+        assert_eq!(eval("redis.call('DEL', 'k') return 1"), b"-ERR boom\r\n");
+        assert_eq!(
+            eval("local r = redis.pcall('DEL', 'k') return r.err"),
+            b"$8\r\nERR boom\r\n"
+        );
+        assert_eq!(
+            eval("return redis.pcall('DEL', 1, 2.0, 2.5)"),
+            b"-ERR boom\r\n"
+        );
+        assert_eq!(
+            calls.lock().unwrap().last().unwrap(),
+            &bytes(&[b"DEL", b"1", b"2", b"2.5"])
+        );
+
+        // Errors raised before dispatch never reach `call`.
+        let dispatched = calls.lock().unwrap().len();
+        assert_eq!(
+            eval("return redis.pcall('NOSUCH')"),
+            b"-ERR Unknown Redis command called from script\r\n"
+        );
+        assert_eq!(
+            eval("return redis.call('CONFIG', 'GET', 'timeout')"),
+            b"-ERR This Redis command is not allowed from script\r\n"
+        );
+        assert_eq!(
+            eval("return redis.call('EVAL', 'return 1', '0')"),
+            b"-ERR This Redis command is not allowed from script\r\n"
+        );
+        assert_eq!(
+            eval("return redis.pcall('GET', {})"),
+            b"-ERR Lua redis lib command arguments must be strings or integers\r\n"
+        );
+        assert_eq!(
+            eval("return redis.pcall()"),
+            b"-ERR Please specify at least one argument for this redis lib call\r\n"
+        );
+        assert_eq!(calls.lock().unwrap().len(), dispatched);
+    }
+
+    #[test]
+    fn script_failures_name_the_script() {
+        let reply = eval("return nosuch.field");
+        let sha = sha1_smol::Sha1::from("return nosuch.field")
+            .digest()
+            .to_string();
+        let reply = String::from_utf8(reply).unwrap();
+        assert!(reply.starts_with("-ERR user_script:1: "), "{reply}");
+        assert!(reply.ends_with(&format!(" script: {sha}\r\n")), "{reply}");
+
+        let reply = String::from_utf8(eval("return +")).unwrap();
+        assert!(
+            reply.starts_with("-ERR Error compiling script: user_script:1:"),
+            "{reply}"
+        );
+        assert_eq!(eval("return dofile"), b"$-1\r\n");
+        assert_eq!(eval("return io"), b"$-1\r\n");
+    }
+
+    #[test]
+    fn scripts_are_cached_by_sha() {
+        let scripting = Scripting::new();
+        let sha = scripting.load(b"return ARGV[1]");
+        assert_eq!(
+            sha,
+            sha1_smol::Sha1::from("return ARGV[1]").digest().to_string()
+        );
+        assert!(scripting.exists(sha.to_ascii_uppercase().as_bytes()));
+        let run = |sha: &[u8]| {
+            scripting.evalsha(sha, &bytes(&[b"0", b"v"]), Duration::from_secs(5), &|_| {
+                Vec::new()
+            })
+        };
+        assert_eq!(run(sha.as_bytes()), b"$1\r\nv\r\n");
+        scripting.flush();
+        assert!(!scripting.exists(sha.as_bytes()));
+        assert_eq!(run(sha.as_bytes()), NOSCRIPT_REPLY);
+
+        // EVAL caches what it runs.
+        scripting.eval(
+            b"return 1",
+            &bytes(&[b"0"]),
+            Duration::from_secs(5),
+            &|_| Vec::new(),
+        );
+        assert!(
+            scripting.exists(
+                sha1_smol::Sha1::from("return 1")
+                    .digest()
+                    .to_string()
+                    .as_bytes()
+            )
+        );
+    }
+
+    /// Waits until commands get `-BUSY` from the script running elsewhere.
+    fn wait_until_busy(scripting: &Scripting) {
+        let started = Instant::now();
+        while scripting.run_command(b"GET", Vec::new) != BUSY_REPLY {
+            assert!(started.elapsed() < Duration::from_secs(5), "never busy");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn runaway_scripts_are_busy_until_killed() {
+        let scripting = Scripting::new();
+        assert_eq!(scripting.kill(), NOTBUSY_REPLY);
+        std::thread::scope(|threads| {
+            let script = threads.spawn(|| {
+                scripting.eval(
+                    b"while true do end",
+                    &bytes(&[b"0"]),
+                    Duration::from_millis(50),
+                    &|_| Vec::new(),
+                )
+            });
+            wait_until_busy(&scripting);
+            assert_eq!(
+                scripting.eval(
+                    b"return 1",
+                    &bytes(&[b"0"]),
+                    Duration::from_secs(5),
+                    &|_| { Vec::new() }
+                ),
+                BUSY_REPLY
+            );
+            assert_eq!(scripting.run_command(b"SCRIPT", Vec::new), b"");
+            assert_eq!(scripting.kill(), b"+OK\r\n");
+            assert_eq!(
+                script.join().unwrap(),
+                format!("-{KILLED_ERROR}\r\n").as_bytes()
+            );
+        });
+        assert_eq!(
+            scripting.run_command(b"GET", || b"$-1\r\n".to_vec()),
+            b"$-1\r\n"
+        );
+        assert_eq!(scripting.kill(), NOTBUSY_REPLY);
+    }
+
+    #[test]
+    fn scripts_that_wrote_cannot_be_killed() {
+        let scripting = Scripting::new();
+        let stop = AtomicBool::new(false);
+        let call = |args: &[Bytes]| match &args[0][..] {
+            b"GET" if stop.load(Ordering::Acquire) => b"$3\r\nyes\r\n".to_vec(),
+            b"GET" => b"$-1\r\n".to_vec(),
+            _ => b"+OK\r\n".to_vec(),
+        };
+        std::thread::scope(|threads| {
+            let script = threads.spawn(|| {
+                scripting.eval(
+                    b"redis.call('SET', 'k', 'v') \
+                      while redis.call('GET', 'stop') ~= 'yes' do end \
+                      return 'done'",
+                    &bytes(&[b"0"]),
+                    Duration::from_millis(50),
+                    &call,
+                )
+            });
+            wait_until_busy(&scripting);
+            assert_eq!(scripting.kill(), UNKILLABLE_REPLY);
+            stop.store(true, Ordering::Release);
+            assert_eq!(script.join().unwrap(), b"$4\r\ndone\r\n");
+        });
+    }
+}
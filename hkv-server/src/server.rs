@@ -3,48 +3,580 @@
 //! Accept RESP2 connections, parse commands, and dispatch them to the
 //! storage engine with minimal overhead.
 
+use std::cell::{Cell, Ref, RefCell};
 use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::Poll;
 use std::time::{Duration, Instant, SystemTime};
 
-use bytes::BytesMut;
-use socket2::{SockRef, TcpKeepalive};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use bytes::{Bytes, BytesMut};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use tracing::{Instrument, Level};
 
+use hkv_common::sysfs::KernelTunables;
+use hkv_common::{HkvError, HkvErrorCategory, HkvResult, TtlAfter};
 use hkv_engine::{KVEngine, TtlStatus};
+#[cfg(not(all(feature = "uring", target_os = "linux")))]
+use no_uring::{RingAcceptor, RingStream};
 
-use crate::metrics::Metrics;
+use crate::access_log::AccessEntry;
+use crate::acl::{self, Acl, DEFAULT_USER, User};
+use crate::config::{ClientClass, OutputBufferLimit, RuntimeConfig};
+use crate::config_file;
+use crate::glob::glob_match;
+use crate::latency::{BGSAVE_EVENT, LatencyMonitor};
+use crate::lifecycle::LifecyclePhase;
+use crate::logging::LoggedArgs;
+use crate::metrics::{Metrics, MetricsSnapshot, SAMPLE_INTERVAL, TRACKED_COMMANDS, command_name};
 use crate::observation::{
     CommandKind, ExperimentObservationSink, ObservationEvent, SharedObservationLog,
 };
+use crate::persistence::{BgsaveStatus, Persistence};
 use crate::protocol::{RespError, RespParser};
+use crate::rate_limit::{PeerRateLimiter, TokenBucket};
+use crate::read_buffer::BufferPool;
+use crate::replication::Replication;
+use crate::reply::{Reply, write_reply};
+#[cfg(feature = "scripting")]
+use crate::scripting::Scripting;
+use crate::shutdown::{ShutdownController, ShutdownToken};
+#[cfg(feature = "tls")]
+use crate::tls::TlsState;
+use crate::tracking::{TrackedClient, Tracker, TrackingOptions};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring::{RingAcceptor, RingStream};
 
 const DEFAULT_SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
-const DEFAULT_KEEPALIVE_TIME: Duration = Duration::from_secs(30);
 const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
 const DEFAULT_KEEPALIVE_RETRIES: u32 = 3;
 
+/// Reply to write commands in read-only mode, worded as in Redis.
+const READONLY_ERROR: &[u8] = b"-READONLY You can't write against a read only replica.\r\n";
+
+/// Reply to commands before `AUTH` while a password is set.
+const NOAUTH_ERROR: &[u8] = b"-NOAUTH Authentication required.\r\n";
+
+/// Reply to a whole-keyspace command abandoned at `command-time-limit`.
+const TIME_LIMIT_ERROR: &[u8] = b"-BUSY operation exceeded time limit\r\n";
+
+/// Reply to data commands while startup work is still loading.
+const LOADING_ERROR: &[u8] = b"-LOADING HybridKV is loading the dataset in memory\r\n";
+
+/// A property of a command, as reported by `COMMAND INFO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CommandFlag {
+    /// May change the data set; refused in read-only mode.
+    Write,
+    /// Reads the data set without changing it.
+    Readonly,
+    /// Server administration.
+    Admin,
+    /// May run on a connection in subscribe mode.
+    Pubsub,
+    /// Not callable from scripts.
+    Noscript,
+}
+
+impl CommandFlag {
+    fn name(self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::Readonly => "readonly",
+            CommandFlag::Admin => "admin",
+            CommandFlag::Pubsub => "pubsub",
+            CommandFlag::Noscript => "noscript",
+        }
+    }
+}
+
+/// Runs a command whose arity and flags `execute_command` has checked.
+type CommandHandler = fn(&[Bytes], &CommandContext<'_>) -> Vec<u8>;
+
+/// A `CommandHandler` whose reply may share large values with the engine.
+type ReplyHandler = fn(&[Bytes], &CommandContext<'_>) -> Reply;
+
+#[derive(Clone, Copy)]
+enum Handler {
+    Bytes(CommandHandler),
+    Reply(ReplyHandler),
+}
+
+/// One entry of the command table: how a command is dispatched, validated
+/// and described.
+pub(crate) struct CommandSpec {
+    /// Lowercase name, as in `TRACKED_COMMANDS`.
+    pub(crate) name: &'static str,
+    handler: Handler,
+    /// Fewest arguments, counting the command name.
+    min_args: usize,
+    /// Most arguments, or `None` for variadic commands.
+    max_args: Option<usize>,
+    flags: &'static [CommandFlag],
+    /// Position of the first key, or 0 for commands without keys.
+    first_key: usize,
+    /// Position of the last key; -1 is the last argument.
+    last_key: isize,
+    key_step: usize,
+    /// Walks the whole keyspace or blocks its thread, so connections run
+    /// it on `spawn_blocking`; `KEYS` also stops at `command-time-limit`.
+    blocking: bool,
+}
+
+impl CommandSpec {
+    const fn new(
+        name: &'static str,
+        min_args: usize,
+        max_args: Option<usize>,
+        flags: &'static [CommandFlag],
+        handler: CommandHandler,
+    ) -> Self {
+        CommandSpec::with_handler(name, min_args, max_args, flags, Handler::Bytes(handler))
+    }
+
+    /// An entry for a command replying with values written from the
+    /// engine's storage.
+    const fn replying(
+        name: &'static str,
+        min_args: usize,
+        max_args: Option<usize>,
+        flags: &'static [CommandFlag],
+        handler: ReplyHandler,
+    ) -> Self {
+        CommandSpec::with_handler(name, min_args, max_args, flags, Handler::Reply(handler))
+    }
+
+    const fn with_handler(
+        name: &'static str,
+        min_args: usize,
+        max_args: Option<usize>,
+        flags: &'static [CommandFlag],
+        handler: Handler,
+    ) -> Self {
+        CommandSpec {
+            name,
+            handler,
+            min_args,
+            max_args,
+            flags,
+            first_key: 0,
+            last_key: 0,
+            key_step: 0,
+            blocking: false,
+        }
+    }
+
+    const fn blocking(self) -> Self {
+        CommandSpec {
+            blocking: true,
+            ..self
+        }
+    }
+
+    const fn keys(self, first_key: usize, last_key: isize, key_step: usize) -> Self {
+        CommandSpec {
+            first_key,
+            last_key,
+            key_step,
+            ..self
+        }
+    }
+
+    /// Whether the command takes `len` arguments, counting its name.
+    pub(crate) fn accepts(&self, len: usize) -> bool {
+        len >= self.min_args && self.max_args.is_none_or(|max| len <= max)
+    }
+
+    pub(crate) fn has(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Arity as `COMMAND INFO` reports it: the exact argument count, or
+    /// the negated minimum for commands taking a range.
+    fn arity(&self) -> i64 {
+        match self.max_args {
+            Some(max) if max == self.min_args => max as i64,
+            _ => -(self.min_args as i64),
+        }
+    }
+
+    /// The key arguments of `args`, a call to this command.
+    pub(crate) fn keys_of<'a>(&self, args: &'a [Bytes]) -> impl Iterator<Item = &'a Bytes> {
+        let last = if self.last_key < 0 {
+            args.len().checked_add_signed(self.last_key)
+        } else {
+            Some(self.last_key as usize)
+        };
+        let keys = match last {
+            Some(last) if self.first_key > 0 && self.first_key <= last && last < args.len() => {
+                &args[self.first_key..=last]
+            }
+            _ => &[],
+        };
+        keys.iter().step_by(self.key_step.max(1))
+    }
+}
+
+/// Flags of `EVAL` and `EVALSHA`. With scripting, each command a script
+/// calls is checked and replicated on its own, so the script is not a write
+/// itself; without it, scripts are refused in read-only mode before
+/// reaching the stub.
+#[cfg(feature = "scripting")]
+const SCRIPT_FLAGS: &[CommandFlag] = &[CommandFlag::Noscript];
+#[cfg(not(feature = "scripting"))]
+const SCRIPT_FLAGS: &[CommandFlag] = &[CommandFlag::Write, CommandFlag::Noscript];
+
+/// Every command the server answers, in `TRACKED_COMMANDS` order.
+static COMMANDS: [CommandSpec; TRACKED_COMMANDS.len()] = {
+    use CommandFlag::{Admin, Noscript, Pubsub, Readonly, Write};
+    [
+        CommandSpec::new("ping", 1, Some(2), &[Pubsub], |args, _| handle_ping(args)),
+        CommandSpec::replying("get", 2, Some(2), &[Readonly], |args, ctx| {
+            observed(args, ctx, handle_get)
+        })
+        .keys(1, 1, 1),
+        // Options are validated by the handler.
+        CommandSpec::replying("set", 3, None, &[Write], |args, ctx| {
+            observed(args, ctx, handle_set)
+        })
+        .keys(1, 1, 1),
+        CommandSpec::replying("del", 2, None, &[Write], |args, ctx| {
+            observed(args, ctx, handle_del)
+        })
+        .keys(1, -1, 1),
+        CommandSpec::replying("expire", 3, Some(3), &[Write], |args, ctx| {
+            observed(args, ctx, handle_expire)
+        })
+        .keys(1, 1, 1),
+        CommandSpec::replying("ttl", 2, Some(2), &[Readonly], |args, ctx| {
+            observed(args, ctx, handle_ttl)
+        })
+        .keys(1, 1, 1),
+        CommandSpec::new("info", 1, Some(2), &[], handle_info),
+        CommandSpec::new("save", 1, Some(1), &[Admin, Noscript], |_, ctx| {
+            handle_save(ctx.engine, ctx.persistence)
+        })
+        .blocking(),
+        CommandSpec::new("bgsave", 1, Some(1), &[Admin, Noscript], handle_bgsave),
+        CommandSpec::new("lastsave", 1, Some(1), &[], |_, ctx| {
+            resp_integer(ctx.persistence.last_save() as i64)
+        }),
+        CommandSpec::new("config", 2, None, &[Admin, Noscript], |args, ctx| {
+            handle_config(args, ctx.metrics, ctx.runtime)
+        }),
+        CommandSpec::new("lolwut", 1, None, &[Readonly], |_, _| handle_lolwut()),
+        CommandSpec::new("eval", 3, None, SCRIPT_FLAGS, handle_eval),
+        CommandSpec::new("evalsha", 3, None, SCRIPT_FLAGS, handle_evalsha),
+        CommandSpec::new("script", 2, None, &[Noscript], handle_script),
+        CommandSpec::new("function", 2, None, &[Noscript], |args, _| {
+            handle_function(args)
+        }),
+        CommandSpec::new("cluster", 2, None, &[], |args, ctx| {
+            handle_cluster(args, ctx.node_id)
+        }),
+        CommandSpec::new("replicaof", 3, Some(3), &[Admin, Noscript], |args, ctx| {
+            handle_replicaof(args, ctx.replication, ctx.runtime)
+        }),
+        CommandSpec::new("slaveof", 3, Some(3), &[Admin, Noscript], |args, ctx| {
+            handle_replicaof(args, ctx.replication, ctx.runtime)
+        }),
+        // Settings such as listening-port and capa are accepted and unused.
+        CommandSpec::new("replconf", 2, None, &[Admin, Noscript], |_, _| {
+            resp_simple("OK")
+        }),
+        // Connections with replication intercept PSYNC before dispatch.
+        CommandSpec::new("psync", 3, Some(3), &[Admin, Noscript], |_, _| {
+            resp_error("PSYNC is only supported on connections to a listening server")
+        }),
+        CommandSpec::new("hello", 1, None, &[Noscript], handle_hello),
+        CommandSpec::new("client", 2, None, &[Admin, Noscript], |args, ctx| {
+            handle_client(args, ctx.connection)
+        }),
+        CommandSpec::new("command", 1, None, &[], |args, _| handle_command(args)),
+        CommandSpec::new("reset", 1, Some(1), &[Noscript], |_, ctx| {
+            ctx.connection.reset(ctx.runtime);
+            resp_simple("RESET")
+        }),
+        CommandSpec::new("auth", 2, Some(3), &[Noscript], handle_auth),
+        CommandSpec::replying("keys", 2, Some(2), &[Readonly], handle_keys).blocking(),
+        CommandSpec::new("flushall", 1, Some(2), &[Write], handle_flush).blocking(),
+        CommandSpec::new("flushdb", 1, Some(2), &[Write], handle_flush).blocking(),
+        CommandSpec::replying("mget", 2, None, &[Readonly], |args, ctx| {
+            handle_mget(args, ctx.engine)
+        })
+        .keys(1, -1, 1),
+        CommandSpec::new("acl", 2, None, &[Admin, Noscript], handle_acl),
+        CommandSpec::new("latency", 2, None, &[Admin, Noscript], |args, ctx| {
+            handle_latency(args, ctx.metrics.latency_monitor())
+        }),
+        CommandSpec::new("debug", 2, None, &[Admin, Noscript], |args, _| {
+            handle_debug(args)
+        })
+        .blocking(),
+    ]
+};
+
+/// The command table, in `TRACKED_COMMANDS` order.
+pub(crate) fn commands() -> &'static [CommandSpec] {
+    &COMMANDS
+}
+
+/// Position of `spec` in the command table.
+pub(crate) fn command_index(spec: &CommandSpec) -> usize {
+    COMMANDS
+        .iter()
+        .position(|entry| std::ptr::eq(entry, spec))
+        .expect("command specs come from the table")
+}
+
+/// The command table entry for `name` (any case).
+pub(crate) fn command_spec(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| eq_ignore_ascii_case(spec.name.as_bytes(), name))
+}
+
+/// Everything a command handler may use besides its arguments.
+struct CommandContext<'a> {
+    engine: &'a dyn KVEngine,
+    /// `engine` as an owned handle, for work that outlives the command
+    /// such as `BGSAVE`; `None` where the engine is only borrowed, which
+    /// refuses those commands.
+    shared_engine: Option<&'a Arc<dyn KVEngine>>,
+    metrics: &'a Metrics,
+    persistence: &'a Arc<Persistence>,
+    runtime: &'a RuntimeConfig,
+    node_id: &'a str,
+    listen_addrs: &'a [SocketAddr],
+    replication: Option<&'a Arc<Replication>>,
+    observation_sink: Option<&'a dyn ExperimentObservationSink>,
+    connection: &'a ConnectionCtx,
+    #[cfg(feature = "scripting")]
+    scripting: &'a Scripting,
+    /// Set once `command-time-limit` passes for a command run on
+    /// `spawn_blocking`; `None` runs whole-keyspace commands to completion.
+    abort: Option<&'a AtomicBool>,
+}
+
+/// `CLUSTER INFO` reply for a server not in cluster mode.
+const CLUSTER_INFO: &str = concat!(
+    "cluster_enabled:0\r\n",
+    "cluster_state:ok\r\n",
+    "cluster_slots_assigned:0\r\n",
+    "cluster_slots_ok:0\r\n",
+    "cluster_slots_pfail:0\r\n",
+    "cluster_slots_fail:0\r\n",
+    "cluster_known_nodes:0\r\n",
+    "cluster_size:0\r\n",
+    "cluster_current_epoch:0\r\n",
+    "cluster_my_epoch:0\r\n",
+    "cluster_stats_messages_sent:0\r\n",
+    "cluster_stats_messages_received:0\r\n",
+    "total_cluster_links_buffer_limit_exceeded:0\r\n",
+);
+
+/// `LOLWUT` reply.
+const LOLWUT_ART: &str = concat!(
+    r"  _   _       _          _     _ _  ____     __",
+    "\n",
+    r" | | | |_   _| |__  _ __(_) __| | |/ /\ \   / /",
+    "\n",
+    r" | |_| | | | | '_ \| '__| |/ _` | ' /  \ \ / / ",
+    "\n",
+    r" |  _  | |_| | |_) | |  | | (_| | . \   \ V /  ",
+    "\n",
+    r" |_| |_|\__, |_.__/|_|  |_|\__,_|_|\_\   \_/   ",
+    "\n",
+    r"        |___/                                  ",
+    "\n",
+    "\nDr. HybridKV: bringing cache to the edge\n\nHybridKV v1.0.0\n",
+);
+
+/// Source of `ClientInfo::id`.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifies a connection in logs.
+#[derive(Clone, Copy)]
+struct ClientInfo {
+    id: u64,
+    addr: Option<SocketAddr>,
+}
+
+impl ClientInfo {
+    fn next(addr: Option<SocketAddr>) -> Self {
+        ClientInfo {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+        }
+    }
+}
+
+/// State owned by one client connection while it is open.
+///
+/// Dropping it deregisters the connection from the tracker, which also
+/// stops any tracking it turned on, however the connection ended.
+struct ConnectionCtx {
+    client: ClientInfo,
+    /// RESP version the connection speaks, switched by `HELLO`.
+    protocol: Cell<u8>,
+    /// Registration with the tracker; `None` on servers without a listener.
+    tracked: Option<TrackedClient>,
+    /// User logged in with `AUTH`, or the default user from the start
+    /// when it needed no password then.
+    login: RefCell<Option<Arc<User>>>,
+    /// The user commands run as, as of ACL generation `acl_generation`.
+    user: RefCell<Option<Arc<User>>>,
+    acl_generation: Cell<u64>,
+}
+
+impl ConnectionCtx {
+    fn new(client: ClientInfo, tracker: Option<&Arc<Tracker>>, runtime: &RuntimeConfig) -> Self {
+        ConnectionCtx {
+            client,
+            protocol: Cell::new(2),
+            tracked: tracker.map(|tracker| tracker.connect(client.id)),
+            login: RefCell::new(runtime.acl().open_default_user()),
+            user: RefCell::new(None),
+            acl_generation: Cell::new(u64::MAX),
+        }
+    }
+
+    fn id(&self) -> u64 {
+        self.client.id
+    }
+
+    fn protocol(&self) -> u8 {
+        self.protocol.get()
+    }
+
+    fn set_protocol(&self, version: u8) {
+        self.protocol.set(version);
+        if let Some(tracked) = &self.tracked {
+            tracked.set_resp3(version == 3);
+        }
+    }
+
+    /// Returns the connection to its state on accept, for `RESET`. The
+    /// client keeps its id.
+    fn reset(&self, runtime: &RuntimeConfig) {
+        self.set_protocol(2);
+        self.log_in(runtime.acl().open_default_user());
+        if let Some(tracked) = &self.tracked {
+            tracked.disable();
+        }
+    }
+
+    /// Makes `user` the one commands run as, or logs out.
+    fn log_in(&self, user: Option<Arc<User>>) {
+        *self.login.borrow_mut() = user;
+        // Looked up again before the next command.
+        self.acl_generation.set(u64::MAX);
+    }
+
+    /// The user commands run as, or `None` if the connection may run only
+    /// `AUTH`. A user changed since the last command is looked up again;
+    /// one deleted or turned off logs the connection out, and a default
+    /// user needing no password lets logged-out connections in.
+    fn user(&self, acl: &Acl) -> Ref<'_, Option<Arc<User>>> {
+        let generation = acl.generation();
+        if self.acl_generation.get() != generation {
+            let login = self
+                .login
+                .borrow()
+                .as_ref()
+                .and_then(|user| acl.user(user.name()))
+                .filter(|user| user.enabled());
+            *self.user.borrow_mut() = login.clone().or_else(|| acl.open_default_user());
+            *self.login.borrow_mut() = login;
+            self.acl_generation.set(generation);
+        }
+        self.user.borrow()
+    }
+
+    fn before_command(&self, args: &[Bytes]) {
+        if let Some(tracked) = &self.tracked {
+            tracked.before_command(args);
+        }
+    }
+
+    fn after_command(&self, args: &[Bytes], failed: bool) {
+        if let Some(tracked) = &self.tracked {
+            tracked.after_command(args, failed);
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 struct ServerConfig {
     shutdown_drain_timeout: Duration,
-    keepalive_time: Duration,
     keepalive_interval: Duration,
     keepalive_retries: u32,
 }
 
 const DEFAULT_SERVER_CONFIG: ServerConfig = ServerConfig {
     shutdown_drain_timeout: DEFAULT_SHUTDOWN_DRAIN_TIMEOUT,
-    keepalive_time: DEFAULT_KEEPALIVE_TIME,
     keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
     keepalive_retries: DEFAULT_KEEPALIVE_RETRIES,
 };
 
+/// Server-wide state shared by every connection.
+#[derive(Clone)]
+struct ServerContext {
+    metrics: Arc<Metrics>,
+    observation_log: Option<Arc<SharedObservationLog>>,
+    persistence: Arc<Persistence>,
+    runtime: Arc<RuntimeConfig>,
+    /// `CLUSTER MYID` reply; see `cluster_node_id`.
+    node_id: Arc<str>,
+    /// Addresses the server accepts connections on, for `INFO server`.
+    listen_addrs: Arc<[SocketAddr]>,
+    /// Master and replica state; only servers with a listener replicate.
+    replication: Option<Arc<Replication>>,
+    /// `CLIENT TRACKING` state; only servers with a listener track.
+    tracker: Option<Arc<Tracker>>,
+    /// Small read buffers handed to new connections.
+    read_buffers: Arc<BufferPool>,
+    /// Handshake every accepted stream before serving it.
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsState>>,
+    /// Script cache and the gate that makes scripts atomic.
+    #[cfg(feature = "scripting")]
+    scripting: Arc<Scripting>,
+}
+
+impl ServerContext {
+    /// Context without observation, with default persistence and settings.
+    fn new(metrics: Arc<Metrics>) -> Self {
+        ServerContext {
+            read_buffers: Arc::new(BufferPool::new(Arc::clone(&metrics))),
+            metrics,
+            observation_log: None,
+            persistence: Arc::new(Persistence::default()),
+            runtime: Arc::new(RuntimeConfig::new()),
+            // Streams served without a listener have no bound address.
+            node_id: cluster_node_id(SocketAddr::from(([0, 0, 0, 0], 0))).into(),
+            listen_addrs: Arc::new([]),
+            replication: None,
+            tracker: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "scripting")]
+            scripting: Arc::new(Scripting::new()),
+        }
+    }
+}
+
 /// Serves accepted TCP connections until shutdown is triggered.
 ///
-/// The shutdown signal stops new accepts immediately, then drains active
-/// connections for a bounded grace period before aborting remaining tasks.
+/// The shutdown signal stops new accepts immediately and tells every
+/// connection to close once the commands it has already received are
+/// answered. Connections still busy after a bounded grace period are aborted.
+/// `ShutdownController::wait` is the usual `shutdown` future.
 pub async fn serve_with_shutdown<E, F>(
     listener: tokio::net::TcpListener,
     engine: Arc<E>,
@@ -55,17 +587,192 @@ where
     E: KVEngine + 'static,
     F: Future<Output = ()>,
 {
-    serve_with_shutdown_with_observation(
+    serve_with_context(
+        vec![listener],
+        engine,
+        ServerContext::new(metrics),
+        shutdown,
+        DEFAULT_SERVER_CONFIG,
+    )
+    .await
+}
+
+/// Serves connections with caller-provided snapshot persistence.
+///
+/// Use this to choose where `SAVE`/`BGSAVE` write `dump.hkv` and to read
+/// `LASTSAVE` state from outside the server.
+pub async fn serve_with_shutdown_and_persistence<E, F>(
+    listener: tokio::net::TcpListener,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    shutdown: F,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    let context = ServerContext {
+        persistence,
+        ..ServerContext::new(metrics)
+    };
+    serve_with_context(
+        vec![listener],
+        engine,
+        context,
+        shutdown,
+        DEFAULT_SERVER_CONFIG,
+    )
+    .await
+}
+
+/// Serves connections like `serve_with_shutdown_and_persistence`, but waits
+/// up to `grace` for connections to finish after shutdown.
+pub async fn serve_with_shutdown_and_grace<E, F>(
+    listener: tokio::net::TcpListener,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    shutdown: F,
+    grace: Duration,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    serve_with_runtime_config(
         listener,
         engine,
         metrics,
-        None,
+        persistence,
+        Arc::new(RuntimeConfig::new()),
         shutdown,
-        DEFAULT_SERVER_CONFIG,
+        grace,
+    )
+    .await
+}
+
+/// Serves connections like `serve_with_shutdown_and_grace`, with settings
+/// the caller can also read and change while serving (`CONFIG GET`/`SET`).
+pub async fn serve_with_runtime_config<E, F>(
+    listener: tokio::net::TcpListener,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    runtime: Arc<RuntimeConfig>,
+    shutdown: F,
+    grace: Duration,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    serve_listeners_with_runtime_config(
+        vec![listener],
+        engine,
+        metrics,
+        persistence,
+        runtime,
+        shutdown,
+        grace,
+    )
+    .await
+}
+
+/// Serves connections like `serve_with_runtime_config` on every listener
+/// at once, such as one per address of a dual-stack host.
+///
+/// All listeners share the connection limit, settings and shutdown; the
+/// first one's address names the server in `CLUSTER MYID` and replication.
+pub async fn serve_listeners_with_runtime_config<E, F>(
+    listeners: Vec<tokio::net::TcpListener>,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    runtime: Arc<RuntimeConfig>,
+    shutdown: F,
+    grace: Duration,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    let context = ServerContext {
+        persistence,
+        runtime,
+        ..ServerContext::new(metrics)
+    };
+    let config = ServerConfig {
+        shutdown_drain_timeout: grace,
+        ..DEFAULT_SERVER_CONFIG
+    };
+    serve_with_context(listeners, engine, context, shutdown, config).await
+}
+
+/// Serves connections like `serve_with_runtime_config`, over TLS.
+///
+/// Each accepted stream is handshaken with `tls` in its own task; a failed
+/// handshake closes that connection only. Call `TlsState::reload` (or
+/// `reload_on_sighup`) to rotate certificates while serving.
+#[cfg(feature = "tls")]
+#[allow(clippy::too_many_arguments)]
+pub async fn serve_tls_with_runtime_config<E, F>(
+    listener: tokio::net::TcpListener,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    runtime: Arc<RuntimeConfig>,
+    tls: Arc<TlsState>,
+    shutdown: F,
+    grace: Duration,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    serve_tls_listeners_with_runtime_config(
+        vec![listener],
+        engine,
+        metrics,
+        persistence,
+        runtime,
+        tls,
+        shutdown,
+        grace,
     )
     .await
 }
 
+/// Serves connections like `serve_listeners_with_runtime_config`, over TLS.
+#[cfg(feature = "tls")]
+#[allow(clippy::too_many_arguments)]
+pub async fn serve_tls_listeners_with_runtime_config<E, F>(
+    listeners: Vec<tokio::net::TcpListener>,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    persistence: Arc<Persistence>,
+    runtime: Arc<RuntimeConfig>,
+    tls: Arc<TlsState>,
+    shutdown: F,
+    grace: Duration,
+) -> std::io::Result<()>
+where
+    E: KVEngine + 'static,
+    F: Future<Output = ()>,
+{
+    let context = ServerContext {
+        persistence,
+        runtime,
+        tls: Some(tls),
+        ..ServerContext::new(metrics)
+    };
+    let config = ServerConfig {
+        shutdown_drain_timeout: grace,
+        ..DEFAULT_SERVER_CONFIG
+    };
+    serve_with_context(listeners, engine, context, shutdown, config).await
+}
+
 pub async fn serve_with_shutdown_and_observation<E, F>(
     listener: tokio::net::TcpListener,
     engine: Arc<E>,
@@ -77,21 +784,26 @@ where
     E: KVEngine + 'static,
     F: Future<Output = ()>,
 {
-    serve_with_shutdown_with_observation(
-        listener,
+    let context = ServerContext {
+        observation_log: Some(observation_log),
+        ..ServerContext::new(metrics)
+    };
+    serve_with_context(
+        vec![listener],
         engine,
-        metrics,
-        Some(observation_log),
+        context,
         shutdown,
         DEFAULT_SERVER_CONFIG,
     )
     .await
 }
 
-/// Handles a single TCP client connection.
-pub async fn handle_connection<E>(stream: TcpStream, engine: Arc<E>) -> std::io::Result<()>
+/// Handles a single client connection over any byte stream, such as a
+/// `TcpStream` or a TLS stream wrapping one.
+pub async fn handle_connection<S, E>(stream: S, engine: Arc<E>) -> std::io::Result<()>
 where
-    E: KVEngine,
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KVEngine + 'static,
 {
     handle_connection_with_metrics(stream, engine, Arc::new(Metrics::new())).await
 }
@@ -108,14 +820,20 @@ where
     E: KVEngine + 'static,
     F: Future<Output = ()>,
 {
-    serve_with_shutdown_with_observation(listener, engine, metrics, None, shutdown, config).await
+    serve_with_context(
+        vec![listener],
+        engine,
+        ServerContext::new(metrics),
+        shutdown,
+        config,
+    )
+    .await
 }
 
-async fn serve_with_shutdown_with_observation<E, F>(
-    listener: tokio::net::TcpListener,
+async fn serve_with_context<E, F>(
+    listeners: Vec<tokio::net::TcpListener>,
     engine: Arc<E>,
-    metrics: Arc<Metrics>,
-    observation_log: Option<Arc<SharedObservationLog>>,
+    context: ServerContext,
     shutdown: F,
     config: ServerConfig,
 ) -> std::io::Result<()>
@@ -123,297 +841,2064 @@ where
     E: KVEngine + 'static,
     F: Future<Output = ()>,
 {
-    let listener = listener;
-    let mut connections = JoinSet::new();
+    let mut listen_addrs = Vec::with_capacity(listeners.len());
+    for listener in &listeners {
+        // `SO_REUSEPORT` listeners share their address.
+        let addr = listener.local_addr()?;
+        if !listen_addrs.contains(&addr) {
+            listen_addrs.push(addr);
+        }
+    }
+    let listen_addrs: Arc<[SocketAddr]> = listen_addrs.into();
+    let Some(&local_addr) = listen_addrs.first() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "no listeners to serve",
+        ));
+    };
+    let replication = Arc::new(Replication::new(
+        Arc::clone(&engine) as Arc<dyn KVEngine>,
+        Arc::clone(&context.runtime),
+        local_addr.port(),
+    ));
+    let context = ServerContext {
+        node_id: cluster_node_id(local_addr).into(),
+        listen_addrs,
+        replication: Some(Arc::clone(&replication)),
+        tracker: Some(Arc::new(Tracker::new(Arc::clone(&context.runtime)))),
+        ..context
+    };
+    let admission = Admission {
+        engine,
+        context,
+        config,
+        peer_limiter: Arc::new(PeerRateLimiter::new()),
+        controller: ShutdownController::new(),
+    };
+    let runtime = Arc::clone(&admission.context.runtime);
+    runtime.lifecycle().advance(LifecyclePhase::Ready);
+    let stop_accepting = ShutdownController::new();
+    let mut acceptors = JoinSet::new();
+    for group in acceptor_groups(listeners)? {
+        acceptors.spawn(accept_loop(
+            group,
+            admission.clone(),
+            stop_accepting.token(),
+        ));
+    }
+    let mut rate_sampler = tokio::time::interval(SAMPLE_INTERVAL);
+    rate_sampler.set_missed_tick_behavior(MissedTickBehavior::Delay);
     tokio::pin!(shutdown);
 
-    loop {
-        tokio::select! {
-            _ = &mut shutdown => break,
-            Some(join_result) = connections.join_next(), if !connections.is_empty() => {
-                reap_connection_task(join_result);
+    // Accept loops only finish early when accepting fails.
+    let mut result = Ok(());
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = rate_sampler.tick() => admission.context.metrics.sample_rates(),
+            Some(joined) = acceptors.join_next() => {
+                if let Err(err) = reap_acceptor(joined) {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+    }
+
+    stop_accepting.trigger();
+    let mut connections = Vec::with_capacity(acceptors.len());
+    while let Some(joined) = acceptors.join_next().await {
+        match reap_acceptor(joined) {
+            Ok(set) => connections.push(set),
+            Err(err) => {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+    }
+    runtime.lifecycle().advance(LifecyclePhase::Draining);
+    admission.controller.trigger();
+    replication.stop_replicating();
+    tracing::info!(
+        connections = connections.iter().map(JoinSet::len).sum::<usize>(),
+        "shutdown: stopped accepting, draining connections"
+    );
+
+    let drain = async {
+        for set in &mut connections {
+            while let Some(join_result) = set.join_next().await {
+                reap_connection_task(join_result);
+            }
+        }
+    };
+
+    if tokio::time::timeout(config.shutdown_drain_timeout, drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            connections = connections.iter().map(JoinSet::len).sum::<usize>(),
+            "shutdown: grace period expired, aborting connections"
+        );
+        for set in &mut connections {
+            set.abort_all();
+            while let Some(join_result) = set.join_next().await {
+                reap_connection_task(join_result);
+            }
+        }
+    }
+    tracing::info!("shutdown: all connections closed");
+
+    result
+}
+
+/// What every accept loop needs to admit and serve a connection.
+struct Admission<E> {
+    engine: Arc<E>,
+    context: ServerContext,
+    config: ServerConfig,
+    /// Shared, so `maxconn-per-ip-per-sec` counts across accept loops.
+    peer_limiter: Arc<PeerRateLimiter>,
+    /// Tells every connection to finish up.
+    controller: ShutdownController,
+}
+
+impl<E> Clone for Admission<E> {
+    fn clone(&self) -> Self {
+        Admission {
+            engine: Arc::clone(&self.engine),
+            context: self.context.clone(),
+            config: self.config,
+            peer_limiter: Arc::clone(&self.peer_limiter),
+            controller: self.controller.clone(),
+        }
+    }
+}
+
+/// Splits listeners into one group per accept loop: the `SO_REUSEPORT`
+/// listeners sharing an address go to different loops, so loop `i` takes
+/// the `i`th listener of every address.
+fn acceptor_groups(
+    listeners: Vec<tokio::net::TcpListener>,
+) -> std::io::Result<Vec<Vec<tokio::net::TcpListener>>> {
+    let mut groups: Vec<Vec<tokio::net::TcpListener>> = Vec::new();
+    let mut seen: Vec<(SocketAddr, usize)> = Vec::new();
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        let index = match seen.iter_mut().find(|(seen, _)| *seen == addr) {
+            Some((_, count)) => {
+                *count += 1;
+                *count - 1
+            }
+            None => {
+                seen.push((addr, 1));
+                0
+            }
+        };
+        if index == groups.len() {
+            groups.push(Vec::new());
+        }
+        groups[index].push(listener);
+    }
+    Ok(groups)
+}
+
+/// Accepts on `listeners` until `stop` fires, then closes them and returns
+/// the connections it started, still running.
+async fn accept_loop<E>(
+    listeners: Vec<tokio::net::TcpListener>,
+    admission: Admission<E>,
+    mut stop: ShutdownToken,
+) -> std::io::Result<JoinSet<std::io::Result<()>>>
+where
+    E: KVEngine + 'static,
+{
+    let mut connections = JoinSet::new();
+    let mut next_listener = 0;
+    let mut ring = start_ring(&listeners, &admission.context.runtime);
+
+    loop {
+        tokio::select! {
+            () = stop.wait() => break,
+            Some(join_result) = connections.join_next(), if !connections.is_empty() => {
+                reap_connection_task(join_result);
+            }
+            accept = accept_any(&listeners, &mut next_listener), if ring.is_none() => {
+                let Some((stream, peer)) = accepted_or_backoff(accept).await? else {
+                    continue;
+                };
+                admit_connection(stream, peer, &admission, &mut connections)?;
+            }
+            accept = accept_ring(ring.as_mut()), if ring.is_some() => {
+                let Some((stream, peer)) = accepted_or_backoff(accept).await? else {
+                    continue;
+                };
+                admit_connection(stream, peer, &admission, &mut connections)?;
+            }
+        }
+    }
+
+    // The ring holds its own copies of the listeners; stop it accepting
+    // first so closing them refuses new connections.
+    ring.take();
+    drop(listeners);
+    Ok(connections)
+}
+
+/// An accept loop's connections, or the error it stopped on; a panic in
+/// the loop is resumed.
+fn reap_acceptor(
+    joined: Result<std::io::Result<JoinSet<std::io::Result<()>>>, tokio::task::JoinError>,
+) -> std::io::Result<JoinSet<std::io::Result<()>>> {
+    match joined {
+        Ok(result) => result,
+        Err(join_error) if join_error.is_panic() => {
+            std::panic::resume_unwind(join_error.into_panic())
+        }
+        Err(join_error) => Err(std::io::Error::other(join_error)),
+    }
+}
+
+/// Starts the io_uring transport when `io-uring` is set, or returns `None`
+/// to accept on the standard path.
+fn start_ring(
+    listeners: &[tokio::net::TcpListener],
+    runtime: &RuntimeConfig,
+) -> Option<RingAcceptor> {
+    if !runtime.io_uring() {
+        return None;
+    }
+    match RingAcceptor::start(listeners) {
+        Ok(ring) => {
+            tracing::info!("serving connections through io_uring");
+            Some(ring)
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, "io_uring unavailable, using the standard network path");
+            None
+        }
+    }
+}
+
+/// Accepts the next connection through the ring; only polled when there is
+/// one.
+async fn accept_ring(ring: Option<&mut RingAcceptor>) -> std::io::Result<(RingStream, SocketAddr)> {
+    match ring {
+        Some(ring) => ring.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Stand-ins for builds without the `uring` feature, where a ring never
+/// starts.
+#[cfg(not(all(feature = "uring", target_os = "linux")))]
+mod no_uring {
+    use std::net::SocketAddr;
+
+    use tokio::net::TcpStream;
+
+    pub(super) type RingStream = TcpStream;
+
+    pub(super) enum RingAcceptor {}
+
+    impl RingAcceptor {
+        pub(super) fn start(_: &[tokio::net::TcpListener]) -> std::io::Result<Self> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "built without the uring feature",
+            ))
+        }
+
+        pub(super) async fn accept(&mut self) -> std::io::Result<(RingStream, SocketAddr)> {
+            match *self {}
+        }
+    }
+}
+
+/// A client's byte stream, with the socket under it for socket options.
+pub(crate) trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    fn socket(&self) -> SockRef<'_>;
+}
+
+impl ClientStream for TcpStream {
+    fn socket(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+}
+
+/// Passes an accepted connection on, or backs off and returns `None` when
+/// the process is out of file descriptors.
+async fn accepted_or_backoff<S>(
+    accept: std::io::Result<(S, SocketAddr)>,
+) -> std::io::Result<Option<(S, SocketAddr)>> {
+    match accept {
+        Ok(accepted) => Ok(Some(accepted)),
+        Err(err) if is_fd_exhaustion(&err) => {
+            tracing::warn!(error = %err, "out of file descriptors, pausing accepts");
+            // Retrying at once would fail the same way; wait for
+            // connections to close and free descriptors.
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Refuses `stream` if protected mode, the per-address rate or
+/// `maxclients` says so, and otherwise spawns its connection task.
+fn admit_connection<S, E>(
+    stream: S,
+    peer: SocketAddr,
+    admission: &Admission<E>,
+    connections: &mut JoinSet<std::io::Result<()>>,
+) -> std::io::Result<()>
+where
+    S: ClientStream,
+    E: KVEngine + 'static,
+{
+    let Admission {
+        engine,
+        context,
+        config,
+        peer_limiter,
+        controller,
+    } = admission;
+    context.metrics.record_accepted_connection();
+    if context.runtime.is_protected() && !is_loopback(peer.ip()) {
+        tracing::warn!(%peer, "protected mode: refusing a connection from outside loopback");
+        context.metrics.record_rejected_connection();
+        connections.spawn(reject_connection(stream, PROTECTED_MODE_REPLY));
+        return Ok(());
+    }
+    let conn_rate = context.runtime.max_conn_per_ip_per_sec();
+    if conn_rate > 0 && !peer_limiter.allow(peer.ip(), conn_rate, Instant::now()) {
+        tracing::warn!(%peer, "connection rate from address exceeded, rejecting connection");
+        context.metrics.record_rate_limited_connection();
+        connections.spawn(reject_connection(stream, CONN_RATE_REPLY));
+        return Ok(());
+    }
+    if context.metrics.connected_clients() >= context.runtime.max_clients() {
+        tracing::warn!(%peer, "max number of clients reached, rejecting connection");
+        context.metrics.record_rejected_connection();
+        connections.spawn(reject_connection(stream, MAX_CLIENTS_REPLY));
+        return Ok(());
+    }
+    configure_accepted_stream(&stream, *config, &context.runtime)?;
+    let engine = Arc::clone(engine);
+    let slot = ClientSlot::acquire(Arc::clone(&context.metrics));
+    let context = context.clone();
+    let token = controller.token();
+    let client = ClientInfo::next(Some(peer));
+    let span = tracing::info_span!("connection", id = client.id, %peer);
+    let connection = async move {
+        let _slot = slot;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = context.tls.clone() {
+            let stream = match tls.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    tracing::debug!(error = %err, "TLS handshake failed");
+                    context.metrics.record_tls_handshake_failure();
+                    return Ok(());
+                }
+            };
+            return serve_connection(stream, engine, context, client, token).await;
+        }
+        serve_connection(stream, engine, context, client, token).await
+    };
+    connections.spawn(
+        async move {
+            tracing::debug!("client connected");
+            let result = connection.await;
+            match &result {
+                Ok(()) => tracing::debug!("client disconnected"),
+                Err(err) => tracing::debug!(error = %err, "client connection failed"),
+            }
+            result
+        }
+        .instrument(span),
+    );
+    Ok(())
+}
+
+/// Accepts the next connection from whichever listener has one first.
+///
+/// Listeners are polled starting after the one that accepted last, so a
+/// busy listener cannot starve the others; `next` carries that position.
+async fn accept_any(
+    listeners: &[tokio::net::TcpListener],
+    next: &mut usize,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            if let Poll::Ready(accepted) = listeners[index].poll_accept(cx) {
+                *next = index + 1;
+                return Poll::Ready(accepted);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Pause before accepting again after running out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Reply sent to connections refused at the `maxclients` limit.
+const MAX_CLIENTS_REPLY: &[u8] = b"-ERR max number of clients reached\r\n";
+
+/// Reply sent to connections from outside loopback in protected mode,
+/// worded as in Redis.
+const PROTECTED_MODE_REPLY: &[u8] = b"-DENIED HybridKV is running in protected mode because protected mode is enabled and no password is set for the default user. In this mode connections are only accepted from the loopback interface. If you want to connect from external computers to HybridKV you may adopt one of the following solutions: 1) Just disable protected mode sending the command 'CONFIG SET protected-mode no' from the loopback interface by connecting to HybridKV from the same host the server is running, however MAKE SURE HybridKV is not publicly accessible from internet if you do so. Use CONFIG REWRITE to make this change permanent. 2) Alternatively you can just disable the protected mode by editing the HybridKV configuration file, and setting the protected mode option to 'no', and then restarting the server. 3) If you started the server manually just for testing, restart it with the '--protected-mode no' option. 4) Set up an authentication password for the default user. NOTE: You only need to do one of the above things in order for the server to start accepting connections from the outside.\r\n";
+
+/// Whether `ip` is on the loopback interface, including IPv4 loopback
+/// addresses mapped into IPv6.
+fn is_loopback(ip: IpAddr) -> bool {
+    ip.to_canonical().is_loopback()
+}
+
+/// Reply sent to connections refused at the `maxconn-per-ip-per-sec` limit.
+const CONN_RATE_REPLY: &[u8] = b"-ERR max connections per second from this address exceeded\r\n";
+
+/// `EMFILE`/`ENFILE`: the process or system is out of file descriptors.
+fn is_fd_exhaustion(err: &std::io::Error) -> bool {
+    // Same values on Linux, macOS and the BSDs.
+    const ENFILE: i32 = 23;
+    const EMFILE: i32 = 24;
+    matches!(err.raw_os_error(), Some(ENFILE | EMFILE))
+}
+
+/// Tells a refused connection why with `reply`, then closes it.
+async fn reject_connection<S>(mut stream: S, reply: &'static [u8]) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    // The client may already be gone; nothing else to do either way.
+    let _ = stream.write_all(reply).await;
+    let _ = stream.shutdown().await;
+    Ok(())
+}
+
+/// Counts a connection in `connected_clients` for as long as it is held,
+/// including when its task is aborted.
+struct ClientSlot {
+    metrics: Arc<Metrics>,
+}
+
+impl ClientSlot {
+    fn acquire(metrics: Arc<Metrics>) -> Self {
+        metrics.record_client_connected();
+        ClientSlot { metrics }
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.metrics.record_client_disconnected();
+    }
+}
+
+/// Handles a single client connection with shared server metrics.
+pub async fn handle_connection_with_metrics<S, E>(
+    stream: S,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KVEngine + 'static,
+{
+    handle_connection_with_observation(stream, engine, metrics, None).await
+}
+
+pub async fn handle_connection_with_observation<S, E>(
+    stream: S,
+    engine: Arc<E>,
+    metrics: Arc<Metrics>,
+    observation_log: Option<Arc<SharedObservationLog>>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KVEngine + 'static,
+{
+    let context = ServerContext {
+        observation_log,
+        ..ServerContext::new(metrics)
+    };
+    serve_connection(
+        stream,
+        engine,
+        context,
+        ClientInfo::next(None),
+        // Dropping the controller leaves a token that never fires.
+        ShutdownController::new().token(),
+    )
+    .await
+}
+
+async fn serve_connection<S, E>(
+    stream: S,
+    engine: Arc<E>,
+    context: ServerContext,
+    client: ClientInfo,
+    mut shutdown: ShutdownToken,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KVEngine + 'static,
+{
+    let ServerContext {
+        metrics,
+        observation_log,
+        persistence,
+        runtime,
+        node_id,
+        listen_addrs,
+        replication,
+        tracker,
+        read_buffers,
+        #[cfg(feature = "scripting")]
+        scripting,
+        ..
+    } = context;
+    let shared_engine: Arc<dyn KVEngine> = engine;
+    let mut stream = stream;
+    let mut buffer = read_buffers.checkout();
+    let mut parser = RespParser::new();
+    let mut replies = ReplyBatch::new(Arc::clone(&metrics));
+    let mut connection = ConnectionCtx::new(client, tracker.as_ref(), &runtime);
+    let mut command_bucket = TokenBucket::new();
+
+    loop {
+        let closing = tokio::select! {
+            read = stream.read_buf(&mut *buffer) => {
+                if read? == 0 {
+                    break;
+                }
+                buffer.record_read();
+                false
+            }
+            () = shutdown.wait() => {
+                // Answer what the client already sent, then close.
+                read_available(&mut stream, &mut buffer).await?;
+                true
+            }
+            () = idle_expired(runtime.idle_timeout()) => {
+                tracing::debug!("closing idle connection");
+                metrics.record_idle_disconnect();
+                break;
+            }
+            push = next_push(&mut connection.tracked) => {
+                let closed = match push {
+                    // Replies are flushed after every read, so a push never
+                    // lands inside a pipelined batch. A client that stops
+                    // reading can block the write, so it races the close.
+                    Some(push) => tokio::select! {
+                        written = stream.write_all(&push) => {
+                            written?;
+                            false
+                        }
+                        () = pushes_closed(connection.tracked.as_ref()) => true,
+                    },
+                    None => true,
+                };
+                if !closed {
+                    continue;
+                }
+                tracing::warn!(
+                    client_id = client.id,
+                    "closing connection past the pubsub output buffer limit"
+                );
+                metrics.record_output_buffer_limit_disconnect();
+                break;
+            }
+        };
+
+        // Answer every complete command in this read, then write the replies
+        // with one syscall so pipelined clients are not charged one per reply.
+        parser.set_limits(runtime.resp_limits());
+        loop {
+            match parser.parse(&mut buffer) {
+                Ok(Some(args)) if is_psync(&args) && replication.is_some() => {
+                    // The connection becomes a replication link from here on.
+                    replies.flush(&mut stream).await?;
+                    let replication = replication.as_deref().expect("checked by the guard");
+                    return replication
+                        .serve_replica(stream, &args, client.id, client.addr, &metrics, shutdown)
+                        .await;
+                }
+                Ok(Some(args)) => {
+                    let cmd_rate = runtime.max_cmd_per_sec();
+                    if cmd_rate > 0 {
+                        let wait = command_bucket.take(cmd_rate, Instant::now());
+                        if !wait.is_zero() {
+                            // Replies already computed go out before the pause.
+                            metrics.record_throttled_command();
+                            replies.flush(&mut stream).await?;
+                            tokio::time::sleep(wait).await;
+                        }
+                    }
+                    metrics.record_request_start();
+                    let started_at = Instant::now();
+                    connection.before_command(&args);
+                    let blocking = args
+                        .first()
+                        .and_then(|name| command_spec(name))
+                        .is_some_and(|spec| spec.blocking);
+                    let response = if blocking {
+                        // The task takes the connection state and hands it
+                        // back with the reply.
+                        let abort = Arc::new(AtomicBool::new(false));
+                        let task = tokio::task::spawn_blocking({
+                            let engine = Arc::clone(&shared_engine);
+                            let metrics = Arc::clone(&metrics);
+                            let persistence = Arc::clone(&persistence);
+                            let runtime = Arc::clone(&runtime);
+                            let node_id = Arc::clone(&node_id);
+                            let listen_addrs = Arc::clone(&listen_addrs);
+                            let replication = replication.clone();
+                            let observation_log = observation_log.clone();
+                            #[cfg(feature = "scripting")]
+                            let scripting = Arc::clone(&scripting);
+                            let abort = Arc::clone(&abort);
+                            let args = args.clone();
+                            move || {
+                                let context = CommandContext {
+                                    engine: engine.as_ref(),
+                                    shared_engine: Some(&engine),
+                                    metrics: &metrics,
+                                    persistence: &persistence,
+                                    runtime: &runtime,
+                                    node_id: &node_id,
+                                    listen_addrs: &listen_addrs,
+                                    replication: replication.as_ref(),
+                                    observation_sink: observation_log_sink(
+                                        observation_log.as_deref(),
+                                    ),
+                                    connection: &connection,
+                                    #[cfg(feature = "scripting")]
+                                    scripting: &scripting,
+                                    abort: Some(&abort),
+                                };
+                                let response = dispatch_command(&args, &context);
+                                (response, connection)
+                            }
+                        });
+                        let (response, returned) =
+                            join_with_time_limit(task, &abort, runtime.command_time_limit())
+                                .await?;
+                        connection = returned;
+                        response
+                    } else {
+                        // Scoped so no borrow in the context is held across an await.
+                        let context = CommandContext {
+                            engine: shared_engine.as_ref(),
+                            shared_engine: Some(&shared_engine),
+                            metrics: &metrics,
+                            persistence: &persistence,
+                            runtime: &runtime,
+                            node_id: &node_id,
+                            listen_addrs: &listen_addrs,
+                            replication: replication.as_ref(),
+                            observation_sink: observation_log_sink(observation_log.as_deref()),
+                            connection: &connection,
+                            #[cfg(feature = "scripting")]
+                            scripting: &scripting,
+                            abort: None,
+                        };
+                        dispatch_command(&args, &context)
+                    };
+                    connection.after_command(&args, response.is_error());
+                    if let Some(access_log) = runtime.access_log() {
+                        access_log.record(
+                            runtime.access_log_sample_rate(),
+                            AccessEntry {
+                                client_id: client.id,
+                                addr: client.addr,
+                                args: &args,
+                                elapsed: started_at.elapsed(),
+                                failed: response.is_error(),
+                            },
+                        );
+                    }
+                    replies.push(started_at, response);
+                    if replies.over_limit(runtime.output_buffer_limit(ClientClass::Normal)) {
+                        tracing::warn!(
+                            client_id = client.id,
+                            queued = replies.queued(),
+                            "closing connection past the normal output buffer limit"
+                        );
+                        metrics.record_output_buffer_limit_disconnect();
+                        return Ok(());
+                    }
+                    if replies.is_full() {
+                        replies.flush(&mut stream).await?;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // The stream cannot be resynchronized; reply and close.
+                    tracing::warn!(error = ?err, "closing connection after protocol error");
+                    metrics.record_request_start();
+                    let started_at = Instant::now();
+                    let response = resp_error(match err {
+                        RespError::Protocol => "protocol error",
+                        RespError::TooLarge => "request too large",
+                    });
+                    replies.push(started_at, response.into());
+                    replies.flush(&mut stream).await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        replies.flush(&mut stream).await?;
+        buffer.maybe_shrink(runtime.read_buffer_shrink_after());
+        if closing {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits for a whole-keyspace command run on `spawn_blocking`, setting
+/// `abort` once `limit` passes. A command that passes the flag to the
+/// engine stops with `Interrupted` and replies `TIME_LIMIT_ERROR`; one that
+/// does not, such as a flush, runs to the end. Either way the task is still
+/// joined, since it holds the connection state. The worker thread is free
+/// meanwhile.
+async fn join_with_time_limit<T>(
+    mut task: tokio::task::JoinHandle<T>,
+    abort: &AtomicBool,
+    limit: Option<Duration>,
+) -> std::io::Result<T> {
+    let joined = match limit {
+        Some(limit) => match tokio::time::timeout(limit, &mut task).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                abort.store(true, Ordering::Relaxed);
+                task.await
+            }
+        },
+        None => task.await,
+    };
+    joined.map_err(std::io::Error::other)
+}
+
+/// The next invalidation push for a tracked connection, or `None` once it
+/// must close; never resolves without a tracker.
+async fn next_push(tracked: &mut Option<TrackedClient>) -> Option<Vec<u8>> {
+    match tracked {
+        Some(tracked) => tracked.next_push().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once the tracker closes the connection; never resolves for a
+/// connection it does not track.
+async fn pushes_closed(tracked: Option<&TrackedClient>) {
+    match tracked {
+        Some(tracked) => tracked.closed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once a connection has waited `timeout` for input; never
+/// resolves when the idle timeout is disabled.
+async fn idle_expired(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Moves everything the stream can yield right now into `buffer` without
+/// waiting for more.
+async fn read_available<S>(stream: &mut S, buffer: &mut BytesMut) -> std::io::Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    // A zero timeout still polls the read once, so ready data (including
+    // records a TLS stream has already decrypted) is taken, and a read that
+    // would block ends the loop.
+    loop {
+        match tokio::time::timeout(Duration::ZERO, stream.read_buf(buffer)).await {
+            Ok(Ok(0)) | Err(_) => return Ok(()),
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => return Err(err),
+        }
+    }
+}
+
+/// Replies buffered before a flush once a pipelined batch grows this large.
+///
+/// The flush is awaited before the next command is parsed, so a client that
+/// pipelines without reading stalls on its own socket instead of growing the
+/// batch; only a single oversized reply can push it past the normal
+/// `client-output-buffer-limit`.
+const REPLY_FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// Replies to the commands parsed from one read, written together.
+///
+/// Large values stay shared with the engine and go out in one vectored
+/// write with the bytes around them.
+///
+/// Requests stay in flight until their reply is written, so latency samples
+/// include the time spent waiting for the batch to flush. Requests still
+/// queued when the batch is dropped, as when its connection task is aborted
+/// mid-write, are completed then so `inflight` never leaks.
+struct ReplyBatch {
+    metrics: Arc<Metrics>,
+    buf: Reply,
+    started: Vec<Instant>,
+    /// When the queued replies first passed the normal soft limit.
+    soft_since: Option<Instant>,
+}
+
+impl ReplyBatch {
+    fn new(metrics: Arc<Metrics>) -> Self {
+        ReplyBatch {
+            metrics,
+            buf: Reply::default(),
+            started: Vec::new(),
+            soft_since: None,
+        }
+    }
+
+    /// Queues `response` for the request that started at `started_at`.
+    fn push(&mut self, started_at: Instant, response: Reply) {
+        if response.is_error() {
+            self.metrics.record_error(error_prefix(response.head()));
+        }
+        self.buf.append(response);
+        self.started.push(started_at);
+    }
+
+    /// Bytes of replies waiting to be written.
+    fn queued(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns true once the queued replies break `limit`.
+    fn over_limit(&mut self, limit: OutputBufferLimit) -> bool {
+        let queued = u64::try_from(self.buf.len()).unwrap_or(u64::MAX);
+        limit.exceeded(queued, &mut self.soft_since, Instant::now())
+    }
+
+    /// Returns true once the batch should be flushed before parsing more.
+    fn is_full(&self) -> bool {
+        self.buf.len() >= REPLY_FLUSH_THRESHOLD
+    }
+
+    /// Writes every queued reply, then completes their requests.
+    async fn flush<W>(&mut self, out: &mut W) -> std::io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.started.is_empty() {
+            return Ok(());
+        }
+        let write_result = write_reply(out, &self.buf).await;
+        self.finish(write_result)
+    }
+
+    /// Completes every queued request, even when the write failed.
+    fn finish(&mut self, write_result: std::io::Result<()>) -> std::io::Result<()> {
+        self.complete_queued();
+        self.buf.clear();
+        write_result
+    }
+
+    fn complete_queued(&mut self) {
+        for started_at in self.started.drain(..) {
+            self.metrics.record_request_end(started_at.elapsed());
+        }
+    }
+}
+
+impl Drop for ReplyBatch {
+    fn drop(&mut self) {
+        self.complete_queued();
+    }
+}
+
+/// Runs a client command; with scripting, only while no script holds the
+/// gate.
+fn dispatch_command(args: &[Bytes], context: &CommandContext<'_>) -> Reply {
+    #[cfg(feature = "scripting")]
+    if let Some(name) = args.first() {
+        return context
+            .scripting
+            .run_command(name, || run_command(args, context));
+    }
+    run_command(args, context)
+}
+
+/// Runs a command from a client or a script, with its span, metrics and
+/// replication.
+fn run_command(args: &[Bytes], context: &CommandContext<'_>) -> Reply {
+    if args.is_empty() {
+        return resp_error("empty command").into();
+    }
+
+    // Span fields are recorded lazily, so a disabled span costs a level check.
+    let span = tracing::debug_span!(
+        "command",
+        name = command_name(&args[0]),
+        db = 0,
+        keys = tracing::field::Empty,
+        duration_us = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+    );
+    let _entered = span.enter();
+    if tracing::enabled!(Level::TRACE) {
+        tracing::trace!(args = %LoggedArgs(args), "executing command");
+    }
+
+    let started_at = Instant::now();
+    let spec = command_spec(&args[0]);
+    if !span.is_disabled() {
+        span.record("keys", spec.map_or(0, |spec| spec.keys_of(args).count()));
+    }
+    let execute = || execute_command(spec, args, context);
+    let response = match context.replication {
+        Some(replication) if spec.is_some_and(|spec| spec.has(CommandFlag::Write)) => {
+            // Write replies are small, so flattening them costs little.
+            replication
+                .propagate_with(args, || execute().into_vec())
+                .into()
+        }
+        _ => execute(),
+    };
+    let elapsed = started_at.elapsed();
+    let failed = response.is_error();
+    context.metrics.record_command(&args[0], elapsed, failed);
+    context.metrics.latency_monitor().observe(
+        context.runtime.latency_monitor_threshold(),
+        command_name(&args[0]),
+        elapsed,
+    );
+    span.record("duration_us", elapsed.as_micros() as u64);
+    span.record("outcome", if failed { "error" } else { "ok" });
+    response
+}
+
+/// Validates `args` against its command table entry, then runs it.
+fn execute_command(
+    spec: Option<&CommandSpec>,
+    args: &[Bytes],
+    context: &CommandContext<'_>,
+) -> Reply {
+    let Some(spec) = spec else {
+        return resp_error("unknown command").into();
+    };
+    // AUTH is how a connection gets a user, so it needs neither.
+    let exempt = spec.name == "auth";
+    let acl = context.runtime.acl();
+    if !exempt && context.connection.user(acl).is_none() {
+        return NOAUTH_ERROR.to_vec().into();
+    }
+    if !spec.accepts(args.len()) {
+        return arity_error(spec.name).into();
+    }
+    if !exempt
+        && let Some(user) = context.connection.user(acl).as_deref()
+        && let Err(denied) = user.permit(spec, args)
+    {
+        return format!("-{denied}\r\n").into_bytes().into();
+    }
+    if context.runtime.lifecycle().phase() == LifecyclePhase::Loading
+        && (spec.has(CommandFlag::Write) || spec.has(CommandFlag::Readonly))
+    {
+        return LOADING_ERROR.to_vec().into();
+    }
+    if context.runtime.read_only() && spec.has(CommandFlag::Write) {
+        return READONLY_ERROR.to_vec().into();
+    }
+    match spec.handler {
+        Handler::Bytes(handler) => handler(args, context).into(),
+        Handler::Reply(handler) => handler(args, context),
+    }
+}
+
+/// The reply to a call with too few or too many arguments, worded as in
+/// Redis.
+fn arity_error(name: &str) -> Vec<u8> {
+    resp_error(&format!("wrong number of arguments for '{name}' command"))
+}
+
+/// Runs a data command, recording what it did for the observation sink.
+fn observed<R: Into<Reply>>(
+    args: &[Bytes],
+    context: &CommandContext<'_>,
+    handler: fn(&[Bytes], &dyn KVEngine) -> R,
+) -> Reply {
+    observe_command_result(context.observation_sink, planned_observations(args), || {
+        handler(args, context.engine).into()
+    })
+}
+
+fn handle_ping(args: &[Bytes]) -> Vec<u8> {
+    match args {
+        [_, message] => resp_bulk(message),
+        _ => resp_simple("PONG"),
+    }
+}
+
+fn handle_get(args: &[Bytes], engine: &dyn KVEngine) -> Reply {
+    match engine.get(&args[1]) {
+        Ok(Some(value)) => Reply::bulk(value),
+        Ok(None) => resp_null().into(),
+        Err(err) => engine_error(err).into(),
+    }
+}
+
+/// `MGET key...`, with a null for each missing key.
+fn handle_mget(args: &[Bytes], engine: &dyn KVEngine) -> Reply {
+    let values: HkvResult<Vec<_>> = args[1..].iter().map(|key| engine.get(key)).collect();
+    match values {
+        Ok(values) => Reply::bulk_array(values),
+        Err(err) => engine_error(err).into(),
+    }
+}
+
+/// Hands the key and value to the engine as slices of the request, so the
+/// engine's own copy is the only one made.
+fn handle_set(args: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    let ttl = match args.len() {
+        3 => None,
+        5 if eq_ignore_ascii_case(&args[3], b"EX") => match parse_u64(&args[4]) {
+            Ok(seconds) => Some(TtlAfter::from_secs(seconds)),
+            Err(resp) => return resp,
+        },
+        _ => return resp_error("unsupported SET options"),
+    };
+
+    match engine.set_borrowed(&args[1], &args[2], ttl) {
+        Ok(()) => resp_simple("OK"),
+        Err(err) => engine_error(err),
+    }
+}
+
+fn handle_del(args: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    let mut removed = 0i64;
+    for key in &args[1..] {
+        match engine.delete(key) {
+            Ok(true) => removed += 1,
+            Ok(false) => {}
+            Err(err) => return engine_error(err),
+        }
+    }
+
+    resp_integer(removed)
+}
+
+fn handle_expire(args: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    let seconds = match parse_u64(&args[2]) {
+        Ok(value) => value,
+        Err(resp) => return resp,
+    };
+
+    match engine.expire(&args[1], TtlAfter::from_secs(seconds)) {
+        Ok(()) => resp_integer(1),
+        Err(HkvError::NotFound) => resp_integer(0),
+        Err(err) => engine_error(err),
+    }
+}
+
+fn handle_ttl(args: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    match engine.ttl(&args[1]) {
+        Ok(TtlStatus::Missing) => resp_integer(-2),
+        Ok(TtlStatus::NoExpiry) => resp_integer(-1),
+        Ok(TtlStatus::ExpiresIn(remaining)) => resp_integer(remaining.as_secs() as i64),
+        Err(err) => engine_error(err),
+    }
+}
+
+/// Answers `INFO [section]`: the default stats, `server`, `memory`,
+/// `commandstats`, `errorstats`, `replication`, `keyspace`, `kernel_cache`,
+/// or all of them for `all`/`everything`.
+/// Unknown sections are empty, as in Redis.
+fn handle_info(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    let (runtime, replication) = (context.runtime, context.replication);
+    let snapshot = context.metrics.snapshot();
+    let role = match replication {
+        Some(replication) if replication.is_replica() => "slave",
+        _ => "master",
+    };
+    let replication_info = || match replication {
+        Some(replication) => replication.info(),
+        None => "# Replication\r\nrole:master\r\nconnected_slaves:0\r\n".to_string(),
+    };
+    let info = match args {
+        [_] => default_info(&snapshot, runtime, role),
+        [_, section] if eq_ignore_ascii_case(section, b"DEFAULT") => {
+            default_info(&snapshot, runtime, role)
+        }
+        [_, section] if eq_ignore_ascii_case(section, b"SERVER") => {
+            server_info(context.listen_addrs)
+        }
+        [_, section] if eq_ignore_ascii_case(section, b"MEMORY") => {
+            memory_info(&snapshot, runtime, context.engine)
+        }
+        [_, section] if eq_ignore_ascii_case(section, b"COMMANDSTATS") => {
+            command_stats_info(&snapshot)
+        }
+        [_, section] if eq_ignore_ascii_case(section, b"ERRORSTATS") => error_stats_info(&snapshot),
+        [_, section] if eq_ignore_ascii_case(section, b"REPLICATION") => replication_info(),
+        [_, section] if eq_ignore_ascii_case(section, b"KEYSPACE") => keyspace_info(context.engine),
+        [_, section] if eq_ignore_ascii_case(section, b"KERNEL_CACHE") => {
+            kernel_cache_info(runtime)
+        }
+        [_, section]
+            if eq_ignore_ascii_case(section, b"ALL")
+                || eq_ignore_ascii_case(section, b"EVERYTHING") =>
+        {
+            format!(
+                "{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}",
+                default_info(&snapshot, runtime, role),
+                server_info(context.listen_addrs),
+                memory_info(&snapshot, runtime, context.engine),
+                command_stats_info(&snapshot),
+                error_stats_info(&snapshot),
+                replication_info(),
+                keyspace_info(context.engine),
+                kernel_cache_info(runtime)
+            )
+        }
+        _ => String::new(),
+    };
+    resp_bulk(info.as_bytes())
+}
+
+/// The listening addresses, one `listenerN` line each in the format of
+/// Redis 7, and the first one's port as `tcp_port`.
+fn server_info(listen_addrs: &[SocketAddr]) -> String {
+    let mut info = String::from("# Server\r\n");
+    let port = listen_addrs.first().map_or(0, SocketAddr::port);
+    info.push_str(&format!("tcp_port:{port}\r\n"));
+    for (index, addr) in listen_addrs.iter().enumerate() {
+        info.push_str(&format!(
+            "listener{index}:name=tcp,bind={},port={}\r\n",
+            addr.ip(),
+            addr.port()
+        ));
+    }
+    info
+}
+
+/// Engine memory against `maxmemory`, and the bytes held by connection read
+/// buffers and pooled for new connections.
+fn memory_info(
+    snapshot: &MetricsSnapshot,
+    runtime: &RuntimeConfig,
+    engine: &dyn KVEngine,
+) -> String {
+    format!(
+        concat!(
+            "# Memory\r\n",
+            "used_memory:{}\r\n",
+            "maxmemory:{}\r\n",
+            "mem_read_buffers:{}\r\n",
+            "mem_read_buffer_pool:{}\r\n"
+        ),
+        engine.stats().used_bytes,
+        runtime.max_memory(),
+        snapshot.read_buffer_bytes,
+        snapshot.read_buffer_pool_bytes,
+    )
+}
+
+/// Lines for every command called at least once, in Redis's format.
+fn command_stats_info(snapshot: &MetricsSnapshot) -> String {
+    let mut info = String::from("# Commandstats\r\n");
+    for command in snapshot.commands.iter().filter(|command| command.calls > 0) {
+        info.push_str(&format!(
+            "cmdstat_{}:calls={},usec={},usec_per_call={:.2},failed_calls={}\r\n",
+            command.name,
+            command.calls,
+            command.usec,
+            command.usec_per_call(),
+            command.failed_calls,
+        ));
+    }
+    info
+}
+
+/// Lines for every error prefix replied at least once, in Redis's format.
+fn error_stats_info(snapshot: &MetricsSnapshot) -> String {
+    let mut info = String::from("# Errorstats\r\n");
+    for (prefix, count) in snapshot.errors.iter().filter(|(_, count)| *count > 0) {
+        info.push_str(&format!("errorstat_{prefix}:count={count}\r\n"));
+    }
+    info
+}
+
+/// The `db0` line of Redis's keyspace section, omitted while empty.
+fn keyspace_info(engine: &dyn KVEngine) -> String {
+    let mut info = String::from("# Keyspace\r\n");
+    let stats = engine.stats();
+    if stats.keys > 0 {
+        info.push_str(&format!(
+            "db0:keys={},expires={},avg_ttl={}\r\n",
+            stats.keys,
+            stats.expires,
+            engine.avg_ttl_ms()
+        ));
+    }
+    info
+}
+
+/// The kernel cache client's health, then the module's limits as its sysfs
+/// attributes report them, or `loaded:0` when the module is not loaded.
+fn kernel_cache_info(runtime: &RuntimeConfig) -> String {
+    let mut info = format!("# Kernel_cache\r\nstatus:{}\r\n", runtime.kernel_health());
+    match KernelTunables::read() {
+        Ok(tunables) => info.push_str(&format!(
+            concat!(
+                "loaded:1\r\n",
+                "max_bytes:{}\r\n",
+                "max_entries:{}\r\n",
+                "default_ttl_override:{}\r\n"
+            ),
+            tunables.max_bytes, tunables.max_entries, tunables.default_ttl_override,
+        )),
+        Err(_) => info.push_str("loaded:0\r\n"),
+    }
+    info
+}
+
+fn default_info(snapshot: &MetricsSnapshot, runtime: &RuntimeConfig, role: &str) -> String {
+    let latency = snapshot.latency.summary();
+    format!(
+        concat!(
+            "role:{}\r\n",
+            "engine:hybridkv\r\n",
+            "requests_total:{}\r\n",
+            "errors_total:{}\r\n",
+            "inflight:{}\r\n",
+            "connected_clients:{}\r\n",
+            "maxclients:{}\r\n",
+            "accepted_connections_total:{}\r\n",
+            "rejected_connections_total:{}\r\n",
+            "idle_disconnects_total:{}\r\n",
+            "output_buffer_limit_disconnects_total:{}\r\n",
+            "tls_handshake_failures_total:{}\r\n",
+            "throttled_commands_total:{}\r\n",
+            "rate_limited_connections_total:{}\r\n",
+            "uptime_sec:{:.3}\r\n",
+            "qps_avg:{:.3}\r\n",
+            "instantaneous_ops_per_sec:{:.3}\r\n",
+            "error_rate:{:.3}\r\n",
+            "latency_samples:{}\r\n",
+            "latency_avg_us:{:.3}\r\n",
+            "latency_max_us:{}\r\n",
+            "latency_p50_us:{}\r\n",
+            "latency_p90_us:{}\r\n",
+            "latency_p99_us:{}\r\n",
+            "latency_p999_us:{}\r\n"
+        ),
+        role,
+        snapshot.requests_total,
+        snapshot.errors_total,
+        snapshot.inflight,
+        snapshot.connected_clients,
+        runtime.max_clients(),
+        snapshot.accepted_connections_total,
+        snapshot.rejected_connections_total,
+        snapshot.idle_disconnects_total,
+        snapshot.output_buffer_limit_disconnects_total,
+        snapshot.tls_handshake_failures_total,
+        snapshot.throttled_commands_total,
+        snapshot.rate_limited_connections_total,
+        snapshot.uptime.as_secs_f64(),
+        snapshot.qps(),
+        snapshot.instantaneous_ops_per_sec,
+        snapshot.error_rate(),
+        snapshot.latency.samples,
+        latency.mean_us,
+        snapshot.latency.max_us,
+        latency.p50_us,
+        latency.p90_us,
+        latency.p99_us,
+        latency.p999_us,
+    )
+}
+
+fn handle_save(engine: &dyn KVEngine, persistence: &Persistence) -> Vec<u8> {
+    if persistence.bgsave_in_progress() {
+        return resp_error("background save already in progress");
+    }
+
+    match persistence.save(engine) {
+        Ok(()) => resp_simple("OK"),
+        Err(err) => {
+            tracing::warn!(error = %err, "SAVE failed");
+            resp_error("snapshot write failed")
+        }
+    }
+}
+
+/// `BGSAVE`, reporting a slow write to the latency monitor under the
+/// threshold in force when it started.
+fn handle_bgsave(_: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    let Some(engine) = context.shared_engine else {
+        return resp_error("BGSAVE is not available here");
+    };
+    let latency = Arc::clone(context.metrics.latency_monitor());
+    let threshold = context.runtime.latency_monitor_threshold();
+    let started = context
+        .persistence
+        .start_bgsave(Arc::clone(engine), move |elapsed| {
+            latency.observe(threshold, BGSAVE_EVENT, elapsed)
+        });
+    match started {
+        BgsaveStatus::Started => resp_simple("Background saving started"),
+        BgsaveStatus::AlreadyInProgress => resp_error("background save already in progress"),
+    }
+}
+
+/// `LATENCY LATEST`, `LATENCY HISTORY event` and `LATENCY RESET [event...]`,
+/// with Redis's reply shapes.
+fn handle_latency(args: &[Bytes], monitor: &LatencyMonitor) -> Vec<u8> {
+    match args {
+        [_, sub] if eq_ignore_ascii_case(sub, b"LATEST") => {
+            let latest = monitor.latest();
+            let mut out = format!("*{}\r\n", latest.len()).into_bytes();
+            for entry in latest {
+                out.extend_from_slice(b"*4\r\n");
+                out.extend_from_slice(&resp_bulk(entry.event.as_bytes()));
+                out.extend_from_slice(&resp_integer(entry.latest.time as i64));
+                out.extend_from_slice(&resp_integer(entry.latest.millis as i64));
+                out.extend_from_slice(&resp_integer(entry.max_millis as i64));
+            }
+            out
+        }
+        [_, sub, event] if eq_ignore_ascii_case(sub, b"HISTORY") => {
+            let history = std::str::from_utf8(event)
+                .map(|event| monitor.history(event))
+                .unwrap_or_default();
+            let mut out = format!("*{}\r\n", history.len()).into_bytes();
+            for sample in history {
+                out.extend_from_slice(b"*2\r\n");
+                out.extend_from_slice(&resp_integer(sample.time as i64));
+                out.extend_from_slice(&resp_integer(sample.millis as i64));
             }
-            accept = listener.accept() => {
-                let (stream, _) = accept?;
-                configure_accepted_stream(&stream, config)?;
-                let engine = Arc::clone(&engine);
-                let metrics = Arc::clone(&metrics);
-                let observation_log = observation_log.as_ref().map(Arc::clone);
-                connections.spawn(async move {
-                    handle_connection_with_observation(stream, engine, metrics, observation_log).await
-                });
+            out
+        }
+        [_, sub, events @ ..] if eq_ignore_ascii_case(sub, b"RESET") => {
+            let events: Vec<_> = events
+                .iter()
+                .filter_map(|event| std::str::from_utf8(event).ok())
+                .collect();
+            // Names that are not UTF-8 name no event; do not let them turn a
+            // targeted reset into a reset of everything.
+            if events.is_empty() && args.len() > 2 {
+                return resp_integer(0);
             }
+            resp_integer(monitor.reset(events) as i64)
         }
+        [_, sub, ..]
+            if [&b"LATEST"[..], b"HISTORY"]
+                .iter()
+                .any(|name| eq_ignore_ascii_case(sub, name)) =>
+        {
+            arity_error("latency")
+        }
+        _ => resp_error("unsupported LATENCY subcommand"),
     }
+}
 
-    drop(listener);
+/// `DEBUG SLEEP seconds`: makes a slow command on purpose. It runs on the
+/// blocking pool, so only the calling connection waits.
+fn handle_debug(args: &[Bytes]) -> Vec<u8> {
+    match args {
+        [_, sub, seconds] if eq_ignore_ascii_case(sub, b"SLEEP") => {
+            let seconds = std::str::from_utf8(seconds)
+                .ok()
+                .and_then(|seconds| seconds.parse::<f64>().ok())
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+            match seconds {
+                Some(duration) => {
+                    std::thread::sleep(duration);
+                    resp_simple("OK")
+                }
+                None => resp_error("value is not a valid float"),
+            }
+        }
+        [_, sub, ..] if eq_ignore_ascii_case(sub, b"SLEEP") => arity_error("debug"),
+        _ => resp_error("unsupported DEBUG subcommand"),
+    }
+}
 
-    let drain = async {
-        while let Some(join_result) = connections.join_next().await {
-            reap_connection_task(join_result);
+/// `KEYS pattern`, abandoned past `command-time-limit`.
+fn handle_keys(args: &[Bytes], context: &CommandContext<'_>) -> Reply {
+    match context
+        .engine
+        .keys(context.abort)
+        .map_err(|err| keyspace_error(err, context))
+    {
+        Ok(keys) => {
+            let matching: Vec<_> = keys
+                .into_iter()
+                .filter(|key| glob_match(&args[1], key))
+                .map(Some)
+                .collect();
+            Reply::bulk_array(matching)
         }
-    };
+        Err(reply) => reply.into(),
+    }
+}
 
-    if tokio::time::timeout(config.shutdown_drain_timeout, drain)
-        .await
-        .is_err()
+/// `FLUSHALL [ASYNC|SYNC]` and `FLUSHDB [ASYNC|SYNC]`; there is one
+/// database, and both modes flush before replying. The flush ignores
+/// `command-time-limit`: a flush stopped part way would already have
+/// emptied some shards, yet its error reply would keep it from replicas and
+/// tracking clients.
+fn handle_flush(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    if let [_, mode] = args
+        && !eq_ignore_ascii_case(mode, b"ASYNC")
+        && !eq_ignore_ascii_case(mode, b"SYNC")
     {
-        connections.abort_all();
-        while let Some(join_result) = connections.join_next().await {
-            reap_connection_task(join_result);
+        return resp_error("syntax error");
+    }
+    match context.engine.flush(None) {
+        Ok(_) => resp_simple("OK"),
+        Err(err) => engine_error(err),
+    }
+}
+
+/// The reply to a failed whole-keyspace command: `TIME_LIMIT_ERROR` when
+/// the engine stopped because the abort flag was set, the `engine_error`
+/// reply otherwise.
+fn keyspace_error(err: HkvError, context: &CommandContext<'_>) -> Vec<u8> {
+    match err {
+        HkvError::Interrupted
+            if context
+                .abort
+                .is_some_and(|abort| abort.load(Ordering::Relaxed)) =>
+        {
+            TIME_LIMIT_ERROR.to_vec()
         }
+        err => engine_error(err),
     }
+}
 
-    Ok(())
+/// `LOLWUT [VERSION n]`. There is only one drawing, so every argument is
+/// accepted and ignored and the command never fails.
+fn handle_lolwut() -> Vec<u8> {
+    resp_bulk(LOLWUT_ART.as_bytes())
 }
 
-/// Handles a single TCP client connection with shared server metrics.
-pub async fn handle_connection_with_metrics<E>(
-    stream: TcpStream,
-    engine: Arc<E>,
-    metrics: Arc<Metrics>,
-) -> std::io::Result<()>
-where
-    E: KVEngine,
-{
-    handle_connection_with_observation(stream, engine, metrics, None).await
+/// `EVAL script numkeys key... arg...`.
+#[cfg(feature = "scripting")]
+fn handle_eval(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    context.scripting.eval(
+        &args[1],
+        &args[2..],
+        context.runtime.lua_time_limit(),
+        &|call| run_command(call, context).into_vec(),
+    )
 }
 
-pub async fn handle_connection_with_observation<E>(
-    stream: TcpStream,
-    engine: Arc<E>,
-    metrics: Arc<Metrics>,
-    observation_log: Option<Arc<SharedObservationLog>>,
-) -> std::io::Result<()>
-where
-    E: KVEngine,
-{
-    let mut stream = stream;
-    let mut buffer = BytesMut::with_capacity(8 * 1024);
-    let mut parser = RespParser::new();
+/// `EVALSHA sha1 numkeys key... arg...`.
+#[cfg(feature = "scripting")]
+fn handle_evalsha(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    context.scripting.evalsha(
+        &args[1],
+        &args[2..],
+        context.runtime.lua_time_limit(),
+        &|call| run_command(call, context).into_vec(),
+    )
+}
 
-    loop {
-        let bytes = stream.read_buf(&mut buffer).await?;
-        if bytes == 0 {
-            break;
+/// `SCRIPT LOAD|EXISTS|FLUSH|KILL`.
+#[cfg(feature = "scripting")]
+fn handle_script(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    let scripting = context.scripting;
+    match args {
+        [_, sub, body] if eq_ignore_ascii_case(sub, b"LOAD") => {
+            resp_bulk(scripting.load(body).as_bytes())
         }
-
-        loop {
-            match parser.parse(&mut buffer) {
-                Ok(Some(args)) => {
-                    metrics.record_request_start();
-                    let started_at = Instant::now();
-                    let response = dispatch_command(
-                        &args,
-                        engine.as_ref(),
-                        metrics.as_ref(),
-                        observation_log_sink(observation_log.as_deref()),
-                    );
-                    let write_result = stream.write_all(&response).await;
-                    finish_tracked_request(metrics.as_ref(), started_at, &response, write_result)?;
-                }
-                Ok(None) => break,
-                Err(RespError::Protocol) => {
-                    metrics.record_request_start();
-                    let started_at = Instant::now();
-                    let response = resp_error("protocol error");
-                    let write_result = stream.write_all(&response).await;
-                    finish_tracked_request(metrics.as_ref(), started_at, &response, write_result)?;
-                    return Ok(());
-                }
+        [_, sub, shas @ ..] if eq_ignore_ascii_case(sub, b"EXISTS") && !shas.is_empty() => {
+            let mut buf = format!("*{}\r\n", shas.len()).into_bytes();
+            for sha in shas {
+                buf.extend_from_slice(&resp_integer(i64::from(scripting.exists(sha))));
             }
+            buf
+        }
+        [_, sub] if eq_ignore_ascii_case(sub, b"FLUSH") => {
+            scripting.flush();
+            resp_simple("OK")
+        }
+        [_, sub, mode]
+            if eq_ignore_ascii_case(sub, b"FLUSH")
+                && (eq_ignore_ascii_case(mode, b"ASYNC")
+                    || eq_ignore_ascii_case(mode, b"SYNC")) =>
+        {
+            scripting.flush();
+            resp_simple("OK")
+        }
+        [_, sub] if eq_ignore_ascii_case(sub, b"KILL") => scripting.kill(),
+        [_, sub, ..]
+            if [&b"LOAD"[..], b"EXISTS", b"FLUSH", b"KILL"]
+                .iter()
+                .any(|name| eq_ignore_ascii_case(sub, name)) =>
+        {
+            arity_error("script")
         }
+        _ => resp_error("unsupported SCRIPT subcommand"),
     }
+}
 
-    Ok(())
+/// Stub `EVAL` and `EVALSHA` for builds without the `scripting` feature.
+#[cfg(not(feature = "scripting"))]
+fn handle_eval(_: &[Bytes], _: &CommandContext<'_>) -> Vec<u8> {
+    resp_error("Lua scripting is not supported in this build")
 }
 
-fn dispatch_command(
-    args: &[Vec<u8>],
-    engine: &impl KVEngine,
-    metrics: &Metrics,
-    observation_sink: Option<&dyn ExperimentObservationSink>,
-) -> Vec<u8> {
-    if args.is_empty() {
-        return resp_error("empty command");
-    }
+#[cfg(not(feature = "scripting"))]
+fn handle_evalsha(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    handle_eval(args, context)
+}
 
-    let cmd = &args[0];
-    if eq_ignore_ascii_case(cmd, b"PING") {
-        return handle_ping(args);
-    }
-    if eq_ignore_ascii_case(cmd, b"GET") {
-        return observe_command_result(observation_sink, planned_observations(args), || {
-            handle_get(args, engine)
-        });
-    }
-    if eq_ignore_ascii_case(cmd, b"SET") {
-        return observe_command_result(observation_sink, planned_observations(args), || {
-            handle_set(args, engine)
-        });
-    }
-    if eq_ignore_ascii_case(cmd, b"DEL") {
-        return observe_command_result(observation_sink, planned_observations(args), || {
-            handle_del(args, engine)
-        });
-    }
-    if eq_ignore_ascii_case(cmd, b"EXPIRE") {
-        return observe_command_result(observation_sink, planned_observations(args), || {
-            handle_expire(args, engine)
-        });
-    }
-    if eq_ignore_ascii_case(cmd, b"TTL") {
-        return observe_command_result(observation_sink, planned_observations(args), || {
-            handle_ttl(args, engine)
-        });
-    }
-    if eq_ignore_ascii_case(cmd, b"INFO") {
-        return handle_info(metrics);
+/// `SCRIPT EXISTS` and `SCRIPT FLUSH` for clients probing for scripting:
+/// no script is ever cached, so nothing exists and flushing is a no-op.
+#[cfg(not(feature = "scripting"))]
+fn handle_script(args: &[Bytes], _: &CommandContext<'_>) -> Vec<u8> {
+    match args {
+        [_, sub, shas @ ..] if eq_ignore_ascii_case(sub, b"EXISTS") && !shas.is_empty() => {
+            let mut buf = format!("*{}\r\n", shas.len()).into_bytes();
+            for _ in shas {
+                buf.extend_from_slice(&resp_integer(0));
+            }
+            buf
+        }
+        [_, sub] if eq_ignore_ascii_case(sub, b"FLUSH") => resp_simple("OK"),
+        [_, sub, mode]
+            if eq_ignore_ascii_case(sub, b"FLUSH")
+                && (eq_ignore_ascii_case(mode, b"ASYNC")
+                    || eq_ignore_ascii_case(mode, b"SYNC")) =>
+        {
+            resp_simple("OK")
+        }
+        [_, sub, ..]
+            if eq_ignore_ascii_case(sub, b"EXISTS") || eq_ignore_ascii_case(sub, b"FLUSH") =>
+        {
+            arity_error("script")
+        }
+        _ => resp_error("unsupported SCRIPT subcommand"),
     }
-
-    resp_error("unknown command")
 }
 
-fn handle_ping(args: &[Vec<u8>]) -> Vec<u8> {
-    match args.len() {
-        1 => resp_simple("PONG"),
-        2 => resp_bulk(&args[1]),
-        _ => resp_error("wrong number of arguments for PING"),
+/// `FUNCTION LIST`, which is always empty without scripting.
+fn handle_function(args: &[Bytes]) -> Vec<u8> {
+    match args {
+        [_, sub, ..] if eq_ignore_ascii_case(sub, b"LIST") => resp_array(&[]),
+        _ => resp_error("unsupported FUNCTION subcommand"),
     }
 }
 
-fn handle_get(args: &[Vec<u8>], engine: &impl KVEngine) -> Vec<u8> {
-    if args.len() != 2 {
-        return resp_error("wrong number of arguments for GET");
+/// `REPLICAOF host port` starts replicating and turns on read-only mode;
+/// `REPLICAOF NO ONE` stops and turns it off again.
+fn handle_replicaof(
+    args: &[Bytes],
+    replication: Option<&Arc<Replication>>,
+    runtime: &RuntimeConfig,
+) -> Vec<u8> {
+    let [_, host, port] = args else {
+        return arity_error("replicaof");
+    };
+    let Some(replication) = replication else {
+        return resp_error("REPLICAOF is only supported on a listening server");
+    };
+    if eq_ignore_ascii_case(host, b"NO") && eq_ignore_ascii_case(port, b"ONE") {
+        if replication.stop_replicating() {
+            runtime.set_read_only(false);
+        }
+        return resp_simple("OK");
     }
-    match engine.get(&args[1]) {
-        Ok(Some(value)) => resp_bulk(&value),
-        Ok(None) => resp_null(),
-        Err(_) => resp_error("engine error"),
+    let port = match parse_u64(port) {
+        Ok(port) => match u16::try_from(port) {
+            Ok(port) => port,
+            Err(_) => return resp_error("invalid master port"),
+        },
+        Err(resp) => return resp,
+    };
+    let Ok(host) = String::from_utf8(host.to_vec()) else {
+        return resp_error("invalid master host");
+    };
+    runtime.set_read_only(true);
+    replication.replicate_from(host, port);
+    resp_simple("OK")
+}
+
+/// Applies a command from a master's replication stream. Read-only mode
+/// does not apply, and only the commands a master propagates are accepted.
+pub(crate) fn apply_replicated_command(args: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    let Some(spec) = args.first().and_then(|cmd| command_spec(cmd)) else {
+        return resp_error("unsupported command in replication stream");
+    };
+    let handler: fn(&[Bytes], &dyn KVEngine) -> Vec<u8> = match spec.name {
+        "set" => handle_set,
+        "del" => handle_del,
+        "expire" => handle_expire,
+        "flushall" | "flushdb" => handle_replicated_flush,
+        "ping" => return resp_simple("PONG"),
+        _ => return resp_error("unsupported command in replication stream"),
+    };
+    if !spec.accepts(args.len()) {
+        return arity_error(spec.name);
     }
+    handler(args, engine)
 }
 
-fn handle_set(args: &[Vec<u8>], engine: &impl KVEngine) -> Vec<u8> {
-    if args.len() < 3 {
-        return resp_error("wrong number of arguments for SET");
+/// A master's `FLUSHALL` or `FLUSHDB`, applied whatever its mode.
+fn handle_replicated_flush(_: &[Bytes], engine: &dyn KVEngine) -> Vec<u8> {
+    match engine.flush(None) {
+        Ok(_) => resp_simple("OK"),
+        Err(err) => engine_error(err),
     }
+}
 
-    let key = args[1].clone();
-    let value = args[2].clone();
+/// Whether `args` is a `PSYNC` request from a replica.
+fn is_psync(args: &[Bytes]) -> bool {
+    args.first()
+        .is_some_and(|cmd| eq_ignore_ascii_case(cmd, b"PSYNC"))
+}
 
-    if args.len() == 3 {
-        if engine.set(key, value).is_ok() {
-            return resp_simple("OK");
-        }
-        return resp_error("engine error");
+/// `CLUSTER INFO`, `CLUSTER MYID` and `CLUSTER NODES` for cluster-aware
+/// clients probing the topology: cluster mode is disabled and this node
+/// knows no others.
+fn handle_cluster(args: &[Bytes], node_id: &str) -> Vec<u8> {
+    match args {
+        [_, sub] if eq_ignore_ascii_case(sub, b"INFO") => resp_bulk(CLUSTER_INFO.as_bytes()),
+        [_, sub] if eq_ignore_ascii_case(sub, b"MYID") => resp_bulk(node_id.as_bytes()),
+        [_, sub] if eq_ignore_ascii_case(sub, b"NODES") => resp_bulk(b""),
+        _ => resp_error("unsupported CLUSTER subcommand"),
     }
+}
 
-    if args.len() == 5 && eq_ignore_ascii_case(&args[3], b"EX") {
-        let seconds = match parse_u64(&args[4]) {
-            Ok(value) => value,
+/// `HELLO [protover]`: switches the connection to RESP2 or RESP3 and
+/// describes the server. Only this reply and invalidation pushes use RESP3
+/// types; other replies keep RESP2 encodings, which RESP3 clients accept.
+fn handle_hello(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    match args {
+        [_] => {}
+        [_, version] => match parse_u64(version) {
+            Ok(version @ (2 | 3)) => context.connection.set_protocol(version as u8),
+            Ok(_) => return b"-NOPROTO unsupported protocol version\r\n".to_vec(),
             Err(resp) => return resp,
-        };
+        },
+        _ => return resp_error("HELLO supports only a protocol version, not AUTH or SETNAME"),
+    }
+    let protocol = context.connection.protocol();
+    let role = match context.replication {
+        Some(replication) if replication.is_replica() => "slave",
+        _ => "master",
+    };
 
-        if engine
-            .set_with_ttl(key, value, Duration::from_secs(seconds))
-            .is_err()
-        {
-            return resp_error("engine error");
-        }
+    let fields: [(&str, Vec<u8>); 7] = [
+        ("server", resp_bulk(b"hybridkv")),
+        ("version", resp_bulk(env!("CARGO_PKG_VERSION").as_bytes())),
+        ("proto", resp_integer(i64::from(protocol))),
+        ("id", resp_integer(context.connection.id() as i64)),
+        ("mode", resp_bulk(b"standalone")),
+        ("role", resp_bulk(role.as_bytes())),
+        ("modules", b"*0\r\n".to_vec()),
+    ];
+    resp_map(protocol, &fields)
+}
 
-        return resp_simple("OK");
+/// A map of `fields` with encoded values: a RESP3 map, or a flat array of
+/// names and values in RESP2.
+fn resp_map(protocol: u8, fields: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut out = match protocol {
+        3 => format!("%{}\r\n", fields.len()),
+        _ => format!("*{}\r\n", fields.len() * 2),
     }
-
-    resp_error("unsupported SET options")
+    .into_bytes();
+    for (name, value) in fields {
+        out.extend_from_slice(&resp_bulk(name.as_bytes()));
+        out.extend_from_slice(value);
+    }
+    out
 }
 
-fn handle_del(args: &[Vec<u8>], engine: &impl KVEngine) -> Vec<u8> {
-    if args.len() < 2 {
-        return resp_error("wrong number of arguments for DEL");
+/// `AUTH [username] password`, logging in as `username`, or as the
+/// default user without one.
+fn handle_auth(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    let acl = context.runtime.acl();
+    let (name, password) = match args {
+        [_, password] => {
+            if acl.open_default_user().is_some() {
+                return resp_error(
+                    "AUTH <password> called without any password configured for the default \
+                     user. Are you sure your configuration is correct?",
+                );
+            }
+            (DEFAULT_USER.as_bytes(), password)
+        }
+        [_, name, password] => (&name[..], password),
+        _ => return arity_error("auth"),
+    };
+    let user = std::str::from_utf8(name)
+        .ok()
+        .and_then(|name| acl.user(name))
+        .filter(|user| user.enabled() && user.check_password(password));
+    match user {
+        Some(user) => {
+            context.connection.log_in(Some(user));
+            resp_simple("OK")
+        }
+        None => b"-WRONGPASS invalid username-password pair or user is disabled.\r\n".to_vec(),
     }
+}
 
-    let mut removed = 0i64;
-    for key in &args[1..] {
-        match engine.delete(key) {
-            Ok(true) => removed += 1,
-            Ok(false) => {}
-            Err(_) => return resp_error("engine error"),
+/// `ACL SETUSER|GETUSER|DELUSER|LIST|USERS|WHOAMI|CAT|LOAD|SAVE`.
+fn handle_acl(args: &[Bytes], context: &CommandContext<'_>) -> Vec<u8> {
+    let acl = context.runtime.acl();
+    let sub = args[1].to_ascii_uppercase();
+    let utf8 = |arg: &Bytes| std::str::from_utf8(arg).map(str::to_string);
+    match (&sub[..], &args[2..]) {
+        (b"SETUSER", [name, rules @ ..]) => {
+            let Ok(rules) = rules.iter().map(utf8).collect::<Result<Vec<_>, _>>() else {
+                return resp_error("ACL rules must be valid UTF-8");
+            };
+            let Ok(name) = utf8(name) else {
+                return resp_error("Usernames must be valid UTF-8");
+            };
+            match acl.set_user(&name, rules.iter().map(String::as_str)) {
+                Ok(()) => resp_simple("OK"),
+                Err(err) => resp_error(&err),
+            }
         }
+        (b"GETUSER", [name]) => match utf8(name).ok().and_then(|name| acl.user(&name)) {
+            Some(user) => describe_user(&user, context.connection.protocol()),
+            None => resp_null(),
+        },
+        (b"DELUSER", names) if !names.is_empty() => {
+            let mut deleted = 0;
+            for name in names {
+                match acl.delete_user(&String::from_utf8_lossy(name)) {
+                    Ok(existed) => deleted += i64::from(existed),
+                    Err(err) => return resp_error(&err),
+                }
+            }
+            resp_integer(deleted)
+        }
+        (b"LIST", []) => {
+            let lines: Vec<String> = acl
+                .users()
+                .iter()
+                .map(|user| format!("user {} {}", user.name(), user.describe()))
+                .collect();
+            let lines: Vec<&[u8]> = lines.iter().map(|line| line.as_bytes()).collect();
+            resp_array(&lines)
+        }
+        (b"USERS", []) => {
+            let users = acl.users();
+            let names: Vec<&[u8]> = users.iter().map(|user| user.name().as_bytes()).collect();
+            resp_array(&names)
+        }
+        (b"WHOAMI", []) => match context.connection.user(acl).as_deref() {
+            Some(user) => resp_bulk(user.name().as_bytes()),
+            None => resp_null(),
+        },
+        (b"CAT", []) => {
+            let names: Vec<&[u8]> = acl::categories().map(str::as_bytes).collect();
+            resp_array(&names)
+        }
+        (b"CAT", [category]) => match acl::category_commands(&String::from_utf8_lossy(category)) {
+            Some(commands) => {
+                let names: Vec<&[u8]> = commands.map(str::as_bytes).collect();
+                resp_array(&names)
+            }
+            None => resp_error(&format!(
+                "Unknown category '{}'",
+                String::from_utf8_lossy(category)
+            )),
+        },
+        (b"LOAD" | b"SAVE", []) => {
+            let Some(path) = context.runtime.aclfile() else {
+                return resp_error("This instance is not configured to use an ACL file");
+            };
+            if &sub[..] == b"LOAD" {
+                match acl.load(path) {
+                    Ok(()) => resp_simple("OK"),
+                    Err(err) => resp_error(&err.to_string()),
+                }
+            } else {
+                match acl.save(path) {
+                    Ok(()) => resp_simple("OK"),
+                    Err(err) => {
+                        tracing::warn!(error = %err, path = %path.display(), "ACL SAVE failed");
+                        resp_error("There was an error trying to save the ACLs")
+                    }
+                }
+            }
+        }
+        (
+            b"SETUSER" | b"GETUSER" | b"DELUSER" | b"LIST" | b"USERS" | b"WHOAMI" | b"CAT"
+            | b"LOAD" | b"SAVE",
+            _,
+        ) => resp_error(&format!(
+            "wrong number of arguments for 'acl|{}' command",
+            String::from_utf8_lossy(&sub).to_ascii_lowercase()
+        )),
+        _ => resp_error("unsupported ACL subcommand"),
     }
+}
 
-    resp_integer(removed)
+/// `ACL GETUSER`'s reply: the user's flags, password digests, commands and
+/// keys, as a map in RESP3.
+fn describe_user(user: &User, protocol: u8) -> Vec<u8> {
+    let mut flags = vec![if user.enabled() { "on" } else { "off" }];
+    if user.nopass() {
+        flags.push("nopass");
+    }
+    if user.all_keys() {
+        flags.push("allkeys");
+    }
+    if user.all_commands() {
+        flags.push("allcommands");
+    }
+    let flags: Vec<&[u8]> = flags.iter().map(|flag| flag.as_bytes()).collect();
+    let hashes: Vec<String> = user.password_hashes().collect();
+    let hashes: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_bytes()).collect();
+    resp_map(
+        protocol,
+        &[
+            ("flags", resp_array(&flags)),
+            ("passwords", resp_array(&hashes)),
+            ("commands", resp_bulk(user.describe_commands().as_bytes())),
+            ("keys", resp_bulk(user.describe_keys().as_bytes())),
+        ],
+    )
 }
 
-fn handle_expire(args: &[Vec<u8>], engine: &impl KVEngine) -> Vec<u8> {
-    if args.len() != 3 {
-        return resp_error("wrong number of arguments for EXPIRE");
+/// `CLIENT ID`, `CLIENT GETREDIR` and
+/// `CLIENT TRACKING ON|OFF [REDIRECT id] [BCAST] [PREFIX prefix]...`.
+fn handle_client(args: &[Bytes], connection: &ConnectionCtx) -> Vec<u8> {
+    let tracked = connection.tracked.as_ref();
+    match args {
+        [_, sub] if eq_ignore_ascii_case(sub, b"ID") => resp_integer(connection.id() as i64),
+        [_, sub] if eq_ignore_ascii_case(sub, b"GETREDIR") => {
+            resp_integer(tracked.map_or(-1, TrackedClient::redirect))
+        }
+        [_, sub, switch, options @ ..] if eq_ignore_ascii_case(sub, b"TRACKING") => {
+            let Some(tracked) = tracked else {
+                return resp_error("CLIENT TRACKING is only supported on a listening server");
+            };
+            if eq_ignore_ascii_case(switch, b"OFF") && options.is_empty() {
+                tracked.disable();
+                return resp_simple("OK");
+            }
+            if !eq_ignore_ascii_case(switch, b"ON") {
+                return resp_error("syntax error");
+            }
+            let options = match parse_tracking_options(options) {
+                Ok(options) => options,
+                Err(resp) => return resp,
+            };
+            match tracked.enable(options) {
+                Ok(()) => resp_simple("OK"),
+                Err(err) => resp_error(&err.to_string()),
+            }
+        }
+        _ => resp_error("unsupported CLIENT subcommand"),
     }
+}
 
-    let seconds = match parse_u64(&args[2]) {
-        Ok(value) => value,
-        Err(resp) => return resp,
-    };
+/// The options after `CLIENT TRACKING ON`. `OPTIN`, `OPTOUT` and `NOLOOP`
+/// are not supported.
+fn parse_tracking_options(args: &[Bytes]) -> Result<TrackingOptions, Vec<u8>> {
+    let mut options = TrackingOptions::default();
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        if eq_ignore_ascii_case(option, b"BCAST") {
+            options.bcast = true;
+        } else if eq_ignore_ascii_case(option, b"REDIRECT") {
+            let id = args.next().ok_or_else(|| resp_error("syntax error"))?;
+            let id = parse_u64(id).map_err(|_| resp_error("Invalid client ID"))?;
+            options.redirect = Some(id);
+        } else if eq_ignore_ascii_case(option, b"PREFIX") {
+            let prefix = args.next().ok_or_else(|| resp_error("syntax error"))?;
+            options.prefixes.push(prefix.to_vec());
+        } else {
+            return Err(resp_error("syntax error"));
+        }
+    }
+    if !options.bcast && !options.prefixes.is_empty() {
+        return Err(resp_error(
+            "PREFIX option requires BCAST mode to be enabled",
+        ));
+    }
+    Ok(options)
+}
 
-    match engine.expire(&args[1], Duration::from_secs(seconds)) {
-        Ok(()) => resp_integer(1),
-        Err(hkv_common::HkvError::NotFound) => resp_integer(0),
-        Err(_) => resp_error("engine error"),
+/// `COMMAND`, `COMMAND COUNT`, `COMMAND LIST` and `COMMAND INFO name...`,
+/// describing the command table in Redis 6's format.
+fn handle_command(args: &[Bytes]) -> Vec<u8> {
+    match args {
+        [_] => command_info(COMMANDS.iter().map(Some)),
+        [_, sub] if eq_ignore_ascii_case(sub, b"COUNT") => resp_integer(COMMANDS.len() as i64),
+        [_, sub] if eq_ignore_ascii_case(sub, b"LIST") => {
+            let names: Vec<&[u8]> = COMMANDS.iter().map(|spec| spec.name.as_bytes()).collect();
+            resp_array(&names)
+        }
+        [_, sub, names @ ..] if eq_ignore_ascii_case(sub, b"INFO") => {
+            command_info(names.iter().map(|name| command_spec(name)))
+        }
+        _ => resp_error("unsupported COMMAND subcommand"),
     }
 }
 
-fn handle_ttl(args: &[Vec<u8>], engine: &impl KVEngine) -> Vec<u8> {
-    if args.len() != 2 {
-        return resp_error("wrong number of arguments for TTL");
+/// One `COMMAND INFO` entry per spec, or a null for unknown names.
+fn command_info<'a>(specs: impl ExactSizeIterator<Item = Option<&'a CommandSpec>>) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", specs.len()).into_bytes();
+    for spec in specs {
+        let Some(spec) = spec else {
+            out.extend_from_slice(b"*-1\r\n");
+            continue;
+        };
+        out.extend_from_slice(b"*6\r\n");
+        out.extend_from_slice(&resp_bulk(spec.name.as_bytes()));
+        out.extend_from_slice(&resp_integer(spec.arity()));
+        out.extend_from_slice(format!("*{}\r\n", spec.flags.len()).as_bytes());
+        for flag in spec.flags {
+            out.extend_from_slice(&resp_simple(flag.name()));
+        }
+        out.extend_from_slice(&resp_integer(spec.first_key as i64));
+        out.extend_from_slice(&resp_integer(spec.last_key as i64));
+        out.extend_from_slice(&resp_integer(spec.key_step as i64));
     }
+    out
+}
 
-    match engine.ttl(&args[1]) {
-        Ok(TtlStatus::Missing) => resp_integer(-2),
-        Ok(TtlStatus::NoExpiry) => resp_integer(-1),
-        Ok(TtlStatus::ExpiresIn(remaining)) => resp_integer(remaining.as_secs() as i64),
-        Err(_) => resp_error("engine error"),
+/// A stable 40-hex-character node ID: the SHA-1 of the listen address.
+fn cluster_node_id(addr: SocketAddr) -> String {
+    sha1_smol::Sha1::from(addr.to_string()).digest().to_string()
+}
+
+/// `CONFIG` parameters that are not a single number.
+const OUTPUT_BUFFER_LIMIT: &str = "client-output-buffer-limit";
+const REQUIREPASS: &[u8] = b"requirepass";
+
+/// `CONFIG GET <param>` and `CONFIG SET <param> <value>` for the
+/// parameters `RuntimeConfig` registers, `CONFIG REWRITE` to save them to
+/// the configuration file, and `CONFIG RESETSTAT`.
+fn handle_config(args: &[Bytes], metrics: &Metrics, runtime: &RuntimeConfig) -> Vec<u8> {
+    match args {
+        [_, sub] if eq_ignore_ascii_case(sub, b"RESETSTAT") => {
+            metrics.reset();
+            resp_simple("OK")
+        }
+        [_, sub] if eq_ignore_ascii_case(sub, b"REWRITE") => {
+            let Some(path) = runtime.config_file() else {
+                return resp_error("The server is running without a config file");
+            };
+            match config_file::rewrite(path, runtime) {
+                Ok(()) => resp_simple("OK"),
+                Err(err) => {
+                    tracing::warn!(error = %err, path = %path.display(), "CONFIG REWRITE failed");
+                    resp_error("Rewriting config file failed")
+                }
+            }
+        }
+        [_, sub, param]
+            if eq_ignore_ascii_case(sub, b"GET")
+                && eq_ignore_ascii_case(param, OUTPUT_BUFFER_LIMIT.as_bytes()) =>
+        {
+            let limits = runtime.client_output_buffer_limit();
+            resp_array(&[OUTPUT_BUFFER_LIMIT.as_bytes(), limits.as_bytes()])
+        }
+        [_, sub, param, value]
+            if eq_ignore_ascii_case(sub, b"SET")
+                && eq_ignore_ascii_case(param, OUTPUT_BUFFER_LIMIT.as_bytes()) =>
+        {
+            let applied = std::str::from_utf8(value)
+                .map_err(|_| "invalid value".to_string())
+                .and_then(|value| runtime.set_client_output_buffer_limit(value));
+            match applied {
+                Ok(()) => resp_simple("OK"),
+                Err(err) => resp_error(&format!("Invalid argument '{OUTPUT_BUFFER_LIMIT}': {err}")),
+            }
+        }
+        [_, sub, param]
+            if eq_ignore_ascii_case(sub, b"GET") && eq_ignore_ascii_case(param, REQUIREPASS) =>
+        {
+            let password = runtime.requirepass();
+            resp_array(&[REQUIREPASS, password.as_deref().unwrap_or_default()])
+        }
+        [_, sub, param, password]
+            if eq_ignore_ascii_case(sub, b"SET") && eq_ignore_ascii_case(param, REQUIREPASS) =>
+        {
+            runtime.set_requirepass(password);
+            resp_simple("OK")
+        }
+        [_, sub, param] if eq_ignore_ascii_case(sub, b"GET") => {
+            match std::str::from_utf8(param)
+                .ok()
+                .and_then(|name| runtime.get(name))
+            {
+                Some((name, value)) => resp_array(&[name.as_bytes(), value.to_string().as_bytes()]),
+                // Redis answers an empty array for unknown parameters.
+                None => resp_array(&[]),
+            }
+        }
+        [_, sub, param, value] if eq_ignore_ascii_case(sub, b"SET") => {
+            // Flags also take yes/no, as in redis.conf.
+            let value = if eq_ignore_ascii_case(value, b"yes") {
+                1
+            } else if eq_ignore_ascii_case(value, b"no") {
+                0
+            } else {
+                match parse_u64(value) {
+                    Ok(value) => value,
+                    Err(resp) => return resp,
+                }
+            };
+            match std::str::from_utf8(param)
+                .ok()
+                .map(|name| runtime.set(name, value))
+            {
+                Some(true) => resp_simple("OK"),
+                _ => resp_error("unsupported CONFIG parameter"),
+            }
+        }
+        _ => arity_error("config"),
     }
 }
 
-fn handle_info(metrics: &Metrics) -> Vec<u8> {
-    let snapshot = metrics.snapshot();
-    let average_us = snapshot.latency.average_us().unwrap_or(0.0);
-    let p50_us = snapshot.latency.percentile_us(50.0).unwrap_or(0);
-    let p90_us = snapshot.latency.percentile_us(90.0).unwrap_or(0);
-    let p99_us = snapshot.latency.percentile_us(99.0).unwrap_or(0);
-    let p999_us = snapshot.latency.percentile_us(99.9).unwrap_or(0);
-    let info = format!(
-        concat!(
-            "role:master\r\n",
-            "engine:hybridkv\r\n",
-            "requests_total:{}\r\n",
-            "errors_total:{}\r\n",
-            "inflight:{}\r\n",
-            "uptime_sec:{:.3}\r\n",
-            "qps_avg:{:.3}\r\n",
-            "error_rate:{:.3}\r\n",
-            "latency_samples:{}\r\n",
-            "latency_avg_us:{:.3}\r\n",
-            "latency_max_us:{}\r\n",
-            "latency_p50_us:{}\r\n",
-            "latency_p90_us:{}\r\n",
-            "latency_p99_us:{}\r\n",
-            "latency_p999_us:{}\r\n"
-        ),
-        snapshot.requests_total,
-        snapshot.errors_total,
-        snapshot.inflight,
-        snapshot.uptime.as_secs_f64(),
-        snapshot.qps(),
-        snapshot.error_rate(),
-        snapshot.latency.samples,
-        average_us,
-        snapshot.latency.max_us,
-        p50_us,
-        p90_us,
-        p99_us,
-        p999_us,
-    );
-    resp_bulk(info.as_bytes())
+/// The reply to a command the engine failed with `err`, with the prefix a
+/// Redis client expects for that kind of failure: `OOM` when memory runs
+/// out, `BUSY` for conditions worth retrying, and `ERR` with a specific
+/// message otherwise. Server-side failures are also logged.
+fn engine_error(err: HkvError) -> Vec<u8> {
+    if err.category() == HkvErrorCategory::Server {
+        tracing::warn!(error = %err, "engine error");
+    }
+    let reply: &[u8] = match err {
+        HkvError::InvalidInput => b"-ERR invalid argument\r\n",
+        HkvError::NotFound => b"-ERR no such key\r\n",
+        HkvError::KeyTooLong => b"-ERR key is too long\r\n",
+        HkvError::ValueTooLong => b"-ERR string exceeds maximum allowed size\r\n",
+        HkvError::OutOfMemory | HkvError::CapacityExceeded => {
+            b"-OOM command not allowed when used memory > 'maxmemory'.\r\n"
+        }
+        HkvError::InternalError => b"-ERR internal engine error\r\n",
+        HkvError::Busy => b"-BUSY the engine is busy, try again later\r\n",
+        HkvError::Timeout => b"-BUSY the engine timed out, try again later\r\n",
+        HkvError::Interrupted => b"-BUSY the engine was interrupted, try again later\r\n",
+        HkvError::VersionMismatch => b"-ERR engine version mismatch\r\n",
+        HkvError::ProtocolViolation => b"-ERR engine protocol violation\r\n",
+        HkvError::UnsupportedCommand => b"-ERR command not supported by the engine\r\n",
+    };
+    reply.to_vec()
 }
 
 fn resp_simple(message: &str) -> Vec<u8> {
@@ -454,16 +2939,24 @@ fn resp_null() -> Vec<u8> {
     b"$-1\r\n".to_vec()
 }
 
+fn resp_array(items: &[&[u8]]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", items.len()).into_bytes();
+    for item in items {
+        buf.extend_from_slice(&resp_bulk(item));
+    }
+    buf
+}
+
 fn observe_command_result<F>(
     sink: Option<&dyn ExperimentObservationSink>,
     events: Vec<ObservationEvent>,
     dispatch: F,
-) -> Vec<u8>
+) -> Reply
 where
-    F: FnOnce() -> Vec<u8>,
+    F: FnOnce() -> Reply,
 {
     let response = dispatch();
-    if !is_error_response(&response)
+    if !response.is_error()
         && let Some(sink) = sink
     {
         for event in events {
@@ -473,7 +2966,7 @@ where
     response
 }
 
-fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
+fn planned_observations(args: &[Bytes]) -> Vec<ObservationEvent> {
     if args.is_empty() {
         return Vec::new();
     }
@@ -483,7 +2976,7 @@ fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
         return match args {
             [_, key] => vec![ObservationEvent::read(
                 CommandKind::Get,
-                key.clone(),
+                key.to_vec(),
                 SystemTime::now(),
             )],
             _ => Vec::new(),
@@ -494,14 +2987,14 @@ fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
         return match args {
             [_, key, value] => vec![ObservationEvent::write(
                 CommandKind::Set,
-                key.clone(),
+                key.to_vec(),
                 Some(value.len()),
                 SystemTime::now(),
             )],
             [_, key, value, ex, _] if eq_ignore_ascii_case(ex, b"EX") => {
                 vec![ObservationEvent::write(
                     CommandKind::Set,
-                    key.clone(),
+                    key.to_vec(),
                     Some(value.len()),
                     SystemTime::now(),
                 )]
@@ -517,7 +3010,7 @@ fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
         return args[1..]
             .iter()
             .map(|key| {
-                ObservationEvent::write(CommandKind::Delete, key.clone(), None, SystemTime::now())
+                ObservationEvent::write(CommandKind::Delete, key.to_vec(), None, SystemTime::now())
             })
             .collect();
     }
@@ -526,7 +3019,7 @@ fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
         return match args {
             [_, key, _] => vec![ObservationEvent::write(
                 CommandKind::Expire,
-                key.clone(),
+                key.to_vec(),
                 None,
                 SystemTime::now(),
             )],
@@ -538,7 +3031,7 @@ fn planned_observations(args: &[Vec<u8>]) -> Vec<ObservationEvent> {
         return match args {
             [_, key] => vec![ObservationEvent::read(
                 CommandKind::Ttl,
-                key.clone(),
+                key.to_vec(),
                 SystemTime::now(),
             )],
             _ => Vec::new(),
@@ -554,38 +3047,89 @@ fn observation_log_sink(
     log.map(|log| log as &dyn ExperimentObservationSink)
 }
 
-fn is_error_response(response: &[u8]) -> bool {
-    response.first() == Some(&b'-')
+/// The first word of an error reply, such as `ERR` or `OOM`, by which
+/// errors are counted.
+fn error_prefix(response: &[u8]) -> &[u8] {
+    let message = response.strip_prefix(b"-").unwrap_or(response);
+    let end = message
+        .iter()
+        .position(|&byte| matches!(byte, b' ' | b'\r'))
+        .unwrap_or(message.len());
+    &message[..end]
 }
 
-fn configure_accepted_stream(stream: &TcpStream, config: ServerConfig) -> std::io::Result<()> {
-    stream.set_nodelay(true)?;
+/// Binds a listener on `addr` with `SO_REUSEADDR`, so a restart does not
+/// fail while the previous process's connections linger in `TIME_WAIT`, and
+/// a pending-connection queue of `backlog` (see `RuntimeConfig::tcp_backlog`).
+///
+/// Must be called from within a Tokio runtime.
+pub fn bind_listener(addr: SocketAddr, backlog: u32) -> std::io::Result<tokio::net::TcpListener> {
+    bind_socket(addr, backlog, false)
+}
+
+/// Binds `count` listeners on `addr` like `bind_listener`, sharing it with
+/// `SO_REUSEPORT` so the kernel spreads incoming connections across them.
+/// Port 0 binds them all to the port the first one was given.
+///
+/// Must be called from within a Tokio runtime.
+#[cfg(unix)]
+pub fn bind_reuseport_listeners(
+    addr: SocketAddr,
+    backlog: u32,
+    count: usize,
+) -> std::io::Result<Vec<tokio::net::TcpListener>> {
+    let first = bind_socket(addr, backlog, true)?;
+    let addr = first.local_addr()?;
+    let mut listeners = vec![first];
+    for _ in 1..count {
+        listeners.push(bind_socket(addr, backlog, true)?);
+    }
+    Ok(listeners)
+}
+
+fn bind_socket(
+    addr: SocketAddr,
+    backlog: u32,
+    reuse_port: bool,
+) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    #[cfg(not(unix))]
+    let _ = reuse_port;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(i32::try_from(backlog).unwrap_or(i32::MAX))?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+/// Applies the `tcp-nodelay` and `tcp-keepalive` settings to an accepted
+/// socket.
+fn configure_accepted_stream<S: ClientStream>(
+    stream: &S,
+    config: ServerConfig,
+    runtime: &RuntimeConfig,
+) -> std::io::Result<()> {
+    let socket = stream.socket();
+    socket.set_tcp_nodelay(runtime.tcp_nodelay())?;
 
-    let socket = SockRef::from(stream);
+    let Some(time) = runtime.tcp_keepalive() else {
+        return socket.set_keepalive(false);
+    };
     socket.set_keepalive(true)?;
 
     #[cfg(not(any(target_os = "openbsd", target_os = "haiku")))]
     {
-        let keepalive = build_tcp_keepalive(config);
+        let keepalive = build_tcp_keepalive(time, config);
         socket.set_tcp_keepalive(&keepalive)?;
     }
 
     Ok(())
 }
 
-fn finish_tracked_request(
-    metrics: &Metrics,
-    started_at: Instant,
-    response: &[u8],
-    write_result: std::io::Result<()>,
-) -> std::io::Result<()> {
-    if is_error_response(response) {
-        metrics.record_error();
-    }
-    metrics.record_request_end(started_at.elapsed());
-    write_result
-}
-
 fn reap_connection_task(join_result: Result<std::io::Result<()>, tokio::task::JoinError>) {
     if let Err(join_error) = join_result
         && join_error.is_panic()
@@ -594,8 +3138,8 @@ fn reap_connection_task(join_result: Result<std::io::Result<()>, tokio::task::Jo
     }
 }
 
-fn build_tcp_keepalive(config: ServerConfig) -> TcpKeepalive {
-    let keepalive = TcpKeepalive::new().with_time(config.keepalive_time);
+fn build_tcp_keepalive(time: Duration, config: ServerConfig) -> TcpKeepalive {
+    let keepalive = TcpKeepalive::new().with_time(time);
     let keepalive = with_keepalive_interval(keepalive, config.keepalive_interval);
     with_keepalive_retries(keepalive, config.keepalive_retries)
 }
@@ -710,15 +3254,79 @@ mod tests {
     use hkv_engine::MemoryEngine;
     use socket2::SockRef;
     use tokio::net::{TcpListener, TcpStream};
-    use tokio::sync::oneshot;
     use tokio::time::timeout;
 
+    use crate::config::{DEFAULT_TCP_BACKLOG, DEFAULT_TCP_KEEPALIVE_SECS};
+    use crate::tracking::TrackingError;
+
+    fn is_error_response(response: &[u8]) -> bool {
+        response.first() == Some(&b'-')
+    }
+
+    /// Dispatches `args` as a connection without tracking would.
+    fn dispatch_with(
+        engine: &dyn KVEngine,
+        node_id: &str,
+        observation_sink: Option<&dyn ExperimentObservationSink>,
+        args: &[Bytes],
+    ) -> Vec<u8> {
+        let connection = ConnectionCtx::new(ClientInfo::next(None), None, &RuntimeConfig::new());
+        dispatch_on(engine, node_id, observation_sink, &connection, args)
+    }
+
+    fn dispatch_on(
+        engine: &dyn KVEngine,
+        node_id: &str,
+        observation_sink: Option<&dyn ExperimentObservationSink>,
+        connection: &ConnectionCtx,
+        args: &[Bytes],
+    ) -> Vec<u8> {
+        let context = CommandContext {
+            engine,
+            shared_engine: None,
+            metrics: &Metrics::new(),
+            persistence: &Arc::new(Persistence::default()),
+            runtime: &RuntimeConfig::new(),
+            node_id,
+            listen_addrs: &[],
+            replication: None,
+            observation_sink,
+            connection,
+            #[cfg(feature = "scripting")]
+            scripting: &Scripting::new(),
+            abort: None,
+        };
+        dispatch_command(args, &context).into_vec()
+    }
+
+    /// Dispatches `args` against `runtime` on a fresh connection.
+    fn dispatch_in(engine: &dyn KVEngine, runtime: &RuntimeConfig, args: &[&[u8]]) -> Vec<u8> {
+        let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+        let connection = ConnectionCtx::new(ClientInfo::next(None), None, runtime);
+        let context = CommandContext {
+            engine,
+            shared_engine: None,
+            metrics: &Metrics::new(),
+            persistence: &Arc::new(Persistence::default()),
+            runtime,
+            node_id: "",
+            listen_addrs: &[],
+            replication: None,
+            observation_sink: None,
+            connection: &connection,
+            #[cfg(feature = "scripting")]
+            scripting: &Scripting::new(),
+            abort: None,
+        };
+        dispatch_command(&args, &context).into_vec()
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq)]
     enum FakeOp {
         Set(Vec<u8>, Vec<u8>),
-        SetWithTtl(Vec<u8>, Vec<u8>, Duration),
+        SetWithTtl(Vec<u8>, Vec<u8>, TtlAfter),
         Delete(Vec<u8>),
-        Expire(Vec<u8>, Duration),
+        Expire(Vec<u8>, TtlAfter),
         Ttl(Vec<u8>),
         Get(Vec<u8>),
     }
@@ -756,7 +3364,7 @@ mod tests {
             Ok(())
         }
 
-        fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: Duration) -> HkvResult<()> {
+        fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: TtlAfter) -> HkvResult<()> {
             self.ops
                 .lock()
                 .unwrap()
@@ -775,7 +3383,7 @@ mod tests {
             Ok(false)
         }
 
-        fn expire(&self, key: &[u8], ttl: Duration) -> HkvResult<()> {
+        fn expire(&self, key: &[u8], ttl: TtlAfter) -> HkvResult<()> {
             self.ops
                 .lock()
                 .unwrap()
@@ -790,6 +3398,18 @@ mod tests {
             self.ops.lock().unwrap().push(FakeOp::Ttl(key.to_vec()));
             Ok(TtlStatus::NoExpiry)
         }
+
+        fn snapshot(&self) -> HkvResult<Vec<hkv_engine::SnapshotEntry>> {
+            Ok(Vec::new())
+        }
+
+        fn stats(&self) -> hkv_engine::EngineStats {
+            hkv_engine::EngineStats::default()
+        }
+
+        fn avg_ttl_ms(&self) -> u64 {
+            0
+        }
     }
 
     fn test_server_config(shutdown_drain_timeout: Duration) -> ServerConfig {
@@ -803,50 +3423,101 @@ mod tests {
         shutdown_drain_timeout: Duration,
     ) -> (
         std::net::SocketAddr,
-        oneshot::Sender<()>,
+        ShutdownController,
         tokio::task::JoinHandle<std::io::Result<()>>,
     ) {
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
         let addr = listener.local_addr().unwrap();
         let engine = Arc::new(MemoryEngine::new());
         let metrics = Arc::new(Metrics::new());
-        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
-
-        let handle = tokio::spawn(async move {
-            serve_with_shutdown_config(
-                listener,
-                engine,
-                metrics,
-                async {
-                    let _ = shutdown_rx.await;
-                },
-                test_server_config(shutdown_drain_timeout),
-            )
-            .await
-        });
+        let shutdown = ShutdownController::new();
+
+        let handle = tokio::spawn(serve_with_shutdown_config(
+            listener,
+            engine,
+            metrics,
+            shutdown.wait(),
+            test_server_config(shutdown_drain_timeout),
+        ));
+
+        (addr, shutdown, handle)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reuseport_siblings_go_to_different_accept_loops() {
+        let shared =
+            bind_reuseport_listeners("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG, 3)
+                .unwrap();
+        let shared_addr = shared[0].local_addr().unwrap();
+        let single = bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+        let single_addr = single.local_addr().unwrap();
 
-        (addr, shutdown_tx, handle)
+        let groups = acceptor_groups(shared.into_iter().chain([single]).collect()).unwrap();
+        let addrs: Vec<Vec<SocketAddr>> = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|listener| listener.local_addr().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            addrs,
+            [
+                vec![shared_addr, single_addr],
+                vec![shared_addr],
+                vec![shared_addr]
+            ]
+        );
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn configure_accepted_stream_enables_nodelay_and_keepalive() {
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    async fn configure_accepted_stream_applies_the_socket_settings() {
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+        assert!(SockRef::from(&listener).reuse_address().unwrap());
         let addr = listener.local_addr().unwrap();
-        let connect = tokio::spawn(async move { TcpStream::connect(addr).await.unwrap() });
+        let runtime = RuntimeConfig::new();
 
-        let (server_stream, _) = listener.accept().await.unwrap();
-        let _client_stream = connect.await.unwrap();
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        configure_accepted_stream(&stream, DEFAULT_SERVER_CONFIG, &runtime).unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(DEFAULT_TCP_KEEPALIVE_SECS)
+        );
+
+        runtime.set_tcp_nodelay(false);
+        runtime.set_tcp_keepalive_secs(0);
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        configure_accepted_stream(&stream, DEFAULT_SERVER_CONFIG, &runtime).unwrap();
+        assert!(!stream.nodelay().unwrap());
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
 
-        configure_accepted_stream(&server_stream, DEFAULT_SERVER_CONFIG).unwrap();
+    #[tokio::test]
+    async fn listeners_rebind_while_connections_linger() {
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        // Closing the server side first leaves it in TIME_WAIT.
+        drop(stream);
+        drop(client);
+        drop(listener);
 
-        assert!(server_stream.nodelay().unwrap());
-        assert!(SockRef::from(&server_stream).keepalive().unwrap());
+        bind_listener(addr, DEFAULT_TCP_BACKLOG).unwrap();
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn serve_with_shutdown_waits_for_active_connections_to_close() {
-        let (addr, shutdown, mut server_task) =
-            spawn_server_for_test(Duration::from_millis(200)).await;
+    async fn serve_with_shutdown_closes_idle_connections_promptly() {
+        let (addr, shutdown, mut server_task) = spawn_server_for_test(Duration::from_secs(5)).await;
         let mut client = StdTcpStream::connect(addr).unwrap();
         client
             .set_read_timeout(Some(Duration::from_secs(1)))
@@ -857,15 +3528,13 @@ mod tests {
         client.read_exact(&mut pong).unwrap();
         assert_eq!(&pong, b"+PONG\r\n");
 
-        shutdown.send(()).unwrap();
-
-        assert!(
-            timeout(Duration::from_millis(50), &mut server_task)
-                .await
-                .is_err()
-        );
+        shutdown.trigger();
 
-        drop(client);
+        // The server closes the idle connection itself instead of waiting
+        // out the 5s grace period.
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
 
         let result = timeout(Duration::from_secs(1), &mut server_task)
             .await
@@ -877,9 +3546,22 @@ mod tests {
     async fn serve_with_shutdown_aborts_stuck_connections_after_timeout() {
         let (addr, shutdown, mut server_task) =
             spawn_server_for_test(Duration::from_millis(50)).await;
-        let _client = StdTcpStream::connect(addr).unwrap();
+        let mut client = StdTcpStream::connect(addr).unwrap();
 
-        shutdown.send(()).unwrap();
+        // Request ~64 MiB of replies and never read them, so the server is
+        // stuck writing when shutdown starts.
+        let value = vec![b'x'; 1 << 20];
+        let mut request =
+            format!("*3\r\n$3\r\nSET\r\n$3\r\nbig\r\n${}\r\n", value.len()).into_bytes();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        for _ in 0..64 {
+            request.extend_from_slice(b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n");
+        }
+        client.write_all(&request).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        shutdown.trigger();
 
         let result = timeout(Duration::from_secs(1), &mut server_task)
             .await
@@ -887,12 +3569,57 @@ mod tests {
         assert!(result.unwrap().is_ok());
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn aborted_connection_releases_inflight_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::new());
+        let shutdown = ShutdownController::new();
+        let server_task = tokio::spawn(serve_with_shutdown_config(
+            listener,
+            Arc::new(MemoryEngine::new()),
+            Arc::clone(&metrics),
+            shutdown.wait(),
+            test_server_config(Duration::from_millis(50)),
+        ));
+        let mut client = StdTcpStream::connect(addr).unwrap();
+
+        // Replies the client never reads leave the connection blocked in a
+        // write with requests queued, until shutdown aborts its task.
+        let value = vec![b'x'; 1 << 20];
+        let mut request =
+            format!("*3\r\n$3\r\nSET\r\n$3\r\nbig\r\n${}\r\n", value.len()).into_bytes();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        for _ in 0..64 {
+            request.extend_from_slice(b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n");
+        }
+        client.write_all(&request).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while metrics.snapshot().inflight == 0 {
+            assert!(Instant::now() < deadline, "no request got stuck in flight");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        shutdown.trigger();
+        timeout(Duration::from_secs(1), server_task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.inflight, 0);
+        assert_eq!(snapshot.connected_clients, 0);
+        assert_eq!(snapshot.latency.samples, snapshot.requests_total);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn serve_with_shutdown_closes_listener_after_shutdown() {
         let (addr, shutdown, mut server_task) =
             spawn_server_for_test(Duration::from_millis(50)).await;
 
-        shutdown.send(()).unwrap();
+        shutdown.trigger();
         let result = timeout(Duration::from_secs(1), &mut server_task)
             .await
             .unwrap();
@@ -902,19 +3629,16 @@ mod tests {
     }
 
     #[test]
-    fn finish_tracked_request_releases_inflight_on_write_error() {
-        let metrics = Metrics::new();
+    fn reply_batch_releases_inflight_on_write_error() {
+        let metrics = Arc::new(Metrics::new());
         metrics.record_request_start();
+        let mut replies = ReplyBatch::new(Arc::clone(&metrics));
+        replies.push(Instant::now(), b"+OK\r\n".to_vec().into());
 
-        let result = finish_tracked_request(
-            &metrics,
-            Instant::now(),
-            b"+OK\r\n",
-            Err(std::io::Error::new(
-                std::io::ErrorKind::BrokenPipe,
-                "write failed",
-            )),
-        );
+        let result = replies.finish(Err(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "write failed",
+        )));
 
         assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
         let snapshot = metrics.snapshot();
@@ -925,19 +3649,30 @@ mod tests {
     }
 
     #[test]
-    fn finish_tracked_request_counts_error_responses_even_on_write_failure() {
-        let metrics = Metrics::new();
+    fn reply_batch_releases_inflight_when_dropped_unflushed() {
+        let metrics = Arc::new(Metrics::new());
         metrics.record_request_start();
+        let mut replies = ReplyBatch::new(Arc::clone(&metrics));
+        replies.push(Instant::now(), b"+OK\r\n".to_vec().into());
 
-        let result = finish_tracked_request(
-            &metrics,
-            Instant::now(),
-            b"-ERR protocol error\r\n",
-            Err(std::io::Error::new(
-                std::io::ErrorKind::BrokenPipe,
-                "write failed",
-            )),
-        );
+        drop(replies);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.inflight, 0);
+        assert_eq!(snapshot.latency.samples, 1);
+    }
+
+    #[test]
+    fn reply_batch_counts_error_responses_even_on_write_failure() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request_start();
+        let mut replies = ReplyBatch::new(Arc::clone(&metrics));
+        replies.push(Instant::now(), b"-ERR protocol error\r\n".to_vec().into());
+
+        let result = replies.finish(Err(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "write failed",
+        )));
 
         assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
         let snapshot = metrics.snapshot();
@@ -950,19 +3685,18 @@ mod tests {
     #[test]
     fn set_ex_dispatches_only_set_with_ttl() {
         let engine = FakeEngine::default();
-        let metrics = Metrics::new();
 
-        let response = dispatch_command(
-            &[
-                b"SET".to_vec(),
-                b"key".to_vec(),
-                b"value".to_vec(),
-                b"EX".to_vec(),
-                b"10".to_vec(),
-            ],
+        let response = dispatch_with(
             &engine,
-            &metrics,
+            "",
             None,
+            &[
+                Bytes::from_static(b"SET"),
+                Bytes::from_static(b"key"),
+                Bytes::from_static(b"value"),
+                Bytes::from_static(b"EX"),
+                Bytes::from_static(b"10"),
+            ],
         );
 
         assert_eq!(response, b"+OK\r\n");
@@ -971,7 +3705,7 @@ mod tests {
             vec![FakeOp::SetWithTtl(
                 b"key".to_vec(),
                 b"value".to_vec(),
-                Duration::from_secs(10)
+                TtlAfter::from_secs(10)
             )]
         );
     }
@@ -979,13 +3713,16 @@ mod tests {
     #[test]
     fn plain_set_still_dispatches_set() {
         let engine = FakeEngine::default();
-        let metrics = Metrics::new();
 
-        let response = dispatch_command(
-            &[b"SET".to_vec(), b"key".to_vec(), b"value".to_vec()],
+        let response = dispatch_with(
             &engine,
-            &metrics,
+            "",
             None,
+            &[
+                Bytes::from_static(b"SET"),
+                Bytes::from_static(b"key"),
+                Bytes::from_static(b"value"),
+            ],
         );
 
         assert_eq!(response, b"+OK\r\n");
@@ -995,28 +3732,633 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lolwut_never_returns_an_error() {
+        let engine = FakeEngine::default();
+        let expected = resp_bulk(LOLWUT_ART.as_bytes());
+        for args in [
+            &[&b"LOLWUT"[..]][..],
+            &[b"lolwut", b"VERSION", b"5"],
+            &[b"LOLWUT", b"VERSION", b"-99999999999999999999"],
+            &[b"LOLWUT", b"VERSION"],
+            &[b"LOLWUT", b"10", b"1", b"1"],
+        ] {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            let response = dispatch_with(&engine, "", None, &args);
+            assert!(!is_error_response(&response), "{args:?}");
+            assert_eq!(response, expected);
+        }
+        assert!(
+            LOLWUT_ART.ends_with("\nDr. HybridKV: bringing cache to the edge\n\nHybridKV v1.0.0\n")
+        );
+        assert!(engine.recorded_ops().is_empty());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn scripts_call_back_into_the_command_table() {
+        let engine = FakeEngine::default();
+        let dispatch = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            dispatch_with(&engine, "", None, &args)
+        };
+        assert_eq!(
+            dispatch(&[
+                b"EVAL",
+                b"local ok = redis.call('SET', KEYS[1], ARGV[1]) \
+                  return {ok, redis.call('GET', KEYS[1])}",
+                b"1",
+                b"k",
+                b"v",
+            ]),
+            b"*2\r\n+OK\r\n$-1\r\n"
+        );
+        assert_eq!(
+            engine.recorded_ops(),
+            vec![
+                FakeOp::Set(b"k".to_vec(), b"v".to_vec()),
+                FakeOp::Get(b"k".to_vec())
+            ]
+        );
+        assert_eq!(
+            dispatch(&[b"EVAL", b"return redis.call('GET', 'missing')", b"0"]),
+            b"$-1\r\n"
+        );
+        assert_eq!(
+            dispatch(&[b"SCRIPT", b"LOAD", b"return 1"]),
+            b"$40\r\ne0e1f9fabfc9d4800c877a703b823ac0578ff8db\r\n"
+        );
+        assert_eq!(
+            dispatch(&[b"SCRIPT", b"KILL"]),
+            b"-NOTBUSY No scripts in execution right now.\r\n"
+        );
+        assert!(is_error_response(&dispatch(&[b"SCRIPT", b"LOAD"])));
+        assert_eq!(
+            dispatch(&[
+                b"EVALSHA",
+                b"e0e1f9fabfc9d4800c877a703b823ac0578ff8db",
+                b"0"
+            ]),
+            b"-NOSCRIPT No matching script. Please use EVAL.\r\n"
+        );
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    #[test]
+    fn scripting_commands_are_stubbed() {
+        let engine = FakeEngine::default();
+        let dispatch = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            dispatch_with(&engine, "", None, &args)
+        };
+        let unsupported = b"-ERR Lua scripting is not supported in this build\r\n";
+        assert_eq!(
+            dispatch(&[b"EVAL", b"return 1", b"1", b"k", b"v"]),
+            unsupported
+        );
+        assert_eq!(dispatch(&[b"evalsha", b"abc", b"0"]), unsupported);
+        assert_eq!(
+            dispatch(&[b"SCRIPT", b"EXISTS", b"abc", b"def"]),
+            b"*2\r\n:0\r\n:0\r\n"
+        );
+        assert_eq!(dispatch(&[b"script", b"flush"]), b"+OK\r\n");
+        assert_eq!(dispatch(&[b"SCRIPT", b"FLUSH", b"ASYNC"]), b"+OK\r\n");
+        assert!(is_error_response(&dispatch(&[b"SCRIPT", b"EXISTS"])));
+        assert!(is_error_response(&dispatch(&[b"SCRIPT", b"LOAD", b"x"])));
+        assert_eq!(dispatch(&[b"FUNCTION", b"LIST"]), b"*0\r\n");
+        assert!(engine.recorded_ops().is_empty());
+    }
+
+    #[test]
+    fn mapped_loopback_addresses_count_as_loopback() {
+        for local in ["127.0.0.1", "127.1.2.3", "::1", "::ffff:127.0.0.1"] {
+            assert!(is_loopback(local.parse().unwrap()), "{local}");
+        }
+        for remote in ["10.0.0.1", "::ffff:10.0.0.1", "2001:db8::1"] {
+            assert!(!is_loopback(remote.parse().unwrap()), "{remote}");
+        }
+    }
+
+    #[test]
+    fn cluster_stubs_describe_a_single_unclustered_node() {
+        let engine = FakeEngine::default();
+        let node_id = cluster_node_id("127.0.0.1:6379".parse().unwrap());
+        let dispatch = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            dispatch_with(&engine, &node_id, None, &args)
+        };
+
+        let info = dispatch(&[b"CLUSTER", b"INFO"]);
+        let info = String::from_utf8(info).unwrap();
+        assert!(info.starts_with('$'), "{info}");
+        for field in [
+            "cluster_enabled:0\r\n",
+            "cluster_state:ok\r\n",
+            "cluster_slots_assigned:0\r\n",
+            "cluster_known_nodes:0\r\n",
+            "cluster_size:0\r\n",
+        ] {
+            assert!(info.contains(field), "{field} missing from {info}");
+        }
+
+        assert_eq!(node_id.len(), 40);
+        assert!(node_id.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(node_id, cluster_node_id("127.0.0.1:6379".parse().unwrap()));
+        assert_ne!(node_id, cluster_node_id("127.0.0.1:6380".parse().unwrap()));
+        assert_eq!(
+            dispatch(&[b"cluster", b"myid"]),
+            resp_bulk(node_id.as_bytes())
+        );
+
+        assert_eq!(dispatch(&[b"CLUSTER", b"NODES"]), b"$0\r\n\r\n");
+        assert!(is_error_response(&dispatch(&[b"CLUSTER", b"SLOTS"])));
+    }
+
+    #[test]
+    fn every_tracked_command_has_a_spec() {
+        let names: Vec<_> = COMMANDS.iter().map(|spec| spec.name).collect();
+        assert_eq!(names, TRACKED_COMMANDS);
+        assert!(command_spec(b"SET").unwrap().has(CommandFlag::Write));
+        assert!(command_spec(b"config").unwrap().has(CommandFlag::Admin));
+        assert!(command_spec(b"NOSUCHCOMMAND").is_none());
+    }
+
+    #[test]
+    fn every_spec_enforces_its_arity() {
+        let engine = FakeEngine::default();
+        for spec in &COMMANDS {
+            let call = |len: usize| {
+                let mut args = vec![Bytes::from(spec.name.to_uppercase())];
+                args.resize(len, Bytes::from_static(b"1"));
+                dispatch_with(&engine, "", None, &args)
+            };
+            let expected = format!(
+                "-ERR wrong number of arguments for '{}' command\r\n",
+                spec.name
+            );
+            if spec.min_args > 1 {
+                assert_eq!(
+                    call(spec.min_args - 1),
+                    expected.as_bytes(),
+                    "{}",
+                    spec.name
+                );
+            }
+            if let Some(max) = spec.max_args {
+                assert_eq!(call(max + 1), expected.as_bytes(), "{}", spec.name);
+            }
+        }
+        assert!(engine.recorded_ops().is_empty());
+    }
+
+    #[test]
+    fn null_arguments_are_validated_like_empty_ones() {
+        let engine = MemoryEngine::new();
+        let call = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            dispatch_with(&engine, "", None, &args)
+        };
+        // What the parser hands over for `$-1` and `*-1`.
+        assert_eq!(call(&[b"SET", b"k", b""]), b"+OK\r\n");
+        assert_eq!(call(&[b"GET", b"k"]), b"$0\r\n\r\n");
+        assert_eq!(call(&[b"EXPIRE", b"k", b""]), b"-ERR invalid integer\r\n");
+        assert_eq!(call(&[]), b"-ERR empty command\r\n");
+    }
+
+    #[test]
+    fn reset_returns_the_connection_to_its_state_on_accept() {
+        let tracker = Arc::new(Tracker::new(Arc::new(RuntimeConfig::new())));
+        let engine = MemoryEngine::new();
+        let connection = ConnectionCtx::new(
+            ClientInfo::next(None),
+            Some(&tracker),
+            &RuntimeConfig::new(),
+        );
+        let call = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            connection.before_command(&args);
+            let response = dispatch_on(&engine, "", None, &connection, &args);
+            connection.after_command(&args, is_error_response(&response));
+            response
+        };
+        call(&[b"HELLO", b"3"]);
+        assert_eq!(call(&[b"CLIENT", b"TRACKING", b"ON"]), b"+OK\r\n");
+        call(&[b"GET", b"k"]);
+        assert_eq!(tracker.tracked_keys(), 1);
+
+        assert_eq!(call(&[b"RESET"]), b"+RESET\r\n");
+        assert_eq!(connection.protocol(), 2);
+        assert_eq!(tracker.tracked_keys(), 0);
+        assert_eq!(call(&[b"CLIENT", b"GETREDIR"]), b":-1\r\n");
+        assert_eq!(
+            call(&[b"CLIENT", b"ID"]),
+            format!(":{}\r\n", connection.id()).as_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn abrupt_disconnects_release_the_connection_state() {
+        let metrics = Arc::new(Metrics::new());
+        let tracker = Arc::new(Tracker::new(Arc::new(RuntimeConfig::new())));
+        let context = ServerContext {
+            tracker: Some(Arc::clone(&tracker)),
+            ..ServerContext::new(Arc::clone(&metrics))
+        };
+        let client = ClientInfo::next(None);
+        let (mut peer, stream) = tokio::io::duplex(4096);
+        let server = tokio::spawn(serve_connection(
+            stream,
+            Arc::new(MemoryEngine::new()),
+            context,
+            client,
+            ShutdownController::new().token(),
+        ));
+
+        let mut request = Vec::new();
+        for args in [
+            &[&b"HELLO"[..], b"3"][..],
+            &[b"CLIENT", b"TRACKING", b"ON"],
+            &[b"GET", b"k"],
+        ] {
+            hkv_client::encode_command(args, &mut request);
+        }
+        peer.write_all(&request).await.unwrap();
+        let mut replies = Vec::new();
+        while !replies.ends_with(b"+OK\r\n$-1\r\n") {
+            let mut chunk = [0; 512];
+            let read = peer.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "connection closed early");
+            replies.extend_from_slice(&chunk[..read]);
+        }
+        assert_eq!(tracker.tracked_keys(), 1);
+
+        // Gone without QUIT or RESET.
+        drop(peer);
+        server.await.unwrap().unwrap();
+        assert_eq!(tracker.tracked_keys(), 0);
+        let other = tracker.connect(ClientInfo::next(None).id);
+        other.set_resp3(true);
+        let redirect = TrackingOptions {
+            redirect: Some(client.id),
+            ..TrackingOptions::default()
+        };
+        assert_eq!(other.enable(redirect), Err(TrackingError::NoSuchRedirect));
+    }
+
+    #[test]
+    fn key_positions_pick_out_every_key() {
+        let args = |args: &[&[u8]]| -> Vec<Bytes> {
+            args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+        };
+        let keys = |args: &[Bytes]| -> Vec<Vec<u8>> {
+            command_spec(&args[0])
+                .unwrap()
+                .keys_of(args)
+                .map(|key| key.to_vec())
+                .collect()
+        };
+        assert_eq!(keys(&args(&[b"GET", b"k"])), [b"k"]);
+        assert_eq!(keys(&args(&[b"SET", b"k", b"v", b"EX", b"1"])), [b"k"]);
+        assert_eq!(keys(&args(&[b"DEL", b"a", b"b"])), [b"a", b"b"]);
+        assert!(keys(&args(&[b"DEL"])).is_empty());
+        assert!(keys(&args(&[b"PING", b"hello"])).is_empty());
+    }
+
+    #[test]
+    fn command_info_describes_the_table() {
+        let engine = FakeEngine::default();
+        let dispatch = |args: &[&[u8]]| {
+            let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+            dispatch_with(&engine, "", None, &args)
+        };
+        assert_eq!(
+            dispatch(&[b"COMMAND", b"COUNT"]),
+            resp_integer(COMMANDS.len() as i64)
+        );
+        assert_eq!(
+            dispatch(&[b"command", b"info", b"get", b"nosuch", b"DEL"]),
+            concat!(
+                "*3\r\n",
+                "*6\r\n$3\r\nget\r\n:2\r\n*1\r\n+readonly\r\n:1\r\n:1\r\n:1\r\n",
+                "*-1\r\n",
+                "*6\r\n$3\r\ndel\r\n:-2\r\n*1\r\n+write\r\n:1\r\n:-1\r\n:1\r\n",
+            )
+            .as_bytes()
+        );
+        assert!(
+            dispatch(&[b"COMMAND"])
+                .starts_with(format!("*{}\r\n*6\r\n$4\r\nping\r\n", COMMANDS.len()).as_bytes())
+        );
+        assert!(is_error_response(&dispatch(&[b"COMMAND", b"DOCS"])));
+    }
+
     #[test]
     fn planned_observations_skip_wrong_arity_commands() {
-        assert!(planned_observations(&[b"GET".to_vec()]).is_empty());
-        assert!(planned_observations(&[b"TTL".to_vec()]).is_empty());
-        assert!(planned_observations(&[b"DEL".to_vec()]).is_empty());
-        assert!(planned_observations(&[b"EXPIRE".to_vec(), b"key".to_vec()]).is_empty());
+        assert!(planned_observations(&[Bytes::from_static(b"GET")]).is_empty());
+        assert!(planned_observations(&[Bytes::from_static(b"TTL")]).is_empty());
+        assert!(planned_observations(&[Bytes::from_static(b"DEL")]).is_empty());
+        assert!(
+            planned_observations(&[Bytes::from_static(b"EXPIRE"), Bytes::from_static(b"key")])
+                .is_empty()
+        );
     }
 
     #[test]
     fn dispatch_command_skips_observation_on_engine_error() {
         let engine = FakeEngine::failing_writes();
-        let metrics = Metrics::new();
         let observation_log = SharedObservationLog::default();
 
-        let response = dispatch_command(
-            &[b"SET".to_vec(), b"key".to_vec(), b"value".to_vec()],
+        let response = dispatch_with(
             &engine,
-            &metrics,
+            "",
             Some(&observation_log),
+            &[
+                Bytes::from_static(b"SET"),
+                Bytes::from_static(b"key"),
+                Bytes::from_static(b"value"),
+            ],
         );
 
-        assert_eq!(response, b"-ERR engine error\r\n");
+        assert_eq!(response, b"-ERR internal engine error\r\n");
         assert!(observation_log.observations().is_empty());
     }
+
+    #[test]
+    fn engine_errors_map_to_redis_error_replies() {
+        let oom = b"-OOM command not allowed when used memory > 'maxmemory'.\r\n";
+        let cases: [(HkvError, &[u8]); 13] = [
+            (HkvError::InvalidInput, b"-ERR invalid argument\r\n"),
+            (HkvError::NotFound, b"-ERR no such key\r\n"),
+            (HkvError::KeyTooLong, b"-ERR key is too long\r\n"),
+            (
+                HkvError::ValueTooLong,
+                b"-ERR string exceeds maximum allowed size\r\n",
+            ),
+            (HkvError::OutOfMemory, oom),
+            (HkvError::CapacityExceeded, oom),
+            (HkvError::InternalError, b"-ERR internal engine error\r\n"),
+            (
+                HkvError::Busy,
+                b"-BUSY the engine is busy, try again later\r\n",
+            ),
+            (
+                HkvError::Timeout,
+                b"-BUSY the engine timed out, try again later\r\n",
+            ),
+            (
+                HkvError::Interrupted,
+                b"-BUSY the engine was interrupted, try again later\r\n",
+            ),
+            (
+                HkvError::VersionMismatch,
+                b"-ERR engine version mismatch\r\n",
+            ),
+            (
+                HkvError::ProtocolViolation,
+                b"-ERR engine protocol violation\r\n",
+            ),
+            (
+                HkvError::UnsupportedCommand,
+                b"-ERR command not supported by the engine\r\n",
+            ),
+        ];
+        for (err, reply) in cases {
+            assert_eq!(engine_error(err), reply, "{err:?}");
+        }
+        assert_eq!(error_prefix(oom), b"OOM");
+        assert_eq!(error_prefix(b"-NOAUTH\r\n"), b"NOAUTH");
+    }
+
+    #[test]
+    fn data_commands_wait_for_loading_to_finish() {
+        let engine = MemoryEngine::new();
+        let runtime = RuntimeConfig::new();
+        runtime.lifecycle().advance(LifecyclePhase::Loading);
+        let dispatch = |args: &[&[u8]]| dispatch_in(&engine, &runtime, args);
+
+        assert_eq!(dispatch(&[b"GET", b"k"]), LOADING_ERROR);
+        assert_eq!(dispatch(&[b"SET", b"k", b"v"]), LOADING_ERROR);
+        assert_eq!(dispatch(&[b"PING"]), b"+PONG\r\n");
+
+        runtime.lifecycle().advance(LifecyclePhase::Ready);
+        assert_eq!(dispatch(&[b"GET", b"k"]), b"$-1\r\n");
+    }
+
+    /// A `MemoryEngine` whose `KEYS` takes a millisecond per key and
+    /// checks its abort flag before each one.
+    #[derive(Default)]
+    struct SlowEngine {
+        inner: MemoryEngine,
+        visited: AtomicU64,
+    }
+
+    impl KVEngine for SlowEngine {
+        fn get(&self, key: &[u8]) -> HkvResult<Option<Arc<[u8]>>> {
+            self.inner.get(key)
+        }
+
+        fn set(&self, key: Vec<u8>, value: Vec<u8>) -> HkvResult<()> {
+            self.inner.set(key, value)
+        }
+
+        fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: TtlAfter) -> HkvResult<()> {
+            self.inner.set_with_ttl(key, value, ttl)
+        }
+
+        fn delete(&self, key: &[u8]) -> HkvResult<bool> {
+            self.inner.delete(key)
+        }
+
+        fn expire(&self, key: &[u8], ttl: TtlAfter) -> HkvResult<()> {
+            self.inner.expire(key, ttl)
+        }
+
+        fn ttl(&self, key: &[u8]) -> HkvResult<TtlStatus> {
+            self.inner.ttl(key)
+        }
+
+        fn snapshot(&self) -> HkvResult<Vec<hkv_engine::SnapshotEntry>> {
+            self.inner.snapshot()
+        }
+
+        fn keys(&self, should_abort: Option<&AtomicBool>) -> HkvResult<Vec<Arc<[u8]>>> {
+            let keys = self.inner.keys(None)?;
+            for _ in &keys {
+                if should_abort.is_some_and(|abort| abort.load(Ordering::Relaxed)) {
+                    return Err(HkvError::Interrupted);
+                }
+                self.visited.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok(keys)
+        }
+
+        fn stats(&self) -> hkv_engine::EngineStats {
+            self.inner.stats()
+        }
+
+        fn avg_ttl_ms(&self) -> u64 {
+            self.inner.avg_ttl_ms()
+        }
+    }
+
+    #[test]
+    fn mget_replies_with_each_value_or_null() {
+        let engine = MemoryEngine::new();
+        let runtime = RuntimeConfig::new();
+        let dispatch = |args: &[&[u8]]| dispatch_in(&engine, &runtime, args);
+        let large = vec![b'x'; crate::reply::VECTORED_VALUE_MIN];
+        assert_eq!(dispatch(&[b"SET", b"a", b"1"]), b"+OK\r\n");
+        assert_eq!(dispatch(&[b"SET", b"large", &large]), b"+OK\r\n");
+
+        let mut expected = b"*3\r\n$1\r\n1\r\n$-1\r\n$1024\r\n".to_vec();
+        expected.extend_from_slice(&large);
+        expected.extend_from_slice(b"\r\n");
+        assert_eq!(dispatch(&[b"MGET", b"a", b"missing", b"large"]), expected);
+        assert_eq!(dispatch(&[b"GET", b"large"]), expected[16..]);
+        assert!(is_error_response(&dispatch(&[b"MGET"])));
+    }
+
+    #[test]
+    fn keys_and_flushall_follow_the_pattern_and_empty_the_engine() {
+        let engine = MemoryEngine::new();
+        let runtime = RuntimeConfig::new();
+        let dispatch = |args: &[&[u8]]| dispatch_in(&engine, &runtime, args);
+        for key in [&b"user:1"[..], b"user:2", b"session"] {
+            assert_eq!(dispatch(&[b"SET", key, b"v"]), b"+OK\r\n");
+        }
+
+        let reply = dispatch(&[b"KEYS", b"user:*"]);
+        assert!(
+            reply == b"*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n"
+                || reply == b"*2\r\n$6\r\nuser:2\r\n$6\r\nuser:1\r\n",
+            "{}",
+            reply.escape_ascii()
+        );
+        assert_eq!(dispatch(&[b"KEYS", b"nothing*"]), b"*0\r\n");
+        assert!(is_error_response(&dispatch(&[b"FLUSHALL", b"LATER"])));
+        assert_eq!(dispatch(&[b"FLUSHALL", b"SYNC"]), b"+OK\r\n");
+        assert_eq!(dispatch(&[b"KEYS", b"*"]), b"*0\r\n");
+        assert_eq!(dispatch(&[b"flushdb"]), b"+OK\r\n");
+    }
+
+    /// Serves `engine` with `runtime`, returning its address.
+    async fn spawn_slow_server(
+        engine: Arc<SlowEngine>,
+        runtime: RuntimeConfig,
+    ) -> (SocketAddr, ShutdownController) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = ShutdownController::new();
+        tokio::spawn(serve_with_runtime_config(
+            listener,
+            engine,
+            Arc::new(Metrics::new()),
+            Arc::new(Persistence::default()),
+            Arc::new(runtime),
+            shutdown.wait(),
+            Duration::from_secs(1),
+        ));
+        (addr, shutdown)
+    }
+
+    /// Sends `request` and reads a reply of at most one read.
+    async fn request(stream: &mut TcpStream, request: &[u8]) -> Vec<u8> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        stream.write_all(request).await.unwrap();
+        let mut reply = vec![0; 64];
+        let n = stream.read(&mut reply).await.unwrap();
+        reply.truncate(n);
+        reply
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn keyspace_commands_past_the_time_limit_reply_busy_and_stop() {
+        let engine = Arc::new(SlowEngine::default());
+        for i in 0..500 {
+            engine
+                .set(format!("key:{i}").into_bytes(), b"v".to_vec())
+                .unwrap();
+        }
+        let runtime = RuntimeConfig::new();
+        runtime.set_command_time_limit(Duration::from_millis(20));
+        let (addr, shutdown) = spawn_slow_server(Arc::clone(&engine), runtime).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let started = Instant::now();
+        let reply = request(&mut stream, b"*2\r\n$4\r\nKEYS\r\n$1\r\n*\r\n").await;
+        assert_eq!(reply, TIME_LIMIT_ERROR);
+        assert!(started.elapsed() < Duration::from_millis(400));
+        // The scan stopped when the command gave up, not after it.
+        let visited = engine.visited.load(Ordering::Relaxed);
+        assert!(visited < 500, "{visited}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(engine.visited.load(Ordering::Relaxed), visited);
+
+        // FLUSHALL runs past the limit rather than stop half done.
+        let reply = request(&mut stream, b"*1\r\n$8\r\nFLUSHALL\r\n").await;
+        assert_eq!(reply, b"+OK\r\n");
+        assert_eq!(engine.stats().keys, 0);
+        // The connection state came back with the reply.
+        assert_eq!(
+            request(&mut stream, b"*1\r\n$4\r\nPING\r\n").await,
+            b"+PONG\r\n"
+        );
+
+        shutdown.trigger();
+    }
+
+    #[tokio::test]
+    async fn keyspace_commands_leave_the_worker_free() {
+        let engine = Arc::new(SlowEngine::default());
+        for i in 0..300 {
+            engine
+                .set(format!("key:{i}").into_bytes(), b"v".to_vec())
+                .unwrap();
+        }
+        // No time limit, on a runtime with a single worker thread.
+        let (addr, shutdown) = spawn_slow_server(Arc::clone(&engine), RuntimeConfig::new()).await;
+        let mut scanning = TcpStream::connect(addr).await.unwrap();
+        let mut other = TcpStream::connect(addr).await.unwrap();
+
+        let flush =
+            tokio::spawn(async move { request(&mut scanning, b"*1\r\n$7\r\nFLUSHDB\r\n").await });
+        while engine.visited.load(Ordering::Relaxed) == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(
+            request(&mut other, b"*1\r\n$4\r\nPING\r\n").await,
+            b"+PONG\r\n"
+        );
+        assert!(engine.visited.load(Ordering::Relaxed) < 300);
+
+        assert_eq!(flush.await.unwrap(), b"+OK\r\n");
+        assert_eq!(engine.stats().keys, 0);
+        shutdown.trigger();
+    }
+
+    #[tokio::test]
+    async fn debug_sleep_leaves_the_worker_free() {
+        let engine = Arc::new(SlowEngine::default());
+        let (addr, shutdown) = spawn_slow_server(engine, RuntimeConfig::new()).await;
+        let mut sleeping = TcpStream::connect(addr).await.unwrap();
+        let mut other = TcpStream::connect(addr).await.unwrap();
+
+        let started = Instant::now();
+        let sleep = tokio::spawn(async move {
+            request(
+                &mut sleeping,
+                b"*3\r\n$5\r\nDEBUG\r\n$5\r\nSLEEP\r\n$3\r\n0.5\r\n",
+            )
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            request(&mut other, b"*1\r\n$4\r\nPING\r\n").await,
+            b"+PONG\r\n"
+        );
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert_eq!(sleep.await.unwrap(), b"+OK\r\n");
+        shutdown.trigger();
+    }
 }
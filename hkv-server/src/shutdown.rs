@@ -0,0 +1,164 @@
+//! # Graceful Shutdown
+//!
+//! One trigger shared by the process signal handlers, the accept loop, and
+//! every connection.
+//!
+//! ## Design Principles
+//!
+//! 1. **Broadcast Once**: `ShutdownController` wraps a `watch` channel that
+//!    flips from `false` to `true` exactly once; any number of
+//!    `ShutdownToken`s observe it without polling.
+//! 2. **Finish What Was Sent**: Connections check their token between reads.
+//!    Commands the client already sent are answered and flushed before the
+//!    socket closes; nothing new is read after that.
+//! 3. **Signals Are Just Triggers**: SIGINT/SIGTERM call `trigger`, the same
+//!    path tests use, so signal-driven and programmatic shutdown behave
+//!    identically.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Owner side of the shutdown broadcast; clones share the same trigger.
+#[derive(Clone, Debug)]
+pub struct ShutdownController {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownController {
+    /// Creates an untriggered controller.
+    pub fn new() -> Self {
+        ShutdownController {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Starts shutdown; later calls are no-ops.
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Returns true once `trigger` has been called.
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Returns a token that observes this controller.
+    pub fn token(&self) -> ShutdownToken {
+        ShutdownToken {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// Returns a future that resolves once shutdown is triggered.
+    ///
+    /// Pass it as the `shutdown` argument of the `serve_with_shutdown*`
+    /// functions.
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut token = self.token();
+        async move { token.wait().await }
+    }
+
+    /// Triggers shutdown on the first SIGINT or SIGTERM (Ctrl-C elsewhere).
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(unix)]
+    pub fn listen_for_signals(&self) -> std::io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let controller = self.clone();
+        tokio::spawn(async move {
+            let signal = tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            };
+            tracing::info!(signal, "shutdown: signal received");
+            controller.trigger();
+        });
+        Ok(())
+    }
+
+    /// Triggers shutdown on the first SIGINT or SIGTERM (Ctrl-C elsewhere).
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(not(unix))]
+    pub fn listen_for_signals(&self) -> std::io::Result<()> {
+        let controller = self.clone();
+        tokio::spawn(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            tracing::info!("shutdown: Ctrl-C received");
+            controller.trigger();
+        });
+        Ok(())
+    }
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receiver side of the shutdown broadcast, held by each connection.
+#[derive(Clone, Debug)]
+pub struct ShutdownToken {
+    rx: watch::Receiver<bool>,
+}
+
+impl ShutdownToken {
+    /// Returns true once shutdown has been triggered.
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once shutdown is triggered; never resolves if every
+    /// controller was dropped without triggering.
+    pub async fn wait(&mut self) {
+        if self.rx.wait_for(|triggered| *triggered).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn trigger_wakes_every_token() {
+        let controller = ShutdownController::new();
+        let mut early = controller.token();
+        let waiter = tokio::spawn(controller.wait());
+
+        assert!(!controller.is_triggered());
+        assert!(!early.is_triggered());
+
+        controller.clone().trigger();
+        timeout(Duration::from_secs(1), early.wait()).await.unwrap();
+        timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Tokens taken after the trigger resolve immediately.
+        let mut late = controller.token();
+        assert!(late.is_triggered());
+        timeout(Duration::from_secs(1), late.wait()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_controller_never_fires() {
+        let mut token = ShutdownController::new().token();
+        assert!(
+            timeout(Duration::from_millis(20), token.wait())
+                .await
+                .is_err()
+        );
+    }
+}
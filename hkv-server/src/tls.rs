@@ -0,0 +1,235 @@
+//! # TLS Termination
+//!
+//! Optional TLS for client connections (`tls` feature), terminated with
+//! rustls before a connection reaches the RESP handler.
+//!
+//! ## Design Principles
+//!
+//! 1. **Stream-Generic Handler**: A completed handshake yields a `TlsStream`
+//!    that the connection handler serves exactly like a `TcpStream`; command
+//!    code never sees TLS.
+//! 2. **Handshakes Off the Accept Loop**: Each handshake runs in its
+//!    connection's task under `HANDSHAKE_TIMEOUT`. A failed or stalled
+//!    handshake closes that connection and is counted in
+//!    `tls_handshake_failures_total`; accepting continues.
+//! 3. **Hot Reload**: `TlsState::reload` rereads the files and swaps the
+//!    acceptor. Running handshakes finish with the old one and new ones use
+//!    the new one, so certificates rotate without a restart (SIGHUP through
+//!    `reload_on_sighup`).
+//! 4. **Fail at Load**: Unreadable, empty or mismatched files are reported
+//!    with their path when loading; a failed reload keeps the previous
+//!    acceptor.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::crypto::{CryptoProvider, ring};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::server::danger::ClientCertVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+
+/// Longest a client may take to complete its handshake.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate, key and client-authentication settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificate chain presented to clients, leaf first.
+    pub cert_file: PathBuf,
+    /// PEM private key for the leaf certificate.
+    pub key_file: PathBuf,
+    /// PEM CA bundle used to verify client certificates.
+    pub ca_file: Option<PathBuf>,
+    /// Refuse clients without a certificate signed by `ca_file`; when
+    /// false, such a certificate is verified if offered but not required.
+    pub require_client_cert: bool,
+}
+
+impl TlsConfig {
+    /// Server-authenticated TLS with the given certificate chain and key.
+    pub fn new(cert_file: impl Into<PathBuf>, key_file: impl Into<PathBuf>) -> Self {
+        TlsConfig {
+            cert_file: cert_file.into(),
+            key_file: key_file.into(),
+            ca_file: None,
+            require_client_cert: false,
+        }
+    }
+
+    /// Verifies client certificates against `ca_file`, requiring one when
+    /// `require` is set.
+    pub fn with_client_ca(mut self, ca_file: impl Into<PathBuf>, require: bool) -> Self {
+        self.ca_file = Some(ca_file.into());
+        self.require_client_cert = require;
+        self
+    }
+}
+
+/// The loaded configuration and the acceptor built from it.
+pub struct TlsState {
+    config: TlsConfig,
+    acceptor: RwLock<TlsAcceptor>,
+}
+
+impl TlsState {
+    /// Reads the files named in `config` and builds the acceptor.
+    ///
+    /// # Errors
+    /// Returns `InvalidInput` for a client-certificate requirement without a
+    /// CA, or the read or parse error of the first bad file.
+    pub fn load(config: TlsConfig) -> io::Result<Self> {
+        let acceptor = build_acceptor(&config)?;
+        Ok(TlsState {
+            config,
+            acceptor: RwLock::new(acceptor),
+        })
+    }
+
+    /// The configuration the files are read from.
+    pub fn config(&self) -> &TlsConfig {
+        &self.config
+    }
+
+    /// Rereads the files; connections accepted afterwards use them.
+    ///
+    /// # Errors
+    /// As for `load`; the previous acceptor stays in use.
+    pub fn reload(&self) -> io::Result<()> {
+        let acceptor = build_acceptor(&self.config)?;
+        *self.acceptor.write().unwrap_or_else(|err| err.into_inner()) = acceptor;
+        Ok(())
+    }
+
+    /// Reloads the files on every SIGHUP, logging failures.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self: &Arc<Self>) -> io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        let state = Arc::clone(self);
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if let Err(err) = state.reload() {
+                    tracing::warn!(error = %err, "TLS reload failed, keeping previous certificates");
+                } else {
+                    tracing::info!("TLS certificates reloaded");
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Performs the server side of the handshake on `stream`.
+    ///
+    /// # Errors
+    /// Returns the handshake error, or `TimedOut` after `HANDSHAKE_TIMEOUT`.
+    pub async fn accept<S>(&self, stream: S) -> io::Result<TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let acceptor = self
+            .acceptor
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+    }
+}
+
+fn build_acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    if config.require_client_cert && config.ca_file.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "requiring client certificates needs a CA file",
+        ));
+    }
+    let provider = Arc::new(ring::default_provider());
+    let certs = load_certs(&config.cert_file)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_file)
+        .map_err(|err| invalid_file(&config.key_file, err))?;
+
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = match &config.ca_file {
+        Some(ca_file) => builder.with_client_cert_verifier(client_verifier(
+            ca_file,
+            config.require_client_cert,
+            provider,
+        )?),
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| invalid_file(&config.key_file, err))?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn client_verifier(
+    ca_file: &Path,
+    require: bool,
+    provider: Arc<CryptoProvider>,
+) -> io::Result<Arc<dyn ClientCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_file)? {
+        roots.add(cert).map_err(|err| invalid_file(ca_file, err))?;
+    }
+    let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+    let builder = if require {
+        builder
+    } else {
+        builder.allow_unauthenticated()
+    };
+    builder.build().map_err(|err| invalid_file(ca_file, err))
+}
+
+/// Reads every certificate in a PEM file; at least one is required.
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| invalid_file(path, err))?;
+    if certs.is_empty() {
+        return Err(invalid_file(path, "no certificates found"));
+    }
+    Ok(certs)
+}
+
+fn invalid_file(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {err}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_cert_requirement_needs_a_ca() {
+        let mut config = TlsConfig::new("cert.pem", "key.pem");
+        config.require_client_cert = true;
+        let err = TlsState::load(config).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn missing_files_name_their_path() {
+        let err = TlsState::load(TlsConfig::new("/nonexistent/cert.pem", "key.pem"))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("/nonexistent/cert.pem"), "{err}");
+    }
+}
@@ -0,0 +1,639 @@
+//! # Client-Side Caching
+//!
+//! `CLIENT TRACKING` lets clients cache what they read and be told when it
+//! changes: the server remembers which connections read which keys and, when
+//! a key is written, sends each of them an `invalidate` RESP3 push naming it.
+//!
+//! ## Design Principles
+//!
+//! 1. **Track Before Reading**: A read registers its key before it executes,
+//!    so a write racing with it always invalidates what it returned.
+//! 2. **Bounded Table**: The key table holds at most
+//!    `tracking-table-max-keys` keys (0 for no limit); past that, arbitrary
+//!    keys are dropped and invalidated as if written, so no client keeps a
+//!    value the server has stopped watching.
+//! 3. **Broadcast Without a Table**: `BCAST` clients hear about every
+//!    written key that matches one of their prefixes, or every key without
+//!    one, and add nothing to the table.
+//! 4. **Lose the Connection, Not the Message**: Pushes wait in a bounded
+//!    queue per connection; a connection that lets it fill up, or queues
+//!    more bytes than the `pubsub` class of `client-output-buffer-limit`
+//!    allows, is closed, since a dropped invalidation would leave its cache
+//!    silently stale.
+//! 5. **Clean Up on Disconnect**: A connection that disconnects or turns
+//!    tracking off is removed from every key it was tracking.
+//!
+//! `FLUSHALL` and `FLUSHDB` empty the table and send every tracking client,
+//! `BCAST` or not, an `invalidate` push with a null key list, as Redis does.
+//!
+//! Only writes made through client connections invalidate: keys that expire,
+//! are evicted or change through the replication stream do not, since the
+//! engine has no key-event hook yet. Without Pub/Sub, `REDIRECT` targets
+//! must speak RESP3 and receive the same push frames.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use bytes::Bytes;
+use tokio::sync::{Notify, mpsc};
+
+use crate::config::{ClientClass, OutputBufferLimit, RuntimeConfig};
+use crate::server::{CommandFlag, command_spec};
+
+/// Pushes a connection may have waiting before it is closed.
+pub const PUSH_QUEUE_LEN: usize = 1024;
+
+/// Options given to `CLIENT TRACKING ON`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingOptions {
+    /// Client id that receives the invalidations instead.
+    pub redirect: Option<u64>,
+    /// Hear about every written key instead of only the ones read.
+    pub bcast: bool,
+    /// Key prefixes a `bcast` client hears about; empty for all keys.
+    pub prefixes: Vec<Vec<u8>>,
+}
+
+/// Why `CLIENT TRACKING ON` was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingError {
+    /// Neither this connection nor a redirect target can receive pushes.
+    NeedsResp3,
+    /// The `REDIRECT` client id is not connected.
+    NoSuchRedirect,
+    /// The `REDIRECT` client is connected but does not speak RESP3.
+    RedirectNeedsResp3,
+}
+
+impl std::fmt::Display for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TrackingError::NeedsResp3 => {
+                "CLIENT TRACKING needs RESP3 (HELLO 3) or a REDIRECT to a RESP3 connection"
+            }
+            TrackingError::NoSuchRedirect => "The client ID you want redirect to does not exist",
+            TrackingError::RedirectNeedsResp3 => {
+                "The client you want redirect to must use RESP3, Pub/Sub is not supported"
+            }
+        })
+    }
+}
+
+/// Which connections track which keys, shared by every connection.
+pub struct Tracker {
+    /// Source of `tracking-table-max-keys`.
+    runtime: Arc<RuntimeConfig>,
+    /// Connections with tracking on; writes skip the lock while it is 0.
+    tracking_clients: AtomicU64,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    clients: HashMap<u64, Client>,
+    /// Key → ids of clients that read it since it was last invalidated.
+    keys: HashMap<Vec<u8>, HashSet<u64>>,
+    /// Ids of `BCAST` clients and their prefixes.
+    broadcasts: BTreeMap<u64, Vec<Vec<u8>>>,
+}
+
+struct Client {
+    /// Dropped when the queue overflows, which closes the connection.
+    pushes: Option<mpsc::Sender<Vec<u8>>>,
+    /// Bytes queued in `pushes`, shared with the `TrackedClient`.
+    queued: Arc<AtomicU64>,
+    /// Signalled when `pushes` is dropped, shared with the `TrackedClient`.
+    closed: Arc<Notify>,
+    /// When `queued` first passed the soft output buffer limit.
+    soft_since: Option<Instant>,
+    resp3: bool,
+    tracking: Option<TrackingOptions>,
+}
+
+impl Tracker {
+    /// Creates an empty tracker reading its table limit from `runtime`.
+    pub fn new(runtime: Arc<RuntimeConfig>) -> Self {
+        Tracker {
+            runtime,
+            tracking_clients: AtomicU64::new(0),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Registers connection `client_id`; it is forgotten when the returned
+    /// handle drops.
+    pub fn connect(self: &Arc<Self>, client_id: u64) -> TrackedClient {
+        let (sender, pushes) = mpsc::channel(PUSH_QUEUE_LEN);
+        let queued = Arc::new(AtomicU64::new(0));
+        let closed = Arc::new(Notify::new());
+        self.lock().clients.insert(
+            client_id,
+            Client {
+                pushes: Some(sender),
+                queued: Arc::clone(&queued),
+                closed: Arc::clone(&closed),
+                soft_since: None,
+                resp3: false,
+                tracking: None,
+            },
+        );
+        TrackedClient {
+            tracker: Arc::clone(self),
+            client_id,
+            pushes,
+            queued,
+            closed,
+        }
+    }
+
+    /// Keys currently in the tracking table.
+    pub fn tracked_keys(&self) -> usize {
+        self.lock().keys.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn track(&self, client_id: u64, keys: &[Vec<u8>]) {
+        let mut state = self.lock();
+        let tracks_reads = state
+            .clients
+            .get(&client_id)
+            .and_then(|client| client.tracking.as_ref())
+            .is_some_and(|tracking| !tracking.bcast);
+        if !tracks_reads {
+            return;
+        }
+        for key in keys {
+            state.keys.entry(key.clone()).or_default().insert(client_id);
+        }
+        let max_keys = self.runtime.tracking_table_max_keys();
+        let excess = (state.keys.len() as u64).saturating_sub(max_keys);
+        if max_keys == 0 || excess == 0 {
+            return;
+        }
+        // HashMap order is arbitrary, which makes this a random eviction.
+        let evicted: Vec<Vec<u8>> = state.keys.keys().take(excess as usize).cloned().collect();
+        tracing::debug!(keys = evicted.len(), "tracking table full, evicting keys");
+        state.invalidate(&evicted, false, self.push_limit());
+    }
+
+    fn invalidate(&self, keys: &[Vec<u8>]) {
+        if self.tracking_clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let limit = self.push_limit();
+        self.lock().invalidate(keys, true, limit);
+    }
+
+    fn invalidate_all(&self) {
+        if self.tracking_clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let limit = self.push_limit();
+        self.lock().invalidate_all(limit);
+    }
+
+    fn push_limit(&self) -> OutputBufferLimit {
+        self.runtime.output_buffer_limit(ClientClass::Pubsub)
+    }
+
+    fn disconnect(&self, client_id: u64) {
+        let mut state = self.lock();
+        self.stop_tracking(&mut state, client_id);
+        state.clients.remove(&client_id);
+    }
+
+    fn stop_tracking(&self, state: &mut State, client_id: u64) {
+        let Some(tracking) = state
+            .clients
+            .get_mut(&client_id)
+            .and_then(|client| client.tracking.take())
+        else {
+            return;
+        };
+        self.tracking_clients.fetch_sub(1, Ordering::Relaxed);
+        if tracking.bcast {
+            state.broadcasts.remove(&client_id);
+        } else {
+            state.keys.retain(|_, readers| {
+                readers.remove(&client_id);
+                !readers.is_empty()
+            });
+        }
+    }
+}
+
+impl State {
+    /// Removes `keys` from the table and sends one push per affected client
+    /// naming the keys it cares about; `written` keys also reach `BCAST`
+    /// clients.
+    fn invalidate(&mut self, keys: &[Vec<u8>], written: bool, limit: OutputBufferLimit) {
+        let mut batches: BTreeMap<u64, Vec<&[u8]>> = BTreeMap::new();
+        for key in keys {
+            if let Some(readers) = self.keys.remove(key) {
+                for reader in readers {
+                    batches.entry(reader).or_default().push(key);
+                }
+            }
+            if !written {
+                continue;
+            }
+            for (&client_id, prefixes) in &self.broadcasts {
+                if prefixes.is_empty() || prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+                    batches.entry(client_id).or_default().push(key);
+                }
+            }
+        }
+        for (client_id, keys) in batches {
+            let push = invalidate_push(&keys);
+            self.send(client_id, &push, limit);
+        }
+    }
+
+    /// Empties the table and tells every tracking client to drop its whole
+    /// cache.
+    fn invalidate_all(&mut self, limit: OutputBufferLimit) {
+        self.keys.clear();
+        let tracking: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.tracking.is_some())
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        for client_id in tracking {
+            self.send(client_id, FLUSH_PUSH, limit);
+        }
+    }
+
+    /// Queues `push` for `client_id`'s redirect target, or the client,
+    /// closing the target if its queue overflows or passes `limit`.
+    fn send(&mut self, client_id: u64, push: &[u8], limit: OutputBufferLimit) {
+        let target = self
+            .clients
+            .get(&client_id)
+            .and_then(|client| client.tracking.as_ref())
+            .and_then(|tracking| tracking.redirect)
+            .unwrap_or(client_id);
+        let Some(client) = self.clients.get_mut(&target).filter(|client| client.resp3) else {
+            return;
+        };
+        let Some(pushes) = &client.pushes else {
+            return;
+        };
+        match pushes.try_send(push.to_vec()) {
+            Ok(()) => {
+                let queued = client
+                    .queued
+                    .fetch_add(push.len() as u64, Ordering::Relaxed)
+                    + push.len() as u64;
+                if limit.exceeded(queued, &mut client.soft_since, Instant::now()) {
+                    tracing::warn!(
+                        client = target,
+                        queued,
+                        "invalidations over client-output-buffer-limit, closing connection"
+                    );
+                    client.close();
+                }
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!(
+                    client = target,
+                    "invalidation queue full, closing connection"
+                );
+                client.close();
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+impl Client {
+    /// Stops queueing pushes and tells the connection to close.
+    fn close(&mut self) {
+        self.pushes = None;
+        // Stores a permit if the connection is not waiting yet.
+        self.closed.notify_one();
+    }
+}
+
+/// A connection's registration with the `Tracker`.
+pub struct TrackedClient {
+    tracker: Arc<Tracker>,
+    client_id: u64,
+    pushes: mpsc::Receiver<Vec<u8>>,
+    queued: Arc<AtomicU64>,
+    closed: Arc<Notify>,
+}
+
+impl TrackedClient {
+    /// Records whether the connection negotiated RESP3 with `HELLO`;
+    /// only RESP3 connections receive pushes.
+    pub fn set_resp3(&self, resp3: bool) {
+        if let Some(client) = self.tracker.lock().clients.get_mut(&self.client_id) {
+            client.resp3 = resp3;
+        }
+    }
+
+    /// Turns tracking on, replacing any previous options.
+    pub fn enable(&self, options: TrackingOptions) -> Result<(), TrackingError> {
+        let mut state = self.tracker.lock();
+        let receiver = options.redirect.unwrap_or(self.client_id);
+        match state.clients.get(&receiver) {
+            Some(client) if client.resp3 => {}
+            Some(_) if options.redirect.is_some() => {
+                return Err(TrackingError::RedirectNeedsResp3);
+            }
+            Some(_) => return Err(TrackingError::NeedsResp3),
+            None => return Err(TrackingError::NoSuchRedirect),
+        }
+        self.tracker.stop_tracking(&mut state, self.client_id);
+        if options.bcast {
+            state
+                .broadcasts
+                .insert(self.client_id, options.prefixes.clone());
+        }
+        if let Some(client) = state.clients.get_mut(&self.client_id) {
+            client.tracking = Some(options);
+            self.tracker
+                .tracking_clients
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Turns tracking off and forgets every key this connection read.
+    pub fn disable(&self) {
+        let mut state = self.tracker.lock();
+        self.tracker.stop_tracking(&mut state, self.client_id);
+    }
+
+    /// The `CLIENT GETREDIR` reply: -1 when not tracking, 0 without a
+    /// redirect, otherwise the target's id.
+    pub fn redirect(&self) -> i64 {
+        match self
+            .tracker
+            .lock()
+            .clients
+            .get(&self.client_id)
+            .and_then(|client| client.tracking.as_ref())
+        {
+            None => -1,
+            Some(tracking) => tracking.redirect.map_or(0, |id| id as i64),
+        }
+    }
+
+    /// Tracks the keys `args` reads, before it executes.
+    pub fn before_command(&self, args: &[Bytes]) {
+        if self.tracker.tracking_clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let keys = keys_with(args, CommandFlag::Readonly);
+        if !keys.is_empty() {
+            self.tracker.track(self.client_id, &keys);
+        }
+    }
+
+    /// Invalidates the keys `args` wrote, or every key after a flush, if
+    /// it succeeded.
+    pub fn after_command(&self, args: &[Bytes], failed: bool) {
+        if failed || self.tracker.tracking_clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        if args.first().is_some_and(|name| {
+            name.eq_ignore_ascii_case(b"flushall") || name.eq_ignore_ascii_case(b"flushdb")
+        }) {
+            self.tracker.invalidate_all();
+            return;
+        }
+        let keys = keys_with(args, CommandFlag::Write);
+        if !keys.is_empty() {
+            self.tracker.invalidate(&keys);
+        }
+    }
+
+    /// The next push frame to write, or `None` once the connection must
+    /// close because its queue overflowed or passed its output buffer limit.
+    pub async fn next_push(&mut self) -> Option<Vec<u8>> {
+        let push = self.pushes.recv().await?;
+        self.queued.fetch_sub(push.len() as u64, Ordering::Relaxed);
+        Some(push)
+    }
+
+    /// Resolves once the tracker has closed the connection, without
+    /// waiting for the pushes already queued to be written.
+    pub async fn closed(&self) {
+        self.closed.notified().await;
+    }
+}
+
+impl Drop for TrackedClient {
+    fn drop(&mut self) {
+        self.tracker.disconnect(self.client_id);
+    }
+}
+
+/// The keys of `args` if its command has `flag` and a valid arity.
+fn keys_with(args: &[Bytes], flag: CommandFlag) -> Vec<Vec<u8>> {
+    match args.first().and_then(|name| command_spec(name)) {
+        Some(spec) if spec.has(flag) && spec.accepts(args.len()) => {
+            spec.keys_of(args).map(|key| key.to_vec()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The `invalidate` RESP3 push with a null key list sent after a flush.
+const FLUSH_PUSH: &[u8] = b">2\r\n$10\r\ninvalidate\r\n_\r\n";
+
+/// An `invalidate` RESP3 push naming `keys`.
+fn invalidate_push(keys: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!(">2\r\n$10\r\ninvalidate\r\n*{}\r\n", keys.len()).into_bytes();
+    for key in keys {
+        out.extend_from_slice(format!("${}\r\n", key.len()).as_bytes());
+        out.extend_from_slice(key);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn args(args: &[&[u8]]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+    }
+
+    fn tracker(max_keys: u64) -> Arc<Tracker> {
+        let runtime = RuntimeConfig::new();
+        runtime.set_tracking_table_max_keys(max_keys);
+        Arc::new(Tracker::new(Arc::new(runtime)))
+    }
+
+    fn resp3_client(tracker: &Arc<Tracker>, id: u64) -> TrackedClient {
+        let client = tracker.connect(id);
+        client.set_resp3(true);
+        client
+    }
+
+    #[test]
+    fn readers_are_told_once_about_written_keys() {
+        let tracker = tracker(0);
+        let mut reader = resp3_client(&tracker, 1);
+        let writer = tracker.connect(2);
+        reader.enable(TrackingOptions::default()).unwrap();
+
+        reader.before_command(&args(&[b"GET", b"k"]));
+        writer.after_command(&args(&[b"SET", b"k", b"v"]), false);
+        assert_eq!(
+            reader.pushes.try_recv().unwrap(),
+            b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n"
+        );
+        // Invalidated keys must be read again to be tracked again.
+        writer.after_command(&args(&[b"DEL", b"k"]), false);
+        assert!(reader.pushes.try_recv().is_err());
+        writer.after_command(&args(&[b"SET", b"k", b"v"]), true);
+        assert_eq!(tracker.tracked_keys(), 0);
+    }
+
+    #[test]
+    fn broadcast_clients_match_prefixes_without_the_table() {
+        let tracker = tracker(0);
+        let mut watcher = resp3_client(&tracker, 1);
+        let writer = tracker.connect(2);
+        watcher
+            .enable(TrackingOptions {
+                bcast: true,
+                prefixes: vec![b"user:".to_vec()],
+                ..TrackingOptions::default()
+            })
+            .unwrap();
+
+        watcher.before_command(&args(&[b"GET", b"user:1"]));
+        assert_eq!(tracker.tracked_keys(), 0);
+        writer.after_command(&args(&[b"DEL", b"user:1", b"order:1", b"user:2"]), false);
+        assert_eq!(
+            watcher.pushes.try_recv().unwrap(),
+            b">2\r\n$10\r\ninvalidate\r\n*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n"
+        );
+    }
+
+    #[test]
+    fn flushes_tell_every_tracking_client_to_drop_its_cache() {
+        let tracker = tracker(0);
+        let mut reader = resp3_client(&tracker, 1);
+        let mut watcher = resp3_client(&tracker, 2);
+        let mut idle = resp3_client(&tracker, 3);
+        let writer = tracker.connect(4);
+        reader.enable(TrackingOptions::default()).unwrap();
+        watcher
+            .enable(TrackingOptions {
+                bcast: true,
+                prefixes: vec![b"user:".to_vec()],
+                ..TrackingOptions::default()
+            })
+            .unwrap();
+
+        reader.before_command(&args(&[b"GET", b"k"]));
+        writer.after_command(&args(&[b"FLUSHALL", b"SYNC"]), true);
+        assert!(reader.pushes.try_recv().is_err());
+        writer.after_command(&args(&[b"flushdb"]), false);
+        for client in [&mut reader, &mut watcher] {
+            assert_eq!(client.pushes.try_recv().unwrap(), FLUSH_PUSH);
+        }
+        assert!(idle.pushes.try_recv().is_err());
+        assert_eq!(tracker.tracked_keys(), 0);
+        // The flush already covered `k`, so a later write sends nothing.
+        writer.after_command(&args(&[b"SET", b"k", b"v"]), false);
+        assert!(reader.pushes.try_recv().is_err());
+    }
+
+    #[test]
+    fn full_table_evicts_and_disconnects_clean_up() {
+        let tracker = tracker(2);
+        let mut reader = resp3_client(&tracker, 1);
+        reader.enable(TrackingOptions::default()).unwrap();
+        for key in [b"a", b"b", b"c"] {
+            reader.before_command(&args(&[b"GET", key]));
+        }
+        assert_eq!(tracker.tracked_keys(), 2);
+        assert!(reader.pushes.try_recv().is_ok());
+
+        drop(reader);
+        assert_eq!(tracker.tracked_keys(), 0);
+        assert_eq!(tracker.tracking_clients.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn pushes_past_the_pubsub_limit_close_the_connection() {
+        let tracker = tracker(0);
+        tracker.runtime.set_output_buffer_limit(
+            ClientClass::Pubsub,
+            OutputBufferLimit {
+                hard_bytes: 100,
+                ..OutputBufferLimit::default()
+            },
+        );
+        let mut watcher = resp3_client(&tracker, 1);
+        let writer = tracker.connect(2);
+        watcher
+            .enable(TrackingOptions {
+                bcast: true,
+                ..TrackingOptions::default()
+            })
+            .unwrap();
+
+        // Each push is 32 bytes: draining them keeps the queue under 100,
+        // while the fourth queued one breaks the limit and drops the sender.
+        let set = args(&[b"SET", b"k", b"v"]);
+        for _ in 0..5 {
+            writer.after_command(&set, false);
+            assert!(watcher.next_push().await.is_some());
+        }
+        for _ in 0..4 {
+            writer.after_command(&set, false);
+        }
+        // The close is signalled before the queued pushes are drained.
+        tokio::time::timeout(Duration::from_secs(1), watcher.closed())
+            .await
+            .unwrap();
+        for _ in 0..4 {
+            assert!(watcher.next_push().await.is_some());
+        }
+        assert_eq!(watcher.next_push().await, None);
+    }
+
+    #[test]
+    fn pushes_need_a_resp3_receiver() {
+        let tracker = tracker(0);
+        let resp2 = tracker.connect(1);
+        assert_eq!(
+            resp2.enable(TrackingOptions::default()),
+            Err(TrackingError::NeedsResp3)
+        );
+        let redirect = |id| TrackingOptions {
+            redirect: Some(id),
+            ..TrackingOptions::default()
+        };
+        assert_eq!(
+            resp2.enable(redirect(9)),
+            Err(TrackingError::NoSuchRedirect)
+        );
+        let other = tracker.connect(2);
+        assert_eq!(
+            resp2.enable(redirect(2)),
+            Err(TrackingError::RedirectNeedsResp3)
+        );
+        other.set_resp3(true);
+        resp2.enable(redirect(2)).unwrap();
+        assert_eq!(resp2.redirect(), 2);
+        resp2.disable();
+        assert_eq!(resp2.redirect(), -1);
+    }
+}
@@ -0,0 +1,810 @@
+//! # io_uring Transport
+//!
+//! An optional network path for Linux 5.19 and later. One thread owns an
+//! io_uring instance and performs every accept, read and write for the
+//! server's listeners; connections are still served by their own tasks,
+//! through the same dispatch and engine as on the standard path.
+//!
+//! ## Design Principles
+//!
+//! 1. **Same Connection Code**: `RingStream` implements `AsyncRead` and
+//!    `AsyncWrite`, so `serve_connection` runs over it unchanged.
+//! 2. **Registered Buffers, Taken When Readable**: Reads land in buffers
+//!    registered with the ring once at startup (`READ_FIXED`), so the kernel
+//!    does not map pages per read. A read first polls its socket and takes a
+//!    buffer only once data has arrived, so idle connections hold none; a
+//!    readable socket waits for a free buffer rather than allocating one.
+//! 3. **Multishot Accept**: One submission accepts every connection on a
+//!    listener until it is cancelled, which is why 5.19 is required.
+//! 4. **Wake Only a Parked Ring**: Tasks queue requests on a channel and
+//!    write the ring's eventfd only while its thread is waiting, so a busy
+//!    ring picks requests up without a syscall per request.
+//! 5. **Ring Outlives Its Streams**: The thread keeps serving until the
+//!    acceptor and every stream are dropped, then cancels what is in flight
+//!    and waits for it, since the kernel may still touch those buffers.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Write as _};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc as std_mpsc;
+use std::task::{Context, Poll, ready};
+
+use bytes::Bytes;
+use io_uring::{IoUring, Probe, cqueue, opcode, squeue, types};
+use socket2::SockRef;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::server::ClientStream;
+
+/// Submission queue entries; completions get twice as many.
+const RING_ENTRIES: u32 = 1024;
+
+/// Buffers registered for reads; readable sockets past this many wait for
+/// one.
+const READ_BUFFERS: u16 = 256;
+
+/// Size of each registered buffer, the most a single read returns.
+const READ_BUFFER_LEN: usize = 16 * 1024;
+
+/// Oldest kernel with multishot accept and cancel-any.
+const MIN_KERNEL: (u32, u32) = (5, 19);
+
+/// An accepted socket and its peer.
+type Accepted = (StdTcpStream, SocketAddr);
+
+/// Accepts connections on the server's listeners through a ring.
+pub(crate) struct RingAcceptor {
+    handle: Handle,
+    accepted: mpsc::UnboundedReceiver<io::Result<Accepted>>,
+    /// Set after an accept error; the ring accepts again on the next call.
+    paused: bool,
+}
+
+impl RingAcceptor {
+    /// Starts a ring thread accepting on `listeners`, or explains why this
+    /// kernel cannot run one.
+    pub(crate) fn start(listeners: &[tokio::net::TcpListener]) -> io::Result<Self> {
+        check_kernel()?;
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        for (name, code) in [
+            ("accept", opcode::AcceptMulti::CODE),
+            ("poll_add", opcode::PollAdd::CODE),
+            ("read_fixed", opcode::ReadFixed::CODE),
+            ("send", opcode::Send::CODE),
+            ("read", opcode::Read::CODE),
+            ("async_cancel", opcode::AsyncCancel2::CODE),
+        ] {
+            if !probe.is_supported(code) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("io_uring does not support {name}"),
+                ));
+            }
+        }
+
+        let mut buffers: Vec<Box<[u8]>> = (0..READ_BUFFERS)
+            .map(|_| vec![0; READ_BUFFER_LEN].into_boxed_slice())
+            .collect();
+        let iovecs: Vec<libc::iovec> = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+        // SAFETY: the buffers move into the driver with the ring and are
+        // neither moved nor freed while it is open.
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+
+        // The ring keeps its own descriptors until the acceptor is dropped,
+        // so a listener closed by the accept loop cannot be reused under a
+        // pending accept.
+        let listeners = listeners
+            .iter()
+            .map(|listener| {
+                socket2::SockRef::from(listener)
+                    .try_clone()
+                    .map(OwnedFd::from)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        // SAFETY: `eventfd` returns a new descriptor or -1.
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if eventfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let waker = Arc::new(Waker {
+            // SAFETY: `eventfd` is a new descriptor nothing else owns.
+            eventfd: File::from(unsafe { OwnedFd::from_raw_fd(eventfd) }),
+            parked: AtomicBool::new(false),
+        });
+        let (commands_tx, commands) = std_mpsc::channel();
+        let (accepted_tx, accepted) = mpsc::unbounded_channel();
+        let driver = Driver {
+            ring,
+            buffers,
+            free_buffers: (0..READ_BUFFERS).rev().collect(),
+            waiting_reads: VecDeque::new(),
+            ops: Vec::new(),
+            free_ops: Vec::new(),
+            accept_ops: vec![None; listeners.len()],
+            listeners,
+            accepting: true,
+            stopping: false,
+            accepted: accepted_tx,
+            commands,
+            waker: Arc::clone(&waker),
+            wake_buf: Box::new([0; 8]),
+        };
+        std::thread::Builder::new()
+            .name("hkv-uring".into())
+            .spawn(move || driver.run())?;
+        Ok(RingAcceptor {
+            handle: Handle {
+                commands: Some(commands_tx),
+                waker,
+            },
+            accepted,
+            paused: false,
+        })
+    }
+
+    /// The next accepted connection.
+    pub(crate) async fn accept(&mut self) -> io::Result<(RingStream, SocketAddr)> {
+        if self.paused {
+            self.handle.send(Command::ResumeAccept)?;
+            self.paused = false;
+        }
+        match self.accepted.recv().await {
+            Some(Ok((socket, peer))) => Ok((RingStream::new(socket, self.handle.clone()), peer)),
+            Some(Err(err)) => {
+                // Accepting again at once would likely fail the same way;
+                // the caller decides when to retry.
+                self.paused = true;
+                Err(err)
+            }
+            None => Err(ring_stopped()),
+        }
+    }
+}
+
+impl Drop for RingAcceptor {
+    fn drop(&mut self) {
+        let _ = self.handle.send(Command::StopAccepting);
+    }
+}
+
+/// A connection whose reads and writes go through the ring.
+///
+/// A read or write, once started, completes even if the caller stops
+/// polling it; the next call of the same kind picks up its result.
+pub(crate) struct RingStream {
+    socket: Arc<StdTcpStream>,
+    handle: Handle,
+    read: Option<oneshot::Receiver<io::Result<Bytes>>>,
+    /// Bytes read by the ring but not yet returned to the caller.
+    unread: Bytes,
+    write: Option<oneshot::Receiver<io::Result<usize>>>,
+}
+
+impl RingStream {
+    fn new(socket: StdTcpStream, handle: Handle) -> Self {
+        RingStream {
+            socket: Arc::new(socket),
+            handle,
+            read: None,
+            unread: Bytes::new(),
+            write: None,
+        }
+    }
+}
+
+impl Drop for RingStream {
+    fn drop(&mut self) {
+        // A read in flight holds the socket open; cancel it so dropping the
+        // stream closes the connection as dropping a `TcpStream` does.
+        if self.read.is_some() || self.write.is_some() {
+            let _ = self.handle.send(Command::Cancel {
+                socket: Arc::clone(&self.socket),
+            });
+        }
+    }
+}
+
+impl ClientStream for RingStream {
+    fn socket(&self) -> SockRef<'_> {
+        SockRef::from(&*self.socket)
+    }
+}
+
+impl AsyncRead for RingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.unread.is_empty() {
+            let pending = match &mut this.read {
+                Some(pending) => pending,
+                None => {
+                    let (reply, pending) = oneshot::channel();
+                    this.handle.send(Command::Read {
+                        socket: Arc::clone(&this.socket),
+                        reply,
+                    })?;
+                    this.read.insert(pending)
+                }
+            };
+            let result = ready!(Pin::new(pending).poll(cx));
+            this.read = None;
+            // An empty read is end of stream, returned as is.
+            this.unread = result.map_err(|_| ring_stopped())??;
+        }
+        let len = this.unread.len().min(buf.remaining());
+        buf.put_slice(&this.unread.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for RingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let this = &mut *self;
+        let pending = match &mut this.write {
+            Some(pending) => pending,
+            None => {
+                let (reply, pending) = oneshot::channel();
+                this.handle.send(Command::Write {
+                    socket: Arc::clone(&this.socket),
+                    data: buf.to_vec(),
+                    reply,
+                })?;
+                this.write.insert(pending)
+            }
+        };
+        let result = ready!(Pin::new(pending).poll(cx));
+        this.write = None;
+        Poll::Ready(result.map_err(|_| ring_stopped())?)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Writes are sent as they are made; only one started and abandoned
+        // may still be in flight.
+        if let Some(pending) = &mut self.write {
+            let result = ready!(Pin::new(pending).poll(cx));
+            self.write = None;
+            result.map_err(|_| ring_stopped())??;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        match self.socket.shutdown(Shutdown::Write) {
+            Err(err) if err.kind() != io::ErrorKind::NotConnected => Poll::Ready(Err(err)),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// A request from the acceptor or a stream to the ring thread.
+enum Command {
+    Read {
+        socket: Arc<StdTcpStream>,
+        reply: oneshot::Sender<io::Result<Bytes>>,
+    },
+    Write {
+        socket: Arc<StdTcpStream>,
+        data: Vec<u8>,
+        reply: oneshot::Sender<io::Result<usize>>,
+    },
+    /// Cancels every operation in flight on `socket`.
+    Cancel {
+        socket: Arc<StdTcpStream>,
+    },
+    ResumeAccept,
+    StopAccepting,
+}
+
+/// Wakes the ring thread through its eventfd.
+struct Waker {
+    eventfd: File,
+    /// Set while the ring thread may be blocked waiting for completions.
+    parked: AtomicBool,
+}
+
+impl Waker {
+    fn wake(&self) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            // Fails only if the counter would overflow, which already wakes.
+            let _ = (&self.eventfd).write(&1u64.to_ne_bytes());
+        }
+    }
+}
+
+/// Sends commands to the ring thread, which stops once every handle is
+/// dropped.
+struct Handle {
+    /// Taken on drop, so the wake that follows sees the channel closed.
+    commands: Option<std_mpsc::Sender<Command>>,
+    waker: Arc<Waker>,
+}
+
+impl Handle {
+    fn send(&self, command: Command) -> io::Result<()> {
+        self.commands
+            .as_ref()
+            .expect("taken only on drop")
+            .send(command)
+            .map_err(|_| ring_stopped())?;
+        self.waker.wake();
+        Ok(())
+    }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Self {
+        Handle {
+            commands: self.commands.clone(),
+            waker: Arc::clone(&self.waker),
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        drop(self.commands.take());
+        self.waker.wake();
+    }
+}
+
+/// A submitted operation and everything the kernel may touch until it
+/// completes.
+enum Op {
+    Accept {
+        listener: usize,
+    },
+    Wake,
+    /// Waits for a socket to become readable before a buffer is taken.
+    Poll {
+        socket: Arc<StdTcpStream>,
+        reply: oneshot::Sender<io::Result<Bytes>>,
+    },
+    Read {
+        // Keeps the descriptor open, and unreused, until the read is done.
+        _socket: Arc<StdTcpStream>,
+        buffer: u16,
+        reply: oneshot::Sender<io::Result<Bytes>>,
+    },
+    Write {
+        _socket: Arc<StdTcpStream>,
+        _data: Vec<u8>,
+        reply: oneshot::Sender<io::Result<usize>>,
+    },
+    Cancel {
+        // Keeps the descriptor from being reused before the cancel runs.
+        _socket: Option<Arc<StdTcpStream>>,
+    },
+}
+
+/// The ring thread's state.
+struct Driver {
+    ring: IoUring,
+    /// Registered with the ring; indexes are buffer ids.
+    buffers: Vec<Box<[u8]>>,
+    free_buffers: Vec<u16>,
+    /// Readable sockets waiting for a free buffer.
+    waiting_reads: VecDeque<(Arc<StdTcpStream>, oneshot::Sender<io::Result<Bytes>>)>,
+    /// In-flight operations by user data.
+    ops: Vec<Option<Op>>,
+    free_ops: Vec<usize>,
+    /// Emptied once accepting stops.
+    listeners: Vec<OwnedFd>,
+    /// Each listener's armed accept, by user data.
+    accept_ops: Vec<Option<usize>>,
+    accepting: bool,
+    stopping: bool,
+    accepted: mpsc::UnboundedSender<io::Result<Accepted>>,
+    commands: std_mpsc::Receiver<Command>,
+    waker: Arc<Waker>,
+    wake_buf: Box<[u8; 8]>,
+}
+
+impl Driver {
+    fn run(mut self) {
+        match self.serve() {
+            Ok(()) => tracing::debug!("io_uring thread stopped"),
+            Err(err) => {
+                tracing::error!(error = %err, "io_uring thread failed");
+                // Operations may still be in flight; leak what they use.
+                std::mem::forget(self);
+            }
+        }
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        self.arm_wake()?;
+        for listener in 0..self.listeners.len() {
+            self.arm_accept(listener)?;
+        }
+        loop {
+            self.waker.parked.store(true, Ordering::SeqCst);
+            self.drain_commands()?;
+            if self.stopping && self.free_ops.len() == self.ops.len() {
+                return Ok(());
+            }
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // The completion queue is full; reaping it below makes room.
+                Err(err) if err.raw_os_error() == Some(libc::EBUSY) => {}
+                Err(err) => return Err(err),
+            }
+            self.waker.parked.store(false, Ordering::SeqCst);
+            let completions: Vec<_> = self
+                .ring
+                .completion()
+                .map(|cqe| (cqe.user_data(), cqe.result(), cqe.flags()))
+                .collect();
+            for (user_data, result, flags) in completions {
+                self.complete(user_data as usize, result, flags)?;
+            }
+        }
+    }
+
+    fn drain_commands(&mut self) -> io::Result<()> {
+        loop {
+            match self.commands.try_recv() {
+                Ok(command) => self.handle(command)?,
+                Err(std_mpsc::TryRecvError::Empty) => return Ok(()),
+                Err(std_mpsc::TryRecvError::Disconnected) => {
+                    if !self.stopping {
+                        self.stopping = true;
+                        let cancel = opcode::AsyncCancel2::new(types::CancelBuilder::any());
+                        self.push(cancel.build(), Op::Cancel { _socket: None })?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) -> io::Result<()> {
+        match command {
+            Command::Read { socket, reply } => {
+                let poll = opcode::PollAdd::new(types::Fd(socket.as_raw_fd()), libc::POLLIN as u32);
+                self.push(poll.build(), Op::Poll { socket, reply })?;
+            }
+            Command::Write {
+                socket,
+                data,
+                reply,
+            } => {
+                let len = u32::try_from(data.len()).unwrap_or(u32::MAX);
+                let send = opcode::Send::new(types::Fd(socket.as_raw_fd()), data.as_ptr(), len)
+                    .flags(libc::MSG_NOSIGNAL);
+                self.push(
+                    send.build(),
+                    Op::Write {
+                        _socket: socket,
+                        _data: data,
+                        reply,
+                    },
+                )?;
+            }
+            Command::Cancel { socket } => {
+                let cancel = opcode::AsyncCancel2::new(
+                    types::CancelBuilder::fd(types::Fd(socket.as_raw_fd())).all(),
+                );
+                self.push(
+                    cancel.build(),
+                    Op::Cancel {
+                        _socket: Some(Arc::clone(&socket)),
+                    },
+                )?;
+                // A readable socket still waiting for a buffer has nothing
+                // in flight.
+                self.waiting_reads
+                    .retain(|(waiting, _)| !Arc::ptr_eq(waiting, &socket));
+            }
+            Command::ResumeAccept => {
+                for listener in 0..self.listeners.len() {
+                    if self.accept_ops[listener].is_none() {
+                        self.arm_accept(listener)?;
+                    }
+                }
+            }
+            Command::StopAccepting => {
+                self.accepting = false;
+                for index in self.accept_ops.clone().into_iter().flatten() {
+                    let cancel =
+                        opcode::AsyncCancel2::new(types::CancelBuilder::user_data(index as u64));
+                    self.push(cancel.build(), Op::Cancel { _socket: None })?;
+                }
+                // An armed accept holds its own reference to the socket
+                // until the cancel lands; after that the port is closed.
+                self.listeners.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self, index: usize, result: i32, flags: u32) -> io::Result<()> {
+        // Only multishot accepts post completions that keep the op armed.
+        if cqueue::more(flags) {
+            self.on_accept(result);
+            return Ok(());
+        }
+        let Some(op) = self.ops.get_mut(index).and_then(Option::take) else {
+            return Ok(());
+        };
+        self.free_ops.push(index);
+        let outcome = if result >= 0 {
+            Ok(result as usize)
+        } else {
+            Err(io::Error::from_raw_os_error(-result))
+        };
+        match op {
+            Op::Accept { listener } => {
+                self.accept_ops[listener] = None;
+                let failed = result < 0;
+                self.on_accept(result);
+                // After an error the acceptor asks again once it is ready.
+                if !failed && self.accepting && !self.stopping {
+                    self.arm_accept(listener)?;
+                }
+            }
+            Op::Wake => {
+                if !self.stopping {
+                    self.arm_wake()?;
+                }
+            }
+            // Hangups and errors are readable too; the read reports them.
+            Op::Poll { socket, reply } => match outcome {
+                Ok(_) => match self.free_buffers.pop() {
+                    Some(buffer) => self.submit_read(socket, buffer, reply)?,
+                    None => self.waiting_reads.push_back((socket, reply)),
+                },
+                Err(err) => {
+                    let _ = reply.send(Err(err));
+                }
+            },
+            Op::Read { buffer, reply, .. } => {
+                let _ =
+                    reply.send(outcome.map(|len| {
+                        Bytes::copy_from_slice(&self.buffers[usize::from(buffer)][..len])
+                    }));
+                match self.waiting_reads.pop_front() {
+                    Some((socket, reply)) => self.submit_read(socket, buffer, reply)?,
+                    None => self.free_buffers.push(buffer),
+                }
+            }
+            Op::Write { reply, .. } => {
+                let _ = reply.send(outcome);
+            }
+            Op::Cancel { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Hands an accept result to the acceptor.
+    fn on_accept(&mut self, result: i32) {
+        let accepted = if result >= 0 {
+            // SAFETY: a successful accept returns a new descriptor that
+            // this process now owns.
+            let socket = StdTcpStream::from(unsafe { OwnedFd::from_raw_fd(result) });
+            match socket.peer_addr() {
+                Ok(peer) => Ok((socket, peer)),
+                // The client has already gone.
+                Err(_) => return,
+            }
+        } else if -result == libc::ECANCELED {
+            return;
+        } else {
+            Err(io::Error::from_raw_os_error(-result))
+        };
+        let _ = self.accepted.send(accepted);
+    }
+
+    fn arm_accept(&mut self, listener: usize) -> io::Result<()> {
+        // Accepted sockets stay blocking: io_uring returns EAGAIN for a
+        // nonblocking one instead of waiting for it to become ready.
+        let accept = opcode::AcceptMulti::new(types::Fd(self.listeners[listener].as_raw_fd()))
+            .flags(libc::SOCK_CLOEXEC);
+        let index = self.push(accept.build(), Op::Accept { listener })?;
+        self.accept_ops[listener] = Some(index);
+        Ok(())
+    }
+
+    fn arm_wake(&mut self) -> io::Result<()> {
+        let read = opcode::Read::new(
+            types::Fd(self.waker.eventfd.as_raw_fd()),
+            self.wake_buf.as_mut_ptr(),
+            8,
+        );
+        self.push(read.build(), Op::Wake).map(drop)
+    }
+
+    fn submit_read(
+        &mut self,
+        socket: Arc<StdTcpStream>,
+        buffer: u16,
+        reply: oneshot::Sender<io::Result<Bytes>>,
+    ) -> io::Result<()> {
+        let read = opcode::ReadFixed::new(
+            types::Fd(socket.as_raw_fd()),
+            self.buffers[usize::from(buffer)].as_mut_ptr(),
+            READ_BUFFER_LEN as u32,
+            buffer,
+        );
+        self.push(
+            read.build(),
+            Op::Read {
+                _socket: socket,
+                buffer,
+                reply,
+            },
+        )
+        .map(drop)
+    }
+
+    /// Queues `entry`, holding `op` until its completion; returns its user
+    /// data.
+    fn push(&mut self, entry: squeue::Entry, op: Op) -> io::Result<usize> {
+        let index = self.free_ops.pop().unwrap_or_else(|| {
+            self.ops.push(None);
+            self.ops.len() - 1
+        });
+        self.ops[index] = Some(op);
+        let entry = entry.user_data(index as u64);
+        // SAFETY: every buffer and descriptor `entry` refers to is owned by
+        // `op` or the driver, both of which outlive its completion.
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.ring.submit()?;
+        }
+        Ok(index)
+    }
+}
+
+/// Refuses kernels older than `MIN_KERNEL`.
+fn check_kernel() -> io::Result<()> {
+    let release = std::fs::read_to_string("/proc/sys/kernel/osrelease")?;
+    let release = release.trim();
+    let mut parts = release
+        .split(['.', '-'])
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+    let version = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    if version < MIN_KERNEL {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("io_uring transport needs Linux 5.19 or later, found {release}"),
+        ));
+    }
+    Ok(())
+}
+
+fn ring_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "io_uring thread stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    fn start(listener: &tokio::net::TcpListener) -> Option<RingAcceptor> {
+        match RingAcceptor::start(std::slice::from_ref(listener)) {
+            Ok(ring) => Some(ring),
+            Err(err) => {
+                eprintln!("skipping: {err}");
+                None
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_read_and_write_through_the_ring() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let Some(mut ring) = start(&listener) else {
+            return;
+        };
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut client = StdTcpStream::connect(addr).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            client.write_all(b"ping").unwrap();
+            let mut reply = vec![0; 3 * READ_BUFFER_LEN];
+            client.read_exact(&mut reply).unwrap();
+            client.shutdown(Shutdown::Write).unwrap();
+            reply
+        });
+
+        let (mut stream, peer) = ring.accept().await.unwrap();
+        assert!(peer.ip().is_loopback());
+        let mut request = [0; 4];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"ping");
+        // Larger than one registered buffer, so it takes several writes.
+        let reply: Vec<u8> = (0..3 * READ_BUFFER_LEN).map(|i| i as u8).collect();
+        stream.write_all(&reply).await.unwrap();
+        assert_eq!(client.join().unwrap(), reply);
+        assert_eq!(stream.read(&mut request).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_do_not_hold_read_buffers() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let Some(mut ring) = start(&listener) else {
+            return;
+        };
+        let addr = listener.local_addr().unwrap();
+        let mut idle = Vec::new();
+        for _ in 0..=READ_BUFFERS {
+            let client = StdTcpStream::connect(addr).unwrap();
+            let (mut stream, _) = ring.accept().await.unwrap();
+            let read = tokio::spawn(async move {
+                let mut request = [0; 4];
+                stream.read_exact(&mut request).await.map(|_| request)
+            });
+            idle.push((client, read));
+        }
+        // Let every idle stream start its read before the busy one does.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut client = StdTcpStream::connect(addr).unwrap();
+        let (mut stream, _) = ring.accept().await.unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut request = [0; 4];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut request))
+            .await
+            .expect("read waited on idle connections")
+            .unwrap();
+        assert_eq!(&request, b"ping");
+
+        let (mut client, read) = idle.swap_remove(0);
+        client.write_all(b"idle").unwrap();
+        assert_eq!(&read.await.unwrap().unwrap(), b"idle");
+    }
+
+    #[tokio::test]
+    async fn streams_keep_working_after_the_acceptor_is_dropped() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let Some(mut ring) = start(&listener) else {
+            return;
+        };
+        let addr = listener.local_addr().unwrap();
+        let mut client = StdTcpStream::connect(addr).unwrap();
+        let (mut stream, _) = ring.accept().await.unwrap();
+        drop(ring);
+        drop(listener);
+
+        client.write_all(b"still here").unwrap();
+        let mut request = [0; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"still here");
+        // Accepting stopped with the acceptor.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(StdTcpStream::connect(addr).is_err());
+    }
+}
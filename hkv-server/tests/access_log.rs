@@ -0,0 +1,161 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::access_log::{AccessLog, AccessLogTarget};
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::TcpListener;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hkv-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn spawn_server(runtime: RuntimeConfig) -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let stopped = shutdown.wait();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        stopped,
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn send(addr: SocketAddr, commands: &[&[&[u8]]]) -> String {
+    let mut request = Vec::new();
+    for args in commands {
+        encode_command(args, &mut request);
+    }
+    let mut stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream.write_all(&request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Polls `path` until it holds `count` lines, since the writer runs behind.
+async fn wait_for_lines(path: &Path, count: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.len() >= count || Instant::now() > deadline {
+            return lines;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn commands_are_logged_without_values_or_secrets() {
+    let dir = temp_dir("access-log");
+    let path = dir.join("access.log");
+    let log = AccessLog::open(AccessLogTarget::File(path.clone()))
+        .await
+        .unwrap();
+    let (addr, shutdown) = spawn_server(RuntimeConfig::new().with_access_log(log)).await;
+
+    let response = send(
+        addr,
+        &[
+            &[b"SET", b"user:1", b"top-secret-value"],
+            &[b"GET", b"user:1"],
+            &[b"AUTH", b"hunter2"],
+        ],
+    );
+    assert!(response.starts_with("+OK\r\n$16\r\ntop-secret-value\r\n-"));
+    let lines = wait_for_lines(&path, 3).await;
+    shutdown.trigger();
+
+    assert_eq!(lines.len(), 3, "{lines:?}");
+    for (line, tail) in lines.iter().zip([
+        "cmd=set key=\"user:1\" ",
+        "cmd=get key=\"user:1\" ",
+        "cmd=auth key=- ",
+    ]) {
+        assert!(line.starts_with("ts="), "{line}");
+        assert!(line.contains(" addr=127.0.0.1:"), "{line}");
+        assert!(line.contains(" name=- db=0 "), "{line}");
+        assert!(line.contains(tail), "{line}");
+    }
+    assert!(lines[0].ends_with("outcome=ok"), "{}", lines[0]);
+    assert!(lines[2].ends_with("outcome=error"), "{}", lines[2]);
+    let text = lines.join("\n");
+    assert!(!text.contains("top-secret-value"));
+    assert!(!text.contains("hunter2"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sample_rate_is_adjustable_and_reopen_follows_rotation() {
+    let dir = temp_dir("access-log-sampling");
+    let path = dir.join("access.log");
+    let log = AccessLog::open(AccessLogTarget::File(path.clone()))
+        .await
+        .unwrap();
+    let (addr, shutdown) =
+        spawn_server(RuntimeConfig::new().with_access_log(Arc::clone(&log))).await;
+
+    // Sampling uses the rate in effect once a command has run, so the
+    // CONFIG SETs below are logged only when the new rate samples them.
+    send(
+        addr,
+        &[
+            &[b"CONFIG", b"SET", b"accesslog-sample-rate", b"0"],
+            &[b"PING"],
+            &[b"CONFIG", b"SET", b"accesslog-sample-rate", b"2"],
+        ],
+    );
+    send(addr, &[&[b"GET", b"a"], &[b"GET", b"b"], &[b"GET", b"c"]]);
+    let lines = wait_for_lines(&path, 2).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    assert!(lines[0].contains("cmd=config key=- "), "{}", lines[0]);
+    assert!(lines[1].contains("cmd=get key=\"b\" "), "{}", lines[1]);
+
+    let rotated = dir.join("access.log.1");
+    std::fs::rename(&path, &rotated).unwrap();
+    log.reopen();
+    let reopened = Instant::now() + Duration::from_secs(5);
+    while !path.exists() && Instant::now() < reopened {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    send(
+        addr,
+        &[&[b"CONFIG", b"SET", b"accesslog-sample-rate", b"1"]],
+    );
+    send(addr, &[&[b"DEL", b"d"]]);
+    let lines = wait_for_lines(&path, 2).await;
+    shutdown.trigger();
+
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    assert!(lines[1].contains("cmd=del key=\"d\" "), "{}", lines[1]);
+    assert_eq!(log.dropped(), 0);
+    assert_eq!(
+        std::fs::read_to_string(&rotated).unwrap().lines().count(),
+        2
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
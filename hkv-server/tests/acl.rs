@@ -0,0 +1,190 @@
+//! # ACL Integration Tests
+//!
+//! Create users over the wire, log in as them and check what they may run
+//! and touch, and round-trip them through the ACL file.
+
+use std::io::{Read, Write};
+use std::net::TcpStream as StdTcpStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+
+fn spawn_server(runtime: RuntimeConfig) -> (u16, ShutdownController) {
+    let listener =
+        server::bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        vec![listener],
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (port, shutdown)
+}
+
+fn connect(port: u16) -> StdTcpStream {
+    let stream = StdTcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends one command and reads one read's worth of reply.
+fn call(stream: &mut StdTcpStream, args: &[&str]) -> String {
+    let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+    let mut request = Vec::new();
+    encode_command(&args, &mut request);
+    stream.write_all(&request).unwrap();
+    let mut reply = vec![0; 8192];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hkv-acl-test-{}-{name}", std::process::id()))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn users_are_limited_to_their_commands_and_keys() {
+    let (port, shutdown) = spawn_server(RuntimeConfig::new());
+    let mut admin = connect(port);
+    assert_eq!(
+        call(
+            &mut admin,
+            &[
+                "ACL", "SETUSER", "cache", "on", ">pw", "~cache:*", "+@read", "+set"
+            ]
+        ),
+        "+OK\r\n"
+    );
+
+    let mut client = connect(port);
+    assert!(call(&mut client, &["AUTH", "cache", "wrong"]).starts_with("-WRONGPASS "));
+    assert_eq!(call(&mut client, &["AUTH", "cache", "pw"]), "+OK\r\n");
+    assert_eq!(
+        call(&mut client, &["ACL", "WHOAMI"]),
+        "-NOPERM User cache has no permissions to run the 'acl' command\r\n"
+    );
+    assert_eq!(call(&mut client, &["SET", "cache:a", "1"]), "+OK\r\n");
+    assert_eq!(call(&mut client, &["GET", "cache:a"]), "$1\r\n1\r\n");
+    assert_eq!(
+        call(&mut client, &["GET", "secret"]),
+        "-NOPERM User cache has no permissions to access the 'secret' key\r\n"
+    );
+    assert_eq!(
+        call(&mut client, &["MGET", "cache:a", "secret"]),
+        "-NOPERM User cache has no permissions to access the 'secret' key\r\n"
+    );
+    assert_eq!(
+        call(&mut client, &["DEL", "cache:a"]),
+        "-NOPERM User cache has no permissions to run the 'del' command\r\n"
+    );
+
+    // Changes apply to connections already logged in.
+    assert_eq!(
+        call(&mut admin, &["ACL", "SETUSER", "cache", "+del"]),
+        "+OK\r\n"
+    );
+    assert_eq!(call(&mut client, &["DEL", "cache:a"]), ":1\r\n");
+    assert_eq!(
+        call(&mut admin, &["ACL", "SETUSER", "cache", "off"]),
+        "+OK\r\n"
+    );
+    // The default user needs no password, so the connection falls back to it.
+    assert_eq!(call(&mut client, &["GET", "secret"]), "$-1\r\n");
+
+    let info = call(&mut admin, &["INFO", "errorstats"]);
+    assert!(info.contains("errorstat_NOPERM:count=4\r\n"), "{info}");
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn default_user_password_comes_from_requirepass() {
+    let runtime = RuntimeConfig::new();
+    runtime.set_requirepass(b"hunter2");
+    let (port, shutdown) = spawn_server(runtime);
+    let mut client = connect(port);
+    assert_eq!(
+        call(&mut client, &["ACL", "WHOAMI"]),
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert_eq!(
+        call(&mut client, &["AUTH", "default", "hunter2"]),
+        "+OK\r\n"
+    );
+    assert_eq!(call(&mut client, &["ACL", "WHOAMI"]), "$7\r\ndefault\r\n");
+    let getuser = call(&mut client, &["ACL", "GETUSER", "default"]);
+    assert!(
+        getuser.starts_with("*8\r\n$5\r\nflags\r\n*3\r\n$2\r\non\r\n"),
+        "{getuser}"
+    );
+    assert!(
+        getuser.contains("$7\r\nallkeys\r\n$11\r\nallcommands\r\n"),
+        "{getuser}"
+    );
+    assert_eq!(
+        call(&mut client, &["ACL", "DELUSER", "default"]),
+        "-ERR The 'default' user cannot be removed\r\n"
+    );
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn acl_save_and_load_round_trip_through_the_file() {
+    let path = temp_path("users.acl");
+    std::fs::write(&path, "user reader on >pw ~* +@read\n").unwrap();
+    let runtime = RuntimeConfig::new().with_aclfile(&path);
+    runtime.acl().load(&path).unwrap();
+    let (port, shutdown) = spawn_server(runtime);
+    let mut admin = connect(port);
+    assert_eq!(
+        call(&mut admin, &["ACL", "USERS"]),
+        "*2\r\n$7\r\ndefault\r\n$6\r\nreader\r\n"
+    );
+
+    assert_eq!(
+        call(
+            &mut admin,
+            &[
+                "ACL", "SETUSER", "writer", "on", "nopass", "~w:*", "+@write"
+            ]
+        ),
+        "+OK\r\n"
+    );
+    assert_eq!(call(&mut admin, &["ACL", "SAVE"]), "+OK\r\n");
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        saved.contains("user writer on nopass ~w:* -@all +set +del +expire"),
+        "{saved}"
+    );
+
+    assert_eq!(
+        call(&mut admin, &["ACL", "DELUSER", "writer", "nobody"]),
+        ":1\r\n"
+    );
+    assert_eq!(call(&mut admin, &["ACL", "LOAD"]), "+OK\r\n");
+    let list = call(&mut admin, &["ACL", "LIST"]);
+    assert!(list.contains("user writer on nopass ~w:*"), "{list}");
+    assert!(list.contains("user reader on #"), "{list}");
+
+    // A file with a bad line is refused whole.
+    std::fs::write(&path, "user reader on\nuser writer +nosuch\n").unwrap();
+    assert!(call(&mut admin, &["ACL", "LOAD"]).starts_with("-ERR "));
+    assert!(call(&mut admin, &["ACL", "LIST"]).contains("user writer "));
+
+    std::fs::remove_file(&path).unwrap();
+    shutdown.trigger();
+}
@@ -0,0 +1,80 @@
+//! Counts the bytes allocated on the way from a large `SET` frame to the
+//! stored value, under a counting global allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use bytes::BytesMut;
+use hkv_client::encode_command;
+use hkv_engine::{KVEngine, MemoryEngine};
+use hkv_server::protocol::RespParser;
+
+const VALUE_LEN: usize = 100 * 1024;
+
+/// System allocator that counts bytes allocated on the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: every call is forwarded unchanged to `System`.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.with(|count| count.set(count.get() + layout.size()));
+        // SAFETY: the caller upholds `GlobalAlloc::alloc`'s contract.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `System` with `layout`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.with(|count| count.set(count.get() + new_size));
+        // SAFETY: the caller upholds `GlobalAlloc::realloc`'s contract.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Bytes allocated by `f` on this thread, and its result.
+fn count_bytes<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = ALLOCATED.with(Cell::get);
+    let result = f();
+    (ALLOCATED.with(Cell::get) - before, result)
+}
+
+#[test]
+fn a_large_set_value_is_copied_once() {
+    let value = vec![b'v'; VALUE_LEN];
+    let mut frame = Vec::new();
+    encode_command(&[b"SET", b"key", &value], &mut frame);
+    let engine = MemoryEngine::new();
+    // Warm the shard so the measured writes only replace the value.
+    engine.set(b"key".to_vec(), b"old".to_vec()).unwrap();
+
+    let mut buf = BytesMut::from(frame.as_slice());
+    let (parsed, args) = count_bytes(|| RespParser::new().parse(&mut buf).unwrap().unwrap());
+    assert!(parsed < 1024, "parsing allocated {parsed} bytes");
+    let (stored, ()) = count_bytes(|| engine.set_borrowed(&args[1], &args[2], None).unwrap());
+    assert!(
+        (VALUE_LEN..VALUE_LEN + 1024).contains(&stored),
+        "storing allocated {stored} bytes"
+    );
+
+    // Owned arguments, as the parser used to return, cost a second copy.
+    let (owned, ()) = count_bytes(|| {
+        let key = args[1].to_vec();
+        let value = args[2].to_vec();
+        engine.set(key, value).unwrap();
+    });
+    assert!(
+        owned >= 2 * VALUE_LEN,
+        "owned arguments allocated {owned} bytes"
+    );
+    assert_eq!(&*engine.get(b"key").unwrap().unwrap(), value.as_slice());
+}
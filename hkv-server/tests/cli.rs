@@ -0,0 +1,52 @@
+//! Runs the `hkv-server` binary to check its command-line handling.
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hkv-server"))
+        .args(args)
+        .env_remove("HKV_ADDR")
+        .env_remove("HKV_MAXMEMORY")
+        .env_remove("HKV_MAXMEMORY_POLICY")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn help_and_version_exit_successfully() {
+    let version = run(&["--version"]);
+    assert!(version.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&version.stdout).trim(),
+        format!("hkv-server {}", env!("CARGO_PKG_VERSION"))
+    );
+
+    let help = run(&["--help"]);
+    assert!(help.status.success());
+    let help = String::from_utf8_lossy(&help.stdout);
+    for flag in [
+        "--port",
+        "--maxmemory",
+        "--maxclients",
+        "[env: HKV_DATA_DIR=",
+    ] {
+        assert!(help.contains(flag), "{flag} missing from:\n{help}");
+    }
+}
+
+#[test]
+fn invalid_values_exit_nonzero_before_binding() {
+    for (args, message) in [
+        (
+            &["--maxmemory", "100mbx"][..],
+            "unknown unit 'mbx' (expected b, k, kb, m, mb, g or gb)",
+        ),
+        (&["--maxmemory-policy", "volatile-lru"], "volatile-lru"),
+        (&["--port", "70000"], "--port"),
+    ] {
+        let output = run(args);
+        assert!(!output.status.success(), "{args:?} was accepted");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{args:?}: {stderr}");
+    }
+}
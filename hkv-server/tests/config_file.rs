@@ -0,0 +1,128 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::cli::ServerConfig;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::config_file::REWRITE_HEADER;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::TcpListener;
+
+fn temp_conf(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hkv-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("hkv.conf");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn load(path: &Path) -> ServerConfig {
+    ServerConfig::try_from_args(["hkv-server", path.to_str().unwrap()]).unwrap()
+}
+
+async fn spawn_server(runtime: RuntimeConfig) -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let stopped = shutdown.wait();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        stopped,
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn send(addr: SocketAddr, commands: &[&[&[u8]]]) -> String {
+    let mut request = Vec::new();
+    for args in commands {
+        encode_command(args, &mut request);
+    }
+    let mut stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream.write_all(&request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn config_rewrite_round_trips_through_the_file() {
+    let path = temp_conf(
+        "rewrite",
+        "# Production settings\n\
+         port 7000\n\
+         timeout 10\n\
+         save 900 1\n\
+         include extra.conf\n",
+    );
+    std::fs::write(path.with_file_name("extra.conf"), "maxmemory 1mb\n").unwrap();
+    let config = load(&path);
+    assert_eq!(config.idle_timeout_secs, 10);
+
+    let (addr, shutdown) = spawn_server(config.runtime_config()).await;
+    let response = send(
+        addr,
+        &[
+            &[b"CONFIG", b"SET", b"timeout", b"30"],
+            &[b"CONFIG", b"SET", b"maxclients", b"77"],
+            &[b"CONFIG", b"SET", b"proto-max-bulk-len", b"4096"],
+            &[b"CONFIG", b"REWRITE"],
+        ],
+    );
+    assert_eq!(response, "+OK\r\n+OK\r\n+OK\r\n+OK\r\n");
+    let live = {
+        let runtime = RuntimeConfig::new();
+        for (name, value) in [
+            ("timeout", 30),
+            ("maxclients", 77),
+            ("proto-max-bulk-len", 4096),
+        ] {
+            assert!(runtime.set(name, value));
+        }
+        runtime.set_max_memory(1 << 20);
+        runtime.parameters().collect::<Vec<_>>()
+    };
+    shutdown.trigger();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        text.starts_with("# Production settings\nport 7000\ntimeout 30\nsave 900 1\n"),
+        "{text}"
+    );
+    assert!(text.contains(REWRITE_HEADER), "{text}");
+
+    let reloaded = load(&path);
+    assert_eq!(reloaded.listen_addrs[0].addr.port(), 7000);
+    assert_eq!(
+        reloaded.runtime_config().parameters().collect::<Vec<_>>(),
+        live
+    );
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn config_rewrite_without_a_file_is_an_error() {
+    let (addr, shutdown) = spawn_server(RuntimeConfig::new()).await;
+    let response = send(addr, &[&[b"CONFIG", b"REWRITE"]]);
+    assert_eq!(
+        response,
+        "-ERR The server is running without a config file\r\n"
+    );
+    shutdown.trigger();
+}
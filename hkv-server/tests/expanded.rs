@@ -2,12 +2,15 @@ use std::io::{Read, Write};
 use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
 use std::sync::{Arc, Barrier};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use hkv_client::KVClient;
 use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
 use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
 use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 
@@ -118,7 +121,7 @@ async fn command_errors_return_stable_resp_errors_without_closing_connection() {
     assert_eq!(
         response,
         concat!(
-            "-ERR wrong number of arguments for GET\r\n",
+            "-ERR wrong number of arguments for 'get' command\r\n",
             "-ERR invalid integer\r\n",
             "-ERR unsupported SET options\r\n",
             "+PONG\r\n"
@@ -131,3 +134,263 @@ async fn command_errors_return_stable_resp_errors_without_closing_connection() {
 
     let _ = shutdown.send(());
 }
+
+fn resp_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn pipelined_get_set_replies_arrive_in_order() {
+    // 2000 GETs of 100-byte values make ~200 KiB of replies, so the batch
+    // is flushed mid-pipeline as well as at the end of each read.
+    const KEYS: usize = 2000;
+
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let mut request = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..KEYS {
+        let key = format!("pipe:{i}");
+        let value = format!("{i:0>100}");
+        request.extend(resp_command(&[b"GET", key.as_bytes()]));
+        request.extend(resp_command(&[b"SET", key.as_bytes(), value.as_bytes()]));
+        request.extend(resp_command(&[b"GET", key.as_bytes()]));
+        expected.extend_from_slice(b"$-1\r\n+OK\r\n");
+        expected.extend_from_slice(format!("$100\r\n{value}\r\n").as_bytes());
+    }
+
+    let response = send_raw(addr, &request).unwrap();
+    assert_eq!(response.len(), expected.len());
+    assert!(response == expected, "pipelined replies out of order");
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn large_mget_replies_match_the_copied_reply() {
+    // 100 values of 4 KiB go out as shared slices, mixed with small values
+    // and missing keys that are copied.
+    const KEYS: usize = 100;
+
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let mut request = Vec::new();
+    let mut mget: Vec<Vec<u8>> = vec![b"MGET".to_vec()];
+    let mut expected_mget = format!("*{}\r\n", KEYS + 2).into_bytes();
+    let mut expected = Vec::new();
+    for i in 0..KEYS {
+        let key = format!("big:{i}");
+        let value = vec![b'a' + (i % 26) as u8; 4096];
+        request.extend(resp_command(&[b"SET", key.as_bytes(), &value]));
+        expected.extend_from_slice(b"+OK\r\n");
+        expected_mget.extend_from_slice(b"$4096\r\n");
+        expected_mget.extend_from_slice(&value);
+        expected_mget.extend_from_slice(b"\r\n");
+        mget.push(key.into_bytes());
+    }
+    request.extend(resp_command(&[b"SET", b"small", b"v"]));
+    expected.extend_from_slice(b"+OK\r\n");
+    mget.extend([b"small".to_vec(), b"missing".to_vec()]);
+    expected_mget.extend_from_slice(b"$1\r\nv\r\n$-1\r\n");
+    let mget: Vec<&[u8]> = mget.iter().map(Vec::as_slice).collect();
+    for _ in 0..4 {
+        request.extend(resp_command(&mget));
+        request.extend(resp_command(&[b"GET", b"big:7"]));
+        expected.extend_from_slice(&expected_mget);
+        expected.extend_from_slice(b"$4096\r\n");
+        expected.extend_from_slice(&[b'h'; 4096]);
+        expected.extend_from_slice(b"\r\n");
+    }
+
+    let response = send_raw(addr, &request).unwrap();
+    assert_eq!(response.len(), expected.len());
+    assert!(response == expected, "vectored replies differ");
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn idle_connections_are_closed_after_config_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let metrics = Arc::new(Metrics::new());
+    let runtime = Arc::new(RuntimeConfig::new());
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::clone(&metrics),
+        Arc::new(Persistence::default()),
+        Arc::clone(&runtime),
+        shutdown.wait(),
+        Duration::from_secs(5),
+    ));
+
+    let configured = send_raw(
+        addr,
+        &[
+            resp_command(&[b"CONFIG", b"SET", b"timeout", b"1"]),
+            resp_command(&[b"CONFIG", b"GET", b"timeout"]),
+        ]
+        .concat(),
+    )
+    .unwrap();
+    assert_eq!(configured, b"+OK\r\n*2\r\n$7\r\ntimeout\r\n$1\r\n1\r\n");
+    assert_eq!(runtime.idle_timeout_secs(), 1);
+
+    // A silent client is dropped once the window passes.
+    let silent = tokio::task::spawn_blocking(move || {
+        let mut stream = StdTcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let started = Instant::now();
+        let mut buf = [0u8; 16];
+        let read = stream.read(&mut buf).unwrap();
+        (read, started.elapsed())
+    })
+    .await
+    .unwrap();
+    assert_eq!(silent.0, 0, "expected EOF");
+    assert!(silent.1 >= Duration::from_millis(900), "{:?}", silent.1);
+    assert!(silent.1 < Duration::from_secs(3), "{:?}", silent.1);
+    assert_eq!(metrics.snapshot().idle_disconnects_total, 1);
+
+    // With the timeout disabled, the same silence is harmless.
+    runtime.set_idle_timeout_secs(0);
+    let kept = tokio::task::spawn_blocking(move || {
+        let client = KVClient::connect(addr.to_string()).unwrap();
+        thread::sleep(Duration::from_millis(1500));
+        client.ping(None).unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(kept, b"PONG");
+    assert_eq!(metrics.snapshot().idle_disconnects_total, 1);
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn connections_past_maxclients_get_an_error() {
+    const MAX_CLIENTS: u64 = 2;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let metrics = Arc::new(Metrics::new());
+    let runtime = Arc::new(RuntimeConfig::new());
+    runtime.set_max_clients(MAX_CLIENTS);
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::clone(&metrics),
+        Arc::new(Persistence::default()),
+        Arc::clone(&runtime),
+        shutdown.wait(),
+        Duration::from_secs(5),
+    ));
+
+    let (refused, info) = tokio::task::spawn_blocking(move || {
+        let clients: Vec<_> = (0..MAX_CLIENTS)
+            .map(|_| {
+                let client = KVClient::connect(addr.to_string()).unwrap();
+                client.ping(None).unwrap();
+                client
+            })
+            .collect();
+
+        let mut extra = StdTcpStream::connect(addr).unwrap();
+        extra
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let mut refused = Vec::new();
+        extra.read_to_end(&mut refused).unwrap();
+
+        let info = clients[0].info().unwrap();
+        (refused, info)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(refused, b"-ERR max number of clients reached\r\n");
+    let info = String::from_utf8(info).unwrap();
+    assert!(info.contains("connected_clients:2\r\n"), "{info}");
+    assert!(info.contains("maxclients:2\r\n"), "{info}");
+    assert!(info.contains("rejected_connections_total:1\r\n"), "{info}");
+
+    // Closing the first clients frees their slots.
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while metrics.snapshot().connected_clients > 0 {
+        assert!(Instant::now() < deadline, "connections were not released");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let pong = tokio::task::spawn_blocking(move || {
+        KVClient::connect(addr.to_string())
+            .unwrap()
+            .ping(None)
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(pong, b"PONG");
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn oversized_requests_are_refused_and_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        shutdown.wait(),
+        Duration::from_secs(5),
+    ));
+
+    let configured = send_raw(
+        addr,
+        &[
+            resp_command(&[b"CONFIG", b"SET", b"proto-max-bulk-len", b"1048576"]),
+            resp_command(&[b"CONFIG", b"SET", b"proto-max-multibulk-len", b"1024"]),
+        ]
+        .concat(),
+    )
+    .unwrap();
+    assert_eq!(configured, b"+OK\r\n+OK\r\n");
+
+    // Headers only: the server must answer without waiting for the data,
+    // and close even though the client keeps its side open.
+    let declared_only = |request: &'static [u8]| {
+        tokio::task::spawn_blocking(move || {
+            let mut stream = StdTcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+            stream.write_all(request).unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            response
+        })
+    };
+    let huge_bulk = declared_only(b"*2\r\n$3\r\nGET\r\n$999999999\r\n")
+        .await
+        .unwrap();
+    assert_eq!(huge_bulk, b"-ERR request too large\r\n");
+    let huge_array = declared_only(b"*999999999\r\n").await.unwrap();
+    assert_eq!(huge_array, b"-ERR request too large\r\n");
+
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    assert_eq!(client.ping(None).unwrap(), b"PONG");
+
+    shutdown.trigger();
+}
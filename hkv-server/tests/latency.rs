@@ -0,0 +1,117 @@
+//! # Latency Monitor Integration Tests
+//!
+//! Make a command slow with `DEBUG SLEEP` and read the event back through
+//! the `LATENCY` commands.
+
+use std::io::{Read, Write};
+use std::net::TcpStream as StdTcpStream;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+
+fn spawn_server() -> (u16, ShutdownController) {
+    let listener =
+        server::bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        vec![listener],
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (port, shutdown)
+}
+
+/// Sends one command and reads one read's worth of reply.
+fn call(stream: &mut StdTcpStream, args: &[&str]) -> String {
+    let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+    let mut request = Vec::new();
+    encode_command(&args, &mut request);
+    stream.write_all(&request).unwrap();
+    let mut reply = vec![0; 8192];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+/// The integers of a reply made only of arrays and integers.
+fn integers(reply: &str) -> Vec<u64> {
+    reply
+        .split("\r\n")
+        .filter_map(|line| line.strip_prefix(':'))
+        .map(|value| value.parse().unwrap())
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn debug_sleep_shows_up_in_latency_history_and_latest() {
+    let (port, shutdown) = spawn_server();
+    let mut stream = StdTcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    // Nothing is recorded until a threshold is set.
+    assert_eq!(call(&mut stream, &["DEBUG", "SLEEP", "0.06"]), "+OK\r\n");
+    assert_eq!(call(&mut stream, &["LATENCY", "LATEST"]), "*0\r\n");
+
+    assert_eq!(
+        call(
+            &mut stream,
+            &["CONFIG", "SET", "latency-monitor-threshold", "50"]
+        ),
+        "+OK\r\n"
+    );
+    let before = unix_now();
+    assert_eq!(call(&mut stream, &["DEBUG", "SLEEP", "0.1"]), "+OK\r\n");
+    assert_eq!(call(&mut stream, &["PING"]), "+PONG\r\n");
+
+    let history = call(&mut stream, &["LATENCY", "HISTORY", "debug"]);
+    assert!(history.starts_with("*1\r\n*2\r\n:"), "{history}");
+    let [time, millis] = integers(&history)[..] else {
+        panic!("unexpected history {history}");
+    };
+    assert!((before..=unix_now()).contains(&time), "{history}");
+    assert!((100..5000).contains(&millis), "{history}");
+    assert_eq!(call(&mut stream, &["LATENCY", "HISTORY", "ping"]), "*0\r\n");
+
+    let latest = call(&mut stream, &["LATENCY", "LATEST"]);
+    assert!(
+        latest.starts_with("*1\r\n*4\r\n$5\r\ndebug\r\n:"),
+        "{latest}"
+    );
+    assert_eq!(integers(&latest), [time, millis, millis]);
+
+    assert_eq!(call(&mut stream, &["LATENCY", "RESET", "get"]), ":0\r\n");
+    assert_eq!(call(&mut stream, &["LATENCY", "RESET"]), ":1\r\n");
+    assert_eq!(
+        call(&mut stream, &["LATENCY", "HISTORY", "debug"]),
+        "*0\r\n"
+    );
+    assert_eq!(
+        call(&mut stream, &["LATENCY", "DOCTOR"]),
+        "-ERR unsupported LATENCY subcommand\r\n"
+    );
+    assert_eq!(
+        call(&mut stream, &["DEBUG", "SLEEP", "soon"]),
+        "-ERR value is not a valid float\r\n"
+    );
+    shutdown.trigger();
+}
@@ -0,0 +1,159 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::task::JoinHandle;
+
+/// Serves one engine on ephemeral IPv4 and IPv6 loopback ports.
+async fn spawn_dual_stack_server() -> (
+    [SocketAddr; 2],
+    ShutdownController,
+    JoinHandle<std::io::Result<()>>,
+) {
+    let listeners = ["127.0.0.1:0", "[::1]:0"]
+        .map(|addr| server::bind_listener(addr.parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap());
+    let addrs = [0, 1].map(|index| listeners[index].local_addr().unwrap());
+    let shutdown = ShutdownController::new();
+    let task = tokio::spawn(server::serve_listeners_with_runtime_config(
+        listeners.into(),
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (addrs, shutdown, task)
+}
+
+fn connect(addr: SocketAddr) -> StdTcpStream {
+    let stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+}
+
+/// Sends one command and reads one read's worth of reply.
+fn call(stream: &mut StdTcpStream, args: &[&[u8]]) -> String {
+    let mut request = Vec::new();
+    encode_command(args, &mut request);
+    stream.write_all(&request).unwrap();
+    let mut reply = vec![0; 4096];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn every_listener_serves_the_same_data() {
+    let ([v4, v6], shutdown, task) = spawn_dual_stack_server().await;
+    let mut over_v4 = connect(v4);
+    let mut over_v6 = connect(v6);
+
+    assert_eq!(call(&mut over_v4, &[b"SET", b"k", b"v"]), "+OK\r\n");
+    assert_eq!(call(&mut over_v6, &[b"GET", b"k"]), "$1\r\nv\r\n");
+
+    let info = call(&mut over_v6, &[b"INFO", b"SERVER"]);
+    assert!(
+        info.contains(&format!("tcp_port:{}\r\n", v4.port())),
+        "{info}"
+    );
+    assert!(
+        info.contains(&format!(
+            "listener0:name=tcp,bind=127.0.0.1,port={}\r\n",
+            v4.port()
+        )),
+        "{info}"
+    );
+    assert!(
+        info.contains(&format!(
+            "listener1:name=tcp,bind=::1,port={}\r\n",
+            v6.port()
+        )),
+        "{info}"
+    );
+
+    // One shutdown closes both listeners.
+    shutdown.trigger();
+    task.await.unwrap().unwrap();
+    assert!(StdTcpStream::connect(v4).is_err());
+    assert!(StdTcpStream::connect(v6).is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reuseport_acceptors_all_serve_and_stop_together() {
+    const ACCEPTORS: usize = 4;
+    const CONNECTIONS: usize = 400;
+    let listeners: Vec<_> = ["127.0.0.1:0", "[::1]:0"]
+        .into_iter()
+        .flat_map(|addr| {
+            server::bind_reuseport_listeners(addr.parse().unwrap(), DEFAULT_TCP_BACKLOG, ACCEPTORS)
+                .unwrap()
+        })
+        .collect();
+    let addrs = [0, ACCEPTORS].map(|index| listeners[index].local_addr().unwrap());
+    assert!(
+        listeners[..ACCEPTORS]
+            .iter()
+            .all(|l| l.local_addr().unwrap() == addrs[0])
+    );
+    let runtime = RuntimeConfig::new();
+    runtime.set_acceptors(ACCEPTORS);
+    let shutdown = ShutdownController::new();
+    let task = tokio::spawn(server::serve_listeners_with_runtime_config(
+        listeners,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+
+    // The kernel hashes each connection to one of an address's sockets;
+    // one whose loop was not accepting would leave its clients unanswered.
+    let clients = tokio::task::spawn_blocking(move || {
+        for index in 0..CONNECTIONS {
+            let mut stream = connect(addrs[index % 2]);
+            assert_eq!(call(&mut stream, &[b"PING"]), "+PONG\r\n");
+        }
+    });
+    clients.await.unwrap();
+
+    let mut stream = connect(addrs[0]);
+    let info = call(&mut stream, &[b"INFO"]);
+    assert!(
+        info.contains(&format!(
+            "accepted_connections_total:{}\r\n",
+            CONNECTIONS + 1
+        )),
+        "{info}"
+    );
+    let info = call(&mut stream, &[b"INFO", b"SERVER"]);
+    assert!(info.contains("listener1:"), "{info}");
+    assert!(!info.contains("listener2:"), "{info}");
+    assert_eq!(
+        call(&mut stream, &[b"CONFIG", b"GET", b"acceptors"]),
+        "*2\r\n$9\r\nacceptors\r\n$1\r\n4\r\n"
+    );
+    drop(stream);
+
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    for addr in addrs {
+        assert!(StdTcpStream::connect(addr).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Checks the spans and events the server emits, using a layer that
+//! captures them in memory.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::metrics::Metrics;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+type Fields = BTreeMap<String, String>;
+
+/// A closed span or an event, with the names of its enclosing spans.
+#[derive(Debug, Clone)]
+struct Captured {
+    name: String,
+    fields: Fields,
+    scope: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+struct Capture {
+    spans: Arc<Mutex<Vec<Captured>>>,
+    events: Arc<Mutex<Vec<Captured>>>,
+}
+
+impl Capture {
+    fn spans(&self, name: &str) -> Vec<Captured> {
+        let spans = self.spans.lock().unwrap();
+        spans
+            .iter()
+            .filter(|span| span.name == name)
+            .cloned()
+            .collect()
+    }
+
+    fn events(&self, message: &str) -> Vec<Captured> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|event| event.fields.get("message").map(String::as_str) == Some(message))
+            .cloned()
+            .collect()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for Capture
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        ctx.span(id).unwrap().extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut extensions = span.extensions_mut();
+        values.record(&mut FieldVisitor(extensions.get_mut::<Fields>().unwrap()));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        let scope = ctx
+            .event_scope(event)
+            .map(|scope| scope.map(|span| span.name().to_string()).collect())
+            .unwrap_or_default();
+        self.events.lock().unwrap().push(Captured {
+            name: event.metadata().name().to_string(),
+            fields,
+            scope,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).unwrap();
+        let fields = span.extensions().get::<Fields>().cloned().unwrap();
+        let scope = span.scope().skip(1).map(|s| s.name().to_string()).collect();
+        self.spans.lock().unwrap().push(Captured {
+            name: span.name().to_string(),
+            fields,
+            scope,
+        });
+    }
+}
+
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_command(args, &mut out);
+    out
+}
+
+#[tokio::test]
+async fn command_spans_record_name_duration_and_outcome() {
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(capture.clone()));
+
+    let (mut client, stream) = tokio::io::duplex(64 * 1024);
+    let connection = tokio::spawn(server::handle_connection(
+        stream,
+        Arc::new(MemoryEngine::new()),
+    ));
+    let value = vec![b'v'; 1000];
+    let mut requests = command(&[b"SET", b"k", &value]);
+    requests.extend(command(&[b"AUTH", b"hunter2"]));
+    requests.extend(command(&[b"GET", b"k"]));
+    requests.extend(command(&[b"GET"]));
+    client.write_all(&requests).await.unwrap();
+    client.shutdown().await.unwrap();
+    connection.await.unwrap().unwrap();
+
+    let commands = capture.spans("command");
+    let summary: Vec<(&str, &str)> = commands
+        .iter()
+        .map(|span| {
+            (
+                span.fields["name"].as_str(),
+                span.fields["outcome"].as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("set", "ok"),
+            ("auth", "error"),
+            ("get", "ok"),
+            ("get", "error")
+        ]
+    );
+    for span in &commands {
+        span.fields["duration_us"].parse::<u64>().unwrap();
+        assert_eq!(span.fields["db"], "0");
+    }
+    let keys: Vec<&str> = commands
+        .iter()
+        .map(|span| span.fields["keys"].as_str())
+        .collect();
+    assert_eq!(keys, ["1", "0", "1", "0"]);
+
+    // Arguments are logged at trace level, redacted and truncated.
+    let logged: Vec<String> = capture
+        .events("executing command")
+        .into_iter()
+        .map(|event| {
+            assert_eq!(event.scope, ["command"]);
+            event.fields["args"].clone()
+        })
+        .collect();
+    assert_eq!(logged.len(), 4);
+    assert!(logged[0].ends_with("... (1000 bytes)"), "{}", logged[0]);
+    assert!(logged[0].len() < 100);
+    assert_eq!(logged[1], "AUTH (redacted)");
+    assert!(logged.iter().all(|args| !args.contains("hunter2")));
+}
+
+#[tokio::test]
+async fn connections_get_a_span_with_client_id_and_peer() {
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(capture.clone()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let server = tokio::spawn(server::serve_with_shutdown(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        shutdown.wait(),
+    ));
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let peer = client.local_addr().unwrap().to_string();
+    client.write_all(&command(&[b"PING"])).await.unwrap();
+    let mut reply = [0; 7];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"+PONG\r\n");
+    drop(client);
+
+    shutdown.trigger();
+    server.await.unwrap().unwrap();
+
+    let connections = capture.spans("connection");
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].fields["peer"], peer);
+    assert!(connections[0].fields["id"].parse::<u64>().unwrap() >= 1);
+
+    let commands = capture.spans("command");
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].fields["name"], "ping");
+    assert_eq!(commands[0].scope, ["connection"]);
+
+    assert_eq!(capture.events("client connected")[0].scope, ["connection"]);
+    assert_eq!(capture.events("shutdown: all connections closed").len(), 1);
+}
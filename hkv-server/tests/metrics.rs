@@ -1,12 +1,18 @@
 use std::io::{Read, Write};
 use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use hkv_client::KVClient;
-use hkv_engine::MemoryEngine;
+use hkv_engine::kernel::MockKernelDevice;
+use hkv_engine::{KernelCacheClient, MemoryEngine};
+use hkv_server::config::RuntimeConfig;
+use hkv_server::exporter;
+use hkv_server::lifecycle::{LifecyclePhase, ServerLifecycle};
 use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
 use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 
@@ -70,6 +76,160 @@ async fn info_reports_request_error_and_latency_metrics() {
     let _ = shutdown.send(());
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn info_keyspace_counts_keys_with_a_ttl() {
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let keyspace = b"*2\r\n$4\r\nINFO\r\n$8\r\nkeyspace\r\n";
+    assert_eq!(
+        send_raw(addr, keyspace).unwrap(),
+        b"$12\r\n# Keyspace\r\n\r\n"
+    );
+
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    for key in ["a", "b", "c"] {
+        client
+            .set_with_ttl(key.as_bytes(), b"value", Duration::from_secs(100))
+            .unwrap();
+    }
+    client.set(b"d", b"value").unwrap();
+
+    let info = String::from_utf8(send_raw(addr, keyspace).unwrap()).unwrap();
+    let line = info
+        .lines()
+        .find_map(|line| line.strip_prefix("db0:keys=4,expires=3,avg_ttl="))
+        .unwrap_or_else(|| panic!("{info}"));
+    let avg_ttl: u64 = line.parse().unwrap();
+    assert!((90_000..=100_000).contains(&avg_ttl), "{info}");
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn info_kernel_cache_reports_the_module_limits() {
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let info = send_raw(addr, b"*2\r\n$4\r\nINFO\r\n$12\r\nkernel_cache\r\n").unwrap();
+    let info = String::from_utf8(info).unwrap();
+
+    assert!(info.contains("# Kernel_cache\r\n"), "{info}");
+    // No kernel cache client was started.
+    assert!(info.contains("status:disabled\r\n"), "{info}");
+    // Hosts without the module report only that it is missing.
+    if !info.contains("loaded:0\r\n") {
+        for field in [
+            "loaded:1",
+            "max_bytes:",
+            "max_entries:",
+            "default_ttl_override:",
+        ] {
+            assert!(info.contains(field), "{info}");
+        }
+    }
+
+    let _ = shutdown.send(());
+}
+
+/// A field of `INFO` `section`.
+fn info_field(addr: SocketAddr, section: &str, field: &str) -> String {
+    let request = format!("*2\r\n$4\r\nINFO\r\n${}\r\n{section}\r\n", section.len());
+    let info = String::from_utf8(send_raw(addr, request.as_bytes()).unwrap()).unwrap();
+    info.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .unwrap_or_else(|| panic!("{info}"))
+        .to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn info_kernel_cache_reports_the_client_health() {
+    let device = Arc::new(MockKernelDevice::new());
+    let kernel = KernelCacheClient::with_device(Arc::clone(&device));
+    let runtime = RuntimeConfig::new().with_kernel_health(kernel.health_monitor());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    assert_eq!(info_field(addr, "kernel_cache", "status"), "healthy");
+
+    // The server only reports the client's health: the module vanishing
+    // mid-run shows up as `degraded`, and a successful probe clears it.
+    device.set_present(false);
+    assert_eq!(kernel.read(b"k"), Ok(None));
+    assert_eq!(info_field(addr, "kernel_cache", "status"), "degraded");
+
+    device.set_present(true);
+    kernel.probe();
+    assert_eq!(info_field(addr, "kernel_cache", "status"), "healthy");
+
+    shutdown.trigger();
+}
+
+/// A field of `INFO memory`.
+fn memory_info(addr: SocketAddr, field: &str) -> u64 {
+    let info = send_raw(addr, b"*2\r\n$4\r\nINFO\r\n$6\r\nmemory\r\n").unwrap();
+    let info = String::from_utf8(info).unwrap();
+    info.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .unwrap_or_else(|| panic!("{info}"))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_buffers_shrink_after_small_requests_and_still_parse_large_ones() {
+    const VALUE_LEN: usize = 256 * 1024;
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let response = send_raw(
+        addr,
+        b"*4\r\n$6\r\nCONFIG\r\n$3\r\nSET\r\n$24\r\nread-buffer-shrink-after\r\n$1\r\n4\r\n",
+    )
+    .unwrap();
+    assert_eq!(response, b"+OK\r\n");
+
+    let value = vec![b'v'; VALUE_LEN];
+    let mut large = format!("*3\r\n$3\r\nSET\r\n$5\r\nlarge\r\n${VALUE_LEN}\r\n").into_bytes();
+    large.extend_from_slice(&value);
+    large.extend_from_slice(b"\r\n");
+    let ping = b"*1\r\n$4\r\nPING\r\n";
+
+    let mut stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut exchange = |request: &[u8], expected: &[u8]| {
+        stream.write_all(request).unwrap();
+        let mut reply = vec![0; expected.len()];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, expected);
+    };
+
+    exchange(&[&large[..], &large].concat(), b"+OK\r\n+OK\r\n");
+    assert!(memory_info(addr, "mem_read_buffers") > VALUE_LEN as u64);
+
+    // The last PING is read only after the shrink that follows the fourth.
+    for _ in 0..5 {
+        exchange(ping, b"+PONG\r\n");
+    }
+    assert!(memory_info(addr, "mem_read_buffers") < 64 * 1024);
+
+    // Large requests pipelined behind a small one parse in the new buffer.
+    let mut get = b"*2\r\n$3\r\nGET\r\n$5\r\nlarge\r\n".to_vec();
+    let mut expected = format!("+PONG\r\n+OK\r\n+OK\r\n${VALUE_LEN}\r\n").into_bytes();
+    expected.extend_from_slice(&value);
+    expected.extend_from_slice(b"\r\n");
+    get.splice(0..0, [&ping[..], &large, &large].concat());
+    exchange(&get, &expected);
+
+    let _ = shutdown.send(());
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn protocol_errors_are_counted_in_metrics() {
     let (addr, shutdown) = spawn_test_server().await.unwrap();
@@ -85,3 +245,250 @@ async fn protocol_errors_are_counted_in_metrics() {
 
     let _ = shutdown.send(());
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn info_commandstats_counts_calls_per_command() {
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+
+    let pipeline = concat!(
+        "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n",
+        "*2\r\n$3\r\nget\r\n$1\r\na\r\n",
+        "*2\r\n$3\r\nGET\r\n$1\r\nb\r\n",
+        "*1\r\n$3\r\nGET\r\n",
+        "*1\r\n$7\r\nNOSUCH1\r\n",
+        "*1\r\n$7\r\nNOSUCH2\r\n",
+    );
+    let response = send_raw(addr, pipeline.as_bytes()).unwrap();
+    assert_eq!(
+        response,
+        concat!(
+            "+OK\r\n$1\r\n1\r\n$-1\r\n",
+            "-ERR wrong number of arguments for 'get' command\r\n",
+            "-ERR unknown command\r\n-ERR unknown command\r\n",
+        )
+        .as_bytes()
+    );
+
+    let response = send_raw(addr, b"*2\r\n$4\r\nINFO\r\n$12\r\ncommandstats\r\n").unwrap();
+    let response = String::from_utf8(response).unwrap();
+    let (_, info) = response.split_once("\r\n").unwrap();
+    let mut lines = info.lines();
+    assert_eq!(lines.next(), Some("# Commandstats"));
+    let stats: Vec<(&str, Vec<&str>)> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (name, fields) = line.split_once(':').unwrap();
+            (name, fields.split(',').collect())
+        })
+        .collect();
+    let names: Vec<&str> = stats.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, ["cmdstat_get", "cmdstat_set", "cmdstat_unknown"]);
+
+    for (name, calls, failed) in [
+        ("cmdstat_get", 3, 1),
+        ("cmdstat_set", 1, 0),
+        ("cmdstat_unknown", 2, 2),
+    ] {
+        let (_, fields) = stats.iter().find(|(line, _)| *line == name).unwrap();
+        assert_eq!(fields[0], format!("calls={calls}"), "{name}");
+        assert!(fields[1].starts_with("usec="), "{name}");
+        assert!(fields[2].starts_with("usec_per_call="), "{name}");
+        assert_eq!(fields[3], format!("failed_calls={failed}"), "{name}");
+    }
+
+    let response = send_raw(addr, b"*2\r\n$4\r\nINFO\r\n$10\r\nerrorstats\r\n").unwrap();
+    assert_eq!(
+        response,
+        b"$37\r\n# Errorstats\r\nerrorstat_ERR:count=3\r\n\r\n"
+    );
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn config_resetstat_clears_info_counters() {
+    let (addr, shutdown) = spawn_test_server().await.unwrap();
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    client.set(b"metrics:key", b"value").unwrap();
+    let response = send_raw(addr, b"*1\r\n$7\r\nUNKNOWN\r\n").unwrap();
+    assert_eq!(response, b"-ERR unknown command\r\n");
+
+    let response = send_raw(addr, b"*2\r\n$6\r\nCONFIG\r\n$9\r\nRESETSTAT\r\n").unwrap();
+    assert_eq!(response, b"+OK\r\n");
+
+    // Only the INFO request itself has been counted since the reset.
+    let info = String::from_utf8(client.info().unwrap()).unwrap();
+    assert!(info.contains("requests_total:1\r\n"), "{info}");
+    assert!(info.contains("errors_total:0\r\n"), "{info}");
+    assert!(info.contains("instantaneous_ops_per_sec:"), "{info}");
+    assert_eq!(
+        client.get(b"metrics:key").unwrap().as_deref(),
+        Some(&b"value"[..])
+    );
+
+    let _ = shutdown.send(());
+}
+
+/// Fetches `path` from the metrics endpoint as one HTTP/1.1 response.
+fn http_get(addr: SocketAddr, path: &str) -> std::io::Result<String> {
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let response = send_raw(addr, request.as_bytes())?;
+    Ok(String::from_utf8(response).unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn prometheus_endpoint_exposes_counters_gauges_and_histogram() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let metrics_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = metrics_listener.local_addr().unwrap();
+
+    let engine = Arc::new(MemoryEngine::new());
+    let metrics = Arc::new(Metrics::new());
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_with_shutdown(
+        listener,
+        Arc::clone(&engine),
+        Arc::clone(&metrics),
+        shutdown.wait(),
+    ));
+    tokio::spawn(exporter::serve_metrics(
+        metrics_listener,
+        engine,
+        metrics,
+        Arc::new(ServerLifecycle::new()),
+        shutdown.wait(),
+    ));
+
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    client.set(b"scrape:key", b"value").unwrap();
+    assert_eq!(
+        client.get(b"scrape:key").unwrap().as_deref(),
+        Some(&b"value"[..])
+    );
+    assert_eq!(client.get(b"scrape:missing").unwrap(), None);
+    let response = send_raw(addr, b"*1\r\n$7\r\nUNKNOWN\r\n").unwrap();
+    assert_eq!(response, b"-ERR unknown command\r\n");
+
+    // A request completes just after its reply is written, so the last
+    // one may still be in flight when the client has already read it.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let response = loop {
+        let response = http_get(metrics_addr, "/metrics").unwrap();
+        if response.contains("hkv_inflight_requests 0\n") || Instant::now() > deadline {
+            break response;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+    assert!(
+        head.contains("Content-Type: text/plain; version=0.0.4; charset=utf-8"),
+        "{head}"
+    );
+    assert!(
+        head.contains(&format!("Content-Length: {}", body.len())),
+        "{head}"
+    );
+
+    for series in [
+        "# TYPE hkv_requests_total counter\nhkv_requests_total 4\n",
+        "# TYPE hkv_errors_total counter\nhkv_errors_total 1\n",
+        "hkv_commands_total{command=\"get\"} 2\n",
+        "hkv_commands_total{command=\"set\"} 1\n",
+        "hkv_commands_total{command=\"unknown\"} 1\n",
+        "hkv_command_errors_total{command=\"get\"} 0\n",
+        "hkv_command_errors_total{command=\"unknown\"} 1\n",
+        "hkv_error_replies_total{prefix=\"ERR\"} 1\n",
+        "hkv_error_replies_total{prefix=\"OOM\"} 0\n",
+        "# TYPE hkv_command_duration_seconds histogram\n",
+        "hkv_command_duration_seconds_bucket{command=\"get\",le=\"0.000001\"} ",
+        "hkv_command_duration_seconds_bucket{command=\"get\",le=\"+Inf\"} 2\n",
+        "hkv_command_duration_seconds_sum{command=\"set\"} ",
+        "hkv_command_duration_seconds_count{command=\"set\"} 1\n",
+        "# TYPE hkv_inflight_requests gauge\nhkv_inflight_requests 0\n",
+        "# TYPE hkv_connected_clients gauge\n",
+        "# TYPE hkv_keys gauge\nhkv_keys 1\n",
+        "# TYPE hkv_used_memory_bytes gauge\nhkv_used_memory_bytes 15\n",
+        "# TYPE hkv_request_duration_seconds histogram\n",
+        "hkv_request_duration_seconds_bucket{le=\"0.000001\"} ",
+        "hkv_request_duration_seconds_bucket{le=\"+Inf\"} 4\n",
+        "hkv_request_duration_seconds_sum ",
+        "hkv_request_duration_seconds_count 4\n",
+    ] {
+        assert!(body.contains(series), "missing {series:?} in\n{body}");
+    }
+
+    let not_found = http_get(metrics_addr, "/").unwrap();
+    assert!(
+        not_found.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{not_found}"
+    );
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn readiness_probe_follows_startup_and_shutdown() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = metrics_listener.local_addr().unwrap();
+    let engine = Arc::new(MemoryEngine::new());
+    let metrics = Arc::new(Metrics::new());
+    let runtime = Arc::new(RuntimeConfig::new());
+    let lifecycle = Arc::clone(runtime.lifecycle());
+    let shutdown = ShutdownController::new();
+    // The probes outlive the RESP server, as in main.
+    let exporter_stop = ShutdownController::new();
+    tokio::spawn(exporter::serve_metrics(
+        metrics_listener,
+        Arc::clone(&engine),
+        Arc::clone(&metrics),
+        Arc::clone(&lifecycle),
+        exporter_stop.wait(),
+    ));
+    let status = |path: &str| {
+        let response = http_get(metrics_addr, path).unwrap();
+        response.lines().next().unwrap().to_string()
+    };
+
+    assert_eq!(status("/healthz"), "HTTP/1.1 200 OK");
+    assert_eq!(status("/readyz"), "HTTP/1.1 503 Service Unavailable");
+    lifecycle.advance(LifecyclePhase::Loading);
+    assert_eq!(status("/readyz"), "HTTP/1.1 503 Service Unavailable");
+
+    let server = tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        engine,
+        metrics,
+        Arc::new(Persistence::default()),
+        runtime,
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while status("/readyz") != "HTTP/1.1 200 OK" {
+        assert!(Instant::now() < deadline, "the server never became ready");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let ready = http_get(metrics_addr, "/readyz").unwrap();
+    assert!(
+        ready.contains("Content-Type: application/json\r\n"),
+        "{ready}"
+    );
+    assert!(
+        ready.ends_with("{\"phase\":\"ready\",\"ready\":true,\"awaiting_sync\":false}\n"),
+        "{ready}"
+    );
+
+    shutdown.trigger();
+    server.await.unwrap().unwrap();
+    let draining = http_get(metrics_addr, "/readyz").unwrap();
+    assert!(
+        draining.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{draining}"
+    );
+    assert!(draining.contains("\"phase\":\"draining\""), "{draining}");
+    assert_eq!(status("/healthz"), "HTTP/1.1 200 OK");
+    exporter_stop.trigger();
+}
@@ -335,13 +335,13 @@ async fn invalid_and_failed_commands_do_not_record_misleading_observation_events
     let wrong_arity_get = send_raw(addr, b"*1\r\n$3\r\nGET\r\n").unwrap();
     assert_eq!(
         wrong_arity_get,
-        b"-ERR wrong number of arguments for GET\r\n"
+        b"-ERR wrong number of arguments for 'get' command\r\n"
     );
 
     let wrong_arity_ttl = send_raw(addr, b"*1\r\n$3\r\nTTL\r\n").unwrap();
     assert_eq!(
         wrong_arity_ttl,
-        b"-ERR wrong number of arguments for TTL\r\n"
+        b"-ERR wrong number of arguments for 'ttl' command\r\n"
     );
 
     let invalid_expire = send_raw(addr, b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$1\r\nx\r\n").unwrap();
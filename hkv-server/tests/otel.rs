@@ -0,0 +1,157 @@
+//! # OpenTelemetry Integration Tests
+//!
+//! Serve commands with telemetry exporting to in-memory exporters, and
+//! check the instruments and spans they received.
+
+#![cfg(feature = "otel")]
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::metrics::Metrics;
+use hkv_server::otel::Telemetry;
+use hkv_server::server;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::Registry;
+
+type Attributes = BTreeMap<String, String>;
+
+fn attributes<'a>(values: impl Iterator<Item = &'a KeyValue>) -> Attributes {
+    values
+        .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+        .collect()
+}
+
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_command(args, &mut out);
+    out
+}
+
+/// The metrics of the last export, by name.
+fn exported(exporter: &InMemoryMetricExporter) -> BTreeMap<String, Vec<(Attributes, f64)>> {
+    let exports: Vec<ResourceMetrics> = exporter.get_finished_metrics().unwrap();
+    let last = exports.last().expect("an export");
+    let mut metrics = BTreeMap::new();
+    for metric in last.scope_metrics().flat_map(|scope| scope.metrics()) {
+        let points: Vec<(Attributes, f64)> = match metric.data() {
+            AggregatedMetrics::U64(MetricData::Sum(sum)) => sum
+                .data_points()
+                .map(|point| (attributes(point.attributes()), point.value() as f64))
+                .collect(),
+            AggregatedMetrics::U64(MetricData::Gauge(gauge)) => gauge
+                .data_points()
+                .map(|point| (attributes(point.attributes()), point.value() as f64))
+                .collect(),
+            AggregatedMetrics::F64(MetricData::Histogram(histogram)) => histogram
+                .data_points()
+                .map(|point| (attributes(point.attributes()), point.count() as f64))
+                .collect(),
+            other => panic!("unexpected data for {}: {other:?}", metric.name()),
+        };
+        metrics.insert(metric.name().to_string(), points);
+    }
+    metrics
+}
+
+#[tokio::test]
+async fn counters_histogram_and_command_spans_are_exported() {
+    let metric_exporter = InMemoryMetricExporter::default();
+    let span_exporter = InMemorySpanExporter::default();
+    let meters = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metric_exporter.clone()).build())
+        .build();
+    let tracer = SdkTracerProvider::builder()
+        .with_simple_exporter(span_exporter.clone())
+        .build();
+    let telemetry = Telemetry::new(meters.clone(), Some(tracer));
+    let metrics = Arc::new(Metrics::new());
+    telemetry.instrument(&metrics);
+    let _guard =
+        tracing::subscriber::set_default(Registry::default().with(telemetry.tracing_layer()));
+
+    let (mut client, stream) = tokio::io::duplex(64 * 1024);
+    let connection = tokio::spawn(server::handle_connection_with_metrics(
+        stream,
+        Arc::new(MemoryEngine::new()),
+        Arc::clone(&metrics),
+    ));
+    let mut requests = command(&[b"SET", b"k", b"v"]);
+    requests.extend(command(&[b"MGET", b"k", b"a", b"b"]));
+    requests.extend(command(&[b"GET"]));
+    client.write_all(&requests).await.unwrap();
+    client.shutdown().await.unwrap();
+    let mut replies = Vec::new();
+    client.read_to_end(&mut replies).await.unwrap();
+    connection.await.unwrap().unwrap();
+    meters.force_flush().unwrap();
+
+    let exported = exported(&metric_exporter);
+    for name in [
+        "hkv.requests",
+        "hkv.errors",
+        "hkv.error_replies",
+        "hkv.connections.accepted",
+        "hkv.connected_clients",
+        "hkv.inflight_requests",
+        "hkv.read_buffer.size",
+        "hkv.command.duration",
+    ] {
+        assert!(exported.contains_key(name), "{name} missing: {exported:?}");
+    }
+    assert_eq!(exported["hkv.requests"], [(Attributes::new(), 3.0)]);
+    let errors: Vec<_> = exported["hkv.error_replies"]
+        .iter()
+        .filter(|(_, count)| *count > 0.0)
+        .collect();
+    assert_eq!(
+        errors,
+        [&(Attributes::from([("prefix".into(), "ERR".into())]), 1.0)]
+    );
+    let mut durations = exported["hkv.command.duration"].clone();
+    durations.sort_by(|a, b| a.0.cmp(&b.0));
+    let expected = [("get", "error"), ("mget", "ok"), ("set", "ok")].map(|(command, outcome)| {
+        (
+            Attributes::from([
+                ("command".into(), command.into()),
+                ("outcome".into(), outcome.into()),
+            ]),
+            1.0,
+        )
+    });
+    assert_eq!(durations, expected);
+
+    let spans = span_exporter.get_finished_spans().unwrap();
+    let commands: Vec<Attributes> = spans
+        .iter()
+        .filter(|span| span.name == "command")
+        .map(|span| {
+            let mut fields = attributes(span.attributes.iter());
+            fields.retain(|key, _| ["name", "db", "keys", "outcome"].contains(&key.as_str()));
+            fields
+        })
+        .collect();
+    let expected = [
+        ("set", "1", "ok"),
+        ("mget", "3", "ok"),
+        ("get", "0", "error"),
+    ]
+    .map(|(name, keys, outcome)| {
+        Attributes::from([
+            ("name".into(), name.into()),
+            ("db".into(), "0".into()),
+            ("keys".into(), keys.into()),
+            ("outcome".into(), outcome.into()),
+        ])
+    });
+    assert_eq!(commands, expected);
+
+    telemetry.shutdown().unwrap();
+}
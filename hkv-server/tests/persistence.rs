@@ -0,0 +1,131 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hkv_client::KVClient;
+use hkv_engine::{MemoryEngine, snapshot};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+async fn spawn_test_server(
+    persistence: Arc<Persistence>,
+) -> std::io::Result<(SocketAddr, oneshot::Sender<()>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let engine = Arc::new(MemoryEngine::new());
+    let metrics = Arc::new(Metrics::new());
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    tokio::spawn(async move {
+        let _ = server::serve_with_shutdown_and_persistence(
+            listener,
+            engine,
+            metrics,
+            persistence,
+            async {
+                let _ = shutdown_rx.await;
+            },
+        )
+        .await;
+    });
+
+    Ok((addr, shutdown_tx))
+}
+
+fn unique_temp_dir(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("hkv-{label}-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn send_raw(addr: SocketAddr, request: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut stream = StdTcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.write_all(request)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
+
+fn parse_integer(response: &[u8]) -> u64 {
+    let text = std::str::from_utf8(response).unwrap();
+    text.strip_prefix(':')
+        .and_then(|rest| rest.strip_suffix("\r\n"))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+fn lastsave(addr: SocketAddr) -> u64 {
+    parse_integer(&send_raw(addr, b"*1\r\n$8\r\nLASTSAVE\r\n").unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn lastsave_increases_after_save() {
+    let dir = unique_temp_dir("save");
+    let persistence = Arc::new(Persistence::new(&dir));
+    let (addr, shutdown) = spawn_test_server(Arc::clone(&persistence)).await.unwrap();
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    client.set(b"persist:key", b"value").unwrap();
+
+    let before = lastsave(addr);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let response = send_raw(addr, b"*1\r\n$4\r\nSAVE\r\n").unwrap();
+    assert_eq!(response, b"+OK\r\n");
+    assert!(lastsave(addr) > before);
+
+    let bytes = std::fs::read(persistence.snapshot_path()).unwrap();
+    let entries = snapshot::decode(&bytes, SystemTime::now()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(&*entries[0].key, b"persist:key");
+    assert_eq!(&*entries[0].value, b"value");
+
+    let _ = shutdown.send(());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn bgsave_writes_snapshot_in_background() {
+    let dir = unique_temp_dir("bgsave");
+    let persistence = Arc::new(Persistence::new(&dir));
+    let (addr, shutdown) = spawn_test_server(Arc::clone(&persistence)).await.unwrap();
+    let client = KVClient::connect(addr.to_string()).unwrap();
+    client
+        .set_with_ttl(b"bg:key", b"value", Duration::from_secs(60))
+        .unwrap();
+
+    let response = send_raw(addr, b"*1\r\n$6\r\nBGSAVE\r\n").unwrap();
+    assert_eq!(response, b"+Background saving started\r\n");
+
+    let mut written = false;
+    for _ in 0..100 {
+        if !persistence.bgsave_in_progress() && persistence.snapshot_path().exists() {
+            written = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(written, "BGSAVE did not finish in time");
+
+    let bytes = std::fs::read(persistence.snapshot_path()).unwrap();
+    let entries = snapshot::decode(&bytes, SystemTime::now()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].ttl.is_some());
+
+    let _ = shutdown.send(());
+    let _ = std::fs::remove_dir_all(dir);
+}
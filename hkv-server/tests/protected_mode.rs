@@ -0,0 +1,138 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream as StdTcpStream, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::cli::ServerConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+
+/// Serves on every interface with the configuration the binary would get
+/// from `--bind 0.0.0.0 --port 0` followed by `args`.
+fn spawn_server(args: &[&str]) -> (u16, ShutdownController) {
+    let config = ServerConfig::try_from_args(
+        ["hkv-server", "--bind", "0.0.0.0", "--port", "0"]
+            .iter()
+            .chain(args),
+    )
+    .unwrap();
+    let runtime = config.runtime_config();
+    let listener = server::bind_listener(config.listen_addrs[0].addr, config.tcp_backlog).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        vec![listener],
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(runtime),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (port, shutdown)
+}
+
+/// A local address that is not loopback, if the host has a route to one.
+fn external_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+}
+
+fn connect(ip: IpAddr, port: u16) -> StdTcpStream {
+    let stream = StdTcpStream::connect(SocketAddr::new(ip, port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends one command and reads one read's worth of reply.
+fn call(stream: &mut StdTcpStream, args: &[&[u8]]) -> String {
+    let mut request = Vec::new();
+    encode_command(args, &mut request);
+    stream.write_all(&request).unwrap();
+    let mut reply = vec![0; 8192];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+fn assert_denied(mut stream: StdTcpStream) {
+    let mut refusal = String::new();
+    stream.read_to_string(&mut refusal).unwrap();
+    assert!(refusal.starts_with("-DENIED "), "{refusal}");
+    assert!(
+        refusal.contains("CONFIG SET protected-mode no"),
+        "{refusal}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn external_clients_are_denied_until_a_password_is_set() {
+    let Some(external) = external_ip() else {
+        eprintln!("skipping: no non-loopback address");
+        return;
+    };
+    let (port, shutdown) = spawn_server(&[]);
+    let mut admin = connect(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+    assert_eq!(call(&mut admin, &[b"PING"]), "+PONG\r\n");
+    assert_denied(connect(external, port));
+    let info = call(&mut admin, &[b"INFO"]);
+    assert!(info.contains("rejected_connections_total:1\r\n"), "{info}");
+
+    assert_eq!(
+        call(&mut admin, &[b"CONFIG", b"SET", b"requirepass", b"hunter2"]),
+        "+OK\r\n"
+    );
+    // The admin connection was let in before the password was set.
+    assert_eq!(call(&mut admin, &[b"PING"]), "+PONG\r\n");
+    let mut client = connect(external, port);
+    assert_eq!(
+        call(&mut client, &[b"GET", b"k"]),
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert!(call(&mut client, &[b"AUTH", b"wrong"]).starts_with("-WRONGPASS "));
+    assert_eq!(call(&mut client, &[b"AUTH", b"hunter2"]), "+OK\r\n");
+    assert_eq!(call(&mut client, &[b"GET", b"k"]), "$-1\r\n");
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn turning_protected_mode_off_admits_external_clients() {
+    let Some(external) = external_ip() else {
+        eprintln!("skipping: no non-loopback address");
+        return;
+    };
+    let (port, shutdown) = spawn_server(&[]);
+    assert_denied(connect(external, port));
+
+    let mut admin = connect(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+    assert_eq!(
+        call(&mut admin, &[b"CONFIG", b"GET", b"protected-mode"]),
+        "*2\r\n$14\r\nprotected-mode\r\n$1\r\n1\r\n"
+    );
+    assert_eq!(
+        call(&mut admin, &[b"CONFIG", b"SET", b"protected-mode", b"no"]),
+        "+OK\r\n"
+    );
+    let mut client = connect(external, port);
+    assert_eq!(call(&mut client, &[b"PING"]), "+PONG\r\n");
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn starting_with_protected_mode_off_admits_external_clients() {
+    let Some(external) = external_ip() else {
+        eprintln!("skipping: no non-loopback address");
+        return;
+    };
+    let (port, shutdown) = spawn_server(&["--protected-mode", "no"]);
+    let mut client = connect(external, port);
+    assert_eq!(call(&mut client, &[b"PING"]), "+PONG\r\n");
+    shutdown.trigger();
+}
@@ -0,0 +1,111 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+
+fn spawn_server() -> (SocketAddr, ShutdownController) {
+    let listener =
+        server::bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        vec![listener],
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn connect(addr: SocketAddr) -> StdTcpStream {
+    let stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends one command and reads one read's worth of reply.
+fn call(stream: &mut StdTcpStream, args: &[&[u8]]) -> String {
+    let mut request = Vec::new();
+    encode_command(args, &mut request);
+    stream.write_all(&request).unwrap();
+    let mut reply = vec![0; 8192];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn commands_past_the_rate_are_delayed_not_refused() {
+    let (addr, shutdown) = spawn_server();
+    let mut client = connect(addr);
+    assert_eq!(
+        call(&mut client, &[b"CONFIG", b"SET", b"maxcmd-per-sec", b"50"]),
+        "+OK\r\n"
+    );
+
+    // 50 tokens of burst, then 50 more at 50 per second.
+    let mut pipeline = Vec::new();
+    for _ in 0..100 {
+        encode_command(&[b"PING"], &mut pipeline);
+    }
+    let started = Instant::now();
+    client.write_all(&pipeline).unwrap();
+    let expected = b"+PONG\r\n".repeat(100);
+    let mut replies = vec![0; expected.len()];
+    client.read_exact(&mut replies).unwrap();
+    assert_eq!(replies, expected);
+    assert!(started.elapsed() >= Duration::from_millis(900));
+
+    assert_eq!(
+        call(&mut client, &[b"CONFIG", b"SET", b"maxcmd-per-sec", b"0"]),
+        "+OK\r\n"
+    );
+    let info = call(&mut client, &[b"INFO"]);
+    assert!(!info.contains("throttled_commands_total:0\r\n"), "{info}");
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn connections_past_the_per_ip_rate_are_refused() {
+    let (addr, shutdown) = spawn_server();
+    let mut admin = connect(addr);
+    assert_eq!(
+        call(
+            &mut admin,
+            &[b"CONFIG", b"SET", b"maxconn-per-ip-per-sec", b"2"]
+        ),
+        "+OK\r\n"
+    );
+
+    // The admin connection was accepted before the limit was set.
+    for _ in 0..2 {
+        let mut allowed = connect(addr);
+        assert_eq!(call(&mut allowed, &[b"PING"]), "+PONG\r\n");
+    }
+    let mut refused = connect(addr);
+    let mut refusal = String::new();
+    refused.read_to_string(&mut refusal).unwrap();
+    assert_eq!(
+        refusal,
+        "-ERR max connections per second from this address exceeded\r\n"
+    );
+
+    let info = call(&mut admin, &[b"INFO"]);
+    assert!(
+        info.contains("rate_limited_connections_total:1\r\n"),
+        "{info}"
+    );
+    shutdown.trigger();
+}
@@ -0,0 +1,113 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::TcpListener;
+
+const READONLY: &str = "-READONLY You can't write against a read only replica.\r\n";
+
+async fn spawn_server(runtime: Arc<RuntimeConfig>) -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let stopped = shutdown.wait();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        runtime,
+        stopped,
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn send(addr: SocketAddr, commands: &[&[&[u8]]]) -> String {
+    let mut request = Vec::new();
+    for args in commands {
+        encode_command(args, &mut request);
+    }
+    let mut stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream.write_all(&request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn pipelines_see_read_only_toggled_between_commands() {
+    let runtime = Arc::new(RuntimeConfig::new());
+    let (addr, shutdown) = spawn_server(Arc::clone(&runtime)).await;
+
+    let response = send(
+        addr,
+        &[
+            &[b"SET", b"k", b"before"],
+            &[b"CONFIG", b"SET", b"read-only", b"1"],
+            &[b"SET", b"k", b"during"],
+            &[b"DEL", b"k"],
+            &[b"EXPIRE", b"k", b"10"],
+            &[b"GET", b"k"],
+            &[b"TTL", b"k"],
+            &[b"CONFIG", b"GET", b"read-only"],
+            &[b"INFO", b"commandstats"],
+            &[b"CONFIG", b"SET", b"read-only", b"0"],
+            &[b"SET", b"k", b"after"],
+            &[b"GET", b"k"],
+        ],
+    );
+    let expected_prefix = format!(
+        "+OK\r\n+OK\r\n{READONLY}{READONLY}{READONLY}$6\r\nbefore\r\n:-1\r\n\
+         *2\r\n$9\r\nread-only\r\n$1\r\n1\r\n$"
+    );
+    assert!(response.starts_with(&expected_prefix), "{response}");
+    assert!(
+        response.ends_with("+OK\r\n+OK\r\n$5\r\nafter\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("cmdstat_set:calls=2,"), "{response}");
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_only_applies_to_other_connections() {
+    let runtime = Arc::new(RuntimeConfig::new());
+    let (addr, shutdown) = spawn_server(Arc::clone(&runtime)).await;
+
+    runtime.set_read_only(true);
+    assert_eq!(
+        send(addr, &[&[b"SET", b"k", b"v"], &[b"GET", b"k"], &[b"PING"]]),
+        format!("{READONLY}$-1\r\n+PONG\r\n")
+    );
+    // Refused whole without scripting, and at the SET with it.
+    assert_eq!(
+        send(
+            addr,
+            &[&[
+                b"EVAL",
+                b"return redis.call('SET', KEYS[1], 'v')",
+                b"1",
+                b"k"
+            ]]
+        ),
+        READONLY
+    );
+    runtime.set_read_only(false);
+    assert_eq!(send(addr, &[&[b"SET", b"k", b"v"]]), "+OK\r\n");
+
+    shutdown.trigger();
+}
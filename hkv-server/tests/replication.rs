@@ -0,0 +1,317 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+
+async fn spawn_server(listener: TcpListener) -> ShutdownController {
+    spawn_with_runtime(listener, Arc::new(RuntimeConfig::new())).await
+}
+
+async fn spawn_with_runtime(
+    listener: TcpListener,
+    runtime: Arc<RuntimeConfig>,
+) -> ShutdownController {
+    let shutdown = ShutdownController::new();
+    let stopped = shutdown.wait();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        runtime,
+        stopped,
+        Duration::from_secs(1),
+    ));
+    shutdown
+}
+
+async fn spawn_on_free_port() -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (addr, spawn_server(listener).await)
+}
+
+fn send(addr: SocketAddr, commands: &[&[&[u8]]]) -> String {
+    let mut request = Vec::new();
+    for args in commands {
+        encode_command(args, &mut request);
+    }
+    let mut stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream.write_all(&request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Polls `args` on `addr` until the reply satisfies `done`, returning the
+/// last reply.
+async fn wait_for(addr: SocketAddr, args: &[&[u8]], done: impl Fn(&str) -> bool) -> String {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let reply = send(addr, &[args]);
+        if done(&reply) || Instant::now() > deadline {
+            return reply;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Forwards connections accepted on `listener` to `master`; aborting the
+/// task closes them, cutting a replica's link.
+fn spawn_proxy(listener: TcpListener, master: SocketAddr) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut links = JoinSet::new();
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            links.spawn(async move {
+                let mut outbound = TcpStream::connect(master).await?;
+                tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await
+            });
+        }
+    })
+}
+
+fn replicaof(addr: SocketAddr, master: SocketAddr) -> String {
+    let port = master.port().to_string();
+    send(addr, &[&[b"REPLICAOF", b"127.0.0.1", port.as_bytes()]])
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn replica_loads_a_snapshot_then_follows_writes() {
+    let (master, master_shutdown) = spawn_on_free_port().await;
+    let (replica, replica_shutdown) = spawn_on_free_port().await;
+    send(
+        master,
+        &[
+            &[b"SET", b"synced", b"from-snapshot"],
+            &[b"SET", b"short", b"lived", b"EX", b"100"],
+        ],
+    );
+    send(replica, &[&[b"SET", b"stale", b"replaced-by-sync"]]);
+
+    assert_eq!(replicaof(replica, master), "+OK\r\n");
+    let reply = wait_for(replica, &[b"GET", b"synced"], |reply| {
+        reply.starts_with("$13")
+    })
+    .await;
+    assert_eq!(reply, "$13\r\nfrom-snapshot\r\n");
+    assert_eq!(send(replica, &[&[b"GET", b"stale"]]), "$-1\r\n");
+    assert!(send(replica, &[&[b"TTL", b"short"]]).starts_with(":9"));
+
+    send(
+        master,
+        &[
+            &[b"SET", b"live", b"streamed", b"EX", b"50"],
+            &[b"DEL", b"synced"],
+            &[b"EXPIRE", b"short", b"5"],
+            &[b"GET", b"live"],
+        ],
+    );
+    // TTL rounds down, so the new 5 second TTL reads as 5 or 4.
+    let lowered = |reply: &str| reply == ":5\r\n" || reply == ":4\r\n";
+    let reply = wait_for(replica, &[b"TTL", b"short"], lowered).await;
+    assert!(lowered(&reply), "{reply}");
+    assert_eq!(send(replica, &[&[b"GET", b"live"]]), "$8\r\nstreamed\r\n");
+    assert_eq!(send(replica, &[&[b"GET", b"synced"]]), "$-1\r\n");
+    assert_eq!(
+        send(replica, &[&[b"SET", b"k", b"v"]]),
+        "-READONLY You can't write against a read only replica.\r\n"
+    );
+
+    let master_info = send(master, &[&[b"INFO", b"replication"]]);
+    assert!(master_info.contains("role:master\r\n"), "{master_info}");
+    assert!(
+        master_info.contains("connected_slaves:1\r\n"),
+        "{master_info}"
+    );
+    let master_offset = field(&master_info, "master_repl_offset");
+    assert!(master_offset > 0, "{master_info}");
+    let replica_info = wait_for(replica, &[b"INFO", b"replication"], |info| {
+        field(info, "slave_repl_offset") == master_offset
+    })
+    .await;
+    assert!(replica_info.contains("role:slave\r\n"), "{replica_info}");
+    assert!(
+        replica_info.contains("master_link_status:up\r\n"),
+        "{replica_info}"
+    );
+    assert!(send(replica, &[&[b"INFO"]]).contains("role:slave\r\n"));
+
+    assert_eq!(
+        send(
+            replica,
+            &[&[b"REPLICAOF", b"NO", b"ONE"], &[b"SET", b"k", b"v"]]
+        ),
+        "+OK\r\n+OK\r\n"
+    );
+    assert!(send(replica, &[&[b"INFO", b"replication"]]).contains("role:master\r\n"));
+
+    replica_shutdown.trigger();
+    master_shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn replica_retries_until_the_master_is_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let replica = listener.local_addr().unwrap();
+    let runtime = Arc::new(RuntimeConfig::new());
+    let replica_shutdown = spawn_with_runtime(listener, Arc::clone(&runtime)).await;
+    let reserved = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let master = reserved.local_addr().unwrap();
+    drop(reserved);
+
+    assert_eq!(replicaof(replica, master), "+OK\r\n");
+    let info = wait_for(replica, &[b"INFO", b"replication"], |info| {
+        info.contains("master_link_status:down")
+    })
+    .await;
+    assert!(info.contains("master_link_status:down\r\n"), "{info}");
+    // Not ready for traffic until the first sync has loaded.
+    assert!(!runtime.lifecycle().is_ready());
+
+    let master_shutdown = spawn_server(TcpListener::bind(master).await.unwrap()).await;
+    send(master, &[&[b"SET", b"late", b"arrival"]]);
+    let reply = wait_for(replica, &[b"GET", b"late"], |reply| reply.starts_with("$7")).await;
+    assert_eq!(reply, "$7\r\narrival\r\n");
+    assert!(runtime.lifecycle().is_ready());
+
+    replica_shutdown.trigger();
+    master_shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reconnects_resume_from_the_backlog_while_it_covers_the_gap() {
+    let (master, master_shutdown) = spawn_on_free_port().await;
+    let (replica, replica_shutdown) = spawn_on_free_port().await;
+    assert_eq!(
+        send(
+            master,
+            &[&[b"CONFIG", b"SET", b"repl-backlog-size", b"1024"]]
+        ),
+        "+OK\r\n"
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let mut proxy = spawn_proxy(listener, master);
+
+    assert_eq!(replicaof(replica, proxy_addr), "+OK\r\n");
+    send(master, &[&[b"SET", b"before", b"cut"]]);
+    let reply = wait_for(replica, &[b"GET", b"before"], |reply| {
+        reply.starts_with("$3")
+    })
+    .await;
+    assert_eq!(reply, "$3\r\ncut\r\n");
+
+    // A short outage: the writes it misses fit in the backlog.
+    proxy.abort();
+    let _ = proxy.await;
+    let link_down = |info: &str| info.contains("master_link_status:down");
+    assert!(link_down(
+        &wait_for(replica, &[b"INFO", b"replication"], link_down).await
+    ));
+    send(
+        master,
+        &[&[b"SET", b"short", b"outage"], &[b"DEL", b"before"]],
+    );
+    proxy = spawn_proxy(TcpListener::bind(proxy_addr).await.unwrap(), master);
+    let reply = wait_for(replica, &[b"GET", b"short"], |reply| {
+        reply.starts_with("$6")
+    })
+    .await;
+    assert_eq!(reply, "$6\r\noutage\r\n");
+    assert_eq!(send(replica, &[&[b"GET", b"before"]]), "$-1\r\n");
+    let info = send(master, &[&[b"INFO", b"replication"]]);
+    assert_eq!(field(&info, "sync_full"), 1, "{info}");
+    assert_eq!(field(&info, "sync_partial_ok"), 1, "{info}");
+
+    // A long outage: more is written than the backlog holds.
+    proxy.abort();
+    let _ = proxy.await;
+    assert!(link_down(
+        &wait_for(replica, &[b"INFO", b"replication"], link_down).await
+    ));
+    let big = vec![b'x'; 2048];
+    send(master, &[&[b"SET", b"long", &big]]);
+    proxy = spawn_proxy(TcpListener::bind(proxy_addr).await.unwrap(), master);
+    let reply = wait_for(replica, &[b"GET", b"long"], |reply| {
+        reply.starts_with("$2048")
+    })
+    .await;
+    assert!(reply.starts_with("$2048\r\nxxx"), "{reply}");
+    assert_eq!(send(replica, &[&[b"GET", b"short"]]), "$6\r\noutage\r\n");
+    let info = send(master, &[&[b"INFO", b"replication"]]);
+    assert_eq!(field(&info, "sync_full"), 2, "{info}");
+    assert_eq!(field(&info, "sync_partial_ok"), 1, "{info}");
+    assert_eq!(field(&info, "sync_partial_err"), 1, "{info}");
+    assert_eq!(field(&info, "repl_backlog_histlen"), 1024, "{info}");
+
+    // Acks report how far the replica has applied.
+    let master_offset = field(&info, "master_repl_offset");
+    let info = wait_for(master, &[b"INFO", b"replication"], |info| {
+        info.contains(&format!(",offset={master_offset},"))
+    })
+    .await;
+    assert!(info.contains("slave0:addr=127.0.0.1:"), "{info}");
+    assert!(
+        info.contains(&format!(",offset={master_offset},")),
+        "{info}"
+    );
+
+    proxy.abort();
+    replica_shutdown.trigger();
+    master_shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn a_flush_past_the_time_limit_still_reaches_the_replica() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let master = listener.local_addr().unwrap();
+    let runtime = Arc::new(RuntimeConfig::new());
+    runtime.set_command_time_limit(Duration::from_millis(1));
+    let master_shutdown = spawn_with_runtime(listener, runtime).await;
+    let (replica, replica_shutdown) = spawn_on_free_port().await;
+    let keys: Vec<Vec<u8>> = (0..20_000)
+        .map(|i| format!("key:{i}").into_bytes())
+        .collect();
+    let sets: Vec<[&[u8]; 3]> = keys.iter().map(|key| [&b"SET"[..], key, b"v"]).collect();
+    let sets: Vec<&[&[u8]]> = sets.iter().map(|set| &set[..]).collect();
+    send(master, &sets);
+
+    assert_eq!(replicaof(replica, master), "+OK\r\n");
+    let reply = wait_for(replica, &[b"GET", b"key:19999"], |reply| {
+        reply.starts_with("$1")
+    })
+    .await;
+    assert_eq!(reply, "$1\r\nv\r\n");
+
+    // Freeing 20000 keys outlasts the 1 ms limit; the flush finishes anyway
+    // and is replicated like any other.
+    assert_eq!(send(master, &[&[b"FLUSHALL"]]), "+OK\r\n");
+    assert_eq!(send(master, &[&[b"KEYS", b"*"]]), "*0\r\n");
+    let reply = wait_for(replica, &[b"KEYS", b"*"], |reply| reply == "*0\r\n").await;
+    assert_eq!(reply, "*0\r\n");
+
+    replica_shutdown.trigger();
+    master_shutdown.trigger();
+}
+
+/// Reads a numeric `name:value` line from an `INFO` reply.
+fn field(info: &str, name: &str) -> u64 {
+    info.split("\r\n")
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
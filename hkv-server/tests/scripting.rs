@@ -0,0 +1,137 @@
+#![cfg(feature = "scripting")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::{DEFAULT_TCP_BACKLOG, RuntimeConfig};
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+
+const BUSY: &str = "-BUSY HybridKV is busy running a script. You can only call SCRIPT KILL.\r\n";
+
+fn spawn_server(runtime: Arc<RuntimeConfig>) -> (SocketAddr, ShutdownController) {
+    let listener =
+        server::bind_listener("127.0.0.1:0".parse().unwrap(), DEFAULT_TCP_BACKLOG).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_listeners_with_runtime_config(
+        vec![listener],
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        runtime,
+        shutdown.wait(),
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn connect(addr: SocketAddr) -> StdTcpStream {
+    let stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+fn send(stream: &mut StdTcpStream, args: &[&[u8]]) {
+    let mut request = Vec::new();
+    encode_command(args, &mut request);
+    stream.write_all(&request).unwrap();
+}
+
+/// Reads one read's worth of reply.
+fn receive(stream: &mut StdTcpStream) -> String {
+    let mut reply = vec![0; 4096];
+    let len = stream.read(&mut reply).unwrap();
+    String::from_utf8(reply[..len].to_vec()).unwrap()
+}
+
+fn call(stream: &mut StdTcpStream, args: &[&[u8]]) -> String {
+    send(stream, args);
+    receive(stream)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn scripts_run_commands_and_are_cached_for_every_connection() {
+    let (addr, shutdown) = spawn_server(Arc::new(RuntimeConfig::new()));
+    let mut first = connect(addr);
+    let mut second = connect(addr);
+
+    let incr_by = b"local v = tonumber(redis.call('GET', KEYS[1]) or '0') + ARGV[1] \
+                    redis.call('SET', KEYS[1], v) \
+                    return v";
+    assert_eq!(
+        call(&mut first, &[b"EVAL", incr_by, b"1", b"counter", b"5"]),
+        ":5\r\n"
+    );
+    assert_eq!(call(&mut second, &[b"GET", b"counter"]), "$1\r\n5\r\n");
+
+    // EVAL cached the script for EVALSHA on any connection.
+    let sha = call(&mut first, &[b"SCRIPT", b"LOAD", incr_by]);
+    let sha = sha.split("\r\n").nth(1).unwrap().to_owned();
+    assert_eq!(
+        call(
+            &mut second,
+            &[b"SCRIPT", b"EXISTS", sha.as_bytes(), b"0000"]
+        ),
+        "*2\r\n:1\r\n:0\r\n"
+    );
+    assert_eq!(
+        call(
+            &mut second,
+            &[b"EVALSHA", sha.as_bytes(), b"1", b"counter", b"2"]
+        ),
+        ":7\r\n"
+    );
+    assert_eq!(
+        call(&mut first, &[b"EVAL", b"return redis.call('NOPE')", b"0"]),
+        "-ERR Unknown Redis command called from script\r\n"
+    );
+
+    assert_eq!(call(&mut first, &[b"SCRIPT", b"FLUSH"]), "+OK\r\n");
+    assert_eq!(
+        call(&mut second, &[b"EVALSHA", sha.as_bytes(), b"0"]),
+        "-NOSCRIPT No matching script. Please use EVAL.\r\n"
+    );
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn a_runaway_script_is_busy_until_script_kill() {
+    let runtime = Arc::new(RuntimeConfig::new());
+    runtime.set_lua_time_limit(Duration::from_millis(100));
+    let (addr, shutdown) = spawn_server(runtime);
+    let mut script = connect(addr);
+    let mut other = connect(addr);
+
+    send(&mut script, &[b"EVAL", b"while true do end", b"0"]);
+    let started = Instant::now();
+    loop {
+        let reply = call(&mut other, &[b"PING"]);
+        if reply == BUSY {
+            break;
+        }
+        assert_eq!(reply, "+PONG\r\n");
+        assert!(started.elapsed() < Duration::from_secs(5), "never busy");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(call(&mut other, &[b"SCRIPT", b"KILL"]), "+OK\r\n");
+    assert_eq!(
+        receive(&mut script),
+        "-ERR Script killed by user with SCRIPT KILL...\r\n"
+    );
+    assert_eq!(call(&mut other, &[b"PING"]), "+PONG\r\n");
+    assert_eq!(
+        call(&mut other, &[b"SCRIPT", b"KILL"]),
+        "-NOTBUSY No scripts in execution right now.\r\n"
+    );
+    shutdown.trigger();
+}
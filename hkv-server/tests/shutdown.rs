@@ -0,0 +1,78 @@
+use std::io::{Read, Write};
+use std::net::TcpStream as StdTcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hkv_engine::MemoryEngine;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::TcpListener;
+
+fn resp_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn shutdown_answers_sent_commands_then_closes() {
+    const GRACE: Duration = Duration::from_secs(5);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let server = tokio::spawn(server::serve_with_shutdown_and_grace(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        shutdown.wait(),
+        GRACE,
+    ));
+
+    let mut request = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..100 {
+        let key = format!("drain:{i}");
+        request.extend(resp_command(&[b"SET", key.as_bytes(), b"v"]));
+        request.extend(resp_command(&[b"GET", key.as_bytes()]));
+        expected.extend_from_slice(b"+OK\r\n$1\r\nv\r\n");
+    }
+    // A trailing partial command is dropped, not answered.
+    request.extend_from_slice(b"*2\r\n$3\r\nGET\r\n$5\r\ndra");
+
+    let client = thread::spawn(move || {
+        let mut stream = StdTcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream.write_all(&request).unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = Instant::now();
+    shutdown.trigger();
+
+    let response = tokio::task::spawn_blocking(move || client.join().unwrap())
+        .await
+        .unwrap();
+    assert!(response == expected, "replies before EOF did not match");
+
+    tokio::time::timeout(Duration::from_secs(1), server)
+        .await
+        .expect("server should not wait out the grace period")
+        .unwrap()
+        .unwrap();
+    assert!(started.elapsed() < GRACE);
+}
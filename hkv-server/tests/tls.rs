@@ -0,0 +1,322 @@
+//! # TLS Integration Tests
+//!
+//! Serve over TLS with certificates generated per test, and talk to the
+//! server with a rustls client (and `redis-cli --tls` when installed).
+//!
+//! ## Design Principles
+//!
+//! 1. **Throwaway PKI**: Each test signs its own server and client
+//!    certificates with a fresh CA, written to a unique temp directory.
+//! 2. **Pure-Rust Client First**: Every behavior is checked with rustls;
+//!    the `redis-cli` test skips when the binary or its TLS support is
+//!    missing.
+
+#![cfg(feature = "tls")]
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use hkv_server::tls::{TlsConfig, TlsState};
+
+const PING: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+
+fn unique_temp_dir(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("hkv-{label}-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A CA and the certificates it signed, as PEM files in `dir`.
+struct Pki {
+    dir: PathBuf,
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new(dir: &Path) -> Self {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        Pki {
+            dir: dir.to_path_buf(),
+            ca,
+            ca_key,
+        }
+    }
+
+    /// Signs a leaf for `names` and writes `<name>.pem`/`<name>-key.pem`.
+    fn issue(&self, name: &str, names: &[&str]) -> (PathBuf, PathBuf) {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(names)
+            .unwrap()
+            .signed_by(&key, &self.ca, &self.ca_key)
+            .unwrap();
+        let cert_file = self.dir.join(format!("{name}.pem"));
+        let key_file = self.dir.join(format!("{name}-key.pem"));
+        std::fs::write(&cert_file, cert.pem()).unwrap();
+        std::fs::write(&key_file, key.serialize_pem()).unwrap();
+        (cert_file, key_file)
+    }
+
+    fn ca_file(&self) -> PathBuf {
+        self.dir.join("ca.pem")
+    }
+
+    /// Issues a server certificate for `127.0.0.1` and returns its config.
+    fn server_config(&self) -> TlsConfig {
+        let (cert_file, key_file) = self.issue("server", &["127.0.0.1", "localhost"]);
+        TlsConfig::new(cert_file, key_file)
+    }
+}
+
+/// Client trusting `ca_file`, presenting `identity` if given.
+fn connector(ca_file: &Path, identity: Option<(PathBuf, PathBuf)>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_file).unwrap() {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some((cert_file, key_file)) => builder
+            .with_client_auth_cert(
+                CertificateDer::pem_file_iter(cert_file)
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .collect(),
+                PrivateKeyDer::from_pem_file(key_file).unwrap(),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    TlsConnector::from(Arc::new(config))
+}
+
+async fn spawn_tls_server(
+    config: TlsConfig,
+) -> (SocketAddr, Arc<TlsState>, Arc<Metrics>, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tls = Arc::new(TlsState::load(config).unwrap());
+    let metrics = Arc::new(Metrics::new());
+    let shutdown = ShutdownController::new();
+    tokio::spawn(server::serve_tls_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::clone(&metrics),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        Arc::clone(&tls),
+        shutdown.wait(),
+        Duration::from_secs(5),
+    ));
+    (addr, tls, metrics, shutdown)
+}
+
+/// Sends `request` over TLS and reads until `expected_len` bytes arrive or
+/// the connection fails.
+async fn tls_request(
+    connector: &TlsConnector,
+    addr: SocketAddr,
+    request: &[u8],
+    expected_len: usize,
+) -> std::io::Result<Vec<u8>> {
+    let tcp = TcpStream::connect(addr).await?;
+    let name = ServerName::try_from("127.0.0.1").unwrap();
+    let mut stream = connector.connect(name, tcp).await?;
+    stream.write_all(request).await?;
+    let mut response = vec![0u8; expected_len];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut response))
+        .await
+        .map_err(std::io::Error::other)??;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn commands_are_served_over_tls() {
+    let dir = unique_temp_dir("tls-serve");
+    let pki = Pki::new(&dir);
+    let (addr, _tls, _metrics, shutdown) = spawn_tls_server(pki.server_config()).await;
+    let client = connector(&pki.ca_file(), None);
+
+    let request = [
+        b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n".as_slice(),
+        b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+    ]
+    .concat();
+    let expected = b"+OK\r\n$5\r\nvalue\r\n";
+    let response = tls_request(&client, addr, &request, expected.len())
+        .await
+        .unwrap();
+    assert_eq!(response, expected);
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn failed_handshakes_do_not_stop_the_server() {
+    let dir = unique_temp_dir("tls-handshake");
+    let pki = Pki::new(&dir);
+    let (addr, _tls, metrics, shutdown) = spawn_tls_server(pki.server_config()).await;
+
+    // Plaintext RESP is not a ClientHello; the server drops the connection.
+    let mut plain = TcpStream::connect(addr).await.unwrap();
+    plain.write_all(PING).await.unwrap();
+    let mut response = Vec::new();
+    let _ = plain.read_to_end(&mut response).await;
+    assert!(!response.starts_with(b"+PONG"));
+
+    // A client that does not trust the server certificate aborts it.
+    let stranger = Pki::new(&unique_temp_dir("tls-stranger"));
+    let untrusted = connector(&stranger.ca_file(), None);
+    assert!(tls_request(&untrusted, addr, PING, 7).await.is_err());
+
+    let client = connector(&pki.ca_file(), None);
+    let response = tls_request(&client, addr, PING, 7).await.unwrap();
+    assert_eq!(response, b"+PONG\r\n");
+    // Failures are counted by the connection task, after the client sees
+    // the connection end.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while metrics.snapshot().tls_handshake_failures_total < 2 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "failures not counted"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(metrics.snapshot().tls_handshake_failures_total, 2);
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(stranger.dir).unwrap();
+}
+
+#[tokio::test]
+async fn client_certificates_are_required_when_configured() {
+    let dir = unique_temp_dir("tls-mutual");
+    let pki = Pki::new(&dir);
+    let config = pki.server_config().with_client_ca(pki.ca_file(), true);
+    let (addr, _tls, _metrics, shutdown) = spawn_tls_server(config).await;
+
+    let anonymous = connector(&pki.ca_file(), None);
+    assert!(tls_request(&anonymous, addr, PING, 7).await.is_err());
+
+    let identity = pki.issue("client", &["client"]);
+    let authenticated = connector(&pki.ca_file(), Some(identity));
+    let response = tls_request(&authenticated, addr, PING, 7).await.unwrap();
+    assert_eq!(response, b"+PONG\r\n");
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn optional_client_certificates_are_not_required() {
+    let dir = unique_temp_dir("tls-optional");
+    let pki = Pki::new(&dir);
+    let config = pki.server_config().with_client_ca(pki.ca_file(), false);
+    let (addr, _tls, _metrics, shutdown) = spawn_tls_server(config).await;
+
+    let anonymous = connector(&pki.ca_file(), None);
+    let response = tls_request(&anonymous, addr, PING, 7).await.unwrap();
+    assert_eq!(response, b"+PONG\r\n");
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn reload_rotates_the_server_certificate() {
+    let dir = unique_temp_dir("tls-reload");
+    let pki = Pki::new(&dir);
+    let (addr, tls, _metrics, shutdown) = spawn_tls_server(pki.server_config()).await;
+    let old_client = connector(&pki.ca_file(), None);
+    assert!(tls_request(&old_client, addr, PING, 7).await.is_ok());
+
+    // Rotate to a certificate from a new CA at the same paths.
+    let rotated_dir = unique_temp_dir("tls-rotated");
+    let rotated = Pki::new(&rotated_dir);
+    let (cert_file, key_file) = rotated.issue("server", &["127.0.0.1"]);
+    std::fs::copy(cert_file, &tls.config().cert_file).unwrap();
+    std::fs::copy(key_file, &tls.config().key_file).unwrap();
+    tls.reload().unwrap();
+
+    let new_client = connector(&rotated.ca_file(), None);
+    let response = tls_request(&new_client, addr, PING, 7).await.unwrap();
+    assert_eq!(response, b"+PONG\r\n");
+    assert!(tls_request(&old_client, addr, PING, 7).await.is_err());
+
+    // A broken file leaves the rotated certificate in place.
+    std::fs::write(&tls.config().key_file, "not a key").unwrap();
+    assert!(tls.reload().is_err());
+    assert!(tls_request(&new_client, addr, PING, 7).await.is_ok());
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(rotated_dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn redis_cli_pings_over_tls() {
+    let supports_tls = Command::new("redis-cli")
+        .arg("--help")
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stderr).contains("--tls"));
+    if !supports_tls {
+        eprintln!("skipping: redis-cli with TLS support is not installed");
+        return;
+    }
+
+    let dir = unique_temp_dir("tls-redis-cli");
+    let pki = Pki::new(&dir);
+    let (addr, _tls, _metrics, shutdown) = spawn_tls_server(pki.server_config()).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new("redis-cli")
+            .args(["--tls", "--insecure", "-p"])
+            .arg(addr.port().to_string())
+            .arg("PING")
+            .output()
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(
+        output.status.success(),
+        "redis-cli failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "PONG");
+
+    shutdown.trigger();
+    std::fs::remove_dir_all(dir).unwrap();
+}
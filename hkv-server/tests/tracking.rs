@@ -0,0 +1,222 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hkv_client::encode_command;
+use hkv_engine::MemoryEngine;
+use hkv_server::config::RuntimeConfig;
+use hkv_server::metrics::Metrics;
+use hkv_server::persistence::Persistence;
+use hkv_server::server;
+use hkv_server::shutdown::ShutdownController;
+use tokio::net::TcpListener;
+
+async fn spawn_server() -> (SocketAddr, ShutdownController) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = ShutdownController::new();
+    let stopped = shutdown.wait();
+    tokio::spawn(server::serve_with_runtime_config(
+        listener,
+        Arc::new(MemoryEngine::new()),
+        Arc::new(Metrics::new()),
+        Arc::new(Persistence::default()),
+        Arc::new(RuntimeConfig::new()),
+        stopped,
+        Duration::from_secs(1),
+    ));
+    (addr, shutdown)
+}
+
+fn connect(addr: SocketAddr) -> StdTcpStream {
+    let stream = StdTcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+}
+
+/// Sends one command and reads its reply up to and including `ends_with`.
+fn call(stream: &mut StdTcpStream, args: &[&[u8]], ends_with: &str) -> String {
+    let mut request = Vec::new();
+    encode_command(args, &mut request);
+    stream.write_all(&request).unwrap();
+    read_until(stream, ends_with)
+}
+
+fn read_until(stream: &mut StdTcpStream, ends_with: &str) -> String {
+    let mut reply = Vec::new();
+    let mut byte = [0];
+    while !reply.ends_with(ends_with.as_bytes()) {
+        stream.read_exact(&mut byte).unwrap();
+        reply.push(byte[0]);
+    }
+    String::from_utf8(reply).unwrap()
+}
+
+fn client_id(stream: &mut StdTcpStream) -> String {
+    let reply = call(stream, &[b"CLIENT", b"ID"], "\r\n");
+    reply.trim_start_matches(':').trim_end().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_write_on_one_connection_invalidates_another_ones_read() {
+    let (addr, shutdown) = spawn_server().await;
+    let mut reader = connect(addr);
+    let mut writer = connect(addr);
+
+    let hello = call(&mut reader, &[b"HELLO", b"3"], "$7\r\nmodules\r\n*0\r\n");
+    assert!(
+        hello.starts_with("%7\r\n$6\r\nserver\r\n$8\r\nhybridkv\r\n"),
+        "{hello}"
+    );
+    assert!(hello.contains("$5\r\nproto\r\n:3\r\n"), "{hello}");
+    assert_eq!(
+        call(&mut reader, &[b"CLIENT", b"TRACKING", b"ON"], "\r\n"),
+        "+OK\r\n"
+    );
+    assert_eq!(call(&mut writer, &[b"SET", b"k", b"v1"], "\r\n"), "+OK\r\n");
+    assert_eq!(call(&mut reader, &[b"GET", b"k"], "v1\r\n"), "$2\r\nv1\r\n");
+
+    let started = Instant::now();
+    assert_eq!(call(&mut writer, &[b"SET", b"k", b"v2"], "\r\n"), "+OK\r\n");
+    assert_eq!(
+        read_until(&mut reader, "$1\r\nk\r\n"),
+        ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n"
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // The key is no longer tracked until read again, and the connection
+    // still answers commands after the push.
+    assert_eq!(call(&mut writer, &[b"DEL", b"k"], "\r\n"), ":1\r\n");
+    assert_eq!(call(&mut reader, &[b"PING"], "\r\n"), "+PONG\r\n");
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn broadcast_invalidations_can_be_redirected() {
+    let (addr, shutdown) = spawn_server().await;
+    let mut receiver = connect(addr);
+    let mut tracker = connect(addr);
+    let mut writer = connect(addr);
+
+    let receiver_id = client_id(&mut receiver);
+    let redirect: &[&[u8]] = &[
+        b"CLIENT",
+        b"TRACKING",
+        b"ON",
+        b"REDIRECT",
+        receiver_id.as_bytes(),
+        b"BCAST",
+        b"PREFIX",
+        b"user:",
+    ];
+    assert_eq!(
+        call(&mut tracker, redirect, "\r\n"),
+        "-ERR The client you want redirect to must use RESP3, Pub/Sub is not supported\r\n"
+    );
+    call(&mut receiver, &[b"HELLO", b"3"], "$7\r\nmodules\r\n*0\r\n");
+    assert_eq!(call(&mut tracker, redirect, "\r\n"), "+OK\r\n");
+    assert_eq!(
+        call(&mut tracker, &[b"CLIENT", b"GETREDIR"], "\r\n"),
+        format!(":{receiver_id}\r\n")
+    );
+
+    call(&mut writer, &[b"SET", b"order:1", b"x"], "\r\n");
+    call(&mut writer, &[b"SET", b"user:1", b"x"], "\r\n");
+    assert_eq!(
+        read_until(&mut receiver, "user:1\r\n"),
+        ">2\r\n$10\r\ninvalidate\r\n*1\r\n$6\r\nuser:1\r\n"
+    );
+
+    // Once the tracking connection closes, nothing more is sent.
+    drop(tracker);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    call(&mut writer, &[b"SET", b"user:2", b"x"], "\r\n");
+    assert_eq!(call(&mut receiver, &[b"PING"], "\r\n"), "+PONG\r\n");
+
+    shutdown.trigger();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn an_unread_subscriber_is_closed_at_the_pubsub_limit() {
+    const WRITES: usize = 4000;
+    let (addr, shutdown) = spawn_server().await;
+    let mut subscriber = connect(addr);
+    let mut writer = connect(addr);
+
+    assert_eq!(
+        call(
+            &mut writer,
+            &[
+                b"CONFIG",
+                b"SET",
+                b"client-output-buffer-limit",
+                b"pubsub 256kb 0 0"
+            ],
+            "\r\n"
+        ),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        call(
+            &mut writer,
+            &[b"CONFIG", b"GET", b"client-output-buffer-limit"],
+            "pubsub 262144 0 0\r\n"
+        ),
+        "*2\r\n$26\r\nclient-output-buffer-limit\r\n\
+         $58\r\nnormal 0 0 0 slave 268435456 67108864 60 pubsub 262144 0 0\r\n"
+    );
+    call(
+        &mut subscriber,
+        &[b"HELLO", b"3"],
+        "$7\r\nmodules\r\n*0\r\n",
+    );
+    assert_eq!(
+        call(
+            &mut subscriber,
+            &[b"CLIENT", b"TRACKING", b"ON", b"BCAST"],
+            "\r\n"
+        ),
+        "+OK\r\n"
+    );
+
+    // Every write pushes the 4KB key to a subscriber that never reads.
+    let key = vec![b'k'; 4096];
+    let mut pipeline = Vec::new();
+    for _ in 0..WRITES {
+        encode_command(&[b"SET", &key, b"v"], &mut pipeline);
+    }
+    writer.write_all(&pipeline).unwrap();
+    let mut replies = vec![0; WRITES * "+OK\r\n".len()];
+    writer.read_exact(&mut replies).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let info = call(&mut writer, &[b"INFO"], "\r\n");
+        let len: usize = info[1..info.len() - 2].parse().unwrap();
+        let mut body = vec![0; len + 2];
+        writer.read_exact(&mut body).unwrap();
+        if String::from_utf8(body)
+            .unwrap()
+            .contains("output_buffer_limit_disconnects_total:1\r\n")
+        {
+            break;
+        }
+        assert!(Instant::now() < deadline, "the subscriber was never closed");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // What reached the socket before the close is bounded; then EOF.
+    let mut received = Vec::new();
+    subscriber.read_to_end(&mut received).unwrap();
+    assert!(
+        received.len() < WRITES * key.len(),
+        "{} bytes",
+        received.len()
+    );
+
+    shutdown.trigger();
+}